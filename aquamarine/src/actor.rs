@@ -30,6 +30,7 @@ use crate::deadline::Deadline;
 use crate::particle_effects::RawRoutingEffects;
 use crate::particle_executor::{FutResult, ParticleExecutor};
 use crate::particle_functions::{Functions, SingleCallStat};
+use crate::progress::progress_particle;
 use crate::spawner::{SpawnFunctions, Spawner};
 use crate::{AquaRuntime, InterpretationStats, ParticleDataStore, ParticleEffects};
 use fluence_keypair::KeyPair;
@@ -73,6 +74,10 @@ pub struct Actor<RT, F> {
     data_store: Arc<ParticleDataStore>,
     spawner: Spawner,
     deal_id: Option<DealId>,
+    /// Progress particle to send along the effects of the ongoing interpretation
+    progress: Option<Particle>,
+    /// Number of progress particles sent so far
+    progress_seq: u32,
 }
 
 impl<RT, F> Actor<RT, F>
@@ -109,6 +114,8 @@ where
             data_store,
             spawner,
             deal_id,
+            progress: None,
+            progress_seq: 0,
         }
     }
 
@@ -127,6 +134,10 @@ where
         (particle_id, self.current_peer_id, signature, token)
     }
 
+    pub fn init_peer_id(&self) -> PeerId {
+        self.particle.init_peer_id
+    }
+
     pub fn mailbox_size(&self) -> usize {
         self.mailbox.len()
     }
@@ -137,6 +148,10 @@ where
 
     #[instrument(level = tracing::Level::INFO, skip_all)]
     pub fn ingest(&mut self, particle: ExtendedParticle) {
+        // Particles produced by the actor carry the trail of the latest received particle
+        // and the highest hop count, so a loop through several actors isn't reset
+        self.particle.trail = particle.particle.trail.clone();
+        self.particle.hops = self.particle.hops.max(particle.particle.hops);
         self.mailbox.push_back(particle);
         self.wake();
     }
@@ -182,15 +197,18 @@ where
                 parent_span.clone(),
             );
 
+            let particle = effects.error_particle.unwrap_or_else(|| Particle {
+                data: effects.new_data,
+                ..self.particle.clone()
+            });
+            let progress = self
+                .progress
+                .take()
+                .map(|p| ExtendedParticle::linked(p, parent_span.clone()));
             let effects = RawRoutingEffects {
-                particle: ExtendedParticle::linked(
-                    Particle {
-                        data: effects.new_data,
-                        ..self.particle.clone()
-                    },
-                    parent_span,
-                ),
+                particle: ExtendedParticle::linked(particle, parent_span),
                 next_peers: effects.next_peers,
+                progress,
             };
             return Some(Poll::Ready(FutResult {
                 runtime: (reusables.vm_id, reusables.vm),
@@ -217,6 +235,7 @@ where
 
         // Gather CallResults
         let (calls, stats, call_spans) = self.functions.drain();
+        self.prepare_progress();
 
         // Take the next particle
         let ext_particle = self.mailbox.pop_front();
@@ -276,6 +295,24 @@ where
         ActorPoll::Executing(stats)
     }
 
+    /// Prepare a progress particle with the calls finished since the previous interpretation
+    fn prepare_progress(&mut self) {
+        let calls = self.functions.drain_progress();
+        if calls.is_empty() {
+            return;
+        }
+        self.progress = progress_particle(
+            &self.particle,
+            self.progress_seq,
+            self.current_peer_id,
+            &self.key_pair,
+            &calls,
+        );
+        if self.progress.is_some() {
+            self.progress_seq += 1;
+        }
+    }
+
     fn create_spans(
         &self,
         call_spans: Vec<Arc<Span>>,
@@ -111,6 +111,7 @@ impl AquaRuntime for AVMRunner<WasmtimeWasmBackend> {
                     next_peers: peers,
                     call_requests: calls,
                     new_data,
+                    error_particle: None,
                 }
             }
             Ok((data, ..)) => {
@@ -16,8 +16,10 @@
  * You should have received a copy of the GNU Affero General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::future::Future;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
@@ -25,14 +27,14 @@ use std::time::Duration;
 use futures::StreamExt;
 use marine_wasmtime_backend::WasmtimeWasmBackend;
 use tokio::sync::mpsc;
-use tokio::task::JoinHandle;
+use tokio::task::{JoinHandle, JoinSet};
 use tracing::{instrument, Instrument};
 
 use health::HealthCheckRegistry;
 use particle_execution::{ParticleFunctionStatic, ServiceFunction};
 use particle_protocol::ExtendedParticle;
 use particle_services::{PeerScope, WasmBackendConfig};
-use peer_metrics::{ParticleExecutorMetrics, VmPoolMetrics};
+use peer_metrics::{AquamarineShardMetrics, ParticleExecutorMetrics, VmPoolMetrics};
 use workers::{Event, KeyStorage, PeerScopes, Receiver, Workers};
 
 use crate::command::Command;
@@ -46,11 +48,23 @@ use crate::{
 pub type EffectsChannel = mpsc::Sender<Result<RemoteRoutingEffects, AquamarineApiError>>;
 
 pub struct AquamarineBackend<RT: AquaRuntime, F> {
-    inlet: mpsc::Receiver<Command>,
+    shards: Vec<AquamarineShard<RT, F>>,
     worker_events: Receiver<Event>,
+    data_store: Arc<ParticleDataStore>,
+}
+
+/// Part of the router state: particles are assigned to shards by particle id,
+/// so all actors of a particle live in a single shard and shards share nothing
+/// except the data store and the builtins
+struct AquamarineShard<RT: AquaRuntime, F> {
+    shard: usize,
+    shards: usize,
+    inlet: mpsc::Receiver<Command>,
+    worker_events: mpsc::UnboundedReceiver<Event>,
+    worker_events_outlet: mpsc::UnboundedSender<Event>,
     plumber: Plumber<RT, F>,
     out: EffectsChannel,
-    data_store: Arc<ParticleDataStore>,
+    metrics: Option<AquamarineShardMetrics>,
 }
 
 impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
@@ -64,16 +78,13 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
         out: EffectsChannel,
         plumber_metrics: Option<ParticleExecutorMetrics>,
         vm_pool_metrics: Option<VmPoolMetrics>,
-        health_registry: Option<&mut HealthCheckRegistry>,
+        shard_metrics: Option<AquamarineShardMetrics>,
+        mut health_registry: Option<&mut HealthCheckRegistry>,
         workers: Arc<Workers>,
         key_storage: Arc<KeyStorage>,
         scopes: PeerScopes,
         worker_events: Receiver<Event>,
     ) -> eyre::Result<(Self, AquamarineApi)> {
-        // TODO: make `100` configurable
-        let (outlet, inlet) = mpsc::channel(100);
-        let sender = AquamarineApi::new(outlet, config.execution_timeout);
-
         let data_store = ParticleDataStore::new(
             data_store_config.particles_dir,
             data_store_config.particles_vault_dir,
@@ -82,35 +93,105 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
         let data_store: Arc<ParticleDataStore> = Arc::new(data_store);
         let avm_wasm_backend = WasmtimeWasmBackend::new(avm_wasm_backend_config.into())?;
 
-        let vm_pool = VmPool::new(
-            config.pool_size,
-            vm_config.clone(),
-            vm_pool_metrics,
-            health_registry,
-            avm_wasm_backend.clone(),
-        );
-        let plumber = Plumber::new(
-            vm_config,
-            vm_pool,
-            data_store.clone(),
-            builtins,
-            plumber_metrics,
-            workers,
-            key_storage,
-            scopes,
-            avm_wasm_backend,
-        );
+        let shards_count = config.shards.max(1);
+        let mut shards = Vec::with_capacity(shards_count);
+        let mut outlets = Vec::with_capacity(shards_count);
+        for shard in 0..shards_count {
+            // TODO: make `100` configurable
+            let (outlet, inlet) = mpsc::channel(100);
+            outlets.push(outlet);
+
+            let vm_pool = VmPool::new(
+                shard_size(config.pool_size, shards_count, shard),
+                vm_config.clone(),
+                // VM pool metrics keep per-VM state, so only the first shard reports them
+                vm_pool_metrics.clone().filter(|_| shard == 0),
+                health_registry.as_deref_mut(),
+                avm_wasm_backend.clone(),
+            );
+            let plumber = Plumber::new(
+                vm_config.clone(),
+                vm_pool,
+                data_store.clone(),
+                builtins.clone(),
+                config.args_limits,
+                plumber_metrics.clone(),
+                workers.clone(),
+                key_storage.clone(),
+                scopes.clone(),
+                avm_wasm_backend.clone(),
+            )
+            .with_args_redaction(config.args_redaction.clone())
+            .with_root_pools(config.root_pools.clone())
+            .with_event_log(config.event_log.clone())
+            .with_clock_correction(config.clock_correction.clone());
+            let (worker_events_outlet, shard_worker_events) = mpsc::unbounded_channel();
+            shards.push(AquamarineShard {
+                shard,
+                shards: shards_count,
+                inlet,
+                worker_events: shard_worker_events,
+                worker_events_outlet,
+                plumber,
+                out: out.clone(),
+                metrics: shard_metrics.clone(),
+            });
+        }
+
+        let sender = AquamarineApi::new(outlets, config.execution_timeout);
         let this = Self {
-            inlet,
+            shards,
             worker_events,
-            plumber,
-            out,
             data_store,
         };
 
         Ok((this, sender))
     }
 
+    pub fn start(self) -> JoinHandle<()> {
+        let Self {
+            shards,
+            mut worker_events,
+            data_store,
+        } = self;
+        let worker_events_outlets: Vec<_> = shards
+            .iter()
+            .map(|shard| shard.worker_events_outlet.clone())
+            .collect();
+
+        let result = tokio::task::Builder::new()
+            .name("Aquamarine")
+            .spawn(
+                async move {
+                    data_store
+                        .initialize()
+                        .await
+                        .expect("Could not initialize data store");
+
+                    // Dropping the set aborts the shards, so aborting this task stops them all
+                    let mut tasks = JoinSet::new();
+                    for shard in shards {
+                        tasks.spawn(shard.run().in_current_span());
+                    }
+
+                    while let Some(event) = worker_events.recv().await {
+                        for outlet in &worker_events_outlets {
+                            // shards live as long as this task
+                            let _ = outlet.send(event.clone());
+                        }
+                    }
+
+                    while tasks.join_next().await.is_some() {}
+                }
+                .in_current_span(),
+            )
+            .expect("Could not spawn task");
+
+        result
+    }
+}
+
+impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineShard<RT, F> {
     pub fn poll(&mut self, cx: &mut std::task::Context<'_>) -> Poll<()> {
         let mut wake = self.process_worker_events();
 
@@ -119,8 +200,11 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
             match self.inlet.poll_recv(cx) {
                 Poll::Ready(Some(Ingest { particle, function })) => {
                     wake = true;
-                    let span = tracing::info_span!(parent: particle.span.as_ref(), "Aquamarine::poll::ingest");
+                    let span = tracing::info_span!(parent: particle.span.as_ref(), "Aquamarine::poll::ingest", shard = self.shard);
                     let _guard = span.entered();
+                    if let Some(m) = self.metrics.as_ref() {
+                        m.particle_ingested(self.shard)
+                    }
                     // set new particle to be executed
                     // every particle that comes from the connection pool first executed on the host peer id
                     self.plumber.ingest(particle, function, PeerScope::Host);
@@ -142,6 +226,9 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
         // check if there are executed particles
         while let Poll::Ready(effects) = self.plumber.poll(cx) {
             wake = true;
+            if let Some(m) = self.metrics.as_ref() {
+                m.effects_produced(self.shard)
+            }
             // send results back
             let sent = self.out.try_send(effects);
             if let Err(err) = sent {
@@ -149,6 +236,10 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
             }
         }
 
+        if let Some(m) = self.metrics.as_ref() {
+            m.set_actors(self.shard, self.plumber.actors_count())
+        }
+
         if wake {
             Poll::Ready(())
         } else {
@@ -167,6 +258,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
                         thread_count,
                     } => {
                         wake = true;
+                        let thread_count = shard_size(thread_count, self.shards, self.shard);
                         self.plumber.create_worker_pool(worker_id, thread_count);
                     }
                     Event::WorkerRemoved { worker_id } => {
@@ -181,40 +273,40 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> AquamarineBackend<RT, F> {
         wake
     }
 
-    pub fn start(mut self) -> JoinHandle<()> {
-        let data_store = self.data_store.clone();
+    async fn run(mut self) {
         let mut stream = futures::stream::poll_fn(move |cx| self.poll(cx).map(|_| Some(()))).fuse();
-        let result = tokio::task::Builder::new()
-            .name("Aquamarine")
-            .spawn(
-                async move {
-                    data_store
-                        .initialize()
-                        .await
-                        .expect("Could not initialize data store");
-                    loop {
-                        stream.next().await;
-                    }
-                }
-                .in_current_span(),
-            )
-            .expect("Could not spawn task");
-
-        result
+        loop {
+            stream.next().await;
+        }
     }
 }
 
+/// Part of `total` that goes to `shard`, at least 1
+fn shard_size(total: usize, shards: usize, shard: usize) -> usize {
+    let size = total / shards + usize::from(shard < total % shards);
+    size.max(1)
+}
+
+/// Shard that handles the particle, stable for the lifetime of the process
+fn shard_of(particle_id: &str, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    particle_id.hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
 #[derive(Clone)]
 pub struct AquamarineApi {
-    outlet: mpsc::Sender<Command>,
+    /// One outlet per shard
+    outlets: Arc<Vec<mpsc::Sender<Command>>>,
     #[allow(dead_code)]
     execution_timeout: Duration,
 }
 
 impl AquamarineApi {
-    pub fn new(outlet: mpsc::Sender<Command>, execution_timeout: Duration) -> Self {
+    pub fn new(outlets: Vec<mpsc::Sender<Command>>, execution_timeout: Duration) -> Self {
+        assert!(!outlets.is_empty(), "Aquamarine needs at least one shard");
         Self {
-            outlet,
+            outlets: Arc::new(outlets),
             execution_timeout,
         }
     }
@@ -227,7 +319,8 @@ impl AquamarineApi {
         function: Option<ServiceFunction>,
     ) -> impl Future<Output = Result<(), AquamarineApiError>> {
         let particle_id = particle.particle.id.clone();
-        self.send_command(Ingest { particle, function }, Some(particle_id))
+        let shard = shard_of(&particle_id, self.outlets.len());
+        self.send_command(shard, Ingest { particle, function }, Some(particle_id))
     }
 
     pub fn add_service(
@@ -235,7 +328,9 @@ impl AquamarineApi {
         service: String,
         functions: HashMap<String, ServiceFunction>,
     ) -> impl Future<Output = Result<(), AquamarineApiError>> {
+        // builtins are shared between shards, so any shard can extend them
         self.send_command(
+            0,
             AddService {
                 service,
                 functions,
@@ -249,17 +344,18 @@ impl AquamarineApi {
         self,
         service: String,
     ) -> impl Future<Output = Result<(), AquamarineApiError>> {
-        self.send_command(RemoveService { service }, None)
+        self.send_command(0, RemoveService { service }, None)
     }
 
     fn send_command(
         self,
+        shard: usize,
         command: Command,
         particle_id: Option<String>,
     ) -> impl Future<Output = Result<(), AquamarineApiError>> {
         use AquamarineApiError::*;
 
-        let interpreters = self.outlet;
+        let interpreters = self.outlets[shard].clone();
 
         async move {
             let sent = interpreters.send(command).await;
@@ -272,3 +368,31 @@ impl AquamarineApi {
         .in_current_span()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shard_sizes_cover_total() {
+        let sizes: Vec<_> = (0..3).map(|shard| shard_size(8, 3, shard)).collect();
+        assert_eq!(sizes, vec![3, 3, 2]);
+        assert_eq!(shard_size(8, 1, 0), 8);
+        // every shard gets at least one VM
+        assert_eq!(shard_size(1, 2, 1), 1);
+    }
+
+    #[test]
+    fn particles_stick_to_shard() {
+        let ids: Vec<_> = (0..100).map(|i| format!("particle_{i}")).collect();
+        for id in &ids {
+            let shard = shard_of(id, 4);
+            assert!(shard < 4);
+            assert_eq!(shard, shard_of(id, 4));
+            assert_eq!(shard_of(id, 1), 0);
+        }
+
+        let used: std::collections::HashSet<_> = ids.iter().map(|id| shard_of(id, 4)).collect();
+        assert_eq!(used.len(), 4);
+    }
+}
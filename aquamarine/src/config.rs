@@ -17,9 +17,14 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::spawner::RootPools;
 use fs_utils::to_abs_path;
 use libp2p::PeerId;
+use node_events::EventLog;
+use now_millis::ClockCorrection;
+use particle_args::{ArgsLimits, ArgsRedaction};
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Debug, Clone)]
@@ -41,10 +46,22 @@ pub struct VmConfig {
 
 #[derive(Debug, Clone)]
 pub struct VmPoolConfig {
-    /// Number of VMs to create
+    /// Number of VMs to create, split between the shards
     pub pool_size: usize,
+    /// Number of independent router shards, each running in its own task
+    pub shards: usize,
     /// Timeout of a particle execution
     pub execution_timeout: Duration,
+    /// Limits on arguments of service calls made by particles
+    pub args_limits: ArgsLimits,
+    /// Rules masking arguments of service calls in logs
+    pub args_redaction: Arc<ArgsRedaction>,
+    /// Dedicated runtimes of the host peer for AVM executions and function calls
+    pub root_pools: RootPools,
+    /// Receives the particles expired before execution
+    pub event_log: EventLog,
+    /// Correction of the local clock applied to particle deadlines
+    pub clock_correction: ClockCorrection,
 }
 
 impl VmConfig {
@@ -70,12 +87,43 @@ impl VmConfig {
 }
 
 impl VmPoolConfig {
-    pub fn new(pool_size: usize, execution_timeout: Duration) -> Self {
+    pub fn new(
+        pool_size: usize,
+        shards: usize,
+        execution_timeout: Duration,
+        args_limits: ArgsLimits,
+    ) -> Self {
         Self {
             pool_size,
+            shards: shards.max(1),
             execution_timeout,
+            args_limits,
+            args_redaction: <_>::default(),
+            root_pools: <_>::default(),
+            event_log: <_>::default(),
+            clock_correction: <_>::default(),
         }
     }
+
+    pub fn with_args_redaction(mut self, args_redaction: ArgsRedaction) -> Self {
+        self.args_redaction = Arc::new(args_redaction);
+        self
+    }
+
+    pub fn with_root_pools(mut self, root_pools: RootPools) -> Self {
+        self.root_pools = root_pools;
+        self
+    }
+
+    pub fn with_event_log(mut self, event_log: EventLog) -> Self {
+        self.event_log = event_log;
+        self
+    }
+
+    pub fn with_clock_correction(mut self, clock_correction: ClockCorrection) -> Self {
+        self.clock_correction = clock_correction;
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
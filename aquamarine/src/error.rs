@@ -63,6 +63,20 @@ pub enum AquamarineApiError {
 }
 
 impl AquamarineApiError {
+    pub fn particle_id(&self) -> Option<&str> {
+        match self {
+            AquamarineApiError::ParticleExpired { particle_id }
+            | AquamarineApiError::OneshotCancelled { particle_id }
+            | AquamarineApiError::ExecutionTimedOut { particle_id, .. }
+            | AquamarineApiError::WorkerIsNotActive { particle_id, .. }
+            | AquamarineApiError::SignatureVerificationFailed { particle_id, .. } => {
+                Some(particle_id)
+            }
+            AquamarineApiError::AquamarineDied { particle_id }
+            | AquamarineApiError::AquamarineQueueFull { particle_id } => particle_id.as_deref(),
+        }
+    }
+
     pub fn into_particle_id(self) -> Option<String> {
         match self {
             AquamarineApiError::ParticleExpired { particle_id } => Some(particle_id),
@@ -40,6 +40,7 @@ mod particle_data_store;
 mod particle_executor;
 mod particle_functions;
 mod plumber;
+mod progress;
 mod spawner;
 
 mod aqua_runtime;
@@ -59,3 +60,4 @@ pub use marine_wasmtime_backend::WasmtimeWasmBackend;
 pub use particle_data_store::{DataStoreError, ParticleDataStore};
 pub use particle_services::WasmBackendConfig;
 pub use plumber::Plumber;
+pub use spawner::RootPools;
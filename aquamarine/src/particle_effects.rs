@@ -22,7 +22,7 @@ use std::time::Duration;
 use libp2p::PeerId;
 
 use avm_server::CallRequests;
-use particle_protocol::ExtendedParticle;
+use particle_protocol::{ExtendedParticle, Particle};
 use types::peer_scope::PeerScope;
 
 #[derive(Clone, Debug)]
@@ -34,6 +34,8 @@ pub struct ParticleEffects {
     pub next_peers: Vec<PeerId>,
     /// Instruction to execute host calls
     pub call_requests: CallRequests,
+    /// Particle reporting an interpreter crash, it's sent to `next_peers` instead of the executed particle
+    pub error_particle: Option<Particle>,
 }
 
 impl ParticleEffects {
@@ -42,6 +44,7 @@ impl ParticleEffects {
             new_data: vec![],
             next_peers: vec![],
             call_requests: <_>::default(),
+            error_particle: None,
         }
     }
 }
@@ -53,6 +56,8 @@ pub struct InterpretationStats {
     pub memory_delta: usize,
     pub new_data_len: Option<usize>,
     pub success: bool,
+    /// Interpreter panicked and its instance was dropped
+    pub crashed: bool,
 }
 
 impl InterpretationStats {
@@ -62,6 +67,14 @@ impl InterpretationStats {
             memory_delta: 0,
             new_data_len: None,
             success: false,
+            crashed: false,
+        }
+    }
+
+    pub fn crashed() -> Self {
+        Self {
+            crashed: true,
+            ..Self::failed()
         }
     }
 }
@@ -72,6 +85,8 @@ impl InterpretationStats {
 pub struct RawRoutingEffects {
     pub particle: ExtendedParticle,
     pub next_peers: Vec<PeerId>,
+    /// Results of the host calls to report to the particle initiator
+    pub progress: Option<ExtendedParticle>,
 }
 
 #[derive(Clone, Debug)]
@@ -18,14 +18,17 @@
  */
 
 use async_trait::async_trait;
+use std::any::Any;
 use std::borrow::Cow;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
 use std::time::Instant;
 
 use avm_server::avm_runner::RawAVMOutcome;
 use avm_server::{CallResults, ParticleParameters};
 use fluence_keypair::KeyPair;
-use tokio::task::JoinError;
+use futures::FutureExt;
+use now_millis::now_ms;
 use tracing::instrument;
 
 use fluence_libp2p::PeerId;
@@ -70,6 +73,12 @@ struct AVMCallResult<'a, RT: AquaRuntime> {
     vm: RT,
 }
 
+enum AVMCallError {
+    /// Interpreter panicked, its instance is lost
+    Panic(String),
+    Cancelled,
+}
+
 #[async_trait]
 impl<RT: AquaRuntime> ParticleExecutor for RT {
     type Output = AVMRes<RT>;
@@ -131,13 +140,15 @@ async fn execute_with_prev_data<RT: AquaRuntime>(
     prev_data: Vec<u8>,
 ) -> AVMRes<RT> {
     let particle_id = particle.id.clone();
+    let init_peer_id = particle.init_peer_id;
+    let ttl = particle.ttl;
     let prev_data_len = prev_data.len();
 
     let avm_result = avm_call(
         spawner,
         vm,
         current_peer_id,
-        key_pair,
+        key_pair.clone(),
         particle,
         call_results,
         prev_data,
@@ -148,25 +159,95 @@ async fn execute_with_prev_data<RT: AquaRuntime>(
         Ok(avm_result) => {
             process_avm_result(data_store, current_peer_id, prev_data_len, avm_result).await
         }
-        Err(err) => {
-            if err.is_cancelled() {
-                tracing::warn!(particle_id, "Particle task was cancelled");
-            } else {
-                tracing::error!(particle_id, "Particle task panic");
-            }
-            let stats = InterpretationStats::failed();
-            let effects = ParticleEffects::empty();
+        Err(AVMCallError::Cancelled) => {
+            tracing::warn!(particle_id, "Particle task was cancelled");
             FutResult {
                 // We loose an AVM instance here
                 // But it will be recreated via VmPool
                 runtime: None,
+                effects: ParticleEffects::empty(),
+                stats: InterpretationStats::failed(),
+            }
+        }
+        Err(AVMCallError::Panic(message)) => {
+            tracing::error!(particle_id, "Interpreter panicked: {}", message);
+            let mut effects = ParticleEffects::empty();
+            // Let the caller know that the particle won't be processed any further
+            if init_peer_id != current_peer_id {
+                let error_particle = interpreter_panic_particle(
+                    &particle_id,
+                    init_peer_id,
+                    ttl,
+                    current_peer_id,
+                    &key_pair,
+                    &message,
+                );
+                if let Some(error_particle) = error_particle {
+                    effects.next_peers = vec![init_peer_id];
+                    effects.error_particle = Some(error_particle);
+                }
+            }
+            FutResult {
+                // The panicked AVM instance is dropped, VmPool will create a new one
+                runtime: None,
                 effects,
-                stats,
+                stats: InterpretationStats::crashed(),
             }
         }
     }
 }
 
+/// Particle calling `("error" "interpreter_panic") [particle_id message]` on the caller
+fn interpreter_panic_particle(
+    particle_id: &str,
+    caller: PeerId,
+    ttl: u32,
+    current_peer_id: PeerId,
+    key_pair: &KeyPair,
+    message: &str,
+) -> Option<Particle> {
+    // AIR string literals can't be escaped
+    let literal = |s: &str| s.replace(['"', '\\'], "'");
+    let script = format!(
+        r#"(call "{caller}" ("error" "interpreter_panic") ["{}" "{}"])"#,
+        literal(particle_id),
+        literal(message)
+    );
+    let mut particle = Particle {
+        id: format!("{particle_id}_interpreter_panic"),
+        init_peer_id: current_peer_id,
+        timestamp: now_ms() as u64,
+        ttl,
+        script,
+        signature: vec![],
+        data: vec![],
+        trail: None,
+        progress: false,
+        hops: 0,
+    };
+    match particle.sign(key_pair) {
+        Ok(()) => Some(particle),
+        Err(err) => {
+            tracing::warn!(
+                particle_id,
+                "Could not sign interpreter panic particle: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
 #[instrument(level = tracing::Level::INFO, skip_all)]
 async fn process_avm_result<RT>(
     data_store: Arc<ParticleDataStore>,
@@ -257,8 +338,8 @@ async fn avm_call<'a, RT: AquaRuntime>(
     particle: Particle,
     call_results: CallResults,
     prev_data: Vec<u8>,
-) -> Result<AVMCallResult<'a, RT>, JoinError> {
-    spawner
+) -> Result<AVMCallResult<'a, RT>, AVMCallError> {
+    let result = spawner
         .spawn_avm_call(async move {
             let particle_id = particle.id.clone();
             let now = Instant::now();
@@ -271,16 +352,18 @@ async fn avm_call<'a, RT: AquaRuntime>(
                 ttl: particle.ttl,
             };
             let current_data = &particle.data[..];
-            let avm_outcome = vm
-                .call(
-                    &particle.script,
-                    prev_data,
-                    current_data,
-                    particle_params.clone(),
-                    call_results.clone(),
-                    &key_pair,
-                )
-                .await;
+            // Isolate interpreter panics, so they don't take down the whole task
+            let avm_outcome = AssertUnwindSafe(vm.call(
+                &particle.script,
+                prev_data,
+                current_data,
+                particle_params.clone(),
+                call_results.clone(),
+                &key_pair,
+            ))
+            .catch_unwind()
+            .await
+            .map_err(|payload| panic_message(payload.as_ref()))?;
             let memory_size_after = vm.memory_stats().memory_size;
 
             let interpretation_time = now.elapsed();
@@ -291,15 +374,67 @@ async fn avm_call<'a, RT: AquaRuntime>(
                 interpretation_time,
                 new_data_len,
                 success: avm_outcome.is_ok(),
+                crashed: false,
             };
-            AVMCallResult {
+            Ok::<_, String>(AVMCallResult {
                 avm_outcome,
                 stats,
                 particle,
                 call_results,
                 particle_params,
                 vm,
-            }
+            })
         })
-        .await
+        .await;
+
+    match result {
+        Ok(result) => result.map_err(AVMCallError::Panic),
+        Err(err) if err.is_panic() => Err(AVMCallError::Panic(panic_message(
+            err.into_panic().as_ref(),
+        ))),
+        Err(_) => Err(AVMCallError::Cancelled),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::RandomPeerId;
+
+    use super::{interpreter_panic_particle, panic_message};
+
+    #[test]
+    fn panic_particle_is_signed_and_escaped() {
+        let key_pair = KeyPair::generate_ed25519();
+        let caller = RandomPeerId::random();
+
+        let particle = interpreter_panic_particle(
+            "particle_1",
+            caller,
+            1000,
+            key_pair.get_peer_id(),
+            &key_pair,
+            r#"unexpected "quote" \ here"#,
+        )
+        .expect("create panic particle");
+
+        assert_eq!(particle.id, "particle_1_interpreter_panic");
+        assert_eq!(particle.init_peer_id, key_pair.get_peer_id());
+        assert_eq!(
+            particle.script,
+            format!(
+                r#"(call "{caller}" ("error" "interpreter_panic") ["particle_1" "unexpected 'quote' ' here"])"#
+            )
+        );
+        particle.verify().expect("panic particle must be signed");
+    }
+
+    #[test]
+    fn panic_payload_message() {
+        let payload = std::panic::catch_unwind(|| panic!("static message")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static message");
+
+        let payload = std::panic::catch_unwind(|| panic!("formatted {}", "message")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted message");
+    }
 }
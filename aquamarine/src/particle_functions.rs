@@ -27,11 +27,12 @@ use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
 use humantime::format_duration as pretty;
+use serde::Serialize;
 use serde_json::json;
 use serde_json::Value as JValue;
 use tracing::{instrument, Instrument, Span};
 
-use particle_args::{Args, JError};
+use particle_args::{Args, ArgsLimits, ArgsRedaction, JError};
 use particle_execution::{
     FunctionOutcome, ParticleFunctionStatic, ParticleParams, ServiceFunction,
 };
@@ -56,37 +57,77 @@ pub struct SingleCallStat {
 pub struct SingleCallResult {
     /// `call_id` comes from AVM's CallRequest
     call_id: u32,
+    service_id: String,
+    function_name: String,
     result: CallServiceResult,
     stat: SingleCallStat,
     span: Arc<Span>,
 }
 
+#[derive(Clone, Debug, Serialize, PartialEq)]
+/// Result of a host call reported to the particle initiator in the progress mode
+pub struct CallProgress {
+    pub call_id: u32,
+    pub service_id: String,
+    pub function_name: String,
+    pub ret_code: i32,
+    pub result: JValue,
+}
+
 pub struct Functions<F> {
     particle: ParticleParams,
     builtins: F,
+    args_limits: ArgsLimits,
+    args_redaction: Arc<ArgsRedaction>,
     function_calls: FuturesUnordered<BoxFuture<'static, SingleCallResult>>,
     call_results: CallResults,
     call_stats: Vec<SingleCallStat>,
     call_spans: Vec<Arc<Span>>,
+    /// Finished calls to report, recorded only if the particle asked for progress
+    progress: Option<Vec<CallProgress>>,
     particle_function: Option<Arc<tokio::sync::Mutex<ServiceFunction>>>,
 }
 
 impl<F: ParticleFunctionStatic> Functions<F> {
-    pub fn new(particle: ParticleParams, builtins: F) -> Self {
+    pub fn new(particle: ParticleParams, builtins: F, args_limits: ArgsLimits) -> Self {
         Self {
             particle,
             builtins,
+            args_limits,
+            args_redaction: <_>::default(),
             function_calls: <_>::default(),
             call_results: <_>::default(),
             call_stats: <_>::default(),
             call_spans: <_>::default(),
+            progress: None,
             particle_function: None,
         }
     }
 
+    /// Record results of the finished calls to report them as progress
+    pub fn with_progress(mut self, progress: bool) -> Self {
+        self.progress = progress.then(Vec::new);
+        self
+    }
+
+    /// Mask arguments matched by the rules when logging the calls
+    pub fn with_args_redaction(mut self, args_redaction: Arc<ArgsRedaction>) -> Self {
+        self.args_redaction = args_redaction;
+        self
+    }
+
     /// Advance call requests execution
     pub fn poll(&mut self, cx: &mut Context<'_>) {
         while let Poll::Ready(Some(r)) = self.function_calls.poll_next_unpin(cx) {
+            if let Some(progress) = self.progress.as_mut() {
+                progress.push(CallProgress {
+                    call_id: r.call_id,
+                    service_id: r.service_id,
+                    function_name: r.function_name,
+                    ret_code: r.result.ret_code,
+                    result: r.result.result.clone(),
+                });
+            }
             let overwritten = self.call_results.insert(r.call_id, r.result);
             self.call_stats.push(r.stat);
             self.call_spans.push(r.span);
@@ -134,6 +175,14 @@ impl<F: ParticleFunctionStatic> Functions<F> {
         (call_results, stats, call_spans)
     }
 
+    /// Retrieve results of the calls finished since the previous drain, if progress is recorded
+    pub fn drain_progress(&mut self) -> Vec<CallProgress> {
+        self.progress
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
     pub fn set_function(&mut self, function: ServiceFunction) {
         self.particle_function = Some(Arc::new(tokio::sync::Mutex::new(function)));
     }
@@ -157,8 +206,10 @@ impl<F: ParticleFunctionStatic> Functions<F> {
     ) -> BoxFuture<'static, SingleCallResult> {
         let async_span =
             tracing::info_span!(parent: span.as_ref(), "ParticleFunctions::call::async");
+        let service_id = call.service_id.clone();
+        let function_name = call.function_name.clone();
         // Deserialize params
-        let args = match Args::try_from(call) {
+        let args = match Args::try_from_with_limits(call, &self.args_limits) {
             Ok(args) => args,
             Err(err) => {
                 return async move {
@@ -170,6 +221,8 @@ impl<F: ParticleFunctionStatic> Functions<F> {
                     };
                     SingleCallResult {
                         call_id,
+                        service_id,
+                        function_name,
                         result,
                         stat: SingleCallStat {
                             call_time: None,
@@ -185,13 +238,17 @@ impl<F: ParticleFunctionStatic> Functions<F> {
             }
         };
 
+        // Secrets must not reach logs, so args are redacted before formatting
         let log_args = format!(
             "{:?} {:?} {}",
             args.service_id,
             args.function_name,
-            json!(&args.function_args)
+            json!(self.args_redaction.redact(
+                &args.service_id,
+                &args.function_name,
+                &args.function_args
+            ))
         );
-        let service_id = args.service_id.clone();
 
         let params = self.particle.clone();
         let builtins = self.builtins.clone();
@@ -279,6 +336,8 @@ impl<F: ParticleFunctionStatic> Functions<F> {
 
             SingleCallResult {
                 call_id,
+                service_id,
+                function_name,
                 result,
                 stat: stats,
                 span,
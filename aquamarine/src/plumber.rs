@@ -39,6 +39,9 @@ use fluence_libp2p::PeerId;
 /// For tests, mocked time is used
 #[cfg(test)]
 use mock_time::now_ms;
+use node_events::{EventKind, EventLog};
+use now_millis::ClockCorrection;
+use particle_args::{ArgsLimits, ArgsRedaction};
 use particle_execution::{ParticleFunctionStatic, ParticleParams, ServiceFunction};
 use particle_protocol::ExtendedParticle;
 use particle_services::PeerScope;
@@ -54,7 +57,7 @@ use crate::deadline::Deadline;
 use crate::error::AquamarineApiError;
 use crate::particle_effects::LocalRoutingEffects;
 use crate::particle_functions::{Functions, SingleCallStat};
-use crate::spawner::{RootSpawner, Spawner, WorkerSpawner};
+use crate::spawner::{RootPools, RootSpawner, Spawner, WorkerSpawner};
 use crate::vm_pool::VmPool;
 use crate::{AquaRuntime, ParticleDataStore, RemoteRoutingEffects};
 use types::peer_scope::WorkerId;
@@ -76,13 +79,20 @@ pub struct Plumber<RT: AquaRuntime, F> {
     workers: Arc<Workers>,
     data_store: Arc<ParticleDataStore>,
     builtins: F,
+    args_limits: ArgsLimits,
+    args_redaction: Arc<ArgsRedaction>,
     waker: Option<Waker>,
     metrics: Option<ParticleExecutorMetrics>,
     key_storage: Arc<KeyStorage>,
     scopes: PeerScopes,
     cleanup_future: Option<BoxFuture<'static, ()>>,
     root_runtime_handle: Handle,
+    root_pools: RootPools,
+    /// Particles expired before they were executed are published here
+    event_log: EventLog,
     avm_wasm_backend: WasmtimeWasmBackend,
+    /// Correction of the local clock applied to particle deadlines
+    clock: ClockCorrection,
 }
 
 impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
@@ -91,6 +101,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         host_vm_pool: VmPool<RT>,
         data_store: Arc<ParticleDataStore>,
         builtins: F,
+        args_limits: ArgsLimits,
         metrics: Option<ParticleExecutorMetrics>,
         workers: Arc<Workers>,
         key_storage: Arc<KeyStorage>,
@@ -102,6 +113,8 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             host_vm_pool,
             data_store,
             builtins,
+            args_limits,
+            args_redaction: <_>::default(),
             events: <_>::default(),
             host_actors: <_>::default(),
             worker_actors: <_>::default(),
@@ -113,10 +126,41 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             scopes: scope,
             cleanup_future: None,
             root_runtime_handle: Handle::current(),
+            root_pools: <_>::default(),
+            event_log: <_>::default(),
             avm_wasm_backend,
+            clock: <_>::default(),
         }
     }
 
+    /// Mask arguments of service calls in logs according to the rules
+    pub fn with_args_redaction(mut self, args_redaction: Arc<ArgsRedaction>) -> Self {
+        self.args_redaction = args_redaction;
+        self
+    }
+
+    /// Execute AIR and function calls of the host peer on the dedicated pools
+    pub fn with_root_pools(mut self, root_pools: RootPools) -> Self {
+        self.root_pools = root_pools;
+        self
+    }
+
+    /// Publish particles that expired before they were executed to the event log
+    pub fn with_event_log(mut self, event_log: EventLog) -> Self {
+        self.event_log = event_log;
+        self
+    }
+
+    /// Check particle deadlines against the local clock corrected by the estimated skew
+    pub fn with_clock_correction(mut self, clock: ClockCorrection) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn corrected_now_ms(&self) -> u64 {
+        (now_ms() as i64 - self.clock.get_ms()).max(0) as u64
+    }
+
     /// Receives and ingests incoming particle: creates a new actor or forwards to the existing mailbox
     #[instrument(level = tracing::Level::INFO, skip_all)]
     pub fn ingest(
@@ -126,8 +170,18 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         peer_scope: PeerScope,
     ) {
         let deadline = Deadline::from(particle.as_ref());
-        if deadline.is_expired(now_ms()) {
+        if deadline.is_expired(self.corrected_now_ms()) {
             tracing::info!(target: "expired", particle_id = particle.particle.id, "Particle is expired");
+            let worker_id = match peer_scope {
+                PeerScope::WorkerId(worker_id) => Some(worker_id.to_string()),
+                PeerScope::Host => None,
+            };
+            self.event_log.publish(
+                EventKind::ParticleExpired,
+                particle.particle.id.clone(),
+                worker_id,
+                Some(format!("init peer {}", particle.particle.init_peer_id)),
+            );
             self.events
                 .push_back(Err(AquamarineApiError::ParticleExpired {
                     particle_id: particle.particle.id,
@@ -205,13 +259,18 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
     ) -> eyre::Result<&mut Actor<RT, F>> {
         let plumber_params = PlumberParams {
             builtins: &self.builtins,
+            args_limits: self.args_limits,
+            args_redaction: self.args_redaction.clone(),
             key_storage: self.key_storage.as_ref(),
             data_store: self.data_store.clone(),
         };
         match peer_scope {
             PeerScope::Host => {
                 let current_peer_id = self.scopes.get_host_peer_id();
-                let spawner = Spawner::Root(RootSpawner::new(self.root_runtime_handle.clone()));
+                let spawner = Spawner::Root(RootSpawner::new(
+                    self.root_runtime_handle.clone(),
+                    &self.root_pools,
+                ));
                 let actor_params = ActorParams {
                     key,
                     particle,
@@ -280,7 +339,10 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
                     actor_params.peer_scope,
                     particle_token.clone(),
                 );
-                let functions = Functions::new(params, builtins.clone());
+                let functions =
+                    Functions::new(params, builtins.clone(), plumber_params.args_limits)
+                        .with_progress(actor_params.particle.particle.progress)
+                        .with_args_redaction(plumber_params.args_redaction.clone());
 
                 let actor = Actor::new(
                     &actor_params.particle.particle,
@@ -298,6 +360,11 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
         Ok(actor)
     }
 
+    /// Number of particle actors on the host and all workers
+    pub fn actors_count(&self) -> usize {
+        self.host_actors.len() + self.worker_actors.values().map(HashMap::len).sum::<usize>()
+    }
+
     pub fn add_service(
         &self,
         service: String,
@@ -442,6 +509,20 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             if let Poll::Ready(result) = actor.poll_completed(cx) {
                 interpretation_stats.push(result.stats);
 
+                if let Some(progress) = result.effects.progress {
+                    let init_peer_id = actor.init_peer_id();
+                    match scopes.scope(init_peer_id) {
+                        Err(_) => remote_effects.push(RemoteRoutingEffects {
+                            particle: progress,
+                            next_peers: vec![init_peer_id],
+                        }),
+                        Ok(scope) => local_effects.push(LocalRoutingEffects {
+                            particle: progress,
+                            next_peers: vec![scope],
+                        }),
+                    }
+                }
+
                 let mut remote_peers = vec![];
                 let mut local_peers = vec![];
                 for next_peer in result.effects.next_peers {
@@ -491,6 +572,9 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
                 } else {
                     m.interpretation_failures.get_or_create(&label).inc();
                 }
+                if stat.crashed {
+                    m.interpreter_crashes.get_or_create(&label).inc();
+                }
 
                 let interpretation_time = stat.interpretation_time.as_secs_f64();
                 m.interpretation_time_sec
@@ -516,7 +600,7 @@ impl<RT: AquaRuntime, F: ParticleFunctionStatic> Plumber<RT, F> {
             // Remove expired actors
             let mut cleanup_keys: Vec<(String, PeerId, Vec<u8>, String)> =
                 Vec::with_capacity(MAX_CLEANUP_KEYS_SIZE);
-            let now = now_ms();
+            let now = self.corrected_now_ms();
             self.cleanup_host_actors(&mut cleanup_keys, now);
             self.cleanup_worker_actors(&mut cleanup_keys, now);
 
@@ -647,6 +731,8 @@ where
     F: Clone,
 {
     builtins: &'p F,
+    args_limits: ArgsLimits,
+    args_redaction: Arc<ArgsRedaction>,
     key_storage: &'p KeyStorage,
     data_store: Arc<ParticleDataStore>,
 }
@@ -665,7 +751,7 @@ mod tests {
     use futures::task::noop_waker_ref;
     use workers::{KeyStorage, PeerScopes, Workers};
 
-    use particle_args::Args;
+    use particle_args::{Args, ArgsLimits};
     use particle_execution::{FunctionOutcome, ParticleFunction, ParticleParams, ServiceFunction};
     use particle_protocol::{ExtendedParticle, Particle};
 
@@ -727,6 +813,7 @@ mod tests {
                 new_data: vec![],
                 next_peers: vec![],
                 call_requests: Default::default(),
+                error_particle: None,
             }
         }
 
@@ -816,6 +903,7 @@ mod tests {
             vm_pool,
             data_store,
             builtin_mock,
+            ArgsLimits::default(),
             None,
             workers.clone(),
             key_storage.clone(),
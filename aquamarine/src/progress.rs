@@ -0,0 +1,159 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use fluence_keypair::KeyPair;
+use fluence_libp2p::PeerId;
+use now_millis::now_ms;
+use serde_json::json;
+
+use particle_protocol::Particle;
+
+use crate::particle_functions::CallProgress;
+
+/// Particle calling `("progress" particle_id) [peer_id results]` on the particle initiator,
+/// where `results` is a base64-encoded JSON array of the host calls finished on `current_peer_id`.
+///
+/// Progress particles live as long as the reported particle and are signed by the current peer.
+pub(crate) fn progress_particle(
+    particle: &Particle,
+    seq: u32,
+    current_peer_id: PeerId,
+    key_pair: &KeyPair,
+    calls: &[CallProgress],
+) -> Option<Particle> {
+    let ttl = u32::try_from(particle.time_to_live().as_millis()).unwrap_or(particle.ttl);
+    if ttl == 0 || calls.is_empty() {
+        return None;
+    }
+
+    // AIR string literals can't be escaped
+    let particle_id = particle.id.replace(['"', '\\'], "'");
+    let results = base64.encode(json!(calls).to_string());
+    let script = format!(
+        r#"(call "{}" ("progress" "{particle_id}") ["{current_peer_id}" "{results}"])"#,
+        particle.init_peer_id
+    );
+    let mut progress = Particle {
+        id: format!("{particle_id}_progress_{seq}"),
+        init_peer_id: current_peer_id,
+        timestamp: now_ms() as u64,
+        ttl,
+        script,
+        signature: vec![],
+        data: vec![],
+        trail: None,
+        progress: false,
+        hops: 0,
+    };
+    match progress.sign(key_pair) {
+        Ok(()) => Some(progress),
+        Err(err) => {
+            tracing::warn!(
+                particle_id = particle.id,
+                "Could not sign progress particle: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD as base64, Engine};
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::RandomPeerId;
+    use now_millis::now_ms;
+    use serde_json::{json, Value as JValue};
+
+    use particle_protocol::Particle;
+
+    use super::progress_particle;
+    use crate::particle_functions::CallProgress;
+
+    #[test]
+    fn progress_particle_reports_calls() {
+        let key_pair = KeyPair::generate_ed25519();
+        let init_peer_id = RandomPeerId::random();
+        let particle = Particle {
+            id: "particle_1".to_string(),
+            init_peer_id,
+            timestamp: now_ms() as u64,
+            ttl: 60_000,
+            progress: true,
+            ..<_>::default()
+        };
+        let calls = vec![CallProgress {
+            call_id: 1,
+            service_id: "srv".to_string(),
+            function_name: "fn".to_string(),
+            ret_code: 0,
+            result: json!({"a": "b"}),
+        }];
+
+        let progress = progress_particle(&particle, 2, key_pair.get_peer_id(), &key_pair, &calls)
+            .expect("create progress particle");
+
+        assert_eq!(progress.id, "particle_1_progress_2");
+        assert_eq!(progress.init_peer_id, key_pair.get_peer_id());
+        assert!(
+            !progress.progress,
+            "progress isn't reported for progress particles"
+        );
+        assert!(progress.ttl > 0 && progress.ttl <= particle.ttl);
+        progress.verify().expect("progress particle must be signed");
+
+        let prefix = format!(
+            r#"(call "{init_peer_id}" ("progress" "particle_1") ["{}" ""#,
+            key_pair.get_peer_id()
+        );
+        let results = progress
+            .script
+            .strip_prefix(&prefix)
+            .and_then(|s| s.strip_suffix(r#""])"#))
+            .expect("progress script");
+        let results: JValue = serde_json::from_slice(&base64.decode(results).unwrap()).unwrap();
+        assert_eq!(
+            results,
+            json!([{"call_id": 1, "service_id": "srv", "function_name": "fn", "ret_code": 0, "result": {"a": "b"}}])
+        );
+    }
+
+    #[test]
+    fn no_progress_for_expired_particle() {
+        let key_pair = KeyPair::generate_ed25519();
+        let particle = Particle {
+            timestamp: 1,
+            ttl: 1,
+            progress: true,
+            ..<_>::default()
+        };
+        let calls = vec![CallProgress {
+            call_id: 1,
+            service_id: "srv".to_string(),
+            function_name: "fn".to_string(),
+            ret_code: 0,
+            result: json!(null),
+        }];
+
+        assert!(
+            progress_particle(&particle, 0, key_pair.get_peer_id(), &key_pair, &calls).is_none()
+        );
+    }
+}
@@ -106,6 +106,18 @@ pub enum Spawner {
     Worker(WorkerSpawner),
 }
 
+/// The `RootPools` struct holds dedicated runtimes of the host peer per workload class.
+///
+/// Workloads without a dedicated runtime are executed on the root runtime.
+#[derive(Clone, Debug, Default)]
+pub struct RootPools {
+    /// Runtime executing AIR scripts.
+    pub avm: Option<Handle>,
+
+    /// Runtime executing calls of services and builtins.
+    pub functions: Option<Handle>,
+}
+
 /// The `RootSpawner` struct represents a spawner for the root runtime.
 ///
 /// It implements the `SpawnFunctions` trait to provide methods for spawning asynchronous tasks
@@ -113,12 +125,22 @@ pub enum Spawner {
 #[derive(Clone)]
 pub struct RootSpawner {
     runtime_handle: Handle,
+    avm_handle: Handle,
+    functions_handle: Handle,
 }
 
 impl RootSpawner {
-    /// Creates a new `RootSpawner` instance with the given runtime handle.
-    pub(crate) fn new(runtime_handle: Handle) -> Self {
-        Self { runtime_handle }
+    /// Creates a new `RootSpawner` instance with the given runtime handle,
+    /// AVM and function calls are spawned on the dedicated pools if they're set.
+    pub(crate) fn new(runtime_handle: Handle, pools: &RootPools) -> Self {
+        Self {
+            avm_handle: pools.avm.clone().unwrap_or_else(|| runtime_handle.clone()),
+            functions_handle: pools
+                .functions
+                .clone()
+                .unwrap_or_else(|| runtime_handle.clone()),
+            runtime_handle,
+        }
     }
 }
 
@@ -135,9 +157,9 @@ impl SpawnFunctions for RootSpawner {
         let task_name = format!("Call function root:{}", &function_identity);
         let builder = tokio::task::Builder::new().name(task_name.as_str());
 
-        let handle = self.runtime_handle.clone();
+        let handle = self.functions_handle.clone();
         builder
-            .spawn_blocking_on(move || handle.block_on(fut), &self.runtime_handle)
+            .spawn_blocking_on(move || handle.block_on(fut), &self.functions_handle)
             .expect("Failed to spawn a task")
     }
 
@@ -146,7 +168,7 @@ impl SpawnFunctions for RootSpawner {
         F: Future + Send + 'static,
         F::Output: Send + 'static,
     {
-        self.runtime_handle
+        self.avm_handle
             .spawn_blocking(|| Handle::current().block_on(fut))
     }
 
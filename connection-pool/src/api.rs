@@ -27,7 +27,9 @@ use tokio_stream::wrappers::UnboundedReceiverStream;
 use particle_protocol::ExtendedParticle;
 use particle_protocol::{Contact, SendStatus};
 
-use crate::connection_pool::LifecycleEvent;
+use crate::capture::{CaptureSettings, CaptureStatus};
+use crate::connection_pool::{ConnectionEvent, LifecycleEvent};
+use crate::sessions::{ResumedSession, SessionCommand, SessionError};
 use crate::ConnectionPoolT;
 
 // marked `pub` to be available in benchmarks
@@ -65,6 +67,20 @@ pub enum Command {
     LifecycleEvents {
         out: mpsc::UnboundedSender<LifecycleEvent>,
     },
+    ConnectionEvents {
+        out: mpsc::UnboundedSender<ConnectionEvent>,
+    },
+    StartCapture {
+        settings: CaptureSettings,
+        out: oneshot::Sender<Result<(), String>>,
+    },
+    StopCapture {
+        out: oneshot::Sender<CaptureStatus>,
+    },
+    CaptureStatus {
+        out: oneshot::Sender<CaptureStatus>,
+    },
+    Session(SessionCommand),
 }
 
 #[derive(Clone, Debug)]
@@ -86,6 +102,85 @@ impl ConnectionPoolApi {
         }
         inlet.map(|r| r.unwrap_or_default()).boxed()
     }
+
+    /// Starts capturing protocol messages, replacing the capture in progress if any
+    pub fn start_capture(
+        &self,
+        settings: CaptureSettings,
+    ) -> BoxFuture<'static, Result<(), String>> {
+        let (out, inlet) = oneshot::channel();
+        if self
+            .outlet
+            .send(Command::StartCapture { settings, out })
+            .is_err()
+        {
+            return futures::future::ready(Err("connection pool is stopped".to_string())).boxed();
+        }
+        inlet
+            .map(|r| r.unwrap_or_else(|_| Err("connection pool is stopped".to_string())))
+            .boxed()
+    }
+
+    /// Stops the capture, returns its final status
+    pub fn stop_capture(&self) -> BoxFuture<'static, CaptureStatus> {
+        self.execute(|out| Command::StopCapture { out })
+    }
+
+    pub fn capture_status(&self) -> BoxFuture<'static, CaptureStatus> {
+        self.execute(|out| Command::CaptureStatus { out })
+    }
+
+    fn execute_session<R, F>(&self, cmd: F) -> BoxFuture<'static, Result<R, SessionError>>
+    where
+        R: Send + 'static,
+        F: FnOnce(oneshot::Sender<Result<R, SessionError>>) -> SessionCommand,
+    {
+        let (out, inlet) = oneshot::channel();
+        if self.outlet.send(Command::Session(cmd(out))).is_err() {
+            return futures::future::ready(Err(SessionError::Stopped)).boxed();
+        }
+        inlet
+            .map(|r| r.unwrap_or(Err(SessionError::Stopped)))
+            .boxed()
+    }
+
+    /// Opens a resumable session of a directly connected client, returns its token
+    pub fn open_session(
+        &self,
+        peer_id: PeerId,
+    ) -> BoxFuture<'static, Result<String, SessionError>> {
+        self.execute_session(|out| SessionCommand::Open { peer_id, out })
+    }
+
+    /// Delivers the particles queued for the client while it was disconnected
+    pub fn resume_session(
+        &self,
+        peer_id: PeerId,
+        token: String,
+    ) -> BoxFuture<'static, Result<ResumedSession, SessionError>> {
+        self.execute_session(|out| SessionCommand::Resume {
+            peer_id,
+            token,
+            out,
+        })
+    }
+
+    pub fn revoke_session(
+        &self,
+        peer_id: PeerId,
+        token: String,
+    ) -> BoxFuture<'static, Result<(), SessionError>> {
+        self.execute_session(|out| SessionCommand::Revoke {
+            peer_id,
+            token,
+            out,
+        })
+    }
+
+    /// Fails unless the client is connected directly and has a live session
+    pub fn check_session(&self, peer_id: PeerId) -> BoxFuture<'static, Result<(), SessionError>> {
+        self.execute_session(|out| SessionCommand::Check { peer_id, out })
+    }
 }
 
 impl ConnectionPoolT for ConnectionPoolApi {
@@ -147,4 +242,14 @@ impl ConnectionPoolT for ConnectionPoolApi {
 
         UnboundedReceiverStream::new(inlet).boxed()
     }
+
+    fn connection_events(&self) -> BoxStream<'static, ConnectionEvent> {
+        let (out, inlet) = mpsc::unbounded_channel();
+        let cmd = Command::ConnectionEvents { out };
+        if self.outlet.send(cmd).is_err() {
+            return futures::stream::empty().boxed();
+        };
+
+        UnboundedReceiverStream::new(inlet).boxed()
+    }
 }
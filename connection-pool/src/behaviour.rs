@@ -26,11 +26,12 @@ use libp2p::swarm::{
     THandlerOutEvent, ToSwarm,
 };
 use libp2p::{
-    core::{ConnectedPoint, Multiaddr},
+    core::{multiaddr::Protocol, ConnectedPoint, Multiaddr},
     swarm::{NetworkBehaviour, NotifyHandler, OneShotHandler},
     PeerId,
 };
 use std::pin::Pin;
+use std::time::{Duration, Instant};
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
     task::{Context, Poll, Waker},
@@ -39,13 +40,26 @@ use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_util::sync::PollSender;
 
-use crate::connection_pool::LifecycleEvent;
+use crate::capture::{ActiveCapture, CaptureDirection, CaptureSettings, CaptureStatus};
+use crate::clock_skew::ClockSkewEstimator;
+use crate::connection_pool::{ConnectionEvent, Direction, LifecycleEvent};
+use crate::qos::{InboundQueues, PeerClass, PeerClasses, QosSettings};
+use crate::sessions::{ResumedSession, SessionCommand, SessionError, SessionSettings, Sessions};
 use crate::{Command, ConnectionPoolApi};
 use fluence_libp2p::remote_multiaddr;
+use now_millis::now_ms;
 use particle_protocol::{
-    CompletionChannel, Contact, ExtendedParticle, HandlerMessage, ProtocolConfig, SendStatus,
+    CompletionChannel, Contact, ExtendedParticle, HandlerMessage, ProtocolConfig, ProtocolMessage,
+    SendStatus,
 };
-use peer_metrics::ConnectionPoolMetrics;
+use peer_metrics::{ConnectionPoolMetrics, TransportKind};
+
+/// Max particles forwarded to the outlet in a single `poll`
+const PARTICLES_PER_POLL: usize = 128;
+/// Max API commands executed in a single `poll`
+const COMMANDS_PER_POLL: usize = 128;
+/// How often connections are checked against the idle timeouts of their classes
+const IDLE_CHECK_PERIOD: Duration = Duration::from_secs(5);
 
 // type SwarmEventType = generate_swarm_event_type!(ConnectionPoolBehaviour);
 
@@ -107,8 +121,9 @@ pub struct ConnectionPoolBehaviour {
 
     outlet: PollSender<ExtendedParticle>,
     subscribers: Vec<mpsc::UnboundedSender<LifecycleEvent>>,
+    connection_subscribers: Vec<mpsc::UnboundedSender<ConnectionEvent>>,
 
-    queue: VecDeque<ExtendedParticle>,
+    queue: InboundQueues<ExtendedParticle>,
     contacts: HashMap<PeerId, Peer>,
     dialing: HashMap<Multiaddr, Vec<oneshot::Sender<Option<Contact>>>>,
 
@@ -117,6 +132,14 @@ pub struct ConnectionPoolBehaviour {
     pub(super) protocol_config: ProtocolConfig,
 
     metrics: Option<ConnectionPoolMetrics>,
+    clock_skew: ClockSkewEstimator,
+    capture: Option<ActiveCapture>,
+    /// Resumable sessions of clients, disabled if not set
+    sessions: Option<Sessions<ExtendedParticle>>,
+    /// Limits per class of peers, all peers are treated alike if not set
+    qos: Option<QosSettings>,
+    classes: PeerClasses,
+    idle_check: Option<tokio::time::Interval>,
 }
 
 impl ConnectionPoolBehaviour {
@@ -130,7 +153,96 @@ impl ConnectionPoolBehaviour {
             Command::Send { to, particle, out } => self.send(to, particle, out),
             Command::CountConnections { out } => self.count_connections(out),
             Command::LifecycleEvents { out } => self.add_subscriber(out),
+            Command::ConnectionEvents { out } => self.add_connection_subscriber(out),
+            Command::StartCapture { settings, out } => self.start_capture(settings, out),
+            Command::StopCapture { out } => self.stop_capture(out),
+            Command::CaptureStatus { out } => self.capture_status(out),
+            Command::Session(cmd) => self.execute_session(cmd),
+        }
+    }
+
+    fn execute_session(&mut self, cmd: SessionCommand) {
+        match cmd {
+            SessionCommand::Open { peer_id, out } => {
+                let result = self
+                    .connected_session(peer_id, |sessions| sessions.open(peer_id, now_ms() as u64));
+                out.send(result).ok();
+            }
+            SessionCommand::Resume {
+                peer_id,
+                token,
+                out,
+            } => {
+                let result = self.resume_session(peer_id, &token);
+                out.send(result).ok();
+            }
+            SessionCommand::Revoke {
+                peer_id,
+                token,
+                out,
+            } => {
+                let result = self.connected_session(peer_id, |sessions| {
+                    sessions.revoke(&peer_id, &token, now_ms() as u64)
+                });
+                out.send(result).ok();
+            }
+            SessionCommand::Check { peer_id, out } => {
+                let result = self.connected_session(peer_id, |sessions| {
+                    sessions.check(&peer_id, now_ms() as u64)
+                });
+                out.send(result).ok();
+            }
+        }
+    }
+
+    /// Sessions are managed only by clients connected directly, they're the ones the particles
+    /// are queued for
+    fn connected_session<R>(
+        &mut self,
+        peer_id: PeerId,
+        f: impl FnOnce(&mut Sessions<ExtendedParticle>) -> Result<R, SessionError>,
+    ) -> Result<R, SessionError> {
+        if !self.contacts.contains_key(&peer_id) {
+            return Err(SessionError::NotConnected(peer_id));
+        }
+        let sessions = self.sessions.as_mut().ok_or(SessionError::Disabled)?;
+        f(sessions)
+    }
+
+    /// Sends the particles queued while the client was away, skipping those that expired
+    fn resume_session(
+        &mut self,
+        peer_id: PeerId,
+        token: &str,
+    ) -> Result<ResumedSession, SessionError> {
+        let resumed = self.connected_session(peer_id, |sessions| {
+            sessions.resume(&peer_id, token, now_ms() as u64)
+        })?;
+        let mut result = ResumedSession {
+            delivered: 0,
+            dropped: resumed.dropped,
+        };
+        for particle in resumed.queued {
+            if particle
+                .particle
+                .is_expired_at(self.clock_skew.correction().now_ms())
+            {
+                result.dropped += 1;
+                continue;
+            }
+            result.delivered += 1;
+            self.push_event(ToSwarm::NotifyHandler {
+                peer_id,
+                handler: NotifyHandler::Any,
+                event: HandlerMessage::OutParticle(particle.particle, CompletionChannel::Ignore),
+            });
         }
+        log::debug!(
+            "Session of {peer_id} resumed, {} queued particles delivered, {} dropped",
+            result.delivered,
+            result.dropped
+        );
+        Ok(result)
     }
 
     /// Dial `address`, and send contact back on success
@@ -224,7 +336,7 @@ impl ConnectionPoolBehaviour {
         let _guard = span.enter();
         if to.peer_id == self.peer_id {
             // If particle is sent to the current node, process it locally
-            self.queue.push_back(particle);
+            self.queue.push_local(particle);
             outlet.send(SendStatus::Ok).ok();
             self.wake();
         } else if self.contacts.contains_key(&to.peer_id) {
@@ -235,6 +347,12 @@ impl ConnectionPoolBehaviour {
                 self.peer_id,
                 to.peer_id
             );
+            if let Some(capture) = &self.capture {
+                capture.record(CaptureDirection::Out, to.peer_id, || {
+                    ProtocolMessage::Particle(particle.particle.clone())
+                });
+            }
+            self.classes.active(&to.peer_id, Instant::now());
             // Send particle to remote peer
             self.push_event(ToSwarm::NotifyHandler {
                 peer_id: to.peer_id,
@@ -245,12 +363,30 @@ impl ConnectionPoolBehaviour {
                 ),
             });
         } else {
-            tracing::warn!(
-                particle_id = particle.particle.id,
-                "Won't send particle to contact {}: not connected",
-                to.peer_id
-            );
-            outlet.send(SendStatus::NotConnected).ok();
+            // A disconnected client with a live session gets the particle once it resumes
+            let particle = match self.sessions.as_mut() {
+                Some(sessions) => sessions.enqueue(&to.peer_id, particle, now_ms() as u64),
+                None => Err(particle),
+            };
+            match particle {
+                Ok(()) => {
+                    tracing::debug!(
+                        target: "network",
+                        "{}: queued particle for {} until it resumes its session",
+                        self.peer_id,
+                        to.peer_id
+                    );
+                    outlet.send(SendStatus::Ok).ok();
+                }
+                Err(particle) => {
+                    tracing::warn!(
+                        particle_id = particle.particle.id,
+                        "Won't send particle to contact {}: not connected",
+                        to.peer_id
+                    );
+                    outlet.send(SendStatus::NotConnected).ok();
+                }
+            }
         }
     }
 
@@ -264,6 +400,21 @@ impl ConnectionPoolBehaviour {
         self.subscribers.push(outlet);
     }
 
+    /// Subscribes given channel for all `ConnectionEvent`s
+    pub fn add_connection_subscriber(&mut self, outlet: mpsc::UnboundedSender<ConnectionEvent>) {
+        self.connection_subscribers.push(outlet);
+    }
+
+    /// Notifies subscribers about protocols the peer reported via identify
+    pub fn add_identified_protocols(&mut self, peer_id: PeerId, protocols: Vec<String>) {
+        self.connection_event(ConnectionEvent::Identified { peer_id, protocols });
+    }
+
+    /// Sets the class of a peer once it's known whether it's a node, see [`crate::qos`]
+    pub fn set_peer_class(&mut self, peer_id: PeerId, class: PeerClass) {
+        self.classes.identified(&peer_id, class);
+    }
+
     pub fn add_discovered_addresses(&mut self, peer_id: PeerId, addresses: Vec<Multiaddr>) {
         self.contacts
             .entry(peer_id)
@@ -272,6 +423,44 @@ impl ConnectionPoolBehaviour {
             .extend(addresses);
     }
 
+    pub fn start_capture(
+        &mut self,
+        settings: CaptureSettings,
+        outlet: oneshot::Sender<Result<(), String>>,
+    ) {
+        let dir = settings.dir.clone();
+        let result = ActiveCapture::start(settings)
+            .map(|capture| {
+                log::info!("Started protocol capture to {:?}", dir);
+                self.capture = Some(capture);
+            })
+            .map_err(|err| format!("failed to start capture in {dir:?}: {err}"));
+        outlet.send(result).ok();
+    }
+
+    pub fn stop_capture(&mut self, outlet: oneshot::Sender<CaptureStatus>) {
+        let status = match self.capture.take() {
+            Some(capture) => {
+                log::info!("Stopped protocol capture");
+                CaptureStatus {
+                    active: false,
+                    ..capture.status()
+                }
+            }
+            None => CaptureStatus::default(),
+        };
+        outlet.send(status).ok();
+    }
+
+    pub fn capture_status(&self, outlet: oneshot::Sender<CaptureStatus>) {
+        let status = self
+            .capture
+            .as_ref()
+            .map(|c| c.status())
+            .unwrap_or_default();
+        outlet.send(status).ok();
+    }
+
     fn meter<U, F: Fn(&ConnectionPoolMetrics) -> U>(&self, f: F) {
         self.metrics.as_ref().map(f);
     }
@@ -283,6 +472,7 @@ impl ConnectionPoolBehaviour {
         protocol_config: ProtocolConfig,
         peer_id: PeerId,
         metrics: Option<ConnectionPoolMetrics>,
+        clock_skew: ClockSkewEstimator,
     ) -> (Self, mpsc::Receiver<ExtendedParticle>, ConnectionPoolApi) {
         let (outlet, inlet) = mpsc::channel(buffer);
         let outlet = PollSender::new(outlet);
@@ -297,18 +487,62 @@ impl ConnectionPoolBehaviour {
             outlet,
             commands: UnboundedReceiverStream::new(command_inlet),
             subscribers: <_>::default(),
-            queue: <_>::default(),
+            connection_subscribers: <_>::default(),
+            queue: InboundQueues::new(None),
             contacts: <_>::default(),
             dialing: <_>::default(),
             events: <_>::default(),
             waker: None,
             protocol_config,
             metrics,
+            clock_skew,
+            capture: None,
+            sessions: None,
+            qos: None,
+            classes: <_>::default(),
+            idle_check: None,
         };
 
         (this, inlet, api)
     }
 
+    /// Keep the state of disconnected clients with open sessions for a while, see [`Sessions`]
+    pub fn with_sessions(mut self, settings: SessionSettings) -> Self {
+        self.sessions = Some(Sessions::new(settings));
+        self
+    }
+
+    /// Apply separate limits to nodes and clients, see [`crate::qos`]
+    pub fn with_qos(mut self, settings: QosSettings) -> Self {
+        self.queue = InboundQueues::new(Some(&settings));
+        self.qos = Some(settings);
+        self
+    }
+
+    /// Handler of a new connection, limited according to the class of the peer
+    fn connection_handler(&mut self, peer_id: PeerId, class: PeerClass) -> THandler<Self> {
+        let class = self.classes.connected(peer_id, class, Instant::now());
+        match &self.qos {
+            Some(qos) => self
+                .protocol_config
+                .clone()
+                .handler(qos.limits(class).max_outbound_streams),
+            None => self.protocol_config.clone().into(),
+        }
+    }
+
+    /// Closes the connections idle for longer than allowed for their class
+    fn close_idle_connections(&mut self) {
+        let Some(qos) = &self.qos else { return };
+        for peer_id in self.classes.idle(qos, Instant::now()) {
+            log::debug!(target: "network", "{}: closing idle connection with {}", self.peer_id, peer_id);
+            self.push_event(ToSwarm::CloseConnection {
+                peer_id,
+                connection: All,
+            });
+        }
+    }
+
     fn wake(&self) {
         if let Some(waker) = &self.waker {
             waker.wake_by_ref();
@@ -353,6 +587,11 @@ impl ConnectionPoolBehaviour {
         })
     }
 
+    fn connection_event(&mut self, event: ConnectionEvent) {
+        self.connection_subscribers
+            .retain(|out| out.send(event.clone()).is_ok())
+    }
+
     fn push_event(&mut self, event: SwarmEventType) {
         self.events.push_back(event);
         self.wake();
@@ -361,6 +600,11 @@ impl ConnectionPoolBehaviour {
     fn remove_contact(&mut self, peer_id: &PeerId, reason: &str) {
         if let Some(contact) = self.contacts.remove(peer_id) {
             log::debug!("Contact {} was removed: {}", peer_id, reason);
+            self.clock_skew.remove_peer(peer_id);
+            self.classes.disconnected(peer_id);
+            if let Some(sessions) = self.sessions.as_mut() {
+                sessions.disconnected(peer_id, now_ms() as u64);
+            }
             self.lifecycle_event(LifecycleEvent::Disconnected(Contact::new(
                 *peer_id,
                 contact.addresses().cloned().collect(),
@@ -374,6 +618,11 @@ impl ConnectionPoolBehaviour {
         }
     }
 
+    /// Round-trip time to the peer, used to correct clock skew samples
+    pub fn record_rtt(&mut self, peer_id: PeerId, rtt: Duration) {
+        self.clock_skew.record_rtt(peer_id, rtt);
+    }
+
     fn get_contact_impl(&self, peer_id: PeerId) -> Option<Contact> {
         self.contacts.get(&peer_id).map(|c| Contact {
             peer_id,
@@ -388,6 +637,12 @@ impl ConnectionPoolBehaviour {
         remaining_established: usize,
     ) {
         let multiaddr = remote_multiaddr(cp);
+        self.connection_event(ConnectionEvent::Closed {
+            peer_id: *peer_id,
+            address: multiaddr.clone(),
+            direction: cp.into(),
+            remaining_established,
+        });
         if remaining_established == 0 {
             self.remove_contact(peer_id, "disconnected");
             log::debug!(
@@ -513,7 +768,7 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         &mut self,
         _connection_id: ConnectionId,
         peer_id: PeerId,
-        _local_addr: &Multiaddr,
+        local_addr: &Multiaddr,
         remote_addr: &Multiaddr,
     ) -> Result<THandler<Self>, ConnectionDenied> {
         log::debug!(
@@ -525,13 +780,23 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         );
 
         self.add_connected_address(peer_id, remote_addr.clone());
+        self.connection_event(ConnectionEvent::Established {
+            peer_id,
+            address: remote_addr.clone(),
+            direction: Direction::Inbound,
+        });
 
         self.lifecycle_event(LifecycleEvent::Connected(Contact::new(
             peer_id,
             vec![remote_addr.clone()],
         )));
 
-        Ok(self.protocol_config.clone().into())
+        // Browsers can only connect over WebSocket and WebRTC
+        let class = match transport_kind(local_addr) {
+            TransportKind::Ws | TransportKind::WebRtc => PeerClass::Client,
+            TransportKind::Tcp | TransportKind::Memory => PeerClass::Node,
+        };
+        Ok(self.connection_handler(peer_id, class))
     }
 
     fn handle_pending_outbound_connection(
@@ -569,23 +834,34 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         );
 
         self.add_connected_address(peer_id, addr.clone());
+        self.connection_event(ConnectionEvent::Established {
+            peer_id,
+            address: addr.clone(),
+            direction: Direction::Outbound,
+        });
 
         self.lifecycle_event(LifecycleEvent::Connected(Contact::new(
             peer_id,
             vec![addr.clone()],
         )));
-        Ok(self.protocol_config.clone().into())
+        Ok(self.connection_handler(peer_id, PeerClass::Node))
     }
 
     fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
         match event {
             FromSwarm::ConnectionEstablished(event) => {
+                if let ConnectedPoint::Listener { local_addr, .. } = event.endpoint {
+                    self.meter(|m| m.inbound_connection_established(transport_kind(local_addr)));
+                }
                 for addr in event.failed_addresses {
                     log::warn!("failed to connect to {} {}", addr, event.peer_id);
                     self.cleanup_address(Some(&event.peer_id), addr)
                 }
             }
             FromSwarm::ConnectionClosed(event) => {
+                if let ConnectedPoint::Listener { local_addr, .. } = event.endpoint {
+                    self.meter(|m| m.inbound_connection_closed(transport_kind(local_addr)));
+                }
                 self.on_connection_closed(
                     &event.peer_id,
                     event.endpoint,
@@ -626,6 +902,11 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
             Ok(HandlerMessage::InParticle(particle)) => {
                 tracing::info!(target: "network", particle_id = particle.id,"{}: received particle from {}; queue {}", self.peer_id, from, self.queue.len());
                 let root_span = tracing::info_span!("Particle", particle_id = particle.id);
+                if let Some(capture) = &self.capture {
+                    capture.record(CaptureDirection::In, from, || {
+                        ProtocolMessage::Particle(particle.clone())
+                    });
+                }
 
                 self.meter(|m| {
                     m.incoming_particle(
@@ -634,8 +915,32 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
                         particle.data.len() as f64,
                     )
                 });
-                self.queue
-                    .push_back(ExtendedParticle::new(particle, root_span));
+                // Only particles created by the sender itself carry its current time. Clients
+                // aren't sampled, so they can't shift the deadlines of the node
+                let now = now_ms() as u64;
+                if particle.init_peer_id == from
+                    && self.classes.is_identified_node(&from)
+                    && self.clock_skew.wants_sample(&from, now)
+                    && particle.verify().is_ok()
+                {
+                    let skew = self.clock_skew.observe(from, particle.timestamp, now);
+                    if let Some(skew) = skew {
+                        self.meter(|m| m.clock_skew_ms.set(skew));
+                    }
+                }
+                self.classes.active(&from, Instant::now());
+                let class = self.classes.class(&from);
+                let particle = ExtendedParticle::new(particle, root_span);
+                if let Err(particle) = self.queue.push(class, particle) {
+                    tracing::warn!(
+                        particle_id = particle.particle.id,
+                        "{}: dropped particle from {}: queue of {:?} peers is full",
+                        self.peer_id,
+                        from,
+                        class
+                    );
+                    self.meter(|m| m.particle_dropped(class));
+                }
                 self.wake();
             }
             Ok(HandlerMessage::Upgrade) => {}
@@ -647,15 +952,22 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
     fn poll(&mut self, cx: &mut Context<'_>) -> Poll<SwarmEventType> {
         self.waker = Some(cx.waker().clone());
 
+        let mut exhausted = false;
+        let mut sent = 0;
         loop {
+            if sent >= PARTICLES_PER_POLL {
+                exhausted = true;
+                break;
+            }
             // Check backpressure on the outlet
             let mut outlet = Pin::new(&mut self.outlet);
             match outlet.as_mut().poll_ready(cx) {
                 Poll::Ready(Ok(_)) => {
                     // channel is ready to consume more particles, so send them
-                    if let Some(particle) = self.queue.pop_front() {
+                    if let Some(particle) = self.queue.pop() {
                         let particle_id = particle.particle.id.clone();
 
+                        sent += 1;
                         if let Err(err) = outlet.start_send(particle) {
                             tracing::error!(
                                 particle_id = particle_id,
@@ -694,8 +1006,26 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         }
 
         self.meter(|m| m.particle_queue_size.set(self.queue.len() as i64));
+        if self.qos.is_some() {
+            let idle_check = self
+                .idle_check
+                .get_or_insert_with(|| tokio::time::interval(IDLE_CHECK_PERIOD));
+            if idle_check.poll_tick(cx).is_ready() {
+                self.close_idle_connections();
+            }
+        }
+        let mut executed = 0;
         while let Poll::Ready(Some(cmd)) = self.commands.poll_next_unpin(cx) {
-            self.execute(cmd)
+            self.execute(cmd);
+            executed += 1;
+            if executed >= COMMANDS_PER_POLL {
+                exhausted = true;
+                break;
+            }
+        }
+        // leftover work is resumed on the next poll, after sibling behaviours had their turn
+        if exhausted {
+            cx.waker().wake_by_ref();
         }
 
         if let Some(event) = self.events.pop_front() {
@@ -705,3 +1035,17 @@ impl NetworkBehaviour for ConnectionPoolBehaviour {
         Poll::Pending
     }
 }
+
+/// Transport the connection was accepted on, judging by the listen address
+fn transport_kind(local_addr: &Multiaddr) -> TransportKind {
+    let mut kind = TransportKind::Tcp;
+    for protocol in local_addr.iter() {
+        match protocol {
+            Protocol::WebRTCDirect => return TransportKind::WebRtc,
+            Protocol::Ws(_) | Protocol::Wss(_) => return TransportKind::Ws,
+            Protocol::Memory(_) => kind = TransportKind::Memory,
+            _ => {}
+        }
+    }
+    kind
+}
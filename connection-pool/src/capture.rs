@@ -0,0 +1,308 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Debug capture of protocol messages.
+//!
+//! Transport encryption hides messages from external sniffers, so the node itself can record
+//! the messages it exchanges with selected peers. Every message is written as a JSON line with
+//! a timestamp and a direction to a file in the capture directory. When the file grows above
+//! the limit, it's rotated like a log: `capture.jsonl` becomes `capture.jsonl.1` and so on,
+//! the oldest file is removed.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use now_millis::now_ms;
+use particle_protocol::ProtocolMessage;
+
+pub const CAPTURE_FILE_NAME: &str = "capture.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureDirection {
+    /// Received from the peer
+    In,
+    /// Sent to the peer
+    Out,
+}
+
+/// Single captured message, a line of the capture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    /// Unix timestamp in milliseconds
+    pub timestamp: u64,
+    pub direction: CaptureDirection,
+    pub peer_id: String,
+    pub message: ProtocolMessage,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureSettings {
+    pub dir: PathBuf,
+    /// Peers to capture messages of, all peers if empty
+    pub peers: HashSet<PeerId>,
+    pub max_file_size: u64,
+    /// How many files are kept, including the one being written
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CaptureStatus {
+    pub active: bool,
+    pub peers: Vec<String>,
+    pub file: Option<String>,
+    pub records: u64,
+}
+
+/// Capture in progress. Records are written by a blocking task, which finishes when it's dropped.
+pub(crate) struct ActiveCapture {
+    settings: CaptureSettings,
+    outlet: mpsc::UnboundedSender<CaptureRecord>,
+    records: Arc<AtomicU64>,
+}
+
+impl ActiveCapture {
+    pub fn start(settings: CaptureSettings) -> std::io::Result<Self> {
+        fs::create_dir_all(&settings.dir)?;
+        let mut writer = RotatingWriter::new(
+            settings.dir.join(CAPTURE_FILE_NAME),
+            settings.max_file_size,
+            settings.max_files,
+        );
+        // Each capture starts in a fresh file
+        writer.rotate()?;
+
+        let (outlet, mut inlet) = mpsc::unbounded_channel::<CaptureRecord>();
+        let records = Arc::new(AtomicU64::new(0));
+        let counter = records.clone();
+        tokio::task::spawn_blocking(move || {
+            while let Some(record) = inlet.blocking_recv() {
+                match writer.write(&record) {
+                    Ok(()) => {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(err) => {
+                        log::warn!("Protocol capture stopped, failed to write record: {err}");
+                        return;
+                    }
+                }
+            }
+            writer.flush().ok();
+        });
+
+        Ok(Self {
+            settings,
+            outlet,
+            records,
+        })
+    }
+
+    /// Records a message if the peer is selected, `message` is built only in that case
+    pub fn record(
+        &self,
+        direction: CaptureDirection,
+        peer_id: PeerId,
+        message: impl FnOnce() -> ProtocolMessage,
+    ) {
+        if !self.settings.peers.is_empty() && !self.settings.peers.contains(&peer_id) {
+            return;
+        }
+        let record = CaptureRecord {
+            timestamp: now_ms() as u64,
+            direction,
+            peer_id: peer_id.to_base58(),
+            message: message(),
+        };
+        self.outlet.send(record).ok();
+    }
+
+    pub fn status(&self) -> CaptureStatus {
+        CaptureStatus {
+            active: !self.outlet.is_closed(),
+            peers: self.settings.peers.iter().map(|p| p.to_base58()).collect(),
+            file: Some(
+                self.settings
+                    .dir
+                    .join(CAPTURE_FILE_NAME)
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            records: self.records.load(Ordering::Relaxed),
+        }
+    }
+}
+
+struct RotatingWriter {
+    path: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    file: Option<BufWriter<File>>,
+    written: u64,
+}
+
+impl RotatingWriter {
+    fn new(path: PathBuf, max_file_size: u64, max_files: usize) -> Self {
+        Self {
+            path,
+            max_file_size,
+            max_files: max_files.max(1),
+            file: None,
+            written: 0,
+        }
+    }
+
+    fn write(&mut self, record: &CaptureRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.written > 0 && self.written + line.len() as u64 > self.max_file_size {
+            self.rotate()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => {
+                let file = File::options().create(true).append(true).open(&self.path)?;
+                self.file.insert(BufWriter::new(file))
+            }
+        };
+        file.write_all(&line)?;
+        // A capture is inspected while it's being written
+        file.flush()?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Shifts existing files by one, removing the oldest one
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.flush()?;
+        self.file = None;
+        self.written = 0;
+
+        let oldest = rotated_path(&self.path, self.max_files - 1);
+        if self.max_files == 1 {
+            return remove_if_exists(&self.path);
+        }
+        remove_if_exists(&oldest)?;
+        for n in (1..self.max_files - 1).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                fs::rename(from, rotated_path(&self.path, n + 1))?;
+            }
+        }
+        if self.path.exists() {
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(n: u64) -> CaptureRecord {
+        CaptureRecord {
+            timestamp: n,
+            direction: CaptureDirection::In,
+            peer_id: PeerId::random().to_base58(),
+            message: ProtocolMessage::Upgrade,
+        }
+    }
+
+    fn lines(path: &Path) -> Vec<CaptureRecord> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rotates_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CAPTURE_FILE_NAME);
+        let line_size = serde_json::to_vec(&record(0)).unwrap().len() as u64 + 1;
+        let mut writer = RotatingWriter::new(path.clone(), line_size * 2, 3);
+
+        for n in 0..7 {
+            writer.write(&record(n)).unwrap();
+        }
+
+        let timestamps = |p: &Path| lines(p).iter().map(|r| r.timestamp).collect::<Vec<_>>();
+        assert_eq!(timestamps(&path), vec![6]);
+        assert_eq!(timestamps(&rotated_path(&path, 1)), vec![4, 5]);
+        assert_eq!(timestamps(&rotated_path(&path, 2)), vec![2, 3]);
+        assert!(!rotated_path(&path, 3).exists());
+    }
+
+    #[tokio::test]
+    async fn captures_only_selected_peers() {
+        let dir = tempfile::tempdir().unwrap();
+        let selected = PeerId::random();
+        let capture = ActiveCapture::start(CaptureSettings {
+            dir: dir.path().to_path_buf(),
+            peers: HashSet::from([selected]),
+            max_file_size: 1024 * 1024,
+            max_files: 2,
+        })
+        .unwrap();
+
+        capture.record(CaptureDirection::In, selected, || ProtocolMessage::Upgrade);
+        capture.record(CaptureDirection::Out, PeerId::random(), || {
+            unreachable!("message of other peers isn't built")
+        });
+        capture.record(CaptureDirection::Out, selected, || ProtocolMessage::Upgrade);
+        drop(capture);
+        // let the writer drain the channel
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+        let records = lines(&dir.path().join(CAPTURE_FILE_NAME));
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, CaptureDirection::In);
+        assert_eq!(records[1].direction, CaptureDirection::Out);
+        assert!(records.iter().all(|r| r.peer_id == selected.to_base58()));
+    }
+}
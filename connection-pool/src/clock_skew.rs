@@ -0,0 +1,219 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use libp2p::PeerId;
+use now_millis::ClockCorrection;
+
+/// How many recent samples are kept per peer
+const MAX_SAMPLES: usize = 16;
+/// Min interval between samples of a peer, each one costs a signature verification
+const SAMPLE_INTERVAL_MS: u64 = 1000;
+
+/// Estimates the skew of the local clock from signed timestamps of particles created by directly
+/// connected nodes.
+///
+/// Each sample is `now - timestamp - rtt / 2`, so processing and network delays can only make
+/// a sample larger. As in NTP's minimum-delay filter, the smallest recent sample is taken as
+/// the peer's estimate, and the skew of the node is the median over peers.
+/// Positive skew means the local clock runs ahead of the network.
+#[derive(Debug)]
+pub struct ClockSkewEstimator {
+    warn_threshold: Duration,
+    /// Whether to correct particle deadlines by the estimated skew
+    compensate: bool,
+    /// Bound of the correction, so a few peers can't shift the deadlines arbitrarily
+    max_correction: Duration,
+    correction: ClockCorrection,
+    rtts: HashMap<PeerId, Duration>,
+    samples: HashMap<PeerId, VecDeque<i64>>,
+    /// Local time of the last sample of each peer
+    sampled_at: HashMap<PeerId, u64>,
+    exceeded: bool,
+}
+
+impl ClockSkewEstimator {
+    pub fn new(
+        warn_threshold: Duration,
+        compensate: bool,
+        max_correction: Duration,
+        correction: ClockCorrection,
+    ) -> Self {
+        Self {
+            warn_threshold,
+            compensate,
+            max_correction,
+            correction,
+            rtts: <_>::default(),
+            samples: <_>::default(),
+            sampled_at: <_>::default(),
+            exceeded: false,
+        }
+    }
+
+    /// Correction of the local clock, zero unless compensation is enabled
+    pub fn correction(&self) -> &ClockCorrection {
+        &self.correction
+    }
+
+    /// Whether enough time passed since the last sample of the peer to take a new one
+    pub fn wants_sample(&self, peer_id: &PeerId, now_ms: u64) -> bool {
+        self.sampled_at
+            .get(peer_id)
+            .map_or(true, |at| now_ms.saturating_sub(*at) >= SAMPLE_INTERVAL_MS)
+    }
+
+    pub fn record_rtt(&mut self, peer_id: PeerId, rtt: Duration) {
+        self.rtts.insert(peer_id, rtt);
+    }
+
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.rtts.remove(peer_id);
+        self.samples.remove(peer_id);
+        self.sampled_at.remove(peer_id);
+    }
+
+    /// Records a particle timestamp of `peer_id` and returns the updated estimate
+    pub fn observe(&mut self, peer_id: PeerId, timestamp_ms: u64, now_ms: u64) -> Option<i64> {
+        let half_rtt = self
+            .rtts
+            .get(&peer_id)
+            .map_or(0, |rtt| rtt.as_millis() as i64 / 2);
+        let sample = now_ms as i64 - timestamp_ms as i64 - half_rtt;
+        self.sampled_at.insert(peer_id, now_ms);
+
+        let samples = self.samples.entry(peer_id).or_default();
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+
+        let skew = self.estimate()?;
+        let exceeded = skew.unsigned_abs() > self.warn_threshold.as_millis() as u64;
+        if exceeded != self.exceeded {
+            if exceeded {
+                log::warn!(
+                    "Local clock is skewed by {skew} ms relative to connected peers, particle deadlines may be miscalculated"
+                );
+            } else {
+                log::info!("Local clock skew is back to {skew} ms");
+            }
+            self.exceeded = exceeded;
+        }
+        if self.compensate {
+            let max = self.max_correction.as_millis() as i64;
+            self.correction.set_ms(skew.clamp(-max, max));
+        }
+
+        Some(skew)
+    }
+
+    pub fn estimate(&self) -> Option<i64> {
+        let mut per_peer: Vec<i64> = self
+            .samples
+            .values()
+            .filter_map(|samples| samples.iter().min().copied())
+            .collect();
+        if per_peer.is_empty() {
+            return None;
+        }
+
+        per_peer.sort_unstable();
+        Some(per_peer[per_peer.len() / 2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_minimal_delay_and_median_over_peers() {
+        let correction = ClockCorrection::default();
+        let mut estimator = ClockSkewEstimator::new(
+            Duration::from_secs(1),
+            false,
+            Duration::from_secs(10),
+            correction.clone(),
+        );
+        let now = 1_000_000;
+
+        let a = PeerId::random();
+        estimator.record_rtt(a, Duration::from_millis(100));
+        // delayed particle, then a fresh one
+        estimator.observe(a, now - 550, now);
+        assert_eq!(estimator.observe(a, now - 50, now), Some(0));
+
+        let b = PeerId::random();
+        estimator.observe(b, now - 3000, now);
+        let c = PeerId::random();
+        assert_eq!(estimator.observe(c, now - 2000, now), Some(2000));
+
+        estimator.remove_peer(&c);
+        assert_eq!(estimator.estimate(), Some(3000));
+        // compensation is disabled
+        assert_eq!(correction.get_ms(), 0);
+    }
+
+    #[test]
+    fn correction_is_clamped_and_kept_per_node() {
+        let correction = ClockCorrection::default();
+        let other_node = ClockCorrection::default();
+        let mut estimator = ClockSkewEstimator::new(
+            Duration::from_secs(1),
+            true,
+            Duration::from_secs(5),
+            correction.clone(),
+        );
+        let now = 1_000_000;
+
+        let a = PeerId::random();
+        assert_eq!(estimator.observe(a, now - 2000, now), Some(2000));
+        assert_eq!(correction.get_ms(), 2000);
+
+        let b = PeerId::random();
+        let c = PeerId::random();
+        estimator.observe(b, now - 60_000, now);
+        assert_eq!(estimator.observe(c, now - 90_000, now), Some(60_000));
+        assert_eq!(correction.get_ms(), 5000);
+        assert_eq!(other_node.get_ms(), 0);
+    }
+
+    #[test]
+    fn peers_are_sampled_at_most_once_per_interval() {
+        let mut estimator = ClockSkewEstimator::new(
+            Duration::from_secs(1),
+            false,
+            Duration::from_secs(5),
+            ClockCorrection::default(),
+        );
+        let now = 1_000_000;
+        let a = PeerId::random();
+        assert!(estimator.wants_sample(&a, now));
+
+        estimator.observe(a, now, now);
+        assert!(!estimator.wants_sample(&a, now + SAMPLE_INTERVAL_MS - 1));
+        assert!(estimator.wants_sample(&a, now + SAMPLE_INTERVAL_MS));
+
+        estimator.remove_peer(&a);
+        assert!(estimator.wants_sample(&a, now));
+    }
+}
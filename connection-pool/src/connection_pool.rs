@@ -20,7 +20,10 @@
 use std::fmt::{Display, Formatter};
 
 use futures::{future::BoxFuture, stream::BoxStream};
-use libp2p::{core::Multiaddr, PeerId};
+use libp2p::{
+    core::{ConnectedPoint, Multiaddr},
+    PeerId,
+};
 
 use particle_protocol::{Contact, ExtendedParticle, SendStatus};
 
@@ -39,6 +42,46 @@ impl Display for LifecycleEvent {
     }
 }
 
+/// Direction of a connection relative to this node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl From<&ConnectedPoint> for Direction {
+    fn from(cp: &ConnectedPoint) -> Self {
+        if cp.is_dialer() {
+            Direction::Outbound
+        } else {
+            Direction::Inbound
+        }
+    }
+}
+
+/// Low-level connection events, more detailed than [`LifecycleEvent`]
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    /// A connection is established. There could be several connections with the same peer.
+    Established {
+        peer_id: PeerId,
+        address: Multiaddr,
+        direction: Direction,
+    },
+    /// A connection is closed
+    Closed {
+        peer_id: PeerId,
+        address: Multiaddr,
+        direction: Direction,
+        remaining_established: usize,
+    },
+    /// The peer reported the protocols it supports via identify
+    Identified {
+        peer_id: PeerId,
+        protocols: Vec<String>,
+    },
+}
+
 pub trait ConnectionPoolT {
     fn dial(&self, addr: Multiaddr) -> BoxFuture<'static, Option<Contact>>;
     fn connect(&self, contact: Contact) -> BoxFuture<'static, bool>;
@@ -48,4 +91,5 @@ pub trait ConnectionPoolT {
     fn send(&self, to: Contact, particle: ExtendedParticle) -> BoxFuture<'static, SendStatus>;
     fn count_connections(&self) -> BoxFuture<'static, usize>;
     fn lifecycle_events(&self) -> BoxStream<'static, LifecycleEvent>;
+    fn connection_events(&self) -> BoxStream<'static, ConnectionEvent>;
 }
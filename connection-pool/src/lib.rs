@@ -34,10 +34,21 @@ pub use api::ConnectionPoolApi;
 // to be available in benchmarks
 pub use api::Command;
 pub use behaviour::ConnectionPoolBehaviour;
+pub use capture::{
+    CaptureDirection, CaptureRecord, CaptureSettings, CaptureStatus, CAPTURE_FILE_NAME,
+};
+pub use clock_skew::ClockSkewEstimator;
+pub use qos::{ClassLimits, PeerClass, QosSettings};
+pub use sessions::{ResumedSession, SessionCommand, SessionError, SessionSettings};
 
 pub use crate::connection_pool::ConnectionPoolT;
 pub use crate::connection_pool::LifecycleEvent;
+pub use crate::connection_pool::{ConnectionEvent, Direction};
 
 mod api;
 mod behaviour;
+mod capture;
+mod clock_skew;
 mod connection_pool;
+mod qos;
+mod sessions;
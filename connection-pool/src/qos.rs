@@ -0,0 +1,248 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Quality of service classes of connected peers.
+//!
+//! Other nodes and clients share the connection pool, but not its limits: particles from nodes
+//! are handed over for execution before those from clients, each class has its own inbound queue
+//! limit and number of concurrent outbound substreams, and idle client connections may be closed
+//! sooner. A peer is a node if it runs Kademlia, which is known once Identify completes. Until then
+//! peers connected over WebSocket or WebRTC are taken for clients, and the rest for nodes.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+pub use peer_metrics::PeerClass;
+
+#[derive(Debug, Clone)]
+pub struct ClassLimits {
+    /// Particles from peers of the class waiting for execution, new ones are dropped beyond that
+    pub max_inbound_queue: usize,
+    /// Outbound substreams opened at once on a connection, the rest wait for them to finish
+    pub max_outbound_streams: u32,
+    /// Connections without particles for that long are closed, only the swarm idle timeout
+    /// applies if not set
+    pub idle_timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QosSettings {
+    pub node: ClassLimits,
+    pub client: ClassLimits,
+}
+
+impl QosSettings {
+    pub fn limits(&self, class: PeerClass) -> &ClassLimits {
+        match class {
+            PeerClass::Node => &self.node,
+            PeerClass::Client => &self.client,
+        }
+    }
+}
+
+/// Received particles waiting for execution, queued by the class of the sender.
+/// Particles from nodes and from the node itself are handed over first.
+pub(crate) struct InboundQueues<T> {
+    node: VecDeque<T>,
+    client: VecDeque<T>,
+    max_node: usize,
+    max_client: usize,
+}
+
+impl<T> InboundQueues<T> {
+    pub fn new(settings: Option<&QosSettings>) -> Self {
+        Self {
+            node: <_>::default(),
+            client: <_>::default(),
+            max_node: settings.map_or(usize::MAX, |s| s.node.max_inbound_queue),
+            max_client: settings.map_or(usize::MAX, |s| s.client.max_inbound_queue),
+        }
+    }
+
+    /// Returns the item back if the queue of the class is full
+    pub fn push(&mut self, class: PeerClass, item: T) -> Result<(), T> {
+        let (queue, max) = match class {
+            PeerClass::Node => (&mut self.node, self.max_node),
+            PeerClass::Client => (&mut self.client, self.max_client),
+        };
+        if queue.len() >= max {
+            return Err(item);
+        }
+        queue.push_back(item);
+        Ok(())
+    }
+
+    /// Particles sent by the node to itself aren't limited
+    pub fn push_local(&mut self, item: T) {
+        self.node.push_back(item);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.node.pop_front().or_else(|| self.client.pop_front())
+    }
+
+    pub fn len(&self) -> usize {
+        self.node.len() + self.client.len()
+    }
+}
+
+struct PeerQos {
+    class: PeerClass,
+    /// Whether the class is reported by identify rather than assumed
+    identified: bool,
+    last_activity: Instant,
+}
+
+/// Classes of the connected peers and when they last sent or received a particle
+#[derive(Default)]
+pub(crate) struct PeerClasses {
+    peers: HashMap<PeerId, PeerQos>,
+}
+
+impl PeerClasses {
+    /// Class of a connected peer, nodes are assumed for unknown peers
+    pub fn class(&self, peer_id: &PeerId) -> PeerClass {
+        self.peers
+            .get(peer_id)
+            .map_or(PeerClass::Node, |peer| peer.class)
+    }
+
+    /// Sets the class of a newly connected peer, keeps the known one if it's connected already
+    pub fn connected(&mut self, peer_id: PeerId, class: PeerClass, now: Instant) -> PeerClass {
+        self.peers
+            .entry(peer_id)
+            .or_insert(PeerQos {
+                class,
+                identified: false,
+                last_activity: now,
+            })
+            .class
+    }
+
+    pub fn identified(&mut self, peer_id: &PeerId, class: PeerClass) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.class = class;
+            peer.identified = true;
+        }
+    }
+
+    /// Whether the peer is connected and reported as a node by identify
+    pub fn is_identified_node(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .is_some_and(|peer| peer.identified && peer.class == PeerClass::Node)
+    }
+
+    pub fn active(&mut self, peer_id: &PeerId, now: Instant) {
+        if let Some(peer) = self.peers.get_mut(peer_id) {
+            peer.last_activity = now;
+        }
+    }
+
+    pub fn disconnected(&mut self, peer_id: &PeerId) {
+        self.peers.remove(peer_id);
+    }
+
+    /// Peers idle for longer than the idle timeout of their class
+    pub fn idle(&self, settings: &QosSettings, now: Instant) -> Vec<PeerId> {
+        self.peers
+            .iter()
+            .filter(|(_, peer)| {
+                settings
+                    .limits(peer.class)
+                    .idle_timeout
+                    .map_or(false, |timeout| {
+                        now.duration_since(peer.last_activity) >= timeout
+                    })
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> QosSettings {
+        QosSettings {
+            node: ClassLimits {
+                max_inbound_queue: 2,
+                max_outbound_streams: 32,
+                idle_timeout: None,
+            },
+            client: ClassLimits {
+                max_inbound_queue: 1,
+                max_outbound_streams: 8,
+                idle_timeout: Some(Duration::from_secs(10)),
+            },
+        }
+    }
+
+    #[test]
+    fn node_particles_go_first() {
+        let settings = settings();
+        let mut queues = InboundQueues::new(Some(&settings));
+        queues.push(PeerClass::Client, 1).unwrap();
+        assert_eq!(queues.push(PeerClass::Client, 2), Err(2));
+        queues.push(PeerClass::Node, 3).unwrap();
+        queues.push_local(4);
+
+        assert_eq!(queues.len(), 3);
+        assert_eq!(queues.pop(), Some(3));
+        assert_eq!(queues.pop(), Some(4));
+        assert_eq!(queues.pop(), Some(1));
+        assert_eq!(queues.pop(), None);
+    }
+
+    #[test]
+    fn queues_are_unbounded_without_settings() {
+        let mut queues = InboundQueues::new(None);
+        for i in 0..100 {
+            queues.push(PeerClass::Client, i).unwrap();
+        }
+        assert_eq!(queues.len(), 100);
+    }
+
+    #[test]
+    fn only_idle_clients_are_reported() {
+        let settings = settings();
+        let start = Instant::now();
+        let node = PeerId::random();
+        let client = PeerId::random();
+        let identified = PeerId::random();
+
+        let mut classes = PeerClasses::default();
+        classes.connected(node, PeerClass::Node, start);
+        classes.connected(client, PeerClass::Client, start);
+        classes.connected(identified, PeerClass::Client, start);
+        classes.identified(&identified, PeerClass::Node);
+        assert_eq!(classes.class(&identified), PeerClass::Node);
+        // connected nodes are assumed, not identified
+        assert!(!classes.is_identified_node(&node));
+        assert!(classes.is_identified_node(&identified));
+
+        let later = start + Duration::from_secs(11);
+        assert_eq!(classes.idle(&settings, later), vec![client]);
+
+        classes.active(&client, later);
+        assert!(classes.idle(&settings, later).is_empty());
+    }
+}
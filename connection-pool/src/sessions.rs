@@ -0,0 +1,328 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Resumable client sessions.
+//!
+//! A client opens a session on its relay and gets a token. When the client disconnects,
+//! the relay queues particles addressed to it for [`SessionSettings::window`]. The client
+//! reconnects within the window and presents the token to get the queued particles.
+//! Provider registrations of the client are kept by the builtins for the same window.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use libp2p::PeerId;
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+const TOKEN_LEN: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct SessionSettings {
+    /// How long a session outlives the connection of its client
+    pub window: Duration,
+    /// Particles queued for a disconnected client, the oldest are dropped beyond that
+    pub max_queued: usize,
+    /// Sessions of connected and disconnected clients kept at once
+    pub max_sessions: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SessionError {
+    #[error("Session resumption is disabled on this node")]
+    Disabled,
+    #[error("Connection pool is stopped")]
+    Stopped,
+    #[error("Peer {0} must be connected directly to manage its session")]
+    NotConnected(PeerId),
+    #[error("Peer {0} has no session, it was never opened, expired or was revoked")]
+    NotFound(PeerId),
+    #[error("Session token of peer {0} doesn't match")]
+    InvalidToken(PeerId),
+    #[error("Peer {0} can't register as a provider of more than {1} aliases")]
+    TooManyRegistrations(PeerId, usize),
+    #[error("Node already keeps {0} sessions, no more can be opened")]
+    TooManySessions(usize),
+}
+
+/// What the client gets back when it resumes the session
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ResumedSession {
+    /// Queued particles delivered to the client
+    pub delivered: usize,
+    /// Particles dropped because the queue was full or their TTL ended
+    pub dropped: usize,
+}
+
+#[derive(Debug)]
+pub enum SessionCommand {
+    Open {
+        peer_id: PeerId,
+        out: oneshot::Sender<Result<String, SessionError>>,
+    },
+    Resume {
+        peer_id: PeerId,
+        token: String,
+        out: oneshot::Sender<Result<ResumedSession, SessionError>>,
+    },
+    Revoke {
+        peer_id: PeerId,
+        token: String,
+        out: oneshot::Sender<Result<(), SessionError>>,
+    },
+    Check {
+        peer_id: PeerId,
+        out: oneshot::Sender<Result<(), SessionError>>,
+    },
+}
+
+struct Session<T> {
+    token: String,
+    /// When the client disconnected, `None` while it's connected
+    disconnected_at: Option<u64>,
+    queue: VecDeque<T>,
+    dropped: usize,
+}
+
+/// Taken from the session on resumption
+pub struct Resumed<T> {
+    pub queued: Vec<T>,
+    pub dropped: usize,
+}
+
+pub struct Sessions<T> {
+    settings: SessionSettings,
+    sessions: HashMap<PeerId, Session<T>>,
+}
+
+impl<T> Sessions<T> {
+    pub fn new(settings: SessionSettings) -> Self {
+        Self {
+            settings,
+            sessions: <_>::default(),
+        }
+    }
+
+    /// Opens a session of a connected client. If the client already has one, its token
+    /// is replaced, so the previous token can't be used anymore.
+    pub fn open(&mut self, peer_id: PeerId, now: u64) -> Result<String, SessionError> {
+        self.expire(now);
+        let max_sessions = self.settings.max_sessions;
+        if !self.sessions.contains_key(&peer_id) && self.sessions.len() >= max_sessions {
+            return Err(SessionError::TooManySessions(max_sessions));
+        }
+        let token = Alphanumeric.sample_string(&mut rand::thread_rng(), TOKEN_LEN);
+        let session = self.sessions.entry(peer_id).or_insert_with(|| Session {
+            token: String::new(),
+            disconnected_at: None,
+            queue: <_>::default(),
+            dropped: 0,
+        });
+        session.token = token.clone();
+        Ok(token)
+    }
+
+    /// Starts the resumption window of the client session, if there's one
+    pub fn disconnected(&mut self, peer_id: &PeerId, now: u64) {
+        self.expire(now);
+        if let Some(session) = self.sessions.get_mut(peer_id) {
+            session.disconnected_at = Some(now);
+        }
+    }
+
+    /// Queues the particle for a client with a live session, gives it back otherwise
+    pub fn enqueue(&mut self, peer_id: &PeerId, item: T, now: u64) -> Result<(), T> {
+        self.expire(now);
+        let max_queued = self.settings.max_queued;
+        match self.sessions.get_mut(peer_id) {
+            Some(session) if max_queued > 0 => {
+                if session.queue.len() >= max_queued {
+                    session.queue.pop_front();
+                    session.dropped += 1;
+                }
+                session.queue.push_back(item);
+                Ok(())
+            }
+            _ => Err(item),
+        }
+    }
+
+    /// Hands the queued particles over to the client, which must be connected again by now
+    pub fn resume(
+        &mut self,
+        peer_id: &PeerId,
+        token: &str,
+        now: u64,
+    ) -> Result<Resumed<T>, SessionError> {
+        let session = self.authorized(peer_id, token, now)?;
+        session.disconnected_at = None;
+        Ok(Resumed {
+            queued: session.queue.drain(..).collect(),
+            dropped: std::mem::take(&mut session.dropped),
+        })
+    }
+
+    /// Drops the session along with its queued particles
+    pub fn revoke(&mut self, peer_id: &PeerId, token: &str, now: u64) -> Result<(), SessionError> {
+        self.authorized(peer_id, token, now)?;
+        self.sessions.remove(peer_id);
+        Ok(())
+    }
+
+    /// Fails unless the client has a live session
+    pub fn check(&mut self, peer_id: &PeerId, now: u64) -> Result<(), SessionError> {
+        self.expire(now);
+        if !self.sessions.contains_key(peer_id) {
+            return Err(SessionError::NotFound(*peer_id));
+        }
+        Ok(())
+    }
+
+    fn authorized(
+        &mut self,
+        peer_id: &PeerId,
+        token: &str,
+        now: u64,
+    ) -> Result<&mut Session<T>, SessionError> {
+        self.expire(now);
+        let session = self
+            .sessions
+            .get_mut(peer_id)
+            .ok_or(SessionError::NotFound(*peer_id))?;
+        if session.token != token {
+            return Err(SessionError::InvalidToken(*peer_id));
+        }
+        Ok(session)
+    }
+
+    fn expire(&mut self, now: u64) {
+        let window = self.settings.window.as_millis() as u64;
+        self.sessions.retain(|peer_id, session| {
+            let live = session
+                .disconnected_at
+                .map_or(true, |at| now < at.saturating_add(window));
+            if !live {
+                log::debug!(
+                    "Session of {peer_id} expired, {} queued particles dropped",
+                    session.queue.len()
+                );
+            }
+            live
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000_000;
+
+    fn sessions() -> Sessions<u32> {
+        Sessions::new(SessionSettings {
+            window: Duration::from_secs(60),
+            max_queued: 2,
+            max_sessions: 2,
+        })
+    }
+
+    #[test]
+    fn resume_within_window() {
+        let mut sessions = sessions();
+        let client = PeerId::random();
+        let token = sessions.open(client, NOW).unwrap();
+
+        sessions.disconnected(&client, NOW);
+        for particle in 1..=3 {
+            sessions.enqueue(&client, particle, NOW + 1).unwrap();
+        }
+        assert_eq!(sessions.check(&client, NOW + 2), Ok(()));
+
+        assert_eq!(
+            sessions.resume(&client, "guess", NOW + 2).err(),
+            Some(SessionError::InvalidToken(client))
+        );
+        let resumed = sessions.resume(&client, &token, NOW + 2).unwrap();
+        assert_eq!(resumed.queued, vec![2, 3]);
+        assert_eq!(resumed.dropped, 1);
+
+        // the session outlives the resumed connection
+        sessions.disconnected(&client, NOW + 120_000);
+        assert!(sessions.resume(&client, &token, NOW + 150_000).is_ok());
+    }
+
+    #[test]
+    fn session_expires() {
+        let mut sessions = sessions();
+        let client = PeerId::random();
+        let token = sessions.open(client, NOW).unwrap();
+        sessions.disconnected(&client, NOW);
+
+        let expired_at = NOW + 60_000;
+        assert_eq!(sessions.enqueue(&client, 1, expired_at), Err(1));
+        assert_eq!(
+            sessions.check(&client, expired_at),
+            Err(SessionError::NotFound(client))
+        );
+        assert_eq!(
+            sessions.resume(&client, &token, expired_at).err(),
+            Some(SessionError::NotFound(client))
+        );
+    }
+
+    #[test]
+    fn revoke_and_reopen() {
+        let mut sessions = sessions();
+        let client = PeerId::random();
+        let first = sessions.open(client, NOW).unwrap();
+        let second = sessions.open(client, NOW).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            sessions.revoke(&client, &first, NOW).err(),
+            Some(SessionError::InvalidToken(client))
+        );
+
+        sessions.revoke(&client, &second, NOW).unwrap();
+        assert!(sessions.check(&client, NOW).is_err());
+        assert_eq!(sessions.enqueue(&client, 1, NOW), Err(1));
+    }
+
+    #[test]
+    fn live_sessions_are_capped() {
+        let mut sessions = sessions();
+        let first = PeerId::random();
+        let second = PeerId::random();
+        sessions.open(first, NOW).unwrap();
+        sessions.open(second, NOW).unwrap();
+        // reopening doesn't count as a new session
+        sessions.open(first, NOW).unwrap();
+
+        let third = PeerId::random();
+        assert_eq!(
+            sessions.open(third, NOW),
+            Err(SessionError::TooManySessions(2))
+        );
+
+        // expired sessions free their slots
+        sessions.disconnected(&first, NOW);
+        assert!(sessions.open(third, NOW + 60_000).is_ok());
+    }
+}
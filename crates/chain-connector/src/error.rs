@@ -63,6 +63,16 @@ pub enum ConnectorError {
     ResponseParseError(String),
     #[error("Parse error: {0}")]
     ParseError(#[from] serde_json::Error),
+    #[error("RPC endpoint '{0}' is not in the allowlist")]
+    EndpointNotAllowed(String),
+    #[error("Rate limit of RPC endpoint '{0}' is exceeded")]
+    RateLimited(String),
+    #[error(
+        "User id '{0}' can't call RPC endpoints: only host, management peer and local spells can"
+    )]
+    CallerNotAllowed(String),
+    #[error("Egress policy of worker {worker_id} doesn't allow RPC endpoint '{endpoint}'")]
+    EgressDenied { worker_id: String, endpoint: String },
 }
 
 pub fn process_response<T>(response: Result<T, RPCError>) -> Result<T, ConnectorError> {
@@ -25,6 +25,7 @@
 mod connector;
 mod error;
 mod function;
+mod rpc;
 
 mod types;
 
@@ -33,3 +34,4 @@ pub use connector::ChainConnector;
 pub use connector::HttpChainConnector;
 pub use error::ConnectorError;
 pub use function::*;
+pub use rpc::RpcBuiltins;
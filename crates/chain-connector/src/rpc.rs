@@ -0,0 +1,469 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::FutureExt;
+use jsonrpsee::core::client::ClientT;
+use jsonrpsee::core::params::ArrayParams;
+use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
+use parking_lot::Mutex;
+use serde_json::{json, Value as JValue};
+
+use particle_args::{Args, ErrorCode, JError};
+use particle_builtins::{wrap, CustomService};
+use particle_execution::{ParticleParams, ServiceFunction};
+use server_config::{RpcConfig, WorkerEgressConfig};
+use types::peer_scope::PeerScope;
+use workers::PeerScopes;
+
+use crate::error::process_response;
+use crate::ConnectorError;
+
+/// Fixed-window limiter of requests per second, each peer scope has its own window
+/// so that one worker can't use up the limit of the others
+struct RateLimiter {
+    max_per_sec: u32,
+    windows: Mutex<HashMap<PeerScope, (Instant, u32)>>,
+}
+
+impl RateLimiter {
+    fn new(max_per_sec: u32) -> Self {
+        Self {
+            max_per_sec,
+            windows: <_>::default(),
+        }
+    }
+
+    fn try_acquire(&self, peer_scope: PeerScope) -> bool {
+        let mut windows = self.windows.lock();
+        let now = Instant::now();
+        windows.retain(|_, (start, _)| now.duration_since(*start) < Duration::from_secs(1));
+        let (_, count) = windows.entry(peer_scope).or_insert((now, 0));
+        if *count >= self.max_per_sec {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+struct RpcEndpoint {
+    client: HttpClient,
+    limiter: RateLimiter,
+}
+
+/// Generic JSON-RPC client available to spells as the `rpc` builtin.
+/// Only the endpoints allowed in [`RpcConfig`] can be reached,
+/// and calls on workers are further restricted by their egress policy.
+pub struct RpcBuiltins {
+    endpoints: HashMap<String, RpcEndpoint>,
+    egress: WorkerEgressConfig,
+    scopes: PeerScopes,
+}
+
+impl RpcBuiltins {
+    pub fn new(
+        config: &RpcConfig,
+        egress: &WorkerEgressConfig,
+        scopes: PeerScopes,
+    ) -> eyre::Result<(Arc<Self>, HashMap<String, CustomService>)> {
+        let endpoints = config
+            .allowed_endpoints
+            .iter()
+            .map(|url| {
+                let client = HttpClientBuilder::default()
+                    .max_response_size(config.max_response_size)
+                    .request_timeout(config.request_timeout)
+                    .build(url)?;
+                let endpoint = RpcEndpoint {
+                    client,
+                    limiter: RateLimiter::new(config.max_requests_per_sec),
+                };
+                Ok((url.clone(), endpoint))
+            })
+            .collect::<eyre::Result<_>>()?;
+
+        let rpc = Arc::new(Self {
+            endpoints,
+            egress: egress.clone(),
+            scopes,
+        });
+        let builtins = Self::make_rpc_builtins(rpc.clone());
+        Ok((rpc, builtins))
+    }
+
+    fn make_rpc_builtins(rpc: Arc<Self>) -> HashMap<String, CustomService> {
+        let mut builtins = HashMap::new();
+        builtins.insert(
+            "rpc".to_string(),
+            CustomService::new(
+                vec![
+                    ("call", Self::make_call_closure(rpc.clone())),
+                    ("eth_call", Self::make_eth_call_closure(rpc.clone())),
+                    ("endpoints", Self::make_endpoints_closure(rpc.clone())),
+                    (
+                        "egress_policy",
+                        Self::make_egress_policy_closure(rpc.clone()),
+                    ),
+                ],
+                None,
+            ),
+        );
+        builtins
+    }
+
+    fn make_call_closure(rpc: Arc<Self>) -> ServiceFunction {
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let rpc = rpc.clone();
+            async move { wrap(rpc.call_builtin(args, params).await) }.boxed()
+        }))
+    }
+
+    fn make_eth_call_closure(rpc: Arc<Self>) -> ServiceFunction {
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let rpc = rpc.clone();
+            async move { wrap(rpc.eth_call_builtin(args, params).await) }.boxed()
+        }))
+    }
+
+    fn make_egress_policy_closure(rpc: Arc<Self>) -> ServiceFunction {
+        ServiceFunction::Immut(Box::new(move |_, params| {
+            let rpc = rpc.clone();
+            async move { wrap(Ok(json!(rpc.egress_policy(params.peer_scope)))) }.boxed()
+        }))
+    }
+
+    fn make_endpoints_closure(rpc: Arc<Self>) -> ServiceFunction {
+        ServiceFunction::Immut(Box::new(move |_, _| {
+            let rpc = rpc.clone();
+            async move { wrap(Ok(json!(rpc.endpoints()))) }.boxed()
+        }))
+    }
+
+    /// rpc.call(endpoint, method, params) -> result
+    async fn call_builtin(&self, args: Args, particle: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let endpoint: String = Args::next("endpoint", &mut args)?;
+        let method: String = Args::next("method", &mut args)?;
+        let params: Vec<JValue> = Args::next("params", &mut args)?;
+
+        self.check_caller(&particle)?;
+        self.check_egress(particle.peer_scope, &endpoint)?;
+        self.call(particle.peer_scope, &endpoint, &method, params)
+            .await
+            .map_err(|err| JError::new(format!("RPC call {method} failed: {err}")))
+    }
+
+    /// rpc.eth_call(endpoint, to, data, block?) -> result
+    async fn eth_call_builtin(
+        &self,
+        args: Args,
+        particle: ParticleParams,
+    ) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let endpoint: String = Args::next("endpoint", &mut args)?;
+        let to: String = Args::next("to", &mut args)?;
+        let data: String = Args::next("data", &mut args)?;
+        let block: Option<String> = Args::next_opt("block", &mut args)?;
+
+        let params = vec![
+            json!({ "to": to, "data": data }),
+            json!(block.unwrap_or("latest".to_string())),
+        ];
+        self.check_caller(&particle)?;
+        self.check_egress(particle.peer_scope, &endpoint)?;
+        self.call(particle.peer_scope, &endpoint, "eth_call", params)
+            .await
+            .map_err(|err| JError::new(format!("RPC call eth_call failed: {err}")))
+    }
+
+    /// Destinations allowed to the caller's worker, empty if egress is unrestricted
+    fn egress_policy(&self, peer_scope: PeerScope) -> Vec<&[String]> {
+        match peer_scope {
+            PeerScope::Host => vec![],
+            PeerScope::WorkerId(worker_id) => self
+                .egress
+                .allowed_for(&worker_id.to_string())
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Only the host, the management peer and spells installed on the host or worker
+    /// may make requests to external endpoints
+    fn check_caller(&self, particle: &ParticleParams) -> Result<(), JError> {
+        let init_peer_id = particle.init_peer_id;
+        // spell particles are sent by the host or worker the spell is installed on
+        let local_spell = ParticleParams::get_spell_id(&particle.id).is_some()
+            && init_peer_id == self.scopes.to_peer_id(particle.peer_scope);
+        if local_spell
+            || self.scopes.is_host(init_peer_id)
+            || self.scopes.is_management(init_peer_id)
+        {
+            Ok(())
+        } else {
+            let err = ConnectorError::CallerNotAllowed(init_peer_id.to_string());
+            Err(JError::with_code(
+                ErrorCode::PermissionDenied,
+                err.to_string(),
+            ))
+        }
+    }
+
+    pub fn check_egress(&self, peer_scope: PeerScope, endpoint: &str) -> Result<(), JError> {
+        let PeerScope::WorkerId(worker_id) = peer_scope else {
+            return Ok(());
+        };
+        let worker_id = worker_id.to_string();
+
+        if self.egress.allows_destination(&worker_id, endpoint) {
+            Ok(())
+        } else {
+            let err = ConnectorError::EgressDenied {
+                worker_id,
+                endpoint: endpoint.to_string(),
+            };
+            Err(JError::with_code(
+                ErrorCode::PermissionDenied,
+                err.to_string(),
+            ))
+        }
+    }
+
+    fn endpoints(&self) -> Vec<&String> {
+        let mut endpoints = self.endpoints.keys().collect::<Vec<_>>();
+        endpoints.sort();
+        endpoints
+    }
+
+    pub async fn call(
+        &self,
+        peer_scope: PeerScope,
+        endpoint: &str,
+        method: &str,
+        params: Vec<JValue>,
+    ) -> Result<JValue, ConnectorError> {
+        let rpc_endpoint = self
+            .endpoints
+            .get(endpoint)
+            .ok_or_else(|| ConnectorError::EndpointNotAllowed(endpoint.to_string()))?;
+        if !rpc_endpoint.limiter.try_acquire(peer_scope) {
+            return Err(ConnectorError::RateLimited(endpoint.to_string()));
+        }
+
+        let mut array_params = ArrayParams::new();
+        for param in params {
+            array_params.insert(param)?;
+        }
+        let response = rpc_endpoint.client.request(method, array_params).await;
+        process_response(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use fluence_keypair::KeyPair;
+    use fluence_libp2p::PeerId;
+    use particle_execution::ParticleParams;
+    use serde_json::json;
+    use server_config::{RpcConfig, WorkerEgressConfig};
+    use tempfile::TempDir;
+    use types::peer_scope::PeerScope;
+    use workers::{KeyStorage, PeerScopes};
+
+    use crate::{ConnectorError, RpcBuiltins};
+
+    fn config(url: String, max_requests_per_sec: u32) -> RpcConfig {
+        RpcConfig {
+            allowed_endpoints: vec![url],
+            max_requests_per_sec,
+            max_response_size: 1024,
+            request_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Builtins of a host without workers, along with the dir of its key storage
+    async fn make_rpc(
+        config: RpcConfig,
+        egress: WorkerEgressConfig,
+    ) -> (Arc<RpcBuiltins>, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let root_key_pair = KeyPair::generate_ed25519();
+        let key_storage = KeyStorage::from_path(dir.path().join("keypairs"), root_key_pair.clone())
+            .await
+            .unwrap();
+        let scopes = PeerScopes::new(
+            root_key_pair.get_peer_id(),
+            PeerId::random(),
+            PeerId::random(),
+            Arc::new(key_storage),
+        );
+        let (rpc, _) = RpcBuiltins::new(&config, &egress, scopes).unwrap();
+        (rpc, dir)
+    }
+
+    fn particle(id: &str, init_peer_id: PeerId, peer_scope: PeerScope) -> ParticleParams {
+        ParticleParams {
+            id: id.to_string(),
+            init_peer_id,
+            peer_scope,
+            timestamp: 0,
+            ttl: 0,
+            script: String::new(),
+            signature: vec![],
+            token: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_allowed_endpoint() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let mock = server
+            .mock("POST", "/")
+            .with_body(r#"{"jsonrpc":"2.0","result":"0x2a","id":0}"#)
+            .create();
+        let (rpc, _dir) = make_rpc(config(url.clone(), 10), <_>::default()).await;
+
+        let result = rpc
+            .call(PeerScope::Host, &url, "eth_blockNumber", vec![])
+            .await
+            .unwrap();
+
+        mock.assert();
+        assert_eq!(result, json!("0x2a"));
+    }
+
+    #[tokio::test]
+    async fn test_endpoint_not_allowed() {
+        let (rpc, _dir) =
+            make_rpc(config("http://127.0.0.1:1".to_string(), 10), <_>::default()).await;
+
+        let result = rpc
+            .call(
+                PeerScope::Host,
+                "http://127.0.0.1:2",
+                "eth_blockNumber",
+                vec![],
+            )
+            .await;
+
+        assert_matches!(result, Err(ConnectorError::EndpointNotAllowed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_per_peer_scope() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        server
+            .mock("POST", "/")
+            .with_body(r#"{"jsonrpc":"2.0","result":"0x2a","id":0}"#)
+            .create();
+        let (rpc, _dir) = make_rpc(config(url.clone(), 1), <_>::default()).await;
+        let worker = PeerScope::WorkerId(PeerId::random().into());
+
+        let first = rpc
+            .call(PeerScope::Host, &url, "eth_blockNumber", vec![])
+            .await;
+        let second = rpc
+            .call(PeerScope::Host, &url, "eth_blockNumber", vec![])
+            .await;
+        let other_scope = rpc.call(worker, &url, "eth_blockNumber", vec![]).await;
+
+        assert!(first.is_ok());
+        assert_matches!(second, Err(ConnectorError::RateLimited(_)));
+        assert!(other_scope.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_response_size_cap() {
+        let mut server = mockito::Server::new_async().await;
+        let url = server.url();
+        let big = "a".repeat(2048);
+        server
+            .mock("POST", "/")
+            .with_body(format!(r#"{{"jsonrpc":"2.0","result":"{big}","id":0}}"#))
+            .create();
+        let (rpc, _dir) = make_rpc(config(url.clone(), 10), <_>::default()).await;
+
+        let result = rpc
+            .call(PeerScope::Host, &url, "eth_blockNumber", vec![])
+            .await;
+
+        assert_matches!(result, Err(ConnectorError::RpcError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_callers() {
+        let (rpc, _dir) =
+            make_rpc(config("http://127.0.0.1:1".to_string(), 10), <_>::default()).await;
+        let host_id = rpc.scopes.get_host_peer_id();
+        let worker_id = PeerId::random();
+        let worker = PeerScope::WorkerId(worker_id.into());
+        let stranger = PeerId::random();
+
+        let allowed = [
+            particle("particle", host_id, PeerScope::Host),
+            particle("spell_some-spell_1", worker_id, worker),
+        ];
+        for particle in allowed {
+            assert!(rpc.check_caller(&particle).is_ok(), "{particle:?}");
+        }
+        let denied = [
+            particle("particle", stranger, worker),
+            particle("particle", worker_id, worker),
+            // spells only act on behalf of the host or worker they're installed on
+            particle("spell_some-spell_1", worker_id, PeerScope::Host),
+        ];
+        for particle in denied {
+            assert!(rpc.check_caller(&particle).is_err(), "{particle:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_egress_policy() {
+        let worker_id = PeerId::random();
+        let egress = WorkerEgressConfig {
+            default_allowed: None,
+            workers: [(
+                worker_id.to_base58(),
+                vec!["rpc.example.com:443".to_string()],
+            )]
+            .into(),
+            trusted_binaries: vec![],
+        };
+        let (rpc, _dir) = make_rpc(config("http://127.0.0.1:1".to_string(), 10), egress).await;
+        let worker = PeerScope::WorkerId(worker_id.into());
+
+        assert!(rpc.check_egress(worker, "https://rpc.example.com").is_ok());
+        assert!(rpc.check_egress(worker, "http://127.0.0.1:1").is_err());
+        assert!(rpc
+            .check_egress(PeerScope::Host, "http://127.0.0.1:1")
+            .is_ok());
+        assert!(rpc.egress_policy(PeerScope::Host).is_empty());
+        assert_eq!(rpc.egress_policy(worker).len(), 1);
+    }
+}
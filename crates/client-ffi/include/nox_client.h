@@ -0,0 +1,64 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#ifndef NOX_CLIENT_H
+#define NOX_CLIENT_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Functions returning a pointer return NULL on error, see nox_last_error.
+ * Strings returned by the library must be freed with nox_string_free. */
+
+typedef struct NoxClient NoxClient;
+
+/* Connect to the node at the multiaddress, e.g. "/dns4/node.example/tcp/9000/ws" */
+NoxClient *nox_client_connect(const char *address);
+
+char *nox_client_peer_id(NoxClient *client);
+
+/* Call service_id.function_name with the arguments given as a JSON array on the target peer,
+ * or on the connected node if target is NULL. Returns the particle id without waiting for the result. */
+char *nox_client_send_call(NoxClient *client,
+                           const char *service_id,
+                           const char *function_name,
+                           const char *args_json,
+                           const char *target);
+
+/* Wait up to timeout_ms for the result of any sent call:
+ * {"particle_id": ..., "result": [...]} or {"particle_id": ..., "error": "..."}.
+ * Returns NULL with nox_last_error() == NULL if nothing arrived in time. */
+char *nox_client_poll_event(NoxClient *client, uint64_t timeout_ms);
+
+void nox_client_free(NoxClient *client);
+
+void nox_string_free(char *s);
+
+/* Error of the last failed call on this thread, NULL if it succeeded.
+ * Owned by the library, valid until the next call on the same thread. */
+const char *nox_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NOX_CLIENT_H */
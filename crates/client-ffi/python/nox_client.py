@@ -0,0 +1,132 @@
+#
+# Nox Fluence Peer
+#
+# Copyright (C) 2024 Fluence DAO
+#
+# This program is free software: you can redistribute it and/or modify
+# it under the terms of the GNU Affero General Public License as
+# published by the Free Software Foundation version 3 of the
+# License.
+#
+# This program is distributed in the hope that it will be useful,
+# but WITHOUT ANY WARRANTY; without even the implied warranty of
+# MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+# GNU Affero General Public License for more details.
+#
+# You should have received a copy of the GNU Affero General Public License
+# along with this program.  If not, see <https://www.gnu.org/licenses/>.
+#
+
+"""Python wrapper of the nox client C ABI.
+
+Build the library with `cargo build --release -p client-ffi` and point NOX_CLIENT_LIB
+to it if it isn't in target/release:
+
+    with Client.connect("/ip4/127.0.0.1/tcp/9999/ws") as client:
+        particle_id = client.send_call("peer", "identify")
+        event = client.poll_event(timeout_ms=10_000)
+"""
+
+import ctypes
+import json
+import os
+import sys
+from pathlib import Path
+
+
+def _library_path():
+    path = os.environ.get("NOX_CLIENT_LIB")
+    if path:
+        return path
+    name = {"darwin": "libnox_client.dylib", "win32": "nox_client.dll"}.get(
+        sys.platform, "libnox_client.so"
+    )
+    return str(Path(__file__).resolve().parents[3] / "target" / "release" / name)
+
+
+_lib = ctypes.CDLL(_library_path())
+
+_lib.nox_client_connect.argtypes = [ctypes.c_char_p]
+_lib.nox_client_connect.restype = ctypes.c_void_p
+_lib.nox_client_peer_id.argtypes = [ctypes.c_void_p]
+_lib.nox_client_peer_id.restype = ctypes.c_void_p
+_lib.nox_client_send_call.argtypes = [ctypes.c_void_p] + [ctypes.c_char_p] * 4
+_lib.nox_client_send_call.restype = ctypes.c_void_p
+_lib.nox_client_poll_event.argtypes = [ctypes.c_void_p, ctypes.c_uint64]
+_lib.nox_client_poll_event.restype = ctypes.c_void_p
+_lib.nox_client_free.argtypes = [ctypes.c_void_p]
+_lib.nox_client_free.restype = None
+_lib.nox_string_free.argtypes = [ctypes.c_void_p]
+_lib.nox_string_free.restype = None
+_lib.nox_last_error.argtypes = []
+_lib.nox_last_error.restype = ctypes.c_char_p
+
+
+class NoxClientError(Exception):
+    pass
+
+
+def _last_error():
+    error = _lib.nox_last_error()
+    return error.decode() if error is not None else None
+
+
+def _take_string(ptr):
+    """Copy a string returned by the library and free it, raise the last error on NULL"""
+    if not ptr:
+        error = _last_error()
+        if error is not None:
+            raise NoxClientError(error)
+        return None
+    try:
+        return ctypes.string_at(ptr).decode()
+    finally:
+        _lib.nox_string_free(ptr)
+
+
+class Client:
+    def __init__(self, handle):
+        self._handle = handle
+
+    @classmethod
+    def connect(cls, address):
+        handle = _lib.nox_client_connect(address.encode())
+        if not handle:
+            raise NoxClientError(_last_error())
+        return cls(handle)
+
+    @property
+    def peer_id(self):
+        return _take_string(_lib.nox_client_peer_id(self._handle))
+
+    def send_call(self, service_id, function_name, args=(), target=None):
+        """Call the function without waiting for the result, returns the particle id"""
+        return _take_string(
+            _lib.nox_client_send_call(
+                self._handle,
+                service_id.encode(),
+                function_name.encode(),
+                json.dumps(list(args)).encode(),
+                target.encode() if target is not None else None,
+            )
+        )
+
+    def poll_event(self, timeout_ms=1000):
+        """Result of any sent call as a dict with `particle_id` and either `result` or `error`,
+        None if nothing arrived in time"""
+        event = _take_string(_lib.nox_client_poll_event(self._handle, timeout_ms))
+        return json.loads(event) if event is not None else None
+
+    def close(self):
+        if self._handle:
+            _lib.nox_client_free(self._handle)
+            self._handle = None
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def __del__(self):
+        self.close()
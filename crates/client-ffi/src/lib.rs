@@ -0,0 +1,306 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! C ABI of the nox client, so it can be used from other languages without reimplementing
+//! the protocol. See `include/nox_client.h` for the interface and `python/nox_client.py`
+//! for the Python wrapper.
+//!
+//! Functions returning a pointer return NULL on error, the error message is then available
+//! through `nox_last_error` on the same thread. Strings returned by the library must be freed
+//! with `nox_string_free`.
+
+#![feature(try_blocks)]
+#![warn(rust_2018_idioms)]
+#![deny(
+    dead_code,
+    nonstandard_style,
+    unused_imports,
+    unused_mut,
+    unused_variables,
+    unused_unsafe,
+    unreachable_patterns
+)]
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::time::Duration;
+
+use connected_client::{ConnectedClient, ParticleBuilder};
+use eyre::{eyre, WrapErr};
+use libp2p::Multiaddr;
+use serde_json::{json, Value as JValue};
+use tokio::runtime::Runtime;
+
+/// Client connected to a node, along with the runtime driving its connection
+pub struct NoxClient {
+    client: ConnectedClient,
+    runtime: Runtime,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(err: eyre::Report) {
+    let message = CString::new(format!("{err:#}").replace('\0', ""))
+        .expect("nul bytes are removed from the message");
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// Store the error of the call for `nox_last_error` and return NULL in its place
+fn or_null<T>(result: eyre::Result<*mut T>) -> *mut T {
+    match result {
+        Ok(value) => {
+            clear_last_error();
+            value
+        }
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+fn into_c_string(s: String) -> eyre::Result<*mut c_char> {
+    Ok(CString::new(s)
+        .wrap_err("string contains a nul byte")?
+        .into_raw())
+}
+
+/// # Safety
+/// `s` must be NULL or a valid nul-terminated string
+unsafe fn read_str<'a>(s: *const c_char, what: &str) -> eyre::Result<&'a str> {
+    if s.is_null() {
+        return Err(eyre!("{what} is NULL"));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .wrap_err_with(|| format!("{what} is not valid UTF-8"))
+}
+
+/// # Safety
+/// `client` must be NULL or a pointer returned by `nox_client_connect` and not freed yet
+unsafe fn read_client<'a>(client: *mut NoxClient) -> eyre::Result<&'a mut NoxClient> {
+    client.as_mut().ok_or_else(|| eyre!("client is NULL"))
+}
+
+fn connect(address: &str) -> eyre::Result<NoxClient> {
+    let address: Multiaddr = address
+        .parse()
+        .wrap_err_with(|| format!("invalid node address '{address}'"))?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("nox-client")
+        .build()
+        .wrap_err("can't start the client runtime")?;
+    let client = runtime
+        .block_on(ConnectedClient::connect_to(address.clone()))
+        .wrap_err_with(|| format!("can't connect to {address}"))?;
+    Ok(NoxClient { client, runtime })
+}
+
+/// Connect to the node at the multiaddress, e.g. `/dns4/node.example/tcp/9000/ws`.
+/// Returns NULL if the node can't be reached. The client must be freed with `nox_client_free`.
+///
+/// # Safety
+/// `address` must be a valid nul-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn nox_client_connect(address: *const c_char) -> *mut NoxClient {
+    let result = read_str(address, "address").and_then(connect);
+    or_null(result.map(|client| Box::into_raw(Box::new(client))))
+}
+
+/// Peer id of the client. The string must be freed with `nox_string_free`.
+///
+/// # Safety
+/// `client` must be a pointer returned by `nox_client_connect`
+#[no_mangle]
+pub unsafe extern "C" fn nox_client_peer_id(client: *mut NoxClient) -> *mut c_char {
+    or_null(read_client(client).and_then(|client| into_c_string(client.client.peer_id.to_string())))
+}
+
+fn send_call(
+    client: &mut NoxClient,
+    service_id: &str,
+    function_name: &str,
+    args: &str,
+    target: Option<&str>,
+) -> eyre::Result<*mut c_char> {
+    let args: Vec<JValue> =
+        serde_json::from_str(args).wrap_err("arguments must be a JSON array")?;
+    let builder = ParticleBuilder::call(service_id, function_name).args(args);
+    let builder = match target {
+        Some(target) => builder.on(target),
+        None => builder,
+    };
+    let NoxClient { client, runtime } = client;
+    let particle_id = runtime.block_on(client.send_call(builder))?;
+    into_c_string(particle_id)
+}
+
+/// Call `service_id.function_name` with the arguments given as a JSON array on the `target` peer,
+/// or on the node the client is connected to if `target` is NULL. Doesn't wait for the result.
+/// Returns the particle id, the result is delivered by `nox_client_poll_event`.
+/// The string must be freed with `nox_string_free`.
+///
+/// # Safety
+/// `client` must be a pointer returned by `nox_client_connect`, other arguments valid
+/// nul-terminated strings, only `target` may be NULL
+#[no_mangle]
+pub unsafe extern "C" fn nox_client_send_call(
+    client: *mut NoxClient,
+    service_id: *const c_char,
+    function_name: *const c_char,
+    args_json: *const c_char,
+    target: *const c_char,
+) -> *mut c_char {
+    let result: eyre::Result<_> = try {
+        let client = read_client(client)?;
+        let service_id = read_str(service_id, "service_id")?;
+        let function_name = read_str(function_name, "function_name")?;
+        let args = read_str(args_json, "args_json")?;
+        let target = if target.is_null() {
+            None
+        } else {
+            Some(read_str(target, "target")?)
+        };
+        send_call(client, service_id, function_name, args, target)?
+    };
+    or_null(result)
+}
+
+/// Result of a call as JSON: `{"particle_id": ..., "result": [...]}`
+/// or `{"particle_id": ..., "error": "..."}` if the call failed
+fn call_event(particle_id: String, result: eyre::Result<Vec<JValue>>) -> String {
+    match result {
+        Ok(result) => json!({ "particle_id": particle_id, "result": result }),
+        Err(err) => json!({ "particle_id": particle_id, "error": format!("{err:#}") }),
+    }
+    .to_string()
+}
+
+/// Wait up to `timeout_ms` for the result of any of the sent calls, see `call_event` for the format.
+/// Returns NULL with an empty `nox_last_error` if nothing arrived in time.
+/// The string must be freed with `nox_string_free`.
+///
+/// # Safety
+/// `client` must be a pointer returned by `nox_client_connect`
+#[no_mangle]
+pub unsafe extern "C" fn nox_client_poll_event(
+    client: *mut NoxClient,
+    timeout_ms: u64,
+) -> *mut c_char {
+    let result = read_client(client).and_then(|client| {
+        let NoxClient { client, runtime } = client;
+        let event = runtime.block_on(client.poll_args(Duration::from_millis(timeout_ms)));
+        match event {
+            Some((particle_id, result)) => into_c_string(call_event(particle_id, result)),
+            None => Ok(ptr::null_mut()),
+        }
+    });
+    or_null(result)
+}
+
+/// Disconnect from the node and free the client
+///
+/// # Safety
+/// `client` must be NULL or a pointer returned by `nox_client_connect` and not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn nox_client_free(client: *mut NoxClient) {
+    if !client.is_null() {
+        let NoxClient { client, runtime } = *Box::from_raw(client);
+        client.client.stop();
+        runtime.shutdown_background();
+    }
+}
+
+/// Free a string returned by the library
+///
+/// # Safety
+/// `s` must be NULL or a string returned by the library and not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn nox_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Error of the last failed call on this thread, NULL if it succeeded.
+/// The string is owned by the library and valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn nox_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let error = nox_last_error();
+        assert!(!error.is_null(), "error must be set");
+        unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn connect_to_invalid_address() {
+        let address = CString::new("not a multiaddr").unwrap();
+        let client = unsafe { nox_client_connect(address.as_ptr()) };
+        assert!(client.is_null());
+        assert!(last_error().contains("invalid node address"));
+    }
+
+    #[test]
+    fn null_arguments_are_errors() {
+        let event = unsafe { nox_client_poll_event(ptr::null_mut(), 0) };
+        assert!(event.is_null());
+        assert_eq!(last_error(), "client is NULL");
+
+        unsafe {
+            nox_client_free(ptr::null_mut());
+            nox_string_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn call_event_format() {
+        let event = call_event("id".to_string(), Ok(vec![json!(1)]));
+        assert_eq!(
+            serde_json::from_str::<JValue>(&event).unwrap(),
+            json!({ "particle_id": "id", "result": [1] })
+        );
+
+        let event = call_event("id".to_string(), Err(eyre!("boom")));
+        assert_eq!(
+            serde_json::from_str::<JValue>(&event).unwrap(),
+            json!({ "particle_id": "id", "error": "boom" })
+        );
+    }
+}
@@ -21,6 +21,7 @@ use std::collections::VecDeque;
 use std::task::{Context, Poll, Waker};
 use std::time::Duration;
 
+use fluence_libp2p::Budgeted;
 use futures::future::BoxFuture;
 use futures::FutureExt;
 use libp2p::core::Endpoint;
@@ -45,9 +46,9 @@ pub type SwarmEventType = ToSwarm<ClientEvent, THandlerInEvent<ClientBehaviour>>
 
 #[derive(NetworkBehaviour)]
 pub struct FluenceClientBehaviour {
-    client: ClientBehaviour,
-    ping: Ping,
-    identify: Identify,
+    client: Budgeted<ClientBehaviour>,
+    ping: Budgeted<Ping>,
+    identify: Budgeted<Identify>,
 }
 
 impl FluenceClientBehaviour {
@@ -63,10 +64,11 @@ impl FluenceClientBehaviour {
                 .with_interval(Duration::from_secs(5))
                 .with_timeout(Duration::from_secs(60)),
         );
+        // budgets interleave sub-behaviours, so busy ping or identify can't delay particle delivery
         Self {
-            client,
-            ping,
-            identify,
+            client: client.into(),
+            ping: ping.into(),
+            identify: identify.into(),
         }
     }
 
@@ -100,7 +100,7 @@ impl Client {
 
     fn dial(
         &self,
-        node: Multiaddr,
+        nodes: &[Multiaddr],
         transport: Transport,
         transport_timeout: Duration,
         idle_connection_timeout: Duration,
@@ -122,11 +122,13 @@ impl Client {
                 .build()
         };
 
-        match Swarm::dial(&mut swarm, node.clone()) {
-            Ok(_) => log::info!("{} dialed to {:?}", self.peer_id, node),
-            Err(e) => {
-                log::error!("Dial to {:?} failed with {:?}", node, e);
-                return Err(e.into());
+        for node in nodes {
+            match Swarm::dial(&mut swarm, node.clone()) {
+                Ok(_) => log::info!("{} dialed to {:?}", self.peer_id, node),
+                Err(e) => {
+                    log::error!("Dial to {:?} failed with {:?}", node, e);
+                    return Err(e.into());
+                }
             }
         }
 
@@ -155,6 +157,25 @@ impl Client {
         transport_timeout: Duration,
         idle_connection_timeout: Duration,
         reconnect_enabled: bool,
+    ) -> Result<(Client, JoinHandle<()>), Box<dyn Error>> {
+        Self::connect_with_relays(
+            vec![relay],
+            transport,
+            key_pair,
+            transport_timeout,
+            idle_connection_timeout,
+            reconnect_enabled,
+        )
+    }
+
+    /// Connects to several relays at once, so particles can be sent through any of them
+    pub fn connect_with_relays(
+        relays: Vec<Multiaddr>,
+        transport: Transport,
+        key_pair: Option<KeyPair>,
+        transport_timeout: Duration,
+        idle_connection_timeout: Duration,
+        reconnect_enabled: bool,
     ) -> Result<(Client, JoinHandle<()>), Box<dyn Error>> {
         let (client_outlet, client_inlet) = mpsc::channel(128);
         let (relay_outlet, mut relay_inlet) = mpsc::channel(128);
@@ -164,7 +185,7 @@ impl Client {
         let protocol_config = ProtocolConfig::new(transport_timeout, transport_timeout);
         let client = Client::new(relay_outlet, client_inlet, stop_outlet, key_pair);
         let mut swarm = client.dial(
-            relay,
+            &relays,
             transport,
             transport_timeout,
             idle_connection_timeout,
@@ -37,6 +37,7 @@ use test_constants::{
 
 use crate::client::Client;
 use crate::event::ClientEvent;
+use crate::particle_builder::ParticleBuilder;
 
 #[allow(clippy::upper_case_acronyms)]
 type AVM = local_vm::AVMRunner<WasmtimeWasmBackend>;
@@ -45,11 +46,17 @@ pub struct ConnectedClient {
     pub client: Client,
     pub node: PeerId,
     pub node_address: Multiaddr,
+    /// Additional relays the client is connected to, used by `send_redundant`
+    pub redundant_relays: Vec<PeerId>,
     pub timeout: Duration,
     pub short_timeout: Duration,
     pub local_vm: tokio::sync::OnceCell<tokio::sync::Mutex<AVM>>,
     pub data_store: Arc<ParticleDataStore>,
     pub particle_ttl: Duration,
+    /// Ask the nodes to record the route of the sent particles in the particle trail
+    pub route_trail: bool,
+    /// Ask the nodes to report host call results of the sent particles as progress particles
+    pub progress: bool,
     pub tmp_dir: TempDir,
 }
 
@@ -69,6 +76,14 @@ impl ConnectedClient {
     pub fn set_particle_ttl(&mut self, particle_ttl: Duration) {
         self.particle_ttl = particle_ttl;
     }
+
+    pub fn set_route_trail(&mut self, route_trail: bool) {
+        self.route_trail = route_trail;
+    }
+
+    pub fn set_progress(&mut self, progress: bool) {
+        self.progress = progress;
+    }
 }
 
 impl Deref for ConnectedClient {
@@ -161,6 +176,58 @@ impl ConnectedClient {
         Ok(result)
     }
 
+    /// Connects to several relays at once: the first one is the primary relay,
+    /// the others receive copies of the particles sent by `send_redundant`
+    pub async fn connect_with_relays(
+        node_addresses: Vec<Multiaddr>,
+        key_pair: Option<KeyPair>,
+    ) -> Result<Self> {
+        let Some(primary_address) = node_addresses.first().cloned() else {
+            bail!("at least one relay address is required");
+        };
+
+        let transport = Transport::from_maddr(&primary_address);
+        let connect = async move {
+            let (mut client, _) = Client::connect_with_relays(
+                node_addresses.clone(),
+                transport,
+                key_pair,
+                TRANSPORT_TIMEOUT,
+                IDLE_CONNECTION_TIMEOUT,
+                true,
+            )
+            .map_err(|err| eyre!("connect to {:?}: {}", node_addresses, err))?;
+
+            let mut connected = HashMap::new();
+            while connected.len() < node_addresses.len() {
+                match client.receive_one().await {
+                    Some(ClientEvent::NewConnection { peer_id, multiaddr }) => {
+                        connected.insert(multiaddr, peer_id);
+                    }
+                    Some(_) => {}
+                    None => bail!("connection to {:?} aborted", node_addresses),
+                }
+            }
+
+            let mut relays = node_addresses
+                .iter()
+                .map(|addr| {
+                    connected
+                        .get(addr)
+                        .copied()
+                        .ok_or_else(|| eyre!("unexpected connection instead of {}", addr))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let primary = relays.remove(0);
+
+            let mut client = ConnectedClient::new(client, primary, primary_address, None).await;
+            client.redundant_relays = relays;
+            Ok::<_, eyre::Report>(client)
+        };
+
+        self::timeout(TIMEOUT, connect).await?
+    }
+
     pub async fn get_local_vm(&self) -> &tokio::sync::Mutex<AVM> {
         self.local_vm
             .get_or_init(|| async {
@@ -201,11 +268,14 @@ impl ConnectedClient {
             client,
             node,
             node_address,
+            redundant_relays: vec![],
             timeout: TIMEOUT,
             short_timeout: SHORT_TIMEOUT,
             local_vm,
             data_store,
             particle_ttl: particle_ttl.unwrap_or(Duration::from_millis(PARTICLE_TTL as u64)),
+            route_trail: false,
+            progress: false,
             tmp_dir,
         }
     }
@@ -218,6 +288,20 @@ impl ConnectedClient {
         self.client.send(particle, self.node).await
     }
 
+    /// Sends the same particle through the primary and all redundant relays.
+    /// Peers drop exact copies of a particle, so it is executed once if several copies arrive.
+    pub async fn send_redundant(&self, particle: Particle) {
+        tracing::debug!(
+            particle_id = particle.id,
+            relays = self.redundant_relays.len() + 1,
+            "Add a particle to the client send queue of every relay"
+        );
+        for relay in &self.redundant_relays {
+            self.client.send(particle.clone(), *relay).await
+        }
+        self.client.send(particle, self.node).await
+    }
+
     pub async fn send_particle(
         &mut self,
         script: impl Into<String>,
@@ -245,22 +329,77 @@ impl ConnectedClient {
             .into_iter()
             .map(|(key, value)| (key.to_string(), value))
             .collect();
+        self.send_particle_with_ttl(script.into(), data, generated, self.particle_ttl())
+            .await
+    }
+
+    /// Sends a particle built by [`ParticleBuilder`] and waits for the result of the call
+    pub async fn call(&mut self, builder: ParticleBuilder) -> Result<Vec<JValue>> {
+        let particle_id = self.send_call(builder).await?;
+        self.wait_particle_args(particle_id).await
+    }
+
+    /// Sends a particle built by [`ParticleBuilder`] without waiting for the result.
+    /// Returns the particle id, the result can be received with [`Self::poll_args`]
+    pub async fn send_call(&mut self, builder: ParticleBuilder) -> Result<String> {
+        let particle = builder.build(self.node, self.peer_id)?;
+        let ttl = particle.ttl.unwrap_or(self.particle_ttl());
+        Ok(self
+            .send_particle_with_ttl(particle.script, particle.data, false, ttl)
+            .await)
+    }
+
+    /// Like [`Self::call`], but sends the particle through all connected relays
+    pub async fn call_redundant(&mut self, builder: ParticleBuilder) -> Result<Vec<JValue>> {
+        let particle = builder.build(self.node, self.peer_id)?;
+        let ttl = particle.ttl.unwrap_or(self.particle_ttl());
+        let particle = self
+            .make_particle(particle.script, particle.data, false, ttl)
+            .await;
+        let particle_id = particle.id.clone();
+        self.send_redundant(particle).await;
+        self.wait_particle_args(particle_id).await
+    }
+
+    async fn send_particle_with_ttl(
+        &mut self,
+        script: String,
+        data: HashMap<String, JValue>,
+        generated: bool,
+        particle_ttl: Duration,
+    ) -> String {
+        let mut particle = self
+            .make_particle(script, data, generated, particle_ttl)
+            .await;
+        if self.route_trail {
+            particle.trail = Some(vec![]);
+        }
+        particle.progress = self.progress;
+        let id = particle.id.clone();
+        self.send(particle).await;
+        id
+    }
+
+    async fn make_particle(
+        &mut self,
+        script: String,
+        data: HashMap<String, JValue>,
+        generated: bool,
+        particle_ttl: Duration,
+    ) -> Particle {
         let mut guard = self.get_local_vm().await.lock().await;
-        let particle = make_particle(
+        make_particle(
             self.peer_id,
             &data,
-            script.into(),
+            script,
             self.node,
             &mut guard,
             self.data_store.clone(),
             generated,
-            self.particle_ttl(),
+            particle_ttl,
             &self.key_pair,
         )
-        .await;
-        let id = particle.id.clone();
-        self.send(particle).await;
-        id
+        .await
     }
 
     pub async fn maybe_receive(&mut self) -> Option<Particle> {
@@ -377,6 +516,46 @@ impl ConnectedClient {
         }
     }
 
+    /// Waits up to `timeout` for any particle returning a result to the client.
+    /// Returns the id of the particle with its returned arguments or the error caught by AIR.
+    pub async fn poll_args(&mut self, timeout: Duration) -> Option<(String, Result<Vec<JValue>>)> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let particle = match self.fetched.pop() {
+                Some(particle) => particle,
+                None => {
+                    let receive = async {
+                        loop {
+                            match self.client.receive_one().await {
+                                Some(ClientEvent::Particle { particle, .. }) => {
+                                    break Some(particle)
+                                }
+                                Some(_) => {}
+                                None => break None,
+                            }
+                        }
+                    };
+                    tokio::time::timeout_at(deadline, receive).await.ok()??
+                }
+            };
+            let particle_id = particle.id.clone();
+            let mut guard = self.get_local_vm().await.lock().await;
+            let result = read_args(
+                particle,
+                self.peer_id,
+                &mut guard,
+                self.data_store.clone(),
+                &self.key_pair,
+            )
+            .await;
+            // Particles which don't return anything are skipped
+            if let Some(result) = result {
+                let result = result.map_err(|args| eyre!("AIR caught an error: {:?}", args));
+                return Some((particle_id, result));
+            }
+        }
+    }
+
     pub async fn listen_for_n<O: Default, F: Fn(Result<Vec<JValue>, Vec<JValue>>) -> O>(
         &mut self,
         mut n: usize,
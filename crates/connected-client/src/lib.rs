@@ -36,7 +36,9 @@ mod client;
 mod command;
 mod connected_client;
 mod event;
+mod particle_builder;
 
 pub use crate::connected_client::ConnectedClient;
 pub use command::ClientCommand;
 pub use event::ClientEvent;
+pub use particle_builder::{ArgKind, BuildError, BuiltParticle, ParticleBuilder};
@@ -0,0 +1,349 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use libp2p::PeerId;
+use serde_json::Value as JValue;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BuildError {
+    #[error("invalid peer id '{peer_id}': {reason}")]
+    InvalidPeerId { peer_id: String, reason: String },
+    #[error(
+        "invalid {what} '{value}': must be non-empty and must not contain quotes or whitespace"
+    )]
+    InvalidName { what: &'static str, value: String },
+    #[error("{service_id}.{function_name} expects {min}..={max} arguments, got {actual}")]
+    Arity {
+        service_id: String,
+        function_name: String,
+        min: usize,
+        max: usize,
+        actual: usize,
+    },
+    #[error(
+        "argument #{position} of {service_id}.{function_name} must be {expected}, got {actual}"
+    )]
+    ArgType {
+        service_id: String,
+        function_name: String,
+        position: usize,
+        expected: ArgKind,
+        actual: JValue,
+    },
+}
+
+/// JSON type of a builtin argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    String,
+    Number,
+    Bool,
+    Array,
+    Object,
+    Any,
+}
+
+impl ArgKind {
+    fn matches(&self, value: &JValue) -> bool {
+        match self {
+            ArgKind::String => value.is_string(),
+            ArgKind::Number => value.is_number(),
+            ArgKind::Bool => value.is_boolean(),
+            ArgKind::Array => value.is_array(),
+            ArgKind::Object => value.is_object(),
+            ArgKind::Any => true,
+        }
+    }
+}
+
+impl std::fmt::Display for ArgKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ArgKind::String => "a string",
+            ArgKind::Number => "a number",
+            ArgKind::Bool => "a boolean",
+            ArgKind::Array => "an array",
+            ArgKind::Object => "an object",
+            ArgKind::Any => "any value",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Required arguments followed by optional ones.
+/// Aqua optional values are passed as arrays of at most one element.
+struct Schema {
+    required: &'static [ArgKind],
+    optional: &'static [ArgKind],
+}
+
+/// Argument schemas of the builtins that are most often called by hand
+fn builtin_schema(service_id: &str, function_name: &str) -> Option<Schema> {
+    use ArgKind::*;
+
+    let (required, optional): (&'static [ArgKind], &'static [ArgKind]) =
+        match (service_id, function_name) {
+            ("peer", "identify") | ("peer", "timestamp_ms") | ("peer", "timestamp_sec") => {
+                (&[], &[])
+            }
+            ("peer", "is_connected") => (&[String], &[]),
+            ("peer", "connect") => (&[String], &[Array]),
+            ("srv", "list") => (&[], &[String]),
+            ("srv", "create") => (&[String], &[String]),
+            ("srv", "remove") | ("srv", "info") | ("srv", "get_interface") => (&[String], &[]),
+            ("srv", "resolve_alias") | ("srv", "resolve_alias_opt") => (&[String], &[]),
+            ("srv", "add_alias") => (&[String, String], &[]),
+            ("dist", "add_blueprint") => (&[String], &[]),
+            ("dist", "make_blueprint") => (&[String, Array], &[]),
+            ("spell", "install") => (&[String, Any, Object], &[String, String]),
+            ("spell", "remove") => (&[String], &[]),
+            ("spell", "list") => (&[], &[String]),
+            ("spell", "update_trigger_config") => (&[String, Object], &[]),
+            _ => return None,
+        };
+    Some(Schema { required, optional })
+}
+
+/// Builds a particle which calls a single service function and returns its result to the client.
+///
+/// ```ignore
+/// let result = client
+///     .call(ParticleBuilder::call("srv", "list").arg("app=indexer"))
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParticleBuilder {
+    target: Option<String>,
+    service_id: String,
+    function_name: String,
+    args: Vec<JValue>,
+    ttl: Option<Duration>,
+}
+
+/// AIR script and its data, ready to be sent by [`crate::ConnectedClient`]
+#[derive(Debug, Clone)]
+pub struct BuiltParticle {
+    pub script: String,
+    pub data: HashMap<String, JValue>,
+    pub ttl: Option<Duration>,
+}
+
+impl ParticleBuilder {
+    pub fn call(service_id: impl Into<String>, function_name: impl Into<String>) -> Self {
+        Self {
+            target: None,
+            service_id: service_id.into(),
+            function_name: function_name.into(),
+            args: vec![],
+            ttl: None,
+        }
+    }
+
+    /// Peer to call the function on, the relay of the client if not set
+    pub fn on(mut self, peer_id: impl Into<String>) -> Self {
+        self.target = Some(peer_id.into());
+        self
+    }
+
+    pub fn arg(mut self, arg: impl Into<JValue>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args(mut self, args: impl IntoIterator<Item = JValue>) -> Self {
+        self.args.extend(args);
+        self
+    }
+
+    /// Particle TTL, the client default if not set
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Validates the call and generates the script. The result is returned to `client` through `relay`.
+    pub fn build(self, relay: PeerId, client: PeerId) -> Result<BuiltParticle, BuildError> {
+        validate_name("service id", &self.service_id)?;
+        validate_name("function name", &self.function_name)?;
+        let target = match &self.target {
+            Some(target) => PeerId::from_str(target).map_err(|err| BuildError::InvalidPeerId {
+                peer_id: target.clone(),
+                reason: err.to_string(),
+            })?,
+            None => relay,
+        };
+        self.check_schema()?;
+
+        let mut data = HashMap::new();
+        data.insert("relay".to_string(), JValue::String(relay.to_string()));
+        data.insert("client".to_string(), JValue::String(client.to_string()));
+        data.insert("target".to_string(), JValue::String(target.to_string()));
+        let arg_names: Vec<_> = self
+            .args
+            .into_iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                let name = format!("arg{i}");
+                data.insert(name.clone(), arg);
+                name
+            })
+            .collect();
+
+        let call = format!(
+            r#"(call target ("{}" "{}") [{}] result)"#,
+            self.service_id,
+            self.function_name,
+            arg_names.join(" ")
+        );
+        // The result goes back through the relay unless the function is called on the relay itself
+        let call = if target == relay {
+            call
+        } else {
+            format!(r#"(seq {call} (call relay ("op" "noop") []))"#)
+        };
+        let script = format!(r#"(seq {call} (call client ("return" "") [result]))"#);
+
+        Ok(BuiltParticle {
+            script,
+            data,
+            ttl: self.ttl,
+        })
+    }
+
+    fn check_schema(&self) -> Result<(), BuildError> {
+        let Some(schema) = builtin_schema(&self.service_id, &self.function_name) else {
+            return Ok(());
+        };
+
+        let min = schema.required.len();
+        let max = min + schema.optional.len();
+        if self.args.len() < min || self.args.len() > max {
+            return Err(BuildError::Arity {
+                service_id: self.service_id.clone(),
+                function_name: self.function_name.clone(),
+                min,
+                max,
+                actual: self.args.len(),
+            });
+        }
+
+        let kinds = schema.required.iter().chain(schema.optional.iter());
+        for (position, (kind, arg)) in kinds.zip(self.args.iter()).enumerate() {
+            // Optional arguments may also be passed as Aqua options, i.e. arrays of at most one element
+            let is_optional = position >= min;
+            let is_option = is_optional
+                && arg
+                    .as_array()
+                    .map_or(false, |a| a.len() <= 1 && a.iter().all(|v| kind.matches(v)));
+            if !kind.matches(arg) && !is_option {
+                return Err(BuildError::ArgType {
+                    service_id: self.service_id.clone(),
+                    function_name: self.function_name.clone(),
+                    position,
+                    expected: *kind,
+                    actual: arg.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_name(what: &'static str, value: &str) -> Result<(), BuildError> {
+    if value.is_empty() || value.contains(|c: char| c == '"' || c.is_whitespace()) {
+        return Err(BuildError::InvalidName {
+            what,
+            value: value.to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn build_call_on_relay() {
+        let relay = PeerId::random();
+        let client = PeerId::random();
+        let particle = ParticleBuilder::call("srv", "resolve_alias")
+            .arg("alias")
+            .build(relay, client)
+            .unwrap();
+
+        assert_eq!(
+            particle.script,
+            r#"(seq (call target ("srv" "resolve_alias") [arg0] result) (call client ("return" "") [result]))"#
+        );
+        assert_eq!(particle.data["arg0"], json!("alias"));
+        assert_eq!(particle.data["target"], json!(relay.to_string()));
+        assert_eq!(particle.data["client"], json!(client.to_string()));
+    }
+
+    #[test]
+    fn build_call_on_other_peer() {
+        let target = PeerId::random();
+        let particle = ParticleBuilder::call("peer", "identify")
+            .on(target.to_string())
+            .build(PeerId::random(), PeerId::random())
+            .unwrap();
+
+        assert!(particle.script.contains(r#"(call relay ("op" "noop") [])"#));
+        assert_eq!(particle.data["target"], json!(target.to_string()));
+    }
+
+    #[test]
+    fn reject_malformed_calls() {
+        let build = |builder: ParticleBuilder| builder.build(PeerId::random(), PeerId::random());
+
+        assert!(matches!(
+            build(ParticleBuilder::call("srv", "list").on("not a peer id")),
+            Err(BuildError::InvalidPeerId { .. })
+        ));
+        assert!(matches!(
+            build(ParticleBuilder::call("srv\"", "list")),
+            Err(BuildError::InvalidName { .. })
+        ));
+        assert!(matches!(
+            build(ParticleBuilder::call("srv", "add_alias").arg("alias")),
+            Err(BuildError::Arity {
+                min: 2,
+                max: 2,
+                actual: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            build(ParticleBuilder::call("srv", "remove").arg(42)),
+            Err(BuildError::ArgType { position: 0, .. })
+        ));
+
+        // Aqua options are accepted for optional arguments
+        assert!(build(ParticleBuilder::call("srv", "list").arg(json!(["app=x"]))).is_ok());
+        // Unknown functions aren't checked
+        assert!(build(ParticleBuilder::call("my_service", "f").arg(1).arg(2)).is_ok());
+    }
+}
@@ -43,7 +43,7 @@ use fluence_libp2p::random_multiaddr::{create_memory_maddr, create_tcp_maddr};
 use fluence_libp2p::Transport;
 use fs_utils::to_abs_path;
 use futures::stream::iter;
-use nox::{Connectivity, Node};
+use nox::{Connectivity, Node, Spells};
 use particle_protocol::ProtocolConfig;
 use rand::RngCore;
 use server_config::{
@@ -505,7 +505,7 @@ pub async fn create_swarm_with_runtime<RT: AquaRuntime>(
             data_store_config,
             "some version",
             "some version",
-            system_service_distros,
+            Spells::new(system_service_distros),
         );
         (node, config.management_keypair.clone(), resolved, task)
     });
@@ -21,12 +21,21 @@
 //!
 //! See [`HealthCheckRegistry`] for details.
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 pub trait HealthCheck: Send + Sync + 'static {
     fn status(&self) -> eyre::Result<()>;
+
+    /// Progress of a long-running check as (done, total), e.g. restoration at startup
+    fn progress(&self) -> Option<(usize, usize)> {
+        None
+    }
 }
 
 pub struct HealthCheckRegistry {
     checks: Vec<(&'static str, Box<dyn HealthCheck>)>,
+    /// Whether each check has passed at least once, to tell startup from degradation
+    passed: Vec<AtomicBool>,
 }
 
 ///  The result of the health check, which can be one of the following:
@@ -39,24 +48,77 @@ pub enum HealthStatus {
     Warning(Vec<&'static str>, Vec<&'static str>),
     Fail(Vec<&'static str>),
 }
+
+/// State of a single component in the readiness report.
+/// A failing check is `Starting` until it passes for the first time, and `Degraded` after that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentState {
+    Ready,
+    Starting,
+    Degraded,
+}
+
+impl ComponentState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComponentState::Ready => "ready",
+            ComponentState::Starting => "starting",
+            ComponentState::Degraded => "degraded",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentReport {
+    pub name: &'static str,
+    pub state: ComponentState,
+    /// Why the check fails, if it does
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Readiness {
+    pub components: Vec<ComponentReport>,
+}
+
+impl Readiness {
+    /// Overall state: degraded if any component is degraded, starting if any is still starting
+    pub fn state(&self) -> ComponentState {
+        let states = self.components.iter().map(|c| c.state);
+        if states.clone().any(|s| s == ComponentState::Degraded) {
+            ComponentState::Degraded
+        } else if states.clone().any(|s| s == ComponentState::Starting) {
+            ComponentState::Starting
+        } else {
+            ComponentState::Ready
+        }
+    }
+}
 /// A HealthCheckRegistry is a collection of health checks that can be registered and executed.
 /// Each health check is associated with a name and is expected to implement the HealthCheck trait.
 impl HealthCheckRegistry {
     pub fn new() -> Self {
-        HealthCheckRegistry { checks: Vec::new() }
+        HealthCheckRegistry {
+            checks: Vec::new(),
+            passed: Vec::new(),
+        }
     }
 
     pub fn register(&mut self, name: &'static str, check: impl HealthCheck) {
         self.checks.push((name, Box::new(check)));
+        self.passed.push(AtomicBool::new(false));
     }
 
     pub fn status(&self) -> HealthStatus {
         let mut fails = Vec::new();
         let mut oks = Vec::new();
 
-        for (name, check) in &self.checks {
+        for ((name, check), passed) in self.checks.iter().zip(&self.passed) {
             match check.status() {
-                Ok(_) => oks.push(*name),
+                Ok(_) => {
+                    passed.store(true, Ordering::Release);
+                    oks.push(*name)
+                }
                 Err(_) => {
                     fails.push(*name);
                 }
@@ -71,6 +133,40 @@ impl HealthCheckRegistry {
             HealthStatus::Warning(oks, fails)
         }
     }
+
+    /// Progress reported by the checks that track it, as (name, done, total)
+    pub fn progress(&self) -> Vec<(&'static str, usize, usize)> {
+        self.checks
+            .iter()
+            .filter_map(|(name, check)| check.progress().map(|(done, total)| (*name, done, total)))
+            .collect()
+    }
+
+    /// Per-component readiness. Checks that haven't passed yet or report unfinished
+    /// progress are starting, checks failing after they've passed once are degraded.
+    pub fn readiness(&self) -> Readiness {
+        let components = self
+            .checks
+            .iter()
+            .zip(&self.passed)
+            .map(|((name, check), passed)| {
+                let in_progress = check.progress().map_or(false, |(done, total)| done < total);
+                let (state, error) = match check.status() {
+                    Ok(_) => {
+                        passed.store(true, Ordering::Release);
+                        (ComponentState::Ready, None)
+                    }
+                    Err(err) if in_progress || !passed.load(Ordering::Acquire) => {
+                        (ComponentState::Starting, Some(err.to_string()))
+                    }
+                    Err(err) => (ComponentState::Degraded, Some(err.to_string())),
+                };
+                ComponentReport { name, state, error }
+            })
+            .collect();
+
+        Readiness { components }
+    }
 }
 
 impl Default for HealthCheckRegistry {
@@ -125,6 +221,61 @@ mod tests {
         assert_eq!(status, HealthStatus::Fail(vec!["MockCheck1"]))
     }
 
+    struct MockProgressCheck;
+
+    impl HealthCheck for MockProgressCheck {
+        fn status(&self) -> eyre::Result<()> {
+            Err(eyre::eyre!("In progress"))
+        }
+
+        fn progress(&self) -> Option<(usize, usize)> {
+            Some((3, 10))
+        }
+    }
+
+    #[test]
+    fn test_health_check_registry_progress() {
+        let mut registry = HealthCheckRegistry::new();
+        registry.register("MockCheck1", MockHealthCheck { should_pass: true });
+        registry.register("MockProgress", MockProgressCheck);
+
+        assert_eq!(registry.progress(), vec![("MockProgress", 3, 10)]);
+    }
+
+    struct ToggleHealthCheck {
+        pass: std::sync::Arc<AtomicBool>,
+    }
+
+    impl HealthCheck for ToggleHealthCheck {
+        fn status(&self) -> eyre::Result<()> {
+            if self.pass.load(Ordering::Acquire) {
+                Ok(())
+            } else {
+                Err(eyre::eyre!("Not yet"))
+            }
+        }
+    }
+
+    #[test]
+    fn test_health_check_registry_readiness() {
+        let pass = std::sync::Arc::new(AtomicBool::new(false));
+        let mut registry = HealthCheckRegistry::new();
+        registry.register("MockCheck1", MockHealthCheck { should_pass: true });
+        registry.register("Toggle", ToggleHealthCheck { pass: pass.clone() });
+
+        let readiness = registry.readiness();
+        assert_eq!(readiness.state(), ComponentState::Starting);
+        assert_eq!(readiness.components[1].error.as_deref(), Some("Not yet"));
+
+        pass.store(true, Ordering::Release);
+        assert_eq!(registry.readiness().state(), ComponentState::Ready);
+
+        pass.store(false, Ordering::Release);
+        let readiness = registry.readiness();
+        assert_eq!(readiness.state(), ComponentState::Degraded);
+        assert_eq!(readiness.components[0].state, ComponentState::Ready);
+    }
+
     #[test]
     fn test_health_check_registry_multiple_checks() {
         let mut registry = HealthCheckRegistry::new();
@@ -48,7 +48,42 @@ pub fn into_array(v: JValue) -> Option<Vec<JValue>> {
     None
 }
 
+/// Size of the value serialized to JSON, counted without allocating the string
+pub fn serialized_size<T: serde::Serialize + ?Sized>(value: &T) -> usize {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    // writing to the counter can't fail
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
 /// Converts an error into IValue::String
 pub fn err_as_value<E: core::fmt::Debug + core::fmt::Display>(err: E) -> JValue {
     JValue::String(format!("Error: {err}\n{err:?}"))
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::serialized_size;
+
+    #[test]
+    fn serialized_size_matches_string() {
+        let value = json!(["peer", {"a": [1, 2, 3]}, null]);
+        assert_eq!(serialized_size(&value), value.to_string().len());
+        assert_eq!(serialized_size(&[0u8; 0]), 2);
+    }
+}
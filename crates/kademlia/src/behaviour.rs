@@ -40,9 +40,10 @@ use libp2p::swarm::{ConnectionDenied, ConnectionId, THandler};
 use libp2p::{
     core::Multiaddr,
     kad::{
-        self, store::MemoryStore, BootstrapError, BootstrapOk, BootstrapResult,
-        Event as KademliaEvent, GetClosestPeersError, GetClosestPeersOk, GetClosestPeersResult,
-        QueryId, QueryResult,
+        self,
+        store::{MemoryStore, RecordStore},
+        BootstrapError, BootstrapOk, BootstrapResult, Event as KademliaEvent, GetClosestPeersError,
+        GetClosestPeersOk, GetClosestPeersResult, QueryId, QueryResult,
     },
     swarm::NetworkBehaviour,
     PeerId, StreamProtocol,
@@ -56,6 +57,7 @@ use tracing::Span;
 
 use control_macro::get_return;
 use particle_protocol::Contact;
+use peer_metrics::{KademliaMetrics, ReclaimedKind};
 
 use crate::error::{KademliaError, Result};
 use crate::{Command, KademliaApi};
@@ -72,6 +74,11 @@ pub struct KademliaConfig {
     pub peer_fail_threshold: usize,
     pub ban_cooldown: Duration,
     pub protocol_name: StreamProtocol,
+    /// How often expired records, stale peer addresses and failure counters are removed.
+    /// Zero disables the collection.
+    pub gc_interval: Duration,
+    /// How long addresses of disconnected peers and failure counters are kept
+    pub stale_peer_retention: Duration,
 }
 #[derive(Debug)]
 pub enum PendingQuery {
@@ -101,11 +108,14 @@ struct FailedPeer {
     pub ban: Option<Instant>,
     /// How many times we failed to discover the peer
     pub count: usize,
+    /// When we failed to discover the peer last time
+    pub last_failure: Option<Instant>,
 }
 
 impl FailedPeer {
-    pub fn increment(&mut self) {
+    pub fn increment(&mut self, now: Instant) {
         self.count += 1;
+        self.last_failure = Some(now);
     }
 }
 
@@ -116,11 +126,16 @@ pub struct Kademlia {
     queries: HashMap<QueryId, PendingQuery>,
     pending_peers: HashMap<PeerId, Vec<PendingPeer>>,
     failed_peers: HashMap<PeerId, FailedPeer>,
+    /// Peers without connections, and since when
+    disconnected_peers: HashMap<PeerId, Instant>,
     config: KademliaConfig,
     waker: Option<Waker>,
     // Timer to track timed out requests, and return errors ASAP
     timer: Delay,
+    // Timer of the garbage collection, absent if it's disabled
+    gc_timer: Option<Delay>,
     metrics: Option<Arc<Metrics>>,
+    gc_metrics: Option<KademliaMetrics>,
     #[cfg(test)]
     parent_span: Span,
 }
@@ -156,9 +171,11 @@ impl Kademlia {
     pub fn new(
         config: KademliaConfig,
         metrics: Option<Arc<Metrics>>,
+        gc_metrics: Option<KademliaMetrics>,
         #[cfg(test)] parent_span: Span,
     ) -> (Self, KademliaApi) {
         let timer = Delay::new(config.query_timeout);
+        let gc_timer = (!config.gc_interval.is_zero()).then(|| Delay::new(config.gc_interval));
 
         let peer_id = config.peer_id;
         let store = MemoryStore::new(peer_id);
@@ -180,10 +197,13 @@ impl Kademlia {
             queries: <_>::default(),
             pending_peers: <_>::default(),
             failed_peers: <_>::default(),
+            disconnected_peers: <_>::default(),
             config,
             waker: None,
             timer,
+            gc_timer,
             metrics,
+            gc_metrics,
             #[cfg(test)]
             parent_span,
         };
@@ -381,6 +401,15 @@ impl Kademlia {
             cx.waker().wake_by_ref()
         }
 
+        if let Some(gc_timer) = self.gc_timer.as_mut() {
+            if gc_timer.poll_unpin(cx).is_ready() {
+                gc_timer.reset(self.config.gc_interval);
+                // register current task within the reset timer
+                gc_timer.poll_unpin(cx).is_ready(); // `is_ready` here is to avoid "must use" warning
+                self.collect_garbage(Instant::now());
+            }
+        }
+
         // Exit early to avoid Instant::now calculation
         if self.pending_peers.is_empty() && self.failed_peers.is_empty() {
             return Poll::Pending;
@@ -407,7 +436,7 @@ impl Kademlia {
             }
             // count failure if there was at least 1 timeout
             if timed_out {
-                failed_peers.entry(*id).or_default().increment();
+                failed_peers.entry(*id).or_default().increment(now);
             }
 
             // empty entries will be removed
@@ -439,6 +468,62 @@ impl Kademlia {
         Poll::Pending
     }
 
+    /// Removes expired records, addresses of peers disconnected for longer than
+    /// `stale_peer_retention`, and failure counters of peers that weren't banned since then
+    fn collect_garbage(&mut self, now: Instant) {
+        let retention = self.config.stale_peer_retention;
+        let is_stale = |since: Instant| now.duration_since(since) >= retention;
+
+        let store = self.kademlia.store_mut();
+        let records_before = store.records().count();
+        store.retain(|_, record| !record.is_expired(now));
+        let records = records_before - store.records().count();
+
+        let expired_providers: Vec<_> = store
+            .provided()
+            .filter(|record| record.is_expired(now))
+            .map(|record| (record.key.clone(), record.provider))
+            .collect();
+        for (key, provider) in expired_providers.iter() {
+            store.remove_provider(key, provider);
+        }
+
+        let mut stale_peers = vec![];
+        self.disconnected_peers.retain(|peer, since| {
+            let stale = is_stale(*since);
+            if stale {
+                stale_peers.push(*peer);
+            }
+            !stale
+        });
+        let mut peers = 0;
+        for peer in stale_peers {
+            if self.kademlia.remove_peer(&peer).is_some() {
+                peers += 1;
+            }
+        }
+
+        let failed_before = self.failed_peers.len();
+        self.failed_peers.retain(|_, failed| {
+            failed.ban.is_some() || !failed.last_failure.map_or(true, is_stale)
+        });
+        let failed = failed_before - self.failed_peers.len();
+
+        log::debug!(
+            "Kademlia GC removed {} records, {} provider records, {} stale peers, {} failure counters",
+            records,
+            expired_providers.len(),
+            peers,
+            failed
+        );
+        if let Some(m) = self.gc_metrics.as_ref() {
+            m.reclaimed(ReclaimedKind::Record, records);
+            m.reclaimed(ReclaimedKind::ProviderRecord, expired_providers.len());
+            m.reclaimed(ReclaimedKind::PeerAddresses, peers);
+            m.reclaimed(ReclaimedKind::FailedPeer, failed);
+        }
+    }
+
     fn wake(&self) {
         if let Some(waker) = self.waker.as_ref() {
             waker.wake_by_ref()
@@ -562,10 +647,14 @@ impl NetworkBehaviour for Kademlia {
         log::trace!("Behaviour event {:?}", event);
         match event {
             FromSwarm::ConnectionEstablished(e) => {
+                self.disconnected_peers.remove(&e.peer_id);
                 self.kademlia
                     .on_swarm_event(FromSwarm::ConnectionEstablished(e));
             }
             FromSwarm::ConnectionClosed(e) => {
+                if e.remaining_established == 0 {
+                    self.disconnected_peers.insert(e.peer_id, Instant::now());
+                }
                 self.kademlia.on_swarm_event(FromSwarm::ConnectionClosed(e));
             }
             FromSwarm::AddressChange(e) => {
@@ -694,6 +783,8 @@ mod tests {
             peer_fail_threshold: 1,
             ban_cooldown: Duration::from_secs(1),
             protocol_name,
+            gc_interval: Duration::from_secs(600),
+            stale_peer_retention: Duration::from_secs(3600),
         }
     }
 
@@ -709,7 +800,7 @@ mod tests {
         );
         let _guard = span.enter();
         let config = kad_config(peer_id, network_id);
-        let (kad, _) = Kademlia::new(config, None, span.clone());
+        let (kad, _) = Kademlia::new(config, None, None, span.clone());
         let timeout = Duration::from_secs(20);
 
         let kp: Keypair = kp.into();
@@ -868,4 +959,46 @@ mod tests {
             .unwrap();
         assert!(matches!(banned, Err(KademliaError::PeerBanned)));
     }
+
+    #[test]
+    fn gc_removes_stale_peers() {
+        use std::time::Instant;
+
+        use super::FailedPeer;
+
+        let network_id = generate_network_id();
+
+        let (mut node, _) = make_node("a".to_string(), network_id);
+        let kad = node.behaviour_mut();
+        let now = Instant::now();
+
+        let stale = RandomPeerId::random();
+        kad.kademlia.add_address(&stale, create_memory_maddr());
+        kad.disconnected_peers.insert(stale, now);
+        let fresh = RandomPeerId::random();
+        kad.kademlia.add_address(&fresh, create_memory_maddr());
+
+        let failed = RandomPeerId::random();
+        kad.failed_peers.entry(failed).or_default().increment(now);
+        let banned = RandomPeerId::random();
+        kad.failed_peers.insert(
+            banned,
+            FailedPeer {
+                ban: Some(now),
+                ..<_>::default()
+            },
+        );
+
+        // nothing is old enough yet
+        kad.collect_garbage(now + Duration::from_secs(60));
+        assert!(!kad.addresses_of_peer(&stale).is_empty());
+        assert_eq!(kad.failed_peers.len(), 2);
+
+        kad.collect_garbage(now + Duration::from_secs(2 * 3600));
+        assert!(kad.addresses_of_peer(&stale).is_empty());
+        assert!(!kad.addresses_of_peer(&fresh).is_empty());
+        assert!(kad.disconnected_peers.is_empty());
+        assert_eq!(kad.failed_peers.len(), 1);
+        assert!(kad.failed_peers.contains_key(&banned));
+    }
 }
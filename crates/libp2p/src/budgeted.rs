@@ -0,0 +1,291 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::ops::{Deref, DerefMut};
+use std::task::{Context, Poll};
+
+use libp2p::core::Endpoint;
+use libp2p::swarm::{
+    ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+
+/// Default number of consecutive events a [`Budgeted`] behaviour may emit before yielding
+pub const DEFAULT_POLL_BUDGET: usize = 32;
+
+/// Wraps a [`NetworkBehaviour`] and limits how many events it may emit in a row.
+///
+/// Derived behaviours poll their fields in declaration order and return on the first
+/// ready event, so a field that is always ready (e.g. ping or identify under heavy load)
+/// prevents the fields after it from ever being polled. Once the budget is spent,
+/// `Budgeted` returns `Pending` once and schedules a wake-up, giving its siblings a turn.
+pub struct Budgeted<B> {
+    inner: B,
+    budget: usize,
+    spent: usize,
+}
+
+impl<B> Budgeted<B> {
+    pub fn new(inner: B, budget: usize) -> Self {
+        Self {
+            inner,
+            budget: budget.max(1),
+            spent: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B: NetworkBehaviour> From<B> for Budgeted<B> {
+    fn from(inner: B) -> Self {
+        Self::new(inner, DEFAULT_POLL_BUDGET)
+    }
+}
+
+impl<B> Deref for Budgeted<B> {
+    type Target = B;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<B> DerefMut for Budgeted<B> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl<B: NetworkBehaviour> NetworkBehaviour for Budgeted<B> {
+    type ConnectionHandler = B::ConnectionHandler;
+    type ToSwarm = B::ToSwarm;
+
+    fn handle_pending_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<(), ConnectionDenied> {
+        self.inner
+            .handle_pending_inbound_connection(connection_id, local_addr, remote_addr)
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner.handle_established_inbound_connection(
+            connection_id,
+            peer,
+            local_addr,
+            remote_addr,
+        )
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        addresses: &[Multiaddr],
+        effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.inner.handle_pending_outbound_connection(
+            connection_id,
+            maybe_peer,
+            addresses,
+            effective_role,
+        )
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner
+            .handle_established_outbound_connection(connection_id, peer, addr, role_override)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm<'_>) {
+        self.inner.on_swarm_event(event)
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        self.inner
+            .on_connection_handler_event(peer_id, connection_id, event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        if self.spent >= self.budget {
+            self.spent = 0;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        match self.inner.poll(cx) {
+            Poll::Ready(event) => {
+                self.spent += 1;
+                Poll::Ready(event)
+            }
+            Poll::Pending => {
+                self.spent = 0;
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::{Context, Poll};
+
+    use futures::task::noop_waker_ref;
+    use libp2p::core::Endpoint;
+    use libp2p::swarm::{
+        dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler,
+        THandlerInEvent, THandlerOutEvent, ToSwarm,
+    };
+    use libp2p::{Multiaddr, PeerId};
+
+    use super::Budgeted;
+
+    /// Emits `pending` events, one per poll, or forever if `pending` is `None`
+    struct Emitter {
+        pending: Option<usize>,
+    }
+
+    impl NetworkBehaviour for Emitter {
+        type ConnectionHandler = dummy::ConnectionHandler;
+        type ToSwarm = ();
+
+        fn handle_established_inbound_connection(
+            &mut self,
+            _: ConnectionId,
+            _: PeerId,
+            _: &Multiaddr,
+            _: &Multiaddr,
+        ) -> Result<THandler<Self>, ConnectionDenied> {
+            Ok(dummy::ConnectionHandler)
+        }
+
+        fn handle_established_outbound_connection(
+            &mut self,
+            _: ConnectionId,
+            _: PeerId,
+            _: &Multiaddr,
+            _: Endpoint,
+        ) -> Result<THandler<Self>, ConnectionDenied> {
+            Ok(dummy::ConnectionHandler)
+        }
+
+        fn on_swarm_event(&mut self, _: FromSwarm<'_>) {}
+
+        fn on_connection_handler_event(
+            &mut self,
+            _: PeerId,
+            _: ConnectionId,
+            _: THandlerOutEvent<Self>,
+        ) {
+        }
+
+        fn poll(&mut self, _: &mut Context<'_>) -> Poll<ToSwarm<(), THandlerInEvent<Self>>> {
+            match &mut self.pending {
+                None => Poll::Ready(ToSwarm::GenerateEvent(())),
+                Some(0) => Poll::Pending,
+                Some(n) => {
+                    *n -= 1;
+                    Poll::Ready(ToSwarm::GenerateEvent(()))
+                }
+            }
+        }
+    }
+
+    #[derive(NetworkBehaviour)]
+    #[behaviour(prelude = "libp2p::swarm::derive_prelude")]
+    struct Combined {
+        busy: Budgeted<Emitter>,
+        quiet: Emitter,
+    }
+
+    /// Counts polls until `quiet` emits `expected` events while `busy` is always ready
+    fn polls_to_deliver(busy: Budgeted<Emitter>, expected: usize, max_polls: usize) -> usize {
+        let mut combined = Combined {
+            busy,
+            quiet: Emitter {
+                pending: Some(expected),
+            },
+        };
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut delivered = 0;
+        for polls in 1..=max_polls {
+            if let Poll::Ready(ToSwarm::GenerateEvent(CombinedEvent::Quiet(()))) =
+                combined.poll(&mut cx)
+            {
+                delivered += 1;
+                if delivered == expected {
+                    return polls;
+                }
+            }
+        }
+        max_polls + 1
+    }
+
+    #[test]
+    fn unbudgeted_busy_behaviour_starves_siblings() {
+        let busy = Budgeted::new(Emitter { pending: None }, usize::MAX);
+        assert_eq!(polls_to_deliver(busy, 1, 10_000), 10_001);
+    }
+
+    #[test]
+    fn busy_behaviour_yields_after_budget() {
+        let budget = 16;
+        let busy = Budgeted::new(Emitter { pending: None }, budget);
+        assert_eq!(polls_to_deliver(busy, 1, 10_000), budget + 1);
+    }
+
+    #[test]
+    fn latency_stays_bounded_under_sustained_load() {
+        // every quiet event must be delivered within one budget round, no matter how many
+        // events the busy behaviour produces in total
+        let budget = 8;
+        let events = 1_000;
+        let busy = Budgeted::new(Emitter { pending: None }, budget);
+        let polls = polls_to_deliver(busy, events, 100_000);
+        assert!(
+            polls <= events * (budget + 1),
+            "{events} quiet events took {polls} polls"
+        );
+    }
+}
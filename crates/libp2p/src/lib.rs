@@ -29,6 +29,7 @@
     unreachable_patterns
 )]
 
+mod budgeted;
 mod connected_point;
 mod macros;
 pub mod random_multiaddr;
@@ -38,10 +39,16 @@ mod serde;
 mod transport;
 
 pub use self::serde::*;
+pub use budgeted::{Budgeted, DEFAULT_POLL_BUDGET};
 pub use connected_point::*;
+#[cfg(feature = "tokio")]
+pub use libp2p_webrtc::tokio::Certificate as WebRtcCertificate;
 pub use random_peer_id::RandomPeerId;
 #[cfg(feature = "tokio")]
-pub use transport::{build_memory_transport, build_transport, Transport};
+pub use transport::{
+    build_memory_transport, build_transport, load_or_generate_webrtc_certificate,
+    with_webrtc_transport, Transport,
+};
 
 // libp2p reexports
 pub use libp2p::PeerId;
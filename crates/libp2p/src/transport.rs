@@ -17,6 +17,8 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::io;
+use std::path::Path;
 use std::time::Duration;
 
 use futures::{AsyncRead, AsyncWrite};
@@ -27,6 +29,7 @@ use libp2p::dns::tokio::Transport as TokioDnsConfig;
 use libp2p::tcp::Transport as TcpTransport;
 use libp2p::tcp::{tokio::Tcp as TokioTcp, Config as GenTcpConfig};
 use libp2p::{core, identity::Keypair, PeerId, Transport as NetworkTransport};
+use libp2p_webrtc::tokio::Certificate;
 use serde::{Deserialize, Serialize};
 
 pub fn build_transport(
@@ -95,6 +98,42 @@ where
         .boxed()
 }
 
+/// Adds WebRTC transport next to the given one, so browsers can connect without a WebSocket proxy.
+///
+/// WebRTC connections are encrypted and multiplexed by the transport itself,
+/// so no upgrades are applied to it.
+pub fn with_webrtc_transport(
+    transport: Boxed<(PeerId, StreamMuxerBox)>,
+    key_pair: &Keypair,
+    certificate: Certificate,
+) -> Boxed<(PeerId, StreamMuxerBox)> {
+    let webrtc = libp2p_webrtc::tokio::Transport::new(key_pair.clone(), certificate)
+        .map(|(peer_id, conn), _| (peer_id, StreamMuxerBox::new(conn)));
+
+    transport
+        .or_transport(webrtc)
+        .map(|either, _| either.into_inner())
+        .boxed()
+}
+
+/// Loads WebRTC certificate from a PEM file, generates and stores a new one if there's none.
+///
+/// The certificate must stay the same across restarts since its hash is a part of the node multiaddr.
+pub fn load_or_generate_webrtc_certificate(path: &Path) -> io::Result<Certificate> {
+    let invalid_data = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+
+    match std::fs::read_to_string(path) {
+        Ok(pem) => Certificate::from_pem(&pem).map_err(invalid_data),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            let certificate =
+                Certificate::generate(&mut rand::thread_rng()).map_err(invalid_data)?;
+            std::fs::write(path, certificate.serialize_pem())?;
+            Ok(certificate)
+        }
+        Err(err) => Err(err),
+    }
+}
+
 pub fn build_memory_transport(
     key_pair: &Keypair,
     transport_timeout: Duration,
@@ -124,3 +163,20 @@ impl Transport {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::load_or_generate_webrtc_certificate;
+
+    #[test]
+    fn webrtc_certificate_is_persisted() {
+        let dir = tempfile::tempdir().expect("create temp dir");
+        let path = dir.path().join("webrtc_certificate.pem");
+
+        let generated = load_or_generate_webrtc_certificate(&path).expect("generate certificate");
+        assert!(path.exists());
+        let loaded = load_or_generate_webrtc_certificate(&path).expect("load certificate");
+
+        assert_eq!(generated.fingerprint(), loaded.fingerprint());
+    }
+}
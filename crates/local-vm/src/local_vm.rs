@@ -101,10 +101,12 @@ pub fn client_functions(data: &HashMap<String, JValue>, args: Args) -> ClientFun
                 returned: None,
             }
         }
-        ("return", _) | ("op", "return") | ("callbackSrv", "response") => ClientFunctionsResult {
-            outcome: FunctionOutcome::Empty,
-            returned: Some(Ok(args.function_args)),
-        },
+        ("return", _) | ("op", "return") | ("callbackSrv", "response") | ("progress", _) => {
+            ClientFunctionsResult {
+                outcome: FunctionOutcome::Empty,
+                returned: Some(Ok(args.function_args)),
+            }
+        }
         ("callbackSrv", _) => {
             log::warn!("got callback: {:?}", args.function_args);
             ClientFunctionsResult {
@@ -272,6 +274,9 @@ pub async fn make_particle(
         script: script.clone(),
         signature: vec![],
         data: vec![],
+        trail: None,
+        progress: false,
+        hops: 0,
     };
     // We can sign at this point since the `data` which is evaluated below isn't part of the signature
     particle.sign(key_pair).expect("sign particle");
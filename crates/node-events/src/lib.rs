@@ -0,0 +1,401 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Log of significant node events: peer churn, services and spells lifecycle, config reloads.
+//! The log is bounded by the number of events and their age, and is persisted as JSON lines.
+//! Frequent events, like spell triggers and expired particles, are only streamed to the live
+//! subscribers and are not persisted.
+
+#![warn(rust_2018_idioms)]
+#![deny(
+    dead_code,
+    nonstandard_style,
+    unused_imports,
+    unused_mut,
+    unused_variables,
+    unused_unsafe,
+    unreachable_patterns
+)]
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use now_millis::now_ms;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Events a live subscriber may fall behind by before it starts missing them
+const LIVE_EVENTS_BUFFER: usize = 1024;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    PeerConnected,
+    PeerDisconnected,
+    ServiceCreated,
+    ServiceRemoved,
+    ServiceFailed,
+    ServiceRestarted,
+    SpellInstalled,
+    SpellRemoved,
+    ConfigReloaded,
+    /// Streamed to the live subscribers only
+    SpellTriggered,
+    /// Streamed to the live subscribers only
+    ParticleExpired,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct NodeEvent {
+    pub seq: u64,
+    /// Unix timestamp in milliseconds
+    pub timestamp: u64,
+    pub kind: EventKind,
+    /// Peer id, service id or spell id the event is about
+    pub subject: String,
+    /// Worker the event happened on, `None` for the host
+    pub worker_id: Option<String>,
+    pub message: Option<String>,
+}
+
+/// Events matching all of the set fields
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EventFilter {
+    /// Any of the kinds, all kinds if empty
+    #[serde(default)]
+    pub kinds: Vec<EventKind>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub worker_id: Option<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &NodeEvent) -> bool {
+        (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+            && self.subject.as_ref().map_or(true, |s| s == &event.subject)
+            && self
+                .worker_id
+                .as_ref()
+                .map_or(true, |w| Some(w) == event.worker_id.as_ref())
+    }
+}
+
+struct Inner {
+    path: PathBuf,
+    file: File,
+    events: VecDeque<NodeEvent>,
+    /// Records in the file, including the pruned ones
+    records: usize,
+    next_seq: u64,
+    live: broadcast::Sender<NodeEvent>,
+}
+
+/// Shared handle to the event log. The default one is disabled and drops all events.
+#[derive(Clone, Default)]
+pub struct EventLog {
+    inner: Option<Arc<Mutex<Inner>>>,
+    capacity: usize,
+    retention: Duration,
+}
+
+impl fmt::Debug for EventLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventLog")
+            .field("enabled", &self.inner.is_some())
+            .field("capacity", &self.capacity)
+            .field("retention", &self.retention)
+            .finish()
+    }
+}
+
+impl EventLog {
+    /// Opens the log at `path`, dropping events beyond `capacity` or older than `retention`
+    pub fn open(path: PathBuf, capacity: usize, retention: Duration) -> io::Result<Self> {
+        let mut events = VecDeque::new();
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                let line = line?;
+                match serde_json::from_str::<NodeEvent>(&line) {
+                    Ok(event) => events.push_back(event),
+                    // the last record could be cut short by a crash
+                    Err(err) => log::warn!("Skipping corrupted event log record: {err}"),
+                }
+            }
+        }
+        let next_seq = events.back().map_or(0, |e| e.seq + 1);
+        prune(&mut events, capacity, retention, now_ms() as u64);
+        let file = write_compacted(&path, &events)?;
+        let records = events.len();
+
+        Ok(Self {
+            inner: Some(Arc::new(Mutex::new(Inner {
+                path,
+                file,
+                events,
+                records,
+                next_seq,
+                live: broadcast::channel(LIVE_EVENTS_BUFFER).0,
+            }))),
+            capacity,
+            retention,
+        })
+    }
+
+    pub fn record(
+        &self,
+        kind: EventKind,
+        subject: impl Into<String>,
+        worker_id: Option<String>,
+        message: Option<String>,
+    ) {
+        let Some(inner) = &self.inner else { return };
+        let mut inner = inner.lock();
+        let event = inner.next_event(kind, subject.into(), worker_id, message);
+
+        if let Err(err) = append(&mut inner.file, &event) {
+            log::warn!("Error writing event {:?} to the log: {err}", event.kind);
+        }
+        inner.records += 1;
+        let now = event.timestamp;
+        inner.events.push_back(event);
+        prune(&mut inner.events, self.capacity, self.retention, now);
+
+        if inner.records > 2 * self.capacity.max(1) {
+            match write_compacted(&inner.path, &inner.events) {
+                Ok(file) => {
+                    inner.file = file;
+                    inner.records = inner.events.len();
+                }
+                Err(err) => log::warn!("Error compacting event log: {err}"),
+            }
+        }
+    }
+
+    /// Sends the event to the live subscribers without persisting it
+    pub fn publish(
+        &self,
+        kind: EventKind,
+        subject: impl Into<String>,
+        worker_id: Option<String>,
+        message: Option<String>,
+    ) {
+        let Some(inner) = &self.inner else { return };
+        inner
+            .lock()
+            .next_event(kind, subject.into(), worker_id, message);
+    }
+
+    /// Events returned by [`EventLog::query`] along with the receiver of the events recorded or
+    /// published after them. The receiver of a disabled log is closed.
+    pub fn subscribe(
+        &self,
+        filter: &EventFilter,
+        since: u64,
+        limit: usize,
+    ) -> (Vec<NodeEvent>, broadcast::Receiver<NodeEvent>) {
+        let Some(inner) = &self.inner else {
+            return (vec![], broadcast::channel(1).1);
+        };
+        // Hold the lock, so no event is recorded between the query and the subscription
+        let inner = inner.lock();
+        let events = query(&inner.events, filter, since, limit, self.retention);
+        (events, inner.live.subscribe())
+    }
+
+    /// Oldest first, at most `limit` events matching the filter with timestamp at least `since`
+    pub fn query(&self, filter: &EventFilter, since: u64, limit: usize) -> Vec<NodeEvent> {
+        let Some(inner) = &self.inner else {
+            return vec![];
+        };
+        query(&inner.lock().events, filter, since, limit, self.retention)
+    }
+}
+
+impl Inner {
+    /// Assigns the next sequence number and sends the event to the live subscribers
+    fn next_event(
+        &mut self,
+        kind: EventKind,
+        subject: String,
+        worker_id: Option<String>,
+        message: Option<String>,
+    ) -> NodeEvent {
+        let event = NodeEvent {
+            seq: self.next_seq,
+            timestamp: now_ms() as u64,
+            kind,
+            subject,
+            worker_id,
+            message,
+        };
+        self.next_seq += 1;
+        // No subscribers is not an error
+        let _ = self.live.send(event.clone());
+        event
+    }
+}
+
+fn query(
+    events: &VecDeque<NodeEvent>,
+    filter: &EventFilter,
+    since: u64,
+    limit: usize,
+    retention: Duration,
+) -> Vec<NodeEvent> {
+    let min_timestamp = (now_ms() as u64).saturating_sub(retention.as_millis() as u64);
+    events
+        .iter()
+        .filter(|e| e.timestamp >= since.max(min_timestamp))
+        .filter(|e| filter.matches(e))
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+fn prune(events: &mut VecDeque<NodeEvent>, capacity: usize, retention: Duration, now: u64) {
+    let min_timestamp = now.saturating_sub(retention.as_millis() as u64);
+    while events.front().map_or(false, |e| {
+        events.len() > capacity || e.timestamp < min_timestamp
+    }) {
+        events.pop_front();
+    }
+}
+
+fn append(file: &mut File, event: &NodeEvent) -> io::Result<()> {
+    let mut line = serde_json::to_vec(event)?;
+    line.push(b'\n');
+    file.write_all(&line)
+}
+
+/// Atomically replaces the log with the retained events, returns the file to append to
+fn write_compacted(path: &Path, events: &VecDeque<NodeEvent>) -> io::Result<File> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        for event in events {
+            serde_json::to_writer(&mut file, event)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)?;
+    OpenOptions::new().append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn survives_restart_and_keeps_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.log");
+
+        let log = EventLog::open(path.clone(), 3, DAY).unwrap();
+        for i in 0..5 {
+            log.record(EventKind::PeerConnected, format!("peer{i}"), None, None);
+        }
+        drop(log);
+
+        let log = EventLog::open(path, 3, DAY).unwrap();
+        let events = log.query(&EventFilter::default(), 0, 10);
+        let subjects: Vec<_> = events.iter().map(|e| e.subject.as_str()).collect();
+        assert_eq!(subjects, vec!["peer2", "peer3", "peer4"]);
+
+        log.record(EventKind::ConfigReloaded, "config", None, None);
+        let last = log.query(&EventFilter::default(), 0, 10).pop().unwrap();
+        assert_eq!(last.seq, 5);
+    }
+
+    #[test]
+    fn filters_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = EventLog::open(dir.path().join("events.log"), 100, DAY).unwrap();
+        log.record(EventKind::SpellInstalled, "spell", Some("w1".into()), None);
+        log.record(EventKind::ServiceFailed, "srv", None, Some("trap".into()));
+        log.record(EventKind::SpellRemoved, "spell", Some("w1".into()), None);
+
+        let filter = EventFilter {
+            kinds: vec![EventKind::SpellInstalled, EventKind::ServiceFailed],
+            ..<_>::default()
+        };
+        assert_eq!(log.query(&filter, 0, 10).len(), 2);
+        assert_eq!(log.query(&filter, 0, 1).len(), 1);
+
+        let filter = EventFilter {
+            worker_id: Some("w1".into()),
+            ..<_>::default()
+        };
+        assert_eq!(log.query(&filter, 0, 10).len(), 2);
+
+        let future = now_ms() as u64 + 60_000;
+        assert!(log.query(&EventFilter::default(), future, 10).is_empty());
+    }
+
+    #[test]
+    fn disabled_log_drops_events() {
+        let log = EventLog::default();
+        log.record(EventKind::PeerConnected, "peer", None, None);
+        assert!(log.query(&EventFilter::default(), 0, 10).is_empty());
+
+        let (recent, mut live) = log.subscribe(&EventFilter::default(), 0, 10);
+        assert!(recent.is_empty());
+        assert!(matches!(
+            live.try_recv(),
+            Err(broadcast::error::TryRecvError::Closed)
+        ));
+    }
+
+    #[test]
+    fn streams_live_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = EventLog::open(dir.path().join("events.log"), 100, DAY).unwrap();
+        log.record(EventKind::PeerConnected, "peer", None, None);
+
+        let (recent, mut live) = log.subscribe(&EventFilter::default(), 0, 10);
+        assert_eq!(recent.len(), 1);
+
+        log.publish(EventKind::SpellTriggered, "spell", None, None);
+        log.record(EventKind::PeerDisconnected, "peer", None, None);
+        assert_eq!(live.try_recv().unwrap().kind, EventKind::SpellTriggered);
+        let disconnected = live.try_recv().unwrap();
+        assert_eq!(disconnected.kind, EventKind::PeerDisconnected);
+        assert_eq!(disconnected.seq, 2);
+
+        // published events aren't persisted
+        let kinds: Vec<_> = log
+            .query(&EventFilter::default(), 0, 10)
+            .into_iter()
+            .map(|e| e.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![EventKind::PeerConnected, EventKind::PeerDisconnected]
+        );
+    }
+}
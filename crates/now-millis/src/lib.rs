@@ -17,6 +17,8 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Returns UNIX timestamp as Duration
@@ -35,3 +37,27 @@ pub fn now_ms() -> u128 {
 pub fn now_sec() -> u64 {
     now().as_secs()
 }
+
+/// Estimated skew of the clock of a node in milliseconds, positive if it runs ahead of the network.
+///
+/// Clones share the value, so the estimator of a node can update it for the components
+/// checking particle deadlines without affecting other nodes of the same process.
+#[derive(Clone, Debug, Default)]
+pub struct ClockCorrection(Arc<AtomicI64>);
+
+impl ClockCorrection {
+    /// Sets the correction subtracted from the local clock by `now_ms`
+    pub fn set_ms(&self, correction: i64) {
+        self.0.store(correction, Ordering::Relaxed)
+    }
+
+    pub fn get_ms(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns UNIX timestamp in milliseconds, corrected by the estimated local clock skew
+    pub fn now_ms(&self) -> u128 {
+        let now = now_ms() as i128 - self.get_ms() as i128;
+        now.max(0) as u128
+    }
+}
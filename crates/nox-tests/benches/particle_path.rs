@@ -0,0 +1,119 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use connected_client::ConnectedClient;
+use created_swarm::make_swarms;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use maplit::hashmap;
+use serde_json::json;
+
+use nox::bench::{process_frame, signed_frame, TriggerLoop};
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("build runtime")
+}
+
+/// Decode → verify → encode of a single particle frame
+fn wire_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("wire_path");
+    for data_size in [0usize, 1024, 64 * 1024] {
+        let frame = signed_frame(data_size).expect("signed frame");
+        group.throughput(Throughput::Bytes(frame.len() as u64));
+        group.bench_function(format!("data_{data_size}"), |b| {
+            b.iter_batched(
+                || frame.clone(),
+                |frame| process_frame(frame).expect("process frame"),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Client → relay → builtin call → client, through a single in-process node
+fn builtin_call(c: &mut Criterion) {
+    let rt = runtime();
+    let swarms = rt.block_on(make_swarms(1));
+    let mut client = rt
+        .block_on(ConnectedClient::connect_to(swarms[0].multiaddr.clone()))
+        .expect("connect client");
+
+    let script = r#"
+        (seq
+            (call relay ("peer" "timestamp_ms") [] ts)
+            (call client ("return" "") [ts])
+        )
+    "#;
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+    };
+
+    let mut group = c.benchmark_group("particle");
+    group.throughput(Throughput::Elements(1));
+    group.sample_size(20);
+    group.bench_function("builtin_call", |b| {
+        b.iter_custom(|iters| {
+            rt.block_on(async {
+                let start = Instant::now();
+                for _ in 0..iters {
+                    client
+                        .execute_particle(script, data.clone())
+                        .await
+                        .expect("execute particle");
+                }
+                start.elapsed()
+            })
+        })
+    });
+    group.finish();
+}
+
+/// KV write → spell event bus → trigger event
+fn spell_triggers(c: &mut Criterion) {
+    let rt = runtime();
+    let mut trigger_loop = rt.block_on(TriggerLoop::new()).expect("trigger loop");
+
+    let mut group = c.benchmark_group("spell");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("kv_trigger", |b| {
+        b.iter_custom(|iters| {
+            rt.block_on(async {
+                let start = Instant::now();
+                for _ in 0..iters {
+                    trigger_loop.trigger_once().await.expect("trigger");
+                }
+                start.elapsed()
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().measurement_time(Duration::from_secs(10));
+    targets = wire_path, builtin_call, spell_triggers
+}
+criterion_main!(benches);
@@ -19,7 +19,7 @@
 
 #[macro_use]
 extern crate fstrings;
-use connected_client::ConnectedClient;
+use connected_client::{ConnectedClient, ParticleBuilder};
 use created_swarm::{
     make_swarms, make_swarms_with_cfg, make_swarms_with_keypair,
     make_swarms_with_transport_and_mocked_vm,
@@ -87,6 +87,102 @@ async fn identify() {
         .unwrap_or_else(|_| panic!("deserialize {:?}", info[0]));
 }
 
+#[tokio::test]
+async fn peer_versions() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (call relay ("peer" "versions") [] versions)
+            (call client ("op" "return") [versions])
+        )
+        "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "client" => json!(client.peer_id.to_string()),
+            },
+        )
+        .await;
+
+    let versions = client
+        .receive_args()
+        .await
+        .wrap_err("receive args")
+        .unwrap();
+    let versions = &versions[0];
+    for component in ["node", "avm", "spell", "marine", "decider"] {
+        assert!(
+            versions[component].is_string(),
+            "no {component} version in {versions}"
+        );
+    }
+    assert!(versions["protocols"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("/fluence/particle/2.0.0")));
+}
+
+#[tokio::test]
+async fn particle_builder_call() {
+    let swarms = make_swarms(2).await;
+
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+
+    let info = client
+        .call(ParticleBuilder::call("peer", "identify").on(swarms[1].peer_id.to_string()))
+        .await
+        .wrap_err("call peer.identify")
+        .unwrap();
+    let _: NodeInfo = serde_json::from_value(info[0].clone())
+        .unwrap_or_else(|_| panic!("deserialize {:?}", info[0]));
+
+    let services = client
+        .call(ParticleBuilder::call("srv", "list"))
+        .await
+        .wrap_err("call srv.list")
+        .unwrap();
+    assert!(services[0].is_array());
+
+    let err = client
+        .call(ParticleBuilder::call("srv", "add_alias").arg("alias"))
+        .await
+        .expect_err("malformed call must be rejected before sending");
+    assert!(err.to_string().contains("expects 2..=2 arguments"));
+}
+
+#[tokio::test]
+async fn particle_builder_call_redundant() {
+    let swarms = make_swarms(3).await;
+
+    let mut client = ConnectedClient::connect_with_relays(
+        vec![swarms[0].multiaddr.clone(), swarms[1].multiaddr.clone()],
+        None,
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+    assert_eq!(client.node, swarms[0].peer_id);
+    assert_eq!(client.redundant_relays, vec![swarms[1].peer_id]);
+
+    let info = client
+        .call_redundant(ParticleBuilder::call("peer", "identify").on(swarms[2].peer_id.to_string()))
+        .await
+        .wrap_err("call peer.identify")
+        .unwrap();
+    let _: NodeInfo = serde_json::from_value(info[0].clone())
+        .unwrap_or_else(|_| panic!("deserialize {:?}", info[0]));
+}
+
 #[ignore]
 #[tokio::test]
 async fn big_identity() {
@@ -374,6 +470,129 @@ async fn non_owner_remove_service() {
     }
 }
 
+#[tokio::test]
+async fn service_package_import() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let tetraplets_service = create_service(
+        &mut client,
+        "tetraplets",
+        load_module("tests/tetraplets/artifacts", "tetraplets").expect("load module"),
+    )
+    .await;
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("srv" "package") [service] package)
+                (call relay ("srv" "import") [package] imported)
+            )
+            (seq
+                (call relay ("srv" "info") [imported] info)
+                (call %init_peer_id% ("op" "return") [package info])
+            )
+        )
+    "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "service" => json!(tetraplets_service.id),
+            },
+        )
+        .await;
+
+    let args = client.receive_args().await.unwrap();
+    let package = &args[0];
+    let info = &args[1];
+    assert_eq!(package["blueprint"]["modules"].as_array().unwrap().len(), 1);
+    assert_eq!(info["blueprint_id"], package["blueprint"]["blueprint_id"]);
+    assert_ne!(info["id"], json!(tetraplets_service.id));
+
+    // a package with tampered module bytes is rejected
+    let mut tampered = package.clone();
+    tampered["blueprint"]["modules"][0]["wasm"] = json!("AAAA");
+    client
+        .send_particle(
+            r#"
+        (xor
+            (call relay ("srv" "import") [package])
+            (call %init_peer_id% ("op" "return") [%last_error%.$.message])
+        )
+    "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "package" => tampered,
+            },
+        )
+        .await;
+
+    let error = client.receive_args().await.unwrap();
+    assert!(
+        error[0].as_str().unwrap().contains("pinned hash"),
+        "unexpected error: {error:?}"
+    );
+}
+
+#[tokio::test]
+async fn event_query() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let tetraplets_service = create_service(
+        &mut client,
+        "tetraplets",
+        load_module("tests/tetraplets/artifacts", "tetraplets").expect("load module"),
+    )
+    .await;
+
+    client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("srv" "remove") [service])
+                (call relay ("event" "query") [filter] events)
+            )
+            (call %init_peer_id% ("op" "return") [events])
+        )
+    "#,
+            hashmap! {
+                "relay" => json!(client.node.to_string()),
+                "service" => json!(tetraplets_service.id),
+                "filter" => json!({
+                    "kinds": ["service_created", "service_removed"],
+                    "subject": tetraplets_service.id,
+                }),
+            },
+        )
+        .await;
+
+    let args = client.receive_args().await.unwrap();
+    let kinds: Vec<_> = args[0]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, vec!["service_created", "service_removed"]);
+}
+
 #[tokio::test]
 async fn resolve_alias() {
     let swarms = make_swarms(1).await;
@@ -1478,6 +1697,158 @@ async fn cmp_cmp() {
     );
 }
 
+#[tokio::test]
+async fn math_min_max_clamp() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .unwrap();
+    assert_eq!(
+        binary_with("math", "min", 2, -4, &mut client)
+            .await
+            .unwrap(),
+        json!(-4)
+    );
+    assert_eq!(
+        binary_with("math", "max", 2, -4, &mut client)
+            .await
+            .unwrap(),
+        json!(2)
+    );
+
+    let result = exec_script_with(
+        &mut client,
+        r#"(call relay ("math" "clamp") [15 0 10] result)"#,
+        hashmap! {},
+        "result",
+    )
+    .await
+    .unwrap();
+    assert_eq!(result[0], json!(10));
+}
+
+#[tokio::test]
+async fn time_builtins() {
+    assert_eq!(
+        unary("time", "parse_duration", "1m 30s").await.unwrap(),
+        json!(90_000)
+    );
+
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .unwrap();
+    let result = exec_script_with(
+        &mut client,
+        r#"
+        (seq
+            (seq
+                (call relay ("time" "monotonic_ms") [] start)
+                (call relay ("time" "elapsed_ms") [start] elapsed)
+            )
+            (call relay ("time" "backoff") [100 3 500] backoff)
+        )
+        "#,
+        hashmap! {},
+        "elapsed backoff",
+    )
+    .await
+    .unwrap();
+    assert!(result[0].as_u64().is_some());
+    assert_eq!(result[1], json!(500));
+}
+
+#[tokio::test]
+async fn rand_seeded() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .unwrap();
+    let result = exec_script_with(
+        &mut client,
+        r#"
+        (seq
+            (seq
+                (call relay ("rand" "int") [0 1000000 seed] first)
+                (call relay ("rand" "int") [0 1000000 seed] second)
+            )
+            (call relay ("rand" "sample") [1.0 []] sampled)
+        )
+        "#,
+        hashmap! {
+            "seed" => json!([42]),
+        },
+        "first second sampled",
+    )
+    .await
+    .unwrap();
+    assert_eq!(result[0], result[1]);
+    assert_eq!(result[2], json!(true));
+}
+
+#[tokio::test]
+async fn id_generate_verify() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .unwrap();
+    let result = exec_script_with(
+        &mut client,
+        r#"
+        (seq
+            (seq
+                (call relay ("id" "generate") ["uuid_v7"] id)
+                (call relay ("id" "verify") ["uuid_v7" id] info)
+            )
+            (call relay ("id" "verify") ["ksuid" id] invalid)
+        )
+        "#,
+        hashmap! {},
+        "id info invalid",
+    )
+    .await
+    .unwrap();
+    assert_eq!(result[0].as_str().unwrap().len(), 36);
+    assert_eq!(result[1]["valid"], json!(true));
+    assert_eq!(result[1]["timestamp_ms"].as_array().unwrap().len(), 1);
+    assert_eq!(result[2], json!({"valid": false, "timestamp_ms": []}));
+}
+
+#[tokio::test]
+async fn collect_scatter_gather() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .unwrap();
+    let result = exec_script_with(
+        &mut client,
+        r#"
+        (seq
+            (call relay ("collect" "create") [3 10000] handle)
+            (par
+                (fold items i
+                    (par
+                        (call relay ("collect" "push") [handle i])
+                        (next i)
+                    )
+                )
+                (call relay ("collect" "await") [handle] collected)
+            )
+        )
+        "#,
+        hashmap! {
+            "items" => json!([1, 2, 3]),
+        },
+        "collected",
+    )
+    .await
+    .unwrap();
+
+    let mut collected: Vec<i64> = serde_json::from_value(result[0].clone()).unwrap();
+    collected.sort_unstable();
+    assert_eq!(collected, vec![1, 2, 3]);
+}
+
 #[tokio::test]
 async fn array_sum() {
     assert_eq!(
@@ -2488,3 +2859,81 @@ async fn subnet_resolve() {
     // check that mock was called
     mock.assert();
 }
+
+#[tokio::test]
+async fn sched_submit() {
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "script" => json!(format!(r#"(call "{}" ("return" "") ["scheduled"])"#, client.peer_id)),
+    };
+
+    let result = client
+        .execute_particle(
+            r#"
+            (seq
+                (seq
+                    (call relay ("sched" "submit") [script [] [1]] job_id)
+                    (call relay ("sched" "list") [] jobs)
+                )
+                (call client ("return" "") [job_id jobs])
+            )
+            "#,
+            data.clone(),
+        )
+        .await
+        .unwrap();
+
+    let job_id = result[0].as_str().unwrap();
+    assert_eq!(result[1][0]["id"], json!(job_id));
+
+    let result = client.receive_args().await.unwrap();
+    assert_eq!(result, vec![json!("scheduled")]);
+
+    let result = client
+        .execute_particle(
+            r#"
+            (seq
+                (call relay ("sched" "list") [] jobs)
+                (call client ("return" "") [jobs])
+            )
+            "#,
+            data,
+        )
+        .await
+        .unwrap();
+    assert_eq!(result, vec![json!([])]);
+}
+
+#[tokio::test]
+async fn sched_list_requires_permissions() {
+    let error = exec_script(
+        r#"
+        (xor
+            (call relay ("sched" "list") [] jobs)
+            (ap %last_error%.$.message error)
+        )
+        "#,
+        <_>::default(),
+        "error",
+        1,
+    )
+    .await
+    .unwrap();
+
+    let error = error[0].as_str().unwrap();
+    assert!(
+        error.contains("can be managed only by the host or peer manager"),
+        "{error}"
+    );
+}
@@ -95,6 +95,9 @@ async fn call_custom_service() {
         script,
         signature: vec![],
         data: vec![],
+        trail: None,
+        progress: false,
+        hops: 0,
     };
 
     let exec_f = swarms[1]
@@ -17,12 +17,13 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
 use connected_client::ConnectedClient;
 use created_swarm::make_swarms;
 
 use eyre::WrapErr;
 use maplit::hashmap;
-use serde_json::json;
+use serde_json::{json, Value as JValue};
 
 #[tokio::test]
 async fn echo_particle() {
@@ -50,3 +51,93 @@ async fn echo_particle() {
         .unwrap();
     assert_eq!(data["name"], response[0]);
 }
+
+#[tokio::test]
+async fn route_trail() {
+    let swarms = make_swarms(2).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+    client.set_route_trail(true);
+
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "peer" => json!(swarms[1].peer_id.to_string()),
+    };
+    client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call peer ("op" "noop") [])
+                (call relay ("op" "noop") [])
+            )
+            (call client ("return" "") [])
+        )"#,
+            data,
+        )
+        .await;
+
+    let particle = client.receive().await.unwrap();
+    let trail = particle.trail.expect("trail is recorded");
+    let route: Vec<_> = trail.iter().map(|hop| hop.peer_id).collect();
+    assert_eq!(
+        route,
+        vec![swarms[0].peer_id, swarms[1].peer_id, swarms[0].peer_id]
+    );
+    assert!(trail.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+}
+
+#[tokio::test]
+async fn progress_particles() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_to(swarms[0].multiaddr.clone())
+        .await
+        .wrap_err("connect client")
+        .unwrap();
+    client.set_progress(true);
+
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+    };
+    let particle_id = client
+        .send_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("op" "identity") ["hello"] x)
+                (call relay ("op" "noop") [])
+            )
+            (call client ("return" "") [x])
+        )"#,
+            data,
+        )
+        .await;
+
+    let result = client.wait_particle_args(&particle_id).await.unwrap();
+    assert_eq!(result, vec![json!("hello")]);
+
+    let mut reported = vec![];
+    for seq in 0..2 {
+        let args = client
+            .wait_particle_args(format!("{particle_id}_progress_{seq}"))
+            .await
+            .unwrap();
+        assert_eq!(args[0], json!(swarms[0].peer_id.to_string()));
+        let calls = base64.decode(args[1].as_str().unwrap()).unwrap();
+        let calls: Vec<JValue> = serde_json::from_slice(&calls).unwrap();
+        reported.extend(calls);
+    }
+    let functions: Vec<_> = reported
+        .iter()
+        .map(|c| (c["function_name"].clone(), c["ret_code"].clone()))
+        .collect();
+    assert_eq!(
+        functions,
+        vec![(json!("identity"), json!(0)), (json!("noop"), json!(0))]
+    );
+    assert_eq!(reported[0]["result"], json!("hello"));
+}
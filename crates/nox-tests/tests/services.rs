@@ -543,3 +543,68 @@ async fn test_create_service_by_other_forbidden() {
         response[0]
     );
 }
+
+#[tokio::test]
+async fn test_list_services_by_labels() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+    let module_name = "tetraplets";
+    let module_bytes = load_module("tests/tetraplets/artifacts", module_name).expect("load module");
+    let response = client
+        .execute_particle(
+            r#"
+        (seq
+            (seq
+                (seq
+                    (call relay ("dist" "default_module_config") [module_name] module_config)
+                    (call relay ("dist" "add_module") [module_bytes module_config] module)
+                )
+                (seq
+                    (seq
+                        (call relay ("dist" "make_blueprint") [name dependencies] blueprint)
+                        (call relay ("dist" "add_blueprint") [blueprint] blueprint_id)
+                    )
+                    (seq
+                        (call relay ("srv" "create") [blueprint_id "app=indexer,env=prod"] prod_id)
+                        (call relay ("srv" "create") [blueprint_id "app=indexer,env=dev"] dev_id)
+                    )
+                )
+            )
+            (seq
+                (seq
+                    (call relay ("srv" "list") ["env=prod"] prod)
+                    (call relay ("srv" "list") ["app=indexer,env!=prod"] not_prod)
+                )
+                (call client ("return" "") [prod_id dev_id prod not_prod])
+            )
+        )"#,
+            hashmap! {
+                "client" => json!(client.peer_id.to_string()),
+                "relay" => json!(client.node.to_string()),
+                "module_name" => json!("module"),
+                "dependencies" => json!([Hash::new(&module_bytes).unwrap()]),
+                "module_bytes" => json!(base64.encode(module_bytes)),
+                "name" => json!("service1"),
+            },
+        )
+        .await
+        .unwrap();
+
+    let ids = |services: &JValue| -> Vec<String> {
+        services
+            .as_array()
+            .expect("srv.list returns an array")
+            .iter()
+            .map(|s| s["id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(ids(&response[2]), vec![response[0].as_str().unwrap()]);
+    assert_eq!(ids(&response[3]), vec![response[1].as_str().unwrap()]);
+    assert_eq!(response[2][0]["labels"], json!(["app=indexer", "env=prod"]));
+}
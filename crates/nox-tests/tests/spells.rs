@@ -35,7 +35,7 @@ use log_utils::enable_logs;
 use service_modules::load_module;
 use spell_event_bus::api::{TriggerInfo, TriggerInfoAqua, MAX_PERIOD_SEC};
 use test_constants::TRANSPORT_TIMEOUT;
-use test_utils::{create_service, create_service_worker};
+use test_utils::{create_service, create_service_worker, spell};
 use workers::CUID;
 
 type SpellId = String;
@@ -397,6 +397,132 @@ async fn spell_install_alias() {
     assert_eq!(actual_spell_id, aliased_spell_id);
 }
 
+#[tokio::test]
+async fn spell_run_as_restricts_calls() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let script = format!(
+        r#"
+        (seq
+            (seq
+                (call %init_peer_id% ("getDataSrv" "spell_id") [] spell_id)
+                (call %init_peer_id% (spell_id "get_script") [] script)
+            )
+            (xor
+                (call %init_peer_id% ("srv" "remove") ["non_existent_srv_id"])
+                (call "{}" ("return" "") [script.$.success %last_error%.$.message])
+            )
+        )"#,
+        client.peer_id
+    );
+
+    let worker_id = create_worker(&mut client, None).await;
+    let data = hashmap! {
+        "script" => json!(script),
+        "config" => json!(make_clock_config(0, 1, 0)),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+        "data" => json!({}),
+        "run_as" => json!({"name": "reader", "grants": [{"service": "kv", "functions": ["get"]}]}),
+    };
+    client
+        .send_particle(
+            r#"
+        (seq
+            (call relay ("op" "noop") [])
+            (seq
+                (call worker_id ("spell" "install") [script data config [] [] run_as] spell_id)
+                (call client ("return" "") [spell_id])
+            )
+        )"#,
+            data,
+        )
+        .await;
+    let response = client.receive_args().await.wrap_err("receive").unwrap();
+    assert!(response[0].is_string(), "spell is installed: {response:?}");
+
+    let response = client.receive_args().await.wrap_err("receive").unwrap();
+    assert_eq!(response[0], json!(true), "spell reads its own KV");
+    let message = response[1].as_str().unwrap();
+    assert!(
+        message.contains("spell identity 'reader' isn't granted it"),
+        "unexpected error: {message}"
+    );
+}
+
+#[tokio::test]
+async fn spell_run_as_restricts_non_mutating_builtins() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let script = format!(
+        r#"
+        (seq
+            (xor
+                (call %init_peer_id% ("capability" "disable") ["srv"])
+                (call %init_peer_id% ("op" "identity") [%last_error%.$.message] capability_error)
+            )
+            (seq
+                (xor
+                    (call %init_peer_id% ("session" "open") [])
+                    (call %init_peer_id% ("op" "identity") [%last_error%.$.message] session_error)
+                )
+                (call "{}" ("return" "") [capability_error session_error])
+            )
+        )"#,
+        client.peer_id
+    );
+
+    let worker_id = create_worker(&mut client, None).await;
+    let data = hashmap! {
+        "script" => json!(script),
+        "config" => json!(make_clock_config(0, 1, 0)),
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+        "data" => json!({}),
+        "run_as" => json!({"name": "reader", "grants": [{"service": "kv", "functions": ["get"]}]}),
+    };
+    client
+        .send_particle(
+            r#"
+        (seq
+            (call relay ("op" "noop") [])
+            (seq
+                (call worker_id ("spell" "install") [script data config [] [] run_as] spell_id)
+                (call client ("return" "") [spell_id])
+            )
+        )"#,
+            data,
+        )
+        .await;
+    let response = client.receive_args().await.wrap_err("receive").unwrap();
+    assert!(response[0].is_string(), "spell is installed: {response:?}");
+
+    let response = client.receive_args().await.wrap_err("receive").unwrap();
+    for error in response {
+        let message = error.as_str().unwrap();
+        assert!(
+            message.contains("spell identity 'reader' isn't granted it"),
+            "unexpected error: {message}"
+        );
+    }
+}
+
 // Check that oneshot spells are actually executed and executed only once
 #[tokio::test]
 async fn spell_run_oneshot() {
@@ -1494,7 +1620,7 @@ async fn resolve_alias_wrong_worker() {
         .unwrap()
         .as_slice()
     {
-        assert!(error.starts_with("Local service error, ret_code is 1, error message is '\"Error: Service with alias 'alias' is not found on worker"));
+        assert!(error.starts_with("Local service error, ret_code is 1, error message is '{\"code\":\"SERVICE_NOT_FOUND\",\"message\":\"Service with alias 'alias' is not found on worker"));
     }
 }
 
@@ -2529,3 +2655,430 @@ async fn test_install_by_other_forbidden() {
     let response = response[0].as_str().unwrap().to_string();
     assert_eq!(response, "done");
 }
+
+#[tokio::test]
+async fn spell_trigger_now_test() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let worker_id = create_worker(&mut client, None).await;
+    let script = r#"(call %init_peer_id% (spell_id "set_string") ["status" "done"])"#;
+    let spell_id = spell::install_spell(
+        &mut client,
+        &worker_id,
+        script,
+        TriggerConfig::default(),
+        json!({}),
+    )
+    .await
+    .unwrap();
+
+    let info = spell::get_spell_info(&mut client, &worker_id, &spell_id)
+        .await
+        .unwrap();
+    assert_eq!(info.script, script);
+    let status = spell::get_spell_string(&mut client, &worker_id, &spell_id, "status")
+        .await
+        .unwrap();
+    assert_eq!(status, None);
+
+    spell::trigger_now(&mut client, &worker_id, &spell_id)
+        .await
+        .unwrap();
+    let status = spell::wait_for_kv(
+        &mut client,
+        &worker_id,
+        &spell_id,
+        "status",
+        |v| v == Some("done"),
+        Duration::from_secs(30),
+    )
+    .await
+    .unwrap();
+    assert_eq!(status.as_deref(), Some("done"));
+}
+
+#[tokio::test]
+async fn spell_kv_cas_and_incr() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let worker_id = create_worker(&mut client, None).await;
+    let spell_id = spell::install_spell(
+        &mut client,
+        &worker_id,
+        "(null)",
+        TriggerConfig::default(),
+        json!({}),
+    )
+    .await
+    .unwrap();
+
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+    };
+    let result = client
+        .execute_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (seq
+                        (call worker_id ("spell" "kv_set_if_equals") [spell_id "owner" [] "a"] first)
+                        (call worker_id ("spell" "kv_set_if_equals") [spell_id "owner" [] "b"] second)
+                    )
+                    (seq
+                        (call worker_id ("spell" "kv_incr") [spell_id "visits" 2] incr1)
+                        (call worker_id ("spell" "kv_incr") [spell_id "visits" 3] incr2)
+                    )
+                )
+            )
+            (call client ("return" "") [first second incr1 incr2])
+        )"#,
+            data,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(result, vec![json!(true), json!(false), json!(2), json!(5)]);
+    let owner = spell::get_spell_string(&mut client, &worker_id, &spell_id, "owner")
+        .await
+        .unwrap();
+    assert_eq!(owner.as_deref(), Some("a"));
+}
+
+#[tokio::test]
+async fn spell_update_script_migrates_kv() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let worker_id = create_worker(&mut client, None).await;
+    let spell_id = spell::install_spell(
+        &mut client,
+        &worker_id,
+        "(null)",
+        TriggerConfig::default(),
+        json!({ "last_block": "42" }),
+    )
+    .await
+    .unwrap();
+
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+        "migration" => json!([
+            { "op": "rename", "from": "last_block", "to": "cursor" },
+            { "op": "set", "key": "cursor_kind", "value": "block" },
+        ]),
+        "broken" => json!([
+            { "op": "delete", "key": "cursor" },
+            { "op": "require", "key": "missing" },
+        ]),
+    };
+    let result = client
+        .execute_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (seq
+                        (call worker_id ("spell" "update_script") [spell_id "(null)" 1 migration])
+                        ; the same version again doesn't rerun the migration
+                        (call worker_id ("spell" "update_script") [spell_id "(null)" 1 migration])
+                    )
+                    (xor
+                        (call worker_id ("spell" "update_script") [spell_id "(null)" 2 broken])
+                        (ap :error:.$.message failed)
+                    )
+                )
+            )
+            (call client ("return" "") [failed])
+        )"#,
+            data,
+        )
+        .await
+        .unwrap();
+    assert!(result[0].as_str().unwrap().contains("missing"));
+
+    for (key, expected) in [
+        ("last_block", None),
+        ("cursor", Some("42")),
+        ("cursor_kind", Some("block")),
+    ] {
+        let value = spell::get_spell_string(&mut client, &worker_id, &spell_id, key)
+            .await
+            .unwrap();
+        assert_eq!(value.as_deref(), expected, "key {key}");
+    }
+}
+
+#[tokio::test]
+async fn spell_package_import() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let worker_id = create_worker(&mut client, None).await;
+    let spell_id = spell::install_spell(
+        &mut client,
+        &worker_id,
+        "(null)",
+        TriggerConfig::default(),
+        json!({}),
+    )
+    .await
+    .unwrap();
+
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+    };
+    let result = client
+        .execute_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (seq
+                        (call worker_id ("spell" "update_script") [spell_id "(seq (null) (null))" 2 []])
+                        (call worker_id ("spell" "package") [spell_id] package)
+                    )
+                    (seq
+                        (call worker_id ("spell" "import") [package] imported)
+                        (seq
+                            (call worker_id (imported "get_script") [] script)
+                            (call worker_id (imported "get_u32") ["hw_schema_version"] version)
+                        )
+                    )
+                )
+            )
+            (call client ("return" "") [package imported script version])
+        )"#,
+            data,
+        )
+        .await
+        .unwrap();
+
+    let package = &result[0];
+    assert_eq!(package["script"], json!("(seq (null) (null))"));
+    assert_eq!(package["schema_version"], json!(2));
+    assert_ne!(result[1], json!(spell_id));
+    assert_eq!(result[2]["value"], json!("(seq (null) (null))"));
+    assert_eq!(result[3]["value"], json!(2));
+}
+
+#[tokio::test]
+async fn spell_install_builtin() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "config" => json!({ "alias": "cleaner", "data": { "selector": "tmp=true" } }),
+    };
+    let result = client
+        .execute_particle(
+            r#"
+        (seq
+            (seq
+                (call relay ("spell" "list_builtin") [] names)
+                (call relay ("spell" "install_builtin") ["disk_cleaner" config] spell_id)
+            )
+            (seq
+                (call relay ("srv" "resolve_alias") ["cleaner"] resolved)
+                (call client ("return" "") [names spell_id resolved])
+            )
+        )"#,
+            data.clone(),
+        )
+        .await
+        .unwrap();
+
+    let names = result[0].as_array().unwrap();
+    assert!(names.contains(&json!("disk_cleaner")));
+    assert_eq!(result[1], result[2]);
+
+    let result = client
+        .execute_particle(
+            r#"
+        (xor
+            (call relay ("spell" "install_builtin") ["heartbeat" []] spell_id)
+            (call client ("return" "") [%last_error%.$.message])
+        )"#,
+            data,
+        )
+        .await
+        .unwrap();
+
+    let message = result[0].as_str().unwrap();
+    assert!(message.contains("INVALID_ARGUMENT"), "{message}");
+}
+
+#[tokio::test]
+async fn spell_receipts() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let worker_id = create_worker(&mut client, None).await;
+    let script = r#"(call %init_peer_id% (spell_id "set_string") ["status" "done"])"#;
+    let spell_id = spell::install_spell(
+        &mut client,
+        &worker_id,
+        script,
+        TriggerConfig::default(),
+        json!({}),
+    )
+    .await
+    .unwrap();
+
+    spell::trigger_now(&mut client, &worker_id, &spell_id)
+        .await
+        .unwrap();
+    spell::wait_for_kv(
+        &mut client,
+        &worker_id,
+        &spell_id,
+        "status",
+        |v| v == Some("done"),
+        Duration::from_secs(30),
+    )
+    .await
+    .unwrap();
+
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+    };
+    let result = client
+        .execute_particle(
+            r#"
+        (seq
+            (call relay ("op" "noop") [])
+            (seq
+                (call worker_id ("spell" "receipts") [spell_id []] receipts)
+                (call client ("return" "") [receipts])
+            )
+        )"#,
+            data,
+        )
+        .await
+        .unwrap();
+
+    let receipts = result[0].as_array().unwrap();
+    assert_eq!(receipts.len(), 1);
+    let receipt = &receipts[0];
+    assert_eq!(receipt["spell_id"], json!(spell_id));
+    assert_eq!(receipt["signer"], json!(worker_id));
+    assert_eq!(
+        receipt["script_hash"],
+        json!(blake3::hash(script.as_bytes()).to_hex().to_string())
+    );
+    assert_eq!(
+        receipt["result_hash"],
+        json!(blake3::hash(b"ok").to_hex().to_string())
+    );
+}
+
+#[tokio::test]
+async fn spell_quarantine() {
+    let swarms = make_swarms(1).await;
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let failing_script = r#"
+        (xor
+            (call %init_peer_id% ("srv" "remove") ["non_existent_srv_id"])
+            (call %init_peer_id% ("errorHandlingSrv" "error") [%last_error% 1])
+        )"#;
+    let config = make_clock_config(1, 1, 0);
+    let (spell_id, worker_id) = create_spell(&mut client, failing_script, config, json!({})).await;
+
+    let data = hashmap! {
+        "relay" => json!(client.node.to_string()),
+        "client" => json!(client.peer_id.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+    };
+    let mut status = json!(null);
+    for _ in 0..30 {
+        let result = client
+            .execute_particle(
+                r#"
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (call worker_id ("spell" "get_status") [spell_id] status)
+                    (call client ("return" "") [status])
+                )
+            )"#,
+                data.clone(),
+            )
+            .await
+            .unwrap();
+        status = result[0].clone();
+        if status["quarantined"] == json!(true) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    assert_eq!(status["quarantined"], json!(true), "{status}");
+    assert!(
+        status["failures_in_a_row"].as_u64().unwrap() >= 3,
+        "{status}"
+    );
+    assert!(status["backoff_factor"].as_u64().unwrap() >= 2, "{status}");
+}
@@ -20,6 +20,7 @@
 use connected_client::ConnectedClient;
 use created_swarm::make_swarms;
 use eyre::Context;
+use fluence_libp2p::RandomPeerId;
 use hex::FromHex;
 use log_utils::enable_logs;
 use maplit::hashmap;
@@ -191,3 +192,102 @@ async fn test_resolve_subnet_on_worker() {
 
     assert_eq!(result, expected)
 }
+
+#[tokio::test]
+async fn test_resolve_workers_from_records() {
+    let deal_id = "0x9DcaFca9B88f49d91c38a32E7d9A86a7d9a37B04";
+
+    let swarms = make_swarms(2).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let worker_id = create_worker(&mut client, deal_id).await;
+
+    let data = hashmap! {
+        "relay" => json!(swarms[0].peer_id.to_string()),
+        "other" => json!(swarms[1].peer_id.to_string()),
+        "worker_id" => json!(worker_id),
+        "deal_id" => json!(deal_id),
+        "client" => json!(client.peer_id.to_string()),
+    };
+
+    let mut result = client
+        .execute_particle(
+            r#"
+            (seq
+                (seq
+                    (call relay ("op" "noop") [])
+                    (call worker_id ("subnet" "worker_record") [] record)
+                )
+                (seq
+                    (seq
+                        (call other ("subnet" "register_worker") [record] registered)
+                        (call other ("subnet" "resolve_workers") [deal_id] workers)
+                    )
+                    (seq
+                        (call relay ("op" "noop") [])
+                        (call client ("return" "") [registered workers])
+                    )
+                )
+            )"#,
+            data,
+        )
+        .await
+        .wrap_err("execute particle")
+        .unwrap();
+
+    assert_eq!(result.remove(0), json!(true));
+    assert_eq!(
+        result.remove(0),
+        json!([{ "worker_id": worker_id, "relay_id": swarms[0].peer_id.to_string() }])
+    );
+}
+
+#[tokio::test]
+async fn test_transfer_worker() {
+    let deal_id = "0x9DcaFca9B88f49d91c38a32E7d9A86a7d9a37B04";
+
+    let swarms = make_swarms(1).await;
+
+    let mut client = ConnectedClient::connect_with_keypair(
+        swarms[0].multiaddr.clone(),
+        Some(swarms[0].management_keypair.clone()),
+    )
+    .await
+    .wrap_err("connect client")
+    .unwrap();
+
+    let worker_id = create_worker(&mut client, deal_id).await;
+    let new_creator = RandomPeerId::random().to_string();
+
+    let data = hashmap! {
+        "relay" => json!(swarms[0].peer_id.to_string()),
+        "worker_id" => json!(worker_id),
+        "new_creator" => json!(new_creator),
+        "client" => json!(client.peer_id.to_string()),
+    };
+
+    let mut result = client
+        .execute_particle(
+            r#"
+            (seq
+                (seq
+                    (call relay ("worker" "transfer") [worker_id new_creator])
+                    (call relay ("worker" "revoked") [] revoked)
+                )
+                (call client ("return" "") [revoked])
+            )"#,
+            data,
+        )
+        .await
+        .wrap_err("execute particle")
+        .unwrap();
+
+    assert_eq!(result.remove(0), json!([]));
+}
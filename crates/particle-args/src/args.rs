@@ -22,6 +22,7 @@ use crate::args_error::ArgsError::{self, MissingField};
 use control_macro::ok_get;
 
 use crate::ArgsError::DeserializeError;
+use crate::ArgsLimits;
 use avm_server::{CallRequestParams, SecurityTetraplet};
 use serde::Deserialize;
 use serde_json::Value as JValue;
@@ -34,9 +35,29 @@ pub struct Args {
     pub function_name: String,
     pub function_args: Vec<serde_json::Value>,
     pub tetraplets: Vec<Vec<SecurityTetraplet>>,
+    /// Sequence number of the call in the stream from the particle sender to the service.
+    /// If set, the call is delivered only after the previous one in the stream.
+    pub sequence: Option<u64>,
 }
 
 impl Args {
+    /// Converts call request to Args, checking arguments against the given limits.
+    /// Arguments of call requests are already parsed by the AquaVM server, so the limits
+    /// decide whether they reach the service, not how much memory parsing them takes.
+    pub fn try_from_with_limits(
+        call: CallRequestParams,
+        limits: &ArgsLimits,
+    ) -> Result<Self, ArgsError> {
+        let arguments = limits.deserialize_args(JValue::Array(call.arguments))?;
+        Ok(Args {
+            service_id: call.service_id,
+            function_name: call.function_name,
+            function_args: arguments,
+            tetraplets: call.tetraplets,
+            sequence: None,
+        })
+    }
+
     /// Retrieves next json value from iterator, parse it to T
     /// `field` is to generate a more accurate error message
     pub fn next<T: for<'de> Deserialize<'de>>(
@@ -117,6 +138,7 @@ impl TryFrom<CallRequestParams> for Args {
             function_name: value.function_name,
             function_args: value.arguments,
             tetraplets: value.tetraplets,
+            sequence: None,
         })
     }
 }
@@ -19,6 +19,8 @@
 
 use json_utils::err_as_value;
 
+use crate::error_code::{ErrorCode, ErrorCoded};
+
 use eyre::Report;
 use serde_json::{json, Value as JValue};
 use std::borrow::Cow;
@@ -47,6 +49,38 @@ pub enum ArgsError {
         value: JValue,
         expected_type: String,
     },
+    #[error("Argument #{position} exceeds {limit} limit at '{path}': {actual} > {max}")]
+    LimitExceeded {
+        position: usize,
+        path: String,
+        limit: ArgsLimit,
+        actual: usize,
+        max: usize,
+    },
+}
+
+/// Kind of the limit violated by call arguments, see [`crate::ArgsLimits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgsLimit {
+    Depth,
+    StringLength,
+    ArrayLength,
+}
+
+impl Display for ArgsLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgsLimit::Depth => write!(f, "nesting depth"),
+            ArgsLimit::StringLength => write!(f, "string length"),
+            ArgsLimit::ArrayLength => write!(f, "array length"),
+        }
+    }
+}
+
+impl ErrorCoded for ArgsError {
+    fn error_code(&self) -> ErrorCode {
+        ErrorCode::InvalidArgument
+    }
 }
 
 impl From<ArgsError> for JValue {
@@ -74,6 +108,23 @@ impl JError {
     pub fn from_eyre(err: Report) -> Self {
         JError(err_as_value(err))
     }
+
+    /// Error payload carrying a stable code: `{"code": "...", "message": "..."}`
+    pub fn with_code(code: ErrorCode, msg: impl AsRef<str>) -> Self {
+        Self(json!({ "code": code, "message": msg.as_ref() }))
+    }
+
+    /// Converts a categorized error into a coded payload, keeping its Display as the message
+    pub fn coded<E: ErrorCoded + Display>(err: E) -> Self {
+        Self::with_code(err.error_code(), err.to_string())
+    }
+
+    /// Code of the error, if it was created with one
+    pub fn code(&self) -> Option<ErrorCode> {
+        self.0
+            .get("code")
+            .and_then(|c| serde_json::from_value(c.clone()).ok())
+    }
 }
 
 impl From<JError> for JValue {
@@ -90,3 +141,27 @@ impl<E: std::error::Error> From<E> for JError {
 
 // It's not possible to implement Error for JError in Rust
 // impl Error for JError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coded_error_payload() {
+        let err = JError::with_code(ErrorCode::SpellNotFound, "spell foo not found");
+        assert_eq!(
+            err.0,
+            json!({ "code": "SPELL_NOT_FOUND", "message": "spell foo not found" })
+        );
+        assert_eq!(err.code(), Some(ErrorCode::SpellNotFound));
+
+        let err = JError::coded(ArgsError::MissingField("spell_id"));
+        assert_eq!(err.code(), Some(ErrorCode::InvalidArgument));
+        assert_eq!(
+            err.0["message"],
+            json!("Field 'spell_id' is missing from args to call_service")
+        );
+
+        assert_eq!(JError::new("plain").code(), None);
+    }
+}
@@ -0,0 +1,398 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use crate::args_error::{ArgsError, ArgsLimit};
+
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Number, Value as JValue};
+use std::cell::RefCell;
+use std::fmt::Formatter;
+
+/// Limits applied to call arguments before they are handed to a service.
+/// Protects the host from pathologically large or deeply nested values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ArgsLimits {
+    /// Maximum nesting depth of arrays and objects
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    /// Maximum length in bytes of any string, including object keys
+    #[serde(default = "default_max_string_length")]
+    pub max_string_length: usize,
+    /// Maximum number of elements in an array or entries in an object
+    #[serde(default = "default_max_array_length")]
+    pub max_array_length: usize,
+}
+
+impl Default for ArgsLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: default_max_depth(),
+            max_string_length: default_max_string_length(),
+            max_array_length: default_max_array_length(),
+        }
+    }
+}
+
+fn default_max_depth() -> usize {
+    64
+}
+
+fn default_max_string_length() -> usize {
+    // 32 MiB
+    32 * 1024 * 1024
+}
+
+fn default_max_array_length() -> usize {
+    100_000
+}
+
+impl ArgsLimits {
+    /// Deserializes call arguments from a JSON array, checking them against the limits on the way.
+    /// Deserialization stops at the first value exceeding a limit, so when reading raw JSON,
+    /// oversized or too deep arguments are never built in full. A parsed `JValue` is checked
+    /// the same way, but it has already been built by whoever parsed it.
+    pub fn deserialize_args<'de, D: Deserializer<'de>>(
+        &self,
+        deserializer: D,
+    ) -> Result<Vec<JValue>, ArgsError> {
+        let violation = RefCell::new(None);
+        let seed = LimitedArgs {
+            limits: self,
+            violation: &violation,
+        };
+        seed.deserialize(deserializer).map_err(|err: D::Error| {
+            violation
+                .into_inner()
+                .unwrap_or_else(|| ArgsError::InvalidFormat {
+                    field: "arguments",
+                    err: err.to_string().into(),
+                })
+        })
+    }
+}
+
+/// Top-level array of call arguments, each one is checked by [`LimitedValue`]
+struct LimitedArgs<'a> {
+    limits: &'a ArgsLimits,
+    violation: &'a RefCell<Option<ArgsError>>,
+}
+
+impl<'de> DeserializeSeed<'de> for LimitedArgs<'_> {
+    type Value = Vec<JValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LimitedArgs<'_> {
+    type Value = Vec<JValue>;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "an array of call arguments")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut args = vec![];
+        loop {
+            let seed = LimitedValue {
+                limits: self.limits,
+                violation: self.violation,
+                position: args.len(),
+                depth: 0,
+                path: String::from("$"),
+            };
+            match seq.next_element_seed(seed)? {
+                Some(arg) => args.push(arg),
+                None => return Ok(args),
+            }
+        }
+    }
+}
+
+/// A single JSON value checked against the limits while it's being deserialized.
+/// Nesting is checked before descending, so deep values can't overflow the stack.
+struct LimitedValue<'a> {
+    limits: &'a ArgsLimits,
+    violation: &'a RefCell<Option<ArgsError>>,
+    position: usize,
+    depth: usize,
+    path: String,
+}
+
+impl LimitedValue<'_> {
+    fn nested(&self, path: String) -> Self {
+        Self {
+            limits: self.limits,
+            violation: self.violation,
+            position: self.position,
+            depth: self.depth + 1,
+            path,
+        }
+    }
+
+    /// Remembers the violated limit to be returned instead of the deserializer error
+    fn exceeded<E: de::Error>(&self, limit: ArgsLimit, path: &str, actual: usize, max: usize) -> E {
+        let err = ArgsError::LimitExceeded {
+            position: self.position,
+            path: path.to_string(),
+            limit,
+            actual,
+            max,
+        };
+        let msg = err.to_string();
+        *self.violation.borrow_mut() = Some(err);
+        E::custom(msg)
+    }
+
+    fn check_string<E: de::Error>(&self, path: &str, s: &str) -> Result<(), E> {
+        let max = self.limits.max_string_length;
+        if s.len() > max {
+            return Err(self.exceeded(ArgsLimit::StringLength, path, s.len(), max));
+        }
+        Ok(())
+    }
+
+    fn check_nested<E: de::Error>(&self) -> Result<(), E> {
+        let max = self.limits.max_depth;
+        if self.depth >= max {
+            return Err(self.exceeded(ArgsLimit::Depth, &self.path, self.depth + 1, max));
+        }
+        Ok(())
+    }
+
+    fn check_length<E: de::Error>(&self, length: usize) -> Result<(), E> {
+        let max = self.limits.max_array_length;
+        if length > max {
+            return Err(self.exceeded(ArgsLimit::ArrayLength, &self.path, length, max));
+        }
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for LimitedValue<'_> {
+    type Value = JValue;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LimitedValue<'_> {
+    type Value = JValue;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "any JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(JValue::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(JValue::from(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(JValue::from(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Number::from_f64(v).map_or(JValue::Null, JValue::Number))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        self.check_string(&self.path, v)?;
+        Ok(JValue::String(v.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        self.check_string(&self.path, &v)?;
+        Ok(JValue::String(v))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(JValue::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(JValue::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        self.check_nested()?;
+        if let Some(length) = seq.size_hint() {
+            self.check_length(length)?;
+        }
+        let mut array = vec![];
+        loop {
+            let seed = self.nested(format!("{}[{}]", self.path, array.len()));
+            match seq.next_element_seed(seed)? {
+                Some(value) => array.push(value),
+                None => return Ok(JValue::Array(array)),
+            }
+            self.check_length(array.len())?;
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        self.check_nested()?;
+        if let Some(length) = map.size_hint() {
+            self.check_length(length)?;
+        }
+        let mut object = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let path = format!("{}.{key}", self.path);
+            self.check_string(&path, &key)?;
+            let value = map.next_value_seed(self.nested(path))?;
+            object.insert(key, value);
+            self.check_length(object.len())?;
+        }
+        Ok(JValue::Object(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArgsLimits;
+    use crate::args_error::{ArgsError, ArgsLimit};
+    use serde_json::{json, Value as JValue};
+
+    fn limits() -> ArgsLimits {
+        ArgsLimits {
+            max_depth: 2,
+            max_string_length: 3,
+            max_array_length: 2,
+        }
+    }
+
+    fn check_err(args: Vec<JValue>) -> (usize, String, ArgsLimit) {
+        match limits().deserialize_args(JValue::Array(args)) {
+            Err(ArgsError::LimitExceeded {
+                position,
+                path,
+                limit,
+                ..
+            }) => (position, path, limit),
+            r => panic!("expected LimitExceeded, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn within_limits() {
+        let args = vec![
+            json!("abc"),
+            json!([["a"], {"b": 1}]),
+            json!(42),
+            json!(null),
+        ];
+        let result = limits().deserialize_args(JValue::Array(args.clone()));
+        assert_eq!(result.unwrap(), args);
+    }
+
+    #[test]
+    fn exceeds_limits() {
+        let (position, path, limit) = check_err(vec![json!("a"), json!("abcd")]);
+        assert_eq!(
+            (position, path.as_str(), limit),
+            (1, "$", ArgsLimit::StringLength)
+        );
+
+        let (position, path, limit) = check_err(vec![json!([1, [[1]]])]);
+        assert_eq!(
+            (position, path.as_str(), limit),
+            (0, "$[1][0]", ArgsLimit::Depth)
+        );
+
+        let (position, path, limit) = check_err(vec![json!({"a": [1, 2, 3]})]);
+        assert_eq!(
+            (position, path.as_str(), limit),
+            (0, "$.a", ArgsLimit::ArrayLength)
+        );
+
+        let (position, path, limit) = check_err(vec![json!({"long": 1})]);
+        assert_eq!(
+            (position, path.as_str(), limit),
+            (0, "$.long", ArgsLimit::StringLength)
+        );
+    }
+
+    #[test]
+    fn raw_json_stops_at_limit() {
+        // the array is rejected on its 3rd element, before the rest is parsed
+        let raw = r#"["a", [1, 2, 3, "never parsed"#;
+        let mut deserializer = serde_json::Deserializer::from_str(raw);
+        let result = limits().deserialize_args(&mut deserializer);
+        assert!(matches!(
+            result,
+            Err(ArgsError::LimitExceeded {
+                position: 1,
+                limit: ArgsLimit::ArrayLength,
+                ..
+            })
+        ));
+
+        let mut deserializer = serde_json::Deserializer::from_str(r#"{"a": 1}"#);
+        let result = limits().deserialize_args(&mut deserializer);
+        assert!(matches!(result, Err(ArgsError::InvalidFormat { .. })));
+    }
+
+    #[test]
+    fn nesting_over_limit() {
+        let limits = ArgsLimits::default();
+        let mut value = json!(1);
+        for _ in 0..=limits.max_depth {
+            value = JValue::Array(vec![value]);
+        }
+        let result = limits.deserialize_args(JValue::Array(vec![value]));
+        match result {
+            Err(ArgsError::LimitExceeded {
+                limit: ArgsLimit::Depth,
+                actual,
+                max,
+                ..
+            }) => assert_eq!(actual, max + 1),
+            r => panic!("expected LimitExceeded, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn deep_nesting_does_not_overflow() {
+        let mut value = json!(1);
+        for _ in 0..100_000 {
+            value = JValue::Array(vec![value]);
+        }
+        let args = JValue::Array(vec![value]);
+        let result = ArgsLimits::default().deserialize_args(&args);
+        assert!(matches!(
+            result,
+            Err(ArgsError::LimitExceeded {
+                limit: ArgsLimit::Depth,
+                ..
+            })
+        ));
+        // dropping a deeply nested value is recursive, so don't
+        std::mem::forget(args);
+    }
+}
@@ -0,0 +1,170 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JValue;
+
+/// Replaces redacted values
+pub const REDACTED: &str = "<redacted>";
+
+/// Rules masking call arguments before they are written to logs and traces,
+/// so secrets passed to services don't leak into observability pipelines.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ArgsRedaction {
+    #[serde(default)]
+    pub rules: Vec<RedactionRule>,
+}
+
+/// Arguments of the matching calls to mask
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RedactionRule {
+    /// Service id or alias as called by the particle, any service if not set
+    #[serde(default)]
+    pub service_id: Option<String>,
+    /// Any function if not set
+    #[serde(default)]
+    pub function_name: Option<String>,
+    /// Positions of the arguments masked as a whole
+    #[serde(default)]
+    pub args: Vec<usize>,
+    /// Names of object fields masked at any depth of the arguments, e.g. `password`
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+impl RedactionRule {
+    fn matches(&self, service_id: &str, function_name: &str) -> bool {
+        self.service_id.as_ref().map_or(true, |s| s == service_id)
+            && self
+                .function_name
+                .as_ref()
+                .map_or(true, |f| f == function_name)
+    }
+
+    fn apply(&self, args: &mut [JValue]) {
+        for position in &self.args {
+            if let Some(arg) = args.get_mut(*position) {
+                *arg = JValue::String(REDACTED.to_string());
+            }
+        }
+        if self.fields.is_empty() {
+            return;
+        }
+        // Traversal is iterative, so deeply nested values can't overflow the stack
+        let mut stack: Vec<&mut JValue> = args.iter_mut().collect();
+        while let Some(value) = stack.pop() {
+            match value {
+                JValue::Array(array) => stack.extend(array.iter_mut()),
+                JValue::Object(object) => {
+                    for (k, v) in object.iter_mut() {
+                        if self.fields.contains(k) {
+                            *v = JValue::String(REDACTED.to_string());
+                        } else {
+                            stack.push(v);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl ArgsRedaction {
+    /// Arguments of the call with the values matched by the rules masked.
+    /// The arguments are copied only if some rule matches the call.
+    pub fn redact<'a>(
+        &self,
+        service_id: &str,
+        function_name: &str,
+        args: &'a [JValue],
+    ) -> Cow<'a, [JValue]> {
+        let mut rules = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(service_id, function_name))
+            .peekable();
+        if rules.peek().is_none() {
+            return Cow::Borrowed(args);
+        }
+        let mut args = args.to_vec();
+        for rule in rules {
+            rule.apply(&mut args);
+        }
+        Cow::Owned(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(service_id: Option<&str>, args: Vec<usize>, fields: Vec<&str>) -> RedactionRule {
+        RedactionRule {
+            service_id: service_id.map(Into::into),
+            function_name: None,
+            args,
+            fields: fields.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    #[test]
+    fn redact_args_and_fields() {
+        let redaction = ArgsRedaction {
+            rules: vec![
+                rule(Some("vault"), vec![1, 5], vec![]),
+                rule(None, vec![], vec!["password"]),
+            ],
+        };
+        let args = vec![
+            json!("user"),
+            json!("secret"),
+            json!([{"password": "p", "login": "l"}]),
+        ];
+
+        let redacted = redaction.redact("vault", "put", &args);
+        assert_eq!(
+            redacted.as_ref(),
+            &[
+                json!("user"),
+                json!(REDACTED),
+                json!([{"password": REDACTED, "login": "l"}]),
+            ]
+        );
+
+        let redacted = redaction.redact("other", "put", &args);
+        assert_eq!(redacted[1], json!("secret"));
+        assert_eq!(redacted[2], json!([{"password": REDACTED, "login": "l"}]));
+    }
+
+    #[test]
+    fn no_matching_rules_borrow_args() {
+        let redaction = ArgsRedaction {
+            rules: vec![rule(Some("vault"), vec![0], vec![])],
+        };
+        let args = vec![json!("value")];
+        assert!(matches!(
+            redaction.redact("srv", "list", &args),
+            Cow::Borrowed(_)
+        ));
+    }
+}
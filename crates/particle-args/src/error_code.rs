@@ -0,0 +1,74 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Stable, machine-readable category of a builtin error.
+///
+/// Serialized into error payloads as `{"code": "SPELL_NOT_FOUND", "message": "..."}`,
+/// so clients and Aqua scripts can branch on the kind of failure instead of matching messages.
+/// Codes must never be renamed once released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    PermissionDenied,
+    SpellNotFound,
+    ServiceNotFound,
+    WorkerNotFound,
+    NotFound,
+    AlreadyExists,
+    InvalidArgument,
+    QuotaExceeded,
+    FailedPrecondition,
+    /// The node is a read-only replica and doesn't accept changes of its state
+    ReadOnly,
+    /// The builtin namespace is disabled by the node operator
+    CapabilityDisabled,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::PermissionDenied => "PERMISSION_DENIED",
+            ErrorCode::SpellNotFound => "SPELL_NOT_FOUND",
+            ErrorCode::ServiceNotFound => "SERVICE_NOT_FOUND",
+            ErrorCode::WorkerNotFound => "WORKER_NOT_FOUND",
+            ErrorCode::NotFound => "NOT_FOUND",
+            ErrorCode::AlreadyExists => "ALREADY_EXISTS",
+            ErrorCode::InvalidArgument => "INVALID_ARGUMENT",
+            ErrorCode::QuotaExceeded => "QUOTA_EXCEEDED",
+            ErrorCode::FailedPrecondition => "FAILED_PRECONDITION",
+            ErrorCode::ReadOnly => "READ_ONLY",
+            ErrorCode::CapabilityDisabled => "CAPABILITY_DISABLED",
+            ErrorCode::Internal => "INTERNAL",
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Errors that can be categorized with an [`ErrorCode`]
+pub trait ErrorCoded {
+    fn error_code(&self) -> ErrorCode;
+}
@@ -30,10 +30,16 @@
 
 mod args;
 mod args_error;
+mod args_limits;
+mod args_redaction;
 mod base58;
+mod error_code;
 
 pub use args::Args;
-pub use args_error::{ArgsError, JError};
+pub use args_error::{ArgsError, ArgsLimit, JError};
+pub use args_limits::ArgsLimits;
+pub use args_redaction::{ArgsRedaction, RedactionRule, REDACTED};
+pub use error_code::{ErrorCode, ErrorCoded};
 
 pub use avm_server::AVMError;
 pub use base58::from_base58;
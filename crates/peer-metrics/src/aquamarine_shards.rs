@@ -0,0 +1,95 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ShardLabel {
+    shard: String,
+}
+
+impl ShardLabel {
+    fn new(shard: usize) -> Self {
+        Self {
+            shard: shard.to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AquamarineShardMetrics {
+    pub particles_ingested: Family<ShardLabel, Counter>,
+    pub effects_produced: Family<ShardLabel, Counter>,
+    pub actors: Family<ShardLabel, Gauge>,
+}
+
+impl AquamarineShardMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("aquamarine_shard");
+
+        let particles_ingested = Family::default();
+        sub_registry.register(
+            "particles_ingested",
+            "Number of particles routed to the shard",
+            particles_ingested.clone(),
+        );
+
+        let effects_produced = Family::default();
+        sub_registry.register(
+            "effects_produced",
+            "Number of routing effects produced by the shard",
+            effects_produced.clone(),
+        );
+
+        let actors = Family::default();
+        sub_registry.register(
+            "actors",
+            "Number of particle actors currently held by the shard",
+            actors.clone(),
+        );
+
+        Self {
+            particles_ingested,
+            effects_produced,
+            actors,
+        }
+    }
+
+    pub fn particle_ingested(&self, shard: usize) {
+        self.particles_ingested
+            .get_or_create(&ShardLabel::new(shard))
+            .inc();
+    }
+
+    pub fn effects_produced(&self, shard: usize) {
+        self.effects_produced
+            .get_or_create(&ShardLabel::new(shard))
+            .inc();
+    }
+
+    pub fn set_actors(&self, shard: usize, count: usize) {
+        self.actors
+            .get_or_create(&ShardLabel::new(shard))
+            .set(count as i64);
+    }
+}
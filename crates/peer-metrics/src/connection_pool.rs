@@ -18,18 +18,47 @@
  */
 
 use crate::{ParticleLabel, ParticleType};
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::Registry;
 
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TransportKind {
+    Tcp,
+    Ws,
+    WebRtc,
+    Memory,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct TransportLabel {
+    transport: TransportKind,
+}
+
+/// Whether a connected peer is another node or a client
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum PeerClass {
+    Node,
+    Client,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct PeerClassLabel {
+    class: PeerClass,
+}
+
 #[derive(Clone)]
 pub struct ConnectionPoolMetrics {
     pub received_particles: Family<ParticleLabel, Counter>,
     pub particle_sizes: Family<ParticleLabel, Histogram>,
     pub connected_peers: Gauge,
     pub particle_queue_size: Gauge,
+    pub inbound_connections: Family<TransportLabel, Gauge>,
+    pub clock_skew_ms: Gauge,
+    pub dropped_particles: Family<PeerClassLabel, Counter>,
 }
 
 impl ConnectionPoolMetrics {
@@ -66,14 +95,56 @@ impl ConnectionPoolMetrics {
             particle_queue_size.clone(),
         );
 
+        let inbound_connections = Family::default();
+        sub_registry.register(
+            "inbound_connections",
+            "Number of established inbound connections by transport",
+            inbound_connections.clone(),
+        );
+
+        let clock_skew_ms = Gauge::default();
+        sub_registry.register(
+            "clock_skew_ms",
+            "Estimated skew of the local clock relative to connected peers, in milliseconds",
+            clock_skew_ms.clone(),
+        );
+
+        let dropped_particles = Family::default();
+        sub_registry.register(
+            "dropped_particles",
+            "Number of received particles dropped because the queue of the sender class was full",
+            dropped_particles.clone(),
+        );
+
         Self {
             received_particles,
             particle_sizes,
             connected_peers,
             particle_queue_size,
+            inbound_connections,
+            clock_skew_ms,
+            dropped_particles,
         }
     }
 
+    pub fn particle_dropped(&self, class: PeerClass) {
+        self.dropped_particles
+            .get_or_create(&PeerClassLabel { class })
+            .inc();
+    }
+
+    pub fn inbound_connection_established(&self, transport: TransportKind) {
+        self.inbound_connections
+            .get_or_create(&TransportLabel { transport })
+            .inc();
+    }
+
+    pub fn inbound_connection_closed(&self, transport: TransportKind) {
+        self.inbound_connections
+            .get_or_create(&TransportLabel { transport })
+            .dec();
+    }
+
     pub fn incoming_particle(&self, particle_id: &str, queue_len: i64, particle_len: f64) {
         self.particle_queue_size.set(queue_len);
         let label = ParticleLabel {
@@ -21,6 +21,7 @@ use crate::{ParticleLabel, ParticleType};
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
 use prometheus_client::registry::Registry;
 
 #[derive(EncodeLabelValue, Hash, Clone, Eq, PartialEq, Debug)]
@@ -36,6 +37,55 @@ pub struct ResolutionLabel {
     action: Resolution,
 }
 
+/// Why a particle was considered to be going in circles
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ForwardingLoop {
+    /// Sent from node to node too many times
+    Hops,
+    /// Its exact copy was already sent by the node too many times
+    Repeated,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct LoopLabel {
+    reason: ForwardingLoop,
+}
+
+/// State a circuit breaker of a remote peer moves to
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum BreakerState {
+    /// Calls to the peer go through
+    Closed,
+    /// Calls to the peer fail fast
+    Open,
+    /// A single call probes whether the peer has recovered
+    HalfOpen,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct BreakerLabel {
+    state: BreakerState,
+}
+
+/// Why a Kademlia lookup of a particle wasn't made
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum QueryRejection {
+    /// Too many lookups were already waiting for a slot
+    QueueFull,
+    /// The particle expired while the lookup was waiting for a slot
+    Expired,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct QueryRejectionLabel {
+    reason: QueryRejection,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ProbeLabel {
+    target: String,
+}
+
 #[derive(Clone)]
 pub struct ConnectivityMetrics {
     contact_resolve: Family<ResolutionLabel, Counter>,
@@ -43,6 +93,15 @@ pub struct ConnectivityMetrics {
     pub particle_send_failure: Family<ParticleLabel, Counter>,
     pub bootstrap_disconnected: Counter,
     pub bootstrap_connected: Counter,
+    probe_reachable: Family<ProbeLabel, Gauge>,
+    probe_failures: Family<ProbeLabel, Counter>,
+    particle_looped: Family<LoopLabel, Counter>,
+    breaker_transitions: Family<BreakerLabel, Counter>,
+    breaker_rejected: Counter,
+    particle_ttl_exhausted: Counter,
+    kademlia_queries_in_flight: Gauge,
+    kademlia_queries_queued: Gauge,
+    kademlia_queries_rejected: Family<QueryRejectionLabel, Counter>,
 }
 
 impl ConnectivityMetrics {
@@ -84,12 +143,96 @@ impl ConnectivityMetrics {
             bootstrap_connected.clone(),
         );
 
+        let probe_reachable = Family::default();
+        sub_registry.register(
+            "probe_reachable",
+            "Whether the last probe of a must-reach target succeeded",
+            probe_reachable.clone(),
+        );
+
+        let probe_failures = Family::default();
+        sub_registry.register(
+            "probe_failures",
+            "Number of failed probes of a must-reach target",
+            probe_failures.clone(),
+        );
+
+        let particle_looped = Family::default();
+        sub_registry.register(
+            "particle_looped",
+            "Number of particles dropped instead of being sent further because of a forwarding loop",
+            particle_looped.clone(),
+        );
+
+        let breaker_transitions = Family::default();
+        sub_registry.register(
+            "breaker_transitions",
+            "Number of times circuit breakers of remote peers moved to a state",
+            breaker_transitions.clone(),
+        );
+
+        let breaker_rejected = Counter::default();
+        sub_registry.register(
+            "breaker_rejected",
+            "Number of sends to remote peers failed fast by their open circuit breakers",
+            breaker_rejected.clone(),
+        );
+
+        let particle_ttl_exhausted = Counter::default();
+        sub_registry.register(
+            "particle_ttl_exhausted",
+            "Number of particles not sent further because too little of their TTL was left",
+            particle_ttl_exhausted.clone(),
+        );
+
+        let kademlia_queries_in_flight = Gauge::default();
+        sub_registry.register(
+            "kademlia_queries_in_flight",
+            "Number of Kademlia lookups made while routing particles",
+            kademlia_queries_in_flight.clone(),
+        );
+
+        let kademlia_queries_queued = Gauge::default();
+        sub_registry.register(
+            "kademlia_queries_queued",
+            "Number of Kademlia lookups of particles waiting for a slot",
+            kademlia_queries_queued.clone(),
+        );
+
+        let kademlia_queries_rejected = Family::default();
+        sub_registry.register(
+            "kademlia_queries_rejected",
+            "Number of Kademlia lookups of particles not made because of the query caps",
+            kademlia_queries_rejected.clone(),
+        );
+
         Self {
             contact_resolve,
             particle_send_success,
             particle_send_failure,
             bootstrap_disconnected,
             bootstrap_connected,
+            probe_reachable,
+            probe_failures,
+            particle_looped,
+            breaker_transitions,
+            breaker_rejected,
+            particle_ttl_exhausted,
+            kademlia_queries_in_flight,
+            kademlia_queries_queued,
+            kademlia_queries_rejected,
+        }
+    }
+
+    pub fn observe_probe(&self, target: &str, reachable: bool) {
+        let label = ProbeLabel {
+            target: target.to_string(),
+        };
+        self.probe_reachable
+            .get_or_create(&label)
+            .set(reachable as i64);
+        if !reachable {
+            self.probe_failures.get_or_create(&label).inc();
         }
     }
 
@@ -99,6 +242,37 @@ impl ConnectivityMetrics {
             .inc();
     }
 
+    pub fn particle_looped(&self, reason: ForwardingLoop) {
+        self.particle_looped
+            .get_or_create(&LoopLabel { reason })
+            .inc();
+    }
+
+    pub fn breaker_transition(&self, state: BreakerState) {
+        self.breaker_transitions
+            .get_or_create(&BreakerLabel { state })
+            .inc();
+    }
+
+    pub fn breaker_rejected(&self) {
+        self.breaker_rejected.inc();
+    }
+
+    pub fn particle_ttl_exhausted(&self) {
+        self.particle_ttl_exhausted.inc();
+    }
+
+    pub fn observe_kademlia_queries(&self, in_flight: usize, queued: usize) {
+        self.kademlia_queries_in_flight.set(in_flight as i64);
+        self.kademlia_queries_queued.set(queued as i64);
+    }
+
+    pub fn kademlia_query_rejected(&self, reason: QueryRejection) {
+        self.kademlia_queries_rejected
+            .get_or_create(&QueryRejectionLabel { reason })
+            .inc();
+    }
+
     pub fn send_particle_ok(&self, particle: &str) {
         self.particle_send_success
             .get_or_create(&ParticleLabel {
@@ -25,6 +25,10 @@ use prometheus_client::registry::Registry;
 #[derive(Clone)]
 pub struct DispatcherMetrics {
     pub expired_particles: Family<ParticleLabel, Counter>,
+    /// Particles replayed from the log after a restart
+    pub replayed_particles: Counter,
+    /// Exact copies of the accepted particles
+    pub deduplicated_particles: Counter,
 }
 
 impl DispatcherMetrics {
@@ -51,7 +55,25 @@ impl DispatcherMetrics {
             expired_particles.clone(),
         );
 
-        DispatcherMetrics { expired_particles }
+        let replayed_particles = Counter::default();
+        sub_registry.register(
+            "particles_replayed",
+            "Number of particles replayed from the log after a restart",
+            replayed_particles.clone(),
+        );
+
+        let deduplicated_particles = Counter::default();
+        sub_registry.register(
+            "particles_deduplicated",
+            "Number of dropped exact copies of the accepted particles",
+            deduplicated_particles.clone(),
+        );
+
+        DispatcherMetrics {
+            expired_particles,
+            replayed_particles,
+            deduplicated_particles,
+        }
     }
 
     pub fn particle_expired(&self, particle_id: &str) {
@@ -61,4 +83,12 @@ impl DispatcherMetrics {
             })
             .inc();
     }
+
+    pub fn particles_replayed(&self, count: usize) {
+        self.replayed_particles.inc_by(count as u64);
+    }
+
+    pub fn particle_deduplicated(&self) {
+        self.deduplicated_particles.inc();
+    }
 }
@@ -0,0 +1,64 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::registry::Registry;
+
+#[derive(EncodeLabelValue, Hash, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ReclaimedKind {
+    Record,
+    ProviderRecord,
+    PeerAddresses,
+    FailedPeer,
+}
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct ReclaimedLabel {
+    kind: ReclaimedKind,
+}
+
+#[derive(Clone)]
+pub struct KademliaMetrics {
+    /// Entries removed by the periodic garbage collection
+    pub reclaimed: Family<ReclaimedLabel, Counter>,
+}
+
+impl KademliaMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let sub_registry = registry.sub_registry_with_prefix("kademlia");
+
+        let reclaimed = Family::default();
+        sub_registry.register(
+            "gc_reclaimed",
+            "Number of expired records, stale peer addresses and failure counters removed by GC",
+            reclaimed.clone(),
+        );
+
+        Self { reclaimed }
+    }
+
+    pub fn reclaimed(&self, kind: ReclaimedKind, count: usize) {
+        if count > 0 {
+            self.reclaimed
+                .get_or_create(&ReclaimedLabel { kind })
+                .inc_by(count as u64);
+        }
+    }
+}
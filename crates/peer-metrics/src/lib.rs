@@ -22,12 +22,17 @@ use std::fmt::Debug;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue, EncodeMetric};
 use prometheus_client::registry::Registry;
 
+pub use aquamarine_shards::AquamarineShardMetrics;
 pub use chain_listener::ChainListenerMetrics;
-pub use connection_pool::ConnectionPoolMetrics;
+pub use connection_pool::{ConnectionPoolMetrics, PeerClass, TransportKind};
+pub use connectivity::BreakerState;
 pub use connectivity::ConnectivityMetrics;
+pub use connectivity::ForwardingLoop;
+pub use connectivity::QueryRejection;
 pub use connectivity::Resolution;
 pub use dispatcher::DispatcherMetrics;
 pub use info::add_info_metrics;
+pub use kademlia::{KademliaMetrics, ReclaimedKind};
 use particle_execution::ParticleParams;
 pub use particle_executor::{FunctionKind, ParticleExecutorMetrics, WorkerLabel, WorkerType};
 pub use services_metrics::{
@@ -37,11 +42,13 @@ pub use services_metrics::{
 pub use spell_metrics::SpellMetrics;
 pub use vm_pool::VmPoolMetrics;
 
+mod aquamarine_shards;
 mod chain_listener;
 mod connection_pool;
 mod connectivity;
 mod dispatcher;
 mod info;
+mod kademlia;
 mod particle_executor;
 mod services_metrics;
 mod spell_metrics;
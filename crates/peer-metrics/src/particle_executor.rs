@@ -46,6 +46,7 @@ pub struct ParticleExecutorMetrics {
     pub interpretation_time_sec: Family<WorkerLabel, Histogram>,
     pub interpretation_successes: Family<WorkerLabel, Counter>,
     pub interpretation_failures: Family<WorkerLabel, Counter>,
+    pub interpreter_crashes: Family<WorkerLabel, Counter>,
     pub total_actors_mailbox: Family<WorkerLabel, Gauge>,
     pub alive_actors: Family<WorkerLabel, Gauge>,
     service_call_time_sec: Family<FunctionKindLabel, Histogram>,
@@ -107,6 +108,13 @@ impl ParticleExecutorMetrics {
             interpretation_failures.clone(),
         );
 
+        let interpreter_crashes = Family::default();
+        sub_registry.register(
+            "interpreter_crashes",
+            "Number of interpreter panics, each of them replaces an AquaVM instance",
+            interpreter_crashes.clone(),
+        );
+
         let total_actors_mailbox: Family<WorkerLabel, Gauge> =
             Family::new_with_constructor(Gauge::default);
         sub_registry.register(
@@ -145,6 +153,7 @@ impl ParticleExecutorMetrics {
             interpretation_time_sec,
             interpretation_successes,
             interpretation_failures,
+            interpreter_crashes,
             total_actors_mailbox,
             alive_actors,
             service_call_time_sec,
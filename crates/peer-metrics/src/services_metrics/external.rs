@@ -29,6 +29,7 @@ use std::fmt::Write;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue, LabelValueEncoder};
 use prometheus_client::metrics::family::Family;
 
+use crate::services_metrics::spell_kv::SpellKvMetrics;
 use crate::{execution_time_buckets, mem_buckets_4gib, mem_buckets_8gib, register};
 
 #[derive(Hash, Clone, Eq, PartialEq, Debug)]
@@ -57,6 +58,12 @@ pub struct ServiceTypeLabel {
     pub service_type: ServiceType,
 }
 
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct BuiltinLabel {
+    pub service_id: String,
+    pub function_name: String,
+}
+
 #[derive(Clone)]
 pub struct ServicesMemoryMetrics {
     /// Actual memory used by a module
@@ -94,8 +101,30 @@ pub struct ServicesMetricsExternal {
     pub call_success_count: Family<ServiceTypeLabel, Counter>,
     pub call_failed_count: Family<ServiceTypeLabel, Counter>,
 
+    /// Builtin call time per function
+    pub builtin_call_time_sec: Family<BuiltinLabel, Histogram>,
+    /// Number of builtin calls per function
+    pub builtin_call_count: Family<BuiltinLabel, Counter>,
+    /// Number of failed builtin calls per function
+    pub builtin_call_failed_count: Family<BuiltinLabel, Counter>,
+
     /// Memory metrics
     pub memory_metrics: ServicesMemoryMetrics,
+
+    /// Number of instances unloaded to fit the memory budget
+    pub unload_count: Counter,
+    /// Number of unloaded instances loaded back on a call
+    pub reload_count: Counter,
+    /// How long it took to load an unloaded instance back
+    pub reload_time_sec: Histogram,
+
+    /// Number of alias lookups that found a service
+    pub alias_lookup_hit_count: Counter,
+    /// Number of alias lookups that found no service
+    pub alias_lookup_miss_count: Counter,
+
+    /// Metrics published by spells via their KV
+    pub spell_kv_metrics: SpellKvMetrics,
 }
 
 impl ServicesMetricsExternal {
@@ -212,6 +241,65 @@ impl ServicesMetricsExternal {
             "call_failed_count",
             "count of fails of calls execution",
         );
+
+        let builtin_call_time_sec: Family<_, _> = register(
+            sub_registry,
+            Family::new_with_constructor(|| Histogram::new(execution_time_buckets())),
+            "builtin_call_time_sec",
+            "how long it took to execute a builtin function",
+        );
+
+        let builtin_call_count = register(
+            sub_registry,
+            Family::default(),
+            "builtin_call_count",
+            "count of builtin function calls",
+        );
+
+        let builtin_call_failed_count = register(
+            sub_registry,
+            Family::default(),
+            "builtin_call_failed_count",
+            "count of failed builtin function calls",
+        );
+
+        let unload_count = register(
+            sub_registry,
+            Counter::default(),
+            "unload_count",
+            "number of instances unloaded to fit the memory budget",
+        );
+
+        let reload_count = register(
+            sub_registry,
+            Counter::default(),
+            "reload_count",
+            "number of unloaded instances loaded back on a call",
+        );
+
+        let reload_time_sec = register(
+            sub_registry,
+            Histogram::new(execution_time_buckets()),
+            "reload_time_sec",
+            "how long it took to load an unloaded instance back",
+        );
+
+        let alias_lookup_hit_count = register(
+            sub_registry,
+            Counter::default(),
+            "alias_lookup_hit_count",
+            "number of alias lookups that found a service",
+        );
+
+        let alias_lookup_miss_count = register(
+            sub_registry,
+            Counter::default(),
+            "alias_lookup_miss_count",
+            "number of alias lookups that found no service",
+        );
+
+        let spell_kv_metrics = SpellKvMetrics::new(sub_registry);
+
         Self {
             services_count,
             creation_time_msec,
@@ -224,7 +312,16 @@ impl ServicesMetricsExternal {
             lock_wait_time_sec,
             call_success_count,
             call_failed_count,
+            builtin_call_time_sec,
+            builtin_call_count,
+            builtin_call_failed_count,
             memory_metrics,
+            unload_count,
+            reload_count,
+            reload_time_sec,
+            alias_lookup_hit_count,
+            alias_lookup_miss_count,
+            spell_kv_metrics,
         }
     }
 
@@ -21,15 +21,17 @@ pub mod backend;
 pub mod builtin;
 pub mod external;
 pub mod message;
+pub mod spell_kv;
 
 use std::{fmt, time::Duration};
 
 pub use crate::services_metrics::backend::ServicesMetricsBackend;
 pub use crate::services_metrics::builtin::ServicesMetricsBuiltin;
 pub use crate::services_metrics::external::ServiceType;
-use crate::services_metrics::external::ServiceTypeLabel;
 pub use crate::services_metrics::external::ServicesMetricsExternal;
+use crate::services_metrics::external::{BuiltinLabel, ServiceTypeLabel};
 pub use crate::services_metrics::message::{ServiceCallStats, ServiceMemoryStat};
+pub use crate::services_metrics::spell_kv::SpellKvMetrics;
 use crate::ServiceCallStats::Success;
 use prometheus_client::registry::Registry;
 use tokio::sync::mpsc;
@@ -107,6 +109,33 @@ impl ServicesMetrics {
         });
     }
 
+    /// Per-function metrics of a builtin call
+    pub fn observe_builtin_call(
+        &self,
+        service_id: &str,
+        function_name: &str,
+        is_ok: bool,
+        call_time: f64,
+    ) {
+        self.observe_external(|external| {
+            let label = BuiltinLabel {
+                service_id: service_id.to_string(),
+                function_name: function_name.to_string(),
+            };
+            external
+                .builtin_call_time_sec
+                .get_or_create(&label)
+                .observe(call_time);
+            external.builtin_call_count.get_or_create(&label).inc();
+            if !is_ok {
+                external
+                    .builtin_call_failed_count
+                    .get_or_create(&label)
+                    .inc();
+            }
+        });
+    }
+
     pub fn observe_service_state(
         &self,
         service_id: String,
@@ -195,6 +224,44 @@ impl ServicesMetrics {
         });
     }
 
+    pub fn observe_alias_resolution(&self, found: bool) {
+        self.observe_external(|external| {
+            if found {
+                external.alias_lookup_hit_count.inc();
+            } else {
+                external.alias_lookup_miss_count.inc();
+            }
+        });
+    }
+
+    pub fn observe_unloaded(&self) {
+        self.observe_external(|external| {
+            external.unload_count.inc();
+        });
+    }
+
+    pub fn observe_reloaded(&self, reload_time_sec: f64) {
+        self.observe_external(|external| {
+            external.reload_count.inc();
+            external.reload_time_sec.observe(reload_time_sec);
+        });
+    }
+
+    /// Publishes a value a spell wrote under the `metric:<name>` KV key
+    pub fn observe_spell_kv_metric(&self, spell_id: &str, name: &str, value: f64) {
+        self.observe_external(|external| {
+            if !external.spell_kv_metrics.set(spell_id, name, value) {
+                log::debug!("Spell {spell_id} reached the limit of KV metrics, {name} is ignored");
+            }
+        });
+    }
+
+    pub fn observe_spell_removed(&self, spell_id: &str) {
+        self.observe_external(|external| {
+            external.spell_kv_metrics.remove_spell(spell_id);
+        });
+    }
+
     fn observe_external<F>(&self, callback: F)
     where
         F: FnOnce(&ServicesMetricsExternal),
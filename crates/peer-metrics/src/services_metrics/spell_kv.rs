@@ -0,0 +1,128 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+use crate::register;
+
+/// Limits the number of series a single spell can create
+pub const MAX_METRICS_PER_SPELL: usize = 32;
+
+#[derive(EncodeLabelSet, Hash, Clone, Eq, PartialEq, Debug)]
+pub struct SpellKvMetricLabel {
+    pub spell_id: String,
+    pub name: String,
+}
+
+/// Values spells publish by writing `metric:<name>` keys into their KV
+#[derive(Clone)]
+pub struct SpellKvMetrics {
+    values: Family<SpellKvMetricLabel, Gauge<f64, AtomicU64>>,
+    names: Arc<Mutex<HashMap<String, HashSet<String>>>>,
+}
+
+impl SpellKvMetrics {
+    pub fn new(registry: &mut Registry) -> Self {
+        let values = register(
+            registry,
+            Family::default(),
+            "spell_kv_metric",
+            "values published by spells under metric:* keys of their KV",
+        );
+
+        Self {
+            values,
+            names: <_>::default(),
+        }
+    }
+
+    /// Returns false if the spell has reached the limit of metrics
+    pub fn set(&self, spell_id: &str, name: &str, value: f64) -> bool {
+        let mut names = self.names.lock();
+        let spell_names = names.entry(spell_id.to_string()).or_default();
+        if !spell_names.contains(name) {
+            if spell_names.len() >= MAX_METRICS_PER_SPELL {
+                return false;
+            }
+            spell_names.insert(name.to_string());
+        }
+
+        let label = SpellKvMetricLabel {
+            spell_id: spell_id.to_string(),
+            name: name.to_string(),
+        };
+        self.values.get_or_create(&label).set(value);
+        true
+    }
+
+    /// Drops all series of a removed spell
+    pub fn remove_spell(&self, spell_id: &str) {
+        let names = self.names.lock().remove(spell_id).unwrap_or_default();
+        for name in names {
+            self.values.remove(&SpellKvMetricLabel {
+                spell_id: spell_id.to_string(),
+                name,
+            });
+        }
+    }
+
+    pub fn get(&self, spell_id: &str, name: &str) -> Option<f64> {
+        let label = SpellKvMetricLabel {
+            spell_id: spell_id.to_string(),
+            name: name.to_string(),
+        };
+        self.names
+            .lock()
+            .get(spell_id)
+            .filter(|names| names.contains(name))
+            .map(|_| self.values.get_or_create(&label).get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus_client::registry::Registry;
+
+    use super::{SpellKvMetrics, MAX_METRICS_PER_SPELL};
+
+    #[test]
+    fn spell_metrics_are_limited_and_removed() {
+        let metrics = SpellKvMetrics::new(&mut Registry::default());
+
+        for i in 0..MAX_METRICS_PER_SPELL {
+            assert!(metrics.set("spell", &format!("m{i}"), i as f64));
+        }
+        assert!(!metrics.set("spell", "one_more", 1.0));
+        // existing metric can still be updated
+        assert!(metrics.set("spell", "m0", 42.0));
+        assert_eq!(metrics.get("spell", "m0"), Some(42.0));
+
+        metrics.remove_spell("spell");
+        assert_eq!(metrics.get("spell", "m0"), None);
+        assert!(metrics.set("spell", "one_more", 1.0));
+    }
+}
@@ -31,6 +31,8 @@ pub struct SpellMetrics {
     spell_scheduled_now: Gauge,
     // Distribution of spell's scheduled periods
     spell_periods: Histogram,
+    // How many spells are backed off after failing in a row
+    spell_quarantined: Gauge,
 }
 
 impl SpellMetrics {
@@ -58,10 +60,18 @@ impl SpellMetrics {
             "Spell particle periods",
         );
 
+        let spell_quarantined = register(
+            sub_registry,
+            Gauge::default(),
+            "quarantined",
+            "Number of spells backed off after failing in a row",
+        );
+
         Self {
             spell_particles_created,
             spell_scheduled_now,
             spell_periods,
+            spell_quarantined,
         }
     }
 
@@ -96,4 +106,12 @@ impl SpellMetrics {
     pub fn observe_spell_cast(&self) {
         self.spell_particles_created.inc();
     }
+
+    pub fn observe_spell_quarantined(&self) {
+        self.spell_quarantined.inc();
+    }
+
+    pub fn observe_spell_released(&self) {
+        self.spell_quarantined.dec();
+    }
 }
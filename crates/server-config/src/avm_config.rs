@@ -19,6 +19,7 @@
 
 use crate::wasm_backend_config::WasmBackendConfig;
 use derivative::Derivative;
+use particle_args::{ArgsLimits, ArgsRedaction};
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use serde_with::DisplayFromStr;
@@ -51,6 +52,14 @@ pub struct AVMConfig {
     #[serde(default)]
     pub hard_limit_enabled: bool,
 
+    /// Limits on nesting depth, string and array lengths of service call arguments.
+    #[serde(default)]
+    pub args_limits: ArgsLimits,
+
+    /// Call arguments masked before they are written to logs and traces.
+    #[serde(default)]
+    pub args_redaction: ArgsRedaction,
+
     #[serde(default)]
     pub wasm_backend: WasmBackendConfig,
 }
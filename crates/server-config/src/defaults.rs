@@ -31,7 +31,7 @@ use maplit::{btreemap, hashmap};
 
 use fluence_libp2p::Transport;
 
-use crate::node_config::PathOrValue;
+use crate::node_config::{PathOrValue, QosClassConfig};
 use crate::system_services_config::ServiceKey;
 
 const CONFIG_VERSION: usize = 1;
@@ -91,10 +91,269 @@ pub fn default_websocket_port() -> u16 {
     9999
 }
 
+pub fn default_listener_rebind_grace_period() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub fn default_rendezvous_ttl() -> Duration {
+    // same as the libp2p rendezvous default
+    Duration::from_secs(2 * 60 * 60)
+}
+
+pub fn default_pex_enabled() -> bool {
+    true
+}
+
+pub fn default_pex_period() -> Duration {
+    Duration::from_secs(60)
+}
+
+pub fn default_pex_sample_size() -> usize {
+    16
+}
+
+pub fn default_pex_fanout() -> usize {
+    3
+}
+
+pub fn default_pex_max_dials() -> usize {
+    8
+}
+
 pub fn default_http_port() -> u16 {
     18080
 }
 
+pub fn default_rpc_max_requests_per_sec() -> u32 {
+    10
+}
+
+pub fn default_rpc_max_response_size() -> u32 {
+    // 1 MiB
+    1024 * 1024
+}
+
+pub fn default_rpc_request_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub fn default_peer_probes_period() -> Duration {
+    Duration::from_secs(60)
+}
+
+pub fn default_peer_probes_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub fn default_service_health_period() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub fn default_service_health_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub fn default_service_health_max_restarts() -> u32 {
+    3
+}
+
+pub fn default_service_health_restart_backoff() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub fn default_service_health_max_restart_backoff() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+pub fn default_partition_detection_enabled() -> bool {
+    true
+}
+
+pub fn default_partition_detection_period() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub fn default_partition_known_after() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+pub fn default_partition_threshold() -> f64 {
+    0.5
+}
+
+pub fn default_partition_min_known_peers() -> usize {
+    3
+}
+
+pub fn default_spell_quarantine_failures() -> u32 {
+    3
+}
+
+pub fn default_spell_quarantine_max_backoff() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+pub fn default_scheduler_max_jobs_per_scope() -> usize {
+    100
+}
+
+pub fn default_scheduler_max_script_size() -> bytesize::ByteSize {
+    bytesize::ByteSize::kb(64)
+}
+
+pub fn default_scheduler_min_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub fn default_forwarding_max_hops() -> u32 {
+    1000
+}
+
+pub fn default_forwarding_max_repeats() -> u32 {
+    3
+}
+
+pub fn default_forwarding_capacity() -> usize {
+    10000
+}
+
+pub fn default_breaker_failures_threshold() -> u32 {
+    5
+}
+
+pub fn default_breaker_cooldown() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub fn default_send_max_retries() -> u32 {
+    2
+}
+
+pub fn default_send_retry_backoff() -> Duration {
+    Duration::from_millis(500)
+}
+
+pub fn default_breaker_capacity() -> usize {
+    10000
+}
+
+pub fn default_min_forward_ttl() -> Duration {
+    Duration::from_millis(200)
+}
+
+pub fn default_notify_initiator() -> bool {
+    true
+}
+
+pub fn default_expired_notice_ttl() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub fn default_snapshot_max_age() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+pub fn default_snapshot_retry_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+pub fn default_session_window() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+pub fn default_session_max_queued_particles() -> usize {
+    100
+}
+
+pub fn default_session_max_registrations() -> usize {
+    100
+}
+
+pub fn default_session_max_sessions() -> usize {
+    10_000
+}
+
+pub fn default_qos_enabled() -> bool {
+    true
+}
+
+pub fn default_qos_node_class() -> QosClassConfig {
+    QosClassConfig {
+        max_inbound_queue: 10_000,
+        max_outbound_streams: 32,
+        idle_timeout: None,
+    }
+}
+
+pub fn default_qos_client_class() -> QosClassConfig {
+    QosClassConfig {
+        max_inbound_queue: 1_000,
+        max_outbound_streams: 8,
+        // a client without particles for a default particle TTL has nothing in flight
+        idle_timeout: Some(Duration::from_secs(120)),
+    }
+}
+
+pub fn default_sink_batch_size() -> usize {
+    100
+}
+
+pub fn default_sink_flush_interval() -> Duration {
+    Duration::from_secs(1)
+}
+
+pub fn default_sink_max_retries() -> u32 {
+    3
+}
+
+pub fn default_sink_retry_backoff() -> Duration {
+    Duration::from_millis(500)
+}
+
+pub fn default_sink_buffer_size() -> usize {
+    10000
+}
+
+pub fn default_resource_monitor_enabled() -> bool {
+    true
+}
+
+pub fn default_resource_monitor_check_period() -> Duration {
+    Duration::from_secs(60)
+}
+
+pub fn default_disk_free_threshold_percent() -> u8 {
+    10
+}
+
+pub fn default_memory_available_threshold_percent() -> u8 {
+    10
+}
+
+pub fn default_self_update_download_timeout() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+pub fn default_self_update_max_download_size() -> bytesize::ByteSize {
+    bytesize::ByteSize::mb(512)
+}
+
+pub fn default_webrtc_udp_port_min() -> u16 {
+    9990
+}
+
+pub fn default_webrtc_udp_port_max() -> u16 {
+    9995
+}
+
+pub fn default_clock_skew_warn_threshold() -> Duration {
+    Duration::from_secs(2)
+}
+
+pub fn default_clock_skew_max_correction() -> Duration {
+    Duration::from_secs(30)
+}
+
 pub fn default_metrics_enabled() -> bool {
     true
 }
@@ -107,6 +366,38 @@ pub fn default_tokio_metrics_poll_histogram_enabled() -> bool {
     false
 }
 
+pub fn default_metrics_push_job() -> String {
+    "nox".to_string()
+}
+
+pub fn default_metrics_push_interval() -> Duration {
+    Duration::from_secs(15)
+}
+
+pub fn default_metrics_push_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+pub fn default_event_log_enabled() -> bool {
+    true
+}
+
+pub fn default_event_log_max_events() -> usize {
+    10_000
+}
+
+pub fn default_event_log_retention() -> Duration {
+    Duration::from_secs(7 * 24 * 60 * 60)
+}
+
+pub fn default_protocol_capture_max_file_size() -> bytesize::ByteSize {
+    bytesize::ByteSize::mb(64)
+}
+
+pub fn default_protocol_capture_max_files() -> usize {
+    4
+}
+
 pub fn default_health_check_enabled() -> bool {
     true
 }
@@ -115,6 +406,14 @@ pub fn default_services_metrics_timer_resolution() -> Duration {
     Duration::from_secs(60)
 }
 
+pub fn default_storage_plaintext_dir() -> PathBuf {
+    if cfg!(target_os = "linux") {
+        "/dev/shm/nox".into()
+    } else {
+        std::env::temp_dir().join("nox")
+    }
+}
+
 pub fn default_base_dir() -> PathBuf {
     format!(".fluence/v{CONFIG_VERSION}").into()
 }
@@ -151,6 +450,10 @@ pub fn default_aquavm_pool_size() -> usize {
     num_cpus::get() * 2
 }
 
+pub fn default_aquamarine_shards() -> usize {
+    1
+}
+
 pub fn default_particle_queue_buffer_size() -> usize {
     128
 }
@@ -163,10 +466,54 @@ pub fn default_workers_queue_buffer_size() -> usize {
     128
 }
 
+pub fn default_services_restore_parallelism() -> usize {
+    num_cpus::get()
+}
+
+pub fn default_services_nested_call_reserve() -> usize {
+    1
+}
+
+pub fn default_spell_receipts_capacity() -> usize {
+    1000
+}
+
+pub fn default_particle_dedup_capacity() -> usize {
+    10_000
+}
+
+pub fn default_kademlia_gc_interval() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+pub fn default_stale_peer_retention() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+pub fn default_max_queries_per_particle() -> usize {
+    4
+}
+
+pub fn default_max_concurrent_queries() -> usize {
+    64
+}
+
+pub fn default_max_queued_queries() -> usize {
+    1024
+}
+
 pub fn default_particle_processor_parallelism() -> Option<usize> {
     Some(num_cpus::get() * 2)
 }
 
+pub fn default_max_spell_data_size() -> usize {
+    1024 * 1024
+}
+
+pub fn default_slow_builtin_call_threshold() -> Duration {
+    Duration::from_secs(1)
+}
+
 pub fn default_max_spell_particle_ttl() -> Duration {
     Duration::from_secs(120)
 }
@@ -301,3 +648,15 @@ pub fn default_binaries_mapping() -> BTreeMap<String, String> {
 pub fn default_proof_poll_period() -> Duration {
     Duration::from_secs(60)
 }
+
+pub fn default_vault_token_env() -> String {
+    "VAULT_TOKEN".to_string()
+}
+
+pub fn default_vault_mount() -> String {
+    "secret".to_string()
+}
+
+pub fn default_secrets_cache_ttl() -> Duration {
+    Duration::from_secs(300)
+}
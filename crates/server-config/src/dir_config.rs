@@ -64,6 +64,9 @@ pub struct UnresolvedDirConfig {
 
     /// Path to stored core_state
     pub core_state_path: Option<PathBuf>,
+
+    /// Path to persisted jobs scheduled by `sched.submit`
+    pub scheduled_jobs_dir: Option<PathBuf>,
 }
 
 impl UnresolvedDirConfig {
@@ -102,6 +105,10 @@ impl UnresolvedDirConfig {
             .cc_events_dir
             .unwrap_or(persistent_base_dir.join("cc_events"));
 
+        let scheduled_jobs_dir = self
+            .scheduled_jobs_dir
+            .unwrap_or(persistent_base_dir.join("scheduled_jobs"));
+
         create_dirs(&[
             &base_dir,
             // ephemeral dirs
@@ -114,6 +121,7 @@ impl UnresolvedDirConfig {
             &spell_base_dir,
             &keypairs_base_dir,
             &workers_base_dir,
+            &scheduled_jobs_dir,
             // other
             &cc_events_dir,
         ])
@@ -131,6 +139,7 @@ impl UnresolvedDirConfig {
         let spell_base_dir = canonicalize(spell_base_dir)?;
         let keypairs_base_dir = canonicalize(keypairs_base_dir)?;
         let workers_base_dir = canonicalize(workers_base_dir)?;
+        let scheduled_jobs_dir = canonicalize(scheduled_jobs_dir)?;
 
         let cc_events_dir = canonicalize(cc_events_dir)?;
 
@@ -155,6 +164,7 @@ impl UnresolvedDirConfig {
             workers_base_dir,
             cc_events_dir,
             core_state_path,
+            scheduled_jobs_dir,
         })
     }
 }
@@ -175,4 +185,5 @@ pub struct ResolvedDirConfig {
     pub workers_base_dir: PathBuf,
     pub cc_events_dir: PathBuf,
     pub core_state_path: PathBuf,
+    pub scheduled_jobs_dir: PathBuf,
 }
@@ -20,6 +20,10 @@
 use libp2p::StreamProtocol;
 use std::time::Duration;
 
+use crate::defaults::{
+    default_kademlia_gc_interval, default_max_concurrent_queries, default_max_queries_per_particle,
+    default_max_queued_queries, default_stale_peer_retention,
+};
 use crate::Network;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
@@ -36,6 +40,22 @@ pub struct UnresolvedKademliaConfig {
     /// Period after which peer ban is lifted
     #[serde(with = "humantime_serde")]
     pub ban_cooldown: Duration,
+    /// How often expired records, stale peer addresses and failure counters are removed,
+    /// zero disables the collection
+    #[serde(default = "default_kademlia_gc_interval", with = "humantime_serde")]
+    pub gc_interval: Duration,
+    /// How long addresses of disconnected peers and failure counters are kept
+    #[serde(default = "default_stale_peer_retention", with = "humantime_serde")]
+    pub stale_peer_retention: Duration,
+    /// Max number of lookups a single particle makes at once, 0 disables the cap
+    #[serde(default = "default_max_queries_per_particle")]
+    pub max_queries_per_particle: usize,
+    /// Max number of lookups made at once while routing particles, 0 disables the cap
+    #[serde(default = "default_max_concurrent_queries")]
+    pub max_concurrent_queries: usize,
+    /// Lookups waiting for a slot until their particles expire, new ones fail beyond that
+    #[serde(default = "default_max_queued_queries")]
+    pub max_queued_queries: usize,
 }
 
 impl UnresolvedKademliaConfig {
@@ -48,6 +68,11 @@ impl UnresolvedKademliaConfig {
             replication_factor: self.replication_factor,
             peer_fail_threshold: self.peer_fail_threshold,
             ban_cooldown: self.ban_cooldown,
+            gc_interval: self.gc_interval,
+            stale_peer_retention: self.stale_peer_retention,
+            max_queries_per_particle: self.max_queries_per_particle,
+            max_concurrent_queries: self.max_concurrent_queries,
+            max_queued_queries: self.max_queued_queries,
             protocol_name,
         })
     }
@@ -66,6 +91,19 @@ pub struct KademliaConfig {
     /// Period after which peer ban is lifted
     #[serde(with = "humantime_serde")]
     pub ban_cooldown: Duration,
+    /// How often expired records, stale peer addresses and failure counters are removed,
+    /// zero disables the collection
+    #[serde(with = "humantime_serde")]
+    pub gc_interval: Duration,
+    /// How long addresses of disconnected peers and failure counters are kept
+    #[serde(with = "humantime_serde")]
+    pub stale_peer_retention: Duration,
+    /// Max number of lookups a single particle makes at once, 0 disables the cap
+    pub max_queries_per_particle: usize,
+    /// Max number of lookups made at once while routing particles, 0 disables the cap
+    pub max_concurrent_queries: usize,
+    /// Lookups waiting for a slot until their particles expire, new ones fail beyond that
+    pub max_queued_queries: usize,
     #[serde_as(as = "DisplayFromStr")]
     pub protocol_name: StreamProtocol,
 }
@@ -78,6 +116,11 @@ impl Default for UnresolvedKademliaConfig {
             replication_factor: None,
             peer_fail_threshold: 3,
             ban_cooldown: Duration::from_secs(60),
+            gc_interval: default_kademlia_gc_interval(),
+            stale_peer_retention: default_stale_peer_retention(),
+            max_queries_per_particle: default_max_queries_per_particle(),
+            max_concurrent_queries: default_max_concurrent_queries(),
+            max_queued_queries: default_max_queued_queries(),
         }
     }
 }
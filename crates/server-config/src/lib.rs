@@ -41,6 +41,7 @@ mod keys;
 mod network_config;
 mod node_config;
 mod resolved_config;
+mod secrets;
 mod services_config;
 pub mod system_services_config;
 mod wasm_backend_config;
@@ -51,9 +52,21 @@ pub use resolved_config::load_config_with_args;
 pub use resolved_config::ConfigData;
 
 pub use bootstrap_config::BootstrapConfig;
+pub use dir_config::{ResolvedDirConfig, UnresolvedDirConfig};
 pub use kademlia_config::KademliaConfig;
 pub use network_config::NetworkConfig;
-pub use node_config::{ChainConfig, ChainListenerConfig, Network, NodeConfig, TransportConfig};
+pub use node_config::{
+    ChainConfig, ChainListenerConfig, CircuitBreakerConfig, ClockSkewConfig, EventLogConfig,
+    ForwardingLoopConfig, KeypairConfig, MetricsPushAuth, MetricsPushConfig, MetricsPushMode,
+    Network, NodeConfig, PartitionDetectionConfig, PeerProbesConfig, PexConfig,
+    ProtocolCaptureConfig, QosClassConfig, QosConfig, RendezvousConfig, ResourceMonitorConfig,
+    RpcConfig, SchedulerConfig, SelfUpdateConfig, ServiceHealthConfig, SessionResumptionConfig,
+    SnapshotSyncConfig, SpellQuarantineConfig, SpellSinkConfig, SpellSinkTarget,
+    StorageEncryptionConfig, ThreadPoolConfig, ThreadPoolsConfig, TransportConfig, TtlGuardConfig,
+    WebRtcConfig, WorkerEgressConfig,
+};
 pub use resolved_config::TracingConfig;
 pub use resolved_config::{ResolvedConfig, UnresolvedConfig};
+pub use secrets::{Secrets, SecretsConfig};
 pub use system_services_config::{AquaIpfsConfig, DeciderConfig, SystemServicesConfig};
+pub use wasm_backend_config::WasmBackendConfig as WasmBackendSettings;
@@ -24,11 +24,14 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use config_utils::to_peer_id;
+use now_millis::ClockCorrection;
 use particle_protocol::ProtocolConfig;
-use peer_metrics::{ConnectionPoolMetrics, ConnectivityMetrics};
+use peer_metrics::{ConnectionPoolMetrics, ConnectivityMetrics, KademliaMetrics};
 
 use crate::kademlia_config::KademliaConfig;
-use crate::{BootstrapConfig, ResolvedConfig};
+use crate::{
+    BootstrapConfig, ClockSkewConfig, PexConfig, QosConfig, ResolvedConfig, SessionResumptionConfig,
+};
 
 pub struct NetworkConfig {
     pub key_pair: Keypair,
@@ -43,8 +46,16 @@ pub struct NetworkConfig {
     pub bootstrap_frequency: usize,
     pub connectivity_metrics: Option<ConnectivityMetrics>,
     pub connection_pool_metrics: Option<ConnectionPoolMetrics>,
+    pub kademlia_metrics: Option<KademliaMetrics>,
     pub connection_limits: ConnectionLimits,
     pub connection_idle_timeout: Duration,
+    pub clock_skew: ClockSkewConfig,
+    /// Set by the clock skew estimator, shared with the components checking particle deadlines
+    pub clock_correction: ClockCorrection,
+    pub rendezvous_server: bool,
+    pub pex: PexConfig,
+    pub session_resumption: SessionResumptionConfig,
+    pub qos: QosConfig,
 }
 
 impl NetworkConfig {
@@ -52,6 +63,7 @@ impl NetworkConfig {
         libp2p_metrics: Option<Arc<Metrics>>,
         connectivity_metrics: Option<ConnectivityMetrics>,
         connection_pool_metrics: Option<ConnectionPoolMetrics>,
+        kademlia_metrics: Option<KademliaMetrics>,
         key_pair: Keypair,
         config: &ResolvedConfig,
         node_version: &'static str,
@@ -70,8 +82,15 @@ impl NetworkConfig {
             bootstrap_frequency: config.bootstrap_frequency,
             connectivity_metrics,
             connection_pool_metrics,
+            kademlia_metrics,
             connection_limits,
             connection_idle_timeout: config.node_config.transport_config.connection_idle_timeout,
+            clock_skew: config.node_config.clock_skew_config.clone(),
+            clock_correction: ClockCorrection::default(),
+            rendezvous_server: config.node_config.rendezvous_config.server,
+            pex: config.node_config.pex_config.clone(),
+            session_resumption: config.node_config.session_resumption_config.clone(),
+            qos: config.node_config.qos_config.clone(),
         }
     }
 }
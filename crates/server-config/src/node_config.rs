@@ -19,7 +19,7 @@
 
 use std::collections::{BTreeMap, HashMap};
 use std::net::IpAddr;
-use std::ops::Deref;
+use std::ops::{Deref, RangeInclusive};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
@@ -39,14 +39,16 @@ use serde_with::DisplayFromStr;
 
 use fluence_libp2p::PeerId;
 use fluence_libp2p::Transport;
+use fluence_spell_dtos::trigger_config::TriggerConfig;
 use fs_utils::to_abs_path;
 use hex_utils::serde_as::Hex;
 use particle_protocol::ProtocolConfig;
-use types::peer_id;
+use types::{peer_id, Address};
 
 use crate::avm_config::AVMConfig;
 use crate::kademlia_config::{KademliaConfig, UnresolvedKademliaConfig};
 use crate::keys::{decode_key, decode_secret_key, load_key};
+use crate::secrets::{Secrets, SecretsConfig};
 use crate::services_config::ServicesConfig;
 use crate::system_services_config::{ServiceKey, SystemServicesConfig};
 use crate::BootstrapConfig;
@@ -70,6 +72,26 @@ pub struct UnresolvedNodeConfig {
     #[serde(default)]
     pub builtins_key_pair: Option<KeypairConfig>,
 
+    /// Derive keys of new workers from the root key by their deal ids, so they can be recovered
+    /// from the root key alone. Existing random worker keys keep working.
+    #[serde(default)]
+    pub derive_worker_keys: bool,
+
+    /// Run as a read-only replica: installing spells, creating services and workers
+    /// and changing their configs is rejected, routing and read builtins work as usual
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// Builtin calls taking longer are logged with their args size and caller
+    #[serde(default = "default_slow_builtin_call_threshold")]
+    #[serde(with = "humantime_serde")]
+    pub slow_builtin_call_threshold: Duration,
+
+    /// Builtin namespaces rejected at dispatch, e.g. `kad` or `providers` on public relays.
+    /// Can be changed at runtime with `capability.disable` and `capability.enable`
+    #[serde(default)]
+    pub disabled_builtins: Vec<String>,
+
     #[serde(flatten)]
     pub transport_config: TransportConfig,
 
@@ -98,6 +120,88 @@ pub struct UnresolvedNodeConfig {
     #[serde(flatten)]
     pub http_config: Option<HttpConfig>,
 
+    #[serde(default)]
+    pub rpc_config: RpcConfig,
+
+    #[serde(default)]
+    pub resource_monitor_config: ResourceMonitorConfig,
+
+    #[serde(default)]
+    pub self_update_config: SelfUpdateConfig,
+
+    #[serde(default)]
+    pub webrtc_config: WebRtcConfig,
+
+    #[serde(default)]
+    pub clock_skew_config: ClockSkewConfig,
+
+    #[serde(default)]
+    pub worker_egress_config: WorkerEgressConfig,
+
+    #[serde(default)]
+    pub rendezvous_config: RendezvousConfig,
+
+    #[serde(default)]
+    pub pex_config: PexConfig,
+
+    #[serde(default)]
+    pub event_log_config: EventLogConfig,
+
+    #[serde(default)]
+    pub protocol_capture_config: ProtocolCaptureConfig,
+
+    #[serde(default)]
+    pub peer_probes_config: PeerProbesConfig,
+
+    #[serde(default)]
+    pub service_health_config: ServiceHealthConfig,
+
+    #[serde(default)]
+    pub partition_detection_config: PartitionDetectionConfig,
+
+    #[serde(default)]
+    pub spell_quarantine_config: SpellQuarantineConfig,
+
+    #[serde(default)]
+    pub scheduler_config: SchedulerConfig,
+
+    #[serde(default)]
+    pub forwarding_loop_config: ForwardingLoopConfig,
+
+    #[serde(default)]
+    pub circuit_breaker_config: CircuitBreakerConfig,
+
+    #[serde(default)]
+    pub ttl_guard_config: TtlGuardConfig,
+
+    #[serde(default)]
+    pub snapshot_sync_config: SnapshotSyncConfig,
+
+    #[serde(default)]
+    pub session_resumption_config: SessionResumptionConfig,
+
+    #[serde(default)]
+    pub thread_pools_config: ThreadPoolsConfig,
+
+    #[serde(default)]
+    pub qos_config: QosConfig,
+
+    /// Encryption at rest of service persistent dirs and spell KVs, disabled if not set
+    #[serde(default)]
+    pub storage_encryption: Option<StorageEncryptionConfig>,
+
+    /// Named trigger configs which spells can be installed with instead of a trigger config
+    #[serde(default)]
+    pub trigger_presets: HashMap<String, TriggerConfig>,
+
+    /// Named external sinks which spells can route the values written to their KV keys to
+    #[serde(default)]
+    pub spell_sinks: HashMap<String, SpellSinkConfig>,
+
+    /// Where keypairs with `secret_ref` and `secret:` values are loaded from
+    #[serde(default)]
+    pub secrets: Option<SecretsConfig>,
+
     #[serde(default)]
     pub bootstrap_config: BootstrapConfig,
 
@@ -118,6 +222,11 @@ pub struct UnresolvedNodeConfig {
     #[serde(default = "default_aquavm_pool_size")]
     pub aquavm_pool_size: usize,
 
+    /// Number of router shards particles are spread across, each running in its own task.
+    /// AVMs of the pool are split between the shards
+    #[serde(default = "default_aquamarine_shards")]
+    pub aquamarine_shards: usize,
+
     /// Default heap size in bytes available for a WASM service unless otherwise specified.
     #[serde_as(as = "Option<DisplayFromStr>")]
     #[serde(default)]
@@ -135,6 +244,33 @@ pub struct UnresolvedNodeConfig {
     #[serde(default = "default_workers_queue_buffer_size")]
     pub workers_queue_buffer: usize,
 
+    /// How many persisted services are restored concurrently at startup
+    #[serde(default = "default_services_restore_parallelism")]
+    pub services_restore_parallelism: usize,
+
+    /// Total memory loaded service instances may use. Least recently used idle instances
+    /// above it are unloaded and loaded back on their next call; only their on-disk state is kept.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default)]
+    pub services_memory_budget: Option<bytesize::ByteSize>,
+
+    /// Maximum number of loaded service instances, enforced the same way as the memory budget
+    #[serde(default)]
+    pub max_loaded_services: Option<usize>,
+
+    /// How many service calls of the host or of a worker are executed at once, unlimited if not set
+    #[serde(default)]
+    pub services_call_concurrency: Option<usize>,
+
+    /// Calls reserved for services calling back into their own worker while it's saturated.
+    /// Nested calls over the reserve fail instead of waiting for the outer call forever.
+    #[serde(default = "default_services_nested_call_reserve")]
+    pub services_nested_call_reserve: usize,
+
+    /// Services (by id or alias) that only the host, its workers and the management peer may call
+    #[serde(default)]
+    pub internal_only_services: Vec<String>,
+
     #[serde(default = "default_particle_processor_parallelism")]
     pub particle_processor_parallelism: Option<usize>,
 
@@ -142,6 +278,22 @@ pub struct UnresolvedNodeConfig {
     #[serde(with = "humantime_serde")]
     pub max_spell_particle_ttl: Duration,
 
+    /// How many signed receipts of spell runs are kept for `spell.receipts`
+    #[serde(default = "default_spell_receipts_capacity")]
+    pub spell_receipts_capacity: usize,
+
+    /// Max size in bytes of JSON data written to a spell KV at once, e.g. `init_data` or a response
+    #[serde(default = "default_max_spell_data_size")]
+    pub max_spell_data_size: usize,
+
+    /// Persist particles accepted but not executed yet, and replay them after a restart
+    #[serde(default)]
+    pub persist_particle_queue: bool,
+
+    /// Max number of accepted particles remembered to drop their exact copies, 0 to disable
+    #[serde(default = "default_particle_dedup_capacity")]
+    pub particle_dedup_capacity: usize,
+
     #[serde(default = "default_bootstrap_frequency")]
     pub bootstrap_frequency: usize,
 
@@ -159,6 +311,10 @@ pub struct UnresolvedNodeConfig {
     #[serde(default = "default_management_peer_id")]
     pub management_peer_id: PeerId,
 
+    /// Revoked keys, workers created by them are paused until transferred to another creator
+    #[serde(default)]
+    pub revoked_keys: Vec<PeerIdSerializable>,
+
     // TODO: leave for now to migrate
     #[serde(default = "default_allowed_binaries")]
     pub allowed_binaries: Vec<String>,
@@ -213,6 +369,27 @@ impl TryFrom<&Network> for StreamProtocol {
 impl UnresolvedNodeConfig {
     pub fn resolve(mut self, persistent_base_dir: &Path) -> eyre::Result<NodeConfig> {
         self.load_system_services_envs();
+        let secrets = self.secrets.clone().map(Secrets::new);
+        if let Some(wallet_key) = self.system_services.decider.wallet_key.take() {
+            let wallet_key = Secrets::resolve_value(secrets.as_ref(), wallet_key)
+                .map_err(|err| eyre!("Failed to load decider wallet key: {err}"))?;
+            self.system_services.decider.wallet_key = Some(wallet_key);
+        }
+        if let Some(push) = self.metrics_config.push.as_mut() {
+            if let Some(auth) = push.auth.take() {
+                let auth = auth
+                    .resolve(secrets.as_ref())
+                    .map_err(|err| eyre!("Failed to load metrics push credentials: {err}"))?;
+                push.auth = Some(auth);
+            }
+        }
+
+        let storage_encryption = self
+            .storage_encryption
+            .take()
+            .map(|config| config.resolve(secrets.as_ref()))
+            .transpose()
+            .map_err(|err| eyre!("Failed to load storage data key: {err}"))?;
 
         let bootstrap_nodes = match self.local {
             Some(true) => vec![],
@@ -222,12 +399,12 @@ impl UnresolvedNodeConfig {
         let root_key_pair = self
             .root_key_pair
             .unwrap_or_default()
-            .get_keypair(default_keypair_path(persistent_base_dir))?;
+            .get_keypair(default_keypair_path(persistent_base_dir), secrets.as_ref())?;
 
-        let builtins_key_pair = self
-            .builtins_key_pair
-            .unwrap_or_default()
-            .get_keypair(default_builtins_keypair_path(persistent_base_dir))?;
+        let builtins_key_pair = self.builtins_key_pair.unwrap_or_default().get_keypair(
+            default_builtins_keypair_path(persistent_base_dir),
+            secrets.as_ref(),
+        )?;
 
         let allowed_effectors = self
             .effectors
@@ -250,6 +427,10 @@ impl UnresolvedNodeConfig {
             bootstrap_nodes,
             root_key_pair,
             builtins_key_pair,
+            derive_worker_keys: self.derive_worker_keys,
+            read_only: self.read_only,
+            slow_builtin_call_threshold: self.slow_builtin_call_threshold,
+            disabled_builtins: self.disabled_builtins,
             external_address: self.external_address,
             external_multiaddresses: self.external_multiaddresses,
             metrics_config: self.metrics_config,
@@ -259,24 +440,61 @@ impl UnresolvedNodeConfig {
             services_envs: self.services_envs,
             protocol_config: self.protocol_config,
             aquavm_pool_size: self.aquavm_pool_size,
+            aquamarine_shards: self.aquamarine_shards,
             default_service_memory_limit: self.default_service_memory_limit,
             avm_config: self.avm_config.unwrap_or_default(),
             kademlia,
             particle_queue_buffer: self.particle_queue_buffer,
             effects_queue_buffer: self.effects_queue_buffer,
             workers_queue_buffer: self.workers_queue_buffer,
+            services_restore_parallelism: self.services_restore_parallelism,
+            services_memory_budget: self.services_memory_budget,
+            max_loaded_services: self.max_loaded_services,
+            services_call_concurrency: self.services_call_concurrency,
+            services_nested_call_reserve: self.services_nested_call_reserve,
+            internal_only_services: self.internal_only_services,
             particle_processor_parallelism: self.particle_processor_parallelism,
             max_spell_particle_ttl: self.max_spell_particle_ttl,
+            spell_receipts_capacity: self.spell_receipts_capacity,
+            max_spell_data_size: self.max_spell_data_size,
+            persist_particle_queue: self.persist_particle_queue,
+            particle_dedup_capacity: self.particle_dedup_capacity,
             bootstrap_frequency: self.bootstrap_frequency,
             allow_local_addresses: self.allow_local_addresses,
             particle_execution_timeout: self.particle_execution_timeout,
             management_peer_id: self.management_peer_id,
+            revoked_keys: self.revoked_keys,
             transport_config: self.transport_config,
             listen_config: self.listen_config,
             allowed_effectors,
             dev_mode_config: self.dev_mode,
             system_services: self.system_services,
             http_config: self.http_config,
+            rpc_config: self.rpc_config,
+            resource_monitor_config: self.resource_monitor_config,
+            self_update_config: self.self_update_config,
+            webrtc_config: self.webrtc_config,
+            clock_skew_config: self.clock_skew_config,
+            worker_egress_config: self.worker_egress_config,
+            rendezvous_config: self.rendezvous_config,
+            pex_config: self.pex_config,
+            event_log_config: self.event_log_config,
+            protocol_capture_config: self.protocol_capture_config,
+            peer_probes_config: self.peer_probes_config,
+            service_health_config: self.service_health_config,
+            partition_detection_config: self.partition_detection_config,
+            spell_quarantine_config: self.spell_quarantine_config,
+            scheduler_config: self.scheduler_config,
+            forwarding_loop_config: self.forwarding_loop_config,
+            circuit_breaker_config: self.circuit_breaker_config,
+            ttl_guard_config: self.ttl_guard_config,
+            snapshot_sync_config: self.snapshot_sync_config,
+            session_resumption_config: self.session_resumption_config,
+            thread_pools_config: self.thread_pools_config,
+            qos_config: self.qos_config,
+            storage_encryption,
+            trigger_presets: self.trigger_presets,
+            spell_sinks: self.spell_sinks,
             chain_config: self.chain_config,
             chain_listener_config: self.chain_listener_config,
             services: self.services,
@@ -395,6 +613,19 @@ pub struct NodeConfig {
     #[serde(skip)]
     pub builtins_key_pair: KeyPair,
 
+    /// Derive keys of new workers from the root key
+    pub derive_worker_keys: bool,
+
+    /// Reject builtins changing services, spells, workers and their configs
+    pub read_only: bool,
+
+    /// Builtin calls taking longer are logged with their args size and caller
+    #[serde(with = "humantime_serde")]
+    pub slow_builtin_call_threshold: Duration,
+
+    /// Builtin namespaces rejected at dispatch
+    pub disabled_builtins: Vec<String>,
+
     pub transport_config: TransportConfig,
 
     pub listen_config: ListenConfig,
@@ -423,6 +654,9 @@ pub struct NodeConfig {
     /// Number of AVMs to create. By default, `num_cpus::get() * 2` is used
     pub aquavm_pool_size: usize,
 
+    /// Number of router shards particles are spread across
+    pub aquamarine_shards: usize,
+
     /// Default heap size in bytes available for a WASM service unless otherwise specified.
     pub default_service_memory_limit: Option<bytesize::ByteSize>,
 
@@ -437,10 +671,40 @@ pub struct NodeConfig {
 
     pub workers_queue_buffer: usize,
 
+    /// How many persisted services are restored concurrently at startup
+    pub services_restore_parallelism: usize,
+
+    /// Total memory loaded service instances may use before idle ones are unloaded
+    pub services_memory_budget: Option<bytesize::ByteSize>,
+
+    /// Maximum number of loaded service instances
+    pub max_loaded_services: Option<usize>,
+
+    /// How many service calls of the host or of a worker are executed at once
+    pub services_call_concurrency: Option<usize>,
+
+    /// Calls reserved for nested service calls of the same worker
+    pub services_nested_call_reserve: usize,
+
+    /// Services (by id or alias) that only the host, its workers and the management peer may call
+    pub internal_only_services: Vec<String>,
+
     pub particle_processor_parallelism: Option<usize>,
 
     pub max_spell_particle_ttl: Duration,
 
+    /// How many signed receipts of spell runs are kept for `spell.receipts`
+    pub spell_receipts_capacity: usize,
+
+    /// Max size in bytes of JSON data written to a spell KV at once
+    pub max_spell_data_size: usize,
+
+    /// Persist particles accepted but not executed yet, and replay them after a restart
+    pub persist_particle_queue: bool,
+
+    /// Max number of accepted particles remembered to drop their exact copies, 0 to disable
+    pub particle_dedup_capacity: usize,
+
     pub bootstrap_frequency: usize,
 
     pub allow_local_addresses: bool,
@@ -450,6 +714,9 @@ pub struct NodeConfig {
     #[serde(serialize_with = "peer_id::serde::serialize")]
     pub management_peer_id: PeerId,
 
+    /// Revoked keys, workers created by them are paused until transferred to another creator
+    pub revoked_keys: Vec<PeerIdSerializable>,
+
     pub allowed_effectors: HashMap<Hash, HashMap<String, String>>,
 
     pub dev_mode_config: DevModeConfig,
@@ -458,6 +725,58 @@ pub struct NodeConfig {
 
     pub http_config: Option<HttpConfig>,
 
+    pub rpc_config: RpcConfig,
+
+    pub resource_monitor_config: ResourceMonitorConfig,
+
+    pub self_update_config: SelfUpdateConfig,
+
+    pub webrtc_config: WebRtcConfig,
+
+    pub clock_skew_config: ClockSkewConfig,
+
+    pub worker_egress_config: WorkerEgressConfig,
+
+    pub rendezvous_config: RendezvousConfig,
+
+    pub pex_config: PexConfig,
+
+    pub event_log_config: EventLogConfig,
+
+    pub protocol_capture_config: ProtocolCaptureConfig,
+
+    pub peer_probes_config: PeerProbesConfig,
+
+    pub service_health_config: ServiceHealthConfig,
+
+    pub partition_detection_config: PartitionDetectionConfig,
+
+    pub spell_quarantine_config: SpellQuarantineConfig,
+
+    pub scheduler_config: SchedulerConfig,
+
+    pub forwarding_loop_config: ForwardingLoopConfig,
+
+    pub circuit_breaker_config: CircuitBreakerConfig,
+
+    pub ttl_guard_config: TtlGuardConfig,
+
+    pub snapshot_sync_config: SnapshotSyncConfig,
+
+    pub session_resumption_config: SessionResumptionConfig,
+
+    pub thread_pools_config: ThreadPoolsConfig,
+
+    pub qos_config: QosConfig,
+
+    pub storage_encryption: Option<StorageEncryptionConfig>,
+
+    /// Named trigger configs which spells can be installed with instead of a trigger config
+    pub trigger_presets: HashMap<String, TriggerConfig>,
+
+    /// Named external sinks which spells can route the values written to their KV keys to
+    pub spell_sinks: HashMap<String, SpellSinkConfig>,
+
     pub chain_config: Option<ChainConfig>,
 
     pub chain_listener_config: Option<ChainListenerConfig>,
@@ -501,108 +820,1076 @@ pub struct TransportConfig {
 pub struct HttpConfig {
     #[serde(default = "default_http_port")]
     pub http_port: u16,
+    /// Serve `/spells/:spell_id/trigger` for spells with a webhook set by `spell.set_webhook`
+    #[serde(default)]
+    pub spell_webhooks: bool,
 }
 
+/// Settings of the `rpc` builtin, a generic JSON-RPC client for spells
 #[derive(Clone, Deserialize, Serialize, Derivative)]
 #[derivative(Debug)]
-pub struct MetricsConfig {
-    #[serde(default = "default_metrics_enabled")]
-    pub metrics_enabled: bool,
+pub struct RpcConfig {
+    /// JSON-RPC endpoints reachable through the `rpc` builtin, none if the list is empty
+    #[serde(default)]
+    pub allowed_endpoints: Vec<String>,
 
-    #[serde(default = "default_services_metrics_timer_resolution")]
-    #[serde(with = "humantime_serde")]
-    pub metrics_timer_resolution: Duration,
+    /// Max number of requests per second of each host or worker to each endpoint
+    #[serde(default = "default_rpc_max_requests_per_sec")]
+    pub max_requests_per_sec: u32,
 
-    #[serde(default = "default_max_builtin_metrics_storage_size")]
-    pub max_builtin_metrics_storage_size: usize,
+    /// Max size of a response body in bytes
+    #[serde(default = "default_rpc_max_response_size")]
+    pub max_response_size: u32,
 
-    #[serde(default = "default_tokio_metrics_enabled")]
-    pub tokio_metrics_enabled: bool,
+    #[serde(default = "default_rpc_request_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub request_timeout: Duration,
+}
 
-    #[serde(default = "default_tokio_metrics_poll_histogram_enabled")]
-    pub tokio_metrics_poll_histogram_enabled: bool,
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            allowed_endpoints: vec![],
+            max_requests_per_sec: default_rpc_max_requests_per_sec(),
+            max_response_size: default_rpc_max_response_size(),
+            request_timeout: default_rpc_request_timeout(),
+        }
+    }
 }
 
+/// Settings of the node resource monitor, which notifies spells about low disk space and memory
 #[derive(Clone, Deserialize, Serialize, Derivative)]
 #[derivative(Debug)]
-pub struct HealthConfig {
-    #[serde(default = "default_health_check_enabled")]
-    pub health_check_enabled: bool,
+pub struct ResourceMonitorConfig {
+    #[serde(default = "default_resource_monitor_enabled")]
+    pub enabled: bool,
+
+    /// How often resources are checked
+    #[serde(default = "default_resource_monitor_check_period")]
+    #[serde(with = "humantime_serde")]
+    pub check_period: Duration,
+
+    /// Percent of free space on the services disk below which the disk event is published
+    #[serde(default = "default_disk_free_threshold_percent")]
+    pub disk_free_threshold_percent: u8,
+
+    /// Percent of available memory below which the memory event is published
+    #[serde(default = "default_memory_available_threshold_percent")]
+    pub memory_available_threshold_percent: u8,
+}
+
+impl Default for ResourceMonitorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_resource_monitor_enabled(),
+            check_period: default_resource_monitor_check_period(),
+            disk_free_threshold_percent: default_disk_free_threshold_percent(),
+            memory_available_threshold_percent: default_memory_available_threshold_percent(),
+        }
+    }
 }
 
+/// Settings of the `self_update` builtin, which lets management upgrade the node binary
 #[derive(Clone, Deserialize, Serialize, Derivative)]
 #[derivative(Debug)]
-pub struct ListenConfig {
-    /// For TCP connections
-    #[serde(default = "default_tcp_port")]
-    pub tcp_port: u16,
+pub struct SelfUpdateConfig {
+    /// URL to download the node binary from.
+    /// The builtin isn't registered if it's not set.
+    #[serde(default)]
+    pub download_url: Option<String>,
 
-    /// Local ip address to listen on
-    #[serde(default = "default_listen_ip")]
-    pub listen_ip: IpAddr,
+    /// URL to download the signed manifest with the version and hash of the binary from,
+    /// `{download_url}.manifest` if not set
+    #[serde(default)]
+    pub manifest_url: Option<String>,
 
-    /// For ws connections
-    #[serde(default = "default_websocket_port")]
-    pub websocket_port: u16,
+    /// Peer ids whose keys are trusted to sign manifests of node binaries
+    #[serde(default)]
+    pub trusted_keys: Vec<String>,
+
+    #[serde(default = "default_self_update_download_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub download_timeout: Duration,
+
+    /// Downloads larger than that are aborted
+    #[serde(default = "default_self_update_max_download_size")]
+    pub max_download_size: bytesize::ByteSize,
 }
 
-#[derive(Clone, Deserialize, Serialize, Debug, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
-#[repr(transparent)]
-pub struct PeerIdSerializable(
-    #[serde(
-        serialize_with = "peer_id::serde::serialize",
-        deserialize_with = "peer_id::serde::deserialize"
-    )]
-    PeerId,
-);
+impl Default for SelfUpdateConfig {
+    fn default() -> Self {
+        Self {
+            download_url: None,
+            manifest_url: None,
+            trusted_keys: vec![],
+            download_timeout: default_self_update_download_timeout(),
+            max_download_size: default_self_update_max_download_size(),
+        }
+    }
+}
 
-impl Deref for PeerIdSerializable {
-    type Target = PeerId;
+/// Settings of the WebRTC transport, which lets browsers connect to the node directly
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct WebRtcConfig {
+    #[serde(default)]
+    pub enabled: bool,
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+    /// UDP ports to listen for WebRTC connections on, the first free one is used
+    #[serde(default = "default_webrtc_udp_port_min")]
+    pub udp_port_min: u16,
+
+    #[serde(default = "default_webrtc_udp_port_max")]
+    pub udp_port_max: u16,
+
+    /// PEM file with the DTLS certificate, `{persistent_base_dir}/webrtc_certificate.pem` if not set.
+    /// Certificate hash is advertised in the multiaddr, so it must be kept across restarts.
+    #[serde(default)]
+    pub certificate_path: Option<PathBuf>,
+}
+
+impl WebRtcConfig {
+    pub fn udp_ports(&self) -> RangeInclusive<u16> {
+        self.udp_port_min..=self.udp_port_max
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-#[serde(untagged)]
-pub enum PathOrValue {
-    Value { value: String },
-    Path { path: PathBuf },
+impl Default for WebRtcConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            udp_port_min: default_webrtc_udp_port_min(),
+            udp_port_max: default_webrtc_udp_port_max(),
+            certificate_path: None,
+        }
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct KeypairConfig {
-    #[serde(default = "default_key_format")]
-    pub format: String,
-    #[serde(flatten)]
+/// Settings of the clock skew estimation against connected peers
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct ClockSkewConfig {
+    /// Skew above which a warning is logged
+    #[serde(default = "default_clock_skew_warn_threshold")]
+    #[serde(with = "humantime_serde")]
+    pub warn_threshold: Duration,
+
+    /// Whether to correct the local clock by the estimated skew when checking particle deadlines
     #[serde(default)]
-    pub keypair: Option<PathOrValue>,
+    pub compensate: bool,
+
+    /// Max correction applied to the local clock, larger estimates are clamped to it
+    #[serde(default = "default_clock_skew_max_correction")]
+    #[serde(with = "humantime_serde")]
+    pub max_correction: Duration,
+}
+
+impl Default for ClockSkewConfig {
+    fn default() -> Self {
+        Self {
+            warn_threshold: default_clock_skew_warn_threshold(),
+            compensate: false,
+            max_correction: default_clock_skew_max_correction(),
+        }
+    }
+}
+
+/// Rendezvous protocol settings: nodes acting as rendezvous points accept registrations under
+/// namespaces (e.g. "ipfs-adapters") and let clients discover the registered peers by namespace
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct RendezvousConfig {
+    /// Act as a rendezvous point
     #[serde(default)]
-    pub secret_key: Option<String>,
+    pub server: bool,
+
+    /// Namespaces to register this node under at every rendezvous point
     #[serde(default)]
-    pub generate_on_absence: bool,
+    pub namespaces: Vec<String>,
+
+    /// Rendezvous points to register at, must end with `/p2p/<peer id>`
+    #[serde(default)]
+    pub points: Vec<Multiaddr>,
+
+    /// For how long a registration is valid, it is refreshed at half of that
+    #[serde(default = "default_rendezvous_ttl")]
+    #[serde(with = "humantime_serde")]
+    pub ttl: Duration,
 }
 
-impl Default for KeypairConfig {
+impl Default for RendezvousConfig {
     fn default() -> Self {
         Self {
-            format: default_key_format(),
-            keypair: None,
-            secret_key: None,
-            generate_on_absence: true,
+            server: false,
+            namespaces: vec![],
+            points: vec![],
+            ttl: default_rendezvous_ttl(),
         }
     }
 }
 
-impl KeypairConfig {
-    pub fn get_keypair(self, default: PathOrValue) -> Result<KeyPair, eyre::Report> {
-        use crate::node_config::PathOrValue::{Path, Value};
+/// Peer exchange: connected nodes periodically share samples of their healthy peers.
+/// Peers learned that way are only dialed, they get into the address book once they
+/// identify as Fluence nodes, so the network can be discovered beyond the bootstrap list.
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct PexConfig {
+    #[serde(default = "default_pex_enabled")]
+    pub enabled: bool,
 
-        debug_assert!(
-            !(self.secret_key.is_some() && self.keypair.is_some()),
-            "shouldn't have both secret_key and keypair defined in KeypairConfig"
-        );
+    /// How often peers are asked for their samples
+    #[serde(default = "default_pex_period")]
+    #[serde(with = "humantime_serde")]
+    pub period: Duration,
+
+    /// Max number of peers shared in one exchange
+    #[serde(default = "default_pex_sample_size")]
+    pub sample_size: usize,
+
+    /// How many random connected peers are asked every period
+    #[serde(default = "default_pex_fanout")]
+    pub fanout: usize,
+
+    /// Max number of new peers dialed from one received sample
+    #[serde(default = "default_pex_max_dials")]
+    pub max_dials: usize,
+}
+
+impl Default for PexConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_pex_enabled(),
+            period: default_pex_period(),
+            sample_size: default_pex_sample_size(),
+            fanout: default_pex_fanout(),
+            max_dials: default_pex_max_dials(),
+        }
+    }
+}
+
+/// Log of node events queryable by `event.query` and the HTTP debug API
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct EventLogConfig {
+    #[serde(default = "default_event_log_enabled")]
+    pub enabled: bool,
+
+    /// Oldest events are dropped above that number
+    #[serde(default = "default_event_log_max_events")]
+    pub max_events: usize,
+
+    /// Events older than that are dropped
+    #[serde(default = "default_event_log_retention")]
+    #[serde(with = "humantime_serde")]
+    pub retention: Duration,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_event_log_enabled(),
+            max_events: default_event_log_max_events(),
+            retention: default_event_log_retention(),
+        }
+    }
+}
+
+/// Limits of the protocol messages capture started by `capture.start`
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct ProtocolCaptureConfig {
+    /// Capture file is rotated when it grows above that
+    #[serde(default = "default_protocol_capture_max_file_size")]
+    pub max_file_size: bytesize::ByteSize,
+
+    /// How many capture files are kept, including the one being written
+    #[serde(default = "default_protocol_capture_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for ProtocolCaptureConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: default_protocol_capture_max_file_size(),
+            max_files: default_protocol_capture_max_files(),
+        }
+    }
+}
+
+/// Must-reach peers which the node dials periodically, publishing the results to metrics
+/// and to spells subscribed with `spell.set_probe_triggers`
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct PeerProbesConfig {
+    /// Addresses like `peer:12D3KooW...` or `relay:12D3KooW.../client:12D3KooW...`.
+    /// The first peer on the path is dialed, a bare `service:id` checks that the host has the service.
+    #[serde(default)]
+    pub targets: Vec<Address>,
+
+    /// How often the targets are probed
+    #[serde(default = "default_peer_probes_period")]
+    #[serde(with = "humantime_serde")]
+    pub period: Duration,
+
+    /// A target is considered unreachable if it isn't reached within that time
+    #[serde(default = "default_peer_probes_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+}
+
+impl Default for PeerProbesConfig {
+    fn default() -> Self {
+        Self {
+            targets: vec![],
+            period: default_peer_probes_period(),
+            timeout: default_peer_probes_timeout(),
+        }
+    }
+}
+
+/// Health checks of services declaring a health function with the `health` label.
+/// Failing instances are restarted, changes are published to spells subscribed with
+/// `spell.set_health_triggers`
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct ServiceHealthConfig {
+    /// How often health functions are called
+    #[serde(default = "default_service_health_period")]
+    #[serde(with = "humantime_serde")]
+    pub period: Duration,
+
+    /// A service is considered unhealthy if its health function doesn't respond within that time
+    #[serde(default = "default_service_health_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+
+    /// Restarts in a row after which a failing service is left as is until it recovers
+    #[serde(default = "default_service_health_max_restarts")]
+    pub max_restarts: u32,
+
+    /// Pause before restarting a service again, doubles after each restart in a row
+    #[serde(default = "default_service_health_restart_backoff")]
+    #[serde(with = "humantime_serde")]
+    pub restart_backoff: Duration,
+
+    #[serde(default = "default_service_health_max_restart_backoff")]
+    #[serde(with = "humantime_serde")]
+    pub max_restart_backoff: Duration,
+}
+
+impl Default for ServiceHealthConfig {
+    fn default() -> Self {
+        Self {
+            period: default_service_health_period(),
+            timeout: default_service_health_timeout(),
+            max_restarts: default_service_health_max_restarts(),
+            restart_backoff: default_service_health_restart_backoff(),
+            max_restart_backoff: default_service_health_max_restart_backoff(),
+        }
+    }
+}
+
+/// Detection of network partitions by the share of long-known peers which are unreachable.
+/// A possible partition fails the `/health` check and is published to spells subscribed with
+/// `spell.set_partition_triggers`
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct PartitionDetectionConfig {
+    #[serde(default = "default_partition_detection_enabled")]
+    pub enabled: bool,
+
+    /// How often the reachability of long-known peers is evaluated
+    #[serde(default = "default_partition_detection_period")]
+    #[serde(with = "humantime_serde")]
+    pub period: Duration,
+
+    /// A peer becomes long-known once it was first connected that long ago
+    #[serde(default = "default_partition_known_after")]
+    #[serde(with = "humantime_serde")]
+    pub known_after: Duration,
+
+    /// Fraction of unreachable long-known peers from which a partition is reported
+    #[serde(default = "default_partition_threshold")]
+    pub threshold: f64,
+
+    /// Nothing is reported while the node knows fewer peers
+    #[serde(default = "default_partition_min_known_peers")]
+    pub min_known_peers: usize,
+}
+
+impl Default for PartitionDetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_partition_detection_enabled(),
+            period: default_partition_detection_period(),
+            known_after: default_partition_known_after(),
+            threshold: default_partition_threshold(),
+            min_known_peers: default_partition_min_known_peers(),
+        }
+    }
+}
+
+/// Backing off the timers of spells failing in a row. A run fails if it reports an error
+/// through `errorHandlingSrv`, the first successful run resets the timer
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct SpellQuarantineConfig {
+    /// Failed runs in a row after which the period of the spell is doubled on each failure,
+    /// 0 disables the quarantine
+    #[serde(default = "default_spell_quarantine_failures")]
+    pub failures_threshold: u32,
+
+    /// The period of a quarantined spell isn't made longer than that
+    #[serde(default = "default_spell_quarantine_max_backoff")]
+    #[serde(with = "humantime_serde")]
+    pub max_backoff: Duration,
+}
+
+impl Default for SpellQuarantineConfig {
+    fn default() -> Self {
+        Self {
+            failures_threshold: default_spell_quarantine_failures(),
+            max_backoff: default_spell_quarantine_max_backoff(),
+        }
+    }
+}
+
+/// Limits of the jobs submitted via `sched.submit`
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct SchedulerConfig {
+    /// Pending jobs a host or a worker can have at once
+    #[serde(default = "default_scheduler_max_jobs_per_scope")]
+    pub max_jobs_per_scope: usize,
+
+    /// Scripts of jobs can't be larger than that
+    #[serde(default = "default_scheduler_max_script_size")]
+    pub max_script_size: bytesize::ByteSize,
+
+    /// Repeated jobs can't run more often than that
+    #[serde(default = "default_scheduler_min_interval")]
+    #[serde(with = "humantime_serde")]
+    pub min_interval: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_jobs_per_scope: default_scheduler_max_jobs_per_scope(),
+            max_script_size: default_scheduler_max_script_size(),
+            min_interval: default_scheduler_min_interval(),
+        }
+    }
+}
+
+/// Particles are dropped before being sent further if they look like they're going in circles
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct ForwardingLoopConfig {
+    /// Max number of times a particle may be sent from node to node, 0 to disable the check
+    #[serde(default = "default_forwarding_max_hops")]
+    pub max_hops: u32,
+
+    /// Max number of times the node sends the exact same particle further, 0 to disable the check
+    #[serde(default = "default_forwarding_max_repeats")]
+    pub max_repeats: u32,
+
+    /// Max number of forwarded particles remembered to count the repeats
+    #[serde(default = "default_forwarding_capacity")]
+    pub capacity: usize,
+}
+
+impl Default for ForwardingLoopConfig {
+    fn default() -> Self {
+        Self {
+            max_hops: default_forwarding_max_hops(),
+            max_repeats: default_forwarding_max_repeats(),
+            capacity: default_forwarding_capacity(),
+        }
+    }
+}
+
+/// Sending particles to remote peers is retried a few times, and peers failing in a row
+/// are failed fast for a while instead of being dialed again and again
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct CircuitBreakerConfig {
+    /// Failed sends in a row after which the breaker of the peer opens, 0 disables the breakers
+    #[serde(default = "default_breaker_failures_threshold")]
+    pub failures_threshold: u32,
+
+    /// How long sends to the peer fail fast before a single send probes it again
+    #[serde(default = "default_breaker_cooldown")]
+    #[serde(with = "humantime_serde")]
+    pub cooldown: Duration,
+
+    /// Max number of times a failed send is retried
+    #[serde(default = "default_send_max_retries")]
+    pub max_retries: u32,
+
+    /// Pause before a retry, doubled on each next one
+    #[serde(default = "default_send_retry_backoff")]
+    #[serde(with = "humantime_serde")]
+    pub retry_backoff: Duration,
+
+    /// Max number of failing peers tracked by the breakers
+    #[serde(default = "default_breaker_capacity")]
+    pub capacity: usize,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failures_threshold: default_breaker_failures_threshold(),
+            cooldown: default_breaker_cooldown(),
+            max_retries: default_send_max_retries(),
+            retry_backoff: default_send_retry_backoff(),
+            capacity: default_breaker_capacity(),
+        }
+    }
+}
+
+/// Particles aren't sent further when they have too little time left to be of any use
+/// on the next peers, their initiators are told about it instead
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct TtlGuardConfig {
+    /// Min remaining TTL a particle must have to be sent to remote peers, 0 to disable the check
+    #[serde(default = "default_min_forward_ttl")]
+    #[serde(with = "humantime_serde")]
+    pub min_forward_ttl: Duration,
+
+    /// Whether to send an `("expired" particle_id)` call to the initiator of a dropped particle
+    #[serde(default = "default_notify_initiator")]
+    pub notify_initiator: bool,
+
+    /// TTL of the particles telling initiators about the dropped ones
+    #[serde(default = "default_expired_notice_ttl")]
+    #[serde(with = "humantime_serde")]
+    pub notice_ttl: Duration,
+}
+
+impl Default for TtlGuardConfig {
+    fn default() -> Self {
+        Self {
+            min_forward_ttl: default_min_forward_ttl(),
+            notify_initiator: default_notify_initiator(),
+            notice_ttl: default_expired_notice_ttl(),
+        }
+    }
+}
+
+/// Provider and worker records are fetched on start from a designated peer
+/// instead of waiting for them to reach the node through the network
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct SnapshotSyncConfig {
+    /// Peer to fetch the snapshot from, trusted implicitly. Nothing is fetched if not set
+    #[serde(default)]
+    pub sync_from: Option<PeerIdSerializable>,
+
+    /// Peers whose snapshots are applied by `providers.apply_snapshot`
+    #[serde(default)]
+    pub trusted_peers: Vec<PeerIdSerializable>,
+
+    /// Snapshots made earlier than this are rejected
+    #[serde(default = "default_snapshot_max_age")]
+    #[serde(with = "humantime_serde")]
+    pub max_age: Duration,
+
+    /// How often the snapshot is requested until one is applied
+    #[serde(default = "default_snapshot_retry_interval")]
+    #[serde(with = "humantime_serde")]
+    pub retry_interval: Duration,
+}
+
+impl Default for SnapshotSyncConfig {
+    fn default() -> Self {
+        Self {
+            sync_from: None,
+            trusted_peers: vec![],
+            max_age: default_snapshot_max_age(),
+            retry_interval: default_snapshot_retry_interval(),
+        }
+    }
+}
+
+/// Clients may open sessions with `session.open` to get their provider registrations
+/// and the particles sent to them back when they reconnect within the window
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct SessionResumptionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// How long the session of a disconnected client is kept
+    #[serde(default = "default_session_window")]
+    #[serde(with = "humantime_serde")]
+    pub window: Duration,
+
+    /// Particles queued for a disconnected client, the oldest are dropped beyond that
+    #[serde(default = "default_session_max_queued_particles")]
+    pub max_queued_particles: usize,
+
+    /// Aliases a client may register itself as a provider of
+    #[serde(default = "default_session_max_registrations")]
+    pub max_registrations: usize,
+
+    /// Sessions of connected and disconnected clients kept at once
+    #[serde(default = "default_session_max_sessions")]
+    pub max_sessions: usize,
+}
+
+impl Default for SessionResumptionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: default_session_window(),
+            max_queued_particles: default_session_max_queued_particles(),
+            max_registrations: default_session_max_registrations(),
+            max_sessions: default_session_max_sessions(),
+        }
+    }
+}
+
+/// External sink receiving the values spells route to it with `spell.set_sinks`.
+/// Values are sent in batches, and a batch failing after all retries is dropped.
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct SpellSinkConfig {
+    #[serde(flatten)]
+    pub target: SpellSinkTarget,
+
+    /// Max number of values sent in a single batch
+    #[serde(default = "default_sink_batch_size")]
+    pub batch_size: usize,
+
+    /// How long values are collected before a batch smaller than `batch_size` is sent
+    #[serde(default = "default_sink_flush_interval")]
+    #[serde(with = "humantime_serde")]
+    pub flush_interval: Duration,
+
+    /// Max number of times a failed batch is retried
+    #[serde(default = "default_sink_max_retries")]
+    pub max_retries: u32,
+
+    /// Pause before a retry, doubled on each next one
+    #[serde(default = "default_sink_retry_backoff")]
+    #[serde(with = "humantime_serde")]
+    pub retry_backoff: Duration,
+
+    /// Values waiting to be sent, new values are dropped beyond that while the sink is failing
+    #[serde(default = "default_sink_buffer_size")]
+    pub buffer_size: usize,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SpellSinkTarget {
+    Kafka {
+        brokers: Vec<String>,
+        topic: String,
+        #[serde(default)]
+        partition: i32,
+    },
+    Nats {
+        url: String,
+        subject: String,
+    },
+}
+
+/// Thread pools of the host peer per workload class, so that background work like spells
+/// doesn't disturb latency-sensitive workloads. Worker pools are sized by their compute units.
+#[derive(Clone, Default, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct ThreadPoolsConfig {
+    /// Main pool running networking, particle routing and everything not moved to other pools.
+    /// Has a thread per system CPU core and is pinned to them if not set.
+    #[serde(default)]
+    pub network: Option<ThreadPoolConfig>,
+
+    /// Pool executing AIR scripts, the main pool is used if not set
+    #[serde(default)]
+    pub avm: Option<ThreadPoolConfig>,
+
+    /// Pool executing calls of services and builtins, the main pool is used if not set
+    #[serde(default)]
+    pub effectors: Option<ThreadPoolConfig>,
+}
+
+/// Separate limits for connections with other nodes and with clients,
+/// so that clients can't degrade routing between nodes under load
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct QosConfig {
+    /// All peers share the same unbounded queue and limits if disabled
+    #[serde(default = "default_qos_enabled")]
+    pub enabled: bool,
+
+    #[serde(default = "default_qos_node_class")]
+    pub node: QosClassConfig,
+
+    #[serde(default = "default_qos_client_class")]
+    pub client: QosClassConfig,
+}
+
+impl Default for QosConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_qos_enabled(),
+            node: default_qos_node_class(),
+            client: default_qos_client_class(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct QosClassConfig {
+    /// Particles from peers of the class waiting for execution, new ones are dropped beyond that
+    pub max_inbound_queue: usize,
+
+    /// Outbound substreams opened at once on a connection
+    pub max_outbound_streams: u32,
+
+    /// Connections without particles for that long are closed,
+    /// only `transport_config.connection_idle_timeout` applies if not set
+    #[serde(default, with = "humantime_serde")]
+    pub idle_timeout: Option<Duration>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct ThreadPoolConfig {
+    pub threads: usize,
+
+    /// Logical CPUs the threads are pinned to, not pinned if empty.
+    /// On NUMA servers, CPUs of the same node should be used.
+    #[serde(default)]
+    pub cpus: Vec<u32>,
+}
+
+/// Keys are hex-encoded 32 bytes and may refer to the secrets provider with the `secret:` prefix.
+/// To rotate the data key, set the new one and move the old one to `previous_keys`
+/// until `nox storage encrypt` re-encrypts the data or all services are reloaded.
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct StorageEncryptionConfig {
+    #[serde(skip_serializing)]
+    #[derivative(Debug = "ignore")]
+    pub data_key: String,
+    /// Keys the data may still be encrypted with, used only to decrypt it
+    #[serde(default, skip_serializing)]
+    #[derivative(Debug = "ignore")]
+    pub previous_keys: Vec<String>,
+    /// Where loaded services get decrypted copies of their persistent dirs.
+    /// It should be on tmpfs, so that plaintext never reaches the disk.
+    #[serde(default = "default_storage_plaintext_dir")]
+    pub plaintext_dir: PathBuf,
+}
+
+impl StorageEncryptionConfig {
+    fn resolve(self, secrets: Option<&Secrets>) -> eyre::Result<Self> {
+        Ok(Self {
+            plaintext_dir: self.plaintext_dir,
+            data_key: Secrets::resolve_value(secrets, self.data_key)?,
+            previous_keys: self
+                .previous_keys
+                .into_iter()
+                .map(|key| Secrets::resolve_value(secrets, key))
+                .collect::<eyre::Result<_>>()?,
+        })
+    }
+}
+
+/// Outbound network destinations reachable on behalf of workers by builtins, spell sinks
+/// and mounted binaries of their services.
+/// Entries are `host` or `host:port`, where host may be `*` or `*.domain` to match subdomains.
+/// Calls made on the host itself aren't restricted.
+#[derive(Clone, Default, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct WorkerEgressConfig {
+    /// Applied to workers without their own policy, egress is unrestricted if not set
+    #[serde(default)]
+    pub default_allowed: Option<Vec<String>>,
+
+    /// Policies of particular workers, by worker peer id
+    #[serde(default)]
+    pub workers: HashMap<String, Vec<String>>,
+
+    /// Mounted binaries which services of restricted workers may still use.
+    /// The node can't see where binaries connect to, so the operator must make sure these
+    /// respect the policy, e.g. by wrapping them
+    #[serde(default)]
+    pub trusted_binaries: Vec<String>,
+}
+
+impl WorkerEgressConfig {
+    /// Allowed destinations of the worker, `None` if egress is unrestricted
+    pub fn allowed_for(&self, worker_id: &str) -> Option<&[String]> {
+        self.workers
+            .get(worker_id)
+            .or(self.default_allowed.as_ref())
+            .map(Vec::as_slice)
+    }
+
+    pub fn allows(&self, worker_id: &str, host: &str, port: Option<u16>) -> bool {
+        match self.allowed_for(worker_id) {
+            None => true,
+            Some(allowed) => allowed
+                .iter()
+                .any(|entry| egress_entry_matches(entry, host, port)),
+        }
+    }
+
+    /// Whether the worker may reach `destination`, which is a URL or a `host:port` address
+    pub fn allows_destination(&self, worker_id: &str, destination: &str) -> bool {
+        if self.allowed_for(worker_id).is_none() {
+            return true;
+        }
+        match url::Url::parse(destination) {
+            Ok(url) if url.has_host() => self.allows(
+                worker_id,
+                url.host_str().unwrap_or_default(),
+                url.port_or_known_default(),
+            ),
+            // `host:port` addresses either fail to parse or are parsed as a scheme and a path
+            _ => {
+                let (host, port) = match destination.rsplit_once(':') {
+                    Some((host, port)) => match port.parse() {
+                        Ok(port) => (host, Some(port)),
+                        Err(_) => return false,
+                    },
+                    None => (destination, None),
+                };
+                self.allows(worker_id, host, port)
+            }
+        }
+    }
+}
+
+fn egress_entry_matches(entry: &str, host: &str, port: Option<u16>) -> bool {
+    let (entry_host, entry_port) = match entry.rsplit_once(':') {
+        // `]` ends a bracketed IPv6 address without a port
+        Some((entry_host, entry_port)) if !entry_port.ends_with(']') => {
+            (entry_host, Some(entry_port))
+        }
+        _ => (entry, None),
+    };
+
+    let port_matches = match entry_port {
+        None | Some("*") => true,
+        Some(entry_port) => port.map(|p| p.to_string()).as_deref() == Some(entry_port),
+    };
+    let host = host.to_lowercase();
+    let host_matches = if entry_host == "*" {
+        true
+    } else if let Some(domain) = entry_host.strip_prefix("*.") {
+        host.ends_with(&format!(".{}", domain.to_lowercase()))
+    } else {
+        host == entry_host.to_lowercase()
+    };
+
+    host_matches && port_matches
+}
+
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct MetricsConfig {
+    #[serde(default = "default_metrics_enabled")]
+    pub metrics_enabled: bool,
+
+    #[serde(default = "default_services_metrics_timer_resolution")]
+    #[serde(with = "humantime_serde")]
+    pub metrics_timer_resolution: Duration,
+
+    #[serde(default = "default_max_builtin_metrics_storage_size")]
+    pub max_builtin_metrics_storage_size: usize,
+
+    #[serde(default = "default_tokio_metrics_enabled")]
+    pub tokio_metrics_enabled: bool,
+
+    #[serde(default = "default_tokio_metrics_poll_histogram_enabled")]
+    pub tokio_metrics_poll_histogram_enabled: bool,
+
+    /// Periodically push metrics to a gateway, for networks where scrapers can't reach the node
+    #[serde(default)]
+    pub push: Option<MetricsPushConfig>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct MetricsPushConfig {
+    /// Base URL of the Pushgateway, or the full URL of the endpoint in the `raw` mode
+    pub endpoint: String,
+    #[serde(default)]
+    pub mode: MetricsPushMode,
+    /// Pushgateway job, metrics are grouped by the job and the node peer id as the instance
+    #[serde(default = "default_metrics_push_job")]
+    pub job: String,
+    #[serde(default = "default_metrics_push_interval")]
+    #[serde(with = "humantime_serde")]
+    pub interval: Duration,
+    #[serde(default = "default_metrics_push_timeout")]
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    /// Credentials may refer to the secrets provider with the `secret:` prefix
+    #[serde(default)]
+    pub auth: Option<MetricsPushAuth>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsPushMode {
+    /// PUT to `{endpoint}/metrics/job/{job}/instance/{peer_id}`, replacing the previous push
+    #[default]
+    Pushgateway,
+    /// POST the OpenMetrics payload to the endpoint as is
+    Raw,
+}
+
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MetricsPushAuth {
+    Basic {
+        username: String,
+        #[derivative(Debug = "ignore")]
+        password: String,
+    },
+    Bearer {
+        #[derivative(Debug = "ignore")]
+        token: String,
+    },
+}
+
+impl MetricsPushAuth {
+    fn resolve(self, secrets: Option<&Secrets>) -> eyre::Result<Self> {
+        Ok(match self {
+            MetricsPushAuth::Basic { username, password } => MetricsPushAuth::Basic {
+                username,
+                password: Secrets::resolve_value(secrets, password)?,
+            },
+            MetricsPushAuth::Bearer { token } => MetricsPushAuth::Bearer {
+                token: Secrets::resolve_value(secrets, token)?,
+            },
+        })
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct HealthConfig {
+    #[serde(default = "default_health_check_enabled")]
+    pub health_check_enabled: bool,
+}
+
+#[derive(Clone, Deserialize, Serialize, Derivative)]
+#[derivative(Debug)]
+pub struct ListenConfig {
+    /// For TCP connections
+    #[serde(default = "default_tcp_port")]
+    pub tcp_port: u16,
+
+    /// Local ip address to listen on
+    #[serde(default = "default_listen_ip")]
+    pub listen_ip: IpAddr,
+
+    /// For ws connections
+    #[serde(default = "default_websocket_port")]
+    pub websocket_port: u16,
+
+    /// How long listeners on old ports keep accepting connections after the ports were changed
+    /// by a config reload
+    #[serde(with = "humantime_serde")]
+    #[serde(default = "default_listener_rebind_grace_period")]
+    pub rebind_grace_period: Duration,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, Copy, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[repr(transparent)]
+pub struct PeerIdSerializable(
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    PeerId,
+);
+
+impl Deref for PeerIdSerializable {
+    type Target = PeerId;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum PathOrValue {
+    Value { value: String },
+    Path { path: PathBuf },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct KeypairConfig {
+    #[serde(default = "default_key_format")]
+    pub format: String,
+    #[serde(flatten)]
+    #[serde(default)]
+    pub keypair: Option<PathOrValue>,
+    #[serde(default)]
+    pub secret_key: Option<String>,
+    /// Reference to a secret with the key in the configured secrets provider
+    #[serde(default)]
+    pub secret_ref: Option<String>,
+    #[serde(default)]
+    pub generate_on_absence: bool,
+}
+
+impl Default for KeypairConfig {
+    fn default() -> Self {
+        Self {
+            format: default_key_format(),
+            keypair: None,
+            secret_key: None,
+            secret_ref: None,
+            generate_on_absence: true,
+        }
+    }
+}
+
+impl KeypairConfig {
+    /// File the keypair is loaded from, if it isn't set by value or by a secret
+    pub fn key_path(&self, default: PathOrValue) -> Option<PathBuf> {
+        if self.secret_ref.is_some() || self.secret_key.is_some() {
+            return None;
+        }
+        match self.keypair.clone().unwrap_or(default) {
+            PathOrValue::Path { path } => Some(to_abs_path(path)),
+            PathOrValue::Value { .. } => None,
+        }
+    }
+
+    pub fn get_keypair(
+        self,
+        default: PathOrValue,
+        secrets: Option<&Secrets>,
+    ) -> Result<KeyPair, eyre::Report> {
+        use crate::node_config::PathOrValue::{Path, Value};
+
+        debug_assert!(
+            !(self.secret_key.is_some() && self.keypair.is_some()),
+            "shouldn't have both secret_key and keypair defined in KeypairConfig"
+        );
+
+        if let Some(secret_ref) = self.secret_ref {
+            let secrets = secrets.ok_or_else(|| {
+                eyre!("keypair refers to secret '{secret_ref}', but no secrets provider is configured")
+            })?;
+            return decode_key(secrets.get(&secret_ref)?, self.format)
+                .map_err(|e| eyre!("Failed to decode key from secret '{secret_ref}': {e}"));
+        }
 
         // first, try to load secret key
         if let Some(secret_key) = self.secret_key {
@@ -23,7 +23,7 @@ use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 
 use clap::{Args, Command, FromArgMatches};
-use config::{Config, Environment, File, FileFormat, FileSourceFile};
+use config::{Config, Environment, File, FileFormat, FileSourceFile, Map};
 use libp2p::core::{multiaddr::Protocol, Multiaddr};
 use serde::{Deserialize, Serialize};
 use url::Url;
@@ -151,9 +151,13 @@ pub struct ConfigData {
 ///  - Load and parse Config.toml from cwd, if exists
 ///  - Load and parse files provided by FLUENCE_CONFIG env var
 ///  - Load and parse files provided by --config arg
-///  - Load config values from env vars
+///  - Load config values from FLUENCE_ env vars, e.g. FLUENCE_TCP_PORT=7777
+///  - Load config values from NOX__ env vars, e.g. NOX__LISTEN__TCP_PORT=7777
 ///  - Load config values from args (throw error on conflicts with env vars)
 /// On each stage the values override the previous ones.
+/// Nested keys in env vars are separated by `__`, e.g. NOX__PROTOCOL_CONFIG__UPGRADE_TIMEOUT=60s.
+/// Sections flattened into the root of the config (listen, transport, metrics, health and http) may be named
+/// in NOX__ env vars too, so NOX__LISTEN__TCP_PORT and NOX__TCP_PORT set the same value.
 ///
 /// # Arguments
 ///
@@ -185,16 +189,8 @@ pub fn load_config_with_args(
         })
         .collect();
 
-    let env_source = Environment::with_prefix("FLUENCE")
-        .try_parsing(true)
-        .prefix_separator("_")
-        .separator("__")
-        .list_separator(",")
-        .with_list_parse_key("allowed_binaries")
-        .with_list_parse_key("external_multiaddresses")
-        .with_list_parse_key("bootstrap_nodes")
-        .with_list_parse_key("listen_config.listen_multiaddrs")
-        .with_list_parse_key("system_services.enable");
+    let fluence_env_source = env_source("FLUENCE", "_");
+    let nox_env_source = env_source("NOX", "__").source(Some(nox_env_vars(std::env::vars())));
 
     let env_config_sources: Vec<File<FileSourceFile, FileFormat>> =
         std::env::var_os("FLUENCE_CONFIG")
@@ -221,7 +217,10 @@ pub fn load_config_with_args(
     for source in arg_config_sources {
         config_builder = config_builder.add_source(source)
     }
-    config_builder = config_builder.add_source(env_source).add_source(arg_source);
+    config_builder = config_builder
+        .add_source(fluence_env_source)
+        .add_source(nox_env_source)
+        .add_source(arg_source);
     let config = config_builder.build()?;
 
     let config: UnresolvedConfig = config.try_deserialize()?;
@@ -229,6 +228,38 @@ pub fn load_config_with_args(
     Ok(config)
 }
 
+/// Sections of the node config which fields are kept in the root of the config
+const FLATTENED_ENV_SECTIONS: [&str; 5] = ["LISTEN", "TRANSPORT", "METRICS", "HEALTH", "HTTP"];
+
+/// Env vars with the names of the flattened sections dropped, e.g. NOX__LISTEN__TCP_PORT becomes NOX__TCP_PORT
+fn nox_env_vars(vars: impl Iterator<Item = (String, String)>) -> Map<String, String> {
+    vars.map(|(name, value)| {
+        let field = name.strip_prefix("NOX__").and_then(|key| {
+            FLATTENED_ENV_SECTIONS
+                .iter()
+                .find_map(|section| key.strip_prefix(section)?.strip_prefix("__"))
+        });
+        match field {
+            Some(field) => (format!("NOX__{field}"), value),
+            None => (name, value),
+        }
+    })
+    .collect()
+}
+
+fn env_source(prefix: &str, prefix_separator: &str) -> Environment {
+    Environment::with_prefix(prefix)
+        .try_parsing(true)
+        .prefix_separator(prefix_separator)
+        .separator("__")
+        .list_separator(",")
+        .with_list_parse_key("allowed_binaries")
+        .with_list_parse_key("external_multiaddresses")
+        .with_list_parse_key("bootstrap_nodes")
+        .with_list_parse_key("listen_config.listen_multiaddrs")
+        .with_list_parse_key("system_services.enable")
+}
+
 fn process_args(raw_args: Vec<OsString>, data: Option<ConfigData>) -> eyre::Result<DerivedArgs> {
     let command = Command::new("Fluence peer");
     let command = if let Some(data) = data {
@@ -303,6 +334,47 @@ mod tests {
         });
     }
 
+    #[test]
+    fn load_worker_egress_config() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [worker_egress_config]
+            default_allowed = ["*.example.com:443"]
+
+            [worker_egress_config.workers]
+            12D3KooWB9P1xmV3c7ZPpBemovbwCiRRTKd3Kq2jsVPQN4ZukDfy = ["10.0.0.1:8545", "rpc.internal"]
+        "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None).expect("Could not load config");
+            let egress = config.node_config.worker_egress_config;
+
+            let worker = "12D3KooWB9P1xmV3c7ZPpBemovbwCiRRTKd3Kq2jsVPQN4ZukDfy";
+            assert!(egress.allows(worker, "10.0.0.1", Some(8545)));
+            assert!(egress.allows(worker, "rpc.internal", Some(80)));
+            assert!(!egress.allows(worker, "10.0.0.1", Some(22)));
+            assert!(!egress.allows(worker, "api.example.com", Some(443)));
+
+            let other = "12D3KooWELdQw9pQVdq5NS6gEHsWMbYpLh3PjqFyNbivYWuATcik";
+            assert!(egress.allows(other, "api.example.com", Some(443)));
+            assert!(!egress.allows(other, "example.com", Some(443)));
+            assert!(!egress.allows(other, "api.example.com", Some(80)));
+
+            assert!(egress.allows_destination(worker, "http://10.0.0.1:8545/rpc"));
+            assert!(egress.allows_destination(worker, "10.0.0.1:8545"));
+            assert!(egress.allows_destination(worker, "rpc.internal:9092"));
+            assert!(!egress.allows_destination(worker, "nats://10.0.0.1:4222"));
+            assert!(!egress.allows_destination(worker, "10.0.0.1:22"));
+            assert!(egress.allows_destination(other, "https://api.example.com"));
+            assert!(!egress.allows_destination(other, "http://api.example.com"));
+        });
+    }
+
     fn encode_secret(config: &ResolvedConfig) -> String {
         match config.root_key_pair.clone() {
             KeyPair::Ed25519(x) => base64.encode(x.secret().0),
@@ -704,6 +776,78 @@ mod tests {
         });
     }
 
+    #[test]
+    fn load_http_port_with_nox_env() {
+        temp_env::with_vars(
+            [
+                ("FLUENCE_HTTP_PORT", Some("1234")),
+                ("NOX__HTTP_PORT", Some("4321")),
+            ],
+            || {
+                let config = load_config_with_args(vec![], None).expect("Could not load config");
+                assert_eq!(
+                    config.node_config.http_config.map(|x| x.http_port),
+                    Some(4321)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn load_nox_env_nested_over_file() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            tcp_port = 1111
+            [protocol_config]
+            upgrade_timeout = "10s"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_vars(
+            [
+                ("FLUENCE_CONFIG", Some(path.as_str())),
+                ("NOX__TCP_PORT", Some("7777")),
+                ("NOX__PROTOCOL_CONFIG__UPGRADE_TIMEOUT", Some("60s")),
+            ],
+            || {
+                let config = load_config_with_args(vec![], None).expect("Could not load config");
+                assert_eq!(config.node_config.listen_config.tcp_port, 7777);
+                assert_eq!(
+                    config.node_config.protocol_config.upgrade_timeout,
+                    Duration::from_secs(60)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn load_nox_env_flattened_section() {
+        temp_env::with_vars(
+            [
+                ("NOX__LISTEN__TCP_PORT", Some("7777")),
+                ("NOX__TRANSPORT__SOCKET_TIMEOUT", Some("30s")),
+                ("NOX__HTTP__HTTP_PORT", Some("4321")),
+            ],
+            || {
+                let config = load_config_with_args(vec![], None).expect("Could not load config");
+                assert_eq!(config.node_config.listen_config.tcp_port, 7777);
+                assert_eq!(
+                    config.node_config.transport_config.socket_timeout,
+                    Duration::from_secs(30)
+                );
+                assert_eq!(
+                    config.node_config.http_config.map(|x| x.http_port),
+                    Some(4321)
+                );
+            },
+        );
+    }
+
     #[test]
     fn load_env_upgrade_timeout() {
         temp_env::with_vars(
@@ -847,4 +991,59 @@ mod tests {
             assert_eq!(config.node_config.network, Network::Custom(expected));
         });
     }
+
+    #[test]
+    fn keypair_secret_requires_provider() {
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [root_key_pair]
+            secret_ref = "nox/root#key"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let err = load_config_with_args(vec![], None)
+                .err()
+                .expect("secret without a provider must fail");
+            assert!(format!("{err:?}").contains("no secrets provider is configured"));
+        });
+    }
+
+    #[test]
+    fn storage_data_key_is_not_serialized() {
+        let key = "0101010101010101010101010101010101010101010101010101010101010101";
+        let mut file = NamedTempFile::new().expect("Could not create temp file");
+        write!(
+            file,
+            r#"
+            [storage_encryption]
+            data_key = "{key}"
+            "#
+        )
+        .expect("Could not write in file");
+
+        let path = file.path().display().to_string();
+
+        temp_env::with_var("FLUENCE_CONFIG", Some(path), || {
+            let config = load_config_with_args(vec![], None)
+                .expect("Could not load config")
+                .resolve()
+                .expect("Could not resolve config");
+            let storage_encryption = config.storage_encryption.as_ref().unwrap();
+            assert_eq!(storage_encryption.data_key, key);
+            assert!(storage_encryption.previous_keys.is_empty());
+            assert_eq!(
+                storage_encryption.plaintext_dir,
+                crate::defaults::default_storage_plaintext_dir()
+            );
+
+            let serialized = toml::to_string(&config).unwrap();
+            assert!(!serialized.contains(key));
+        });
+    }
 }
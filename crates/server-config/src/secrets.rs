@@ -0,0 +1,369 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Secrets loaded from HashiCorp Vault or AWS Secrets Manager instead of plaintext files.
+//!
+//! A secret is referenced as `<name>#<field>`. For Vault, `name` is the path of a KV v2 secret
+//! and `field` defaults to `value`. For AWS, `name` is the secret id, and if `field` is set,
+//! the secret string is parsed as a JSON object and the field is taken from it.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use eyre::{eyre, WrapErr};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JValue};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::defaults::{default_secrets_cache_ttl, default_vault_mount, default_vault_token_env};
+
+/// Prefix of string config values that are references to secrets
+pub const SECRET_PREFIX: &str = "secret:";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum SecretsConfig {
+    Vault {
+        address: Url,
+        /// Environment variable with the Vault token
+        #[serde(default = "default_vault_token_env")]
+        token_env: String,
+        /// Mount path of the KV v2 secrets engine
+        #[serde(default = "default_vault_mount")]
+        mount: String,
+        /// How long a fetched secret is reused, e.g. by config reloads
+        #[serde(default = "default_secrets_cache_ttl")]
+        #[serde(with = "humantime_serde")]
+        cache_ttl: Duration,
+    },
+    /// Credentials are taken from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`
+    /// and optional `AWS_SESSION_TOKEN` on every fetch, so rotated credentials are picked up
+    AwsSecretsManager {
+        region: String,
+        /// Custom endpoint, e.g. a VPC endpoint
+        #[serde(default)]
+        endpoint: Option<Url>,
+        #[serde(default = "default_secrets_cache_ttl")]
+        #[serde(with = "humantime_serde")]
+        cache_ttl: Duration,
+    },
+}
+
+/// Fetched secrets by provider and reference, shared between config reloads
+fn cache() -> &'static Mutex<HashMap<String, (Instant, String)>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, (Instant, String)>>> = OnceLock::new();
+    CACHE.get_or_init(<_>::default)
+}
+
+#[derive(Clone, Debug)]
+pub struct Secrets {
+    config: SecretsConfig,
+}
+
+impl Secrets {
+    pub fn new(config: SecretsConfig) -> Self {
+        Self { config }
+    }
+
+    /// Fetches the secret, reusing a value fetched less than `cache_ttl` ago
+    pub fn get(&self, reference: &str) -> eyre::Result<String> {
+        let cache_key = format!("{}:{reference}", self.provider_id());
+        if let Some((fetched_at, value)) = cache().lock().expect("poisoned").get(&cache_key) {
+            if fetched_at.elapsed() < self.cache_ttl() {
+                return Ok(value.clone());
+            }
+        }
+
+        // Blocking HTTP client can't run on an async runtime thread, config reloads happen on one
+        let value = std::thread::scope(|s| {
+            s.spawn(|| self.fetch(reference))
+                .join()
+                .map_err(|_| eyre!("secret fetching thread panicked"))?
+        })
+        .wrap_err_with(|| format!("failed to fetch secret '{reference}'"))?;
+
+        cache()
+            .lock()
+            .expect("poisoned")
+            .insert(cache_key, (Instant::now(), value.clone()));
+        Ok(value)
+    }
+
+    /// Resolves `secret:<reference>` values, other values are returned as is
+    pub fn resolve_value(secrets: Option<&Secrets>, value: String) -> eyre::Result<String> {
+        match value.strip_prefix(SECRET_PREFIX) {
+            Some(reference) => secrets
+                .ok_or_else(|| {
+                    eyre!("'{value}' refers to a secret, but no secrets provider is configured")
+                })?
+                .get(reference),
+            None => Ok(value),
+        }
+    }
+
+    fn provider_id(&self) -> String {
+        match &self.config {
+            SecretsConfig::Vault { address, mount, .. } => format!("vault:{address}{mount}"),
+            SecretsConfig::AwsSecretsManager { region, .. } => format!("aws:{region}"),
+        }
+    }
+
+    fn cache_ttl(&self) -> Duration {
+        match &self.config {
+            SecretsConfig::Vault { cache_ttl, .. }
+            | SecretsConfig::AwsSecretsManager { cache_ttl, .. } => *cache_ttl,
+        }
+    }
+
+    fn fetch(&self, reference: &str) -> eyre::Result<String> {
+        let (name, field) = match reference.split_once('#') {
+            Some((name, field)) => (name, Some(field)),
+            None => (reference, None),
+        };
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+
+        match &self.config {
+            SecretsConfig::Vault {
+                address,
+                token_env,
+                mount,
+                ..
+            } => {
+                let token = std::env::var(token_env)
+                    .map_err(|_| eyre!("Vault token env variable {token_env} is not set"))?;
+                renew_vault_token(&client, address, &token);
+
+                let url = address.join(&format!("v1/{mount}/data/{name}"))?;
+                let response: JValue = client
+                    .get(url)
+                    .header("X-Vault-Token", token)
+                    .send()?
+                    .error_for_status()?
+                    .json()?;
+                let field = field.unwrap_or("value");
+                response["data"]["data"][field]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| eyre!("Vault secret {name} has no string field '{field}'"))
+            }
+            SecretsConfig::AwsSecretsManager {
+                region, endpoint, ..
+            } => {
+                let credentials = AwsCredentials::from_env()?;
+                let endpoint = match endpoint {
+                    Some(endpoint) => endpoint.clone(),
+                    None => Url::parse(&format!("https://secretsmanager.{region}.amazonaws.com/"))?,
+                };
+                let host = endpoint
+                    .host_str()
+                    .ok_or_else(|| eyre!("AWS endpoint {endpoint} has no host"))?
+                    .to_string();
+                let body = json!({ "SecretId": name }).to_string();
+                let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+                let authorization =
+                    credentials.authorization(region, &host, &amz_date, body.as_bytes());
+
+                let mut request = client
+                    .post(endpoint)
+                    .header("Content-Type", AWS_CONTENT_TYPE)
+                    .header("X-Amz-Date", amz_date)
+                    .header("X-Amz-Target", AWS_TARGET)
+                    .header("Authorization", authorization);
+                if let Some(token) = &credentials.session_token {
+                    request = request.header("X-Amz-Security-Token", token);
+                }
+                let response: JValue = request.body(body).send()?.error_for_status()?.json()?;
+                let secret = response["SecretString"]
+                    .as_str()
+                    .ok_or_else(|| eyre!("AWS secret {name} has no SecretString"))?;
+
+                match field {
+                    None => Ok(secret.to_string()),
+                    Some(field) => serde_json::from_str::<JValue>(secret)?[field]
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| eyre!("AWS secret {name} has no string field '{field}'")),
+                }
+            }
+        }
+    }
+}
+
+/// Extends the token lease, so a periodic token doesn't expire while the node is running.
+/// Root and non-renewable tokens can't be renewed, that's fine as long as they are valid.
+fn renew_vault_token(client: &reqwest::blocking::Client, address: &Url, token: &str) {
+    let result: eyre::Result<()> = try {
+        let url = address.join("v1/auth/token/renew-self")?;
+        client
+            .post(url)
+            .header("X-Vault-Token", token)
+            .send()?
+            .error_for_status()?;
+    };
+    if let Err(err) = result {
+        log::debug!("Vault token wasn't renewed: {err}");
+    }
+}
+
+const AWS_SERVICE: &str = "secretsmanager";
+const AWS_TARGET: &str = "secretsmanager.GetSecretValue";
+const AWS_CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+struct AwsCredentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    fn from_env() -> eyre::Result<Self> {
+        let var = |name: &str| std::env::var(name).map_err(|_| eyre!("{name} is not set"));
+        Ok(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    /// Signature Version 4 `Authorization` header of a GetSecretValue request
+    fn authorization(&self, region: &str, host: &str, amz_date: &str, body: &[u8]) -> String {
+        let date = &amz_date[..8];
+        let mut headers = vec![
+            ("content-type", AWS_CONTENT_TYPE),
+            ("host", host),
+            ("x-amz-date", amz_date),
+            ("x-amz-target", AWS_TARGET),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.as_str()));
+        }
+        headers.sort_unstable();
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{name}:{value}\n"))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+            hex::encode(Sha256::digest(body))
+        );
+        let scope = format!("{date}/{region}/{AWS_SERVICE}/aws4_request");
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = aws_signing_key(&self.secret_access_key, date, region, AWS_SERVICE);
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn aws_signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let k_date = hmac_sha256(
+        format!("AWS4{secret_access_key}").as_bytes(),
+        date.as_bytes(),
+    );
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signing_key_matches_aws_example() {
+        let key = aws_signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn authorization_signs_session_token() {
+        let mut credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+        };
+        let host = "secretsmanager.eu-west-1.amazonaws.com";
+        let plain = credentials.authorization("eu-west-1", host, "20240102T030405Z", b"{}");
+        assert!(plain.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20240102/eu-west-1/secretsmanager/aws4_request, SignedHeaders=content-type;host;x-amz-date;x-amz-target, Signature="
+        ));
+
+        credentials.session_token = Some("token".to_string());
+        let with_token = credentials.authorization("eu-west-1", host, "20240102T030405Z", b"{}");
+        assert!(with_token.contains("x-amz-date;x-amz-security-token;x-amz-target"));
+    }
+
+    #[test]
+    fn plain_values_are_not_resolved() {
+        let value = Secrets::resolve_value(None, "0xabc".to_string()).unwrap();
+        assert_eq!(value, "0xabc");
+        assert!(Secrets::resolve_value(None, "secret:wallet#key".to_string()).is_err());
+    }
+
+    #[test]
+    fn config_is_tagged_by_provider() {
+        let config: SecretsConfig = toml::from_str(
+            r#"
+            provider = "vault"
+            address = "https://vault.local:8200/"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            SecretsConfig::Vault {
+                address: Url::parse("https://vault.local:8200/").unwrap(),
+                token_env: "VAULT_TOKEN".to_string(),
+                mount: "secret".to_string(),
+                cache_ttl: Duration::from_secs(300),
+            }
+        );
+    }
+}
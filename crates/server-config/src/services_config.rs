@@ -23,4 +23,8 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ServicesConfig {
     pub wasm_backend: WasmBackendConfig,
+    /// Engine for new services during an upgrade window.
+    /// Services created before keep the `wasm_backend` engine until they are migrated.
+    #[serde(default)]
+    pub wasm_backend_next: Option<WasmBackendConfig>,
 }
@@ -17,15 +17,24 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use connection_pool::LifecycleEvent;
+use connection_pool::{ConnectionEvent, Direction, LifecycleEvent};
 use fluence_libp2p::PeerId;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
+use serde_json::Value as JValue;
+use std::collections::{HashMap, HashSet};
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::{mpsc, oneshot};
 use types::peer_id;
 
 pub use crate::config::*;
+pub use crate::cron::*;
+pub use crate::source::*;
 
 pub type SpellId = String;
 
@@ -41,6 +50,20 @@ pub enum TriggerInfo {
     Timer(TimerEvent),
     /// Event is triggered by a peer event.
     Peer(PeerEvent),
+    /// Event is triggered by a node resource going below its threshold.
+    Resource(ResourceEvent),
+    /// Event is triggered by a change of a watched KV key of another spell.
+    KvChange(KvChangeEvent),
+    /// Event is triggered by an authenticated HTTP request to the spell webhook.
+    Webhook(WebhookEvent),
+    /// Event is triggered by a must-reach target becoming unreachable or reachable again.
+    Probe(ProbeEvent),
+    /// Event is triggered by a service failing or passing its health check, or being restarted.
+    Health(HealthEvent),
+    /// Event is triggered by the node suspecting a network partition or recovering from it.
+    Partition(PartitionEvent),
+    /// Event is triggered by a trigger source added by an embedder of the node.
+    Custom(CustomEvent),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -57,23 +80,91 @@ pub struct PeerEvent {
     )]
     pub peer_id: PeerId,
     pub connected: bool,
+    /// Details available to payload templates, see [`PeerPayloadTemplate`]
+    #[serde(skip)]
+    pub details: PeerEventDetails,
+    /// Payload rendered by the template of the subscription, written to the mailbox as is
+    #[serde(skip)]
+    pub payload: Option<JValue>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PeerEventDetails {
+    /// Direction of the connection that was established or closed last
+    pub direction: Option<Direction>,
+    /// Protocols the peer reported via identify, usually not known yet on the first connection
+    pub protocols: Vec<String>,
+    /// Unix time in seconds
+    pub timestamp: u64,
 }
 
 impl From<LifecycleEvent> for PeerEvent {
     fn from(e: LifecycleEvent) -> Self {
-        match e {
-            LifecycleEvent::Connected(c) => Self {
-                peer_id: c.peer_id,
-                connected: true,
-            },
-            LifecycleEvent::Disconnected(c) => Self {
-                peer_id: c.peer_id,
-                connected: false,
+        let (peer_id, connected) = match e {
+            LifecycleEvent::Connected(c) => (c.peer_id, true),
+            LifecycleEvent::Disconnected(c) => (c.peer_id, false),
+        };
+        Self {
+            peer_id,
+            connected,
+            details: PeerEventDetails {
+                timestamp: now_millis::now_sec(),
+                ..<_>::default()
             },
+            payload: None,
         }
     }
 }
 
+/// Peer events with connection details. A peer is connected on each established connection
+/// and disconnected when its last connection is closed, like in [`LifecycleEvent`].
+pub fn peer_events(
+    connection_events: BoxStream<'static, ConnectionEvent>,
+) -> BoxStream<'static, PeerEvent> {
+    let mut protocols: HashMap<PeerId, Vec<String>> = HashMap::new();
+    connection_events
+        .filter_map(move |event| {
+            let event = match event {
+                ConnectionEvent::Established {
+                    peer_id, direction, ..
+                } => Some((peer_id, true, direction)),
+                ConnectionEvent::Closed {
+                    peer_id,
+                    direction,
+                    remaining_established: 0,
+                    ..
+                } => Some((peer_id, false, direction)),
+                ConnectionEvent::Closed { .. } => None,
+                ConnectionEvent::Identified {
+                    peer_id,
+                    protocols: reported,
+                } => {
+                    protocols.insert(peer_id, reported);
+                    None
+                }
+            };
+            let event = event.map(|(peer_id, connected, direction)| {
+                let known = if connected {
+                    protocols.get(&peer_id).cloned()
+                } else {
+                    protocols.remove(&peer_id)
+                };
+                PeerEvent {
+                    peer_id,
+                    connected,
+                    details: PeerEventDetails {
+                        direction: Some(direction),
+                        protocols: known.unwrap_or_default(),
+                        timestamp: now_millis::now_sec(),
+                    },
+                    payload: None,
+                }
+            });
+            futures::future::ready(event)
+        })
+        .boxed()
+}
+
 impl PeerEvent {
     pub(crate) fn get_type(&self) -> PeerEventType {
         if self.connected {
@@ -91,12 +182,206 @@ pub enum PeerEventType {
     Disconnected,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Event is triggered when a node resource goes below its threshold
+pub struct ResourceEvent {
+    pub resource: ResourceEventType,
+    /// Available amount in bytes
+    pub available: u64,
+    /// Total amount in bytes
+    pub total: u64,
+}
+
+/// Node resources which pressure spells can subscribe to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceEventType {
+    /// Free space on the disk with services
+    Disk,
+    /// Available memory
+    Memory,
+}
+
+/// KV key where a spell script may store the minimal interval (in seconds) until its next timer run.
+/// The bus applies the hint as soon as it's written, writing 0 resets the timer back to its period.
+pub const BACKOFF_HINT_KEY: &str = "backoff_hint_sec";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// A string written to the KV of a spell. Triggers spells watching the key only if the value differs
+/// from the previous one seen by the bus.
+pub struct KvChangeEvent {
+    pub spell_id: SpellId,
+    pub key: String,
+    pub value: String,
+}
+
+impl KvChangeEvent {
+    pub(crate) fn watch(&self) -> KvWatch {
+        KvWatch {
+            spell_id: self.spell_id.clone(),
+            key: self.key.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Request to `/spells/:spell_id/trigger` of the node HTTP endpoint
+pub struct WebhookEvent {
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+    /// Request body
+    pub payload: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Event is triggered when a probed must-reach target changes its reachability
+pub struct ProbeEvent {
+    /// Address of the target, e.g. `peer:12D3KooW...`
+    pub target: String,
+    pub reachable: bool,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+impl ProbeEvent {
+    pub(crate) fn get_type(&self) -> ProbeEventType {
+        if self.reachable {
+            ProbeEventType::Reachable
+        } else {
+            ProbeEventType::Unreachable
+        }
+    }
+}
+
+/// Probe results which spells can subscribe to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeEventType {
+    Reachable,
+    Unreachable,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Event is triggered when a service declaring a health function changes its health
+pub struct HealthEvent {
+    pub service_id: String,
+    /// Peer id of the worker the service is deployed on, or of the host
+    pub worker_id: String,
+    pub status: HealthEventType,
+    /// Error of the failed health check, empty if the check succeeded
+    pub error: String,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+/// Service health changes which spells can subscribe to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthEventType {
+    Unhealthy,
+    Healthy,
+    Restarted,
+    /// The service keeps failing after the maximum number of restarts
+    Exhausted,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Event is triggered when too many long-known peers become unreachable at once, or enough of them
+/// are reachable again
+pub struct PartitionEvent {
+    pub status: PartitionEventType,
+    /// Number of long-known peers which are currently unreachable
+    pub unreachable: u32,
+    /// Number of long-known peers
+    pub known: u32,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+/// Network partition changes which spells can subscribe to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionEventType {
+    /// Possible partition: the fraction of unreachable long-known peers is above the threshold
+    Partitioned,
+    Healed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Event of a [`TriggerSource`] added by an embedder of the node
+pub struct CustomEvent {
+    /// Name of the source
+    pub source: String,
+    pub kind: String,
+    pub payload: String,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+impl CustomEvent {
+    pub(crate) fn new(source: String, event: SourceEvent) -> Self {
+        Self {
+            source,
+            kind: event.kind,
+            payload: event.payload,
+            timestamp: now_millis::now_sec(),
+        }
+    }
+
+    pub(crate) fn get_type(&self) -> CustomEventType {
+        CustomEventType {
+            source: self.source.clone(),
+            kind: self.kind.clone(),
+        }
+    }
+}
+
+/// Kind of events of a custom trigger source which spells can subscribe to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct CustomEventType {
+    pub source: String,
+    pub kind: String,
+}
+
+/// Hash under which the webhook token of a spell is kept, so the token itself isn't stored anywhere
+pub fn webhook_token_hash(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+/// KV key of a spell which other spells can subscribe to.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
+pub struct KvWatch {
+    pub spell_id: SpellId,
+    pub key: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TriggerInfoAqua {
     // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
     timer: Vec<TimerEvent>,
     // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
     peer: Vec<PeerEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    resource: Vec<ResourceEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    kv_change: Vec<KvChangeEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    webhook: Vec<WebhookEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    probe: Vec<ProbeEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    health: Vec<HealthEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    partition: Vec<PartitionEvent>,
+    // Vec is a representation for Aqua optional values. This Vec always holds at most 1 element.
+    #[serde(default)]
+    custom: Vec<CustomEvent>,
 }
 
 impl From<TriggerInfo> for TriggerInfoAqua {
@@ -105,10 +390,101 @@ impl From<TriggerInfo> for TriggerInfoAqua {
             TriggerInfo::Timer(t) => Self {
                 timer: vec![t],
                 peer: vec![], // Empty Vec corresponds to Aqua nil
+                resource: vec![],
+                kv_change: vec![],
+                webhook: vec![],
+                probe: vec![],
+                health: vec![],
+                partition: vec![],
+                custom: vec![],
             },
             TriggerInfo::Peer(p) => Self {
                 timer: vec![], // Empty Vec corresponds to Aqua nil
                 peer: vec![p],
+                resource: vec![],
+                kv_change: vec![],
+                webhook: vec![],
+                probe: vec![],
+                health: vec![],
+                partition: vec![],
+                custom: vec![],
+            },
+            TriggerInfo::Resource(r) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                resource: vec![r],
+                kv_change: vec![],
+                webhook: vec![],
+                probe: vec![],
+                health: vec![],
+                partition: vec![],
+                custom: vec![],
+            },
+            TriggerInfo::KvChange(k) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                resource: vec![],
+                kv_change: vec![k],
+                webhook: vec![],
+                probe: vec![],
+                health: vec![],
+                partition: vec![],
+                custom: vec![],
+            },
+            TriggerInfo::Webhook(w) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                resource: vec![],
+                kv_change: vec![],
+                webhook: vec![w],
+                probe: vec![],
+                health: vec![],
+                partition: vec![],
+                custom: vec![],
+            },
+            TriggerInfo::Probe(p) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                resource: vec![],
+                kv_change: vec![],
+                webhook: vec![],
+                probe: vec![p],
+                health: vec![],
+                partition: vec![],
+                custom: vec![],
+            },
+            TriggerInfo::Health(h) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                resource: vec![],
+                kv_change: vec![],
+                webhook: vec![],
+                probe: vec![],
+                health: vec![h],
+                partition: vec![],
+                custom: vec![],
+            },
+            TriggerInfo::Partition(p) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                resource: vec![],
+                kv_change: vec![],
+                webhook: vec![],
+                probe: vec![],
+                health: vec![],
+                partition: vec![p],
+                custom: vec![],
+            },
+            TriggerInfo::Custom(c) => Self {
+                timer: vec![], // Empty Vec corresponds to Aqua nil
+                peer: vec![],
+                resource: vec![],
+                kv_change: vec![],
+                webhook: vec![],
+                probe: vec![],
+                health: vec![],
+                partition: vec![],
+                custom: vec![c],
             },
         }
     }
@@ -116,10 +492,29 @@ impl From<TriggerInfo> for TriggerInfoAqua {
 
 impl From<TriggerInfoAqua> for TriggerInfo {
     fn from(i: TriggerInfoAqua) -> Self {
-        match (i.timer.first(), i.peer.first()) {
-            (Some(t), None) => Self::Timer(t.clone()),
-            (None, Some(p)) => Self::Peer(p.clone()),
-            _ => unreachable!("TriggerInfoAqua should always have either timer or peer event"),
+        match (
+            i.timer.first(),
+            i.peer.first(),
+            i.resource.first(),
+            i.kv_change.first(),
+            i.webhook.first(),
+            i.probe.first(),
+            i.health.first(),
+            i.partition.first(),
+            i.custom.first(),
+        ) {
+            (Some(t), None, None, None, None, None, None, None, None) => Self::Timer(t.clone()),
+            (None, Some(p), None, None, None, None, None, None, None) => Self::Peer(p.clone()),
+            (None, None, Some(r), None, None, None, None, None, None) => Self::Resource(r.clone()),
+            (None, None, None, Some(k), None, None, None, None, None) => Self::KvChange(k.clone()),
+            (None, None, None, None, Some(w), None, None, None, None) => Self::Webhook(w.clone()),
+            (None, None, None, None, None, Some(p), None, None, None) => Self::Probe(p.clone()),
+            (None, None, None, None, None, None, Some(h), None, None) => Self::Health(h.clone()),
+            (None, None, None, None, None, None, None, Some(p), None) => Self::Partition(p.clone()),
+            (None, None, None, None, None, None, None, None, Some(c)) => Self::Custom(c.clone()),
+            _ => unreachable!(
+                "TriggerInfoAqua should always have exactly one of timer, peer, resource, kv_change, webhook, probe, health, partition or custom event"
+            ),
         }
     }
 }
@@ -127,7 +522,8 @@ impl From<TriggerInfoAqua> for TriggerInfo {
 #[derive(Debug)]
 pub(crate) struct Command {
     pub(crate) action: Action,
-    pub(crate) reply: oneshot::Sender<()>,
+    /// Whether the action took effect, only webhooks can be rejected
+    pub(crate) reply: oneshot::Sender<bool>,
 }
 
 #[derive(Debug, Clone)]
@@ -136,6 +532,20 @@ pub enum Action {
     Subscribe(SpellId, SpellTriggerConfigs),
     /// Remove all subscriptions of a spell
     Unsubscribe(SpellId),
+    /// Set the backoff hint for the timer of a spell
+    SetBackoff(SpellId, Duration),
+    /// Set the quarantine level of a spell failing in a row
+    SetQuarantine {
+        spell_id: SpellId,
+        level: u32,
+        max_backoff: Duration,
+    },
+    /// Trigger the spell if it has a webhook with the token of this hash
+    Webhook {
+        spell_id: SpellId,
+        token_hash: String,
+        event: WebhookEvent,
+    },
     /// Actually start the scheduling
     Start,
 }
@@ -154,6 +564,8 @@ pub enum EventBusError {
 #[derive(Clone)]
 pub struct SpellEventBusApi {
     pub(crate) send_cmd_channel: mpsc::UnboundedSender<Command>,
+    /// Names of the custom trigger sources added to the bus
+    pub(crate) trigger_sources: Arc<RwLock<HashSet<String>>>,
 }
 
 impl std::fmt::Debug for SpellEventBusApi {
@@ -163,7 +575,7 @@ impl std::fmt::Debug for SpellEventBusApi {
 }
 
 impl SpellEventBusApi {
-    async fn send(&self, action: Action) -> Result<(), EventBusError> {
+    async fn send(&self, action: Action) -> Result<bool, EventBusError> {
         let (send, recv) = oneshot::channel();
         let command = Command {
             action: action.clone(),
@@ -176,8 +588,12 @@ impl SpellEventBusApi {
                 reason: Box::pin(e),
             })?;
 
-        recv.await.map_err(|_| EventBusError::ReplyError(action))?;
-        Ok(())
+        recv.await.map_err(|_| EventBusError::ReplyError(action))
+    }
+
+    /// Whether the bus still accepts commands, i.e. its task hasn't stopped
+    pub fn is_alive(&self) -> bool {
+        !self.send_cmd_channel.is_closed()
     }
 
     /// Subscribe a spell to a list of events
@@ -188,15 +604,73 @@ impl SpellEventBusApi {
         spell_id: SpellId,
         config: SpellTriggerConfigs,
     ) -> Result<(), EventBusError> {
-        self.send(Action::Subscribe(spell_id, config)).await
+        self.send(Action::Subscribe(spell_id, config)).await?;
+        Ok(())
     }
 
     /// Unsubscribe a spell from all events.
     pub async fn unsubscribe(&self, spell_id: SpellId) -> Result<(), EventBusError> {
-        self.send(Action::Unsubscribe(spell_id)).await
+        self.send(Action::Unsubscribe(spell_id)).await?;
+        Ok(())
+    }
+
+    /// Set the minimal interval between timer runs of a spell.
+    /// The next run is computed from the previous one as `max(period, backoff)`,
+    /// so the spell can slow itself down, but never run more often than its period.
+    /// `Duration::ZERO` resets the spell back to its configured period.
+    pub async fn set_backoff(
+        &self,
+        spell_id: SpellId,
+        backoff: Duration,
+    ) -> Result<(), EventBusError> {
+        self.send(Action::SetBackoff(spell_id, backoff)).await?;
+        Ok(())
+    }
+
+    /// Back off the timer of a spell failing in a row: on each level its period is doubled,
+    /// up to `max_backoff`. Level 0 takes the spell out of the quarantine.
+    pub async fn set_quarantine(
+        &self,
+        spell_id: SpellId,
+        level: u32,
+        max_backoff: Duration,
+    ) -> Result<(), EventBusError> {
+        self.send(Action::SetQuarantine {
+            spell_id,
+            level,
+            max_backoff,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Trigger the spell with a webhook event. Returns false if the spell has no webhook,
+    /// the token doesn't match or the scheduling hasn't started yet.
+    pub async fn trigger_webhook(
+        &self,
+        spell_id: SpellId,
+        token: &str,
+        payload: String,
+    ) -> Result<bool, EventBusError> {
+        let event = WebhookEvent {
+            timestamp: now_millis::now_sec(),
+            payload,
+        };
+        self.send(Action::Webhook {
+            spell_id,
+            token_hash: webhook_token_hash(token),
+            event,
+        })
+        .await
+    }
+
+    /// Whether a custom trigger source with this name was added to the bus
+    pub fn has_trigger_source(&self, name: &str) -> bool {
+        self.trigger_sources.read().contains(name)
     }
 
     pub async fn start_scheduling(&self) -> Result<(), EventBusError> {
-        self.send(Action::Start).await
+        self.send(Action::Start).await?;
+        Ok(())
     }
 }
@@ -18,13 +18,16 @@
  */
 
 use crate::api::*;
-use crate::config::{SpellTriggerConfigs, TriggerConfig};
+use crate::config::{Exclusion, PeerPayloadTemplate, SpellTriggerConfigs, TriggerConfig};
 use futures::stream::BoxStream;
 use futures::StreamExt;
 use futures::{future, FutureExt};
+use parking_lot::RwLock;
 use peer_metrics::SpellMetrics;
 use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
@@ -34,18 +37,18 @@ use tokio::sync::mpsc;
 use tokio::task;
 use tracing::Instrument;
 
-struct PeerEventSubscribers {
-    subscribers: HashMap<PeerEventType, Vec<Arc<SpellId>>>,
+struct EventSubscribers<E> {
+    subscribers: HashMap<E, Vec<Arc<SpellId>>>,
 }
 
-impl PeerEventSubscribers {
+impl<E: Hash + Eq> EventSubscribers<E> {
     fn new() -> Self {
         Self {
             subscribers: HashMap::new(),
         }
     }
 
-    fn add(&mut self, spell_id: Arc<SpellId>, event_types: Vec<PeerEventType>) {
+    fn add(&mut self, spell_id: Arc<SpellId>, event_types: Vec<E>) {
         for event_type in event_types {
             self.subscribers
                 .entry(event_type)
@@ -54,7 +57,7 @@ impl PeerEventSubscribers {
         }
     }
 
-    fn get(&self, event_type: &PeerEventType) -> impl Iterator<Item = &Arc<SpellId>> {
+    fn get(&self, event_type: &E) -> impl Iterator<Item = &Arc<SpellId>> {
         self.subscribers
             .get(event_type)
             .map(|x| x.iter())
@@ -74,6 +77,59 @@ struct Periodic {
     id: Arc<SpellId>,
     period: Duration,
     end_at: Option<Instant>,
+    /// Interval hint reported by the spell itself, see [`SpellEventBusApi::set_backoff`].
+    backoff: Duration,
+    /// Interval of the spell failing in a row, see [`SpellEventBusApi::set_quarantine`].
+    quarantine: Duration,
+    /// Wall-clock schedule of the spell, then `period` is only the interval between its first runs
+    cron: Option<CronSchedule>,
+}
+
+impl Periodic {
+    /// The backoff hint and the quarantine can only make the spell run less often than its configured period.
+    fn interval(&self) -> Duration {
+        self.period.max(self.backoff).max(self.quarantine)
+    }
+
+    /// The run following the one at `after`, `None` on overflow
+    fn next_run(&self, after: Instant) -> Option<Instant> {
+        match &self.cron {
+            None => after.checked_add(self.interval()),
+            // Cron runs falling into the backoff or the quarantine are skipped
+            Some(cron) => {
+                next_cron_run(cron, after.checked_add(self.backoff.max(self.quarantine))?)
+            }
+        }
+    }
+}
+
+/// The first run by the cron schedule strictly after `after`
+fn next_cron_run(cron: &CronSchedule, after: Instant) -> Option<Instant> {
+    let now = Instant::now();
+    let wall_now = SystemTime::now();
+    // Rounded to the nearest second, so a timer woken up slightly before
+    // the scheduled second doesn't run the spell twice
+    let after =
+        wall_now.checked_add(after.saturating_duration_since(now) + Duration::from_millis(500))?;
+    let after = after.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let next = UNIX_EPOCH.checked_add(Duration::from_secs(cron.next_after(after)?))?;
+    now.checked_add(next.duration_since(wall_now).unwrap_or_default())
+}
+
+/// Backoff hint written by a spell to its KV, anything but a number of seconds resets it
+fn backoff_hint(value: &str) -> Duration {
+    let hint = value.trim().parse::<u32>().unwrap_or(0).min(MAX_PERIOD_SEC);
+    Duration::from_secs(hint as u64)
+}
+
+/// The period doubled on each quarantine level, but not longer than `max_backoff`
+fn quarantine_interval(period: Duration, level: u32, max_backoff: Duration) -> Duration {
+    if level == 0 {
+        return Duration::ZERO;
+    }
+    period
+        .saturating_mul(1u32 << level.min(31))
+        .min(max_backoff.max(period))
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -81,23 +137,47 @@ struct Scheduled {
     data: Periodic,
     /// the time after which we need to notify the subscriber
     run_at: Instant,
+    /// the time of the previous run, `None` if the spell hasn't been run yet
+    last_run: Option<Instant>,
+    /// a run missed during a downtime, it's made once and isn't rescheduled
+    missed: bool,
 }
 
 impl Scheduled {
     fn new(data: Periodic, run_at: Instant) -> Self {
-        Self { data, run_at }
+        Self {
+            data,
+            run_at,
+            last_run: None,
+            missed: false,
+        }
+    }
+
+    fn missed(data: Periodic, run_at: Instant) -> Self {
+        Self {
+            data,
+            run_at,
+            last_run: None,
+            missed: true,
+        }
     }
 
-    /// Reschedule a spell to `now` + `period`.
+    /// Reschedule a spell to `now` + `period` (or the backoff hint if it's longer),
+    /// or to its next run by the cron schedule.
     /// Return `None` if the spell is supposed to end at the given time `end_at`.
     fn at(data: Periodic, now: Instant) -> Option<Scheduled> {
-        // We do checked_add here only to avoid a mere possibility of internal panic.
-        let run_at = now.checked_add(data.period)?;
+        // `next_run` does checked_add only to avoid a mere possibility of internal panic.
+        let run_at = data.next_run(now)?;
         if data.end_at.map(|end_at| end_at <= run_at).unwrap_or(false) {
             return None;
         }
 
-        Some(Scheduled { data, run_at })
+        Some(Scheduled {
+            data,
+            run_at,
+            last_run: Some(now),
+            missed: false,
+        })
     }
 }
 
@@ -115,7 +195,23 @@ impl PartialOrd for Scheduled {
 }
 
 struct SubscribersState {
-    subscribers: PeerEventSubscribers,
+    subscribers: EventSubscribers<PeerEventType>,
+    resource_subscribers: EventSubscribers<ResourceEventType>,
+    probe_subscribers: EventSubscribers<ProbeEventType>,
+    health_subscribers: EventSubscribers<HealthEventType>,
+    partition_subscribers: EventSubscribers<PartitionEventType>,
+    custom_subscribers: EventSubscribers<CustomEventType>,
+    kv_subscribers: EventSubscribers<KvWatch>,
+    /// Hashes of the last seen values of the watched KV keys
+    kv_hashes: HashMap<KvWatch, u64>,
+    /// Hashes of the webhook tokens by spell
+    webhook_tokens: HashMap<SpellId, String>,
+    /// Windows when spells aren't triggered
+    exclusions: HashMap<SpellId, Vec<Exclusion>>,
+    /// Shapes of the peer event triggers by spell
+    peer_payloads: HashMap<SpellId, PeerPayloadTemplate>,
+    /// Backoff hints of spells, kept over resubscriptions
+    backoffs: HashMap<SpellId, Duration>,
     scheduled: BinaryHeap<Scheduled>,
     active: HashSet<Arc<SpellId>>,
 }
@@ -123,31 +219,111 @@ struct SubscribersState {
 impl SubscribersState {
     fn new() -> Self {
         Self {
-            subscribers: PeerEventSubscribers::new(),
+            subscribers: EventSubscribers::new(),
+            resource_subscribers: EventSubscribers::new(),
+            probe_subscribers: EventSubscribers::new(),
+            health_subscribers: EventSubscribers::new(),
+            partition_subscribers: EventSubscribers::new(),
+            custom_subscribers: EventSubscribers::new(),
+            kv_subscribers: EventSubscribers::new(),
+            kv_hashes: HashMap::new(),
+            webhook_tokens: HashMap::new(),
+            exclusions: HashMap::new(),
+            peer_payloads: HashMap::new(),
+            backoffs: HashMap::new(),
             scheduled: BinaryHeap::new(),
             active: HashSet::new(),
         }
     }
 
     fn subscribe(&mut self, spell_id: SpellId, config: &SpellTriggerConfigs) {
+        let backoff = self.backoffs.get(&spell_id).copied().unwrap_or_default();
         let spell_id = Arc::new(spell_id);
         for config in &config.triggers {
             match config {
                 TriggerConfig::Timer(config) => {
-                    let periodic = Periodic {
+                    let periodic = || Periodic {
                         id: spell_id.clone(),
                         period: config.period,
                         end_at: config.end_at,
+                        backoff,
+                        quarantine: Duration::ZERO,
+                        cron: None,
                     };
-                    let scheduled = Scheduled::new(periodic, config.start_at);
+                    let now = Instant::now();
+                    for _ in 0..config.missed_runs {
+                        self.scheduled.push(Scheduled::missed(periodic(), now));
+                    }
+                    let scheduled = Scheduled::new(periodic(), config.start_at);
                     self.scheduled.push(scheduled);
                 }
+                TriggerConfig::Cron(config) => {
+                    let now = Instant::now();
+                    let schedule = &config.schedule;
+                    let first = schedule.next_after(now_millis::now_sec());
+                    let second = first.and_then(|first| schedule.next_after(first));
+                    let first_run = next_cron_run(schedule, now);
+                    let (Some(first), Some(second), Some(first_run)) = (first, second, first_run)
+                    else {
+                        log::warn!("Cron trigger of {spell_id} has no runs ahead, skipping it");
+                        continue;
+                    };
+                    let periodic = || Periodic {
+                        id: spell_id.clone(),
+                        // The quarantine is counted in the intervals between runs
+                        period: Duration::from_secs(second - first),
+                        end_at: None,
+                        backoff,
+                        quarantine: Duration::ZERO,
+                        cron: Some(config.schedule.clone()),
+                    };
+                    for _ in 0..config.missed_runs {
+                        self.scheduled.push(Scheduled::missed(periodic(), now));
+                    }
+                    self.scheduled.push(Scheduled::new(periodic(), first_run));
+                }
                 TriggerConfig::PeerEvent(config) => {
                     self.subscribers
                         .add(spell_id.clone(), config.events.clone());
+                    if let Some(payload) = &config.payload {
+                        self.peer_payloads
+                            .insert((*spell_id).clone(), payload.clone());
+                    }
+                }
+                TriggerConfig::ResourceEvent(config) => {
+                    self.resource_subscribers
+                        .add(spell_id.clone(), config.events.clone());
+                }
+                TriggerConfig::KvChange(config) => {
+                    self.kv_subscribers
+                        .add(spell_id.clone(), config.watches.clone());
+                }
+                TriggerConfig::Webhook(config) => {
+                    self.webhook_tokens
+                        .insert((*spell_id).clone(), config.token_hash.clone());
+                }
+                TriggerConfig::ProbeEvent(config) => {
+                    self.probe_subscribers
+                        .add(spell_id.clone(), config.events.clone());
+                }
+                TriggerConfig::HealthEvent(config) => {
+                    self.health_subscribers
+                        .add(spell_id.clone(), config.events.clone());
+                }
+                TriggerConfig::PartitionEvent(config) => {
+                    self.partition_subscribers
+                        .add(spell_id.clone(), config.events.clone());
+                }
+                TriggerConfig::CustomEvent(config) => {
+                    self.custom_subscribers
+                        .add(spell_id.clone(), config.events.clone());
                 }
             }
         }
+        if !config.exclusions.is_empty() {
+            self.exclusions
+                .insert((*spell_id).clone(), config.exclusions.clone());
+        }
         self.active.insert(spell_id);
     }
 
@@ -157,12 +333,137 @@ impl SubscribersState {
         self.scheduled
             .retain(|scheduled| *scheduled.data.id != *spell_id);
         self.subscribers.remove(spell_id);
+        self.resource_subscribers.remove(spell_id);
+        self.probe_subscribers.remove(spell_id);
+        self.health_subscribers.remove(spell_id);
+        self.partition_subscribers.remove(spell_id);
+        self.custom_subscribers.remove(spell_id);
+        self.kv_subscribers.remove(spell_id);
+        self.webhook_tokens.remove(spell_id);
+        self.exclusions.remove(spell_id);
+        self.peer_payloads.remove(spell_id);
+    }
+
+    /// Whether the spell is in one of its exclusion windows at the unix time in seconds
+    fn is_excluded(&self, spell_id: &SpellId, timestamp: u64) -> bool {
+        self.exclusions.get(spell_id).map_or(false, |exclusions| {
+            exclusions.iter().any(|window| window.contains(timestamp))
+        })
+    }
+
+    fn is_webhook_allowed(&self, spell_id: &SpellId, token_hash: &str) -> bool {
+        self.webhook_tokens
+            .get(spell_id)
+            .map_or(false, |expected| expected == token_hash)
+    }
+
+    /// Apply the backoff hint of a spell and move its next run accordingly.
+    /// If the new run time is past the spell's `end_at`, the next run is kept as it was.
+    fn set_backoff(&mut self, spell_id: &SpellId, backoff: Duration) {
+        if backoff.is_zero() {
+            self.backoffs.remove(spell_id);
+        } else {
+            self.backoffs.insert(spell_id.clone(), backoff);
+        }
+        self.reschedule(spell_id, |periodic| periodic.backoff = backoff);
+    }
+
+    /// Stretch the timer of a spell failing in a row, level 0 restores its interval
+    fn set_quarantine(&mut self, spell_id: &SpellId, level: u32, max_backoff: Duration) {
+        self.reschedule(spell_id, |periodic| {
+            periodic.quarantine = quarantine_interval(periodic.period, level, max_backoff)
+        });
+    }
+
+    /// Update the timer of a spell and move its next run according to the new interval.
+    /// If the new run time is past the spell's `end_at`, the next run is kept as it was.
+    fn reschedule(&mut self, spell_id: &SpellId, update: impl Fn(&mut Periodic)) {
+        let scheduled = std::mem::take(&mut self.scheduled);
+        self.scheduled = scheduled
+            .into_iter()
+            .map(|mut scheduled| {
+                if *scheduled.data.id != *spell_id {
+                    return scheduled;
+                }
+                update(&mut scheduled.data);
+                // The spell hasn't been run yet, so it still waits for its `start_at`.
+                let Some(last_run) = scheduled.last_run else {
+                    return scheduled;
+                };
+                let run_at = scheduled.data.next_run(last_run).filter(|run_at| {
+                    scheduled
+                        .data
+                        .end_at
+                        .map(|end_at| *run_at < end_at)
+                        .unwrap_or(true)
+                });
+                if let Some(run_at) = run_at {
+                    scheduled.run_at = run_at;
+                }
+                scheduled
+            })
+            .collect();
     }
 
     fn subscribers(&self, event_type: &PeerEventType) -> impl Iterator<Item = &Arc<SpellId>> {
         self.subscribers.get(event_type)
     }
 
+    fn resource_subscribers(
+        &self,
+        event_type: &ResourceEventType,
+    ) -> impl Iterator<Item = &Arc<SpellId>> {
+        self.resource_subscribers.get(event_type)
+    }
+
+    fn probe_subscribers(
+        &self,
+        event_type: &ProbeEventType,
+    ) -> impl Iterator<Item = &Arc<SpellId>> {
+        self.probe_subscribers.get(event_type)
+    }
+
+    fn health_subscribers(
+        &self,
+        event_type: &HealthEventType,
+    ) -> impl Iterator<Item = &Arc<SpellId>> {
+        self.health_subscribers.get(event_type)
+    }
+
+    fn partition_subscribers(
+        &self,
+        event_type: &PartitionEventType,
+    ) -> impl Iterator<Item = &Arc<SpellId>> {
+        self.partition_subscribers.get(event_type)
+    }
+
+    fn custom_subscribers(
+        &self,
+        event_type: &CustomEventType,
+    ) -> impl Iterator<Item = &Arc<SpellId>> {
+        self.custom_subscribers.get(event_type)
+    }
+
+    /// Returns subscribers of the written key if its value differs from the previously seen one.
+    /// The first write seen by the bus is always considered a change.
+    fn kv_changed_subscribers(&mut self, event: &KvChangeEvent) -> Vec<Arc<SpellId>> {
+        let watch = event.watch();
+        let subscribers: Vec<_> = self.kv_subscribers.get(&watch).cloned().collect();
+        if subscribers.is_empty() {
+            // Nobody watches the key anymore, no need to remember its value
+            self.kv_hashes.remove(&watch);
+            return subscribers;
+        }
+
+        let mut hasher = DefaultHasher::new();
+        event.value.hash(&mut hasher);
+        let hash = hasher.finish();
+        match self.kv_hashes.insert(watch, hash) {
+            Some(previous) if previous == hash => vec![],
+            _ => subscribers,
+        }
+    }
+
     fn next_scheduled_in(&self, now: Instant) -> Option<Duration> {
         self.scheduled
             .peek()
@@ -170,6 +471,12 @@ impl SubscribersState {
     }
 }
 
+#[derive(Debug, Error)]
+pub enum TriggerSourceError {
+    #[error("trigger source {0} has already been added to the bus")]
+    Duplicate(String),
+}
+
 #[derive(Debug, Error)]
 enum BusInternalError {
     // oneshot::Sender doesn't provide the reasons why it failed to send a message
@@ -177,13 +484,27 @@ enum BusInternalError {
         "failed to send a result of a command execution ({0:?}): receiving end probably dropped"
     )]
     Reply(Action),
-    #[error("failed to send notification about a peer event {1:?} to spell {0}: {2}")]
+    #[error("failed to send notification about an event {1:?} to spell {0}: {2}")]
     SendEvent(SpellId, TriggerInfo, Pin<Box<dyn std::error::Error>>),
 }
 
 pub struct SpellEventBus {
     /// List of events producers.
     sources: Vec<BoxStream<'static, PeerEvent>>,
+    /// Producers of node resource pressure events.
+    resource_sources: Vec<BoxStream<'static, ResourceEvent>>,
+    /// Producers of spell KV writes.
+    kv_sources: Vec<BoxStream<'static, KvChangeEvent>>,
+    /// Producers of must-reach targets probe results.
+    probe_sources: Vec<BoxStream<'static, ProbeEvent>>,
+    /// Producers of service health changes.
+    health_sources: Vec<BoxStream<'static, HealthEvent>>,
+    /// Producers of network partition changes.
+    partition_sources: Vec<BoxStream<'static, PartitionEvent>>,
+    /// Trigger sources added by the embedder of the node, see [`TriggerSource`].
+    custom_sources: Vec<Box<dyn TriggerSource>>,
+    /// Names of the custom sources shared with the API
+    trigger_sources: Arc<RwLock<HashSet<String>>>,
     /// API connections
     recv_cmd_channel: mpsc::UnboundedReceiver<Command>,
     /// Notify when trigger happened
@@ -196,18 +517,31 @@ impl SpellEventBus {
     pub fn new(
         spell_metrics: Option<SpellMetrics>,
         sources: Vec<BoxStream<'static, PeerEvent>>,
+        resource_sources: Vec<BoxStream<'static, ResourceEvent>>,
+        kv_sources: Vec<BoxStream<'static, KvChangeEvent>>,
     ) -> (
         Self,
         SpellEventBusApi,
         mpsc::UnboundedReceiver<TriggerEvent>,
     ) {
         let (send_cmd_channel, recv_cmd_channel) = mpsc::unbounded_channel();
-        let api = SpellEventBusApi { send_cmd_channel };
+        let trigger_sources = Arc::new(RwLock::new(HashSet::new()));
+        let api = SpellEventBusApi {
+            send_cmd_channel,
+            trigger_sources: trigger_sources.clone(),
+        };
 
         let (send_events, recv_events) = mpsc::unbounded_channel();
 
         let this = Self {
             sources,
+            resource_sources,
+            kv_sources,
+            probe_sources: vec![],
+            health_sources: vec![],
+            partition_sources: vec![],
+            custom_sources: vec![],
+            trigger_sources,
             recv_cmd_channel,
             send_events,
             spell_metrics,
@@ -215,6 +549,44 @@ impl SpellEventBus {
         (this, api, recv_events)
     }
 
+    pub fn with_probe_sources(
+        mut self,
+        probe_sources: Vec<BoxStream<'static, ProbeEvent>>,
+    ) -> Self {
+        self.probe_sources = probe_sources;
+        self
+    }
+
+    pub fn with_health_sources(
+        mut self,
+        health_sources: Vec<BoxStream<'static, HealthEvent>>,
+    ) -> Self {
+        self.health_sources = health_sources;
+        self
+    }
+
+    pub fn with_partition_sources(
+        mut self,
+        partition_sources: Vec<BoxStream<'static, PartitionEvent>>,
+    ) -> Self {
+        self.partition_sources = partition_sources;
+        self
+    }
+
+    /// Add a custom trigger source, spells subscribe to its events by its name.
+    /// Fails if a source with the same name has already been added.
+    pub fn with_trigger_source(
+        mut self,
+        source: Box<dyn TriggerSource>,
+    ) -> Result<Self, TriggerSourceError> {
+        let name = source.name();
+        if !self.trigger_sources.write().insert(name.clone()) {
+            return Err(TriggerSourceError::Duplicate(name));
+        }
+        self.custom_sources.push(source);
+        Ok(self)
+    }
+
     pub fn start(self) -> task::JoinHandle<()> {
         task::Builder::new()
             .name("spell-bus")
@@ -231,6 +603,48 @@ impl SpellEventBus {
             .map(|source| source.fuse())
             .collect::<Vec<_>>();
         let mut sources_channel = futures::stream::select_all(sources);
+        let resource_sources = self
+            .resource_sources
+            .into_iter()
+            .map(|source| source.fuse())
+            .collect::<Vec<_>>();
+        let mut resource_channel = futures::stream::select_all(resource_sources);
+        let kv_sources = self
+            .kv_sources
+            .into_iter()
+            .map(|source| source.fuse())
+            .collect::<Vec<_>>();
+        let mut kv_channel = futures::stream::select_all(kv_sources);
+        let probe_sources = self
+            .probe_sources
+            .into_iter()
+            .map(|source| source.fuse())
+            .collect::<Vec<_>>();
+        let mut probe_channel = futures::stream::select_all(probe_sources);
+        let health_sources = self
+            .health_sources
+            .into_iter()
+            .map(|source| source.fuse())
+            .collect::<Vec<_>>();
+        let mut health_channel = futures::stream::select_all(health_sources);
+        let partition_sources = self
+            .partition_sources
+            .into_iter()
+            .map(|source| source.fuse())
+            .collect::<Vec<_>>();
+        let mut partition_channel = futures::stream::select_all(partition_sources);
+        let custom_sources = self
+            .custom_sources
+            .into_iter()
+            .map(|source| {
+                let name = source.name();
+                source
+                    .events()
+                    .map(move |event| CustomEvent::new(name.clone(), event))
+                    .fuse()
+            })
+            .collect::<Vec<_>>();
+        let mut custom_channel = futures::stream::select_all(custom_sources);
 
         let mut state = SubscribersState::new();
         let mut is_started = false;
@@ -261,6 +675,7 @@ impl SpellEventBus {
                 select! {
                     Some(command) = self.recv_cmd_channel.recv() => {
                         let Command { action, reply } = command;
+                        let mut accepted = true;
                         match &action {
                             Action::Subscribe(spell_id, config) => {
                                 log::trace!("Subscribe {spell_id} to {:?}", config);
@@ -277,19 +692,81 @@ impl SpellEventBus {
                                 log::trace!("Unsubscribe {spell_id}");
                                 state.unsubscribe(spell_id);
                             },
+                            Action::SetBackoff(spell_id, backoff) => {
+                                log::trace!("Set backoff of {spell_id} to {:?}", backoff);
+                                state.set_backoff(spell_id, *backoff);
+                            },
+                            Action::SetQuarantine { spell_id, level, max_backoff } => {
+                                log::trace!("Set quarantine level of {spell_id} to {level}");
+                                state.set_quarantine(spell_id, *level, *max_backoff);
+                            },
+                            Action::Webhook { spell_id, token_hash, event } => {
+                                accepted = is_started && state.is_webhook_allowed(spell_id, token_hash);
+                                if accepted {
+                                    log::trace!("Webhook of {spell_id}");
+                                    let event = TriggerInfo::Webhook(event.clone());
+                                    Self::trigger_spell(&send_events, &state, &Arc::new(spell_id.clone()), event)?;
+                                } else {
+                                    log::debug!("Webhook of {spell_id} is rejected");
+                                }
+                            }
                             Action::Start => {
                                 log::trace!("Start the bus");
                                 is_started = true;
                             }
                         };
-                        reply.send(()).map_err(|_| {
+                        reply.send(accepted).map_err(|_| {
                             BusInternalError::Reply(action)
                         })?;
                     },
                     Some(event) = sources_channel.next(), if is_started => {
                         for spell_id in state.subscribers(&event.get_type()) {
-                            let event = TriggerInfo::Peer(event.clone());
-                            Self::trigger_spell(&send_events, spell_id, event)?;
+                            let mut event = event.clone();
+                            event.payload = state
+                                .peer_payloads
+                                .get(&**spell_id)
+                                .map(|template| template.render(&event));
+                            let event = TriggerInfo::Peer(event);
+                            Self::trigger_spell(&send_events, &state, spell_id, event)?;
+                        }
+                    },
+                    Some(event) = resource_channel.next(), if is_started => {
+                        for spell_id in state.resource_subscribers(&event.resource) {
+                            let event = TriggerInfo::Resource(event.clone());
+                            Self::trigger_spell(&send_events, &state, spell_id, event)?;
+                        }
+                    },
+                    Some(event) = probe_channel.next(), if is_started => {
+                        for spell_id in state.probe_subscribers(&event.get_type()) {
+                            let event = TriggerInfo::Probe(event.clone());
+                            Self::trigger_spell(&send_events, &state, spell_id, event)?;
+                        }
+                    },
+                    Some(event) = health_channel.next(), if is_started => {
+                        for spell_id in state.health_subscribers(&event.status) {
+                            let event = TriggerInfo::Health(event.clone());
+                            Self::trigger_spell(&send_events, &state, spell_id, event)?;
+                        }
+                    },
+                    Some(event) = partition_channel.next(), if is_started => {
+                        for spell_id in state.partition_subscribers(&event.status) {
+                            let event = TriggerInfo::Partition(event.clone());
+                            Self::trigger_spell(&send_events, &state, spell_id, event)?;
+                        }
+                    },
+                    Some(event) = custom_channel.next(), if is_started => {
+                        for spell_id in state.custom_subscribers(&event.get_type()) {
+                            let event = TriggerInfo::Custom(event.clone());
+                            Self::trigger_spell(&send_events, &state, spell_id, event)?;
+                        }
+                    },
+                    Some(event) = kv_channel.next(), if is_started => {
+                        if event.key == BACKOFF_HINT_KEY {
+                            state.set_backoff(&event.spell_id, backoff_hint(&event.value));
+                        }
+                        for spell_id in state.kv_changed_subscribers(&event) {
+                            let event = TriggerInfo::KvChange(event.clone());
+                            Self::trigger_spell(&send_events, &state, &spell_id, event)?;
                         }
                     },
                     _ = timer_task, if is_started => {
@@ -298,9 +775,11 @@ impl SpellEventBus {
                             log::trace!("Execute: {:?}", scheduled_spell);
                             let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
                             let spell_id = scheduled_spell.data.id.clone();
-                            Self::trigger_spell(&send_events, &scheduled_spell.data.id, TriggerInfo::Timer(TimerEvent{ timestamp }))?;
-                            // Do not reschedule the spell otherwise.
-                            if let Some(rescheduled) = Scheduled::at(scheduled_spell.data, Instant::now()) {
+                            Self::trigger_spell(&send_events, &state, &scheduled_spell.data.id, TriggerInfo::Timer(TimerEvent{ timestamp }))?;
+                            // Missed runs are made only once, the regular schedule of the spell goes on.
+                            if scheduled_spell.missed {
+                                log::trace!("Made a missed run of {spell_id}");
+                            } else if let Some(rescheduled) = Scheduled::at(scheduled_spell.data, Instant::now()) {
                                 log::trace!("Reschedule: {:?}", rescheduled);
                                 state.scheduled.push(rescheduled);
                             } else {
@@ -322,9 +801,18 @@ impl SpellEventBus {
     #[allow(clippy::result_large_err)]
     fn trigger_spell(
         send_events: &mpsc::UnboundedSender<TriggerEvent>,
+        state: &SubscribersState,
         id: &Arc<SpellId>,
         event: TriggerInfo,
     ) -> Result<(), BusInternalError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        if state.is_excluded(id, now) {
+            log::trace!("Spell {id} isn't triggered by {event:?} in its exclusion window");
+            return Ok(());
+        }
         send_events
             .send(TriggerEvent {
                 spell_id: (**id).clone(),
@@ -339,6 +827,7 @@ impl SpellEventBus {
 mod tests {
     use crate::bus::*;
     use connection_pool::LifecycleEvent;
+    use fluence_spell_dtos::trigger_config::TriggerConfig as UserTriggerConfig;
     use futures::StreamExt;
     use libp2p::PeerId;
     use maplit::hashmap;
@@ -398,7 +887,11 @@ mod tests {
         api.subscribe(
             spell_id,
             SpellTriggerConfigs {
-                triggers: vec![TriggerConfig::PeerEvent(PeerEventConfig { events })],
+                triggers: vec![TriggerConfig::PeerEvent(PeerEventConfig {
+                    events,
+                    payload: None,
+                })],
+                exclusions: vec![],
             },
         )
         .await
@@ -410,6 +903,7 @@ mod tests {
             spell_id,
             SpellTriggerConfigs {
                 triggers: vec![TriggerConfig::Timer(config)],
+                exclusions: vec![],
             },
         )
         .await
@@ -435,7 +929,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_subscribe_one() {
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let event_stream = UnboundedReceiverStream::new(event_receiver);
@@ -460,7 +954,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_subscribe_many() {
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let event_stream = UnboundedReceiverStream::new(event_receiver);
@@ -494,7 +988,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_subscribe_oneshot() {
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let event_stream = UnboundedReceiverStream::new(event_receiver);
@@ -526,7 +1020,7 @@ mod tests {
     async fn test_subscribe_connect() {
         let (send, recv) = mpsc::unbounded_channel();
         let recv = UnboundedReceiverStream::new(recv).boxed();
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![recv]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![recv], vec![], vec![]);
         let mut event_stream = UnboundedReceiverStream::new(event_receiver);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
@@ -553,11 +1047,364 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_subscribe_kv_change() {
+        let (send, recv) = mpsc::unbounded_channel();
+        let recv = UnboundedReceiverStream::new(recv).boxed();
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![recv]);
+        let bus = bus.start();
+        let _ = api.start_scheduling().await;
+
+        let spell_id = "downstream".to_string();
+        let watch = KvWatch {
+            spell_id: "upstream".to_string(),
+            key: "price".to_string(),
+        };
+        api.subscribe(
+            spell_id.clone(),
+            add_kv_triggers(None, vec![watch]).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        let write = |key: &str, value: &str| KvChangeEvent {
+            spell_id: "upstream".to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        send.send(write("price", "1")).unwrap();
+        let first = event_receiver.recv().await.unwrap();
+        // The same value and unwatched keys don't trigger the spell
+        send.send(write("price", "1")).unwrap();
+        send.send(write("volume", "2")).unwrap();
+        send.send(write("price", "2")).unwrap();
+        let second = event_receiver.recv().await.unwrap();
+        let other = event_receiver.try_recv();
+        try_catch(
+            || {
+                assert_eq!(first.spell_id, spell_id);
+                assert_matches!(first.info, TriggerInfo::KvChange(k) if k.value == "1");
+                assert_matches!(second.info, TriggerInfo::KvChange(k) if k.key == "price" && k.value == "2");
+                assert!(
+                    other.is_err(),
+                    "unchanged values must not trigger the spell"
+                );
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_resource_event() {
+        let (send, recv) = mpsc::unbounded_channel();
+        let recv = UnboundedReceiverStream::new(recv).boxed();
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![recv], vec![]);
+        let bus = bus.start();
+        let _ = api.start_scheduling().await;
+
+        let disk_spell_id = "disk_spell".to_string();
+        api.subscribe(
+            disk_spell_id.clone(),
+            add_resource_triggers(None, vec![ResourceEventType::Disk]).unwrap(),
+        )
+        .await
+        .unwrap();
+        let memory_spell_id = "memory_spell".to_string();
+        api.subscribe(
+            memory_spell_id.clone(),
+            add_resource_triggers(None, vec![ResourceEventType::Memory]).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        send.send(ResourceEvent {
+            resource: ResourceEventType::Disk,
+            available: 1,
+            total: 100,
+        })
+        .unwrap();
+
+        let event = event_receiver.recv().await.unwrap();
+        let other = event_receiver.try_recv();
+        try_catch(
+            || {
+                assert_eq!(event.spell_id, disk_spell_id);
+                assert_matches!(
+                    event.info,
+                    TriggerInfo::Resource(r) if r.resource == ResourceEventType::Disk && r.available == 1
+                );
+                assert!(other.is_err(), "memory spell must not be triggered");
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_probe_event() {
+        let (send, recv) = mpsc::unbounded_channel();
+        let recv = UnboundedReceiverStream::new(recv).boxed();
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.with_probe_sources(vec![recv]).start();
+        let _ = api.start_scheduling().await;
+
+        let spell_id = "alert_spell".to_string();
+        api.subscribe(
+            spell_id.clone(),
+            add_probe_triggers(None, vec![ProbeEventType::Unreachable]).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        for reachable in [true, false] {
+            send.send(ProbeEvent {
+                target: "service:srv".to_string(),
+                reachable,
+                timestamp: 1,
+            })
+            .unwrap();
+        }
+
+        let event = event_receiver.recv().await.unwrap();
+        let other = event_receiver.try_recv();
+        try_catch(
+            || {
+                assert_eq!(event.spell_id, spell_id);
+                assert_matches!(
+                    event.info,
+                    TriggerInfo::Probe(p) if p.target == "service:srv" && !p.reachable
+                );
+                assert!(
+                    other.is_err(),
+                    "reachable targets must not trigger the spell"
+                );
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_health_event() {
+        let (send, recv) = mpsc::unbounded_channel();
+        let recv = UnboundedReceiverStream::new(recv).boxed();
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.with_health_sources(vec![recv]).start();
+        let _ = api.start_scheduling().await;
+
+        let spell_id = "watchdog_spell".to_string();
+        api.subscribe(
+            spell_id.clone(),
+            add_health_triggers(None, vec![HealthEventType::Exhausted]).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        for status in [HealthEventType::Unhealthy, HealthEventType::Exhausted] {
+            send.send(HealthEvent {
+                service_id: "srv".to_string(),
+                worker_id: "worker".to_string(),
+                status,
+                error: "boom".to_string(),
+                timestamp: 1,
+            })
+            .unwrap();
+        }
+
+        let event = event_receiver.recv().await.unwrap();
+        let other = event_receiver.try_recv();
+        try_catch(
+            || {
+                assert_eq!(event.spell_id, spell_id);
+                assert_matches!(
+                    event.info,
+                    TriggerInfo::Health(h) if h.service_id == "srv" && h.status == HealthEventType::Exhausted
+                );
+                assert!(
+                    other.is_err(),
+                    "unsubscribed health changes must not trigger the spell"
+                );
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_partition_event() {
+        let (send, recv) = mpsc::unbounded_channel();
+        let recv = UnboundedReceiverStream::new(recv).boxed();
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.with_partition_sources(vec![recv]).start();
+        let _ = api.start_scheduling().await;
+
+        let spell_id = "writer_spell".to_string();
+        api.subscribe(
+            spell_id.clone(),
+            add_partition_triggers(None, vec![PartitionEventType::Partitioned]).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        for status in [PartitionEventType::Healed, PartitionEventType::Partitioned] {
+            send.send(PartitionEvent {
+                status,
+                unreachable: 3,
+                known: 4,
+                timestamp: 1,
+            })
+            .unwrap();
+        }
+
+        let event = event_receiver.recv().await.unwrap();
+        let other = event_receiver.try_recv();
+        try_catch(
+            || {
+                assert_eq!(event.spell_id, spell_id);
+                assert_matches!(
+                    event.info,
+                    TriggerInfo::Partition(p) if p.status == PartitionEventType::Partitioned && p.unreachable == 3
+                );
+                assert!(
+                    other.is_err(),
+                    "unsubscribed partition changes must not trigger the spell"
+                );
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_custom_event() {
+        let (send, recv) = mpsc::unbounded_channel();
+        let recv = UnboundedReceiverStream::new(recv).boxed();
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus
+            .with_trigger_source(Box::new(StreamSource::new("mqtt", recv)))
+            .unwrap()
+            .start();
+        let _ = api.start_scheduling().await;
+        assert!(api.has_trigger_source("mqtt"));
+        assert!(!api.has_trigger_source("fs"));
+
+        let spell_id = "mqtt_spell".to_string();
+        let event_type = CustomEventType {
+            source: "mqtt".to_string(),
+            kind: "sensors/temperature".to_string(),
+        };
+        api.subscribe(
+            spell_id.clone(),
+            add_custom_triggers(None, vec![event_type]).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        for kind in ["sensors/humidity", "sensors/temperature"] {
+            send.send(SourceEvent {
+                kind: kind.to_string(),
+                payload: "42".to_string(),
+            })
+            .unwrap();
+        }
+
+        let event = event_receiver.recv().await.unwrap();
+        let other = event_receiver.try_recv();
+        try_catch(
+            || {
+                assert_eq!(event.spell_id, spell_id);
+                assert_matches!(
+                    event.info,
+                    TriggerInfo::Custom(c) if c.source == "mqtt" && c.kind == "sensors/temperature" && c.payload == "42"
+                );
+                assert!(
+                    other.is_err(),
+                    "unsubscribed kinds of events must not trigger the spell"
+                );
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[test]
+    fn test_duplicate_trigger_source() {
+        let source = || Box::new(StreamSource::new("mqtt", futures::stream::empty().boxed()));
+        let (bus, _api, _event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.with_trigger_source(source()).unwrap();
+        let Err(TriggerSourceError::Duplicate(name)) = bus.with_trigger_source(source()) else {
+            panic!("a source with the same name must not be added twice");
+        };
+        assert_eq!(name, "mqtt");
+    }
+
+    #[tokio::test]
+    async fn test_webhook() {
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.start();
+
+        let spell_id = "hooked".to_string();
+        let token_hash = webhook_token_hash("secret");
+        api.subscribe(
+            spell_id.clone(),
+            add_webhook_trigger(None, Some(token_hash)).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        // webhooks are rejected until the scheduling starts
+        let before_start = api
+            .trigger_webhook(spell_id.clone(), "secret", "early".to_string())
+            .await
+            .unwrap();
+        let _ = api.start_scheduling().await;
+        let wrong_token = api
+            .trigger_webhook(spell_id.clone(), "guess", "{}".to_string())
+            .await
+            .unwrap();
+        let unknown_spell = api
+            .trigger_webhook("other".to_string(), "secret", "{}".to_string())
+            .await
+            .unwrap();
+        let accepted = api
+            .trigger_webhook(spell_id.clone(), "secret", r#"{"ref":"main"}"#.to_string())
+            .await
+            .unwrap();
+
+        let event = event_receiver.recv().await.unwrap();
+        let other = event_receiver.try_recv();
+        try_catch(
+            || {
+                assert!(!before_start && !wrong_token && !unknown_spell);
+                assert!(accepted);
+                assert_eq!(event.spell_id, spell_id);
+                assert_matches!(
+                    event.info,
+                    TriggerInfo::Webhook(w) if w.payload == r#"{"ref":"main"}"#
+                );
+                assert!(
+                    other.is_err(),
+                    "rejected webhooks must not trigger the spell"
+                );
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
     #[tokio::test]
     async fn test_unsubscribe() {
         let (send, recv) = mpsc::unbounded_channel();
         let recv = UnboundedReceiverStream::new(recv).boxed();
-        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![recv]);
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![recv], vec![], vec![]);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
 
@@ -587,7 +1434,7 @@ mod tests {
     async fn test_subscribe_many_spells_with_diff_event_types() {
         let (recv, hdl) = emulate_connect(Duration::from_millis(10));
         let recv = UnboundedReceiverStream::new(recv).boxed();
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![recv]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![recv], vec![], vec![]);
         let event_stream = UnboundedReceiverStream::new(event_receiver);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
@@ -623,7 +1470,7 @@ mod tests {
     #[tokio::test]
     async fn test_double_subscribe_before_run() {
         //log_utils::enable_logs();
-        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
         let bus = bus.start();
         let mut event_stream = UnboundedReceiverStream::new(event_receiver).fuse();
         let spell1_id = "spell1".to_string();
@@ -661,7 +1508,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_resubscribing_same_spell() {
-        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![]);
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
         let bus = bus.start();
         let _ = api.start_scheduling().await;
         let spell1_id = "spell1".to_string();
@@ -693,4 +1540,244 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn test_backoff_slows_down_timer() {
+        let mut state = SubscribersState::new();
+        let spell_id = "spell1".to_string();
+        let period = Duration::from_millis(5);
+        let last_run = Instant::now();
+        let periodic = Periodic {
+            id: Arc::new(spell_id.clone()),
+            period,
+            end_at: None,
+            backoff: Duration::ZERO,
+            quarantine: Duration::ZERO,
+            cron: None,
+        };
+        state
+            .scheduled
+            .push(Scheduled::at(periodic, last_run).unwrap());
+
+        state.set_backoff(&spell_id, Duration::from_secs(60));
+        assert_eq!(
+            state.scheduled.peek().unwrap().run_at,
+            last_run + Duration::from_secs(60),
+            "backoff hint is ignored"
+        );
+
+        state.set_backoff(&spell_id, Duration::ZERO);
+        assert_eq!(
+            state.scheduled.peek().unwrap().run_at,
+            last_run + period,
+            "reset backoff must restore the period"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exclusion_windows() {
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.start();
+        let _ = api.start_scheduling().await;
+        let spell1_id = "spell1".to_string();
+        let timer = SpellTriggerConfigs {
+            triggers: vec![TriggerConfig::Timer(TimerConfig::periodic(
+                Duration::from_millis(5),
+                Instant::now(),
+                None,
+            ))],
+            exclusions: vec![],
+        };
+        // Two windows covering the whole day
+        let window = |start: &str, end: &str| ExclusionWindow {
+            weekdays: vec![],
+            start: start.to_string(),
+            end: end.to_string(),
+        };
+        let windows = vec![window("00:00", "12:00"), window("12:00", "00:00")];
+        let config = add_exclusion_windows(Some(timer.clone()), &windows)
+            .unwrap()
+            .unwrap();
+        api.subscribe(spell1_id.clone(), config)
+            .await
+            .expect("Could not subscribe timer");
+        let excluded = tokio::time::timeout(Duration::from_millis(50), event_receiver.recv()).await;
+        api.subscribe(spell1_id.clone(), timer)
+            .await
+            .expect("Could not subscribe timer");
+        let event = tokio::time::timeout(Duration::from_millis(50), event_receiver.recv()).await;
+
+        try_catch(
+            || {
+                assert!(excluded.is_err(), "spell was triggered in exclusion window");
+                let event = event.ok().flatten();
+                assert!(event.is_some(), "spell wasn't triggered without windows");
+                assert_eq!(event.unwrap().spell_id, spell1_id);
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missed_runs() {
+        let (bus, api, mut event_receiver) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.start();
+        let _ = api.start_scheduling().await;
+        let spell1_id = "spell1".to_string();
+        let mut config = TimerConfig::periodic(
+            Duration::from_secs(60),
+            Instant::now() + Duration::from_secs(60),
+            None,
+        );
+        config.missed_runs = 3;
+        subscribe_timer(&api, spell1_id.clone(), config).await;
+
+        let mut missed = vec![];
+        for _ in 0..3 {
+            missed.push(event_receiver.recv().await);
+        }
+        let regular = tokio::time::timeout(Duration::from_millis(50), event_receiver.recv()).await;
+
+        try_catch(
+            || {
+                for event in missed {
+                    let event = event.expect("missed run wasn't made");
+                    assert_eq!(event.spell_id, spell1_id);
+                    assert_matches!(event.info, TriggerInfo::Timer(_));
+                }
+                assert!(regular.is_err(), "missed runs must not be rescheduled");
+            },
+            || {
+                bus.abort();
+            },
+        );
+    }
+
+    #[test]
+    fn test_backoff_never_speeds_up() {
+        let mut state = SubscribersState::new();
+        let spell_id = "spell1".to_string();
+        let period = Duration::from_secs(10);
+        let last_run = Instant::now();
+        let periodic = Periodic {
+            id: Arc::new(spell_id.clone()),
+            period,
+            end_at: None,
+            backoff: Duration::ZERO,
+            quarantine: Duration::ZERO,
+            cron: None,
+        };
+        state
+            .scheduled
+            .push(Scheduled::at(periodic, last_run).unwrap());
+
+        state.set_backoff(&spell_id, Duration::from_secs(1));
+        assert_eq!(state.scheduled.peek().unwrap().run_at, last_run + period);
+
+        state.set_backoff(&spell_id, Duration::from_secs(30));
+        assert_eq!(
+            state.scheduled.peek().unwrap().run_at,
+            last_run + Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn test_backoff_hint_survives_resubscription() {
+        let mut state = SubscribersState::new();
+        let spell_id = "spell1".to_string();
+        let config = SpellTriggerConfigs {
+            triggers: vec![TriggerConfig::Timer(TimerConfig::periodic(
+                Duration::from_secs(10),
+                Instant::now(),
+                None,
+            ))],
+            exclusions: vec![],
+        };
+        state.subscribe(spell_id.clone(), &config);
+        state.set_backoff(&spell_id, backoff_hint("30"));
+
+        state.unsubscribe(&spell_id);
+        state.subscribe(spell_id.clone(), &config);
+        let scheduled = state.scheduled.peek().unwrap();
+        assert_eq!(scheduled.data.backoff, Duration::from_secs(30));
+
+        state.set_backoff(&spell_id, backoff_hint(""));
+        state.unsubscribe(&spell_id);
+        state.subscribe(spell_id.clone(), &config);
+        let scheduled = state.scheduled.peek().unwrap();
+        assert_eq!(scheduled.data.backoff, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_quarantine_backs_off_exponentially() {
+        let mut state = SubscribersState::new();
+        let spell_id = "spell1".to_string();
+        let period = Duration::from_secs(10);
+        let max_backoff = Duration::from_secs(60);
+        let last_run = Instant::now();
+        let periodic = Periodic {
+            id: Arc::new(spell_id.clone()),
+            period,
+            end_at: None,
+            backoff: Duration::ZERO,
+            quarantine: Duration::ZERO,
+            cron: None,
+        };
+        state
+            .scheduled
+            .push(Scheduled::at(periodic, last_run).unwrap());
+        let next_run = |state: &SubscribersState| state.scheduled.peek().unwrap().run_at;
+
+        state.set_quarantine(&spell_id, 1, max_backoff);
+        assert_eq!(next_run(&state), last_run + Duration::from_secs(20));
+
+        state.set_quarantine(&spell_id, 2, max_backoff);
+        assert_eq!(next_run(&state), last_run + Duration::from_secs(40));
+
+        // capped by the max backoff
+        state.set_quarantine(&spell_id, 40, max_backoff);
+        assert_eq!(next_run(&state), last_run + max_backoff);
+
+        // the backoff hint still applies during the quarantine
+        state.set_backoff(&spell_id, Duration::from_secs(90));
+        assert_eq!(next_run(&state), last_run + Duration::from_secs(90));
+        state.set_backoff(&spell_id, Duration::ZERO);
+
+        state.set_quarantine(&spell_id, 0, max_backoff);
+        assert_eq!(next_run(&state), last_run + period);
+    }
+
+    #[test]
+    fn test_cron_trigger() {
+        let mut state = SubscribersState::new();
+        let spell_id = "spell1".to_string();
+        let cron = CronSpec {
+            schedule: "* * * * *".to_string(),
+            timezone: None,
+        };
+        let config = from_user_config(&UserTriggerConfig::default(), Some(&cron)).unwrap();
+        let config = apply_missed_run_policy(
+            config,
+            MissedRunPolicy::Once,
+            Some(now_millis::now_sec() - 600),
+        )
+        .unwrap();
+        let now = Instant::now();
+        state.subscribe(spell_id.clone(), &config);
+
+        // The missed run is made right away, then the spell runs at the start of the next minute
+        let missed = state.scheduled.pop().unwrap();
+        assert!(missed.missed && missed.run_at <= Instant::now());
+        let scheduled = state.scheduled.pop().unwrap();
+        assert!(!scheduled.missed);
+        assert!(scheduled.run_at > now && scheduled.run_at <= now + Duration::from_secs(61));
+        assert_eq!(scheduled.data.period, Duration::from_secs(60));
+
+        // The next run is by the schedule too
+        let rescheduled = Scheduled::at(scheduled.data, scheduled.run_at).unwrap();
+        let interval = rescheduled.run_at - scheduled.run_at;
+        assert!(interval > Duration::from_secs(59) && interval < Duration::from_secs(61));
+    }
 }
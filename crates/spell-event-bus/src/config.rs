@@ -17,10 +17,17 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use crate::api::PeerEventType;
+use crate::api::{
+    CustomEventType, HealthEventType, KvWatch, PartitionEventType, PeerEvent, PeerEventType,
+    ProbeEventType, ResourceEventType,
+};
+use crate::cron::{CronSchedule, CronSpec};
+use connection_pool::Direction;
 use fluence_spell_dtos::trigger_config::{
     ClockConfig, ConnectionPoolConfig, TriggerConfig as UserTriggerConfig,
 };
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JValue};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
@@ -29,6 +36,22 @@ const MAX_PERIOD_YEAR: u32 = 100;
 /// Max period is 100 years in secs: 60 sec * 60 min * 24 hours * 365 days * 100 years
 pub const MAX_PERIOD_SEC: u32 = 60 * 60 * 24 * 365 * MAX_PERIOD_YEAR;
 
+/// Max number of missed timer runs replayed with [`MissedRunPolicy::All`]
+pub const MAX_MISSED_RUNS: u32 = 16;
+
+/// What to do with the timer runs a spell missed while the node was down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MissedRunPolicy {
+    /// Wait for the next run by schedule
+    Skip,
+    /// Run once right away if any run was missed
+    #[default]
+    Once,
+    /// Run every missed run right away, up to [`MAX_MISSED_RUNS`]
+    All,
+}
+
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error(
@@ -39,6 +62,185 @@ pub enum ConfigError {
     InvalidPeriod,
     #[error("invalid config: end_sec is less than start_sec or in the past")]
     InvalidEndSec,
+    #[error("invalid exclusion window: {0}")]
+    InvalidExclusionWindow(String),
+    #[error("invalid payload template: {0}")]
+    InvalidPayloadTemplate(String),
+    #[error("invalid cron trigger: {0}")]
+    InvalidCron(String),
+}
+
+const SECS_IN_DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Sun,
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+}
+
+/// Weekly window in UTC when a spell isn't triggered, e.g. a maintenance window.
+/// The user-facing form is `{"weekdays": ["sun"], "start": "02:00", "end": "03:00"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExclusionWindow {
+    /// Days the window starts on, every day if empty
+    #[serde(default)]
+    pub weekdays: Vec<Weekday>,
+    /// Start of the window, "HH:MM"
+    pub start: String,
+    /// End of the window, "HH:MM". A window ending before its start ends on the next day.
+    pub end: String,
+}
+
+/// Parsed [`ExclusionWindow`], times are minutes since midnight
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Exclusion {
+    /// Bit `n` is set if the window starts on the weekday `n`, Sunday is 0
+    weekdays: u8,
+    start: u32,
+    end: u32,
+}
+
+impl Exclusion {
+    fn parse(window: &ExclusionWindow) -> Result<Self, ConfigError> {
+        let start = parse_time(&window.start)?;
+        let end = parse_time(&window.end)?;
+        if start == end {
+            return Err(ConfigError::InvalidExclusionWindow(format!(
+                "window {}-{} is empty",
+                window.start, window.end
+            )));
+        }
+        let weekdays = if window.weekdays.is_empty() {
+            u8::MAX
+        } else {
+            window
+                .weekdays
+                .iter()
+                .fold(0, |days, day| days | 1 << *day as u8)
+        };
+        Ok(Self {
+            weekdays,
+            start,
+            end,
+        })
+    }
+
+    fn starts_on(&self, days_since_epoch: u64) -> bool {
+        // 1 January 1970 was Thursday
+        let weekday = (days_since_epoch + Weekday::Thu as u64) % 7;
+        self.weekdays & 1 << weekday != 0
+    }
+
+    /// Whether the unix time in seconds falls into the window
+    pub(crate) fn contains(&self, timestamp: u64) -> bool {
+        let day = timestamp / SECS_IN_DAY;
+        let minute = (timestamp % SECS_IN_DAY / 60) as u32;
+        if self.start < self.end {
+            self.starts_on(day) && self.start <= minute && minute < self.end
+        } else {
+            // The window goes past midnight
+            (self.starts_on(day) && self.start <= minute)
+                || (day > 0 && self.starts_on(day - 1) && minute < self.end)
+        }
+    }
+}
+
+/// Parse "HH:MM" into minutes since midnight
+fn parse_time(time: &str) -> Result<u32, ConfigError> {
+    let invalid = || ConfigError::InvalidExclusionWindow(format!("expected HH:MM, got '{time}'"));
+    let (hours, minutes) = time.split_once(':').ok_or_else(invalid)?;
+    let hours: u32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u32 = minutes.parse().map_err(|_| invalid())?;
+    if hours >= 24 || minutes >= 60 {
+        return Err(invalid());
+    }
+    Ok(hours * 60 + minutes)
+}
+
+/// Fields of a peer event available to [`PeerPayloadTemplate`]
+const PEER_PAYLOAD_FIELDS: [&str; 5] = ["peer_id", "event", "direction", "protocols", "timestamp"];
+
+/// Shape of the trigger written to the spell mailbox on peer events instead of the default one.
+/// It's any JSON where a string naming a field is replaced with the field value:
+/// `$peer_id`, `$event` ("connected" or "disconnected"), `$direction` ("inbound", "outbound" or null),
+/// `$protocols` and `$timestamp` (unix time in seconds). Other strings starting with `$` are written as `$$`.
+/// E.g. `{"peer": "$peer_id", "meta": ["$direction", "$timestamp"]}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerPayloadTemplate(JValue);
+
+impl PeerPayloadTemplate {
+    pub fn parse(template: JValue) -> Result<Self, ConfigError> {
+        fn check(value: &JValue) -> Result<(), ConfigError> {
+            match value {
+                JValue::String(s) => match placeholder(s) {
+                    Some(Ok(field)) if !PEER_PAYLOAD_FIELDS.contains(&field) => {
+                        Err(ConfigError::InvalidPayloadTemplate(format!(
+                            "unknown field ${field}, expected one of {}",
+                            PEER_PAYLOAD_FIELDS.map(|f| format!("${f}")).join(", ")
+                        )))
+                    }
+                    _ => Ok(()),
+                },
+                JValue::Array(values) => values.iter().try_for_each(check),
+                JValue::Object(fields) => fields.values().try_for_each(check),
+                _ => Ok(()),
+            }
+        }
+        check(&template)?;
+        Ok(Self(template))
+    }
+
+    pub(crate) fn render(&self, event: &PeerEvent) -> JValue {
+        fn render(value: &JValue, event: &PeerEvent) -> JValue {
+            match value {
+                JValue::String(s) => match placeholder(s) {
+                    Some(Ok("peer_id")) => json!(event.peer_id.to_string()),
+                    Some(Ok("event")) => {
+                        json!(if event.connected {
+                            "connected"
+                        } else {
+                            "disconnected"
+                        })
+                    }
+                    Some(Ok("direction")) => match event.details.direction {
+                        Some(Direction::Inbound) => json!("inbound"),
+                        Some(Direction::Outbound) => json!("outbound"),
+                        None => JValue::Null,
+                    },
+                    Some(Ok("protocols")) => json!(event.details.protocols),
+                    Some(Ok("timestamp")) => json!(event.details.timestamp),
+                    Some(Ok(_)) => JValue::Null,
+                    Some(Err(escaped)) => json!(escaped),
+                    None => value.clone(),
+                },
+                JValue::Array(values) => values.iter().map(|v| render(v, event)).collect(),
+                JValue::Object(fields) => JValue::Object(
+                    fields
+                        .iter()
+                        .map(|(k, v)| (k.clone(), render(v, event)))
+                        .collect(),
+                ),
+                _ => value.clone(),
+            }
+        }
+        render(&self.0, event)
+    }
+}
+
+/// Field named by a `$field` string, or the string with `$$` unescaped as an error
+fn placeholder(s: &str) -> Option<Result<&str, &str>> {
+    let rest = s.strip_prefix('$')?;
+    if rest.starts_with('$') {
+        Some(Err(rest))
+    } else {
+        Some(Ok(rest))
+    }
 }
 
 /// Convert timestamp to std::time::Instant.
@@ -50,8 +252,11 @@ fn to_instant(timestamp: u64) -> Option<Instant> {
 }
 
 /// Convert user-friendly config to event-bus-friendly config, validating it in the process.
+/// Spell triggers from the trigger config set by users. The cron schedule is set next to
+/// the config, since the config stored by the spell service has no place for it.
 pub fn from_user_config(
     user_config: &UserTriggerConfig,
+    cron: Option<&CronSpec>,
 ) -> Result<Option<SpellTriggerConfigs>, ConfigError> {
     let mut triggers = Vec::new();
 
@@ -61,12 +266,22 @@ pub fn from_user_config(
         triggers.push(TriggerConfig::Timer(timer_config));
     }
 
+    if let Some(cron) = cron {
+        triggers.push(TriggerConfig::Cron(CronConfig {
+            schedule: CronSchedule::parse(cron)?,
+            missed_runs: 0,
+        }));
+    }
+
     if let Some(peer_event_config) = from_connection_config(&user_config.connections) {
         triggers.push(TriggerConfig::PeerEvent(peer_event_config));
     }
 
     let cfg = if !triggers.is_empty() {
-        Some(SpellTriggerConfigs { triggers })
+        Some(SpellTriggerConfigs {
+            triggers,
+            exclusions: vec![],
+        })
     } else {
         None
     };
@@ -86,6 +301,7 @@ fn from_connection_config(connection_config: &ConnectionPoolConfig) -> Option<Pe
     } else {
         Some(PeerEventConfig {
             events: pool_events,
+            payload: None,
         })
     }
 }
@@ -128,9 +344,179 @@ fn from_clock_config(clock: &ClockConfig) -> Result<TimerConfig, ConfigError> {
     Ok(config)
 }
 
-#[derive(Debug, Clone)]
+/// Realign the timer trigger of a spell after a downtime and schedule the runs it missed
+/// according to the policy. `last_fired` is the unix time in seconds of the last timer run.
+pub fn apply_missed_run_policy(
+    config: Option<SpellTriggerConfigs>,
+    policy: MissedRunPolicy,
+    last_fired: Option<u64>,
+) -> Option<SpellTriggerConfigs> {
+    let (Some(mut config), Some(last_fired)) = (config, last_fired) else {
+        return config;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+    for trigger in &mut config.triggers {
+        match trigger {
+            TriggerConfig::Timer(timer) => timer.apply_missed_runs(policy, last_fired, now),
+            TriggerConfig::Cron(cron) => cron.apply_missed_runs(policy, last_fired, now),
+            _ => {}
+        }
+    }
+    Some(config)
+}
+
+/// Add resource event triggers to the spell triggers.
+/// Returns `None` if there are no triggers at all.
+pub fn add_resource_triggers(
+    config: Option<SpellTriggerConfigs>,
+    events: Vec<ResourceEventType>,
+) -> Option<SpellTriggerConfigs> {
+    if events.is_empty() {
+        return config;
+    }
+    let mut config = config.unwrap_or_default();
+    config
+        .triggers
+        .push(TriggerConfig::ResourceEvent(ResourceEventConfig { events }));
+    Some(config)
+}
+
+/// Add triggers on probe results of must-reach targets to the spell triggers.
+/// Returns `None` if there are no triggers at all.
+pub fn add_probe_triggers(
+    config: Option<SpellTriggerConfigs>,
+    events: Vec<ProbeEventType>,
+) -> Option<SpellTriggerConfigs> {
+    if events.is_empty() {
+        return config;
+    }
+    let mut config = config.unwrap_or_default();
+    config
+        .triggers
+        .push(TriggerConfig::ProbeEvent(ProbeEventConfig { events }));
+    Some(config)
+}
+
+/// Add triggers on health changes of services to the spell triggers.
+/// Returns `None` if there are no triggers at all.
+pub fn add_health_triggers(
+    config: Option<SpellTriggerConfigs>,
+    events: Vec<HealthEventType>,
+) -> Option<SpellTriggerConfigs> {
+    if events.is_empty() {
+        return config;
+    }
+    let mut config = config.unwrap_or_default();
+    config
+        .triggers
+        .push(TriggerConfig::HealthEvent(HealthEventConfig { events }));
+    Some(config)
+}
+
+/// Add triggers on network partition changes to the spell triggers.
+/// Returns `None` if there are no triggers at all.
+pub fn add_partition_triggers(
+    config: Option<SpellTriggerConfigs>,
+    events: Vec<PartitionEventType>,
+) -> Option<SpellTriggerConfigs> {
+    if events.is_empty() {
+        return config;
+    }
+    let mut config = config.unwrap_or_default();
+    config
+        .triggers
+        .push(TriggerConfig::PartitionEvent(PartitionEventConfig {
+            events,
+        }));
+    Some(config)
+}
+
+/// Add triggers on events of custom trigger sources to the spell triggers.
+/// Returns `None` if there are no triggers at all.
+pub fn add_custom_triggers(
+    config: Option<SpellTriggerConfigs>,
+    events: Vec<CustomEventType>,
+) -> Option<SpellTriggerConfigs> {
+    if events.is_empty() {
+        return config;
+    }
+    let mut config = config.unwrap_or_default();
+    config
+        .triggers
+        .push(TriggerConfig::CustomEvent(CustomEventConfig { events }));
+    Some(config)
+}
+
+/// Add triggers on changes of KV keys of other spells to the spell triggers.
+/// Returns `None` if there are no triggers at all.
+pub fn add_kv_triggers(
+    config: Option<SpellTriggerConfigs>,
+    watches: Vec<KvWatch>,
+) -> Option<SpellTriggerConfigs> {
+    if watches.is_empty() {
+        return config;
+    }
+    let mut config = config.unwrap_or_default();
+    config
+        .triggers
+        .push(TriggerConfig::KvChange(KvChangeConfig { watches }));
+    Some(config)
+}
+
+/// Add the webhook trigger with the hash of its token to the spell triggers.
+/// Returns `None` if there are no triggers at all.
+pub fn add_webhook_trigger(
+    config: Option<SpellTriggerConfigs>,
+    token_hash: Option<String>,
+) -> Option<SpellTriggerConfigs> {
+    let Some(token_hash) = token_hash else {
+        return config;
+    };
+    let mut config = config.unwrap_or_default();
+    config
+        .triggers
+        .push(TriggerConfig::Webhook(WebhookConfig { token_hash }));
+    Some(config)
+}
+
+/// Set the payload template of the peer event triggers of the spell, if it has any
+pub fn add_peer_payload_template(
+    mut config: Option<SpellTriggerConfigs>,
+    template: Option<PeerPayloadTemplate>,
+) -> Option<SpellTriggerConfigs> {
+    let triggers = config.iter_mut().flat_map(|c| c.triggers.iter_mut());
+    for trigger in triggers {
+        if let TriggerConfig::PeerEvent(peer_config) = trigger {
+            peer_config.payload = template.clone();
+        }
+    }
+    config
+}
+
+/// Set the windows when the spell isn't triggered, validating them.
+/// Returns `None` if there are no triggers at all.
+pub fn add_exclusion_windows(
+    config: Option<SpellTriggerConfigs>,
+    windows: &[ExclusionWindow],
+) -> Result<Option<SpellTriggerConfigs>, ConfigError> {
+    let exclusions = windows
+        .iter()
+        .map(Exclusion::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(config.map(|mut config| {
+        config.exclusions = exclusions;
+        config
+    }))
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct SpellTriggerConfigs {
     pub(crate) triggers: Vec<TriggerConfig>,
+    /// Windows when no trigger fires
+    pub(crate) exclusions: Vec<Exclusion>,
 }
 
 impl SpellTriggerConfigs {
@@ -145,6 +531,7 @@ impl SpellTriggerConfigs {
         } else {
             Some(SpellTriggerConfigs {
                 triggers: new_triggers,
+                exclusions: self.exclusions,
             })
         }
     }
@@ -153,7 +540,15 @@ impl SpellTriggerConfigs {
 #[derive(Debug, Clone)]
 pub(crate) enum TriggerConfig {
     Timer(TimerConfig),
+    Cron(CronConfig),
     PeerEvent(PeerEventConfig),
+    ResourceEvent(ResourceEventConfig),
+    KvChange(KvChangeConfig),
+    Webhook(WebhookConfig),
+    ProbeEvent(ProbeEventConfig),
+    HealthEvent(HealthEventConfig),
+    PartitionEvent(PartitionEventConfig),
+    CustomEvent(CustomEventConfig),
 }
 
 impl TriggerConfig {
@@ -161,7 +556,8 @@ impl TriggerConfig {
         if let TriggerConfig::Timer(c) = self {
             c.into_rescheduled().map(TriggerConfig::Timer)
         } else {
-            // Peer events can't stop being relevant
+            // Cron schedules are validated to run at least once, so they can't stop being relevant
+            // as well as peer, resource, KV, webhook, probe, health, partition and custom events
             Some(self)
         }
     }
@@ -172,6 +568,8 @@ pub(crate) struct TimerConfig {
     pub(crate) period: Duration,
     pub(crate) start_at: Instant,
     pub(crate) end_at: Option<Instant>,
+    /// Runs to be made right away before the first run at `start_at`
+    pub(crate) missed_runs: u32,
 }
 
 impl TimerConfig {
@@ -180,6 +578,7 @@ impl TimerConfig {
             period,
             start_at,
             end_at,
+            missed_runs: 0,
         }
     }
 
@@ -190,7 +589,31 @@ impl TimerConfig {
             period: Duration::ZERO,
             start_at,
             end_at: Some(start_at),
+            missed_runs: 0,
+        }
+    }
+
+    /// Continue the schedule from the last run at `last_fired` instead of starting it anew.
+    /// Both `last_fired` and `now` are unix times in seconds.
+    fn apply_missed_runs(&mut self, policy: MissedRunPolicy, last_fired: u64, now: u64) {
+        let period = self.period.as_secs();
+        // Oneshot timers and timers which haven't started yet have nothing to catch up on
+        if period == 0 || now <= last_fired || self.start_at > Instant::now() {
+            return;
         }
+        let elapsed = now - last_fired;
+        let Some(next_at) =
+            Instant::now().checked_add(Duration::from_secs(period - elapsed % period))
+        else {
+            return;
+        };
+        let missed = u32::try_from(elapsed / period).unwrap_or(u32::MAX);
+        self.start_at = next_at;
+        self.missed_runs = match policy {
+            MissedRunPolicy::Skip => 0,
+            MissedRunPolicy::Once => missed.min(1),
+            MissedRunPolicy::All => missed.min(MAX_MISSED_RUNS),
+        };
     }
 
     pub fn into_rescheduled(self) -> Option<TimerConfig> {
@@ -207,17 +630,84 @@ impl TimerConfig {
     }
 }
 
+#[derive(Debug, Clone)]
+pub(crate) struct CronConfig {
+    pub(crate) schedule: CronSchedule,
+    /// Runs to be made right away before the next run by schedule
+    pub(crate) missed_runs: u32,
+}
+
+impl CronConfig {
+    /// Count the runs by schedule since the last run at `last_fired`.
+    /// Both `last_fired` and `now` are unix times in seconds.
+    fn apply_missed_runs(&mut self, policy: MissedRunPolicy, last_fired: u64, now: u64) {
+        let max = match policy {
+            MissedRunPolicy::Skip => 0,
+            MissedRunPolicy::Once => 1,
+            MissedRunPolicy::All => MAX_MISSED_RUNS,
+        };
+        self.missed_runs = self.schedule.runs_between(last_fired, now, max);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct PeerEventConfig {
     pub(crate) events: Vec<PeerEventType>,
+    /// Shape of the trigger in the mailbox, the default one if not set
+    pub(crate) payload: Option<PeerPayloadTemplate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResourceEventConfig {
+    pub(crate) events: Vec<ResourceEventType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ProbeEventConfig {
+    pub(crate) events: Vec<ProbeEventType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HealthEventConfig {
+    pub(crate) events: Vec<HealthEventType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PartitionEventConfig {
+    pub(crate) events: Vec<PartitionEventType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CustomEventConfig {
+    pub(crate) events: Vec<CustomEventType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KvChangeConfig {
+    pub(crate) watches: Vec<KvWatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct WebhookConfig {
+    pub(crate) token_hash: String,
 }
 
 #[cfg(test)]
 mod trigger_config_tests {
-    use crate::api::PeerEventType;
-    use crate::config::{PeerEventConfig, SpellTriggerConfigs, TimerConfig, TriggerConfig};
+    use crate::api::{PeerEvent, PeerEventDetails, PeerEventType};
+    use crate::config::{
+        add_exclusion_windows, add_kv_triggers, add_peer_payload_template, add_resource_triggers,
+        add_webhook_trigger, apply_missed_run_policy, from_user_config, ConfigError, CronConfig,
+        Exclusion, ExclusionWindow, MissedRunPolicy, PeerEventConfig, PeerPayloadTemplate,
+        SpellTriggerConfigs, TimerConfig, TriggerConfig, Weekday, MAX_MISSED_RUNS,
+    };
+    use crate::cron::{CronSchedule, CronSpec};
+    use connection_pool::Direction;
+    use fluence_libp2p::PeerId;
+    use fluence_spell_dtos::trigger_config::{ClockConfig, TriggerConfig as UserTriggerConfig};
+    use serde_json::json;
     use std::assert_matches::assert_matches;
-    use std::time::{Duration, Instant};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
     #[test]
     fn test_reschedule_ok_periodic() {
@@ -297,6 +787,7 @@ mod trigger_config_tests {
         let peer_events = vec![PeerEventType::Connected, PeerEventType::Disconnected];
         let peer_event_config = PeerEventConfig {
             events: peer_events,
+            payload: None,
         };
         let trigger_config = TriggerConfig::PeerEvent(peer_event_config);
         let rescheduled = trigger_config.into_rescheduled();
@@ -312,6 +803,7 @@ mod trigger_config_tests {
         let peer_events = vec![PeerEventType::Connected, PeerEventType::Disconnected];
         let peer_event_config = PeerEventConfig {
             events: peer_events,
+            payload: None,
         };
         let peer_trigger_config = TriggerConfig::PeerEvent(peer_event_config);
         let timer_config = TriggerConfig::Timer(TimerConfig::oneshot(
@@ -319,6 +811,7 @@ mod trigger_config_tests {
         ));
         let spell_trigger_config = SpellTriggerConfigs {
             triggers: vec![peer_trigger_config, timer_config],
+            exclusions: vec![],
         };
         let rescheduled = spell_trigger_config.into_rescheduled();
         assert!(
@@ -336,6 +829,7 @@ mod trigger_config_tests {
         let peer_events = vec![PeerEventType::Connected, PeerEventType::Disconnected];
         let peer_event_config = PeerEventConfig {
             events: peer_events,
+            payload: None,
         };
         let peer_trigger_config = TriggerConfig::PeerEvent(peer_event_config);
         let timer_config = TriggerConfig::Timer(TimerConfig::periodic(
@@ -345,6 +839,7 @@ mod trigger_config_tests {
         ));
         let spell_trigger_config = SpellTriggerConfigs {
             triggers: vec![peer_trigger_config, timer_config],
+            exclusions: vec![],
         };
         let rescheduled = spell_trigger_config.into_rescheduled();
         assert!(
@@ -356,4 +851,251 @@ mod trigger_config_tests {
             [TriggerConfig::PeerEvent(_), TriggerConfig::Timer(_)]
         );
     }
+
+    #[test]
+    fn test_missed_runs() {
+        let period = Duration::from_secs(60);
+        let now = 1_000_000;
+        // 100 periods and a half have passed since the last run
+        let last_fired = now - 100 * 60 - 30;
+        let timer = TimerConfig::periodic(period, Instant::now(), None);
+
+        let mut skip = timer.clone();
+        skip.apply_missed_runs(MissedRunPolicy::Skip, last_fired, now);
+        assert_eq!(skip.missed_runs, 0);
+        // The next run is aligned to the schedule of the last run
+        let next_in = skip.start_at - Instant::now();
+        assert!(next_in <= Duration::from_secs(30) && next_in > Duration::from_secs(25));
+
+        let mut once = timer.clone();
+        once.apply_missed_runs(MissedRunPolicy::Once, last_fired, now);
+        assert_eq!(once.missed_runs, 1);
+
+        let mut all = timer.clone();
+        all.apply_missed_runs(MissedRunPolicy::All, last_fired, now);
+        assert_eq!(all.missed_runs, MAX_MISSED_RUNS);
+
+        let mut all = timer.clone();
+        all.apply_missed_runs(MissedRunPolicy::All, now - 150, now);
+        assert_eq!(all.missed_runs, 2);
+
+        // Nothing is missed when the last run was within the period
+        let mut once = timer;
+        once.apply_missed_runs(MissedRunPolicy::Once, now - 10, now);
+        assert_eq!(once.missed_runs, 0);
+    }
+
+    #[test]
+    fn test_cron_from_user_config() {
+        let cron = CronSpec {
+            schedule: "0 9 * * mon-fri".to_string(),
+            timezone: Some("Europe/Berlin".to_string()),
+        };
+        // The cron schedule alone is enough to trigger the spell
+        let config = from_user_config(&UserTriggerConfig::default(), Some(&cron))
+            .unwrap()
+            .unwrap();
+        assert_matches!(&config.triggers[..], [TriggerConfig::Cron(_)]);
+
+        let invalid = CronSpec {
+            schedule: "0 9 * * mon-fri".to_string(),
+            timezone: Some("Europe/Atlantis".to_string()),
+        };
+        assert_matches!(
+            from_user_config(&UserTriggerConfig::default(), Some(&invalid)),
+            Err(ConfigError::InvalidCron(_))
+        );
+    }
+
+    #[test]
+    fn test_missed_cron_runs() {
+        let schedule = CronSchedule::parse(&CronSpec {
+            schedule: "@hourly".to_string(),
+            timezone: None,
+        })
+        .unwrap();
+        // Monday, 1 January 2024 00:00:00 UTC
+        let last_fired = 1704067200;
+        let cron = CronConfig {
+            schedule,
+            missed_runs: 0,
+        };
+
+        let mut skip = cron.clone();
+        skip.apply_missed_runs(MissedRunPolicy::Skip, last_fired, last_fired + 3 * 3600);
+        assert_eq!(skip.missed_runs, 0);
+
+        let mut once = cron.clone();
+        once.apply_missed_runs(MissedRunPolicy::Once, last_fired, last_fired + 3 * 3600);
+        assert_eq!(once.missed_runs, 1);
+
+        let mut all = cron.clone();
+        all.apply_missed_runs(MissedRunPolicy::All, last_fired, last_fired + 3 * 3600);
+        assert_eq!(all.missed_runs, 3);
+
+        let mut all = cron;
+        all.apply_missed_runs(MissedRunPolicy::All, last_fired, last_fired + 100 * 3600);
+        assert_eq!(all.missed_runs, MAX_MISSED_RUNS);
+    }
+
+    /// Stored triggers are added before the missed runs are applied, which must not change
+    /// anything for a spell run only by its timer
+    #[test]
+    fn test_missed_runs_of_timer_only_spell() {
+        let user_config = UserTriggerConfig {
+            clock: ClockConfig {
+                start_sec: 1,
+                end_sec: 0,
+                period_sec: 60,
+            },
+            ..Default::default()
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let last_fired = Some(now - 150);
+        let config = from_user_config(&user_config, None)
+            .unwrap()
+            .and_then(|c| c.into_rescheduled());
+
+        // Stored triggers of a spell which has none
+        let with_stored = add_resource_triggers(config.clone(), vec![]);
+        let with_stored = add_kv_triggers(with_stored, vec![]);
+        let with_stored = add_webhook_trigger(with_stored, None);
+        let with_stored = add_peer_payload_template(with_stored, None);
+        let with_stored = add_exclusion_windows(with_stored, &[]).unwrap();
+        let config = apply_missed_run_policy(with_stored, MissedRunPolicy::All, last_fired);
+
+        let triggers = config.unwrap().triggers;
+        let [TriggerConfig::Timer(timer)] = &triggers[..] else {
+            panic!("expected only the timer trigger, got {triggers:?}");
+        };
+        assert_eq!(timer.missed_runs, 2);
+        assert_eq!(timer.period, Duration::from_secs(60));
+        // The next run is aligned to the schedule of the last run
+        let next_in = timer.start_at.saturating_duration_since(Instant::now());
+        assert!(next_in <= Duration::from_secs(30) && next_in > Duration::from_secs(25));
+    }
+
+    #[test]
+    fn test_missed_runs_oneshot() {
+        let mut timer = TimerConfig::oneshot(Instant::now());
+        timer.apply_missed_runs(MissedRunPolicy::All, 0, 1_000_000);
+        assert_eq!(timer.missed_runs, 0);
+    }
+
+    fn window(weekdays: Vec<Weekday>, start: &str, end: &str) -> Result<Exclusion, ConfigError> {
+        Exclusion::parse(&ExclusionWindow {
+            weekdays,
+            start: start.to_string(),
+            end: end.to_string(),
+        })
+    }
+
+    // Sunday, 7 January 2024, 00:00 UTC
+    const SUNDAY: u64 = 1_704_585_600;
+    const HOUR: u64 = 60 * 60;
+
+    #[test]
+    fn test_exclusion_window() {
+        let window = window(vec![Weekday::Sun], "02:00", "03:00").unwrap();
+        assert!(!window.contains(SUNDAY + HOUR));
+        assert!(window.contains(SUNDAY + 2 * HOUR));
+        assert!(window.contains(SUNDAY + 3 * HOUR - 1));
+        assert!(!window.contains(SUNDAY + 3 * HOUR));
+        // Monday
+        assert!(!window.contains(SUNDAY + 26 * HOUR));
+        // Next Sunday
+        assert!(window.contains(SUNDAY + 7 * 24 * HOUR + 2 * HOUR));
+    }
+
+    #[test]
+    fn test_exclusion_window_past_midnight() {
+        let window = window(vec![Weekday::Sat], "23:00", "01:00").unwrap();
+        // Saturday 23:30
+        assert!(window.contains(SUNDAY - HOUR / 2));
+        // Sunday 00:30 still belongs to the Saturday window
+        assert!(window.contains(SUNDAY + HOUR / 2));
+        assert!(!window.contains(SUNDAY + HOUR));
+        // Sunday 23:30
+        assert!(!window.contains(SUNDAY + 23 * HOUR + HOUR / 2));
+    }
+
+    #[test]
+    fn test_exclusion_window_every_day() {
+        let window = window(vec![], "12:00", "12:30").unwrap();
+        for day in 0..7 {
+            assert!(window.contains(SUNDAY + day * 24 * HOUR + 12 * HOUR));
+            assert!(!window.contains(SUNDAY + day * 24 * HOUR + 13 * HOUR));
+        }
+    }
+
+    #[test]
+    fn test_invalid_exclusion_windows() {
+        assert_matches!(
+            window(vec![], "24:00", "01:00"),
+            Err(ConfigError::InvalidExclusionWindow(_))
+        );
+        assert_matches!(
+            window(vec![], "2am", "3am"),
+            Err(ConfigError::InvalidExclusionWindow(_))
+        );
+        assert_matches!(
+            window(vec![], "02:00", "02:00"),
+            Err(ConfigError::InvalidExclusionWindow(_))
+        );
+    }
+
+    #[test]
+    fn test_exclusions_without_triggers() {
+        let windows = vec![ExclusionWindow {
+            weekdays: vec![],
+            start: "02:00".to_string(),
+            end: "03:00".to_string(),
+        }];
+        assert!(add_exclusion_windows(None, &windows).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_peer_payload_template() {
+        let template = PeerPayloadTemplate::parse(json!({
+            "peer": "$peer_id",
+            "meta": ["$event", "$direction", "$timestamp"],
+            "protocols": "$protocols",
+            "price": "$$5",
+            "kind": "connection"
+        }))
+        .unwrap();
+        let peer_id = PeerId::random();
+        let event = PeerEvent {
+            peer_id,
+            connected: true,
+            details: PeerEventDetails {
+                direction: Some(Direction::Inbound),
+                protocols: vec!["/fluence/particle/2.0.0".to_string()],
+                timestamp: 1700000000,
+            },
+            payload: None,
+        };
+
+        assert_eq!(
+            template.render(&event),
+            json!({
+                "peer": peer_id.to_string(),
+                "meta": ["connected", "inbound", 1700000000],
+                "protocols": ["/fluence/particle/2.0.0"],
+                "price": "$5",
+                "kind": "connection"
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_peer_payload_template() {
+        assert_matches!(
+            PeerPayloadTemplate::parse(json!({"peer": "$peer"})),
+            Err(ConfigError::InvalidPayloadTemplate(_))
+        );
+    }
 }
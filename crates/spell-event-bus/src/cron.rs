@@ -0,0 +1,362 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Cron schedules of spells.
+//!
+//! The schedule is parsed here rather than with the `cron` or `croner` crates:
+//! - `cron` expects a seconds field in front of the classic five and matches the day of month
+//!   together with the day of week, while users write classic cron specs where either day matches;
+//! - catching up on missed runs needs the number of runs between two moments, capped at
+//!   a maximum, which is a loop over [`CronSchedule::next_after`] here.
+//!
+//! Timezones are IANA names from `chrono-tz`. The schedule is matched against the local time
+//! of the zone, so a spell scheduled at 09:00 in "Europe/Berlin" runs at 09:00 both in winter
+//! and in summer. A local time skipped by a DST change doesn't run, a local time repeated by it
+//! runs once, at its first occurrence.
+use crate::config::ConfigError;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MINUTES_IN_DAY: u32 = 24 * 60;
+/// How far ahead the next run is looked for, long enough for schedules running on February 29
+const MAX_SEARCH_DAYS: u32 = 366 * 8;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Cron trigger of a spell as set by users, e.g. `{"schedule": "0 */6 * * *", "timezone": "Europe/Berlin"}`.
+/// The schedule has the usual five fields: minute, hour, day of month, month and day of week,
+/// or one of `@hourly`, `@daily`, `@weekly`, `@monthly`, `@yearly`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CronSpec {
+    pub schedule: String,
+    /// IANA timezone the schedule is in, e.g. "Europe/Berlin" or "America/New_York". UTC if not set.
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+/// Parsed [`CronSpec`], bit `n` of a field is set if the field matches `n`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    /// Whether the day of month and the day of week are both restricted,
+    /// then a day matching either of them matches as in the classic cron
+    any_day: bool,
+    timezone: Tz,
+}
+
+impl CronSchedule {
+    pub fn parse(spec: &CronSpec) -> Result<Self, ConfigError> {
+        let schedule = match spec.schedule.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            schedule => schedule,
+        };
+        let fields: Vec<&str> = schedule.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(ConfigError::InvalidCron(format!(
+                "expected 5 fields in '{}', got {}",
+                spec.schedule,
+                fields.len()
+            )));
+        };
+        let weekdays_mask = parse_field(weekdays, 0, 7, &WEEKDAY_NAMES)?;
+        let cron = Self {
+            minutes: parse_field(minutes, 0, 59, &[])?,
+            hours: parse_field(hours, 0, 23, &[])? as u32,
+            days: parse_field(days, 1, 31, &[])? as u32,
+            months: parse_field(months, 1, 12, &MONTH_NAMES)? as u16,
+            // 7 is Sunday as well as 0
+            weekdays: (weekdays_mask | (weekdays_mask >> 7)) as u8 & 0x7f,
+            any_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+            timezone: parse_timezone(spec.timezone.as_deref())?,
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs();
+        if cron.next_after(now).is_none() {
+            return Err(ConfigError::InvalidCron(format!(
+                "'{}' never runs",
+                spec.schedule
+            )));
+        }
+        Ok(cron)
+    }
+
+    /// Unix time in seconds of the first run strictly after the unix time `timestamp`
+    pub fn next_after(&self, timestamp: u64) -> Option<u64> {
+        let timestamp = i64::try_from(timestamp).ok()?;
+        let local = DateTime::from_timestamp(timestamp, 0)?
+            .with_timezone(&self.timezone)
+            .naive_local();
+        let mut day = local.date();
+        let mut first_minute = local.hour() * 60 + local.minute() + 1;
+        for _ in 0..MAX_SEARCH_DAYS {
+            if self.matches_day(day) {
+                for minute_of_day in first_minute..MINUTES_IN_DAY {
+                    let (hour, minute) = (minute_of_day / 60, minute_of_day % 60);
+                    if self.hours & 1 << hour == 0 || self.minutes & 1 << minute == 0 {
+                        continue;
+                    }
+                    let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+                    let run = self
+                        .timezone
+                        .from_local_datetime(&day.and_time(time))
+                        .earliest()
+                        .map(|run| run.timestamp());
+                    // Runs before `timestamp` are possible when the local time is repeated
+                    if let Some(run) = run.filter(|run| *run > timestamp) {
+                        return u64::try_from(run).ok();
+                    }
+                }
+            }
+            day = day.succ_opt()?;
+            first_minute = 0;
+        }
+        None
+    }
+
+    /// Number of runs after `since` up to `until`, both are unix times in seconds. Stops counting at `max`.
+    pub(crate) fn runs_between(&self, since: u64, until: u64, max: u32) -> u32 {
+        let mut runs = 0;
+        let mut timestamp = since;
+        while runs < max {
+            match self.next_after(timestamp) {
+                Some(next) if next <= until => {
+                    runs += 1;
+                    timestamp = next;
+                }
+                _ => break,
+            }
+        }
+        runs
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & 1 << date.month() == 0 {
+            return false;
+        }
+        let day_matches = self.days & 1 << date.day() != 0;
+        let weekday_matches = self.weekdays & 1 << date.weekday().num_days_from_sunday() != 0;
+        if self.any_day {
+            day_matches || weekday_matches
+        } else {
+            day_matches && weekday_matches
+        }
+    }
+}
+
+/// Parse a cron field like `*/15`, `1-5`, `mon,wed,fri` into a bitmask
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, ConfigError> {
+    let invalid = |reason: &str| ConfigError::InvalidCron(format!("field '{field}' {reason}"));
+    let value = |v: &str| -> Result<u32, ConfigError> {
+        let v = match names.iter().position(|name| name.eq_ignore_ascii_case(v)) {
+            // Months are counted from 1, weekdays from 0
+            Some(position) => position as u32 + min,
+            None => v.parse().map_err(|_| invalid("isn't a number"))?,
+        };
+        if v < min || v > max {
+            return Err(invalid(&format!("is out of range {min}-{max}")));
+        }
+        Ok(v)
+    };
+
+    let mut mask = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| invalid("has invalid step"))?;
+                if step == 0 {
+                    return Err(invalid("has zero step"));
+                }
+                (range, step)
+            }
+            None => (item, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // `5/15` runs from 5 to the end of the range
+                None if step > 1 => (value(range)?, max),
+                None => {
+                    let v = value(range)?;
+                    (v, v)
+                }
+            },
+        };
+        if start > end {
+            return Err(invalid("has a reversed range"));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+/// Parse an IANA timezone name like "Europe/Berlin", UTC if not set
+fn parse_timezone(timezone: Option<&str>) -> Result<Tz, ConfigError> {
+    match timezone.map(str::trim) {
+        None | Some("") => Ok(Tz::UTC),
+        Some(timezone) => timezone.parse().map_err(|_| {
+            ConfigError::InvalidCron(format!(
+                "unknown timezone '{timezone}', expected an IANA name like Europe/Berlin"
+            ))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cron(schedule: &str, timezone: Option<&str>) -> CronSchedule {
+        CronSchedule::parse(&CronSpec {
+            schedule: schedule.to_string(),
+            timezone: timezone.map(str::to_string),
+        })
+        .expect("valid cron")
+    }
+
+    // Monday, 1 January 2024 00:00:00 UTC
+    const MONDAY: u64 = 1704067200;
+    const HOUR: u64 = 60 * 60;
+
+    #[test]
+    fn test_next_after() {
+        let every_6_hours = cron("0 */6 * * *", None);
+        assert_eq!(every_6_hours.next_after(MONDAY), Some(MONDAY + 6 * HOUR));
+        assert_eq!(
+            every_6_hours.next_after(MONDAY - 1),
+            Some(MONDAY),
+            "runs at the start of the next minute"
+        );
+
+        let weekdays = cron("30 9 * * mon-fri", None);
+        // Friday 09:30 is followed by Monday 09:30
+        let friday = MONDAY + 4 * 24 * HOUR + 9 * HOUR + 30 * 60;
+        assert_eq!(
+            weekdays.next_after(MONDAY),
+            Some(MONDAY + 9 * HOUR + 30 * 60)
+        );
+        assert_eq!(weekdays.next_after(friday), Some(friday + 3 * 24 * HOUR));
+
+        // 29 February 2024
+        let leap_day = cron("0 0 29 feb *", None);
+        assert_eq!(leap_day.next_after(MONDAY), Some(MONDAY + 59 * 24 * HOUR));
+    }
+
+    #[test]
+    fn test_day_of_month_or_weekday() {
+        // Either the 15th or a Sunday
+        let cron = cron("0 0 15 * 0", None);
+        assert_eq!(cron.next_after(MONDAY), Some(MONDAY + 6 * 24 * HOUR));
+        assert_eq!(
+            cron.next_after(MONDAY + 13 * 24 * HOUR),
+            Some(MONDAY + 14 * 24 * HOUR)
+        );
+    }
+
+    #[test]
+    fn test_timezone() {
+        let utc = cron("@daily", None);
+        let ahead = cron("@daily", Some("Asia/Tokyo"));
+        let behind = cron("0 0 * * *", Some("America/St_Johns"));
+        assert_eq!(utc.next_after(MONDAY), Some(MONDAY + 24 * HOUR));
+        assert_eq!(ahead.next_after(MONDAY), Some(MONDAY + 15 * HOUR));
+        assert_eq!(behind.next_after(MONDAY), Some(MONDAY + 3 * HOUR + 30 * 60));
+    }
+
+    // Sunday, 31 March 2024 00:00:00 UTC, clocks in Berlin go from 02:00 to 03:00 at 01:00 UTC
+    const SPRING_FORWARD: u64 = 1711843200;
+    // Sunday, 27 October 2024 00:00:00 UTC, clocks in Berlin go from 03:00 to 02:00 at 01:00 UTC
+    const FALL_BACK: u64 = 1729987200;
+
+    #[test]
+    fn test_dst() {
+        // The local time stays the same across the DST change
+        let morning = cron("0 9 * * *", Some("Europe/Berlin"));
+        // 09:00 CET is 08:00 UTC
+        assert_eq!(
+            morning.next_after(SPRING_FORWARD - 24 * HOUR),
+            Some(SPRING_FORWARD - 24 * HOUR + 8 * HOUR)
+        );
+        // 09:00 CEST is 07:00 UTC
+        assert_eq!(
+            morning.next_after(SPRING_FORWARD),
+            Some(SPRING_FORWARD + 7 * HOUR)
+        );
+
+        // 02:30 doesn't exist on the day clocks go forward
+        let skipped = cron("30 2 * * *", Some("Europe/Berlin"));
+        assert_eq!(
+            skipped.next_after(SPRING_FORWARD),
+            Some(SPRING_FORWARD + 24 * HOUR + 30 * 60)
+        );
+
+        // 02:30 happens twice on the day clocks go back, the spell runs at the first one
+        let repeated = cron("30 2 * * *", Some("Europe/Berlin"));
+        let first = FALL_BACK + 30 * 60;
+        assert_eq!(repeated.next_after(FALL_BACK), Some(first));
+        assert_eq!(
+            repeated.next_after(first),
+            Some(FALL_BACK + 24 * HOUR + HOUR + 30 * 60)
+        );
+    }
+
+    #[test]
+    fn test_runs_between() {
+        let hourly = cron("@hourly", None);
+        assert_eq!(hourly.runs_between(MONDAY, MONDAY + 5 * HOUR, 16), 5);
+        assert_eq!(hourly.runs_between(MONDAY, MONDAY + 5 * HOUR, 2), 2);
+        assert_eq!(hourly.runs_between(MONDAY, MONDAY + HOUR - 1, 16), 0);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let parse = |schedule: &str, timezone: Option<&str>| {
+            CronSchedule::parse(&CronSpec {
+                schedule: schedule.to_string(),
+                timezone: timezone.map(str::to_string),
+            })
+        };
+        assert!(parse("* * * *", None).is_err());
+        assert!(parse("60 * * * *", None).is_err());
+        assert!(parse("*/0 * * * *", None).is_err());
+        assert!(parse("5-1 * * * *", None).is_err());
+        assert!(parse("0 0 * foo *", None).is_err());
+        assert!(parse("0 0 31 feb *", None).is_err());
+        assert!(parse("0 0 * * *", Some("Europe/Berlin")).is_ok());
+        assert!(parse("0 0 * * *", Some("Mars/Olympus_Mons")).is_err());
+        assert!(parse("0 0 * * *", Some("+05:30")).is_err());
+    }
+}
@@ -23,3 +23,5 @@
 pub mod api;
 pub mod bus;
 mod config;
+mod cron;
+mod source;
@@ -0,0 +1,66 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use futures::stream::BoxStream;
+use serde::{Deserialize, Serialize};
+
+/// An event produced by a [`TriggerSource`]. The bus stamps it with the name of the source
+/// and the time it was received before passing it to the spells as a `CustomEvent`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SourceEvent {
+    /// Kind of the event within its source, spells subscribe to kinds, e.g. an MQTT topic
+    pub kind: String,
+    /// Event data, passed to the spells as is
+    pub payload: String,
+}
+
+/// A source of spell triggers added by an embedder of the node, e.g. MQTT messages or
+/// file system watches. Spells subscribe to the events of the source by its name and
+/// the kinds of the events with `spell.set_custom_triggers`.
+pub trait TriggerSource: Send {
+    /// Name of the source, unique among the sources of the bus
+    fn name(&self) -> String;
+
+    /// Events of the source, the stream is polled once the bus starts scheduling
+    fn events(self: Box<Self>) -> BoxStream<'static, SourceEvent>;
+}
+
+/// A [`TriggerSource`] made of a name and a stream, for the sources that don't need their own type
+pub struct StreamSource {
+    name: String,
+    events: BoxStream<'static, SourceEvent>,
+}
+
+impl StreamSource {
+    pub fn new(name: impl Into<String>, events: BoxStream<'static, SourceEvent>) -> Self {
+        Self {
+            name: name.into(),
+            events,
+        }
+    }
+}
+
+impl TriggerSource for StreamSource {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn events(self: Box<Self>) -> BoxStream<'static, SourceEvent> {
+        self.events
+    }
+}
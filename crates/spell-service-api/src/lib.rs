@@ -20,12 +20,20 @@
 use fluence_libp2p::PeerId;
 use fluence_spell_dtos::trigger_config::{TriggerConfig, TriggerConfigValue};
 use fluence_spell_dtos::value::{ScriptValue, SpellValueT, StringValue, U32Value, UnitValue};
+use json_utils::serialized_size;
+use particle_args::{ErrorCode, ErrorCoded};
 use particle_execution::{FunctionOutcome, ParticleParams};
-use particle_services::{ParticleAppServices, PeerScope};
+use particle_services::{ParticleAppServices, PeerScope, INCR_U32, SET_STRING_IF_EQUALS};
 use serde::de::DeserializeOwned;
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
+use std::iter::Peekable;
 use std::time::Duration;
 
+/// Default max size of JSON data written to the spell KV at once, 1 MiB
+pub const DEFAULT_MAX_KV_DATA_SIZE: usize = 1024 * 1024;
+/// KV data objects are written in chunks of whole fields of about this size
+const KV_CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(Debug, thiserror::Error)]
 pub enum CallError {
     #[error("Spell {spell_id} not found (function {function_name})")]
@@ -57,6 +65,23 @@ pub enum CallError {
         function_name: String,
         reason: String,
     },
+    #[error("Data for {spell_id}.{function_name} is {size} bytes, the limit is {limit} bytes")]
+    TooLarge {
+        spell_id: String,
+        function_name: String,
+        size: usize,
+        limit: usize,
+    },
+}
+
+impl ErrorCoded for CallError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            CallError::ServiceNotFound { .. } => ErrorCode::SpellNotFound,
+            CallError::TooLarge { .. } => ErrorCode::QuotaExceeded,
+            _ => ErrorCode::Internal,
+        }
+    }
 }
 
 struct Function {
@@ -123,11 +148,21 @@ impl CallParams {
 #[derive(Clone, Debug)]
 pub struct SpellServiceApi {
     services: ParticleAppServices,
+    /// Max size of JSON data written to the spell KV by [`SpellServiceApi::update_kv`]
+    max_kv_data_size: usize,
 }
 
 impl SpellServiceApi {
     pub fn new(services: ParticleAppServices) -> Self {
-        Self { services }
+        Self {
+            services,
+            max_kv_data_size: DEFAULT_MAX_KV_DATA_SIZE,
+        }
+    }
+
+    pub fn with_max_kv_data_size(mut self, max_kv_data_size: usize) -> Self {
+        self.max_kv_data_size = max_kv_data_size;
+        self
     }
 
     pub async fn set_script(&self, params: CallParams, script: String) -> Result<(), CallError> {
@@ -169,13 +204,35 @@ impl SpellServiceApi {
         Ok(trigger_config_value.config)
     }
 
+    /// Write the fields of the JSON object to the spell KV. Data bigger than the limit is rejected
+    /// with [`CallError::TooLarge`] before anything is written. Big objects are written in chunks,
+    /// so the whole data is never stringified at once.
     // TODO: use `Map<String, Value>` for init_data instead of `Value`
     pub async fn update_kv(&self, params: CallParams, kv_data: Value) -> Result<(), CallError> {
-        let function = Function {
-            name: "set_json_fields",
-            args: vec![json!(kv_data.to_string())],
+        let size = serialized_size(&kv_data);
+        if size > self.max_kv_data_size {
+            return Err(CallError::TooLarge {
+                spell_id: params.spell_id,
+                function_name: "set_json_fields".to_string(),
+                size,
+                limit: self.max_kv_data_size,
+            });
+        }
+
+        let chunks: Box<dyn Iterator<Item = String> + Send + '_> = match &kv_data {
+            Value::Object(fields) if size > KV_CHUNK_SIZE => {
+                Box::new(ObjectChunks::new(fields, KV_CHUNK_SIZE))
+            }
+            // non-objects are passed as is to get the error from the spell
+            kv_data => Box::new(std::iter::once(kv_data.to_string())),
         };
-        let _ = self.call::<UnitValue>(params, function).await?;
+        for chunk in chunks {
+            let function = Function {
+                name: "set_json_fields",
+                args: vec![json!(chunk)],
+            };
+            let _ = self.call::<UnitValue>(params.clone(), function).await?;
+        }
         Ok(())
     }
 
@@ -206,6 +263,15 @@ impl SpellServiceApi {
         Ok(())
     }
 
+    pub async fn remove_key(&self, params: CallParams, key: String) -> Result<(), CallError> {
+        let function = Function {
+            name: "remove_key",
+            args: vec![json!(key)],
+        };
+        let _ = self.call::<UnitValue>(params, function).await?;
+        Ok(())
+    }
+
     /// Load the counter (how many times the spell was run)
     pub async fn get_counter(&self, params: CallParams) -> Result<Option<u32>, CallError> {
         let function = Function {
@@ -216,6 +282,70 @@ impl SpellServiceApi {
         Ok((!result.absent).then_some(result.value))
     }
 
+    pub async fn get_u32(&self, params: CallParams, key: String) -> Result<Option<u32>, CallError> {
+        let function = Function {
+            name: "get_u32",
+            args: vec![json!(key)],
+        };
+        let result = self.call::<U32Value>(params, function).await?;
+        Ok((!result.absent).then_some(result.value))
+    }
+
+    pub async fn set_u32(
+        &self,
+        params: CallParams,
+        key: String,
+        value: u32,
+    ) -> Result<(), CallError> {
+        let function = Function {
+            name: "set_u32",
+            args: vec![json!(key), json!(value)],
+        };
+        let _ = self.call::<UnitValue>(params, function).await?;
+        Ok(())
+    }
+
+    /// Set the string `key` to `value` only if its current value is `expected`,
+    /// `None` meaning that the key must be absent.
+    /// Returns whether the value was set.
+    ///
+    /// The node makes the read and the write under the spell instance lock,
+    /// so the check is atomic relative to any other call to the spell.
+    pub async fn set_string_if_equals(
+        &self,
+        params: CallParams,
+        key: String,
+        expected: Option<String>,
+        value: String,
+    ) -> Result<bool, CallError> {
+        let function = Function {
+            name: SET_STRING_IF_EQUALS,
+            args: vec![json!(key), json!(expected), json!(value)],
+        };
+        let previous = self.call::<StringValue>(params, function).await?;
+        let previous = (!previous.absent).then_some(previous.value);
+        Ok(previous == expected)
+    }
+
+    /// Add `delta` to the u32 `key`, an absent key is considered to be 0.
+    /// Returns the new value.
+    ///
+    /// The node makes the read and the write under the spell instance lock,
+    /// so increments are never lost, whoever else writes to the spell.
+    pub async fn incr_u32(
+        &self,
+        params: CallParams,
+        key: String,
+        delta: u32,
+    ) -> Result<u32, CallError> {
+        let function = Function {
+            name: INCR_U32,
+            args: vec![json!(key), json!(delta)],
+        };
+        let result = self.call::<U32Value>(params, function).await?;
+        Ok(result.value)
+    }
+
     /// Update the counter (how many times the spell was run)
     /// TODO: permission check here or not?
     pub async fn set_counter(&self, params: CallParams, counter: u32) -> Result<(), CallError> {
@@ -296,6 +426,47 @@ impl SpellServiceApi {
     }
 }
 
+/// Splits a JSON object into JSON objects of whole fields, serialized one chunk at a time.
+/// A chunk exceeds the chunk size only if it consists of a single big field.
+struct ObjectChunks<'a> {
+    fields: Peekable<serde_json::map::Iter<'a>>,
+    chunk_size: usize,
+}
+
+impl<'a> ObjectChunks<'a> {
+    fn new(fields: &'a Map<String, Value>, chunk_size: usize) -> Self {
+        Self {
+            fields: fields.iter().peekable(),
+            chunk_size,
+        }
+    }
+}
+
+impl Iterator for ObjectChunks<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fields.peek()?;
+        let mut chunk = String::from("{");
+        while let Some((key, value)) = self.fields.peek() {
+            // `"key":value` and a comma
+            let field_size = serialized_size(key) + 1 + serialized_size(value) + 1;
+            if chunk.len() > 1 && chunk.len() + field_size > self.chunk_size {
+                break;
+            }
+            if chunk.len() > 1 {
+                chunk.push(',');
+            }
+            chunk.push_str(&Value::String(key.to_string()).to_string());
+            chunk.push(':');
+            chunk.push_str(&value.to_string());
+            self.fields.next();
+        }
+        chunk.push('}');
+        Some(chunk)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -317,11 +488,11 @@ mod tests {
     use fluence_spell_dtos::trigger_config::TriggerConfig;
     use fluence_spell_dtos::value::*;
     use maplit::hashmap;
-    use serde_json::json;
+    use serde_json::{json, Value};
     use std::time::Duration;
     use workers::{KeyStorage, PeerScopes, Workers};
 
-    use crate::{CallParams, SpellServiceApi};
+    use crate::{CallError, CallParams, ObjectChunks, SpellServiceApi};
 
     const TTL: Duration = Duration::from_millis(100000);
 
@@ -544,6 +715,112 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_object_chunks() {
+        let fields = json!({
+            "a": "x".repeat(10),
+            "b": 1,
+            "c": "y".repeat(40),
+            "d": [1, 2, 3],
+        });
+        let fields = fields.as_object().unwrap();
+        let chunks: Vec<String> = ObjectChunks::new(fields, 32).collect();
+        // the big field gets a chunk of its own
+        assert_eq!(chunks.len(), 3);
+        let mut merged = serde_json::Map::new();
+        for chunk in chunks {
+            let chunk: Value = serde_json::from_str(&chunk).unwrap();
+            merged.extend(chunk.as_object().unwrap().clone());
+        }
+        assert_eq!(&merged, fields);
+
+        assert_eq!(ObjectChunks::new(&serde_json::Map::new(), 32).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_kv_data_limit() {
+        let (api, params) = setup().await;
+        let api = api.with_max_kv_data_size(16);
+        let result = api
+            .update_kv(params, json!({ "h_big": "x".repeat(16) }))
+            .await;
+        assert!(
+            matches!(result, Err(CallError::TooLarge { limit: 16, .. })),
+            "must reject data over the limit: {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_string_if_equals() {
+        let (api, params) = setup().await;
+        let key = "owner".to_string();
+
+        let result = api
+            .set_string_if_equals(params.clone(), key.clone(), None, "a".to_string())
+            .await;
+        assert!(result.unwrap(), "must set an absent key");
+
+        let result = api
+            .set_string_if_equals(params.clone(), key.clone(), None, "b".to_string())
+            .await;
+        assert!(
+            !result.unwrap(),
+            "must not set a present key expected to be absent"
+        );
+
+        let result = api
+            .set_string_if_equals(
+                params.clone(),
+                key.clone(),
+                Some("a".to_string()),
+                "c".to_string(),
+            )
+            .await;
+        assert!(result.unwrap(), "must set a key with the expected value");
+
+        let value = api.get_string(params, key).await.unwrap();
+        assert_eq!(value, Some("c".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_set_string_if_equals_call() {
+        let (api, params) = setup().await;
+        api.set_string(params.clone(), "owner".to_string(), "a".to_string())
+            .await
+            .unwrap();
+
+        // scripts call the function on the spell service and get the previous value
+        let function = super::Function {
+            name: particle_services::SET_STRING_IF_EQUALS,
+            args: vec![json!("owner"), json!("a"), json!("b")],
+        };
+        let previous = api
+            .call::<StringValue>(params.clone(), function)
+            .await
+            .unwrap();
+        assert!(!previous.absent);
+        assert_eq!(previous.value, "a");
+
+        let value = api.get_string(params, "owner".to_string()).await.unwrap();
+        assert_eq!(value, Some("b".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_incr_u32() {
+        let (api, params) = setup().await;
+        let key = "visits".to_string();
+
+        let tasks = (0..5).map(|_| api.incr_u32(params.clone(), key.clone(), 2));
+        let results = futures::future::join_all(tasks).await;
+        assert!(results.iter().all(|r| r.is_ok()), "{:?}", results);
+
+        let value = api.get_u32(params.clone(), key.clone()).await.unwrap();
+        assert_eq!(value, Some(10), "concurrent increments must not be lost");
+
+        let result = api.incr_u32(params, key, u32::MAX).await;
+        assert!(result.is_err(), "overflow must be reported");
+    }
+
     #[tokio::test]
     async fn test_trigger_event() {
         let (api, params) = setup().await;
@@ -26,7 +26,7 @@ use futures::{FutureExt, StreamExt, TryStreamExt};
 use libp2p::PeerId;
 use particle_execution::FunctionOutcome;
 use particle_modules::{AddBlueprint, ModuleRepository};
-use particle_services::{ParticleAppServices, PeerScope, ServiceError, ServiceType};
+use particle_services::{Labels, ParticleAppServices, PeerScope, ServiceError, ServiceType};
 use serde_json::{json, Value as JValue};
 use sorcerer::{install_spell, remove_spell};
 use spell_event_bus::api::{SpellEventBusApi, SpellId};
@@ -208,7 +208,8 @@ impl Deployer {
             );
         }
 
-        let trigger_config = spell_event_bus::api::from_user_config(&spell_distro.trigger_config)?;
+        let trigger_config =
+            spell_event_bus::api::from_user_config(&spell_distro.trigger_config, None)?;
         let params = CallParams::new(
             self.host_peer_id,
             PeerScope::Host,
@@ -269,9 +270,12 @@ impl Deployer {
             get_deployer_particle_id(),
             DEPLOYER_TTL,
             spell_distro.trigger_config,
+            None,
             spell_distro.air.to_string(),
             json!(spell_distro.kv),
             self.host_peer_id,
+            Labels::new(),
+            None,
         )
         .await
         .map_err(|e| eyre!(e))?;
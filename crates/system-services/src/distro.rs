@@ -48,6 +48,10 @@ pub struct SystemServiceDistros {
 }
 
 impl SystemServiceDistros {
+    pub fn versions(&self) -> Versions {
+        self.versions.clone()
+    }
+
     /// With overriding existing packages
     pub fn extend(mut self, distros: Vec<PackageDistro>) -> Self {
         for distro in distros {
@@ -40,4 +40,5 @@ pub use crate::misc::*;
 mod misc;
 pub mod pinning;
 mod service;
+pub mod spell;
 mod utils;
@@ -0,0 +1,246 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use eyre::{eyre, WrapErr};
+use fluence_spell_dtos::trigger_config::{TriggerConfig, TriggerConfigValue};
+use fluence_spell_dtos::value::{ScriptValue, StringValue};
+use maplit::hashmap;
+use serde_json::{json, Value as JValue};
+
+use connected_client::ConnectedClient;
+
+/// How often `wait_for_kv` polls the spell KV
+const KV_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone)]
+pub struct SpellInfo {
+    pub script: String,
+    pub trigger_config: TriggerConfig,
+}
+
+/// Installs a spell on the worker, returns the spell id
+pub async fn install_spell(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    script: &str,
+    config: TriggerConfig,
+    init_data: JValue,
+) -> eyre::Result<String> {
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+        "script" => json!(script),
+        "config" => json!(config),
+        "data" => init_data,
+    };
+
+    let response = client
+        .execute_particle(
+            r#"
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (call worker_id ("spell" "install") [script data config] spell_id)
+                    (call client ("return" "") [spell_id])
+                )
+            )"#,
+            data,
+        )
+        .await?;
+
+    response
+        .first()
+        .and_then(JValue::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| eyre!("spell_id is not in response: {response:?}"))
+}
+
+/// Returns the script and the trigger config of the spell
+pub async fn get_spell_info(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    spell_id: &str,
+) -> eyre::Result<SpellInfo> {
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+    };
+
+    let mut response = client
+        .execute_particle(
+            r#"
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (seq
+                        (call worker_id (spell_id "get_script") [] script)
+                        (call worker_id (spell_id "get_trigger_config") [] config)
+                    )
+                    (call client ("return" "") [script config])
+                )
+            )"#,
+            data,
+        )
+        .await?
+        .into_iter();
+
+    let script: ScriptValue = next_value(&mut response, "script")?;
+    let config: TriggerConfigValue = next_value(&mut response, "config")?;
+    if !script.success {
+        return Err(eyre!("get_script failed: {}", script.error));
+    }
+    if !config.success {
+        return Err(eyre!("get_trigger_config failed: {}", config.error));
+    }
+
+    Ok(SpellInfo {
+        script: script.value,
+        trigger_config: config.config,
+    })
+}
+
+/// Reads a string from the spell KV, returns `None` if the key is absent
+pub async fn get_spell_string(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    spell_id: &str,
+    key: &str,
+) -> eyre::Result<Option<String>> {
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+        "key" => json!(key),
+    };
+
+    let mut response = client
+        .execute_particle(
+            r#"
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (call worker_id (spell_id "get_string") [key] value)
+                    (call client ("return" "") [value])
+                )
+            )"#,
+            data,
+        )
+        .await?
+        .into_iter();
+
+    let value: StringValue = next_value(&mut response, "value")?;
+    if !value.success {
+        return Err(eyre!("get_string failed: {}", value.error));
+    }
+
+    Ok((!value.absent).then_some(value.value))
+}
+
+/// Polls the spell KV until the value under `key` satisfies `predicate`.
+/// Absent key is passed to the predicate as `None`.
+/// Returns the last seen value, or an error if `timeout` has elapsed.
+pub async fn wait_for_kv<P>(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    spell_id: &str,
+    key: &str,
+    predicate: P,
+    timeout: Duration,
+) -> eyre::Result<Option<String>>
+where
+    P: Fn(Option<&str>) -> bool,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let value = get_spell_string(client, worker_id, spell_id, key)
+            .await
+            .wrap_err_with(|| format!("read '{key}' of spell {spell_id}"))?;
+        if predicate(value.as_deref()) {
+            return Ok(value);
+        }
+        if Instant::now() >= deadline {
+            return Err(eyre!(
+                "timed out after {timeout:?} waiting for '{key}' of spell {spell_id}, last value: {value:?}"
+            ));
+        }
+        tokio::time::sleep(KV_POLL_INTERVAL).await;
+    }
+}
+
+/// Makes the spell run right away.
+/// The clock trigger is restarted from now, keeping its period and the length of its window;
+/// other triggers are left as is. If the spell has no clock trigger, it is run once.
+pub async fn trigger_now(
+    client: &mut ConnectedClient,
+    worker_id: &str,
+    spell_id: &str,
+) -> eyre::Result<()> {
+    let mut config = get_spell_info(client, worker_id, spell_id)
+        .await?
+        .trigger_config;
+    // start_sec in the past makes the event bus schedule the spell immediately
+    let clock = &mut config.clock;
+    if clock.end_sec != 0 {
+        // keep the window length, otherwise an end_sec in the past makes the config invalid
+        let window = clock.end_sec.saturating_sub(clock.start_sec).max(1);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+        clock.end_sec = now.saturating_add(window);
+    }
+    clock.start_sec = 1;
+
+    let data = hashmap! {
+        "client" => json!(client.peer_id.to_string()),
+        "relay" => json!(client.node.to_string()),
+        "worker_id" => json!(worker_id),
+        "spell_id" => json!(spell_id),
+        "config" => json!(config),
+    };
+
+    client
+        .execute_particle(
+            r#"
+            (seq
+                (call relay ("op" "noop") [])
+                (seq
+                    (call worker_id ("spell" "update_trigger_config") [spell_id config])
+                    (call client ("return" "") [true])
+                )
+            )"#,
+            data,
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn next_value<T: serde::de::DeserializeOwned>(
+    values: &mut impl Iterator<Item = JValue>,
+    name: &str,
+) -> eyre::Result<T> {
+    let value = values
+        .next()
+        .ok_or_else(|| eyre!("'{name}' is missing from the response"))?;
+    serde_json::from_value(value).wrap_err_with(|| format!("deserialize '{name}'"))
+}
@@ -62,6 +62,7 @@ impl AquaRuntime for EasyVM {
                 .map(|peer| PeerId::from_str(peer).expect("invalid peer id"))
                 .collect(),
             call_requests: outcome.call_requests,
+            error_particle: None,
         }
     }
 
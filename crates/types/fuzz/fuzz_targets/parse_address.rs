@@ -0,0 +1,35 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use types::Address;
+
+fuzz_target!(|data: &str| {
+    // Addresses come from CLI args and configs, so no input may panic the parser,
+    // and whatever is parsed must be formatted back to an address that parses the same
+    if let Ok(address) = data.parse::<Address>() {
+        let formatted = address.to_string();
+        let reparsed: Address = formatted
+            .parse()
+            .unwrap_or_else(|e| panic!("formatted address '{formatted}' doesn't parse: {e}"));
+        assert_eq!(reparsed, address);
+    }
+});
@@ -0,0 +1,286 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Human-readable addresses of peers, relayed clients and services.
+//!
+//! An address is a path of `kind:value` segments joined by `/`, for example
+//! `peer:12D3KooW...`, `service:IPFS.multiaddr` or `relay:12D3KooW.../client:12D3KooW...`.
+
+use libp2p_identity::PeerId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+const SEPARATOR: char = '/';
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum AddressParseError {
+    #[error("address is empty")]
+    Empty,
+    #[error("segment {position} of the address is empty")]
+    EmptySegment { position: usize },
+    #[error("segment '{segment}' has no 'kind:value' form")]
+    MalformedSegment { segment: String },
+    #[error("unknown segment kind '{kind}', expected one of peer, relay, client, service")]
+    UnknownKind { kind: String },
+    #[error("invalid peer id '{value}' in {kind} segment: {reason}")]
+    InvalidPeerId {
+        kind: &'static str,
+        value: String,
+        reason: String,
+    },
+    #[error("service id '{value}' can't be empty or contain whitespace")]
+    InvalidServiceId { value: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    Peer(PeerId),
+    Relay(PeerId),
+    Client(PeerId),
+    /// Service id or alias, can't contain `/` or whitespace
+    Service(String),
+}
+
+impl Protocol {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Protocol::Peer(_) => "peer",
+            Protocol::Relay(_) => "relay",
+            Protocol::Client(_) => "client",
+            Protocol::Service(_) => "service",
+        }
+    }
+
+    fn is_valid_service_id(id: &str) -> bool {
+        !id.is_empty() && !id.contains(|c: char| c == SEPARATOR || c.is_whitespace())
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Protocol::Peer(id) | Protocol::Relay(id) | Protocol::Client(id) => {
+                write!(f, "{}:{}", self.kind(), id.to_base58())
+            }
+            Protocol::Service(id) => write!(f, "{}:{}", self.kind(), id),
+        }
+    }
+}
+
+impl FromStr for Protocol {
+    type Err = AddressParseError;
+
+    fn from_str(segment: &str) -> Result<Self, Self::Err> {
+        let (kind, value) =
+            segment
+                .split_once(':')
+                .ok_or_else(|| AddressParseError::MalformedSegment {
+                    segment: segment.to_string(),
+                })?;
+        let peer_id = |kind: &'static str| {
+            PeerId::from_str(value).map_err(|e| AddressParseError::InvalidPeerId {
+                kind,
+                value: value.to_string(),
+                reason: e.to_string(),
+            })
+        };
+        match kind {
+            "peer" => Ok(Protocol::Peer(peer_id("peer")?)),
+            "relay" => Ok(Protocol::Relay(peer_id("relay")?)),
+            "client" => Ok(Protocol::Client(peer_id("client")?)),
+            "service" if Protocol::is_valid_service_id(value) => {
+                Ok(Protocol::Service(value.to_string()))
+            }
+            "service" => Err(AddressParseError::InvalidServiceId {
+                value: value.to_string(),
+            }),
+            kind => Err(AddressParseError::UnknownKind {
+                kind: kind.to_string(),
+            }),
+        }
+    }
+}
+
+/// Path to a peer, a client behind a relay or a service, see the module docs for the string form.
+/// Serialized as its string form.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Address(Vec<Protocol>);
+
+impl Address {
+    pub fn peer(peer_id: PeerId) -> Self {
+        Address(vec![Protocol::Peer(peer_id)])
+    }
+
+    pub fn client(relay: PeerId, client: PeerId) -> Self {
+        Address(vec![Protocol::Relay(relay), Protocol::Client(client)])
+    }
+
+    /// Append a segment to the path, e.g. a service on the addressed peer
+    pub fn append(mut self, protocol: Protocol) -> Self {
+        self.0.push(protocol);
+        self
+    }
+
+    pub fn protocols(&self) -> &[Protocol] {
+        &self.0
+    }
+
+    /// The last peer on the path, if any
+    pub fn destination_peer(&self) -> Option<PeerId> {
+        self.0.iter().rev().find_map(|protocol| match protocol {
+            Protocol::Peer(id) | Protocol::Relay(id) | Protocol::Client(id) => Some(*id),
+            Protocol::Service(_) => None,
+        })
+    }
+}
+
+impl From<Protocol> for Address {
+    fn from(protocol: Protocol) -> Self {
+        Address(vec![protocol])
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, protocol) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "{SEPARATOR}")?;
+            }
+            protocol.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(AddressParseError::Empty);
+        }
+        s.split(SEPARATOR)
+            .enumerate()
+            .map(|(position, segment)| {
+                if segment.is_empty() {
+                    Err(AddressParseError::EmptySegment { position })
+                } else {
+                    segment.parse()
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(Address)
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p_identity::Keypair;
+
+    fn random_peer_id() -> PeerId {
+        Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let relay = random_peer_id();
+        let client = random_peer_id();
+        let addresses = vec![
+            Address::peer(relay),
+            Address::from(Protocol::Service("IPFS.multiaddr".to_string())),
+            Address::client(relay, client),
+            Address::peer(relay).append(Protocol::Service("sig".to_string())),
+        ];
+        for address in addresses {
+            let s = address.to_string();
+            assert_eq!(s.parse::<Address>(), Ok(address), "{s}");
+        }
+
+        let s = format!("relay:{relay}/client:{client}");
+        let address: Address = s.parse().unwrap();
+        assert_eq!(address, Address::client(relay, client));
+        assert_eq!(address.destination_peer(), Some(client));
+        assert_eq!(address.to_string(), s);
+    }
+
+    #[test]
+    fn serde_as_string() {
+        let peer_id = PeerId::from_str("QmY28NSCefB532XbERtnKHadexGuNzAfYnh5fJk6qhLsSi").unwrap();
+        let address = Address::peer(peer_id).append(Protocol::Service("srv".to_string()));
+        let json = serde_json::to_value(&address).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!("peer:QmY28NSCefB532XbERtnKHadexGuNzAfYnh5fJk6qhLsSi/service:srv")
+        );
+        assert_eq!(serde_json::from_value::<Address>(json).unwrap(), address);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!("".parse::<Address>(), Err(AddressParseError::Empty));
+        assert_eq!(
+            "service:a//service:b".parse::<Address>(),
+            Err(AddressParseError::EmptySegment { position: 1 })
+        );
+        assert_eq!(
+            "peer".parse::<Address>(),
+            Err(AddressParseError::MalformedSegment {
+                segment: "peer".to_string()
+            })
+        );
+        assert_eq!(
+            "host:x".parse::<Address>(),
+            Err(AddressParseError::UnknownKind {
+                kind: "host".to_string()
+            })
+        );
+        assert_eq!(
+            "service: a".parse::<Address>(),
+            Err(AddressParseError::InvalidServiceId {
+                value: " a".to_string()
+            })
+        );
+        assert!(matches!(
+            "peer:Qm".parse::<Address>(),
+            Err(AddressParseError::InvalidPeerId { kind: "peer", .. })
+        ));
+    }
+}
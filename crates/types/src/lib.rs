@@ -17,8 +17,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+pub mod address;
 mod deal_id;
 pub mod peer_id;
 pub mod peer_scope;
 
+pub use address::Address;
 pub use deal_id::DealId;
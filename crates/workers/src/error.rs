@@ -85,6 +85,9 @@ pub enum KeyStorageError {
 
     #[error("Keypair for peer_id {0} not found")]
     KeypairNotFound(PeerId),
+
+    #[error("Failed to derive keypair by path {path}: {reason}")]
+    DeriveKeypair { path: String, reason: String },
 }
 
 #[derive(Debug, Error)]
@@ -0,0 +1,132 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Deterministic derivation of worker keys from the root key.
+//!
+//! Derivation follows BIP32 hardened derivation, with BLAKE3 in place of HMAC-SHA512:
+//! the master node (a key and a chain code) is derived from the secret of the root key, and
+//! every segment of the path derives a child node from the parent's key and chain code.
+//! Worker keys are derived by the path `m/workers'/<deal id>'`, so all worker identities
+//! can be regenerated from the root key alone, e.g. when the keypairs directory is lost.
+
+use std::fmt::{Display, Formatter};
+
+use fluence_keypair::{KeyFormat, KeyPair};
+use types::DealId;
+
+use crate::KeyStorageError;
+
+const MASTER_CONTEXT: &str = "fluence nox 2024-05-01 worker keys master node";
+
+/// Hardened derivation path, rendered as `m/a'/b'`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<String>);
+
+impl DerivationPath {
+    pub fn worker(deal_id: &DealId) -> Self {
+        Self(vec!["workers".to_string(), deal_id.as_str().to_string()])
+    }
+}
+
+impl Display for DerivationPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "m")?;
+        for segment in &self.0 {
+            write!(f, "/{segment}'")?;
+        }
+        Ok(())
+    }
+}
+
+struct Node {
+    key: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl Node {
+    fn from_output(mut output: blake3::OutputReader) -> Self {
+        let mut bytes = [0u8; 64];
+        output.fill(&mut bytes);
+        let mut node = Node {
+            key: [0; 32],
+            chain_code: [0; 32],
+        };
+        node.key.copy_from_slice(&bytes[..32]);
+        node.chain_code.copy_from_slice(&bytes[32..]);
+        node
+    }
+
+    fn master(seed: &[u8]) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key(MASTER_CONTEXT);
+        hasher.update(seed);
+        Self::from_output(hasher.finalize_xof())
+    }
+
+    fn child(&self, segment: &str) -> Self {
+        let mut hasher = blake3::Hasher::new_keyed(&self.chain_code);
+        // 0x00 || key || segment, as hardened derivation in BIP32
+        hasher.update(&[0]);
+        hasher.update(&self.key);
+        hasher.update(segment.as_bytes());
+        Self::from_output(hasher.finalize_xof())
+    }
+}
+
+/// Derives an Ed25519 keypair by the `path` from the root keypair
+pub fn derive_key_pair(root: &KeyPair, path: &DerivationPath) -> Result<KeyPair, KeyStorageError> {
+    let seed = root
+        .secret()
+        .map_err(|_| KeyStorageError::CannotExtractRSASecretKey)?;
+    let node = path
+        .0
+        .iter()
+        .fold(Node::master(&seed), |node, segment| node.child(segment));
+    KeyPair::from_secret_key(node.key.to_vec(), KeyFormat::Ed25519).map_err(|err| {
+        KeyStorageError::DeriveKeypair {
+            path: path.to_string(),
+            reason: format!("{err:?}"),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+    use types::DealId;
+
+    use super::{derive_key_pair, DerivationPath};
+
+    #[test]
+    fn derivation_is_deterministic() {
+        let root = KeyPair::generate_ed25519();
+        let deal: DealId = "0xAbC123".into();
+        let path = DerivationPath::worker(&deal);
+        assert_eq!(path.to_string(), "m/workers'/abc123'");
+
+        let first = derive_key_pair(&root, &path).unwrap();
+        let second = derive_key_pair(&root, &path).unwrap();
+        assert_eq!(first.get_peer_id(), second.get_peer_id());
+
+        let other_deal = derive_key_pair(&root, &DerivationPath::worker(&"def456".into())).unwrap();
+        assert_ne!(first.get_peer_id(), other_deal.get_peer_id());
+
+        let other_root = derive_key_pair(&KeyPair::generate_ed25519(), &path).unwrap();
+        assert_ne!(first.get_peer_id(), other_root.get_peer_id());
+    }
+}
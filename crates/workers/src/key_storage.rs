@@ -23,16 +23,24 @@ use std::str::FromStr;
 
 use parking_lot::RwLock;
 
-use crate::persistence::{load_persisted_key_pairs, persist_keypair, remove_keypair};
+use crate::key_derivation::{derive_key_pair, DerivationPath};
+use crate::persistence::{
+    load_persisted_key_pairs, persist_keypair, remove_keypair, PersistedKeypair,
+};
 use crate::KeyStorageError;
 use fluence_keypair::{KeyFormat, KeyPair};
 use types::peer_scope::{PeerScope, WorkerId};
+use types::DealId;
 
 pub struct KeyStorage {
     /// worker_id -> worker_keypair
     worker_key_pairs: RwLock<HashMap<WorkerId, KeyPair>>,
+    /// worker_id -> derivation path, for keys derived from the root key
+    derivation_paths: RwLock<HashMap<WorkerId, String>>,
     key_pairs_dir: PathBuf,
     pub root_key_pair: KeyPair,
+    /// Whether keys of new workers are derived from the root key
+    derive_worker_keys: bool,
 }
 
 impl KeyStorage {
@@ -40,21 +48,35 @@ impl KeyStorage {
         let key_pairs = load_persisted_key_pairs(key_pairs_dir.as_path()).await?;
 
         let mut worker_key_pairs = HashMap::with_capacity(key_pairs.len());
+        let mut derivation_paths = HashMap::new();
         for (keypair, path) in key_pairs {
+            let derivation_path = keypair.derivation_path;
             let format = KeyFormat::from_str(&keypair.key_format)
                 .map_err(|err| KeyStorageError::PersistedKeypairInvalidKeyFormat { err, path })?;
             let keypair: KeyPair = KeyPair::from_secret_key(keypair.private_key_bytes, format)?;
 
             let worker_id: WorkerId = keypair.get_peer_id().into();
             worker_key_pairs.insert(worker_id, keypair);
+            if let Some(derivation_path) = derivation_path {
+                derivation_paths.insert(worker_id, derivation_path);
+            }
         }
         Ok(Self {
             worker_key_pairs: RwLock::new(worker_key_pairs),
+            derivation_paths: RwLock::new(derivation_paths),
             key_pairs_dir,
             root_key_pair,
+            derive_worker_keys: false,
         })
     }
 
+    /// Derive keys of new workers from the root key instead of generating random ones.
+    /// Random keys of existing workers are still loaded and used.
+    pub fn with_derived_worker_keys(mut self, enabled: bool) -> Self {
+        self.derive_worker_keys = enabled;
+        self
+    }
+
     pub fn get_keypair(&self, peer_scope: PeerScope) -> Option<KeyPair> {
         match peer_scope {
             PeerScope::WorkerId(worker_id) => self.get_worker_key_pair(worker_id),
@@ -74,10 +96,65 @@ impl KeyStorage {
         Ok(keypair)
     }
 
+    /// Creates the key of the worker of a deal, derived or random depending on the configuration
+    pub async fn create_worker_key_pair(
+        &self,
+        deal_id: &DealId,
+    ) -> Result<KeyPair, KeyStorageError> {
+        if !self.derive_worker_keys {
+            return self.create_key_pair().await;
+        }
+
+        let path = DerivationPath::worker(deal_id);
+        let keypair = derive_key_pair(&self.root_key_pair, &path)?;
+        self.store_derived(keypair.clone(), path).await?;
+        Ok(keypair)
+    }
+
+    /// Regenerates the lost key of a worker, if it was derived from the root key.
+    /// Returns `None` if the derived key doesn't match the worker id, i.e. the key was random.
+    pub async fn recover_worker_key_pair(
+        &self,
+        worker_id: WorkerId,
+        deal_id: &DealId,
+    ) -> Result<Option<KeyPair>, KeyStorageError> {
+        let path = DerivationPath::worker(deal_id);
+        let keypair = derive_key_pair(&self.root_key_pair, &path)?;
+        if WorkerId::from(keypair.get_peer_id()) != worker_id {
+            return Ok(None);
+        }
+        self.store_derived(keypair.clone(), path).await?;
+        Ok(Some(keypair))
+    }
+
+    async fn store_derived(
+        &self,
+        keypair: KeyPair,
+        path: DerivationPath,
+    ) -> Result<(), KeyStorageError> {
+        let worker_id: WorkerId = keypair.get_peer_id().into();
+        let persisted = PersistedKeypair {
+            derivation_path: Some(path.to_string()),
+            ..PersistedKeypair::try_from(&keypair)?
+        };
+        persist_keypair(&self.key_pairs_dir, worker_id, persisted).await?;
+        self.worker_key_pairs.write().insert(worker_id, keypair);
+        self.derivation_paths
+            .write()
+            .insert(worker_id, path.to_string());
+        Ok(())
+    }
+
+    /// Derivation path of the worker key, `None` if the key is random
+    pub fn derivation_path(&self, worker_id: WorkerId) -> Option<String> {
+        self.derivation_paths.read().get(&worker_id).cloned()
+    }
+
     pub async fn remove_key_pair(&self, worker_id: WorkerId) -> Result<(), KeyStorageError> {
         remove_keypair(&self.key_pairs_dir, worker_id).await?;
         let mut guard = self.worker_key_pairs.write();
         guard.remove(&worker_id);
+        self.derivation_paths.write().remove(&worker_id);
         Ok(())
     }
 }
@@ -235,4 +312,60 @@ mod tests {
             None
         );
     }
+
+    #[tokio::test]
+    async fn test_derived_keys_are_recovered() {
+        let temp_dir = tempdir().expect("Failed to create temporary directory");
+        let key_pairs_dir = temp_dir.path().to_path_buf();
+        let root_key_pair = fluence_keypair::KeyPair::generate_ed25519();
+        let deal_id: types::DealId = "0x1234".into();
+
+        let key_storage = KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+            .await
+            .expect("Failed to create KeyStorage from path")
+            .with_derived_worker_keys(true);
+        let derived = key_storage
+            .create_worker_key_pair(&deal_id)
+            .await
+            .expect("Failed to create derived key pair");
+        let random = key_storage
+            .create_key_pair()
+            .await
+            .expect("Failed to create random key pair");
+        let derived_id = derived.get_peer_id().into();
+        let random_id = random.get_peer_id().into();
+        assert_eq!(
+            key_storage.derivation_path(derived_id),
+            Some("m/workers'/1234'".to_string())
+        );
+        assert_eq!(key_storage.derivation_path(random_id), None);
+        drop(key_storage);
+
+        // paths survive restart
+        let key_storage = KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+            .await
+            .expect("Failed to create KeyStorage from path");
+        assert_eq!(
+            key_storage.derivation_path(derived_id),
+            Some("m/workers'/1234'".to_string())
+        );
+        drop(key_storage);
+
+        // only the root key is left
+        std::fs::remove_dir_all(&key_pairs_dir).unwrap();
+        std::fs::create_dir_all(&key_pairs_dir).unwrap();
+        let key_storage = KeyStorage::from_path(key_pairs_dir.clone(), root_key_pair.clone())
+            .await
+            .expect("Failed to create KeyStorage from path");
+        let recovered = key_storage
+            .recover_worker_key_pair(derived_id, &deal_id)
+            .await
+            .expect("Failed to recover key pair");
+        assert_eq!(recovered.map(|k| k.to_vec()), Some(derived.to_vec()));
+        let not_derived = key_storage
+            .recover_worker_key_pair(random_id, &deal_id)
+            .await
+            .expect("Failed to recover key pair");
+        assert!(not_derived.is_none());
+    }
 }
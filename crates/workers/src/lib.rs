@@ -20,6 +20,7 @@
 #![feature(try_blocks)]
 
 mod error;
+mod key_derivation;
 mod key_storage;
 mod persistence;
 mod scope;
@@ -30,6 +31,7 @@ pub use core_distributor::PersistentCoreDistributor;
 pub use core_distributor::CUID;
 pub use error::KeyStorageError;
 pub use error::WorkersError;
+pub use key_derivation::DerivationPath;
 pub use key_storage::KeyStorage;
 pub use scope::PeerScopes;
 pub use tokio::sync::mpsc::Receiver;
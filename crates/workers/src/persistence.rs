@@ -40,6 +40,9 @@ pub const fn default_bool<const V: bool>() -> bool {
 pub struct PersistedKeypair {
     pub private_key_bytes: Vec<u8>,
     pub key_format: String,
+    /// Path the key is derived by from the root key, `None` for random keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -75,6 +78,7 @@ impl TryFrom<&KeyPair> for PersistedKeypair {
         Ok(Self {
             private_key_bytes: keypair.secret().map_err(|_| CannotExtractRSASecretKey)?,
             key_format: keypair.public().get_key_format().into(),
+            derivation_path: None,
         })
     }
 }
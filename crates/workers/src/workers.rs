@@ -88,7 +88,7 @@ pub struct Workers {
     sender: Sender<Event>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Event {
     WorkerCreated {
         worker_id: WorkerId,
@@ -131,7 +131,23 @@ impl Workers {
 
         for (w, _) in workers {
             let worker_id = w.worker_id;
-            let deal_id = w.deal_id.clone().into();
+            let deal_id: DealId = w.deal_id.clone().into();
+            if key_storage.get_worker_key_pair(worker_id).is_none() {
+                match key_storage
+                    .recover_worker_key_pair(worker_id, &deal_id)
+                    .await
+                {
+                    Ok(Some(_)) => {
+                        tracing::info!(target: "worker", "Recovered key of worker {worker_id} from the root key")
+                    }
+                    Ok(None) => {
+                        tracing::warn!(target: "worker", "Key of worker {worker_id} is missing and wasn't derived from the root key")
+                    }
+                    Err(err) => {
+                        tracing::warn!(target: "worker", "Failed to recover key of worker {worker_id}: {err}")
+                    }
+                }
+            }
             let cu_ids = w.cu_ids.clone();
             worker_infos.insert(worker_id, w.into());
             worker_ids.insert(deal_id, worker_id);
@@ -215,7 +231,7 @@ impl Workers {
             _ => {
                 let key_pair = self
                     .key_storage
-                    .create_key_pair()
+                    .create_worker_key_pair(&deal_id)
                     .await
                     .map_err(|err| WorkersError::CreateWorkerKeyPair { err })?;
 
@@ -404,6 +420,55 @@ impl Workers {
         Ok(())
     }
 
+    /// Transfers the worker with the specified `worker_id` to a new creator.
+    ///
+    /// The new creator is persisted, the status of the worker stays the same.
+    ///
+    /// # Arguments
+    ///
+    /// * `worker_id` - The `PeerId` of the worker to be transferred.
+    /// * `creator` - The `PeerId` of the new creator.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<(), WorkersError>` where:
+    /// - `Ok(())` if the transfer is successful.
+    /// - `Err(WorkersError)` if an error occurs, such as the worker not found.
+    ///
+    pub async fn set_worker_creator(
+        &self,
+        worker_id: WorkerId,
+        creator: PeerId,
+    ) -> Result<(), WorkersError> {
+        let (deal_id, active, cu_ids) = {
+            let mut guard = self.worker_infos.write();
+            let worker_info = guard
+                .get_mut(&worker_id)
+                .ok_or(WorkersError::WorkerNotFound(worker_id))?;
+            worker_info.creator = creator;
+
+            (
+                worker_info.deal_id.clone(),
+                *worker_info.active.read(),
+                worker_info.cu_ids.clone(),
+            )
+        };
+
+        persist_worker(
+            &self.workers_dir,
+            worker_id,
+            PersistedWorker {
+                worker_id,
+                creator,
+                deal_id: deal_id.into(),
+                active,
+                cu_ids,
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
     pub fn get_runtime_handle(&self, worker_id: WorkerId) -> Option<Handle> {
         self.runtimes
             .read()
@@ -496,6 +561,21 @@ impl Workers {
         self.worker_infos.read().keys().cloned().collect()
     }
 
+    /// Workers with their deals and derivation paths of their keys, `None` for random keys
+    pub fn key_derivation_paths(&self) -> Vec<(WorkerId, DealId, Option<String>)> {
+        self.worker_infos
+            .read()
+            .iter()
+            .map(|(worker_id, info)| {
+                (
+                    *worker_id,
+                    info.deal_id.clone(),
+                    self.key_storage.derivation_path(*worker_id),
+                )
+            })
+            .collect()
+    }
+
     pub fn shutdown(&self) {
         tracing::debug!("Shutdown worker runtimes");
         let mut runtimes = self.runtimes.write();
@@ -977,6 +1057,11 @@ mod tests {
             .expect("Failed to activate worker");
         let status = workers.is_worker_active(worker_id_1);
         assert!(!status);
+        let new_creator = PeerId::random();
+        workers
+            .set_worker_creator(worker_id_1, new_creator)
+            .await
+            .expect("Failed to transfer worker");
         drop(key_storage);
         // tokio doesn't allow to drop runtimes in async context, so shifting workers drop to the blocking thread
         tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
@@ -1013,6 +1098,10 @@ mod tests {
         assert!(key_2.is_none());
         let status = workers.is_worker_active(worker_id_1);
         assert!(!status);
+        let creator = workers
+            .get_worker_creator(worker_id_1)
+            .expect("Failed to get worker creator");
+        assert_eq!(creator, new_creator);
         // tokio doesn't allow to drop runtimes in async context, so shifting workers drop to the blocking thread
         tokio::task::spawn_blocking(|| drop(workers)).await.unwrap();
     }
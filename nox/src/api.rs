@@ -0,0 +1,419 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Curated API for embedding nox as a library.
+//!
+//! Types here are kept stable across internal refactorings, so downstream tooling
+//! should prefer them over depending on sorcerer, particle-services and other internal crates.
+
+use std::time::Duration;
+
+use eyre::eyre;
+use fluence_spell_dtos::trigger_config::{ClockConfig, ConnectionPoolConfig, TriggerConfig};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use libp2p::{Multiaddr, PeerId};
+use serde_json::Value as JValue;
+
+use connection_pool::{ConnectionPoolApi, ConnectionPoolT, LifecycleEvent};
+use particle_execution::FunctionOutcome;
+use particle_services::{Labels, ParticleAppServices, PeerScope};
+use sorcerer::{get_spell_info, install_spell, remove_spell, Sorcerer};
+use spell_event_bus::api::SpellEventBusApi;
+use spell_service_api::SpellServiceApi;
+use spell_storage::SpellStorage;
+use uuid_utils::uuid;
+
+/// TTL of the particles emulated for the calls made through the API
+const API_CALL_TTL: Duration = Duration::from_secs(60);
+
+fn api_particle_id() -> String {
+    format!("node-api_{}", uuid())
+}
+
+/// When a spell installed through the API is run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpellTriggers {
+    /// Run the spell by timer
+    pub timer: Option<SpellTimer>,
+    /// Run the spell when a peer connects to the node
+    pub on_connect: bool,
+    /// Run the spell when a peer disconnects from the node
+    pub on_disconnect: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpellTimer {
+    /// Unix time in seconds of the first run, a time in the past starts the spell right away
+    pub start_sec: u32,
+    /// Unix time in seconds after which the spell isn't run anymore
+    pub end_sec: Option<u32>,
+    /// Interval between the runs in seconds, 0 to run the spell once
+    pub period_sec: u32,
+}
+
+impl From<SpellTriggers> for TriggerConfig {
+    fn from(triggers: SpellTriggers) -> Self {
+        let clock = triggers.timer.map_or_else(ClockConfig::default, |timer| {
+            // zero start means no timer at all
+            ClockConfig {
+                start_sec: timer.start_sec.max(1),
+                end_sec: timer.end_sec.unwrap_or(0),
+                period_sec: timer.period_sec,
+            }
+        });
+        TriggerConfig {
+            clock,
+            connections: ConnectionPoolConfig {
+                connect: triggers.on_connect,
+                disconnect: triggers.on_disconnect,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+impl From<TriggerConfig> for SpellTriggers {
+    fn from(config: TriggerConfig) -> Self {
+        let clock = config.clock;
+        let timer = (clock.start_sec != 0).then(|| SpellTimer {
+            start_sec: clock.start_sec,
+            end_sec: (clock.end_sec != 0).then_some(clock.end_sec),
+            period_sec: clock.period_sec,
+        });
+        SpellTriggers {
+            timer,
+            on_connect: config.connections.connect,
+            on_disconnect: config.connections.disconnect,
+        }
+    }
+}
+
+/// Handle to a node, gives access to its spells, services and events.
+/// All calls are made on behalf of the host peer.
+#[derive(Clone)]
+pub struct NodeHandle {
+    peer_id: PeerId,
+    spells: SpellsApi,
+    services: ServicesApi,
+    events: EventsApi,
+}
+
+impl NodeHandle {
+    pub(crate) fn new(sorcerer: &Sorcerer, connection_pool: ConnectionPoolApi) -> Self {
+        let peer_id = sorcerer.scopes.get_host_peer_id();
+        Self {
+            peer_id,
+            spells: SpellsApi {
+                host_peer_id: peer_id,
+                services: sorcerer.services.clone(),
+                spell_storage: sorcerer.spell_storage.clone(),
+                spell_event_bus_api: sorcerer.spell_event_bus_api.clone(),
+                spell_service_api: sorcerer.spell_service_api.clone(),
+            },
+            services: ServicesApi {
+                host_peer_id: peer_id,
+                services: sorcerer.services.clone(),
+            },
+            events: EventsApi { connection_pool },
+        }
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    pub fn spells(&self) -> &SpellsApi {
+        &self.spells
+    }
+
+    pub fn services(&self) -> &ServicesApi {
+        &self.services
+    }
+
+    pub fn events(&self) -> &EventsApi {
+        &self.events
+    }
+}
+
+/// Spells installed on the host
+#[derive(Clone)]
+pub struct SpellsApi {
+    host_peer_id: PeerId,
+    services: ParticleAppServices,
+    spell_storage: SpellStorage,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+}
+
+impl SpellsApi {
+    /// Ids of all spells installed on the host
+    pub fn list(&self) -> Vec<String> {
+        self.spell_storage.get_registered_spells_by(PeerScope::Host)
+    }
+
+    /// Installs a spell and subscribes it to its triggers, returns the id of the new spell
+    pub async fn install(
+        &self,
+        script: String,
+        triggers: SpellTriggers,
+        init_data: JValue,
+    ) -> eyre::Result<String> {
+        install_spell(
+            &self.services,
+            &self.spell_storage,
+            &self.spell_event_bus_api,
+            &self.spell_service_api,
+            PeerScope::Host,
+            api_particle_id(),
+            API_CALL_TTL,
+            triggers.into(),
+            None,
+            script,
+            init_data,
+            self.host_peer_id,
+            Labels::new(),
+            None,
+        )
+        .await
+        .map_err(|err| eyre!("{err}"))
+    }
+
+    /// Unsubscribes a spell from its triggers and removes it
+    pub async fn remove(&self, spell_id: &str) -> eyre::Result<()> {
+        remove_spell(
+            &api_particle_id(),
+            &self.spell_storage,
+            &self.services,
+            &self.spell_event_bus_api,
+            spell_id,
+            PeerScope::Host,
+            self.host_peer_id,
+        )
+        .await
+        .map_err(|err| eyre!("{err}"))
+    }
+
+    /// Returns the script and the triggers of a spell
+    pub async fn get(&self, spell_id: &str) -> eyre::Result<(String, SpellTriggers)> {
+        let info = get_spell_info(
+            &self.spell_service_api,
+            PeerScope::Host,
+            API_CALL_TTL,
+            spell_id.to_string(),
+            self.host_peer_id,
+        )
+        .await
+        .map_err(|err| eyre!("{err}"))?;
+
+        Ok((info.script, info.trigger_config.into()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServiceSummary {
+    pub id: String,
+    pub blueprint_id: String,
+    pub owner_id: PeerId,
+    pub aliases: Vec<String>,
+}
+
+/// Services created on the host
+#[derive(Clone)]
+pub struct ServicesApi {
+    host_peer_id: PeerId,
+    services: ParticleAppServices,
+}
+
+impl ServicesApi {
+    pub async fn list(&self) -> Vec<ServiceSummary> {
+        self.services
+            .list_services(PeerScope::Host)
+            .await
+            .into_iter()
+            .map(|info| ServiceSummary {
+                id: info.id,
+                blueprint_id: info.blueprint_id,
+                owner_id: info.owner_id,
+                aliases: info.aliases,
+            })
+            .collect()
+    }
+
+    /// Calls a function of a service by its id or alias
+    pub async fn call(
+        &self,
+        service_id: &str,
+        function_name: &str,
+        args: Vec<JValue>,
+    ) -> eyre::Result<JValue> {
+        let outcome = self
+            .services
+            .call_function(
+                PeerScope::Host,
+                service_id,
+                function_name,
+                args,
+                None,
+                self.host_peer_id,
+                API_CALL_TTL,
+            )
+            .await;
+
+        match outcome {
+            FunctionOutcome::Ok(result) => Ok(result),
+            FunctionOutcome::Empty => Ok(JValue::Null),
+            FunctionOutcome::Err(err) => Err(eyre!("{err}")),
+            FunctionOutcome::NotDefined { .. } => Err(eyre!("service {service_id} not found")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeEvent {
+    PeerConnected(PeerId),
+    PeerDisconnected(PeerId),
+}
+
+/// Direction of a connection relative to the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl From<connection_pool::Direction> for Direction {
+    fn from(direction: connection_pool::Direction) -> Self {
+        match direction {
+            connection_pool::Direction::Inbound => Direction::Inbound,
+            connection_pool::Direction::Outbound => Direction::Outbound,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// A connection is established. There could be several connections with the same peer.
+    Established {
+        peer_id: PeerId,
+        address: Multiaddr,
+        direction: Direction,
+    },
+    /// A connection is closed
+    Closed {
+        peer_id: PeerId,
+        address: Multiaddr,
+        direction: Direction,
+        remaining_established: usize,
+    },
+    /// The peer reported the protocols it supports
+    Identified {
+        peer_id: PeerId,
+        protocols: Vec<String>,
+    },
+}
+
+impl From<connection_pool::ConnectionEvent> for ConnectionEvent {
+    fn from(event: connection_pool::ConnectionEvent) -> Self {
+        use connection_pool::ConnectionEvent as Event;
+        match event {
+            Event::Established {
+                peer_id,
+                address,
+                direction,
+            } => ConnectionEvent::Established {
+                peer_id,
+                address,
+                direction: direction.into(),
+            },
+            Event::Closed {
+                peer_id,
+                address,
+                direction,
+                remaining_established,
+            } => ConnectionEvent::Closed {
+                peer_id,
+                address,
+                direction: direction.into(),
+                remaining_established,
+            },
+            Event::Identified { peer_id, protocols } => {
+                ConnectionEvent::Identified { peer_id, protocols }
+            }
+        }
+    }
+}
+
+/// Events happening on the node
+#[derive(Clone)]
+pub struct EventsApi {
+    connection_pool: ConnectionPoolApi,
+}
+
+impl EventsApi {
+    /// Subscribes to connections and disconnections of peers
+    pub fn subscribe(&self) -> BoxStream<'static, NodeEvent> {
+        self.connection_pool
+            .lifecycle_events()
+            .map(|event| match event {
+                LifecycleEvent::Connected(contact) => NodeEvent::PeerConnected(contact.peer_id),
+                LifecycleEvent::Disconnected(contact) => {
+                    NodeEvent::PeerDisconnected(contact.peer_id)
+                }
+            })
+            .boxed()
+    }
+
+    /// Subscribes to every established and closed connection, and to peers' identified protocols
+    pub fn subscribe_connections(&self) -> BoxStream<'static, ConnectionEvent> {
+        self.connection_pool
+            .connection_events()
+            .map(ConnectionEvent::from)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_spell_dtos::trigger_config::TriggerConfig;
+
+    use super::{SpellTimer, SpellTriggers};
+
+    #[test]
+    fn spell_triggers_roundtrip() {
+        let triggers = SpellTriggers {
+            timer: Some(SpellTimer {
+                start_sec: 1,
+                end_sec: None,
+                period_sec: 60,
+            }),
+            on_connect: true,
+            on_disconnect: false,
+        };
+        let config = TriggerConfig::from(triggers.clone());
+        assert_eq!(config.clock.start_sec, 1);
+        assert_eq!(config.clock.end_sec, 0);
+        assert!(config.connections.connect);
+        assert_eq!(SpellTriggers::from(config), triggers);
+
+        let config = TriggerConfig::from(SpellTriggers::default());
+        assert_eq!(config.clock.start_sec, 0, "no timer must stay disabled");
+        assert_eq!(SpellTriggers::from(config), SpellTriggers::default());
+    }
+}
@@ -17,6 +17,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use connection_pool::PeerClass;
 use itertools::Itertools;
 use libp2p::{
     core::{multiaddr::Protocol, Multiaddr},
@@ -67,6 +68,15 @@ impl FluenceNetworkBehaviour {
                     );
                     // Add addresses to connection pool disregarding whether it supports kademlia or not
                     // we want to have full info on non-kademlia peers as well
+                    self.connection_pool
+                        .add_identified_protocols(peer_id, protocols);
+                    // Clients don't run Kademlia
+                    let class = if supports_kademlia {
+                        PeerClass::Node
+                    } else {
+                        PeerClass::Client
+                    };
+                    self.connection_pool.set_peer_class(peer_id, class);
                     self.connection_pool
                         .add_discovered_addresses(peer_id, addresses.clone());
                     if supports_kademlia {
@@ -96,7 +106,7 @@ impl FluenceNetworkBehaviour {
     }
 }
 
-fn filter_addresses(addresses: Vec<Multiaddr>, allow_local: bool) -> Vec<Multiaddr> {
+pub(super) fn filter_addresses(addresses: Vec<Multiaddr>, allow_local: bool) -> Vec<Multiaddr> {
     // Deduplicate addresses
     let addresses = addresses.iter().unique();
 
@@ -16,33 +16,51 @@
  * You should have received a copy of the GNU Affero General Public License
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
+use std::sync::Arc;
+
 use libp2p::identify::Config as IdentifyConfig;
 use libp2p::{
     connection_limits::Behaviour as ConnectionLimits,
     identify::Behaviour as Identify,
     ping::{Behaviour as Ping, Config as PingConfig},
-    swarm::NetworkBehaviour,
+    rendezvous::{
+        client::Behaviour as RendezvousClient,
+        server::{Behaviour as RendezvousServer, Config as RendezvousServerConfig},
+    },
+    swarm::{behaviour::toggle::Toggle, NetworkBehaviour},
     PeerId,
 };
 use tokio::sync::mpsc;
 
-use connection_pool::ConnectionPoolBehaviour;
+use connection_pool::{
+    ClassLimits, ClockSkewEstimator, ConnectionPoolBehaviour, QosSettings, SessionSettings,
+};
+use fluence_libp2p::Budgeted;
 use health::HealthCheckRegistry;
 use kademlia::{Kademlia, KademliaConfig};
 use particle_protocol::{ExtendedParticle, PROTOCOL_NAME};
-use server_config::NetworkConfig;
+use server_config::{NetworkConfig, QosClassConfig};
 
+use crate::behaviour::pex::{pex_behaviour, PexBehaviour};
 use crate::connectivity::Connectivity;
 use crate::health::{BootstrapNodesHealth, ConnectivityHealth, KademliaBootstrapHealth};
+use crate::kademlia_limiter::KademliaQueryLimiter;
 
 /// Coordinates protocols, so they can cooperate
+///
+/// Sub-behaviours are polled in declaration order, so the connection pool goes first to
+/// deliver particles with the lowest latency. Every event-producing behaviour is wrapped in
+/// [`Budgeted`], so none of them can starve the others under load.
 #[derive(NetworkBehaviour)]
 pub struct FluenceNetworkBehaviour {
-    identify: Identify,
-    ping: Ping,
     connection_limits: ConnectionLimits,
-    pub(crate) connection_pool: ConnectionPoolBehaviour,
-    pub(crate) kademlia: Kademlia,
+    pub(crate) connection_pool: Budgeted<ConnectionPoolBehaviour>,
+    ping: Budgeted<Ping>,
+    identify: Budgeted<Identify>,
+    pub(crate) kademlia: Budgeted<Kademlia>,
+    rendezvous_server: Toggle<Budgeted<RendezvousServer>>,
+    pub(crate) rendezvous_client: Budgeted<RendezvousClient>,
+    pub(crate) pex: Toggle<Budgeted<PexBehaviour>>,
 }
 
 struct KademliaConfigAdapter {
@@ -60,6 +78,8 @@ impl From<KademliaConfigAdapter> for KademliaConfig {
             peer_fail_threshold: value.config.peer_fail_threshold,
             ban_cooldown: value.config.ban_cooldown,
             protocol_name: value.config.protocol_name,
+            gc_interval: value.config.gc_interval,
+            stale_peer_retention: value.config.stale_peer_retention,
         }
     }
 }
@@ -76,27 +96,70 @@ impl FluenceNetworkBehaviour {
         );
         let ping = Ping::new(PingConfig::new());
 
+        let query_limiter = KademliaQueryLimiter::new(
+            cfg.kademlia_config.max_queries_per_particle,
+            cfg.kademlia_config.max_concurrent_queries,
+            cfg.kademlia_config.max_queued_queries,
+            cfg.connectivity_metrics.clone(),
+        );
         let kad_config = KademliaConfigAdapter {
             peer_id: cfg.local_peer_id,
             config: cfg.kademlia_config,
         };
 
-        let (kademlia, kademlia_api) = Kademlia::new(kad_config.into(), cfg.libp2p_metrics);
+        let (kademlia, kademlia_api) =
+            Kademlia::new(kad_config.into(), cfg.libp2p_metrics, cfg.kademlia_metrics);
         let (connection_pool, particle_stream, connection_pool_api) = ConnectionPoolBehaviour::new(
             cfg.particle_queue_buffer,
             cfg.protocol_config,
             cfg.local_peer_id,
             cfg.connection_pool_metrics,
+            ClockSkewEstimator::new(
+                cfg.clock_skew.warn_threshold,
+                cfg.clock_skew.compensate,
+                cfg.clock_skew.max_correction,
+                cfg.clock_correction,
+            ),
         );
+        let connection_pool = if cfg.session_resumption.enabled {
+            connection_pool.with_sessions(SessionSettings {
+                window: cfg.session_resumption.window,
+                max_queued: cfg.session_resumption.max_queued_particles,
+                max_sessions: cfg.session_resumption.max_sessions,
+            })
+        } else {
+            connection_pool
+        };
+        let connection_pool = if cfg.qos.enabled {
+            let limits = |class: &QosClassConfig| ClassLimits {
+                max_inbound_queue: class.max_inbound_queue,
+                max_outbound_streams: class.max_outbound_streams,
+                idle_timeout: class.idle_timeout,
+            };
+            connection_pool.with_qos(QosSettings {
+                node: limits(&cfg.qos.node),
+                client: limits(&cfg.qos.client),
+            })
+        } else {
+            connection_pool
+        };
 
         let connection_limits = ConnectionLimits::new(cfg.connection_limits);
+        let rendezvous_server = cfg
+            .rendezvous_server
+            .then(|| RendezvousServer::new(RendezvousServerConfig::default()).into());
+        let rendezvous_client = RendezvousClient::new(cfg.key_pair.clone());
+        let pex = cfg.pex.enabled.then(|| pex_behaviour().into());
 
         let this = Self {
-            kademlia,
-            connection_pool,
             connection_limits,
-            identify,
-            ping,
+            connection_pool: connection_pool.into(),
+            ping: ping.into(),
+            identify: identify.into(),
+            kademlia: kademlia.into(),
+            rendezvous_server: rendezvous_server.into(),
+            rendezvous_client: rendezvous_client.into(),
+            pex: pex.into(),
         };
 
         let bootstrap_nodes = cfg.bootstrap_nodes.clone();
@@ -121,6 +184,7 @@ impl FluenceNetworkBehaviour {
             bootstrap_frequency: cfg.bootstrap_frequency,
             metrics: cfg.connectivity_metrics,
             health,
+            query_limiter: Arc::new(query_limiter),
         };
 
         (this, connectivity, particle_stream)
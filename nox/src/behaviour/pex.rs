@@ -0,0 +1,306 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Peer exchange (PEX): connected nodes periodically share samples of their healthy peers,
+//! i.e. peers that are connected and identified as Fluence nodes. Received peers aren't trusted,
+//! they are only dialed, and get into Kademlia and the connection pool via Identify
+//! once they prove to be Fluence nodes.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use libp2p::core::multiaddr::Protocol;
+use libp2p::identify::Event as IdentifyEvent;
+use libp2p::request_response::{self, json, Message, ProtocolSupport};
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+
+use particle_protocol::PROTOCOL_NAME;
+use server_config::PexConfig;
+
+use super::identify::filter_addresses;
+use super::FluenceNetworkBehaviour;
+
+pub const PEX_PROTOCOL: StreamProtocol = StreamProtocol::new("/fluence/pex/1.0.0");
+
+/// Max addresses of a single peer taken from a sample
+const MAX_PEER_ADDRESSES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PexPeer {
+    pub peer_id: String,
+    pub addresses: Vec<String>,
+}
+
+/// Both the request and the response carry a sample of the sender's healthy peers
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PexMessage {
+    pub peers: Vec<PexPeer>,
+}
+
+pub type PexBehaviour = json::Behaviour<PexMessage, PexMessage>;
+
+pub fn pex_behaviour() -> PexBehaviour {
+    json::Behaviour::new(
+        [(PEX_PROTOCOL, ProtocolSupport::Full)],
+        request_response::Config::default().with_request_timeout(Duration::from_secs(10)),
+    )
+}
+
+/// Healthy peers of the node, the exchange happens in the node loop
+pub struct PeerExchange {
+    config: PexConfig,
+    local_peer_id: PeerId,
+    allow_local_addresses: bool,
+    healthy: HashMap<PeerId, Vec<Multiaddr>>,
+}
+
+impl PeerExchange {
+    pub fn new(config: PexConfig, local_peer_id: PeerId, allow_local_addresses: bool) -> Self {
+        Self {
+            config,
+            local_peer_id,
+            allow_local_addresses,
+            healthy: <_>::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn period(&self) -> Duration {
+        self.config.period
+    }
+
+    /// Peers that identified as Fluence nodes supporting PEX become healthy
+    pub fn inject_identify_event(&mut self, event: &IdentifyEvent) {
+        if let IdentifyEvent::Received { peer_id, info, .. } = event {
+            let is_fluence = info.protocols.iter().any(|p| p.eq(&PROTOCOL_NAME));
+            let supports_pex = info.protocols.contains(&PEX_PROTOCOL);
+            let addresses = filter_addresses(info.listen_addrs.clone(), self.allow_local_addresses);
+            if is_fluence && supports_pex && !addresses.is_empty() {
+                self.healthy.insert(*peer_id, addresses);
+            }
+        }
+    }
+
+    pub fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.healthy.remove(peer_id);
+    }
+
+    /// Random healthy peers to exchange samples with
+    pub fn exchange_targets(&self) -> Vec<PeerId> {
+        self.healthy
+            .keys()
+            .copied()
+            .choose_multiple(&mut rand::thread_rng(), self.config.fanout)
+    }
+
+    /// Random sample of healthy peers, except the peer it's sent to
+    pub fn sample(&self, to: &PeerId) -> PexMessage {
+        let peers = self
+            .healthy
+            .iter()
+            .filter(|(peer_id, _)| *peer_id != to)
+            .choose_multiple(&mut rand::thread_rng(), self.config.sample_size)
+            .into_iter()
+            .map(|(peer_id, addresses)| PexPeer {
+                peer_id: peer_id.to_string(),
+                addresses: addresses.iter().map(|a| a.to_string()).collect(),
+            })
+            .collect();
+        PexMessage { peers }
+    }
+
+    /// Unknown peers from a received sample to dial. Invalid entries are dropped,
+    /// addresses are normalized to end with `/p2p/<peer id>`.
+    pub fn candidates(&self, from: &PeerId, message: PexMessage) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        message
+            .peers
+            .into_iter()
+            .filter_map(|peer| {
+                let peer_id: PeerId = peer.peer_id.parse().ok()?;
+                if peer_id == self.local_peer_id
+                    || &peer_id == from
+                    || self.healthy.contains_key(&peer_id)
+                {
+                    return None;
+                }
+                let addresses: Vec<Multiaddr> = peer
+                    .addresses
+                    .iter()
+                    .take(MAX_PEER_ADDRESSES)
+                    .filter_map(|addr| with_peer_id(addr.parse().ok()?, peer_id))
+                    .collect();
+                let addresses = filter_addresses(addresses, self.allow_local_addresses);
+                (!addresses.is_empty()).then_some((peer_id, addresses))
+            })
+            .take(self.config.max_dials)
+            .collect()
+    }
+}
+
+/// Appends `/p2p/<peer id>` to the address, `None` if it ends with another peer id
+fn with_peer_id(mut addr: Multiaddr, peer_id: PeerId) -> Option<Multiaddr> {
+    match addr.iter().last() {
+        Some(Protocol::P2p(id)) if id == peer_id => Some(addr),
+        Some(Protocol::P2p(_)) => None,
+        _ => {
+            addr.push(Protocol::P2p(peer_id));
+            Some(addr)
+        }
+    }
+}
+
+impl FluenceNetworkBehaviour {
+    /// Asks random healthy peers for their samples, sharing ours
+    pub fn exchange_peers(&mut self, pex: &PeerExchange) {
+        let Some(behaviour) = self.pex.as_mut() else {
+            return;
+        };
+        for peer_id in pex.exchange_targets() {
+            behaviour.send_request(&peer_id, pex.sample(&peer_id));
+        }
+    }
+
+    /// Answers PEX requests, returns peers from received samples to dial
+    pub fn inject_pex_event(
+        &mut self,
+        event: request_response::Event<PexMessage, PexMessage>,
+        pex: &PeerExchange,
+    ) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                Message::Request {
+                    request, channel, ..
+                } => {
+                    if let Some(behaviour) = self.pex.as_mut() {
+                        if behaviour.send_response(channel, pex.sample(&peer)).is_err() {
+                            log::debug!("Failed to answer PEX request from {peer}");
+                        }
+                    }
+                    pex.candidates(&peer, request)
+                }
+                Message::Response { response, .. } => pex.candidates(&peer, response),
+            },
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                log::debug!("PEX request to {peer} failed: {error}");
+                vec![]
+            }
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                log::debug!("PEX request from {peer} failed: {error}");
+                vec![]
+            }
+            request_response::Event::ResponseSent { .. } => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::{Multiaddr, PeerId};
+    use server_config::PexConfig;
+
+    use super::{PeerExchange, PexMessage, PexPeer};
+
+    fn healthy(pex: &mut PeerExchange, peer_id: PeerId, addr: &str) {
+        pex.healthy.insert(peer_id, vec![addr.parse().unwrap()]);
+    }
+
+    #[test]
+    fn sample_excludes_receiver() {
+        let mut pex = PeerExchange::new(PexConfig::default(), PeerId::random(), true);
+        let a = PeerId::random();
+        let b = PeerId::random();
+        healthy(&mut pex, a, "/ip4/1.2.3.4/tcp/7777");
+        healthy(&mut pex, b, "/ip4/1.2.3.5/tcp/7777");
+
+        let sample = pex.sample(&a);
+        assert_eq!(
+            sample.peers,
+            vec![PexPeer {
+                peer_id: b.to_string(),
+                addresses: vec!["/ip4/1.2.3.5/tcp/7777".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn candidates_are_validated() {
+        let local = PeerId::random();
+        let config = PexConfig {
+            max_dials: 2,
+            ..PexConfig::default()
+        };
+        let mut pex = PeerExchange::new(config, local, false);
+        let known = PeerId::random();
+        healthy(&mut pex, known, "/ip4/1.2.3.4/tcp/7777");
+        let from = PeerId::random();
+        let new = PeerId::random();
+        let other = PeerId::random();
+
+        let peer = |peer_id: String, addresses: Vec<String>| PexPeer { peer_id, addresses };
+        let message = PexMessage {
+            peers: vec![
+                peer(
+                    "not a peer id".to_string(),
+                    vec!["/ip4/1.1.1.1/tcp/1".to_string()],
+                ),
+                peer(local.to_string(), vec!["/ip4/1.1.1.1/tcp/1".to_string()]),
+                peer(from.to_string(), vec!["/ip4/1.1.1.1/tcp/1".to_string()]),
+                peer(known.to_string(), vec!["/ip4/1.1.1.1/tcp/1".to_string()]),
+                // local addresses and addresses of other peers are dropped
+                peer(
+                    other.to_string(),
+                    vec![
+                        "/ip4/127.0.0.1/tcp/1".to_string(),
+                        format!("/ip4/1.1.1.1/tcp/1/p2p/{from}"),
+                    ],
+                ),
+                peer(
+                    new.to_string(),
+                    vec!["/ip4/8.8.8.8/tcp/7777".to_string(), "garbage".to_string()],
+                ),
+            ],
+        };
+
+        let candidates = pex.candidates(&from, message);
+        let expected: Multiaddr = format!("/ip4/8.8.8.8/tcp/7777/p2p/{new}").parse().unwrap();
+        assert_eq!(candidates, vec![(new, vec![expected])]);
+    }
+
+    #[test]
+    fn dials_are_capped() {
+        let config = PexConfig {
+            max_dials: 2,
+            ..PexConfig::default()
+        };
+        let pex = PeerExchange::new(config, PeerId::random(), true);
+        let peers = (0..5)
+            .map(|_| PexPeer {
+                peer_id: PeerId::random().to_string(),
+                addresses: vec!["/ip4/1.1.1.1/tcp/1".to_string()],
+            })
+            .collect();
+
+        let candidates = pex.candidates(&PeerId::random(), PexMessage { peers });
+        assert_eq!(candidates.len(), 2);
+    }
+}
@@ -0,0 +1,157 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::collections::HashMap;
+use std::time::Duration;
+
+use libp2p::core::multiaddr::Protocol;
+use libp2p::rendezvous::{client, Namespace, Ttl};
+use libp2p::{Multiaddr, PeerId};
+use server_config::RendezvousConfig;
+
+use super::FluenceNetworkBehaviour;
+
+/// Namespaces the node registers under and rendezvous points it registers at
+pub struct RendezvousRegistrations {
+    points: HashMap<PeerId, Multiaddr>,
+    namespaces: Vec<Namespace>,
+    ttl: Ttl,
+}
+
+impl RendezvousRegistrations {
+    pub fn new(config: &RendezvousConfig) -> Self {
+        let points = config
+            .points
+            .iter()
+            .filter_map(|addr| match addr.iter().last() {
+                Some(Protocol::P2p(peer_id)) => Some((peer_id, addr.clone())),
+                _ => {
+                    log::warn!("Rendezvous point {addr} doesn't end with /p2p/<peer id>, ignoring");
+                    None
+                }
+            })
+            .collect();
+        let namespaces = config
+            .namespaces
+            .iter()
+            .filter_map(|ns| match Namespace::new(ns.clone()) {
+                Ok(ns) => Some(ns),
+                Err(err) => {
+                    log::warn!("Invalid rendezvous namespace {ns}: {err}, ignoring");
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            points,
+            namespaces,
+            ttl: config.ttl.as_secs(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.points.is_empty() && !self.namespaces.is_empty()
+    }
+
+    pub fn points(&self) -> impl Iterator<Item = (&PeerId, &Multiaddr)> {
+        self.points.iter()
+    }
+
+    pub fn is_point(&self, peer_id: &PeerId) -> bool {
+        self.points.contains_key(peer_id)
+    }
+
+    /// Registrations are refreshed at half of their TTL, so they never lapse
+    pub fn refresh_period(&self) -> Duration {
+        Duration::from_secs((self.ttl / 2).max(1))
+    }
+}
+
+impl FluenceNetworkBehaviour {
+    /// Registers the node under all configured namespaces at the rendezvous point
+    pub fn register_at_rendezvous(
+        &mut self,
+        registrations: &RendezvousRegistrations,
+        point: PeerId,
+    ) {
+        for namespace in &registrations.namespaces {
+            let result =
+                self.rendezvous_client
+                    .register(namespace.clone(), point, Some(registrations.ttl));
+            if let Err(err) = result {
+                log::warn!(
+                    "Failed to register under {namespace} at rendezvous point {point}: {err}"
+                );
+            }
+        }
+    }
+
+    pub fn inject_rendezvous_event(&mut self, event: client::Event) {
+        match event {
+            client::Event::Registered {
+                rendezvous_node,
+                ttl,
+                namespace,
+            } => {
+                log::debug!("Registered under {namespace} at {rendezvous_node} for {ttl}s")
+            }
+            client::Event::RegisterFailed {
+                rendezvous_node,
+                namespace,
+                error,
+            } => {
+                log::warn!("Registration under {namespace} at {rendezvous_node} failed: {error:?}")
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use libp2p::PeerId;
+    use server_config::RendezvousConfig;
+
+    use super::RendezvousRegistrations;
+
+    #[test]
+    fn skips_points_without_peer_id() {
+        let peer_id = PeerId::random();
+        let config = RendezvousConfig {
+            server: false,
+            namespaces: vec!["ipfs-adapters".to_string(), "x".repeat(256)],
+            points: vec![
+                format!("/ip4/127.0.0.1/tcp/7777/p2p/{peer_id}")
+                    .parse()
+                    .unwrap(),
+                "/ip4/127.0.0.1/tcp/7778".parse().unwrap(),
+            ],
+            ttl: Duration::from_secs(7200),
+        };
+
+        let registrations = RendezvousRegistrations::new(&config);
+        assert!(registrations.is_enabled());
+        assert_eq!(registrations.points().count(), 1);
+        assert!(registrations.is_point(&peer_id));
+        assert_eq!(registrations.namespaces.len(), 1);
+        assert_eq!(registrations.refresh_period(), Duration::from_secs(3600));
+    }
+}
@@ -0,0 +1,260 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `nox bench` runs a self-test workload in-process and prints throughput and latency percentiles
+//! of the particle wire path (decode → verify → encode) and of spell triggers.
+//! The workloads are also used by the criterion benches in `crates/nox-tests/benches`.
+
+use std::ffi::OsString;
+use std::time::{Duration, Instant};
+
+use asynchronous_codec::{BytesMut, Decoder, Encoder};
+use clap::Parser;
+use eyre::{eyre, WrapErr};
+use fluence_keypair::KeyPair;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use futures::StreamExt;
+use particle_protocol::{FluenceCodec, Particle, ProtocolMessage};
+use spell_event_bus::api::{add_kv_triggers, KvChangeEvent, KvWatch, TriggerEvent};
+use spell_event_bus::bus::SpellEventBus;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "nox bench",
+    about = "Measure particle wire path and spell trigger performance in-process"
+)]
+struct BenchArgs {
+    /// Particles to pass through the wire path
+    #[arg(long, default_value_t = 10_000)]
+    particles: usize,
+    /// Size of the data of each particle in bytes
+    #[arg(long, default_value_t = 1024)]
+    data_size: usize,
+    /// Spell triggers to deliver
+    #[arg(long, default_value_t = 10_000)]
+    triggers: usize,
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub name: &'static str,
+    pub count: usize,
+    pub ops_per_sec: f64,
+    pub p50_us: u128,
+    pub p90_us: u128,
+    pub p99_us: u128,
+    pub max_us: u128,
+}
+
+impl Summary {
+    fn new(name: &'static str, mut latencies: Vec<Duration>, total: Duration) -> Self {
+        latencies.sort_unstable();
+        let percentile = |p: usize| {
+            latencies
+                .get((latencies.len() * p / 100).min(latencies.len().saturating_sub(1)))
+                .map_or(0, Duration::as_micros)
+        };
+        Self {
+            name,
+            count: latencies.len(),
+            ops_per_sec: latencies.len() as f64 / total.as_secs_f64().max(f64::EPSILON),
+            p50_us: percentile(50),
+            p90_us: percentile(90),
+            p99_us: percentile(99),
+            max_us: latencies.last().map_or(0, Duration::as_micros),
+        }
+    }
+}
+
+/// A signed particle with `data_size` bytes of data, encoded as a wire frame
+pub fn signed_frame(data_size: usize) -> eyre::Result<BytesMut> {
+    let keypair = KeyPair::generate_ed25519();
+    let mut particle = Particle {
+        id: uuid_utils::uuid(),
+        init_peer_id: keypair.get_peer_id(),
+        timestamp: now_millis::now_ms() as u64,
+        ttl: 60_000,
+        script: r#"(call %init_peer_id% ("peer" "timestamp_ms") [] ts)"#.to_string(),
+        signature: vec![],
+        data: vec![0; data_size],
+        trail: None,
+        progress: false,
+        hops: 0,
+    };
+    particle.sign(&keypair)?;
+    let mut frame = BytesMut::new();
+    FluenceCodec::new().encode(ProtocolMessage::Particle(particle), &mut frame)?;
+    Ok(frame)
+}
+
+/// Decode a particle frame, verify its signature and encode it back, as done on each hop
+pub fn process_frame(mut frame: BytesMut) -> eyre::Result<BytesMut> {
+    let mut codec = FluenceCodec::new();
+    let message = codec
+        .decode(&mut frame)?
+        .ok_or_else(|| eyre!("incomplete particle frame"))?;
+    if let ProtocolMessage::Particle(particle) = &message {
+        particle.verify()?;
+    }
+    let mut out = BytesMut::with_capacity(frame.capacity());
+    codec.encode(message, &mut out)?;
+    Ok(out)
+}
+
+/// Spell event bus with a single spell triggered by KV writes of another spell
+pub struct TriggerLoop {
+    writes: mpsc::UnboundedSender<KvChangeEvent>,
+    events: mpsc::UnboundedReceiver<TriggerEvent>,
+    bus: tokio::task::JoinHandle<()>,
+    counter: u64,
+}
+
+impl TriggerLoop {
+    pub async fn new() -> eyre::Result<Self> {
+        let (writes, recv) = mpsc::unbounded_channel();
+        let kv_source = UnboundedReceiverStream::new(recv).boxed();
+        let (bus, api, events) = SpellEventBus::new(None, vec![], vec![], vec![kv_source]);
+        let bus = bus.start();
+        api.start_scheduling().await?;
+        let watch = KvWatch {
+            spell_id: "bench_source".to_string(),
+            key: "counter".to_string(),
+        };
+        let config = add_kv_triggers(None, vec![watch]).ok_or_else(|| eyre!("no triggers"))?;
+        api.subscribe("bench_spell".to_string(), config).await?;
+        Ok(Self {
+            writes,
+            events,
+            bus,
+            counter: 0,
+        })
+    }
+
+    /// Write a new value to the watched key and wait for the spell to be triggered
+    pub async fn trigger_once(&mut self) -> eyre::Result<()> {
+        self.counter += 1;
+        self.writes.send(KvChangeEvent {
+            spell_id: "bench_source".to_string(),
+            key: "counter".to_string(),
+            value: self.counter.to_string(),
+        })?;
+        self.events
+            .recv()
+            .await
+            .ok_or_else(|| eyre!("spell event bus stopped"))?;
+        Ok(())
+    }
+}
+
+impl Drop for TriggerLoop {
+    fn drop(&mut self) {
+        self.bus.abort();
+    }
+}
+
+fn bench_wire_path(particles: usize, data_size: usize) -> eyre::Result<Summary> {
+    let frame = signed_frame(data_size)?;
+    let mut latencies = Vec::with_capacity(particles);
+    let start = Instant::now();
+    for _ in 0..particles {
+        let frame = frame.clone();
+        let started = Instant::now();
+        process_frame(frame)?;
+        latencies.push(started.elapsed());
+    }
+    Ok(Summary::new("wire_path", latencies, start.elapsed()))
+}
+
+async fn bench_spell_triggers(triggers: usize) -> eyre::Result<Summary> {
+    let mut trigger_loop = TriggerLoop::new().await?;
+    let mut latencies = Vec::with_capacity(triggers);
+    let start = Instant::now();
+    for _ in 0..triggers {
+        let started = Instant::now();
+        trigger_loop.trigger_once().await?;
+        latencies.push(started.elapsed());
+    }
+    Ok(Summary::new("spell_triggers", latencies, start.elapsed()))
+}
+
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = BenchArgs::parse_from(args);
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .wrap_err("error starting the runtime")?;
+
+    let summaries = vec![
+        bench_wire_path(args.particles, args.data_size)?,
+        runtime.block_on(bench_spell_triggers(args.triggers))?,
+    ];
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+    } else {
+        println!(
+            "{:<16} {:>8} {:>12} {:>8} {:>8} {:>8} {:>8}",
+            "workload", "count", "ops/sec", "p50 us", "p90 us", "p99 us", "max us"
+        );
+        for s in summaries {
+            println!(
+                "{:<16} {:>8} {:>12.0} {:>8} {:>8} {:>8} {:>8}",
+                s.name, s.count, s.ops_per_sec, s.p50_us, s.p90_us, s.p99_us, s.max_us
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_frame_roundtrip() {
+        let frame = signed_frame(16).unwrap();
+        let processed = process_frame(frame.clone()).unwrap();
+        assert_eq!(processed, frame);
+    }
+
+    #[test]
+    fn test_summary_percentiles() {
+        let latencies = (1..=100).map(Duration::from_micros).collect();
+        let summary = Summary::new("test", latencies, Duration::from_secs(1));
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50_us, 51);
+        assert_eq!(summary.p99_us, 100);
+        assert_eq!(summary.max_us, 100);
+        assert_eq!(summary.ops_per_sec, 100.0);
+    }
+
+    #[tokio::test]
+    async fn test_trigger_loop() {
+        let mut trigger_loop = TriggerLoop::new().await.unwrap();
+        for _ in 0..3 {
+            trigger_loop.trigger_once().await.unwrap();
+        }
+    }
+}
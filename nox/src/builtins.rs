@@ -22,11 +22,16 @@ use particle_builtins::{ok, CustomService, NodeInfo};
 use particle_execution::ServiceFunction;
 use serde_json::json;
 
-pub fn make_peer_builtin(node_info: NodeInfo) -> (String, CustomService) {
+use crate::Versions;
+
+pub fn make_peer_builtin(node_info: NodeInfo, versions: Versions) -> (String, CustomService) {
     (
         "peer".to_string(),
         CustomService::new(
-            vec![("identify", make_peer_identify_closure(node_info))],
+            vec![
+                ("identify", make_peer_identify_closure(node_info)),
+                ("versions", make_peer_versions_closure(versions)),
+            ],
             None,
         ),
     )
@@ -37,3 +42,11 @@ fn make_peer_identify_closure(node_info: NodeInfo) -> ServiceFunction {
         async move { ok(json!(node_info)) }.boxed()
     }))
 }
+
+fn make_peer_versions_closure(versions: Versions) -> ServiceFunction {
+    let versions = versions.to_json();
+    ServiceFunction::Immut(Box::new(move |_args, _params| {
+        let versions = versions.clone();
+        async move { ok(versions) }.boxed()
+    }))
+}
@@ -0,0 +1,234 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Per-peer circuit breakers for sending particles to remote peers.
+//! After `failures_threshold` failed sends in a row the breaker of the peer opens, and sends to
+//! the peer fail fast for the `cooldown` instead of tying up resources on doomed dials. Then the
+//! breaker is half-open: a single send probes the peer, closing the breaker on success and
+//! opening it again on failure.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use fluence_libp2p::PeerId;
+use parking_lot::Mutex;
+
+use peer_metrics::{BreakerState, ConnectivityMetrics};
+use server_config::CircuitBreakerConfig;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Breaker {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe was let through at `since`. If it never reports back, another one is let
+    /// through after the cooldown.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+pub struct CircuitBreakers {
+    failures_threshold: u32,
+    cooldown: Duration,
+    capacity: usize,
+    /// Breakers of the peers which failed recently, peers without one are closed
+    peers: Mutex<HashMap<PeerId, Breaker>>,
+    metrics: Option<ConnectivityMetrics>,
+}
+
+impl CircuitBreakers {
+    pub fn new(config: &CircuitBreakerConfig, metrics: Option<ConnectivityMetrics>) -> Self {
+        Self {
+            failures_threshold: config.failures_threshold,
+            cooldown: config.cooldown,
+            capacity: config.capacity,
+            peers: <_>::default(),
+            metrics,
+        }
+    }
+
+    fn transition(&self, state: BreakerState) {
+        if let Some(m) = self.metrics.as_ref() {
+            m.breaker_transition(state);
+        }
+    }
+
+    /// Whether a particle can be sent to the peer now
+    pub fn allow(&self, peer_id: PeerId) -> bool {
+        self.allow_at(peer_id, Instant::now())
+    }
+
+    fn allow_at(&self, peer_id: PeerId, now: Instant) -> bool {
+        if self.failures_threshold == 0 {
+            return true;
+        }
+
+        let mut peers = self.peers.lock();
+        let probe_at = match peers.get(&peer_id) {
+            None | Some(Breaker::Closed { .. }) => return true,
+            Some(Breaker::Open { until }) => *until,
+            Some(Breaker::HalfOpen { since }) => *since + self.cooldown,
+        };
+        if now < probe_at {
+            drop(peers);
+            if let Some(m) = self.metrics.as_ref() {
+                m.breaker_rejected();
+            }
+            return false;
+        }
+
+        let previous = peers.insert(peer_id, Breaker::HalfOpen { since: now });
+        drop(peers);
+        if matches!(previous, Some(Breaker::Open { .. })) {
+            self.transition(BreakerState::HalfOpen);
+        }
+        true
+    }
+
+    /// A particle was sent to the peer
+    pub fn on_success(&self, peer_id: PeerId) {
+        if self.failures_threshold == 0 {
+            return;
+        }
+
+        let removed = self.peers.lock().remove(&peer_id);
+        if matches!(
+            removed,
+            Some(Breaker::Open { .. } | Breaker::HalfOpen { .. })
+        ) {
+            self.transition(BreakerState::Closed);
+        }
+    }
+
+    /// A particle couldn't be sent to the peer
+    pub fn on_failure(&self, peer_id: PeerId) {
+        self.on_failure_at(peer_id, Instant::now())
+    }
+
+    fn on_failure_at(&self, peer_id: PeerId, now: Instant) {
+        if self.failures_threshold == 0 {
+            return;
+        }
+
+        let mut peers = self.peers.lock();
+        if !peers.contains_key(&peer_id) && peers.len() >= self.capacity {
+            // forget the peers which haven't failed enough to be cut off
+            peers.retain(|_, breaker| !matches!(breaker, Breaker::Closed { .. }));
+            if peers.len() >= self.capacity {
+                // too many failing peers to track, they aren't cut off
+                return;
+            }
+        }
+
+        let breaker = peers
+            .entry(peer_id)
+            .or_insert(Breaker::Closed { failures: 0 });
+        let opened = match breaker {
+            Breaker::Closed { failures } => {
+                *failures += 1;
+                *failures >= self.failures_threshold
+            }
+            Breaker::HalfOpen { .. } => true,
+            // a send which started before the breaker opened
+            Breaker::Open { .. } => false,
+        };
+        if opened {
+            *breaker = Breaker::Open {
+                until: now + self.cooldown,
+            };
+            drop(peers);
+            self.transition(BreakerState::Open);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breakers(failures_threshold: u32) -> CircuitBreakers {
+        CircuitBreakers::new(
+            &CircuitBreakerConfig {
+                failures_threshold,
+                cooldown: Duration::from_secs(10),
+                max_retries: 0,
+                retry_backoff: Duration::ZERO,
+                capacity: 10,
+            },
+            None,
+        )
+    }
+
+    #[test]
+    fn opens_after_failures_in_a_row() {
+        let breakers = breakers(3);
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+
+        breakers.on_failure_at(peer_id, now);
+        breakers.on_failure_at(peer_id, now);
+        // a success resets the count
+        breakers.on_success(peer_id);
+        breakers.on_failure_at(peer_id, now);
+        breakers.on_failure_at(peer_id, now);
+        assert!(breakers.allow_at(peer_id, now));
+
+        breakers.on_failure_at(peer_id, now);
+        assert!(!breakers.allow_at(peer_id, now));
+        assert!(breakers.allow_at(PeerId::random(), now));
+    }
+
+    #[test]
+    fn half_open_probe() {
+        let breakers = breakers(1);
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+        let after_cooldown = now + Duration::from_secs(10);
+
+        breakers.on_failure_at(peer_id, now);
+        assert!(!breakers.allow_at(peer_id, now));
+
+        // a single probe is let through after the cooldown
+        assert!(breakers.allow_at(peer_id, after_cooldown));
+        assert!(!breakers.allow_at(peer_id, after_cooldown));
+
+        // the failed probe opens the breaker again
+        breakers.on_failure_at(peer_id, after_cooldown);
+        assert!(!breakers.allow_at(peer_id, after_cooldown + Duration::from_secs(5)));
+
+        // the succeeded probe closes it
+        let after_second_cooldown = after_cooldown + Duration::from_secs(10);
+        assert!(breakers.allow_at(peer_id, after_second_cooldown));
+        breakers.on_success(peer_id);
+        assert!(breakers.allow_at(peer_id, after_second_cooldown));
+    }
+
+    #[test]
+    fn disabled() {
+        let breakers = breakers(0);
+        let peer_id = PeerId::random();
+        let now = Instant::now();
+
+        breakers.on_failure_at(peer_id, now);
+        assert!(breakers.allow_at(peer_id, now));
+    }
+}
@@ -0,0 +1,247 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `session` builtin lets clients resume their state on the relay after reconnecting.
+//!
+//! `session.open()` returns a token. While the session lives, `session.register(alias)` makes
+//! the client a provider of the alias, listed by `providers.get(alias)` along with the announced
+//! providers. Once the client disconnects, particles sent to it are queued and its registrations
+//! are kept for the resumption window. After reconnecting, `session.resume(token)` delivers the
+//! particles and returns the registrations the client still has. `session.revoke(token)` drops
+//! the session and the registrations right away. The client calling these functions must be
+//! connected to the node directly.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use connection_pool::{ConnectionPoolApi, ConnectionPoolT, LifecycleEvent, SessionError};
+use futures::{FutureExt, StreamExt};
+use libp2p::PeerId;
+use now_millis::now_ms;
+use parking_lot::RwLock;
+use particle_args::{Args, ErrorCode, JError};
+use particle_builtins::{wrap, CustomService, ProviderTable};
+use particle_execution::{ParticleParams, ServiceFunction};
+use serde_json::{json, Value as JValue};
+use server_config::SessionResumptionConfig;
+use tokio::task;
+use tracing::Instrument;
+
+pub struct ClientSessions {
+    connection_pool: ConnectionPoolApi,
+    provider_table: Arc<RwLock<ProviderTable>>,
+    window: Duration,
+    max_registrations: usize,
+}
+
+impl ClientSessions {
+    pub fn new(
+        connection_pool: ConnectionPoolApi,
+        provider_table: Arc<RwLock<ProviderTable>>,
+        config: &SessionResumptionConfig,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            connection_pool,
+            provider_table,
+            window: config.window,
+            max_registrations: config.max_registrations,
+        })
+    }
+
+    /// Starts the resumption window of the registrations of each client that disconnects
+    pub fn start(self: Arc<Self>) {
+        let mut events = self.connection_pool.lifecycle_events();
+        task::Builder::new()
+            .name("client-sessions")
+            .spawn(
+                async move {
+                    while let Some(event) = events.next().await {
+                        if let LifecycleEvent::Disconnected(contact) = event {
+                            self.disconnected(contact.peer_id);
+                        }
+                    }
+                }
+                .in_current_span(),
+            )
+            .expect("Could not spawn task");
+    }
+
+    fn disconnected(&self, peer_id: PeerId) {
+        let now = now_ms() as u64;
+        let expires_at = now.saturating_add(self.window.as_millis() as u64);
+        self.provider_table
+            .write()
+            .expire_client(&peer_id.to_base58(), expires_at, now);
+    }
+
+    pub fn make_builtin(self: Arc<Self>) -> (String, CustomService) {
+        let open = {
+            let this = self.clone();
+            ServiceFunction::Immut(Box::new(move |_args, params| {
+                let this = this.clone();
+                async move { wrap(this.open(params).await) }.boxed()
+            }))
+        };
+        let resume = {
+            let this = self.clone();
+            ServiceFunction::Immut(Box::new(move |args, params| {
+                let this = this.clone();
+                async move { wrap(this.resume(args, params).await) }.boxed()
+            }))
+        };
+        let revoke = {
+            let this = self.clone();
+            ServiceFunction::Immut(Box::new(move |args, params| {
+                let this = this.clone();
+                async move { wrap(this.revoke(args, params).await) }.boxed()
+            }))
+        };
+        let register = {
+            let this = self.clone();
+            ServiceFunction::Immut(Box::new(move |args, params| {
+                let this = this.clone();
+                async move { wrap(this.register(args, params).await) }.boxed()
+            }))
+        };
+        let unregister = {
+            let this = self;
+            ServiceFunction::Immut(Box::new(move |args, params| {
+                let this = this.clone();
+                async move { wrap(this.unregister(args, params).await) }.boxed()
+            }))
+        };
+        (
+            "session".to_string(),
+            CustomService::new(
+                vec![
+                    ("open", open),
+                    ("resume", resume),
+                    ("revoke", revoke),
+                    ("register", register),
+                    ("unregister", unregister),
+                ],
+                None,
+            ),
+        )
+    }
+
+    /// session.open() -> token
+    /// Opening the session again replaces the token
+    async fn open(&self, params: ParticleParams) -> Result<JValue, JError> {
+        let token = self
+            .connection_pool
+            .open_session(params.init_peer_id)
+            .await
+            .map_err(session_error)?;
+        Ok(json!(token))
+    }
+
+    /// session.resume(token: string) -> {delivered, dropped, registrations}
+    async fn resume(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let token: String = Args::next("token", &mut args)?;
+
+        let resumed = self
+            .connection_pool
+            .resume_session(params.init_peer_id, token)
+            .await
+            .map_err(session_error)?;
+        let registrations = self
+            .provider_table
+            .write()
+            .resume_client(&params.init_peer_id.to_base58(), now_ms() as u64);
+        Ok(json!({
+            "delivered": resumed.delivered,
+            "dropped": resumed.dropped,
+            "registrations": registrations,
+        }))
+    }
+
+    /// session.revoke(token: string)
+    async fn revoke(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let token: String = Args::next("token", &mut args)?;
+
+        self.connection_pool
+            .revoke_session(params.init_peer_id, token)
+            .await
+            .map_err(session_error)?;
+        self.provider_table
+            .write()
+            .remove_client(&params.init_peer_id.to_base58());
+        Ok(JValue::Null)
+    }
+
+    /// session.register(alias: string)
+    async fn register(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let alias: String = Args::next("alias", &mut args)?;
+
+        let peer_id = params.init_peer_id;
+        self.connection_pool
+            .check_session(peer_id)
+            .await
+            .map_err(session_error)?;
+        let registered = self.provider_table.write().register_client(
+            &peer_id.to_base58(),
+            alias,
+            self.max_registrations,
+            now_ms() as u64,
+        );
+        if !registered {
+            return Err(session_error(SessionError::TooManyRegistrations(
+                peer_id,
+                self.max_registrations,
+            )));
+        }
+        Ok(JValue::Null)
+    }
+
+    /// session.unregister(alias: string) -> bool
+    /// Returns whether the client was registered as a provider of the alias
+    async fn unregister(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let alias: String = Args::next("alias", &mut args)?;
+
+        let peer_id = params.init_peer_id;
+        self.connection_pool
+            .check_session(peer_id)
+            .await
+            .map_err(session_error)?;
+        let removed = self.provider_table.write().unregister_client(
+            &peer_id.to_base58(),
+            &alias,
+            now_ms() as u64,
+        );
+        Ok(json!(removed))
+    }
+}
+
+fn session_error(err: SessionError) -> JError {
+    let code = match err {
+        SessionError::Disabled | SessionError::NotConnected(_) => ErrorCode::FailedPrecondition,
+        SessionError::NotFound(_) => ErrorCode::NotFound,
+        SessionError::InvalidToken(_) => ErrorCode::PermissionDenied,
+        SessionError::TooManyRegistrations(..) | SessionError::TooManySessions(_) => {
+            ErrorCode::QuotaExceeded
+        }
+        SessionError::Stopped => ErrorCode::Internal,
+    };
+    JError::with_code(code, err.to_string())
+}
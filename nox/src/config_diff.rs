@@ -0,0 +1,252 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `nox config diff` compares the config on disk with the effective config of a running node,
+//! so operators see what a restart is going to change before doing it.
+
+use std::ffi::OsString;
+use std::fmt;
+
+use clap::{Parser, Subcommand};
+use eyre::{eyre, WrapErr};
+use serde::Serialize;
+use toml::Value;
+
+use server_config::load_config_with_args;
+
+#[derive(Parser, Debug)]
+#[command(name = "nox config", about = "Tools for the node configuration")]
+struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Compare the config on disk with the one the running node uses
+    Diff {
+        /// HTTP endpoint of the running node
+        #[arg(long, short, default_value = "http://127.0.0.1:18080")]
+        endpoint: String,
+        /// Print the diff as JSON
+        #[arg(long)]
+        json: bool,
+        /// Exit with code 1 if the configs differ
+        #[arg(long)]
+        exit_code: bool,
+        /// Arguments the node is started with, the config is loaded from them the same way
+        #[arg(last = true)]
+        node_args: Vec<OsString>,
+    },
+}
+
+/// Entrypoint of `nox config`, `args` start with the subcommand name
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = ConfigArgs::parse_from(args);
+    match args.command {
+        ConfigCommand::Diff {
+            endpoint,
+            json,
+            exit_code,
+            node_args,
+        } => {
+            let raw_args = std::iter::once(OsString::from("nox"))
+                .chain(node_args)
+                .collect();
+            let on_disk = load_config_with_args(raw_args, None)?.resolve()?;
+            let on_disk = Value::try_from(on_disk).wrap_err("error serializing config on disk")?;
+            let running = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?
+                .block_on(fetch_running_config(&endpoint))?;
+
+            let changes = diff(&running, &on_disk);
+            if json {
+                println!("{}", serde_json::to_string_pretty(&changes)?);
+            } else if changes.is_empty() {
+                println!("No differences, a restart won't change the config");
+            } else {
+                for change in &changes {
+                    println!("{change}");
+                }
+            }
+
+            if exit_code && !changes.is_empty() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+    }
+}
+
+async fn fetch_running_config(endpoint: &str) -> eyre::Result<Value> {
+    let url = format!("{}/config", endpoint.trim_end_matches('/'));
+    let response = reqwest::get(&url)
+        .await
+        .wrap_err_with(|| format!("error fetching {url}"))?;
+    if !response.status().is_success() {
+        return Err(eyre!("{url} responded with {}", response.status()));
+    }
+    let body = response.text().await?;
+    toml::from_str(&body).wrap_err("running node returned invalid config")
+}
+
+/// Difference at a single dotted path of the config
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConfigChange {
+    /// Only in the config on disk, a restart sets it
+    Added { path: String, on_disk: Value },
+    /// Only in the running config, a restart unsets it
+    Removed { path: String, running: Value },
+    /// Set in both, a restart replaces the running value
+    Changed {
+        path: String,
+        running: Value,
+        on_disk: Value,
+    },
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigChange::Added { path, on_disk } => write!(f, "+ {path} = {on_disk}"),
+            ConfigChange::Removed { path, running } => write!(f, "- {path} = {running}"),
+            ConfigChange::Changed {
+                path,
+                running,
+                on_disk,
+            } => write!(f, "~ {path} = {running} -> {on_disk}"),
+        }
+    }
+}
+
+/// Walks both configs and reports differences by their dotted paths, sorted by path.
+/// Tables are compared key by key, everything else (including arrays) as a whole.
+pub fn diff(running: &Value, on_disk: &Value) -> Vec<ConfigChange> {
+    let mut changes = vec![];
+    diff_at(String::new(), running, on_disk, &mut changes);
+    changes
+}
+
+fn diff_at(path: String, running: &Value, on_disk: &Value, changes: &mut Vec<ConfigChange>) {
+    match (running, on_disk) {
+        (Value::Table(running), Value::Table(on_disk)) => {
+            let mut keys: Vec<&String> = running.keys().chain(on_disk.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match (running.get(key), on_disk.get(key)) {
+                    (Some(running), Some(on_disk)) => diff_at(path, running, on_disk, changes),
+                    (Some(running), None) => changes.push(ConfigChange::Removed {
+                        path,
+                        running: running.clone(),
+                    }),
+                    (None, Some(on_disk)) => changes.push(ConfigChange::Added {
+                        path,
+                        on_disk: on_disk.clone(),
+                    }),
+                    (None, None) => unreachable!("key comes from one of the tables"),
+                }
+            }
+        }
+        (running, on_disk) if running != on_disk => changes.push(ConfigChange::Changed {
+            path,
+            running: running.clone(),
+            on_disk: on_disk.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> Value {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn same_configs_have_no_diff() {
+        let config = parse(
+            r#"
+            a = 1
+            [node]
+            b = "x"
+            "#,
+        );
+        assert!(diff(&config, &config).is_empty());
+    }
+
+    #[test]
+    fn nested_changes_by_path() {
+        let running = parse(
+            r#"
+            pool = 4
+            [node]
+            removed = true
+            same = "x"
+            [node.kad]
+            peers = ["a", "b"]
+            "#,
+        );
+        let on_disk = parse(
+            r#"
+            pool = 8
+            [node]
+            same = "x"
+            added = 1
+            [node.kad]
+            peers = ["a"]
+            "#,
+        );
+
+        let changes = diff(&running, &on_disk);
+        assert_eq!(
+            changes,
+            vec![
+                ConfigChange::Added {
+                    path: "node.added".into(),
+                    on_disk: Value::Integer(1)
+                },
+                ConfigChange::Changed {
+                    path: "node.kad.peers".into(),
+                    running: parse("v = [\"a\", \"b\"]")["v"].clone(),
+                    on_disk: parse("v = [\"a\"]")["v"].clone(),
+                },
+                ConfigChange::Removed {
+                    path: "node.removed".into(),
+                    running: Value::Boolean(true)
+                },
+                ConfigChange::Changed {
+                    path: "pool".into(),
+                    running: Value::Integer(4),
+                    on_disk: Value::Integer(8)
+                },
+            ]
+        );
+        assert_eq!(changes[3].to_string(), "~ pool = 4 -> 8");
+    }
+}
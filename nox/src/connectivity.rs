@@ -19,6 +19,7 @@
 
 use std::cmp::min;
 use std::collections::HashSet;
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::health::ConnectivityHealth;
@@ -33,6 +34,7 @@ use peer_metrics::{ConnectivityMetrics, Resolution};
 use tokio::time::sleep;
 use tracing::{instrument, Instrument, Span};
 
+use crate::kademlia_limiter::KademliaQueryLimiter;
 use crate::tasks::Tasks;
 
 #[derive(Clone)]
@@ -49,6 +51,8 @@ pub struct Connectivity {
     pub bootstrap_frequency: usize,
     pub metrics: Option<ConnectivityMetrics>,
     pub health: Option<ConnectivityHealth>,
+    /// Caps Kademlia lookups made while resolving contacts
+    pub query_limiter: Arc<KademliaQueryLimiter>,
 }
 
 impl Connectivity {
@@ -66,7 +70,12 @@ impl Connectivity {
     }
 
     #[instrument(level = tracing::Level::INFO, skip_all)]
-    pub async fn resolve_contact(&self, target: PeerId, particle_id: &str) -> Option<Contact> {
+    pub async fn resolve_contact(
+        &self,
+        target: PeerId,
+        particle_id: &str,
+        ttl: Duration,
+    ) -> Option<Contact> {
         let metrics = self.metrics.as_ref();
         let contact = self.connection_pool.get_contact(target).await;
         if let Some(contact) = contact {
@@ -77,7 +86,21 @@ impl Connectivity {
             return Some(contact);
         } else {
             // contact isn't connected, have to discover it
+            let permit = match self.query_limiter.acquire(particle_id, ttl).await {
+                Ok(permit) => permit,
+                Err(err) => {
+                    tracing::warn!(
+                        particle_id = particle_id,
+                        "{} Didn't discover {}: {}",
+                        self.peer_id,
+                        target,
+                        err
+                    );
+                    return None;
+                }
+            };
             let contact = self.discover_peer(target).await;
+            drop(permit);
             match contact {
                 Ok(Some(contact)) => {
                     // connect to the discovered contact
@@ -0,0 +1,270 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use eyre::{eyre, WrapErr};
+use fluence_spell_dtos::trigger_config::TriggerConfig;
+use serde::Deserialize;
+use serde_json::{json, Map, Value as JValue};
+use service_modules::{AddBlueprint, Blueprint, Hash};
+
+/// Desired state of services and spells on a node
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(default)]
+    pub services: Vec<ServiceManifest>,
+    #[serde(default)]
+    pub spells: Vec<SpellManifest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServiceManifest {
+    pub alias: String,
+    pub modules: Vec<ModuleManifest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleManifest {
+    pub name: String,
+    /// Path to the wasm file, relative to the manifest
+    pub wasm: PathBuf,
+    /// Module config as accepted by `dist.add_module`, `name` is added automatically
+    #[serde(default)]
+    pub config: Map<String, JValue>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpellManifest {
+    pub alias: String,
+    /// Inline AIR script, conflicts with `script_path`
+    pub script: Option<String>,
+    /// Path to the AIR script, relative to the manifest
+    pub script_path: Option<PathBuf>,
+    #[serde(default)]
+    pub trigger_config: TriggerConfig,
+    #[serde(default = "empty_init_data")]
+    pub init_data: JValue,
+}
+
+fn empty_init_data() -> JValue {
+    json!({})
+}
+
+impl Manifest {
+    /// Load a manifest from a YAML or JSON file; paths inside are resolved relative to the file
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("error reading manifest {}", path.display()))?;
+        let is_json = path.extension().map(|e| e == "json").unwrap_or(false);
+        let mut manifest: Manifest = if is_json {
+            serde_json::from_str(&content)?
+        } else {
+            serde_yaml::from_str(&content)?
+        };
+
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        manifest.resolve_paths(base_dir);
+        manifest.validate()?;
+        Ok(manifest)
+    }
+
+    fn resolve_paths(&mut self, base_dir: &Path) {
+        for module in self.services.iter_mut().flat_map(|s| s.modules.iter_mut()) {
+            module.wasm = base_dir.join(&module.wasm);
+        }
+        for spell in self.spells.iter_mut() {
+            if let Some(script_path) = &spell.script_path {
+                spell.script_path = Some(base_dir.join(script_path));
+            }
+        }
+    }
+
+    fn validate(&self) -> eyre::Result<()> {
+        let mut aliases = HashSet::new();
+        let all_aliases = self
+            .services
+            .iter()
+            .map(|s| &s.alias)
+            .chain(self.spells.iter().map(|s| &s.alias));
+        for alias in all_aliases {
+            if !aliases.insert(alias) {
+                return Err(eyre!("alias '{alias}' is used more than once"));
+            }
+        }
+        for service in &self.services {
+            if service.modules.is_empty() {
+                return Err(eyre!("service '{}' has no modules", service.alias));
+            }
+        }
+        for spell in &self.spells {
+            if spell.script.is_some() == spell.script_path.is_some() {
+                return Err(eyre!(
+                    "spell '{}' must have exactly one of 'script' and 'script_path'",
+                    spell.alias
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ServiceManifest {
+    /// Compute the blueprint id the node would assign to the service without uploading it.
+    /// Modules and blueprints are content-addressed, so it matches the id returned by `dist.add_blueprint`.
+    pub fn blueprint_id(&self) -> eyre::Result<String> {
+        let dependencies = self
+            .modules
+            .iter()
+            .map(|module| Hash::new(&module.load_wasm()?))
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let blueprint = Blueprint::new(AddBlueprint::new(self.alias.clone(), dependencies))?;
+        Ok(blueprint.id)
+    }
+}
+
+impl ModuleManifest {
+    pub fn load_wasm(&self) -> eyre::Result<Vec<u8>> {
+        std::fs::read(&self.wasm)
+            .wrap_err_with(|| format!("error reading module {}", self.wasm.display()))
+    }
+
+    pub fn named_config(&self) -> JValue {
+        let mut config = self.config.clone();
+        config.insert("name".to_string(), json!(self.name));
+        JValue::Object(config)
+    }
+}
+
+impl SpellManifest {
+    pub fn load_script(&self) -> eyre::Result<String> {
+        match (&self.script, &self.script_path) {
+            (Some(script), _) => Ok(script.clone()),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .wrap_err_with(|| format!("error reading script {}", path.display())),
+            (None, None) => Err(eyre!("spell '{}' has no script", self.alias)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_yaml() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "main.air", "(null)");
+        let mut trigger_config = TriggerConfig::default();
+        trigger_config.clock.period_sec = 60;
+        let trigger_config = serde_json::to_string(&trigger_config).unwrap();
+        let path = write(
+            dir.path(),
+            "deploy.yaml",
+            &format!(
+                r#"
+services:
+  - alias: counter
+    modules:
+      - name: counter
+        wasm: counter.wasm
+        config:
+          logger_enabled: true
+spells:
+  - alias: watcher
+    script_path: main.air
+    trigger_config: {trigger_config}
+"#
+            ),
+        );
+
+        let manifest = Manifest::load(&path).unwrap();
+        let module = &manifest.services[0].modules[0];
+        assert_eq!(module.wasm, dir.path().join("counter.wasm"));
+        assert_eq!(
+            module.named_config(),
+            json!({"logger_enabled": true, "name": "counter"})
+        );
+        let spell = &manifest.spells[0];
+        assert_eq!(spell.load_script().unwrap(), "(null)");
+        assert_eq!(spell.trigger_config.clock.period_sec, 60);
+        assert_eq!(spell.init_data, json!({}));
+    }
+
+    #[test]
+    fn load_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write(
+            dir.path(),
+            "deploy.json",
+            r#"{"spells": [{"alias": "noop", "script": "(null)"}]}"#,
+        );
+
+        let manifest = Manifest::load(&path).unwrap();
+        assert!(manifest.services.is_empty());
+        assert_eq!(manifest.spells[0].load_script().unwrap(), "(null)");
+    }
+
+    #[test]
+    fn reject_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let duplicate = write(
+            dir.path(),
+            "duplicate.json",
+            r#"{"spells": [{"alias": "a", "script": "(null)"}, {"alias": "a", "script": "(null)"}]}"#,
+        );
+        let no_script = write(
+            dir.path(),
+            "no_script.json",
+            r#"{"spells": [{"alias": "a"}]}"#,
+        );
+
+        assert!(Manifest::load(&duplicate).is_err());
+        assert!(Manifest::load(&no_script).is_err());
+    }
+
+    #[test]
+    fn blueprint_id_is_content_addressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let wasm = write(dir.path(), "counter.wasm", "not really wasm");
+        let service = |alias: &str| ServiceManifest {
+            alias: alias.to_string(),
+            modules: vec![ModuleManifest {
+                name: "counter".to_string(),
+                wasm: wasm.clone(),
+                config: Map::new(),
+            }],
+        };
+
+        let id = service("counter").blueprint_id().unwrap();
+        assert_eq!(id, service("counter").blueprint_id().unwrap());
+        assert_ne!(id, service("other").blueprint_id().unwrap());
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `nox deploy` reconciles services and spells on a node with a declarative manifest.
+
+mod manifest;
+mod node;
+mod plan;
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use clap::Parser;
+use connected_client::ConnectedClient;
+use eyre::WrapErr;
+use fluence_keypair::{KeyFormat, KeyPair};
+use libp2p::{Multiaddr, PeerId};
+
+pub use manifest::{Manifest, ModuleManifest, ServiceManifest, SpellManifest};
+pub use plan::{plan, Change};
+
+use node::DeployTarget;
+use plan::{DesiredService, DesiredSpell};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "nox deploy",
+    about = "Reconcile services and spells on a node with a manifest"
+)]
+pub struct DeployArgs {
+    /// YAML or JSON manifest describing services and spells
+    #[arg(long, short)]
+    manifest: PathBuf,
+    /// Multiaddr of the node to deploy to
+    #[arg(long, short)]
+    addr: Multiaddr,
+    /// Deploy to the worker instead of the host
+    #[arg(long, short)]
+    worker_id: Option<PeerId>,
+    /// Secret key in base64 of the peer deploying the manifest, usually the management key
+    #[arg(long, short('y'))]
+    secret_key: String,
+    #[arg(long, short('f'), default_value = "ed25519")]
+    key_format: String,
+    /// Remove aliased services and spells created by the deploying peer that aren't in the manifest
+    #[arg(long)]
+    prune: bool,
+    /// Print the diff without applying it
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Entrypoint of `nox deploy`, `args` start with the subcommand name
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = DeployArgs::parse_from(args);
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(deploy(args))
+}
+
+async fn deploy(args: DeployArgs) -> eyre::Result<()> {
+    let manifest = Manifest::load(&args.manifest)?;
+
+    let secret_key = base64
+        .decode(&args.secret_key)
+        .wrap_err("secret key isn't a valid base64")?;
+    let key_format: KeyFormat = args.key_format.parse()?;
+    let key_pair = KeyPair::from_secret_key(secret_key, key_format)?;
+    let client = ConnectedClient::connect_with_keypair(args.addr.clone(), Some(key_pair))
+        .await
+        .wrap_err_with(|| format!("error connecting to {}", args.addr))?;
+    let mut target = DeployTarget::new(client, args.worker_id);
+
+    let mut desired_services = vec![];
+    for service in &manifest.services {
+        // a dry run must not change the node, so blueprint ids are computed locally
+        let blueprint_id = if args.dry_run {
+            service.blueprint_id()?
+        } else {
+            target.upload_service(service).await?
+        };
+        desired_services.push(DesiredService {
+            alias: service.alias.clone(),
+            blueprint_id,
+        });
+    }
+    let desired_spells = manifest
+        .spells
+        .iter()
+        .map(|spell| {
+            Ok(DesiredSpell {
+                alias: spell.alias.clone(),
+                script: spell.load_script()?,
+                trigger_config: spell.trigger_config.clone(),
+                init_data: spell.init_data.clone(),
+            })
+        })
+        .collect::<eyre::Result<Vec<_>>>()?;
+
+    let changes = plan(
+        desired_services,
+        desired_spells,
+        target.deployed_services().await?,
+        target.deployed_spells().await?,
+        args.prune,
+    );
+
+    if changes.is_empty() {
+        println!("Nothing to change");
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{change}");
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+
+    for change in changes {
+        match &change {
+            Change::CreateService(desired) => target.create_service(desired).await?,
+            Change::RecreateService { current, desired } => {
+                target.remove_service(&current.id).await?;
+                target.create_service(desired).await?;
+            }
+            Change::RemoveService(current) => target.remove_service(&current.id).await?,
+            Change::InstallSpell(desired) => target.install_spell(desired).await?,
+            Change::ReinstallSpell { current, desired } => {
+                target.remove_spell(&current.id).await?;
+                target.install_spell(desired).await?;
+            }
+            Change::UpdateSpellConfig { current, desired } => {
+                target
+                    .update_spell_config(&current.id, &desired.trigger_config)
+                    .await?
+            }
+            Change::RemoveSpell(current) => target.remove_spell(&current.id).await?,
+        }
+        println!("Applied: {change}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,242 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use connected_client::ConnectedClient;
+use eyre::{eyre, WrapErr};
+use fluence_spell_dtos::trigger_config::TriggerConfig;
+use libp2p::PeerId;
+use serde::Deserialize;
+use serde_json::{json, Value as JValue};
+
+use crate::deploy::manifest::ServiceManifest;
+use crate::deploy::plan::{DeployedService, DeployedSpell, DesiredService, DesiredSpell};
+
+#[derive(Deserialize)]
+struct ListedService {
+    id: String,
+    blueprint_id: String,
+    service_type: String,
+    owner_id: String,
+    aliases: Vec<String>,
+}
+
+/// Node (or worker) the manifest is deployed to, accessed through the connected client
+pub struct DeployTarget {
+    client: ConnectedClient,
+    /// Worker to deploy to, the host itself if `None`
+    worker_id: Option<PeerId>,
+}
+
+impl DeployTarget {
+    pub fn new(client: ConnectedClient, worker_id: Option<PeerId>) -> Self {
+        Self { client, worker_id }
+    }
+
+    /// Call a builtin or a service on the target and return its result
+    async fn call(
+        &mut self,
+        service: &str,
+        function: &str,
+        args: Vec<JValue>,
+    ) -> eyre::Result<JValue> {
+        let arg_names = (0..args.len())
+            .map(|i| format!("arg{i}"))
+            .collect::<Vec<_>>();
+        let script = format!(
+            r#"
+        (xor
+            (seq
+                (seq
+                    (call relay ("op" "noop") [])
+                    (call target ("{service}" "{function}") [{}] result)
+                )
+                (call client ("return" "") [true result])
+            )
+            (call client ("return" "") [false %last_error%.$.message])
+        )"#,
+            arg_names.join(" ")
+        );
+
+        let target = self.worker_id.unwrap_or(self.client.node);
+        let mut data = HashMap::new();
+        data.insert("relay", json!(self.client.node.to_string()));
+        data.insert("client", json!(self.client.peer_id.to_string()));
+        data.insert("target", json!(target.to_string()));
+        for (name, arg) in arg_names.iter().zip(args) {
+            data.insert(name.as_str(), arg);
+        }
+
+        let result = self
+            .client
+            .execute_particle(script, data)
+            .await
+            .wrap_err_with(|| format!("{service}.{function} call failed"))?;
+        match result.as_slice() {
+            [JValue::Bool(true), result] => Ok(result.clone()),
+            [JValue::Bool(false), error] => Err(eyre!("{service}.{function} failed: {error}")),
+            other => Err(eyre!("unexpected {service}.{function} result: {other:?}")),
+        }
+    }
+
+    fn owner_id(&self) -> String {
+        self.client.peer_id.to_string()
+    }
+
+    /// Upload modules of the service and return its blueprint id.
+    /// Modules and blueprints are content-addressed, so uploading them doesn't affect running services.
+    pub async fn upload_service(&mut self, service: &ServiceManifest) -> eyre::Result<String> {
+        let mut hashes = vec![];
+        for module in &service.modules {
+            let wasm = base64.encode(module.load_wasm()?);
+            let hash = self
+                .call(
+                    "dist",
+                    "add_module",
+                    vec![json!(wasm), module.named_config()],
+                )
+                .await?;
+            hashes.push(hash);
+        }
+        let blueprint = self
+            .call(
+                "dist",
+                "make_blueprint",
+                vec![json!(service.alias), json!(hashes)],
+            )
+            .await?;
+        let blueprint_id = self.call("dist", "add_blueprint", vec![blueprint]).await?;
+        blueprint_id
+            .as_str()
+            .map(|id| id.to_string())
+            .ok_or_else(|| eyre!("unexpected blueprint id {blueprint_id}"))
+    }
+
+    async fn list(&mut self) -> eyre::Result<Vec<ListedService>> {
+        let services = self.call("srv", "list", vec![]).await?;
+        Ok(serde_json::from_value(services)?)
+    }
+
+    pub async fn deployed_services(&mut self) -> eyre::Result<Vec<DeployedService>> {
+        let owner_id = self.owner_id();
+        let services = self
+            .list()
+            .await?
+            .into_iter()
+            .filter(|s| s.service_type == "service")
+            .filter_map(|s| {
+                let alias = s.aliases.first()?.clone();
+                Some(DeployedService {
+                    id: s.id,
+                    alias,
+                    blueprint_id: s.blueprint_id,
+                    owned: s.owner_id == owner_id,
+                })
+            })
+            .collect();
+        Ok(services)
+    }
+
+    pub async fn deployed_spells(&mut self) -> eyre::Result<Vec<DeployedSpell>> {
+        let owner_id = self.owner_id();
+        let listed = self
+            .list()
+            .await?
+            .into_iter()
+            .filter(|s| s.service_type == "spell");
+
+        let mut spells = vec![];
+        for spell in listed {
+            let Some(alias) = spell.aliases.first().cloned() else {
+                continue;
+            };
+            let script = self.call(&spell.id, "get_script", vec![]).await?;
+            let script = script
+                .get("value")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| eyre!("unexpected script of spell {alias}: {script}"))?
+                .to_string();
+            let config = self.call(&spell.id, "get_trigger_config", vec![]).await?;
+            let trigger_config: TriggerConfig = config
+                .get("config")
+                .cloned()
+                .map(serde_json::from_value)
+                .transpose()?
+                .ok_or_else(|| eyre!("unexpected trigger config of spell {alias}: {config}"))?;
+            spells.push(DeployedSpell {
+                id: spell.id,
+                alias,
+                script,
+                trigger_config,
+                owned: spell.owner_id == owner_id,
+            });
+        }
+        Ok(spells)
+    }
+
+    pub async fn create_service(&mut self, service: &DesiredService) -> eyre::Result<()> {
+        let service_id = self
+            .call("srv", "create", vec![json!(service.blueprint_id)])
+            .await?;
+        self.call("srv", "add_alias", vec![json!(service.alias), service_id])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_service(&mut self, service_id: &str) -> eyre::Result<()> {
+        self.call("srv", "remove", vec![json!(service_id)]).await?;
+        Ok(())
+    }
+
+    pub async fn install_spell(&mut self, spell: &DesiredSpell) -> eyre::Result<()> {
+        self.call(
+            "spell",
+            "install",
+            vec![
+                json!(spell.script),
+                spell.init_data.clone(),
+                json!(spell.trigger_config),
+                json!(spell.alias),
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_spell_config(
+        &mut self,
+        spell_id: &str,
+        trigger_config: &TriggerConfig,
+    ) -> eyre::Result<()> {
+        self.call(
+            "spell",
+            "update_trigger_config",
+            vec![json!(spell_id), json!(trigger_config)],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_spell(&mut self, spell_id: &str) -> eyre::Result<()> {
+        self.call("spell", "remove", vec![json!(spell_id)]).await?;
+        Ok(())
+    }
+}
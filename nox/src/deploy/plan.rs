@@ -0,0 +1,273 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use fluence_spell_dtos::trigger_config::TriggerConfig;
+use serde_json::Value as JValue;
+
+/// Service as described by the manifest, with modules already uploaded to the node
+#[derive(Debug, Clone)]
+pub struct DesiredService {
+    pub alias: String,
+    pub blueprint_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DesiredSpell {
+    pub alias: String,
+    pub script: String,
+    pub trigger_config: TriggerConfig,
+    pub init_data: JValue,
+}
+
+/// Aliased service found on the node
+#[derive(Debug, Clone)]
+pub struct DeployedService {
+    pub id: String,
+    pub alias: String,
+    pub blueprint_id: String,
+    /// Whether the service was created by the peer running the deploy; only such services are pruned
+    pub owned: bool,
+}
+
+/// Aliased spell found on the node
+#[derive(Debug, Clone)]
+pub struct DeployedSpell {
+    pub id: String,
+    pub alias: String,
+    pub script: String,
+    pub trigger_config: TriggerConfig,
+    /// Whether the spell was installed by the peer running the deploy; only such spells are pruned
+    pub owned: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    CreateService(DesiredService),
+    /// Services can't be updated in place, so the old one is removed and a new one is created
+    RecreateService {
+        current: DeployedService,
+        desired: DesiredService,
+    },
+    RemoveService(DeployedService),
+    InstallSpell(DesiredSpell),
+    /// A new script requires reinstalling the spell, which resets its KV
+    ReinstallSpell {
+        current: DeployedSpell,
+        desired: DesiredSpell,
+    },
+    UpdateSpellConfig {
+        current: DeployedSpell,
+        desired: DesiredSpell,
+    },
+    RemoveSpell(DeployedSpell),
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::CreateService(desired) => write!(
+                f,
+                "+ service {} (blueprint {})",
+                desired.alias, desired.blueprint_id
+            ),
+            Change::RecreateService { current, desired } => write!(
+                f,
+                "~ service {} (blueprint {} -> {})",
+                desired.alias, current.blueprint_id, desired.blueprint_id
+            ),
+            Change::RemoveService(current) => {
+                write!(f, "- service {} ({})", current.alias, current.id)
+            }
+            Change::InstallSpell(desired) => write!(f, "+ spell {}", desired.alias),
+            Change::ReinstallSpell { current, .. } => {
+                write!(
+                    f,
+                    "~ spell {} (script changed, KV will be reset)",
+                    current.alias
+                )
+            }
+            Change::UpdateSpellConfig { current, .. } => {
+                write!(f, "~ spell {} (trigger config changed)", current.alias)
+            }
+            Change::RemoveSpell(current) => {
+                write!(f, "- spell {} ({})", current.alias, current.id)
+            }
+        }
+    }
+}
+
+/// Compute changes needed to bring the node to the desired state.
+/// Services and spells without aliases are never touched.
+pub fn plan(
+    desired_services: Vec<DesiredService>,
+    desired_spells: Vec<DesiredSpell>,
+    deployed_services: Vec<DeployedService>,
+    deployed_spells: Vec<DeployedSpell>,
+    prune: bool,
+) -> Vec<Change> {
+    let mut changes = vec![];
+
+    let mut deployed_services: HashMap<String, DeployedService> = deployed_services
+        .into_iter()
+        .map(|s| (s.alias.clone(), s))
+        .collect();
+    for desired in desired_services {
+        match deployed_services.remove(&desired.alias) {
+            None => changes.push(Change::CreateService(desired)),
+            Some(current) if current.blueprint_id != desired.blueprint_id => {
+                changes.push(Change::RecreateService { current, desired })
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut deployed_spells: HashMap<String, DeployedSpell> = deployed_spells
+        .into_iter()
+        .map(|s| (s.alias.clone(), s))
+        .collect();
+    for desired in desired_spells {
+        match deployed_spells.remove(&desired.alias) {
+            None => changes.push(Change::InstallSpell(desired)),
+            Some(current) if current.script != desired.script => {
+                changes.push(Change::ReinstallSpell { current, desired })
+            }
+            Some(current) if current.trigger_config != desired.trigger_config => {
+                changes.push(Change::UpdateSpellConfig { current, desired })
+            }
+            Some(_) => {}
+        }
+    }
+
+    if prune {
+        let mut extraneous_services = deployed_services
+            .into_values()
+            .filter(|s| s.owned)
+            .collect::<Vec<_>>();
+        extraneous_services.sort_by(|a, b| a.alias.cmp(&b.alias));
+        changes.extend(extraneous_services.into_iter().map(Change::RemoveService));
+
+        let mut extraneous_spells = deployed_spells
+            .into_values()
+            .filter(|s| s.owned)
+            .collect::<Vec<_>>();
+        extraneous_spells.sort_by(|a, b| a.alias.cmp(&b.alias));
+        changes.extend(extraneous_spells.into_iter().map(Change::RemoveSpell));
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::assert_matches::assert_matches;
+
+    fn desired_service(alias: &str, blueprint_id: &str) -> DesiredService {
+        DesiredService {
+            alias: alias.to_string(),
+            blueprint_id: blueprint_id.to_string(),
+        }
+    }
+
+    fn deployed_service(alias: &str, blueprint_id: &str, owned: bool) -> DeployedService {
+        DeployedService {
+            id: format!("{alias}-id"),
+            alias: alias.to_string(),
+            blueprint_id: blueprint_id.to_string(),
+            owned,
+        }
+    }
+
+    fn desired_spell(alias: &str, script: &str, period_sec: u32) -> DesiredSpell {
+        let mut trigger_config = TriggerConfig::default();
+        trigger_config.clock.period_sec = period_sec;
+        DesiredSpell {
+            alias: alias.to_string(),
+            script: script.to_string(),
+            trigger_config,
+            init_data: json!({}),
+        }
+    }
+
+    fn deployed_spell(alias: &str, script: &str, period_sec: u32) -> DeployedSpell {
+        let desired = desired_spell(alias, script, period_sec);
+        DeployedSpell {
+            id: format!("{alias}-id"),
+            alias: desired.alias,
+            script: desired.script,
+            trigger_config: desired.trigger_config,
+            owned: true,
+        }
+    }
+
+    #[test]
+    fn plan_services() {
+        let changes = plan(
+            vec![
+                desired_service("new", "bp1"),
+                desired_service("changed", "bp2"),
+                desired_service("same", "bp3"),
+            ],
+            vec![],
+            vec![
+                deployed_service("changed", "bp1", true),
+                deployed_service("same", "bp3", true),
+                deployed_service("extra", "bp4", true),
+                deployed_service("system", "bp5", false),
+            ],
+            vec![],
+            true,
+        );
+
+        assert_eq!(changes.len(), 3, "{changes:?}");
+        assert_matches!(&changes[0], Change::CreateService(s) if s.alias == "new");
+        assert_matches!(&changes[1], Change::RecreateService { current, .. } if current.alias == "changed");
+        assert_matches!(&changes[2], Change::RemoveService(s) if s.alias == "extra");
+    }
+
+    #[test]
+    fn plan_spells() {
+        let changes = plan(
+            vec![],
+            vec![
+                desired_spell("new", "(null)", 0),
+                desired_spell("script", "(seq (null) (null))", 0),
+                desired_spell("config", "(null)", 60),
+                desired_spell("same", "(null)", 0),
+            ],
+            vec![],
+            vec![
+                deployed_spell("script", "(null)", 0),
+                deployed_spell("config", "(null)", 0),
+                deployed_spell("same", "(null)", 0),
+                deployed_spell("extra", "(null)", 0),
+            ],
+            false,
+        );
+
+        assert_eq!(changes.len(), 3, "{changes:?}");
+        assert_matches!(&changes[0], Change::InstallSpell(s) if s.alias == "new");
+        assert_matches!(&changes[1], Change::ReinstallSpell { current, .. } if current.alias == "script");
+        assert_matches!(&changes[2], Change::UpdateSpellConfig { current, .. } if current.alias == "config");
+    }
+}
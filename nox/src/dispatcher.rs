@@ -17,7 +17,9 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use futures::{FutureExt, StreamExt};
+use std::sync::Arc;
+
+use futures::{stream, FutureExt, StreamExt};
 use prometheus_client::registry::Registry;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
@@ -25,10 +27,13 @@ use tracing::{instrument, Instrument};
 
 use aquamarine::{AquamarineApi, AquamarineApiError, RemoteRoutingEffects};
 use fluence_libp2p::PeerId;
+use now_millis::ClockCorrection;
 use particle_protocol::{ExtendedParticle, Particle};
 use peer_metrics::DispatcherMetrics;
 
 use crate::effectors::Effectors;
+use crate::particle_dedup::ParticleDedup;
+use crate::particle_wal::ParticleWal;
 use crate::tasks::Tasks;
 
 type Effects = Result<RemoteRoutingEffects, AquamarineApiError>;
@@ -42,6 +47,14 @@ pub struct Dispatcher {
     aquamarine: AquamarineApi,
     effectors: Effectors,
     metrics: Option<DispatcherMetrics>,
+    /// Log of particles that aren't executed yet, absent if persistence is disabled
+    wal: Option<Arc<ParticleWal>>,
+    /// Particles from the log to execute before the new ones
+    replay: Vec<Particle>,
+    /// Filter of exact particle copies, absent if disabled
+    dedup: Option<Arc<ParticleDedup>>,
+    /// Correction of the local clock applied to particle deadlines
+    clock: ClockCorrection,
 }
 
 impl Dispatcher {
@@ -58,18 +71,52 @@ impl Dispatcher {
             aquamarine,
             particle_parallelism,
             metrics: registry.map(|r| DispatcherMetrics::new(r, particle_parallelism)),
+            wal: None,
+            replay: vec![],
+            dedup: None,
+            clock: <_>::default(),
         }
     }
+
+    /// Check particle deadlines against the local clock corrected by the estimated skew
+    pub fn with_clock_correction(mut self, clock: ClockCorrection) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Persist accepted particles to `wal` and replay `replay` on start
+    pub fn with_particle_wal(mut self, wal: ParticleWal, replay: Vec<Particle>) -> Self {
+        self.wal = Some(Arc::new(wal));
+        self.replay = replay;
+        self
+    }
+
+    /// Drop exact copies of the accepted particles, e.g. sent by a client through several relays
+    pub fn with_particle_dedup(mut self, dedup: ParticleDedup) -> Self {
+        self.dedup = Some(Arc::new(dedup));
+        self
+    }
 }
 
 impl Dispatcher {
     pub fn start(
-        self,
+        mut self,
         particle_stream: mpsc::Receiver<ExtendedParticle>,
         effects_stream: mpsc::Receiver<Effects>,
     ) -> Tasks {
         log::info!("starting dispatcher");
-        let particle_stream = ReceiverStream::new(particle_stream);
+        let replay = std::mem::take(&mut self.replay);
+        if !replay.is_empty() {
+            log::info!("replaying {} particles from the log", replay.len());
+            if let Some(m) = self.metrics.as_ref() {
+                m.particles_replayed(replay.len());
+            }
+        }
+        let replay = stream::iter(replay).map(|particle| {
+            let span = tracing::info_span!("Dispatcher::replay", particle_id = particle.id);
+            ExtendedParticle::new(particle, span)
+        });
+        let particle_stream = replay.chain(ReceiverStream::new(particle_stream));
         let effects_stream = ReceiverStream::new(effects_stream);
         let particles = tokio::task::Builder::new()
             .name("particles")
@@ -94,6 +141,9 @@ impl Dispatcher {
         let parallelism = self.particle_parallelism;
         let aquamarine = self.aquamarine;
         let metrics = self.metrics;
+        let wal = self.wal;
+        let dedup = self.dedup;
+        let clock = self.clock;
         particle_stream
             .for_each_concurrent(parallelism, move |ext_particle| {
                 let current_span = tracing::info_span!(parent: ext_particle.span.as_ref(), "Dispatcher::process_particles::for_each");
@@ -103,7 +153,7 @@ impl Dispatcher {
                 let metrics = metrics.clone();
                 let particle: &Particle = ext_particle.as_ref();
 
-                if particle.is_expired() {
+                if particle.is_expired_at(clock.now_ms()) {
                     let particle_id = &particle.id.as_str();
                     if let Some(m) = metrics {
                         m.particle_expired(particle_id);
@@ -111,6 +161,16 @@ impl Dispatcher {
                     tracing::info!(target: "expired", particle_id = particle_id, "Particle is expired");
                     return async {}.boxed();
                 }
+                if dedup.as_ref().is_some_and(|d| d.is_duplicate(particle)) {
+                    if let Some(m) = metrics {
+                        m.particle_deduplicated();
+                    }
+                    tracing::debug!(particle_id = particle.id, "Particle is a copy of the accepted one");
+                    return async {}.boxed();
+                }
+                if let Some(wal) = wal.as_ref() {
+                    wal.accepted(particle);
+                }
 
                 async move {
                     aquamarine
@@ -134,9 +194,19 @@ impl Dispatcher {
     {
         let parallelism = self.particle_parallelism;
         let effectors = self.effectors;
+        let wal = self.wal;
         effects_stream
             .for_each_concurrent(parallelism, move |effects| {
                 let effectors = effectors.clone();
+                if let Some(wal) = wal.as_ref() {
+                    let particle_id = match &effects {
+                        Ok(effects) => Some(effects.particle.particle.id.clone()),
+                        Err(err) => err.particle_id().map(str::to_string),
+                    };
+                    if let Some(particle_id) = particle_id {
+                        wal.done(&particle_id);
+                    }
+                }
 
                 async move {
                     match effects {
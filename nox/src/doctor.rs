@@ -0,0 +1,566 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! `nox doctor` checks the environment the node is going to run in: permissions of the key and
+//! storage paths, availability of the ports, the clock, limits of open files and connectivity
+//! to bootstrap nodes and chain RPC endpoints. It prints a finding with a hint for each problem.
+
+use std::ffi::OsString;
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{IpAddr, TcpListener};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+use eyre::{eyre, WrapErr};
+use libp2p::multiaddr::Protocol;
+use libp2p::Multiaddr;
+use serde::Serialize;
+use serde_json::{json, Value as JValue};
+use tokio::net::TcpStream;
+
+use server_config::{
+    default_builtins_keypair_path, default_keypair_path, load_config_with_args, KeypairConfig,
+    ResolvedConfig,
+};
+
+/// 2024-01-01T00:00:00Z, a clock before that is certainly wrong
+const MIN_SANE_TIMESTAMP: u64 = 1_704_067_200;
+/// Soft limit of open files below which a busy node starts to fail connections
+const MIN_OPEN_FILES: u64 = 65536;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "nox doctor",
+    about = "Check the environment before starting the node"
+)]
+struct DoctorArgs {
+    /// Print the findings as JSON
+    #[arg(long)]
+    json: bool,
+    /// Timeout of every network check, in seconds
+    #[arg(long, default_value_t = 5)]
+    timeout: u64,
+    /// Arguments the node is started with, the config is loaded from them the same way
+    #[arg(last = true)]
+    node_args: Vec<OsString>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Result of a single check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            check,
+            severity: Severity::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warning(check: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            check,
+            severity: Severity::Warning,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn error(check: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            check,
+            severity: Severity::Error,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.severity {
+            Severity::Ok => " ok ",
+            Severity::Warning => "warn",
+            Severity::Error => "FAIL",
+        };
+        write!(f, "[{label}] {}: {}", self.check, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       hint: {hint}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Entrypoint of `nox doctor`, `args` start with the subcommand name
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = DoctorArgs::parse_from(args);
+    let timeout = Duration::from_secs(args.timeout);
+
+    let raw_args = std::iter::once(OsString::from("nox"))
+        .chain(args.node_args)
+        .collect();
+    let config = load_config_with_args(raw_args, None)?;
+    // key configs are consumed by resolve, keep them to locate the key files
+    let keys = KeyConfigs {
+        root: config.node_config.root_key_pair.clone().unwrap_or_default(),
+        builtins: config
+            .node_config
+            .builtins_key_pair
+            .clone()
+            .unwrap_or_default(),
+    };
+    let findings = match config.resolve() {
+        Ok(config) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(diagnose(&config, &keys, timeout)),
+        Err(err) => vec![Finding::error(
+            "config",
+            format!("{err:#}"),
+            "fix the config, the node won't start with it",
+        )],
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+    } else {
+        for finding in &findings {
+            println!("{finding}");
+        }
+    }
+
+    if findings.iter().any(|f| f.severity == Severity::Error) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+struct KeyConfigs {
+    root: KeypairConfig,
+    builtins: KeypairConfig,
+}
+
+async fn diagnose(config: &ResolvedConfig, keys: &KeyConfigs, timeout: Duration) -> Vec<Finding> {
+    let mut findings = vec![Finding::ok("config", "loaded and resolved")];
+
+    let dirs = &config.dir_config;
+    for (name, path) in [
+        ("base_dir", &dirs.base_dir),
+        ("persistent_base_dir", &dirs.persistent_base_dir),
+        ("ephemeral_base_dir", &dirs.ephemeral_base_dir),
+        ("services_persistent_dir", &dirs.services_persistent_dir),
+        ("avm_base_dir", &dirs.avm_base_dir),
+        ("keypairs_base_dir", &dirs.keypairs_base_dir),
+    ] {
+        findings.push(check_dir(name, path));
+    }
+
+    let root_key = keys
+        .root
+        .key_path(default_keypair_path(&dirs.persistent_base_dir));
+    let builtins_key = keys
+        .builtins
+        .key_path(default_builtins_keypair_path(&dirs.persistent_base_dir));
+    for path in root_key.iter().chain(builtins_key.iter()) {
+        findings.push(check_key_file(path));
+    }
+
+    let listen = &config.listen_config;
+    let mut ports = vec![
+        ("tcp", listen.tcp_port),
+        ("websocket", listen.websocket_port),
+    ];
+    if let Some(http) = &config.http_config {
+        ports.push(("http", http.http_port));
+    }
+    for (name, port) in ports {
+        findings.push(check_port(name, listen.listen_ip, port));
+    }
+
+    findings.push(check_open_files());
+
+    let reference_time = match &config.chain_config {
+        Some(chain) => {
+            let (finding, date) =
+                check_chain_rpc(&chain.http_endpoint, chain.network_id, timeout).await;
+            findings.push(finding);
+            date
+        }
+        None => None,
+    };
+    findings.push(check_clock(
+        SystemTime::now(),
+        reference_time,
+        config.clock_skew_config.warn_threshold,
+    ));
+
+    if let Some(listener) = &config.chain_listener_config {
+        findings.push(check_url_reachable("chain_listener", &listener.ws_endpoint, timeout).await);
+        if let Some(ccp) = &listener.ccp_endpoint {
+            findings.push(check_url_reachable("ccp", ccp, timeout).await);
+        }
+    }
+
+    for node in &config.bootstrap_nodes {
+        findings.push(check_bootstrap(node, timeout).await);
+    }
+
+    findings
+}
+
+fn check_dir(name: &'static str, path: &Path) -> Finding {
+    let probe = path.join(format!(".nox-doctor-{}", std::process::id()));
+    let result = std::fs::create_dir_all(path)
+        .and_then(|_| std::fs::write(&probe, b"probe"))
+        .and_then(|_| std::fs::remove_file(&probe));
+    match result {
+        Ok(()) => Finding::ok(name, format!("{} is writable", path.display())),
+        Err(err) => Finding::error(
+            name,
+            format!("{} is not writable: {err}", path.display()),
+            "make the directory owned by the user running nox, e.g. `chown -R <user> <dir>`",
+        ),
+    }
+}
+
+fn check_key_file(path: &Path) -> Finding {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Finding::ok(
+                "key",
+                format!("{} doesn't exist and will be generated", path.display()),
+            )
+        }
+        Err(err) => {
+            return Finding::error(
+                "key",
+                format!("{} can't be read: {err}", path.display()),
+                "make the key file readable by the user running nox",
+            )
+        }
+    };
+
+    if let Some(mode) = unix_mode(&metadata) {
+        if mode & 0o077 != 0 {
+            return Finding::warning(
+                "key",
+                format!(
+                    "{} is accessible by other users (mode {:o})",
+                    path.display(),
+                    mode & 0o777
+                ),
+                format!("restrict it with `chmod 600 {}`", path.display()),
+            );
+        }
+    }
+    Finding::ok("key", format!("{} is private", path.display()))
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+fn check_port(name: &'static str, ip: IpAddr, port: u16) -> Finding {
+    match TcpListener::bind((ip, port)) {
+        Ok(_) => Finding::ok(name, format!("{ip}:{port} is free")),
+        Err(err) if err.kind() == ErrorKind::AddrInUse => Finding::error(
+            name,
+            format!("{ip}:{port} is already in use"),
+            "stop the process listening on it (is another node running?) or change the port in the config",
+        ),
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => Finding::error(
+            name,
+            format!("not allowed to listen on {ip}:{port}"),
+            "ports below 1024 require privileges, use a higher port",
+        ),
+        Err(err) => Finding::error(
+            name,
+            format!("can't listen on {ip}:{port}: {err}"),
+            "check that listen_ip is an address of this host",
+        ),
+    }
+}
+
+#[cfg(unix)]
+fn check_open_files() -> Finding {
+    use nix::sys::resource::{getrlimit, Resource};
+
+    match getrlimit(Resource::RLIMIT_NOFILE) {
+        Ok((soft, _)) if soft < MIN_OPEN_FILES => Finding::warning(
+            "open_files",
+            format!("limit of open files is {soft}"),
+            format!("raise it to at least {MIN_OPEN_FILES}, e.g. `ulimit -n {MIN_OPEN_FILES}` or LimitNOFILE in the systemd unit"),
+        ),
+        Ok((soft, _)) => Finding::ok("open_files", format!("limit of open files is {soft}")),
+        Err(err) => Finding::warning(
+            "open_files",
+            format!("can't get the limit of open files: {err}"),
+            "check `ulimit -n`",
+        ),
+    }
+}
+
+#[cfg(not(unix))]
+fn check_open_files() -> Finding {
+    Finding::ok("open_files", "not checked on this platform")
+}
+
+/// Checks that the local clock is sane, and close to `reference` if it's known
+fn check_clock(now: SystemTime, reference: Option<SystemTime>, threshold: Duration) -> Finding {
+    let now_secs = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    if now_secs < MIN_SANE_TIMESTAMP {
+        return Finding::error(
+            "clock",
+            "system clock is in the past",
+            "enable time synchronization, e.g. `timedatectl set-ntp true`",
+        );
+    }
+
+    let Some(reference) = reference else {
+        return Finding::ok("clock", "no reference time to compare with");
+    };
+    let skew = match now.duration_since(reference) {
+        Ok(ahead) => ahead,
+        Err(behind) => behind.duration(),
+    };
+    // HTTP dates have a precision of a second
+    if skew > threshold + Duration::from_secs(1) {
+        Finding::warning(
+            "clock",
+            format!("clock differs from the chain RPC by {}s", skew.as_secs()),
+            "enable time synchronization, particles may be treated as expired otherwise",
+        )
+    } else {
+        Finding::ok("clock", "in sync with the chain RPC")
+    }
+}
+
+/// Checks the chain RPC responds with the configured network id; returns its `Date` if any
+async fn check_chain_rpc(
+    endpoint: &str,
+    network_id: u64,
+    timeout: Duration,
+) -> (Finding, Option<SystemTime>) {
+    let result: eyre::Result<_> = async {
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": "eth_chainId", "params": []});
+        let response = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()?
+            .post(endpoint)
+            .header("content-type", "application/json")
+            .body(serde_json::to_vec(&body)?)
+            .send()
+            .await?;
+        let date = response
+            .headers()
+            .get("date")
+            .and_then(|d| d.to_str().ok())
+            .and_then(|d| httpdate::parse_http_date(d).ok());
+        let response: JValue = serde_json::from_slice(&response.bytes().await?)?;
+        let chain_id = response["result"]
+            .as_str()
+            .and_then(|id| u64::from_str_radix(id.trim_start_matches("0x"), 16).ok())
+            .ok_or_else(|| eyre!("unexpected response {response}"))?;
+        Ok((chain_id, date))
+    }
+    .await;
+
+    match result {
+        Ok((chain_id, date)) if chain_id == network_id => (
+            Finding::ok("chain_rpc", format!("{endpoint} serves network {chain_id}")),
+            date,
+        ),
+        Ok((chain_id, date)) => (
+            Finding::error(
+                "chain_rpc",
+                format!("{endpoint} serves network {chain_id}, but network_id is {network_id}"),
+                "point chain_config.http_endpoint to the RPC of the configured network",
+            ),
+            date,
+        ),
+        Err(err) => (
+            Finding::error(
+                "chain_rpc",
+                format!("{endpoint} is unavailable: {err:#}"),
+                "check the URL and that the RPC provider is reachable from this host",
+            ),
+            None,
+        ),
+    }
+}
+
+async fn check_url_reachable(check: &'static str, url: &str, timeout: Duration) -> Finding {
+    let target = reqwest::Url::parse(url)
+        .wrap_err("invalid URL")
+        .and_then(|url| {
+            let host = url.host_str().ok_or_else(|| eyre!("no host in URL"))?;
+            let port = url
+                .port_or_known_default()
+                .or_else(|| match url.scheme() {
+                    "ws" => Some(80),
+                    "wss" => Some(443),
+                    _ => None,
+                })
+                .ok_or_else(|| eyre!("no port in URL"))?;
+            Ok((host.to_string(), port))
+        });
+    let (host, port) = match target {
+        Ok(target) => target,
+        Err(err) => {
+            return Finding::error(
+                check,
+                format!("{url}: {err}"),
+                "fix the endpoint in the config",
+            )
+        }
+    };
+
+    match connect(&host, port, timeout).await {
+        Ok(()) => Finding::ok(check, format!("{url} is reachable")),
+        Err(err) => Finding::error(
+            check,
+            format!("{url} is unreachable: {err:#}"),
+            "check the URL and firewall rules for outgoing connections",
+        ),
+    }
+}
+
+async fn check_bootstrap(node: &Multiaddr, timeout: Duration) -> Finding {
+    let Some((host, port)) = tcp_target(node) else {
+        return Finding::ok(
+            "bootstrap",
+            format!("{node} isn't a TCP address, not checked"),
+        );
+    };
+
+    match connect(&host, port, timeout).await {
+        Ok(()) => Finding::ok("bootstrap", format!("{node} is reachable")),
+        Err(err) => Finding::warning(
+            "bootstrap",
+            format!("{node} is unreachable: {err:#}"),
+            "check outgoing connections, the node can't join the network without bootstrap nodes",
+        ),
+    }
+}
+
+/// Host and TCP port of a multiaddr
+fn tcp_target(addr: &Multiaddr) -> Option<(String, u16)> {
+    let mut host = None;
+    let mut port = None;
+    for protocol in addr.iter() {
+        match protocol {
+            Protocol::Ip4(ip) => host = Some(ip.to_string()),
+            Protocol::Ip6(ip) => host = Some(ip.to_string()),
+            Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+                host = Some(name.to_string())
+            }
+            Protocol::Tcp(p) => port = Some(p),
+            _ => {}
+        }
+    }
+    host.zip(port)
+}
+
+async fn connect(host: &str, port: u16, timeout: Duration) -> eyre::Result<()> {
+    tokio::time::timeout(timeout, TcpStream::connect((host, port)))
+        .await
+        .map_err(|_| eyre!("timed out after {}s", timeout.as_secs()))??;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_tcp_target() {
+        let addr: Multiaddr = "/dns4/stage.fluence.dev/tcp/7777/p2p/12D3KooWDcpWuyrMTDinqNgmXAuRdfd2mTdY9VoXZSAet2pDzh6r"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            tcp_target(&addr),
+            Some(("stage.fluence.dev".to_string(), 7777))
+        );
+
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/9990/ws".parse().unwrap();
+        assert_eq!(tcp_target(&addr), Some(("127.0.0.1".to_string(), 9990)));
+
+        let addr: Multiaddr = "/memory/42".parse().unwrap();
+        assert_eq!(tcp_target(&addr), None);
+    }
+
+    #[test]
+    fn checks_clock() {
+        let threshold = Duration::from_secs(2);
+        let now = UNIX_EPOCH + Duration::from_secs(MIN_SANE_TIMESTAMP + 1000);
+
+        let past = UNIX_EPOCH + Duration::from_secs(1000);
+        assert_eq!(check_clock(past, None, threshold).severity, Severity::Error);
+        assert_eq!(check_clock(now, None, threshold).severity, Severity::Ok);
+
+        let close = now - Duration::from_secs(1);
+        assert_eq!(
+            check_clock(now, Some(close), threshold).severity,
+            Severity::Ok
+        );
+        let far = now + Duration::from_secs(60);
+        assert_eq!(
+            check_clock(now, Some(far), threshold).severity,
+            Severity::Warning
+        );
+    }
+
+    #[test]
+    fn detects_busy_port() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let finding = check_port("tcp", "127.0.0.1".parse().unwrap(), port);
+        assert_eq!(finding.severity, Severity::Error);
+        assert!(finding.message.contains("in use"));
+    }
+}
@@ -17,54 +17,195 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::sync::Arc;
+use std::time::Duration;
+
+use fluence_libp2p::PeerId;
 use futures::{stream::iter, StreamExt};
 use tracing::instrument;
 
 use aquamarine::RemoteRoutingEffects;
-use particle_protocol::Particle;
+use now_millis::{now_ms, ClockCorrection};
+use particle_protocol::{ExtendedParticle, Particle};
 
+use crate::circuit_breaker::CircuitBreakers;
 use crate::connectivity::Connectivity;
+use crate::loop_detector::LoopDetector;
+use crate::ttl_guard::TtlGuard;
 
 #[derive(Clone)]
 pub struct Effectors {
     pub connectivity: Connectivity,
+    /// Drops particles going in circles, absent if disabled
+    loop_detector: Option<Arc<LoopDetector>>,
+    /// Fail fast sends to peers failing in a row, absent if disabled
+    circuit_breakers: Option<Arc<CircuitBreakers>>,
+    /// Drops particles with too little TTL left, absent if disabled
+    ttl_guard: Option<Arc<TtlGuard>>,
+    /// Max number of times a failed send is retried
+    max_retries: u32,
+    /// Pause before the first retry, doubled on each next one
+    retry_backoff: Duration,
+    /// Correction of the local clock applied to particle deadlines
+    clock: ClockCorrection,
 }
 
 impl Effectors {
     pub fn new(connectivity: Connectivity) -> Self {
-        Self { connectivity }
+        Self {
+            connectivity,
+            loop_detector: None,
+            circuit_breakers: None,
+            ttl_guard: None,
+            max_retries: 0,
+            retry_backoff: Duration::ZERO,
+            clock: <_>::default(),
+        }
+    }
+
+    /// Check particle deadlines against the local clock corrected by the estimated skew
+    pub fn with_clock_correction(mut self, clock: ClockCorrection) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Fail fast instead of sending particles to peers whose breakers are open
+    pub fn with_circuit_breakers(mut self, circuit_breakers: CircuitBreakers) -> Self {
+        self.circuit_breakers = Some(Arc::new(circuit_breakers));
+        self
+    }
+
+    /// Retry failed sends up to `max_retries` times, with exponential backoff
+    pub fn with_retries(mut self, max_retries: u32, retry_backoff: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Drop particles instead of sending them further if they're going in circles
+    pub fn with_loop_detector(mut self, loop_detector: LoopDetector) -> Self {
+        self.loop_detector = Some(Arc::new(loop_detector));
+        self
+    }
+
+    /// Drop particles instead of sending them further if they have too little TTL left
+    pub fn with_ttl_guard(mut self, ttl_guard: TtlGuard) -> Self {
+        self.ttl_guard = Some(Arc::new(ttl_guard));
+        self
     }
 
     /// Perform effects that Aquamarine instructed us to
     #[instrument(level = tracing::Level::INFO, skip_all)]
     pub async fn execute(self, effects: RemoteRoutingEffects) {
         let particle: &Particle = effects.particle.as_ref();
-        if particle.is_expired() {
+        if particle.is_expired_at(self.clock.now_ms()) {
             tracing::info!(target: "expired", particle_id = particle.id, "Particle is expired");
             return;
         }
 
+        // nothing is sent, so it doesn't count as a forward
+        if effects.next_peers.is_empty() {
+            return;
+        }
+        let looped = self
+            .loop_detector
+            .as_ref()
+            .and_then(|detector| detector.check(particle));
+        if let Some(reason) = looped {
+            if let Some(m) = self.connectivity.metrics.as_ref() {
+                m.particle_looped(reason);
+            }
+            tracing::warn!(
+                particle_id = particle.id,
+                init_peer_id = %particle.init_peer_id,
+                hops = particle.hops,
+                next_peers = ?effects.next_peers,
+                "Particle is dropped instead of being sent further: forwarding loop detected ({reason:?})"
+            );
+            return;
+        }
+
+        let exhausted = self
+            .ttl_guard
+            .as_ref()
+            .and_then(|guard| Some((guard, guard.check(particle, self.clock.now_ms())?)));
+        if let Some((guard, remaining)) = exhausted {
+            if let Some(m) = self.connectivity.metrics.as_ref() {
+                m.particle_ttl_exhausted();
+            }
+            tracing::warn!(
+                particle_id = particle.id,
+                init_peer_id = %particle.init_peer_id,
+                remaining_ms = remaining.as_millis() as u64,
+                next_peers = ?effects.next_peers,
+                "Particle is dropped instead of being sent further: too little TTL left"
+            );
+            if let Some(notice) = guard.expired_notice(particle, remaining) {
+                let init_peer_id = particle.init_peer_id;
+                let notice = ExtendedParticle::linked(notice, effects.particle.span.clone());
+                self.send_with_retries(init_peer_id, &notice).await;
+            }
+            return;
+        }
+
+        let mut particle = effects.particle;
+        particle
+            .particle
+            .append_trail(self.connectivity.peer_id, now_ms() as u64);
+        particle.particle.increment_hops();
+
         // take every next peers, and try to send particle there concurrently
         let nps = iter(effects.next_peers);
-        let particle = &effects.particle;
-        let connectivity = self.connectivity.clone();
-        nps.for_each_concurrent(None, move |target| {
-            let connectivity = connectivity.clone();
-            let particle = particle.clone();
-            async move {
-                // resolve contact
-                if let Some(contact) = connectivity
-                    .resolve_contact(target, particle.as_ref())
-                    .await
-                {
-                    // forward particle
-                    let sent = connectivity.send(contact, particle).await;
-                    if sent {
-                        // resolved and sent, exit
-                    }
+        let particle = &particle;
+        let this = &self;
+        nps.for_each_concurrent(None, move |target| this.send_with_retries(target, particle))
+            .await;
+    }
+
+    /// Resolve and send the particle to the target, retrying on failures
+    /// unless the breaker of the target is open or the particle expires
+    async fn send_with_retries(&self, target: PeerId, particle: &ExtendedParticle) {
+        let mut backoff = self.retry_backoff;
+        for attempt in 0..=self.max_retries {
+            if attempt > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff = backoff.saturating_mul(2);
+                if particle.particle.is_expired_at(self.clock.now_ms()) {
+                    return;
+                }
+            }
+
+            let breakers = self.circuit_breakers.as_ref();
+            if !breakers.map_or(true, |b| b.allow(target)) {
+                tracing::debug!(
+                    particle_id = particle.particle.id,
+                    "Particle isn't sent to {target}: its circuit breaker is open"
+                );
+                return;
+            }
+
+            let sent = match self
+                .connectivity
+                .resolve_contact(
+                    target,
+                    particle.as_ref(),
+                    particle.particle.time_to_live_at(self.clock.now_ms()),
+                )
+                .await
+            {
+                Some(contact) => self.connectivity.send(contact, particle.clone()).await,
+                None => false,
+            };
+            if let Some(breakers) = breakers {
+                if sent {
+                    breakers.on_success(target);
+                } else {
+                    breakers.on_failure(target);
                 }
             }
-        })
-        .await;
+            if sent {
+                return;
+            }
+        }
     }
 }
@@ -0,0 +1,112 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! HTTP gateway of the node: metrics, health, config, events, logs and spell webhooks.
+//!
+//! The gateway is compiled in with the `http` feature. Without it the node doesn't listen on
+//! the HTTP port, even if it's configured.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use health::HealthCheckRegistry;
+use libp2p::PeerId;
+use node_events::EventLog;
+use prometheus_client::registry::Registry;
+use server_config::ResolvedConfig;
+use spell_event_bus::api::SpellEventBusApi;
+
+use crate::log_stream::LogStream;
+
+#[cfg(feature = "http")]
+pub(crate) use crate::http::start_http_endpoint;
+
+#[derive(Debug)]
+pub struct StartedHttp {
+    pub listen_addr: SocketAddr,
+}
+
+#[derive(Default)]
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub struct HttpEndpointData {
+    pub(crate) metrics_registry: Option<Arc<Registry>>,
+    pub(crate) health_registry: Option<HealthCheckRegistry>,
+    pub(crate) nox_config: Option<ResolvedConfig>,
+    /// Serve spell webhooks if set
+    pub(crate) spell_event_bus: Option<SpellEventBusApi>,
+    /// Serve the node events if set
+    pub(crate) event_log: Option<EventLog>,
+    /// Serve the logs to the management peer if set
+    pub(crate) log_stream: Option<(LogStream, PeerId)>,
+}
+
+impl HttpEndpointData {
+    pub fn new(
+        metrics_registry: Option<Arc<Registry>>,
+        health_registry: Option<HealthCheckRegistry>,
+        nox_config: Option<ResolvedConfig>,
+    ) -> Self {
+        Self {
+            metrics_registry,
+            health_registry,
+            nox_config,
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
+        }
+    }
+
+    pub fn with_spell_webhooks(mut self, spell_event_bus: SpellEventBusApi) -> Self {
+        self.spell_event_bus = Some(spell_event_bus);
+        self
+    }
+
+    pub fn with_event_log(mut self, event_log: EventLog) -> Self {
+        self.event_log = Some(event_log);
+        self
+    }
+
+    pub fn with_log_stream(mut self, log_stream: LogStream, management_peer_id: PeerId) -> Self {
+        self.log_stream = Some((log_stream, management_peer_id));
+        self
+    }
+}
+
+/// Address the gateway is served on, `None` if it's disabled in the config or compiled out
+pub(crate) fn listen_addr(config: &ResolvedConfig) -> Option<SocketAddr> {
+    let listen_addr = config.http_listen_addr();
+    if cfg!(feature = "http") {
+        return listen_addr;
+    }
+    if let Some(listen_addr) = listen_addr {
+        log::warn!("HTTP gateway is compiled out, not listening on {listen_addr}");
+    }
+    None
+}
+
+/// Never called, [`listen_addr`] is always `None` when the gateway is compiled out
+#[cfg(not(feature = "http"))]
+pub(crate) async fn start_http_endpoint(
+    _listen_addr: SocketAddr,
+    _peer_id: PeerId,
+    _versions: crate::Versions,
+    _http_endpoint_data: HttpEndpointData,
+    _notify: tokio::sync::oneshot::Sender<StartedHttp>,
+) -> eyre::Result<()> {
+    futures::future::pending().await
+}
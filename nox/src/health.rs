@@ -20,7 +20,7 @@
 use health::HealthCheck;
 use libp2p::Multiaddr;
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
@@ -105,6 +105,32 @@ impl HealthCheck for KademliaBootstrapHealth {
     }
 }
 
+/// Tracks the addresses the libp2p swarm listens on
+#[derive(Clone, Default)]
+pub struct SwarmListenHealth {
+    listen_addrs: Arc<RwLock<HashSet<Multiaddr>>>,
+}
+
+impl SwarmListenHealth {
+    pub fn on_new_listen_addr(&self, addr: Multiaddr) {
+        self.listen_addrs.write().insert(addr);
+    }
+
+    pub fn on_expired_listen_addr(&self, addr: &Multiaddr) {
+        self.listen_addrs.write().remove(addr);
+    }
+}
+
+impl HealthCheck for SwarmListenHealth {
+    fn status(&self) -> eyre::Result<()> {
+        if self.listen_addrs.read().is_empty() {
+            Err(eyre::eyre!("Swarm isn't listening on any address"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -212,4 +238,17 @@ mod tests {
             "Kademlia bootstrap not finished"
         );
     }
+
+    #[test]
+    fn swarm_listen_health_fails_without_listen_addrs() {
+        let health = SwarmListenHealth::default();
+        let addr: Multiaddr = "/ip4/127.0.0.1/tcp/7777".parse().unwrap();
+        assert!(health.status().is_err());
+
+        health.on_new_listen_addr(addr.clone());
+        assert!(health.status().is_ok());
+
+        health.on_expired_listen_addr(&addr);
+        assert!(health.status().is_err());
+    }
 }
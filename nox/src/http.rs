@@ -17,31 +17,104 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use crate::gateway::{HttpEndpointData, StartedHttp};
+use crate::log_stream::{verify_auth_header, LogFilter, LogLevel, LogStream};
 use crate::Versions;
 use axum::body::Body;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{DefaultBodyLimit, Path, Query};
+use axum::http::header::AUTHORIZATION;
 use axum::http::header::CONTENT_TYPE;
+use axum::http::HeaderMap;
 use axum::response::ErrorResponse;
 use axum::{
     extract::State,
     http::StatusCode,
     response::{IntoResponse, Response},
-    routing::get,
+    routing::{get, post},
     Json, Router,
 };
-use health::{HealthCheckRegistry, HealthStatus};
+use health::{ComponentState, HealthCheckRegistry, HealthStatus};
 use libp2p::PeerId;
+use node_events::{EventFilter, EventKind, EventLog, NodeEvent};
 use prometheus_client::encoding::text::encode;
 use prometheus_client::registry::Registry;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use server_config::ResolvedConfig;
+use spell_event_bus::api::SpellEventBusApi;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::oneshot;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi};
+
+/// Max size of a webhook request body
+const MAX_WEBHOOK_PAYLOAD_BYTES: usize = 64 * 1024;
+/// Events returned by `/events` when no limit is given
+const DEFAULT_EVENTS_LIMIT: usize = 100;
+const MAX_EVENTS_LIMIT: usize = 1000;
+/// Recent log entries sent by `/logs` when no tail is given
+const DEFAULT_LOGS_TAIL: usize = 100;
 
 async fn handler_404() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "No such endpoint")
 }
 
+/// OpenAPI specification of the routes below, served at `/api/openapi.json`
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Nox HTTP API", description = "HTTP endpoints of a Fluence peer"),
+    paths(
+        handle_metrics,
+        handle_peer_id,
+        handle_versions,
+        handle_health,
+        handle_readiness,
+        handle_config,
+        handle_events,
+        handle_logs,
+        handle_spell_trigger,
+        handle_openapi
+    ),
+    modifiers(&BearerAuth)
+)]
+struct ApiDoc;
+
+/// Spell webhooks take tokens in the `Authorization: Bearer` header
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    responses((status = 200, description = "OpenAPI specification of the node HTTP API", body = Object))
+)]
+async fn handle_openapi(State(state): State<RouteState>) -> Response {
+    let mut doc = ApiDoc::openapi();
+    doc.info.version = state.0.versions.node_version.clone();
+    Json(doc).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in the OpenMetrics text format", body = String, content_type = "application/openmetrics-text"),
+        (status = 404, description = "Metrics are disabled")
+    )
+)]
 async fn handle_metrics(State(state): State<RouteState>) -> axum::response::Result<Response<Body>> {
     let mut buf = String::new();
     let registry = state
@@ -67,6 +140,11 @@ async fn handle_metrics(State(state): State<RouteState>) -> axum::response::Resu
         })
 }
 
+#[utoipa::path(
+    get,
+    path = "/peer_id",
+    responses((status = 200, description = "Peer id of the node", body = Object))
+)]
 async fn handle_peer_id(State(state): State<RouteState>) -> Response {
     let peer_id = state.0.peer_id;
     Json(json!({
@@ -75,21 +153,26 @@ async fn handle_peer_id(State(state): State<RouteState>) -> Response {
     .into_response()
 }
 
+#[utoipa::path(
+    get,
+    path = "/versions",
+    responses((status = 200, description = "Versions of the node, AquaVM, Marine and system services", body = Object))
+)]
 async fn handle_versions(State(state): State<RouteState>) -> Response {
-    let versions = &state.0.versions;
-    Json(json!({
-        "node": versions.node_version,
-        "avm": versions.avm_version,
-        "spell": versions.spell_version,
-        "aqua_ipfs": versions.system_service.aqua_ipfs_version,
-        "trust_graph": versions.system_service.trust_graph_version,
-        "registry": versions.system_service.registry_version,
-        "decider": versions.system_service.decider_version,
-    }))
-    .into_response()
+    Json(state.0.versions.to_json()).into_response()
 }
 
 /// Health check endpoint follows consul contract https://developer.hashicorp.com/consul/docs/services/usage/checks#http-checks
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "All health checks pass", body = [Object]),
+        (status = 429, description = "Some health checks fail", body = [Object]),
+        (status = 503, description = "All health checks fail", body = [Object]),
+        (status = 404, description = "Health checks are disabled")
+    )
+)]
 async fn handle_health(State(state): State<RouteState>) -> axum::response::Result<Response> {
     fn make_json(keys: Vec<&'static str>, status: &str) -> Vec<Value> {
         keys.into_iter().map(|k| json!({k: status})).collect()
@@ -100,23 +183,75 @@ async fn handle_health(State(state): State<RouteState>) -> axum::response::Resul
         .health_registry
         .as_ref()
         .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
-    let result = match registry.status() {
-        HealthStatus::Ok(keys) => (StatusCode::OK, Json(make_json(keys, "Ok"))).into_response(),
+    let (status, mut result) = match registry.status() {
+        HealthStatus::Ok(keys) => (StatusCode::OK, make_json(keys, "Ok")),
         HealthStatus::Warning(ok, fail) => {
             let mut result = make_json(ok, "Ok");
             let mut fail = make_json(fail, "Fail");
             result.append(&mut fail);
-            (StatusCode::TOO_MANY_REQUESTS, Json(result)).into_response()
+            (StatusCode::TOO_MANY_REQUESTS, result)
         }
-        HealthStatus::Fail(keys) => (
-            StatusCode::SERVICE_UNAVAILABLE,
-            Json(make_json(keys, "Fail")),
-        )
-            .into_response(),
+        HealthStatus::Fail(keys) => (StatusCode::SERVICE_UNAVAILABLE, make_json(keys, "Fail")),
     };
-    Ok(result)
+    // Checks tracking startup work report how much of it is done
+    let progress = registry.progress();
+    if !progress.is_empty() {
+        let progress: serde_json::Map<String, Value> = progress
+            .into_iter()
+            .map(|(name, done, total)| (name.to_string(), json!({"done": done, "total": total})))
+            .collect();
+        result.push(json!({ "startup_progress": progress }));
+    }
+    Ok((status, Json(result)).into_response())
 }
 
+/// Readiness of each node component for Kubernetes-style probes: 200 only when every
+/// component is ready, 503 otherwise, the body tells a node that is starting from a degraded one
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "All components are ready", body = Object),
+        (status = 503, description = "Some components are starting or degraded", body = Object),
+        (status = 404, description = "Health checks are disabled")
+    )
+)]
+async fn handle_readiness(State(state): State<RouteState>) -> axum::response::Result<Response> {
+    let registry = state
+        .0
+        .health_registry
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
+    let readiness = registry.readiness();
+    let overall = readiness.state();
+    let components: serde_json::Map<String, Value> = readiness
+        .components
+        .into_iter()
+        .map(|c| {
+            let mut report = json!({ "status": c.state.as_str() });
+            if let Some(error) = c.error {
+                report["error"] = json!(error);
+            }
+            (c.name.to_string(), report)
+        })
+        .collect();
+    let status = if overall == ComponentState::Ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({ "status": overall.as_str(), "components": components });
+    Ok((status, Json(body)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/config",
+    responses(
+        (status = 200, description = "Resolved node config", body = String, content_type = "application/toml"),
+        (status = 500, description = "Config can't be serialized")
+    )
+)]
 async fn handle_config(State(state): State<RouteState>) -> axum::response::Result<Response> {
     let toml = toml::to_string_pretty(&state.0.nox_config);
     match toml {
@@ -128,42 +263,277 @@ async fn handle_config(State(state): State<RouteState>) -> axum::response::Resul
     }
 }
 
-#[derive(Clone)]
-struct RouteState(Arc<Inner>);
+#[derive(Deserialize, IntoParams)]
+struct EventsQuery {
+    /// Comma-separated event kinds
+    kind: Option<String>,
+    subject: Option<String>,
+    worker_id: Option<String>,
+    since: Option<u64>,
+    limit: Option<usize>,
+}
 
-struct Inner {
-    peer_id: PeerId,
-    versions: Versions,
-    metric_registry: Option<Registry>,
-    health_registry: Option<HealthCheckRegistry>,
-    nox_config: Option<ResolvedConfig>,
+/// Node events, oldest first, e.g. `/events?kind=spell_installed,spell_removed&since=1700000000000`.
+/// With a WebSocket upgrade, the events since `since`, if given, are followed by the live ones,
+/// each sent as a JSON text message. Spell triggers and expired particles are only sent live.
+#[utoipa::path(
+    get,
+    path = "/events",
+    params(EventsQuery),
+    responses(
+        (status = 101, description = "WebSocket streaming node events as JSON text messages"),
+        (status = 200, description = "Node events, oldest first", body = [Object]),
+        (status = 400, description = "Unknown event kind"),
+        (status = 404, description = "Event log is disabled")
+    )
+)]
+async fn handle_events(
+    State(state): State<RouteState>,
+    Query(query): Query<EventsQuery>,
+    ws: Option<WebSocketUpgrade>,
+) -> axum::response::Result<Response> {
+    let event_log = state
+        .0
+        .event_log
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
+    let kinds = query
+        .kind
+        .iter()
+        .flat_map(|kinds| kinds.split(','))
+        .map(|kind| serde_json::from_value(Value::String(kind.trim().to_string())))
+        .collect::<Result<Vec<EventKind>, _>>()
+        .map_err(|_| (StatusCode::BAD_REQUEST, "Unknown event kind"))?;
+    let filter = EventFilter {
+        kinds,
+        subject: query.subject,
+        worker_id: query.worker_id,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_EVENTS_LIMIT)
+        .min(MAX_EVENTS_LIMIT);
+
+    if let Some(ws) = ws {
+        // Only the live events unless asked for the recent ones
+        let since = query.since.unwrap_or(u64::MAX);
+        let (recent, live) = event_log.subscribe(&filter, since, limit);
+        return Ok(ws.on_upgrade(move |socket| stream_events(socket, recent, live, filter)));
+    }
+
+    let events = event_log.query(&filter, query.since.unwrap_or(0), limit);
+    Ok(Json(events).into_response())
+}
+
+async fn stream_events(
+    mut socket: WebSocket,
+    recent: Vec<NodeEvent>,
+    mut live: broadcast::Receiver<NodeEvent>,
+    filter: EventFilter,
+) {
+    for event in recent {
+        if send_json(&mut socket, &event).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            event = live.recv() => match event {
+                Ok(event) if filter.matches(&event) => {
+                    if send_json(&mut socket, &event).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                // The client is too slow, drop the events it missed and carry on
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    let _ = socket.close().await;
 }
-#[derive(Debug)]
-pub struct StartedHttp {
-    pub listen_addr: SocketAddr,
+
+/// Triggers the spell webhook with the request body as the payload.
+/// Unknown spells and wrong tokens are reported the same way, so spell ids can't be probed.
+#[utoipa::path(
+    post,
+    path = "/spells/{spell_id}/trigger",
+    params(("spell_id" = String, Path, description = "Id of the spell to trigger")),
+    request_body(content = String, description = "Payload passed to the spell", content_type = "text/plain"),
+    responses(
+        (status = 202, description = "Spell is triggered"),
+        (status = 401, description = "Token is missing or the webhook rejected it"),
+        (status = 404, description = "Spell webhooks are disabled"),
+        (status = 503, description = "Spell webhook couldn't be triggered")
+    ),
+    security(("bearer" = []))
+)]
+async fn handle_spell_trigger(
+    State(state): State<RouteState>,
+    Path(spell_id): Path<String>,
+    headers: HeaderMap,
+    payload: String,
+) -> axum::response::Result<Response> {
+    let spell_event_bus = state
+        .0
+        .spell_event_bus
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or((StatusCode::UNAUTHORIZED, "Bearer token is required"))?;
+
+    match spell_event_bus
+        .trigger_webhook(spell_id.clone(), token, payload)
+        .await
+    {
+        Ok(true) => Ok(StatusCode::ACCEPTED.into_response()),
+        Ok(false) => Err((
+            StatusCode::UNAUTHORIZED,
+            "Spell webhook rejected the request",
+        )
+            .into()),
+        Err(error) => {
+            tracing::warn!(
+                error = error.to_string(),
+                spell_id,
+                "Could not trigger spell webhook"
+            );
+            Err(StatusCode::SERVICE_UNAVAILABLE.into())
+        }
+    }
 }
 
-#[derive(Default)]
-pub struct HttpEndpointData {
-    metrics_registry: Option<Registry>,
-    health_registry: Option<HealthCheckRegistry>,
-    nox_config: Option<ResolvedConfig>,
+#[derive(Deserialize, IntoParams)]
+struct LogsQuery {
+    /// The most verbose level to send
+    #[param(value_type = Option<String>)]
+    level: Option<LogLevel>,
+    /// Module the entries are sent for, along with its submodules
+    module: Option<String>,
+    tail: Option<usize>,
+    /// Keep sending new entries after the recent ones
+    #[serde(default)]
+    follow: bool,
 }
 
-impl HttpEndpointData {
-    pub fn new(
-        metrics_registry: Option<Registry>,
-        health_registry: Option<HealthCheckRegistry>,
-        nox_config: Option<ResolvedConfig>,
-    ) -> Self {
-        Self {
-            metrics_registry,
-            health_registry,
-            nox_config,
+/// Streams recent and, with `follow=true`, live log entries as JSON text messages over WebSocket,
+/// e.g. `/logs?level=debug&module=sorcerer&follow=true`.
+/// Only the management peer is allowed, see [`crate::log_stream::auth_header`].
+#[utoipa::path(
+    get,
+    path = "/logs",
+    params(LogsQuery),
+    responses(
+        (status = 101, description = "WebSocket streaming log entries as JSON text messages"),
+        (status = 401, description = "Signature of the management key is missing or invalid"),
+        (status = 404, description = "Log streaming is disabled")
+    )
+)]
+async fn handle_logs(
+    State(state): State<RouteState>,
+    Query(query): Query<LogsQuery>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> axum::response::Result<Response> {
+    let (log_stream, management_peer_id) = state
+        .0
+        .log_stream
+        .clone()
+        .ok_or((StatusCode::NOT_FOUND, "No such endpoint"))?;
+    let authorized = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |header| {
+            verify_auth_header(
+                header,
+                &state.0.peer_id,
+                &management_peer_id,
+                now_millis::now_ms() as u64,
+            )
+        });
+    if !authorized {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Signature of the management key is required",
+        )
+            .into());
+    }
+
+    let filter = LogFilter {
+        level: query.level,
+        module: query.module,
+    };
+    let tail = query.tail.unwrap_or(DEFAULT_LOGS_TAIL);
+    Ok(ws.on_upgrade(move |socket| stream_logs(socket, log_stream, filter, tail, query.follow)))
+}
+
+async fn stream_logs(
+    mut socket: WebSocket,
+    log_stream: LogStream,
+    filter: LogFilter,
+    tail: usize,
+    follow: bool,
+) {
+    let (recent, mut live) = log_stream.subscribe(&filter, tail);
+    for entry in recent {
+        if send_json(&mut socket, &entry).await.is_err() {
+            return;
         }
     }
+    if !follow {
+        let _ = socket.close().await;
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            entry = live.recv() => match entry {
+                Ok(entry) if filter.matches(&entry) => {
+                    if send_json(&mut socket, &entry).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                // The client is too slow, drop the entries it missed and carry on
+                Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    let _ = socket.close().await;
 }
 
+async fn send_json(socket: &mut WebSocket, value: &impl Serialize) -> Result<(), axum::Error> {
+    let json = serde_json::to_string(value).map_err(axum::Error::new)?;
+    socket.send(Message::Text(json)).await
+}
+
+#[derive(Clone)]
+struct RouteState(Arc<Inner>);
+
+struct Inner {
+    peer_id: PeerId,
+    versions: Versions,
+    metric_registry: Option<Arc<Registry>>,
+    health_registry: Option<HealthCheckRegistry>,
+    nox_config: Option<ResolvedConfig>,
+    spell_event_bus: Option<SpellEventBusApi>,
+    event_log: Option<EventLog>,
+    log_stream: Option<(LogStream, PeerId)>,
+}
 pub async fn start_http_endpoint(
     listen_addr: SocketAddr,
     peer_id: PeerId,
@@ -177,13 +547,24 @@ pub async fn start_http_endpoint(
         metric_registry: http_endpoint_data.metrics_registry,
         health_registry: http_endpoint_data.health_registry,
         nox_config: http_endpoint_data.nox_config,
+        spell_event_bus: http_endpoint_data.spell_event_bus,
+        event_log: http_endpoint_data.event_log,
+        log_stream: http_endpoint_data.log_stream,
     }));
     let app: Router = Router::new()
         .route("/metrics", get(handle_metrics))
         .route("/peer_id", get(handle_peer_id))
         .route("/versions", get(handle_versions))
         .route("/health", get(handle_health))
+        .route("/health/ready", get(handle_readiness))
         .route("/config", get(handle_config))
+        .route("/events", get(handle_events))
+        .route("/logs", get(handle_logs))
+        .route("/api/openapi.json", get(handle_openapi))
+        .route(
+            "/spells/:spell_id/trigger",
+            post(handle_spell_trigger).layer(DefaultBodyLimit::max(MAX_WEBHOOK_PAYLOAD_BYTES)),
+        )
         .fallback(handler_404)
         .with_state(state);
 
@@ -208,18 +589,21 @@ mod tests {
     use server_config::UnresolvedConfig;
     use std::net::SocketAddr;
     use std::path::Path;
+    use std::time::Duration;
 
     fn test_versions() -> Versions {
         Versions {
             node_version: "node_test_version".to_string(),
             avm_version: "avm_test_version".to_string(),
             spell_version: "spell_test_version".to_string(),
-            system_service: system_services::Versions {
-                aqua_ipfs_version: "aqua_ipfs_test_version",
-                trust_graph_version: "trust_graph_test_version",
-                registry_version: "registry_test_version",
-                decider_version: "decider_test_version",
-            },
+            marine_version: "marine_test_version".to_string(),
+            protocols: vec!["/fluence/particle/2.0.0".to_string()],
+            system_services: vec![
+                ("aqua_ipfs", "aqua_ipfs_test_version"),
+                ("trust_graph", "trust_graph_test_version"),
+                ("registry", "registry_test_version"),
+                ("decider", "decider_test_version"),
+            ],
         }
     }
 
@@ -253,7 +637,53 @@ mod tests {
         let status = response.status();
         let body = response.bytes().await.unwrap();
         assert_eq!(status, StatusCode::OK);
-        assert_eq!(&body[..], br#"{"node":"node_test_version","avm":"avm_test_version","spell":"spell_test_version","aqua_ipfs":"aqua_ipfs_test_version","trust_graph":"trust_graph_test_version","registry":"registry_test_version","decider":"decider_test_version"}"#);
+        assert_eq!(&body[..], br#"{"node":"node_test_version","avm":"avm_test_version","spell":"spell_test_version","marine":"marine_test_version","protocols":["/fluence/particle/2.0.0"],"aqua_ipfs":"aqua_ipfs_test_version","trust_graph":"trust_graph_test_version","registry":"registry_test_version","decider":"decider_test_version"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_route() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                PeerId::random(),
+                test_versions(),
+                HttpEndpointData::default(),
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let response = reqwest::get(format!("http://{}/api/openapi.json", http_info.listen_addr))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let spec: Value = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+
+        assert_eq!(spec["info"]["version"], "node_test_version");
+        let paths = spec["paths"].as_object().unwrap();
+        for path in [
+            "/metrics",
+            "/peer_id",
+            "/versions",
+            "/health",
+            "/config",
+            "/events",
+            "/logs",
+            "/spells/{spell_id}/trigger",
+            "/api/openapi.json",
+        ] {
+            assert!(paths.contains_key(path), "{path} isn't documented");
+        }
+        let params = spec["paths"]["/events"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        assert!(params.iter().any(|p| p["name"] == "since"));
     }
 
     #[tokio::test]
@@ -293,6 +723,105 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_events_route() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let event_log =
+            EventLog::open(dir.path().join("events.log"), 100, Duration::from_secs(60)).unwrap();
+        event_log.record(EventKind::SpellInstalled, "spell", None, None);
+        event_log.record(EventKind::PeerConnected, "peer", None, None);
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let endpoint_config = HttpEndpointData::default().with_event_log(event_log);
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                PeerId::random(),
+                test_versions(),
+                endpoint_config,
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!(
+                "http://{}/events?kind=spell_installed,spell_removed",
+                http_info.listen_addr
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let events: Vec<Value> = serde_json::from_slice(&response.bytes().await.unwrap()).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["subject"], "spell");
+
+        let response = client
+            .get(format!(
+                "http://{}/events?kind=unknown",
+                http_info.listen_addr
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_events_websocket() {
+        use futures::StreamExt;
+        use tokio_tungstenite::tungstenite::Message as WsMessage;
+
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let event_log =
+            EventLog::open(dir.path().join("events.log"), 100, Duration::from_secs(60)).unwrap();
+        event_log.record(EventKind::SpellInstalled, "spell", None, None);
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let endpoint_config = HttpEndpointData::default().with_event_log(event_log.clone());
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                PeerId::random(),
+                test_versions(),
+                endpoint_config,
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+        let url = format!(
+            "ws://{}/events?kind=spell_installed,spell_triggered&since=0",
+            http_info.listen_addr
+        );
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        let parse = |message: WsMessage| {
+            let WsMessage::Text(text) = message else {
+                panic!("expected a text message, got {message:?}");
+            };
+            serde_json::from_str::<Value>(&text).unwrap()
+        };
+        let installed = parse(socket.next().await.unwrap().unwrap());
+        assert_eq!(installed["kind"], "spell_installed");
+
+        // The subscription is set up on upgrade, so the live events aren't missed
+        event_log.record(EventKind::PeerConnected, "peer", None, None);
+        event_log.publish(EventKind::SpellTriggered, "spell", None, None);
+        let triggered = parse(socket.next().await.unwrap().unwrap());
+        assert_eq!(triggered["kind"], "spell_triggered");
+        assert_eq!(triggered["subject"], "spell");
+    }
+
     #[tokio::test]
     async fn test_health_route_empty_registry() {
         // Create a test server
@@ -305,6 +834,9 @@ mod tests {
             metrics_registry: None,
             health_registry: Some(health_registry),
             nox_config: None,
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
         };
 
         tokio::spawn(async move {
@@ -355,6 +887,9 @@ mod tests {
             metrics_registry: None,
             health_registry: Some(health_registry),
             nox_config: None,
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
         };
         tokio::spawn(async move {
             start_http_endpoint(
@@ -411,6 +946,9 @@ mod tests {
             metrics_registry: None,
             health_registry: Some(health_registry),
             nox_config: None,
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
         };
         tokio::spawn(async move {
             start_http_endpoint(
@@ -442,6 +980,64 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_health_route_startup_progress() {
+        // Create a test server
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let mut health_registry = HealthCheckRegistry::new();
+        struct RestoringHealthCheck {}
+        impl HealthCheck for RestoringHealthCheck {
+            fn status(&self) -> eyre::Result<()> {
+                Err(eyre::eyre!("Restoring"))
+            }
+
+            fn progress(&self) -> Option<(usize, usize)> {
+                Some((7, 10))
+            }
+        }
+        health_registry.register("test_check", RestoringHealthCheck {});
+        let endpoint_config = HttpEndpointData {
+            metrics_registry: None,
+            health_registry: Some(health_registry),
+            nox_config: None,
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
+        };
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                peer_id,
+                test_versions(),
+                endpoint_config,
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("http://{}/health", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            &body[..],
+            (r#"[{"test_check":"Fail"},{"startup_progress":{"test_check":{"done":7,"total":10}}}]"#)
+                .as_bytes()
+        );
+    }
+
     #[tokio::test]
     async fn test_health_route_fail_checks() {
         // Create a test server
@@ -462,6 +1058,9 @@ mod tests {
             metrics_registry: None,
             health_registry: Some(health_registry),
             nox_config: None,
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
         };
 
         tokio::spawn(async move {
@@ -491,6 +1090,72 @@ mod tests {
         assert_eq!(&body[..], (r#"[{"test_check":"Fail"}]"#).as_bytes());
     }
 
+    #[tokio::test]
+    async fn test_readiness_route() {
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let peer_id = PeerId::random();
+
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let mut health_registry = HealthCheckRegistry::new();
+        struct SuccessHealthCheck {}
+        impl HealthCheck for SuccessHealthCheck {
+            fn status(&self) -> eyre::Result<()> {
+                Ok(())
+            }
+        }
+        struct FailHealthCheck {}
+        impl HealthCheck for FailHealthCheck {
+            fn status(&self) -> eyre::Result<()> {
+                Err(eyre::eyre!("Failed"))
+            }
+        }
+        health_registry.register("test_check", SuccessHealthCheck {});
+        health_registry.register("test_check_2", FailHealthCheck {});
+        let endpoint_config = HttpEndpointData {
+            metrics_registry: None,
+            health_registry: Some(health_registry),
+            nox_config: None,
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
+        };
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                peer_id,
+                test_versions(),
+                endpoint_config,
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+
+        let http_info = notify_receiver.await.unwrap();
+
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("http://{}/health/ready", http_info.listen_addr))
+            .send()
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.bytes().await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body,
+            json!({
+                "status": "starting",
+                "components": {
+                    "test_check": {"status": "ready"},
+                    "test_check_2": {"status": "starting", "error": "Failed"}
+                }
+            })
+        );
+    }
+
     #[tokio::test]
     async fn test_config_endpoint() {
         let tmp_dir = tempfile::tempdir().expect("Could not create temp dir");
@@ -507,6 +1172,9 @@ mod tests {
             metrics_registry: None,
             health_registry: None,
             nox_config: Some(resolved_config),
+            spell_event_bus: None,
+            event_log: None,
+            log_stream: None,
         };
 
         tokio::spawn(async move {
@@ -554,6 +1222,124 @@ mod tests {
         assert_eq!(result, expected_config);
     }
 
+    #[tokio::test]
+    async fn test_logs_route() {
+        use crate::log_stream::{auth_header, LogEntry};
+        use fluence_keypair::KeyPair;
+        use futures::StreamExt;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        use tokio_tungstenite::tungstenite::{Error as WsError, Message as WsMessage};
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let log_stream = LogStream::new(10);
+        let subscriber = tracing_subscriber::registry().with(log_stream.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!(target: "sorcerer", "spell installed");
+            tracing::info!(target: "nox::node", "node started");
+        });
+
+        let management = KeyPair::generate_ed25519();
+        let peer_id = PeerId::random();
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let endpoint_config =
+            HttpEndpointData::default().with_log_stream(log_stream, management.get_peer_id());
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                peer_id,
+                test_versions(),
+                endpoint_config,
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+        let http_info = notify_receiver.await.unwrap();
+        let url = format!("ws://{}/logs?module=sorcerer", http_info.listen_addr);
+
+        let anonymous = tokio_tungstenite::connect_async(url.as_str()).await;
+        assert!(
+            matches!(anonymous, Err(WsError::Http(response)) if response.status().as_u16() == 401)
+        );
+
+        let mut request = url.as_str().into_client_request().unwrap();
+        let header = auth_header(&management, &peer_id, now_millis::now_ms() as u64).unwrap();
+        request
+            .headers_mut()
+            .insert("authorization", header.parse().unwrap());
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        let message = socket.next().await.unwrap().unwrap();
+        let WsMessage::Text(text) = message else {
+            panic!("expected a text message, got {message:?}");
+        };
+        let entry: LogEntry = serde_json::from_str(&text).unwrap();
+        assert_eq!(entry.module, "sorcerer");
+        assert_eq!(entry.message, "spell installed");
+        // Without follow the stream ends after the recent entries
+        assert!(matches!(
+            socket.next().await,
+            Some(Ok(WsMessage::Close(_))) | None
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_spell_trigger_route() {
+        use spell_event_bus::api::{add_webhook_trigger, webhook_token_hash, TriggerInfo};
+        use spell_event_bus::bus::SpellEventBus;
+
+        let (bus, api, mut events) = SpellEventBus::new(None, vec![], vec![], vec![]);
+        let bus = bus.start();
+        api.start_scheduling().await.unwrap();
+        let config = add_webhook_trigger(None, Some(webhook_token_hash("secret"))).unwrap();
+        api.subscribe("spell".to_string(), config).await.unwrap();
+
+        let addr = "127.0.0.1:0".parse::<SocketAddr>().unwrap();
+        let (notify_sender, notify_receiver) = oneshot::channel();
+        let endpoint_config = HttpEndpointData::default().with_spell_webhooks(api);
+        tokio::spawn(async move {
+            start_http_endpoint(
+                addr,
+                PeerId::random(),
+                test_versions(),
+                endpoint_config,
+                notify_sender,
+            )
+            .await
+            .unwrap();
+        });
+        let http_info = notify_receiver.await.unwrap();
+        let url = format!("http://{}/spells/spell/trigger", http_info.listen_addr);
+        let client = reqwest::Client::new();
+
+        let anonymous = client.post(&url).body("{}").send().await.unwrap();
+        let wrong_token = client
+            .post(&url)
+            .bearer_auth("guess")
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+        let accepted = client
+            .post(&url)
+            .bearer_auth("secret")
+            .body(r#"{"build":"green"}"#)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(wrong_token.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(accepted.status(), StatusCode::ACCEPTED);
+        let event = events.recv().await.unwrap();
+        assert_eq!(event.spell_id, "spell");
+        assert!(
+            matches!(event.info, TriggerInfo::Webhook(w) if w.payload == r#"{"build":"green"}"#)
+        );
+        bus.abort();
+    }
+
     async fn get_config(path: &Path) -> ResolvedConfig {
         let unresolved_config = tokio::fs::read("./tests/http_test_config.toml")
             .await
@@ -0,0 +1,262 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Caps on Kademlia lookups made while routing particles. A particle sent to many
+//! unresolved peers waits for slots instead of flooding the DHT, so lookups of other particles
+//! and the rest of the routing aren't starved.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use peer_metrics::{ConnectivityMetrics, QueryRejection};
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum QueryLimitError {
+    #[error("too many Kademlia lookups are waiting for a slot")]
+    QueueFull,
+    #[error("particle expired while waiting for a Kademlia lookup slot")]
+    Expired,
+}
+
+impl From<&QueryLimitError> for QueryRejection {
+    fn from(err: &QueryLimitError) -> Self {
+        match err {
+            QueryLimitError::QueueFull => QueryRejection::QueueFull,
+            QueryLimitError::Expired => QueryRejection::Expired,
+        }
+    }
+}
+
+/// Slots of a particle, removed once none of its lookups is made or waiting
+struct ParticleSlots {
+    semaphore: Arc<Semaphore>,
+    users: usize,
+}
+
+pub struct KademliaQueryLimiter {
+    per_particle: usize,
+    global: Option<Arc<Semaphore>>,
+    max_queued: usize,
+    particles: Mutex<HashMap<String, ParticleSlots>>,
+    in_flight: AtomicUsize,
+    queued: AtomicUsize,
+    metrics: Option<ConnectivityMetrics>,
+}
+
+/// Slots held by a lookup, released when it's dropped
+pub struct QueryPermit<'a> {
+    limiter: &'a KademliaQueryLimiter,
+    particle_id: String,
+    _particle: Option<OwnedSemaphorePermit>,
+    _global: Option<OwnedSemaphorePermit>,
+}
+
+impl Drop for QueryPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.limiter.release_particle(&self.particle_id);
+        self.limiter.observe();
+    }
+}
+
+/// Counts a lookup as queued while it waits for its slots
+struct Queued<'a>(&'a KademliaQueryLimiter);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::Relaxed);
+        self.0.observe();
+    }
+}
+
+impl KademliaQueryLimiter {
+    /// Zero `per_particle` or `global` disables the corresponding cap
+    pub fn new(
+        per_particle: usize,
+        global: usize,
+        max_queued: usize,
+        metrics: Option<ConnectivityMetrics>,
+    ) -> Self {
+        Self {
+            per_particle,
+            global: (global > 0).then(|| Arc::new(Semaphore::new(global))),
+            max_queued,
+            particles: <_>::default(),
+            in_flight: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            metrics,
+        }
+    }
+
+    /// Wait for the slots of a lookup of the particle, giving up when the particle expires
+    pub async fn acquire(
+        &self,
+        particle_id: &str,
+        ttl: Duration,
+    ) -> Result<QueryPermit<'_>, QueryLimitError> {
+        let particle = self.particle_semaphore(particle_id);
+        let result = self.acquire_slots(particle, ttl).await;
+        match result {
+            Ok((particle, global)) => {
+                self.in_flight.fetch_add(1, Ordering::Relaxed);
+                self.observe();
+                Ok(QueryPermit {
+                    limiter: self,
+                    particle_id: particle_id.to_string(),
+                    _particle: particle,
+                    _global: global,
+                })
+            }
+            Err(err) => {
+                self.release_particle(particle_id);
+                if let Some(m) = self.metrics.as_ref() {
+                    m.kademlia_query_rejected((&err).into());
+                }
+                Err(err)
+            }
+        }
+    }
+
+    async fn acquire_slots(
+        &self,
+        particle: Option<Arc<Semaphore>>,
+        ttl: Duration,
+    ) -> Result<(Option<OwnedSemaphorePermit>, Option<OwnedSemaphorePermit>), QueryLimitError> {
+        let available = |semaphore: &Option<Arc<Semaphore>>| {
+            semaphore
+                .as_ref()
+                .map_or(true, |s| s.available_permits() > 0)
+        };
+        let _queued = if available(&particle) && available(&self.global) {
+            None
+        } else {
+            if self.queued.fetch_add(1, Ordering::Relaxed) >= self.max_queued {
+                self.queued.fetch_sub(1, Ordering::Relaxed);
+                return Err(QueryLimitError::QueueFull);
+            }
+            self.observe();
+            Some(Queued(self))
+        };
+
+        // slots of the particle are taken first, so its excess lookups don't hold global slots
+        let acquire = async {
+            let particle = match particle {
+                Some(s) => Some(s.acquire_owned().await.expect("semaphore is never closed")),
+                None => None,
+            };
+            let global = match &self.global {
+                Some(s) => Some(
+                    s.clone()
+                        .acquire_owned()
+                        .await
+                        .expect("semaphore is never closed"),
+                ),
+                None => None,
+            };
+            (particle, global)
+        };
+        tokio::time::timeout(ttl, acquire)
+            .await
+            .map_err(|_| QueryLimitError::Expired)
+    }
+
+    fn particle_semaphore(&self, particle_id: &str) -> Option<Arc<Semaphore>> {
+        if self.per_particle == 0 {
+            return None;
+        }
+        let mut particles = self.particles.lock();
+        let slots = particles
+            .entry(particle_id.to_string())
+            .or_insert_with(|| ParticleSlots {
+                semaphore: Arc::new(Semaphore::new(self.per_particle)),
+                users: 0,
+            });
+        slots.users += 1;
+        Some(slots.semaphore.clone())
+    }
+
+    fn release_particle(&self, particle_id: &str) {
+        if self.per_particle == 0 {
+            return;
+        }
+        let mut particles = self.particles.lock();
+        if let Some(slots) = particles.get_mut(particle_id) {
+            slots.users -= 1;
+            if slots.users == 0 {
+                particles.remove(particle_id);
+            }
+        }
+    }
+
+    fn observe(&self) {
+        if let Some(m) = self.metrics.as_ref() {
+            m.observe_kademlia_queries(
+                self.in_flight.load(Ordering::Relaxed),
+                self.queued.load(Ordering::Relaxed),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn caps_lookups_of_a_particle() {
+        let limiter = KademliaQueryLimiter::new(1, 10, 10, None);
+
+        let first = limiter.acquire("particle", TTL).await.unwrap();
+        let second = limiter
+            .acquire("particle", Duration::from_millis(10))
+            .await
+            .err();
+        assert_eq!(second, Some(QueryLimitError::Expired));
+        assert!(limiter.acquire("other", TTL).await.is_ok());
+
+        drop(first);
+        assert!(limiter.acquire("particle", TTL).await.is_ok());
+        assert!(limiter.particles.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn queues_lookups_over_global_cap() {
+        let limiter = KademliaQueryLimiter::new(0, 1, 1, None);
+
+        let first = limiter.acquire("first", TTL).await.unwrap();
+        let waiting = limiter.acquire("second", TTL);
+        tokio::pin!(waiting);
+        assert!(futures::poll!(&mut waiting).is_pending());
+        assert_eq!(limiter.queued.load(Ordering::Relaxed), 1);
+
+        let rejected = limiter.acquire("third", TTL).await.err();
+        assert_eq!(rejected, Some(QueryLimitError::QueueFull));
+
+        drop(first);
+        assert!(waiting.await.is_ok());
+        assert_eq!(limiter.queued.load(Ordering::Relaxed), 0);
+        assert_eq!(limiter.in_flight.load(Ordering::Relaxed), 0);
+    }
+}
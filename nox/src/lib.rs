@@ -17,6 +17,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 #![feature(extend_one)]
+#![feature(assert_matches)]
 #![feature(try_blocks)]
 #![feature(ip)]
 #![feature(extract_if)]
@@ -32,26 +33,64 @@
     unreachable_patterns
 )]
 
+#[cfg(feature = "spells")]
+pub mod api;
+pub mod bench;
 mod builtins;
+mod circuit_breaker;
+mod client_sessions;
+pub mod config_diff;
 mod connectivity;
+pub mod deploy;
 mod dispatcher;
+pub mod doctor;
 mod effectors;
+mod gateway;
 mod health;
+#[cfg(feature = "http")]
 mod http;
+mod kademlia_limiter;
 mod layers;
+mod listeners;
+pub mod localnet;
+mod log_stream;
+pub mod logs;
+mod loop_detector;
 mod metrics;
+mod metrics_push;
+pub mod migrations;
 mod node;
+mod particle_dedup;
+pub mod particle_inspect;
+mod particle_wal;
+mod protocol_capture;
+pub mod self_update;
+mod snapshot_sync;
+mod spells;
+pub mod storage;
 mod tasks;
+mod thread_pools;
+mod ttl_guard;
+mod webrtc;
 mod behaviour {
     mod identify;
     mod network;
+    mod pex;
+    mod rendezvous;
 
     pub use network::{FluenceNetworkBehaviour, FluenceNetworkBehaviourEvent};
+    pub use pex::{PeerExchange, PEX_PROTOCOL};
+    pub use rendezvous::RendezvousRegistrations;
 }
 
+#[cfg(feature = "spells")]
+pub use api::NodeHandle;
 pub use behaviour::{FluenceNetworkBehaviour, FluenceNetworkBehaviourEvent};
-pub use http::StartedHttp;
+pub use gateway::StartedHttp;
+pub use log_stream::LogStream;
 pub use node::Node;
+pub use spell_event_bus::api::{SourceEvent, StreamSource, TriggerSource};
+pub use spells::Spells;
 
 // to be available in benchmarks
 pub use connection_pool::Command as ConnectionPoolCommand;
@@ -66,7 +105,12 @@ pub struct Versions {
     pub node_version: String,
     pub avm_version: String,
     pub spell_version: String,
-    pub system_service: system_services::Versions,
+    /// Version of the Marine runtime the services are run on
+    pub marine_version: String,
+    /// Names of the libp2p protocols the node speaks, with their versions
+    pub protocols: Vec<String>,
+    /// Names of the system services with their versions, empty if spells are compiled out
+    pub system_services: Vec<(&'static str, &'static str)>,
 }
 
 impl Versions {
@@ -74,13 +118,31 @@ impl Versions {
         node_version: String,
         avm_version: String,
         spell_version: String,
-        system_service: system_services::Versions,
+        protocols: Vec<String>,
+        system_services: Vec<(&'static str, &'static str)>,
     ) -> Self {
         Self {
             node_version,
             avm_version,
             spell_version,
-            system_service,
+            marine_version: env!("MARINE_RUNTIME_VERSION").to_string(),
+            protocols,
+            system_services,
         }
     }
+
+    /// Returned by `/versions` and `peer.versions`
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut versions = serde_json::json!({
+            "node": self.node_version,
+            "avm": self.avm_version,
+            "spell": self.spell_version,
+            "marine": self.marine_version,
+            "protocols": self.protocols,
+        });
+        for (name, version) in &self.system_services {
+            versions[*name] = serde_json::json!(version);
+        }
+        versions
+    }
 }
@@ -0,0 +1,180 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
+
+use libp2p::core::transport::{ListenerId, TransportError};
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{Multiaddr, Swarm};
+use tokio::time::Instant;
+
+/// Tracks swarm listeners by address, so they can be rebound when the listen ports change.
+///
+/// Listeners on addresses that are no longer configured are retired after a grace period
+/// instead of being closed right away. Established connections are never closed by this,
+/// only the listening sockets are, so clients keep their sessions while they move to the new ports.
+#[derive(Default)]
+pub struct Listeners {
+    active: HashMap<Multiaddr, ListenerId>,
+    retiring: Vec<(Instant, Multiaddr, ListenerId)>,
+}
+
+impl Listeners {
+    pub fn listen<B: NetworkBehaviour>(
+        &mut self,
+        swarm: &mut Swarm<B>,
+        addrs: Vec<Multiaddr>,
+    ) -> Result<(), TransportError<io::Error>> {
+        for addr in addrs {
+            if self.active.contains_key(&addr) {
+                continue;
+            }
+            // the address may be coming back before its old listener was retired
+            if let Some(pos) = self.retiring.iter().position(|(_, a, _)| a == &addr) {
+                let (_, addr, id) = self.retiring.remove(pos);
+                self.active.insert(addr, id);
+                continue;
+            }
+            let id = swarm.listen_on(addr.clone())?;
+            self.active.insert(addr, id);
+        }
+        Ok(())
+    }
+
+    /// Starts listening on the new addresses and schedules listeners on the addresses that
+    /// aren't in `addrs` anymore to be closed after `grace_period`
+    pub fn rebind<B: NetworkBehaviour>(
+        &mut self,
+        swarm: &mut Swarm<B>,
+        addrs: Vec<Multiaddr>,
+        grace_period: Duration,
+    ) -> Result<(), TransportError<io::Error>> {
+        // open the new sockets first, so that there is no moment without a listener
+        self.listen(swarm, addrs.clone())?;
+
+        let deadline = Instant::now() + grace_period;
+        let stale: Vec<_> = self
+            .active
+            .keys()
+            .filter(|addr| !addrs.contains(addr))
+            .cloned()
+            .collect();
+        for addr in stale {
+            if let Some(id) = self.active.remove(&addr) {
+                log::info!("Listener on {addr} will be closed in {grace_period:?}");
+                self.retiring.push((deadline, addr, id));
+            }
+        }
+        Ok(())
+    }
+
+    /// When the next retiring listener is due to be closed
+    pub fn next_retirement(&self) -> Option<Instant> {
+        self.retiring.iter().map(|(deadline, _, _)| *deadline).min()
+    }
+
+    /// Closes the listeners whose grace period has passed
+    pub fn retire_due<B: NetworkBehaviour>(&mut self, swarm: &mut Swarm<B>) {
+        let now = Instant::now();
+        self.retiring.retain(|(deadline, addr, id)| {
+            if *deadline > now {
+                return true;
+            }
+            log::info!("Closing listener on {addr}");
+            swarm.remove_listener(*id);
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fluence_libp2p::build_memory_transport;
+    use libp2p::identity::Keypair;
+    use libp2p::swarm::{dummy, Config};
+    use libp2p::{Multiaddr, Swarm};
+
+    use super::Listeners;
+
+    fn memory(port: u64) -> Multiaddr {
+        format!("/memory/{port}").parse().unwrap()
+    }
+
+    #[test]
+    fn rebind_keeps_unchanged_and_retires_removed() {
+        let key = Keypair::generate_ed25519();
+        let transport = build_memory_transport(&key, Duration::from_secs(10));
+        let mut swarm = Swarm::new(
+            transport,
+            dummy::Behaviour,
+            key.public().to_peer_id(),
+            Config::without_executor(),
+        );
+        let (a, b, c) = (memory(4001), memory(4002), memory(4003));
+
+        let mut listeners = Listeners::default();
+        listeners
+            .listen(&mut swarm, vec![a.clone(), b.clone()])
+            .unwrap();
+        let b_id = listeners.active[&b];
+
+        listeners
+            .rebind(&mut swarm, vec![b.clone(), c.clone()], Duration::ZERO)
+            .unwrap();
+        assert_eq!(listeners.active[&b], b_id);
+        assert!(listeners.active.contains_key(&c));
+        assert_eq!(listeners.retiring.len(), 1);
+        assert_eq!(listeners.retiring[0].1, a);
+        assert!(listeners.next_retirement().is_some());
+
+        listeners.retire_due(&mut swarm);
+        assert!(listeners.retiring.is_empty());
+        assert!(listeners.next_retirement().is_none());
+    }
+
+    #[test]
+    fn address_coming_back_cancels_retirement() {
+        let key = Keypair::generate_ed25519();
+        let transport = build_memory_transport(&key, Duration::from_secs(10));
+        let mut swarm = Swarm::new(
+            transport,
+            dummy::Behaviour,
+            key.public().to_peer_id(),
+            Config::without_executor(),
+        );
+        let (a, b) = (memory(4011), memory(4012));
+
+        let mut listeners = Listeners::default();
+        listeners.listen(&mut swarm, vec![a.clone()]).unwrap();
+        let a_id = listeners.active[&a];
+        listeners
+            .rebind(&mut swarm, vec![b.clone()], Duration::from_secs(60))
+            .unwrap();
+        listeners
+            .rebind(&mut swarm, vec![a.clone(), b], Duration::from_secs(60))
+            .unwrap();
+
+        assert_eq!(listeners.active[&a], a_id);
+        assert!(listeners.retiring.is_empty());
+    }
+}
@@ -0,0 +1,446 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `nox localnet` runs a few local nodes bootstrapped from each other for experiments and tests.
+//! `up` generates keys, starts the nodes as child processes of the current binary and writes
+//! a manifest with their peer ids and ports, `down` stops the nodes of a detached localnet.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use clap::{Args, Parser, Subcommand};
+use eyre::{eyre, WrapErr};
+use fluence_keypair::KeyPair;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+
+const MANIFEST_FILE: &str = "localnet.json";
+/// How long stopped nodes get to shut down gracefully before they're killed
+const STOP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+#[derive(Parser, Debug)]
+#[command(name = "nox localnet", about = "Run a local network of nodes")]
+struct LocalnetArgs {
+    #[command(subcommand)]
+    command: LocalnetCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum LocalnetCommand {
+    /// Start the nodes, stopping them on Ctrl-C unless detached
+    Up(UpArgs),
+    /// Stop the nodes of a detached localnet
+    Down(DownArgs),
+}
+
+#[derive(Args, Debug)]
+struct UpArgs {
+    /// Number of nodes
+    #[arg(long, short, default_value_t = 3)]
+    nodes: usize,
+    /// Directory with the manifest, the keys, the logs and the data of the nodes
+    #[arg(long, short, default_value = "localnet")]
+    dir: PathBuf,
+    /// TCP port of the first node, the next nodes get the following ones
+    #[arg(long, default_value_t = 7771)]
+    tcp_port: u16,
+    /// Websocket port of the first node, the next nodes get the following ones
+    #[arg(long, default_value_t = 9991)]
+    ws_port: u16,
+    /// HTTP port of the first node, the next nodes get the following ones
+    #[arg(long, default_value_t = 18080)]
+    http_port: u16,
+    /// Write the manifest and exit when the nodes are ready, leaving them running
+    #[arg(long)]
+    detach: bool,
+    /// How long to wait for the nodes to start, in seconds
+    #[arg(long, default_value_t = 60)]
+    startup_timeout: u64,
+    /// Arguments every node is started with in addition to the generated ones
+    #[arg(last = true)]
+    node_args: Vec<OsString>,
+}
+
+#[derive(Args, Debug)]
+struct DownArgs {
+    /// Directory of the localnet
+    #[arg(long, short, default_value = "localnet")]
+    dir: PathBuf,
+    /// Remove the directory of the localnet after the nodes are stopped
+    #[arg(long)]
+    purge: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalnetManifest {
+    /// Peer id of the management peer of every node
+    pub management_peer_id: String,
+    /// Secret key in base64 of the management peer, e.g. for `nox logs`
+    pub management_secret_key: String,
+    pub nodes: Vec<LocalnetNode>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalnetNode {
+    pub index: usize,
+    pub peer_id: String,
+    pub secret_key: String,
+    pub tcp_port: u16,
+    pub ws_port: u16,
+    pub http_port: u16,
+    pub multiaddr: String,
+    pub ws_multiaddr: String,
+    pub dir: PathBuf,
+    #[serde(default)]
+    pub pid: Option<u32>,
+}
+
+impl LocalnetNode {
+    /// Node arguments, every node but the first is bootstrapped from the first one
+    fn node_args(&self, management_peer_id: &str, bootstrap: Option<&str>) -> Vec<OsString> {
+        let mut args: Vec<OsString> = vec![
+            "--secret-key".into(),
+            self.secret_key.clone().into(),
+            "--keypair-format".into(),
+            "ed25519".into(),
+            "--tcp-port".into(),
+            self.tcp_port.to_string().into(),
+            "--ws-port".into(),
+            self.ws_port.to_string().into(),
+            "--http-port".into(),
+            self.http_port.to_string().into(),
+            "--management-peer-id".into(),
+            management_peer_id.into(),
+            "--allow-private-ips".into(),
+        ];
+        match bootstrap {
+            Some(bootstrap) => args.extend(["--bootstraps".into(), bootstrap.into()]),
+            None => args.push("--local".into()),
+        }
+        args
+    }
+}
+
+/// Entrypoint of `nox localnet`, `args` start with the subcommand name
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = LocalnetArgs::parse_from(args);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    match args.command {
+        LocalnetCommand::Up(args) => runtime.block_on(up(args)),
+        LocalnetCommand::Down(args) => down(args),
+    }
+}
+
+/// Keys, ports and directories of the nodes
+fn plan(args: &UpArgs) -> eyre::Result<LocalnetManifest> {
+    if args.nodes == 0 {
+        return Err(eyre!("localnet needs at least one node"));
+    }
+    let port = |base: u16, index: usize| {
+        u16::try_from(index)
+            .ok()
+            .and_then(|index| base.checked_add(index))
+            .ok_or_else(|| eyre!("port {base} + {index} is out of range"))
+    };
+    let secret_key =
+        |key_pair: &KeyPair| -> eyre::Result<String> { Ok(base64.encode(key_pair.secret()?)) };
+
+    let management = KeyPair::generate_ed25519();
+    let nodes = (0..args.nodes)
+        .map(|index| {
+            let key_pair = KeyPair::generate_ed25519();
+            let peer_id = key_pair.get_peer_id().to_base58();
+            let tcp_port = port(args.tcp_port, index)?;
+            let ws_port = port(args.ws_port, index)?;
+            Ok(LocalnetNode {
+                index,
+                secret_key: secret_key(&key_pair)?,
+                tcp_port,
+                ws_port,
+                http_port: port(args.http_port, index)?,
+                multiaddr: format!("/ip4/127.0.0.1/tcp/{tcp_port}/p2p/{peer_id}"),
+                ws_multiaddr: format!("/ip4/127.0.0.1/tcp/{ws_port}/ws/p2p/{peer_id}"),
+                dir: args.dir.join(format!("node-{index}")),
+                peer_id,
+                pid: None,
+            })
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    Ok(LocalnetManifest {
+        management_peer_id: management.get_peer_id().to_base58(),
+        management_secret_key: secret_key(&management)?,
+        nodes,
+    })
+}
+
+async fn up(args: UpArgs) -> eyre::Result<()> {
+    if let Some(manifest) = read_manifest(&args.dir)? {
+        if manifest
+            .nodes
+            .iter()
+            .any(|node| node.pid.is_some_and(is_running))
+        {
+            return Err(eyre!(
+                "localnet in {} is already running, stop it with `nox localnet down`",
+                args.dir.display()
+            ));
+        }
+    }
+
+    let mut manifest = plan(&args)?;
+    let exe = std::env::current_exe().wrap_err("error locating the nox binary")?;
+    let bootstrap = manifest.nodes[0].multiaddr.clone();
+    let mut children = Vec::with_capacity(manifest.nodes.len());
+    for node in manifest.nodes.iter_mut() {
+        let bootstrap = (node.index > 0).then_some(bootstrap.as_str());
+        match start_node(&exe, node, &manifest.management_peer_id, bootstrap, &args) {
+            Ok(child) => {
+                node.pid = Some(child.id());
+                children.push(child);
+            }
+            Err(err) => {
+                stop_children(children);
+                return Err(err);
+            }
+        }
+    }
+    write_manifest(&args.dir, &manifest)?;
+
+    let deadline = Instant::now() + Duration::from_secs(args.startup_timeout);
+    if let Err(err) = wait_ready(&manifest, &mut children, deadline).await {
+        stop_children(children);
+        return Err(err);
+    }
+
+    for node in &manifest.nodes {
+        println!(
+            "node {} {} tcp {} ws {} http {}",
+            node.index, node.peer_id, node.tcp_port, node.ws_port, node.http_port
+        );
+    }
+    let manifest_path = args.dir.join(MANIFEST_FILE);
+    println!("manifest: {}", manifest_path.display());
+
+    if args.detach {
+        // the nodes outlive this process, `nox localnet down` stops them by their pids
+        drop(children);
+        return Ok(());
+    }
+
+    println!("press Ctrl-C to stop the nodes");
+    tokio::signal::ctrl_c().await?;
+    stop_children(children);
+    for node in manifest.nodes.iter_mut() {
+        node.pid = None;
+    }
+    write_manifest(&args.dir, &manifest)?;
+    Ok(())
+}
+
+fn start_node(
+    exe: &Path,
+    node: &LocalnetNode,
+    management_peer_id: &str,
+    bootstrap: Option<&str>,
+    args: &UpArgs,
+) -> eyre::Result<Child> {
+    std::fs::create_dir_all(&node.dir)
+        .wrap_err_with(|| format!("error creating {}", node.dir.display()))?;
+    let log_path = node.dir.join("nox.log");
+    let log = File::create(&log_path)
+        .wrap_err_with(|| format!("error creating {}", log_path.display()))?;
+
+    // the data of the node goes to the default base dir, relative to its own directory
+    Command::new(exe)
+        .args(node.node_args(management_peer_id, bootstrap))
+        .args(&args.node_args)
+        .current_dir(&node.dir)
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log)
+        .spawn()
+        .wrap_err_with(|| format!("error starting node {}", node.index))
+}
+
+/// Wait for every node to serve its peer id over HTTP
+async fn wait_ready(
+    manifest: &LocalnetManifest,
+    children: &mut [Child],
+    deadline: Instant,
+) -> eyre::Result<()> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(1))
+        .build()?;
+    for (node, child) in manifest.nodes.iter().zip(children.iter_mut()) {
+        let url = format!("http://127.0.0.1:{}/peer_id", node.http_port);
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Err(eyre!(
+                    "node {} exited with {status}, see {}",
+                    node.index,
+                    node.dir.join("nox.log").display()
+                ));
+            }
+            let ready = client
+                .get(&url)
+                .send()
+                .await
+                .is_ok_and(|response| response.status().is_success());
+            if ready {
+                break;
+            }
+            if Instant::now() > deadline {
+                return Err(eyre!("node {} didn't start in time", node.index));
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+    Ok(())
+}
+
+fn down(args: DownArgs) -> eyre::Result<()> {
+    let mut manifest = read_manifest(&args.dir)?
+        .ok_or_else(|| eyre!("no localnet manifest in {}", args.dir.display()))?;
+
+    let pids: Vec<u32> = manifest
+        .nodes
+        .iter_mut()
+        .filter_map(|node| node.pid.take())
+        .collect();
+    stop_pids(&pids);
+
+    if args.purge {
+        std::fs::remove_dir_all(&args.dir)
+            .wrap_err_with(|| format!("error removing {}", args.dir.display()))?;
+    } else {
+        write_manifest(&args.dir, &manifest)?;
+    }
+    println!("stopped {} nodes", pids.len());
+    Ok(())
+}
+
+fn stop_children(mut children: Vec<Child>) {
+    let pids: Vec<u32> = children.iter().map(Child::id).collect();
+    stop_pids(&pids);
+    // reap the children, killing those which didn't stop in time
+    for child in children.iter_mut() {
+        if let Ok(None) = child.try_wait() {
+            let _ = child.kill();
+        }
+        let _ = child.wait();
+    }
+}
+
+/// Ask the nodes to shut down and kill those still running after the grace period
+fn stop_pids(pids: &[u32]) {
+    for &pid in pids {
+        let _ = kill(Pid::from_raw(pid as i32), Signal::SIGTERM);
+    }
+    let deadline = Instant::now() + STOP_GRACE_PERIOD;
+    while pids.iter().any(|&pid| is_running(pid)) && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    for &pid in pids.iter().filter(|&&pid| is_running(pid)) {
+        let _ = kill(Pid::from_raw(pid as i32), Signal::SIGKILL);
+    }
+}
+
+fn is_running(pid: u32) -> bool {
+    kill(Pid::from_raw(pid as i32), None).is_ok()
+}
+
+fn read_manifest(dir: &Path) -> eyre::Result<Option<LocalnetManifest>> {
+    let path = dir.join(MANIFEST_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(manifest) => serde_json::from_str(&manifest)
+            .map(Some)
+            .wrap_err_with(|| format!("invalid manifest {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).wrap_err_with(|| format!("error reading {}", path.display())),
+    }
+}
+
+fn write_manifest(dir: &Path, manifest: &LocalnetManifest) -> eyre::Result<()> {
+    std::fs::create_dir_all(dir).wrap_err_with(|| format!("error creating {}", dir.display()))?;
+    let path = dir.join(MANIFEST_FILE);
+    std::fs::write(&path, serde_json::to_string_pretty(manifest)?)
+        .wrap_err_with(|| format!("error writing {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn up_args(nodes: usize, dir: &Path) -> UpArgs {
+        let args = LocalnetArgs::parse_from([
+            "localnet".into(),
+            "up".into(),
+            "--nodes".into(),
+            nodes.to_string().into(),
+            "--dir".into(),
+            OsString::from(dir),
+        ]);
+        match args.command {
+            LocalnetCommand::Up(args) => args,
+            command => panic!("unexpected command {command:?}"),
+        }
+    }
+
+    #[test]
+    fn plans_nodes() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = plan(&up_args(3, dir.path())).unwrap();
+
+        assert_eq!(manifest.nodes.len(), 3);
+        let ports: Vec<_> = manifest.nodes.iter().map(|n| n.tcp_port).collect();
+        assert_eq!(ports, vec![7771, 7772, 7773]);
+        assert_eq!(manifest.nodes[2].http_port, 18082);
+        assert_eq!(manifest.nodes[1].dir, dir.path().join("node-1"));
+        assert!(manifest.nodes[0]
+            .multiaddr
+            .ends_with(&manifest.nodes[0].peer_id));
+
+        let first = manifest.nodes[0].node_args(&manifest.management_peer_id, None);
+        assert!(first.contains(&"--local".into()));
+        let bootstrap = manifest.nodes[0].multiaddr.as_str();
+        let second = manifest.nodes[1].node_args(&manifest.management_peer_id, Some(bootstrap));
+        assert!(second.contains(&bootstrap.into()));
+        assert!(!second.contains(&"--local".into()));
+
+        assert!(plan(&up_args(0, dir.path())).is_err());
+    }
+
+    #[test]
+    fn manifest_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_manifest(dir.path()).unwrap(), None);
+
+        let manifest = plan(&up_args(2, dir.path())).unwrap();
+        write_manifest(dir.path(), &manifest).unwrap();
+        assert_eq!(read_manifest(dir.path()).unwrap(), Some(manifest));
+    }
+}
@@ -0,0 +1,378 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Keeps recent log entries and fans out new ones, so the management peer can tail the logs
+//! of a running node with `nox logs` without shell access to it.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use fluence_keypair::{KeyPair, PublicKey, Signature};
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+/// Scheme of the `Authorization` header accepted by `/logs`
+pub const AUTH_SCHEME: &str = "Fluence";
+/// How far the signed timestamp may be from the node clock
+const AUTH_MAX_SKEW: Duration = Duration::from_secs(60);
+/// Live entries a slow subscriber may fall behind before it starts missing them
+const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+impl From<&Level> for LogLevel {
+    fn from(level: &Level) -> Self {
+        match *level {
+            Level::ERROR => LogLevel::Error,
+            Level::WARN => LogLevel::Warn,
+            Level::INFO => LogLevel::Info,
+            Level::DEBUG => LogLevel::Debug,
+            Level::TRACE => LogLevel::Trace,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// UNIX timestamp in milliseconds
+    pub timestamp: u64,
+    pub level: LogLevel,
+    /// Target of the event, usually the module path
+    pub module: String,
+    /// The message followed by the other fields of the event
+    pub message: String,
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {}: {}",
+            self.timestamp,
+            self.level.as_str().to_ascii_uppercase(),
+            self.module,
+            self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    /// The most verbose level to pass, all levels pass if not set
+    pub level: Option<LogLevel>,
+    /// Passes entries of the module and its submodules
+    pub module: Option<String>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        let level_matches = self.level.map_or(true, |level| entry.level <= level);
+        let module_matches = self.module.as_ref().map_or(true, |module| {
+            entry
+                .module
+                .strip_prefix(module.as_str())
+                .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+        });
+        level_matches && module_matches
+    }
+}
+
+/// Ring buffer of recent log entries and a channel of the new ones.
+/// Entries are captured by the layer returned from [`LogStream::layer`].
+#[derive(Clone)]
+pub struct LogStream {
+    recent: Arc<Mutex<VecDeque<LogEntry>>>,
+    capacity: usize,
+    live: broadcast::Sender<LogEntry>,
+}
+
+impl LogStream {
+    /// Keeps up to `capacity` recent entries
+    pub fn new(capacity: usize) -> Self {
+        let (live, _) = broadcast::channel(LIVE_CHANNEL_CAPACITY);
+        Self {
+            recent: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            live,
+        }
+    }
+
+    pub fn layer(&self) -> LogCaptureLayer {
+        LogCaptureLayer {
+            log_stream: self.clone(),
+        }
+    }
+
+    fn push(&self, entry: LogEntry) {
+        let mut recent = self.recent.lock();
+        if recent.len() >= self.capacity {
+            recent.pop_front();
+        }
+        // Sent under the lock, so `subscribe` never sees an entry both as recent and as live
+        let _ = self.live.send(entry.clone());
+        if self.capacity > 0 {
+            recent.push_back(entry);
+        }
+    }
+
+    /// Returns up to `tail` last recent entries passing the filter, oldest first,
+    /// and a receiver of the entries logged after them
+    pub fn subscribe(
+        &self,
+        filter: &LogFilter,
+        tail: usize,
+    ) -> (Vec<LogEntry>, broadcast::Receiver<LogEntry>) {
+        let recent = self.recent.lock();
+        let live = self.live.subscribe();
+        let mut entries: Vec<_> = recent
+            .iter()
+            .rev()
+            .filter(|entry| filter.matches(entry))
+            .take(tail)
+            .cloned()
+            .collect();
+        entries.reverse();
+        (entries, live)
+    }
+}
+
+/// Captures every event that passes the filters of the subscriber into the [`LogStream`]
+pub struct LogCaptureLayer {
+    log_stream: LogStream,
+}
+
+impl<S: Subscriber> Layer<S> for LogCaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // Events coming from the `log` crate carry their real metadata in fields
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        self.log_stream.push(LogEntry {
+            timestamp: now_millis::now_ms() as u64,
+            level: metadata.level().into(),
+            module: metadata.target().to_string(),
+            message: visitor.message,
+        });
+    }
+}
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name().starts_with("log.") {
+            return;
+        }
+        if !self.message.is_empty() {
+            self.message.push(' ');
+        }
+        let _ = if field.name() == "message" {
+            write!(self.message, "{value:?}")
+        } else {
+            write!(self.message, "{}={value:?}", field.name())
+        };
+    }
+}
+
+fn auth_message(node_peer_id: &PeerId, timestamp: u64) -> Vec<u8> {
+    format!("nox logs {node_peer_id} {timestamp}").into_bytes()
+}
+
+/// Value of the `Authorization` header for `/logs` of the node, signed by the management key
+pub fn auth_header(
+    key_pair: &KeyPair,
+    node_peer_id: &PeerId,
+    timestamp: u64,
+) -> eyre::Result<String> {
+    let signature = key_pair.sign(&auth_message(node_peer_id, timestamp))?;
+    Ok(format!(
+        "{AUTH_SCHEME} {timestamp}.{}",
+        base64.encode(signature.to_vec())
+    ))
+}
+
+/// Checks the header is signed by the management key recently enough, so it can't be replayed later
+#[cfg_attr(not(feature = "http"), allow(dead_code))]
+pub fn verify_auth_header(
+    header: &str,
+    node_peer_id: &PeerId,
+    management_peer_id: &PeerId,
+    now: u64,
+) -> bool {
+    let verified: Option<()> = try {
+        let credentials = header.strip_prefix(AUTH_SCHEME)?.strip_prefix(' ')?;
+        let (timestamp, signature) = credentials.split_once('.')?;
+        let timestamp: u64 = timestamp.parse().ok()?;
+        (timestamp.abs_diff(now) <= AUTH_MAX_SKEW.as_millis() as u64).then_some(())?;
+        let key = PublicKey::try_from(*management_peer_id).ok()?;
+        let signature = Signature::from_bytes(key.get_key_format(), base64.decode(signature).ok()?);
+        key.verify(&auth_message(node_peer_id, timestamp), &signature)
+            .ok()?
+    };
+    verified.is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn entry(level: LogLevel, module: &str, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: 0,
+            level,
+            module: module.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let filter = LogFilter {
+            level: Some(LogLevel::Info),
+            module: Some("sorcerer".to_string()),
+        };
+        assert!(filter.matches(&entry(LogLevel::Warn, "sorcerer", "")));
+        assert!(filter.matches(&entry(LogLevel::Info, "sorcerer::spell", "")));
+        assert!(!filter.matches(&entry(LogLevel::Debug, "sorcerer", "")));
+        assert!(!filter.matches(&entry(LogLevel::Info, "sorcerer_extra", "")));
+        assert!(!filter.matches(&entry(LogLevel::Info, "nox::node", "")));
+        assert!(LogFilter::default().matches(&entry(LogLevel::Trace, "nox", "")));
+    }
+
+    #[test]
+    fn recent_and_live() {
+        let log_stream = LogStream::new(2);
+        log_stream.push(entry(LogLevel::Info, "nox", "first"));
+        log_stream.push(entry(LogLevel::Debug, "nox", "second"));
+        log_stream.push(entry(LogLevel::Info, "nox", "third"));
+
+        let (recent, _) = log_stream.subscribe(&LogFilter::default(), 10);
+        let messages: Vec<_> = recent.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["second", "third"]);
+
+        let filter = LogFilter {
+            level: Some(LogLevel::Info),
+            module: None,
+        };
+        let (recent, mut live) = log_stream.subscribe(&filter, 10);
+        let messages: Vec<_> = recent.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, vec!["third"]);
+
+        log_stream.push(entry(LogLevel::Info, "nox", "fourth"));
+        assert_eq!(live.try_recv().unwrap().message, "fourth");
+    }
+
+    #[test]
+    fn capture_layer() {
+        let log_stream = LogStream::new(10);
+        let subscriber = tracing_subscriber::registry().with(log_stream.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(target: "nox::test", spell_id = "spell", "spell {} failed", 1);
+        });
+
+        let (recent, _) = log_stream.subscribe(&LogFilter::default(), 10);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].level, LogLevel::Warn);
+        assert_eq!(recent[0].module, "nox::test");
+        assert_eq!(recent[0].message, "spell 1 failed spell_id=\"spell\"");
+    }
+
+    #[test]
+    fn auth() {
+        let node = PeerId::random();
+        let management = KeyPair::generate_ed25519();
+        let management_peer_id = management.get_peer_id();
+        let now = 1_700_000_000_000;
+
+        let header = auth_header(&management, &node, now).unwrap();
+        assert!(verify_auth_header(
+            &header,
+            &node,
+            &management_peer_id,
+            now + 1000
+        ));
+        // stale
+        assert!(!verify_auth_header(
+            &header,
+            &node,
+            &management_peer_id,
+            now + 120_000
+        ));
+        // signed for another node
+        assert!(!verify_auth_header(
+            &header,
+            &PeerId::random(),
+            &management_peer_id,
+            now
+        ));
+
+        let stranger = KeyPair::generate_ed25519();
+        let header = auth_header(&stranger, &node, now).unwrap();
+        assert!(!verify_auth_header(
+            &header,
+            &node,
+            &management_peer_id,
+            now
+        ));
+        assert!(!verify_auth_header(
+            "Bearer token",
+            &node,
+            &management_peer_id,
+            now
+        ));
+    }
+}
@@ -0,0 +1,147 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `nox logs` prints the logs of a running node, streamed from its `/logs` endpoint,
+//! so nodes in containers or VMs can be tailed without shell access.
+
+use std::ffi::OsString;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use clap::Parser;
+use eyre::{eyre, WrapErr};
+use fluence_keypair::{KeyFormat, KeyPair};
+use futures::StreamExt;
+use libp2p::PeerId;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::log_stream::{auth_header, LogEntry, LogLevel};
+
+#[derive(Parser, Debug)]
+#[command(name = "nox logs", about = "Print logs of a running node")]
+struct LogsArgs {
+    /// HTTP endpoint of the running node
+    #[arg(long, short, default_value = "http://127.0.0.1:18080")]
+    endpoint: String,
+    /// Secret key in base64 of the management peer of the node
+    #[arg(long, short('y'))]
+    secret_key: String,
+    #[arg(long, short('k'), default_value = "ed25519")]
+    key_format: String,
+    /// Keep printing new entries as they are logged
+    #[arg(long, short)]
+    follow: bool,
+    /// The most verbose level to print
+    #[arg(long, short, value_enum)]
+    level: Option<LogLevel>,
+    /// Print only the entries of the module and its submodules
+    #[arg(long, short)]
+    module: Option<String>,
+    /// Number of recent entries to print first
+    #[arg(long, short('n'), default_value_t = 100)]
+    tail: usize,
+    /// Print entries as JSON lines
+    #[arg(long)]
+    json: bool,
+}
+
+/// Entrypoint of `nox logs`, `args` start with the subcommand name
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = LogsArgs::parse_from(args);
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(stream_logs(args))
+}
+
+async fn stream_logs(args: LogsArgs) -> eyre::Result<()> {
+    let secret_key = base64
+        .decode(&args.secret_key)
+        .wrap_err("secret key isn't a valid base64")?;
+    let key_format: KeyFormat = args.key_format.parse()?;
+    let key_pair = KeyPair::from_secret_key(secret_key, key_format)?;
+
+    let endpoint = args.endpoint.trim_end_matches('/');
+    // The signature is bound to the node, so it can't be used with another one
+    let node_peer_id = fetch_peer_id(endpoint).await?;
+
+    let mut url = reqwest::Url::parse(&format!("{endpoint}/logs"))
+        .wrap_err_with(|| format!("invalid endpoint {endpoint}"))?;
+    let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+    url.set_scheme(scheme)
+        .map_err(|_| eyre!("invalid endpoint {endpoint}"))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("tail", &args.tail.to_string());
+        query.append_pair("follow", &args.follow.to_string());
+        if let Some(level) = args.level {
+            query.append_pair("level", level.as_str());
+        }
+        if let Some(module) = &args.module {
+            query.append_pair("module", module);
+        }
+    }
+
+    let mut request = url.as_str().into_client_request()?;
+    let header = auth_header(&key_pair, &node_peer_id, now_millis::now_ms() as u64)?;
+    request.headers_mut().insert(AUTHORIZATION, header.parse()?);
+    let (mut socket, _) = tokio_tungstenite::connect_async(request)
+        .await
+        .wrap_err_with(|| format!("error connecting to {url}"))?;
+
+    while let Some(message) = socket.next().await {
+        match message.wrap_err("error reading logs")? {
+            Message::Text(text) => {
+                if args.json {
+                    println!("{text}");
+                } else {
+                    let entry: LogEntry =
+                        serde_json::from_str(&text).wrap_err("node sent invalid log entry")?;
+                    println!("{entry}");
+                }
+            }
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+async fn fetch_peer_id(endpoint: &str) -> eyre::Result<PeerId> {
+    #[derive(Deserialize)]
+    struct PeerIdResponse {
+        peer_id: String,
+    }
+
+    let url = format!("{endpoint}/peer_id");
+    let response = reqwest::get(&url)
+        .await
+        .wrap_err_with(|| format!("error fetching {url}"))?;
+    if !response.status().is_success() {
+        return Err(eyre!("{url} responded with {}", response.status()));
+    }
+    let response: PeerIdResponse = serde_json::from_str(&response.text().await?)
+        .wrap_err("running node returned invalid peer id")?;
+    response
+        .peer_id
+        .parse()
+        .wrap_err("running node returned invalid peer id")
+}
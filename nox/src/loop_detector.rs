@@ -0,0 +1,150 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Detection of particle forwarding loops.
+//! A routing mistake or a malicious chain of peers could make nodes pass a particle around
+//! in circles, amplifying traffic. A particle isn't sent further if
+//! - it was already sent from node to node `max_hops` times, according to its hop count
+//! - the node already sent its exact copy `max_repeats` times. The hop count isn't signed,
+//!   so this check holds even if peers on the loop reset it.
+
+use std::collections::HashMap;
+
+use parking_lot::Mutex;
+
+use particle_protocol::Particle;
+use peer_metrics::ForwardingLoop;
+use server_config::ForwardingLoopConfig;
+
+use crate::particle_dedup::{idempotency_key, now_ms, IdempotencyKey};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Forwarded {
+    count: u32,
+    deadline: u64,
+}
+
+pub struct LoopDetector {
+    max_hops: u32,
+    max_repeats: u32,
+    capacity: usize,
+    /// Idempotency keys of forwarded particles mapped to the number of sends
+    forwarded: Mutex<HashMap<IdempotencyKey, Forwarded>>,
+}
+
+impl LoopDetector {
+    pub fn new(config: &ForwardingLoopConfig) -> Self {
+        Self {
+            max_hops: config.max_hops,
+            max_repeats: config.max_repeats,
+            capacity: config.capacity,
+            forwarded: <_>::default(),
+        }
+    }
+
+    /// Records that the particle is about to be sent further,
+    /// returns why it shouldn't be if it's going in circles
+    pub fn check(&self, particle: &Particle) -> Option<ForwardingLoop> {
+        if self.max_hops > 0 && particle.hops >= self.max_hops {
+            return Some(ForwardingLoop::Hops);
+        }
+        if self.max_repeats == 0 {
+            return None;
+        }
+
+        let key = idempotency_key(particle);
+        let deadline = particle.deadline().unwrap_or(u64::MAX);
+        let now = now_ms();
+
+        let mut forwarded = self.forwarded.lock();
+        if let Some(known) = forwarded.get_mut(&key).filter(|f| f.deadline > now) {
+            if known.count >= self.max_repeats {
+                return Some(ForwardingLoop::Repeated);
+            }
+            known.count += 1;
+            return None;
+        }
+
+        if forwarded.len() >= self.capacity {
+            forwarded.retain(|_, f| f.deadline > now);
+        }
+        if forwarded.len() >= self.capacity {
+            // forget the particle that expires first, it's the least likely to come back
+            let first = forwarded
+                .iter()
+                .min_by_key(|(_, f)| f.deadline)
+                .map(|(k, _)| *k);
+            if let Some(first) = first {
+                forwarded.remove(&first);
+            }
+        }
+        forwarded.insert(key, Forwarded { count: 1, deadline });
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detector(max_hops: u32, max_repeats: u32) -> LoopDetector {
+        LoopDetector::new(&ForwardingLoopConfig {
+            max_hops,
+            max_repeats,
+            capacity: 10,
+        })
+    }
+
+    fn particle(data: &[u8], hops: u32) -> Particle {
+        Particle {
+            id: "id".to_string(),
+            timestamp: now_ms(),
+            ttl: 60_000,
+            data: data.to_vec(),
+            hops,
+            ..<_>::default()
+        }
+    }
+
+    #[test]
+    fn drops_particles_with_too_many_hops() {
+        let detector = detector(5, 0);
+
+        assert_eq!(detector.check(&particle(b"1", 4)), None);
+        assert_eq!(
+            detector.check(&particle(b"1", 5)),
+            Some(ForwardingLoop::Hops)
+        );
+    }
+
+    #[test]
+    fn drops_repeated_copies() {
+        let detector = detector(0, 2);
+
+        assert_eq!(detector.check(&particle(b"1", 0)), None);
+        // the hop count may be reset by a peer on the loop, it doesn't matter
+        assert_eq!(detector.check(&particle(b"1", 7)), None);
+        assert_eq!(
+            detector.check(&particle(b"1", 0)),
+            Some(ForwardingLoop::Repeated)
+        );
+        // a particle with new data makes progress, it isn't a loop
+        assert_eq!(detector.check(&particle(b"2", 0)), None);
+    }
+}
@@ -29,15 +29,17 @@
     unreachable_patterns
 )]
 
-use axum::async_trait;
+use async_trait::async_trait;
 use base64::{engine::general_purpose::STANDARD as base64, Engine};
 use cpu_utils::pinning::ThreadPinner;
-use cpu_utils::HwlocCPUTopology;
+use cpu_utils::{HwlocCPUTopology, LogicalCoreId};
 use eyre::WrapErr;
-use libp2p::PeerId;
+use futures::future;
+use libp2p::{Multiaddr, PeerId};
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::signal;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::sync::CancellationToken;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -47,7 +49,8 @@ use aquamarine::{AVMRunner, DataStoreConfig, VmConfig};
 use config_utils::to_peer_id;
 use core_distributor::{AcquireStrategy, CoreDistributor, PersistentCoreDistributor};
 use fs_utils::to_abs_path;
-use nox::{env_filter, log_layer, tracing_layer, Node};
+use node_events::{EventKind, EventLog};
+use nox::{env_filter, log_layer, tracing_layer, LogStream, Node, Spells};
 use server_config::{load_config, ConfigData, ResolvedConfig};
 use tracing_panic::panic_hook;
 use tracing_subscriber::reload;
@@ -57,6 +60,8 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
+/// Recent log entries kept for `nox logs`
+const RECENT_LOG_ENTRIES: usize = 1000;
 
 #[async_trait]
 trait Stoppable {
@@ -80,13 +85,28 @@ fn main() -> eyre::Result<()> {
     let (reloadable_tracing_layer, reload_handle) = reload::Layer::new(None);
 
     let (log_layer, _worker_guard) = log_layer();
+    let log_stream = LogStream::new(RECENT_LOG_ENTRIES);
 
     tracing_subscriber::registry()
         .with(env_filter())
         .with(log_layer)
+        .with(log_stream.layer())
         .with(reloadable_tracing_layer)
         .init();
 
+    let subcommand_args = || std::env::args_os().skip(1);
+    match std::env::args().nth(1).as_deref() {
+        Some("deploy") => return nox::deploy::run(subcommand_args()),
+        Some("config") => return nox::config_diff::run(subcommand_args()),
+        Some("doctor") => return nox::doctor::run(subcommand_args()),
+        Some("bench") => return nox::bench::run(subcommand_args()),
+        Some("storage") => return nox::storage::run(subcommand_args()),
+        Some("particle") => return nox::particle_inspect::run(subcommand_args()),
+        Some("logs") => return nox::logs::run(subcommand_args()),
+        Some("localnet") => return nox::localnet::run(subcommand_args()),
+        _ => {}
+    }
+
     let version = format!("{}; AIR version {}", VERSION, air_interpreter_wasm::VERSION);
     let authors = format!("by {AUTHORS}");
     let config_data = ConfigData {
@@ -124,6 +144,10 @@ fn main() -> eyre::Result<()> {
 
     let resolved_config = config.clone().resolve()?;
 
+    let layout_version = nox::migrations::run(&resolved_config.dir_config)
+        .wrap_err("failed to migrate persistent data")?;
+    tracing::info!("Persistent data layout version {layout_version}");
+
     let acquire_strategy = if resolved_config.dev_mode_config.enable {
         AcquireStrategy::RoundRobin
     } else {
@@ -144,15 +168,30 @@ fn main() -> eyre::Result<()> {
         )?;
     let system_cpu_cores_assignment = core_distributor.get_system_cpu_assignment();
 
-    let builder_thread_pinner = thread_pinner.clone();
-    let mut builder = tokio::runtime::Builder::new_multi_thread();
     // worker thread count should be equal assigned logical CPU count
     // because it is the optimal count of worker threads in Tokio runtime
-    // also we pin these threads to the assigned cores to prevent influence threads on each other
-    builder.worker_threads(system_cpu_cores_assignment.logical_core_ids.len());
+    // also we pin these threads to the assigned cores to prevent influence threads on each other,
+    // unless the network pool is configured explicitly
+    let (worker_threads, pinned_cores) =
+        match &resolved_config.node_config.thread_pools_config.network {
+            Some(pool) => {
+                eyre::ensure!(pool.threads > 0, "network thread pool must have threads");
+                let cores = pool.cpus.iter().copied().map(LogicalCoreId::new).collect();
+                (pool.threads, cores)
+            }
+            None => (
+                system_cpu_cores_assignment.logical_core_ids.len(),
+                system_cpu_cores_assignment.logical_core_ids,
+            ),
+        };
+
+    let builder_thread_pinner = thread_pinner.clone();
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.worker_threads(worker_threads);
     builder.on_thread_start(move || {
-        builder_thread_pinner
-            .pin_current_thread_to_cpuset(&system_cpu_cores_assignment.logical_core_ids);
+        if !pinned_cores.is_empty() {
+            builder_thread_pinner.pin_current_thread_to_cpuset(&pinned_cores);
+        }
     });
 
     builder.enable_all();
@@ -166,7 +205,7 @@ fn main() -> eyre::Result<()> {
         builder.enable_metrics_poll_count_histogram();
     }
 
-    builder
+    let restart = builder
         .build()
         .expect("Could not make tokio runtime")
         .block_on(async {
@@ -189,16 +228,64 @@ fn main() -> eyre::Result<()> {
             write_default_air_interpreter(&interpreter_path)?;
             log::info!("AIR interpreter: {:?}", interpreter_path);
 
-            let fluence =
-                start_fluence(resolved_config, core_distributor, thread_pinner, peer_id).await?;
+            let (fluence, restart_inlet, rebind_outlet, event_log) =
+                start_fluence(
+                    resolved_config,
+                    core_distributor,
+                    thread_pinner,
+                    peer_id,
+                    log_stream,
+                )
+                .await?;
             log::info!("Fluence has been successfully started.");
 
-            signal::ctrl_c().await.expect("Failed to listen for event");
+            let restart_requested = async {
+                match restart_inlet {
+                    Some(restart_inlet) => restart_inlet.await.ok(),
+                    None => future::pending().await,
+                }
+            };
+            tokio::pin!(restart_requested);
+            let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())?;
+            let restart = loop {
+                tokio::select! {
+                    result = signal::ctrl_c() => {
+                        result.expect("Failed to listen for event");
+                        break None;
+                    }
+                    Some(staged) = &mut restart_requested => break Some(staged),
+                    Some(()) = hangup.recv() => {
+                        log::info!("SIGHUP received, reloading listen addresses");
+                        match load_config(None).and_then(|c| c.resolve()) {
+                            // unchanged addresses are kept as is, so this is a no-op if ports didn't change
+                            Ok(config) => {
+                                event_log.record(EventKind::ConfigReloaded, "listen_config", None, None);
+                                let _ = rebind_outlet.send(config.listen_multiaddrs());
+                            }
+                            Err(err) => {
+                                log::error!("Failed to reload config: {:?}", err);
+                                event_log.record(
+                                    EventKind::ConfigReloaded,
+                                    "listen_config",
+                                    None,
+                                    Some(format!("failed: {err}")),
+                                );
+                            }
+                        }
+                    }
+                }
+            };
             log::info!("Shutting down...");
 
             fluence.stop().await;
-            Ok(())
-        })
+            eyre::Ok(restart)
+        })?;
+
+    // The runtime is shut down at this point, so nothing of the old node is left running
+    if let Some(staged) = restart {
+        return Err(nox::self_update::exec_staged(staged));
+    }
+    Ok(())
 }
 
 // NOTE: to stop Fluence just call Stoppable::stop()
@@ -207,7 +294,13 @@ async fn start_fluence(
     core_distributor: Arc<dyn CoreDistributor>,
     thread_pinner: Arc<dyn ThreadPinner>,
     peer_id: PeerId,
-) -> eyre::Result<impl Stoppable> {
+    log_stream: LogStream,
+) -> eyre::Result<(
+    impl Stoppable,
+    Option<oneshot::Receiver<PathBuf>>,
+    mpsc::UnboundedSender<Vec<Multiaddr>>,
+    EventLog,
+)> {
     log::trace!("starting Fluence");
 
     let listen_addrs = config.listen_multiaddrs();
@@ -215,10 +308,7 @@ async fn start_fluence(
 
     let data_store_config = DataStoreConfig::new(config.dir_config.avm_base_dir.clone());
 
-    let system_services_config = config.system_services.clone();
-    let system_service_distros =
-        system_services::SystemServiceDistros::default_from(system_services_config)
-            .wrap_err("Failed to get default system service distros")?;
+    let spells = Spells::from_config(&config)?;
 
     let mut node: Box<Node<AVMRunner>> = Node::new(
         config,
@@ -228,10 +318,11 @@ async fn start_fluence(
         data_store_config,
         VERSION,
         air_interpreter_wasm::VERSION,
-        system_service_distros,
+        spells,
     )
     .await
-    .wrap_err("error create node instance")?;
+    .wrap_err("error create node instance")?
+    .with_log_stream(log_stream);
     node.listen(listen_addrs).wrap_err("error on listen")?;
 
     let started_node = node.start(peer_id).await.wrap_err("node failed to start")?;
@@ -251,10 +342,15 @@ async fn start_fluence(
         }
     }
 
-    Ok(Fluence {
-        node_exit_outlet: started_node.exit_outlet,
-        cancellation_token: started_node.cancellation_token,
-    })
+    Ok((
+        Fluence {
+            node_exit_outlet: started_node.exit_outlet,
+            cancellation_token: started_node.cancellation_token,
+        },
+        started_node.restart_inlet,
+        started_node.rebind_outlet,
+        started_node.event_log,
+    ))
 }
 
 fn vm_config(config: &ResolvedConfig) -> VmConfig {
@@ -0,0 +1,154 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Push mode for metrics: the node periodically sends its OpenMetrics payload to a gateway,
+//! for deployments where scrapers can't open connections to the node.
+
+use std::sync::Arc;
+
+use libp2p::PeerId;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::registry::Registry;
+use reqwest::header::CONTENT_TYPE;
+use server_config::{MetricsPushAuth, MetricsPushConfig, MetricsPushMode};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Debug, Error)]
+pub enum MetricsPushError {
+    #[error("Failed to encode metrics: {0}")]
+    Encode(#[from] std::fmt::Error),
+    #[error("Failed to push metrics to {url}: {err}")]
+    Request {
+        url: String,
+        #[source]
+        err: reqwest::Error,
+    },
+}
+
+pub struct MetricsPusher {
+    url: String,
+    config: MetricsPushConfig,
+    client: reqwest::Client,
+    registry: Arc<Registry>,
+}
+
+impl MetricsPusher {
+    pub fn new(
+        config: MetricsPushConfig,
+        peer_id: PeerId,
+        registry: Arc<Registry>,
+    ) -> eyre::Result<Self> {
+        let client = reqwest::Client::builder().timeout(config.timeout).build()?;
+        Ok(Self {
+            url: push_url(&config, peer_id),
+            config,
+            client,
+            registry,
+        })
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::task::Builder::new()
+            .name("metrics-push")
+            .spawn(async move {
+                let mut interval = tokio::time::interval(self.config.interval);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = self.push().await {
+                        tracing::warn!("{err}");
+                    }
+                }
+            })
+            .expect("Could not spawn task")
+    }
+
+    async fn push(&self) -> Result<(), MetricsPushError> {
+        let mut payload = String::new();
+        encode(&mut payload, &self.registry)?;
+
+        let request = match self.config.mode {
+            MetricsPushMode::Pushgateway => self.client.put(&self.url),
+            MetricsPushMode::Raw => self.client.post(&self.url),
+        };
+        let request = match &self.config.auth {
+            Some(MetricsPushAuth::Basic { username, password }) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(MetricsPushAuth::Bearer { token }) => request.bearer_auth(token),
+            None => request,
+        };
+        request
+            .header(CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)
+            .body(payload)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|err| MetricsPushError::Request {
+                url: self.url.clone(),
+                err,
+            })?;
+
+        Ok(())
+    }
+}
+
+fn push_url(config: &MetricsPushConfig, peer_id: PeerId) -> String {
+    match config.mode {
+        MetricsPushMode::Pushgateway => format!(
+            "{}/metrics/job/{}/instance/{peer_id}",
+            config.endpoint.trim_end_matches('/'),
+            config.job
+        ),
+        MetricsPushMode::Raw => config.endpoint.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config(mode: MetricsPushMode) -> MetricsPushConfig {
+        MetricsPushConfig {
+            endpoint: "http://gateway:9091/".to_string(),
+            mode,
+            job: "nox".to_string(),
+            interval: Duration::from_secs(15),
+            timeout: Duration::from_secs(10),
+            auth: None,
+        }
+    }
+
+    #[test]
+    fn pushgateway_url_groups_by_peer_id() {
+        let peer_id = PeerId::random();
+        assert_eq!(
+            push_url(&config(MetricsPushMode::Pushgateway), peer_id),
+            format!("http://gateway:9091/metrics/job/nox/instance/{peer_id}")
+        );
+        assert_eq!(
+            push_url(&config(MetricsPushMode::Raw), peer_id),
+            "http://gateway:9091/"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Versioned migrations of the on-disk layout of persistent data.
+//!
+//! The layout version is kept in the persistent base dir. On startup, migrations newer than it
+//! run in order, each exactly once. The dirs and files a migration changes are backed up before
+//! it runs, and restored if it fails, so the node never starts on half-migrated data.
+//! A backup left by a migration interrupted by a crash is restored on the next start.
+//!
+//! A release changing the layout of spell storage, services dirs, keystores, etc appends
+//! a [`Migration`] with the next version to [`MIGRATIONS`].
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, eyre, WrapErr};
+
+use fs_utils::{copy_dir_all, remove_dir};
+use server_config::ResolvedDirConfig;
+
+const LAYOUT_VERSION_FILE: &str = "layout_version";
+const BACKUP_DIR: &str = "migration_backup";
+/// Lists the backed up paths, one per line, the backup of the n-th path is named `n`
+const BACKUP_MANIFEST: &str = "paths";
+
+pub struct Migration {
+    /// Layout version of the data after the migration
+    pub version: u32,
+    pub description: &'static str,
+    /// Dirs and files changed by the migration, backed up before it runs.
+    /// Must not contain the persistent base dir itself, backups are stored there.
+    pub paths: fn(&ResolvedDirConfig) -> Vec<PathBuf>,
+    pub run: fn(&ResolvedDirConfig) -> eyre::Result<()>,
+}
+
+/// Migrations of all releases, ordered by version
+pub const MIGRATIONS: &[Migration] = &[];
+
+/// Brings persistent data to the latest layout version, returns that version
+pub fn run(dirs: &ResolvedDirConfig) -> eyre::Result<u32> {
+    apply(dirs, MIGRATIONS)
+}
+
+fn apply(dirs: &ResolvedDirConfig, migrations: &[Migration]) -> eyre::Result<u32> {
+    let base_dir = &dirs.persistent_base_dir;
+    let backup_dir = base_dir.join(BACKUP_DIR);
+    if backup_dir.exists() {
+        log::warn!("Previous migration was interrupted, restoring data from {backup_dir:?}");
+        restore(&backup_dir)?;
+    }
+    // A backup which wasn't complete when the node stopped, the data wasn't touched yet
+    let partial_backup_dir = base_dir.join(format!("{BACKUP_DIR}.partial"));
+    if partial_backup_dir.exists() {
+        remove_dir(&partial_backup_dir)?;
+    }
+
+    let version_path = base_dir.join(LAYOUT_VERSION_FILE);
+    let mut version = read_version(&version_path)?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+    if version > latest {
+        bail!(
+            "Persistent data layout version {version} is newer than version {latest} supported by this nox, downgrades aren't supported"
+        );
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > version) {
+        log::info!(
+            "Migrating persistent data to layout version {}: {}",
+            migration.version,
+            migration.description
+        );
+        backup(&(migration.paths)(dirs), &partial_backup_dir)?;
+        fs::rename(&partial_backup_dir, &backup_dir)
+            .wrap_err_with(|| format!("error finishing backup to {backup_dir:?}"))?;
+
+        if let Err(err) = (migration.run)(dirs) {
+            restore(&backup_dir)?;
+            return Err(err.wrap_err(format!(
+                "Migration to layout version {} failed, the data was restored",
+                migration.version
+            )));
+        }
+
+        fs::write(&version_path, migration.version.to_string())
+            .wrap_err_with(|| format!("error writing layout version to {version_path:?}"))?;
+        remove_dir(&backup_dir)?;
+        version = migration.version;
+    }
+
+    Ok(version)
+}
+
+/// Data created before migrations were introduced has version 0
+fn read_version(path: &Path) -> eyre::Result<u32> {
+    match fs::read_to_string(path) {
+        Ok(version) => version
+            .trim()
+            .parse()
+            .wrap_err_with(|| format!("invalid layout version in {path:?}")),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err).wrap_err_with(|| format!("error reading {path:?}")),
+    }
+}
+
+fn backup(paths: &[PathBuf], backup_dir: &Path) -> eyre::Result<()> {
+    fs::create_dir_all(backup_dir)
+        .wrap_err_with(|| format!("error creating backup dir {backup_dir:?}"))?;
+    for (n, path) in paths.iter().enumerate() {
+        let target = backup_dir.join(n.to_string());
+        if path.is_dir() {
+            copy_dir_all(path, &target)
+        } else if path.exists() {
+            fs::copy(path, &target).map(drop).map_err(Into::into)
+        } else {
+            Ok(())
+        }
+        .wrap_err_with(|| format!("error backing up {path:?}"))?;
+    }
+
+    let manifest = paths
+        .iter()
+        .map(|path| {
+            path.to_str()
+                .ok_or_else(|| eyre!("path {path:?} contains non-UTF-8 characters"))
+        })
+        .collect::<eyre::Result<Vec<_>>>()?
+        .join("\n");
+    fs::write(backup_dir.join(BACKUP_MANIFEST), manifest)
+        .wrap_err_with(|| format!("error writing backup manifest to {backup_dir:?}"))
+}
+
+/// Puts the backed up paths back, removing those which didn't exist before the migration
+fn restore(backup_dir: &Path) -> eyre::Result<()> {
+    let manifest = fs::read_to_string(backup_dir.join(BACKUP_MANIFEST))
+        .wrap_err_with(|| format!("error reading backup manifest from {backup_dir:?}"))?;
+    for (n, path) in manifest.lines().enumerate() {
+        let path = Path::new(path);
+        if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else if path.exists() {
+            fs::remove_file(path)
+        } else {
+            Ok(())
+        }
+        .wrap_err_with(|| format!("error removing migrated {path:?}"))?;
+
+        let backup = backup_dir.join(n.to_string());
+        if backup.exists() {
+            fs::rename(&backup, path).wrap_err_with(|| format!("error restoring {path:?}"))?;
+        }
+    }
+    remove_dir(backup_dir)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use server_config::UnresolvedDirConfig;
+
+    use super::*;
+
+    fn dirs(base_dir: &Path) -> ResolvedDirConfig {
+        UnresolvedDirConfig {
+            base_dir: base_dir.to_path_buf(),
+            persistent_base_dir: None,
+            ephemeral_base_dir: None,
+            services_persistent_dir: None,
+            services_ephemeral_dir: None,
+            avm_base_dir: None,
+            air_interpreter_path: None,
+            spell_base_dir: None,
+            keypairs_base_dir: None,
+            workers_base_dir: None,
+            cc_events_dir: None,
+            core_state_path: None,
+            scheduled_jobs_dir: None,
+        }
+        .resolve()
+        .unwrap()
+    }
+
+    fn spells_paths(dirs: &ResolvedDirConfig) -> Vec<PathBuf> {
+        vec![dirs.spell_base_dir.clone()]
+    }
+
+    const MOVE_SPELL_DATA: Migration = Migration {
+        version: 1,
+        description: "move spell data to a subdir",
+        paths: spells_paths,
+        run: |dirs| {
+            let from = dirs.spell_base_dir.join("data");
+            let to = dirs.spell_base_dir.join("v1");
+            fs::create_dir_all(&to)?;
+            fs::rename(from, to.join("data"))?;
+            Ok(())
+        },
+    };
+
+    const BROKEN: Migration = Migration {
+        version: 2,
+        description: "fails after changing the data",
+        paths: spells_paths,
+        run: |dirs| {
+            fs::write(dirs.spell_base_dir.join("v1").join("data"), "corrupted")?;
+            bail!("broken migration")
+        },
+    };
+
+    #[test]
+    fn migrations_run_once() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dirs = dirs(tmp.path());
+        fs::write(dirs.spell_base_dir.join("data"), "spell").unwrap();
+
+        assert_eq!(apply(&dirs, &[MOVE_SPELL_DATA]).unwrap(), 1);
+        let migrated = dirs.spell_base_dir.join("v1").join("data");
+        assert_eq!(fs::read_to_string(&migrated).unwrap(), "spell");
+        assert!(!dirs.persistent_base_dir.join(BACKUP_DIR).exists());
+
+        // the data was already moved, running the migration again would fail
+        assert_eq!(apply(&dirs, &[MOVE_SPELL_DATA]).unwrap(), 1);
+        assert_eq!(fs::read_to_string(migrated).unwrap(), "spell");
+    }
+
+    #[test]
+    fn failed_migration_is_rolled_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dirs = dirs(tmp.path());
+        fs::write(dirs.spell_base_dir.join("data"), "spell").unwrap();
+
+        let err = apply(&dirs, &[MOVE_SPELL_DATA, BROKEN]).unwrap_err();
+        assert!(err.to_string().contains("layout version 2"), "{err}");
+
+        // the first migration is kept, the second is rolled back
+        let migrated = dirs.spell_base_dir.join("v1").join("data");
+        assert_eq!(fs::read_to_string(migrated).unwrap(), "spell");
+        assert_eq!(
+            read_version(&dirs.persistent_base_dir.join(LAYOUT_VERSION_FILE)).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn interrupted_migration_is_restored() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dirs = dirs(tmp.path());
+        fs::write(dirs.spell_base_dir.join("data"), "spell").unwrap();
+
+        // as if the node crashed in the middle of a migration
+        let backup_dir = dirs.persistent_base_dir.join(BACKUP_DIR);
+        backup(&spells_paths(&dirs), &backup_dir).unwrap();
+        fs::write(dirs.spell_base_dir.join("data"), "half-migrated").unwrap();
+
+        assert_eq!(apply(&dirs, &[]).unwrap(), 0);
+        let data = dirs.spell_base_dir.join("data");
+        assert_eq!(fs::read_to_string(data).unwrap(), "spell");
+    }
+
+    #[test]
+    fn newer_layout_is_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dirs = dirs(tmp.path());
+        fs::write(dirs.persistent_base_dir.join(LAYOUT_VERSION_FILE), "2").unwrap();
+
+        assert!(apply(&dirs, &[MOVE_SPELL_DATA]).is_err());
+    }
+}
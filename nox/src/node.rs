@@ -17,6 +17,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::path::PathBuf;
 use std::process::exit;
 use std::sync::Arc;
 use std::{io, net::SocketAddr};
@@ -26,7 +27,10 @@ use cpu_utils::pinning::ThreadPinner;
 use eyre::WrapErr;
 use fluence_keypair::KeyPair;
 use futures::future::OptionFuture;
+use futures::stream::BoxStream;
 use futures::{stream::StreamExt, FutureExt};
+use libp2p::ping;
+use libp2p::swarm::dial_opts::{DialOpts, PeerCondition};
 use libp2p::swarm::SwarmEvent;
 use libp2p::SwarmBuilder;
 use libp2p::{
@@ -46,34 +50,55 @@ use aquamarine::{
     AquaRuntime, AquamarineApi, AquamarineApiError, AquamarineBackend, DataStoreConfig,
     RemoteRoutingEffects, VmPoolConfig, WasmBackendConfig,
 };
-use chain_connector::HttpChainConnector;
+use chain_connector::{HttpChainConnector, RpcBuiltins};
 use chain_listener::ChainListener;
 use config_utils::to_peer_id;
-use connection_pool::ConnectionPoolT;
+use connection_pool::{ConnectionPoolT, LifecycleEvent};
 use core_distributor::CoreDistributor;
-use fluence_libp2p::build_transport;
+use fluence_libp2p::{build_transport, load_or_generate_webrtc_certificate, with_webrtc_transport};
 use health::HealthCheckRegistry;
-use particle_builtins::{Builtins, CustomService, NodeInfo, ParticleAppServicesConfig};
+use node_events::{EventKind, EventLog};
+use particle_builtins::{
+    Builtins, CustomService, NodeInfo, ParticleAppServicesConfig, SnapshotPolicy,
+};
 use particle_execution::ParticleFunctionStatic;
-use particle_protocol::ExtendedParticle;
+use particle_protocol::{ExtendedParticle, PROTOCOL_NAME};
+use particle_services::{
+    BinaryEgress, InternalOnlyServices, MemoryBudget, ParticleAppServices, StorageEncryption,
+    StorageKeys,
+};
 use peer_metrics::{
-    ChainListenerMetrics, ConnectionPoolMetrics, ConnectivityMetrics, ParticleExecutorMetrics,
-    ServicesMetrics, ServicesMetricsBackend, SpellMetrics, VmPoolMetrics,
+    AquamarineShardMetrics, ChainListenerMetrics, ConnectionPoolMetrics, ConnectivityMetrics,
+    KademliaMetrics, ParticleExecutorMetrics, ServicesMetrics, ServicesMetricsBackend,
+    SpellMetrics, VmPoolMetrics,
 };
 use server_config::system_services_config::ServiceKey;
-use server_config::{NetworkConfig, ResolvedConfig};
-use sorcerer::Sorcerer;
-use spell_event_bus::api::{PeerEvent, SpellEventBusApi, TriggerEvent};
-use spell_event_bus::bus::SpellEventBus;
-use system_services::{Deployer, SystemServiceDistros};
+use server_config::{NetworkConfig, ResolvedConfig, WasmBackendSettings};
 use workers::{KeyStorage, PeerScopes, Workers};
 
-use crate::behaviour::FluenceNetworkBehaviourEvent;
+use crate::behaviour::PEX_PROTOCOL;
+use crate::behaviour::{FluenceNetworkBehaviourEvent, PeerExchange, RendezvousRegistrations};
 use crate::builtins::make_peer_builtin;
+use crate::circuit_breaker::CircuitBreakers;
+use crate::client_sessions::ClientSessions;
 use crate::dispatcher::Dispatcher;
 use crate::effectors::Effectors;
-use crate::http::{start_http_endpoint, HttpEndpointData};
+use crate::gateway::{self, start_http_endpoint, HttpEndpointData};
+use crate::health::SwarmListenHealth;
+use crate::listeners::Listeners;
+use crate::log_stream::LogStream;
+use crate::loop_detector::LoopDetector;
 use crate::metrics::TokioCollector;
+use crate::metrics_push::MetricsPusher;
+use crate::particle_dedup::ParticleDedup;
+use crate::particle_wal::ParticleWal;
+use crate::protocol_capture::ProtocolCapture;
+use crate::self_update::SelfUpdate;
+use crate::snapshot_sync::SnapshotSync;
+use crate::spells::{SpellRuntime, Spells, SpellsContext};
+use crate::thread_pools::ThreadPools;
+use crate::ttl_guard::TtlGuard;
+use crate::webrtc::WebRtcListener;
 use crate::{Connectivity, Versions};
 
 use super::behaviour::FluenceNetworkBehaviour;
@@ -88,12 +113,9 @@ pub struct Node<RT: AquaRuntime> {
     pub aquamarine_api: AquamarineApi,
     pub dispatcher: Dispatcher,
     aquamarine_backend: AquamarineBackend<RT, Arc<Builtins<Connectivity>>>,
-    system_service_deployer: Deployer,
 
-    spell_event_bus_api: SpellEventBusApi,
-    spell_event_bus: SpellEventBus,
-    spell_events_receiver: mpsc::UnboundedReceiver<TriggerEvent>,
-    sorcerer: Sorcerer,
+    /// Sorcerer, spell event bus and system services, unless spells are compiled out
+    spells: Box<dyn SpellRuntime>,
 
     metrics_registry: Option<Registry>,
     health_registry: Option<HealthCheckRegistry>,
@@ -113,9 +135,49 @@ pub struct Node<RT: AquaRuntime> {
 
     workers: Arc<Workers>,
 
+    /// Decrypted copies of service storage are removed on shutdown if storage encryption is enabled
+    services: ParticleAppServices,
+
+    /// Receives the path of the staged binary when `self_update.restart` is called
+    restart_inlet: Option<oneshot::Receiver<PathBuf>>,
+
+    webrtc: Option<WebRtcListener>,
+
+    /// Dedicated pools for AVM executions and service calls, stopped with the node
+    thread_pools: ThreadPools,
+
+    /// TCP and WS listeners, rebound when listen ports change
+    listeners: Listeners,
+
+    rendezvous: RendezvousRegistrations,
+
+    /// Healthy peers shared with other nodes via peer exchange
+    pex: PeerExchange,
+
+    event_log: EventLog,
+
+    /// Logs served on `/logs` to the management peer
+    log_stream: Option<LogStream>,
+
+    /// Fetches provider and worker records from a designated peer on start, if configured
+    snapshot_sync: Option<SnapshotSync>,
+
+    /// Addresses the swarm listens on, reported on `/health`
+    swarm_health: SwarmListenHealth,
+
     config: ResolvedConfig,
 }
 
+async fn record_peer_churn(mut events: BoxStream<'static, LifecycleEvent>, event_log: EventLog) {
+    while let Some(event) = events.next().await {
+        let (kind, contact) = match event {
+            LifecycleEvent::Connected(contact) => (EventKind::PeerConnected, contact),
+            LifecycleEvent::Disconnected(contact) => (EventKind::PeerDisconnected, contact),
+        };
+        event_log.record(kind, contact.peer_id.to_base58(), None, None);
+    }
+}
+
 async fn setup_listener(
     connector: Option<Arc<HttpChainConnector>>,
     config: &ResolvedConfig,
@@ -170,12 +232,38 @@ impl<RT: AquaRuntime> Node<RT> {
         data_store_config: DataStoreConfig,
         node_version: &'static str,
         air_version: &'static str,
-        system_service_distros: SystemServiceDistros,
+        spells: Spells,
     ) -> eyre::Result<Box<Self>> {
         let key_pair: Keypair = config.node_config.root_key_pair.clone().into();
-        let transport = config.transport_config.transport;
-        let transport =
-            build_transport(transport, &key_pair, config.transport_config.socket_timeout);
+        let transport_kind = config.transport_config.transport;
+        let transport = build_transport(
+            transport_kind,
+            &key_pair,
+            config.transport_config.socket_timeout,
+        );
+        let (transport, webrtc) = if config.webrtc_config.enabled && transport_kind.is_network() {
+            let certificate_path = config
+                .webrtc_config
+                .certificate_path
+                .clone()
+                .unwrap_or_else(|| {
+                    config
+                        .dir_config
+                        .persistent_base_dir
+                        .join("webrtc_certificate.pem")
+                });
+            let certificate = load_or_generate_webrtc_certificate(&certificate_path)
+                .wrap_err_with(|| {
+                    format!("error loading WebRTC certificate from {certificate_path:?}")
+                })?;
+            let webrtc = WebRtcListener::new(&config, &certificate);
+            (
+                with_webrtc_transport(transport, &key_pair, certificate),
+                Some(webrtc),
+            )
+        } else {
+            (transport, None)
+        };
 
         let builtins_peer_id = to_peer_id(&config.builtins_key_pair.clone().into());
 
@@ -185,7 +273,8 @@ impl<RT: AquaRuntime> Node<RT> {
             config.dir_config.keypairs_base_dir.clone(),
             root_key_pair.clone(),
         )
-        .await?;
+        .await?
+        .with_derived_worker_keys(config.derive_worker_keys);
 
         let key_storage = Arc::new(key_storage);
 
@@ -209,7 +298,7 @@ impl<RT: AquaRuntime> Node<RT> {
 
         let wasm_backend_config = services_wasm_backend_config(&config);
 
-        let services_config = ParticleAppServicesConfig::new(
+        let mut services_config = ParticleAppServicesConfig::new(
             scopes.get_host_peer_id(),
             config.dir_config.services_persistent_dir.clone(),
             config.dir_config.services_ephemeral_dir.clone(),
@@ -230,6 +319,58 @@ impl<RT: AquaRuntime> Node<RT> {
             wasm_backend_config,
         )
         .expect("create services config");
+        services_config.restore_parallelism = config.node_config.services_restore_parallelism;
+        services_config.wasm_backend_next = config
+            .node_config
+            .services
+            .wasm_backend_next
+            .as_ref()
+            .map(to_wasm_backend_config);
+        services_config.memory_budget = MemoryBudget {
+            max_memory: config
+                .node_config
+                .services_memory_budget
+                .map(|b| b.as_u64()),
+            max_instances: config.node_config.max_loaded_services,
+        };
+        services_config.call_concurrency = config.node_config.services_call_concurrency;
+        services_config.nested_call_reserve = config.node_config.services_nested_call_reserve;
+        let egress = config.worker_egress_config.clone();
+        services_config.binary_egress = BinaryEgress {
+            restricted: Arc::new(move |worker_id| {
+                egress.allowed_for(&worker_id.to_string()).is_some()
+            }),
+            trusted_binaries: config
+                .worker_egress_config
+                .trusted_binaries
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        if let Some(storage_encryption) = &config.node_config.storage_encryption {
+            let keys = StorageKeys::from_hex(
+                &storage_encryption.data_key,
+                &storage_encryption.previous_keys,
+            )
+            .wrap_err("invalid storage encryption config")?;
+            services_config.storage_encryption = Some(StorageEncryption {
+                keys: Arc::new(keys),
+                // nodes sharing a host don't clean up copies of each other
+                plaintext_dir: storage_encryption
+                    .plaintext_dir
+                    .join(scopes.get_host_peer_id().to_string()),
+            });
+        }
+        if !config.node_config.internal_only_services.is_empty() {
+            services_config.call_authorizer = Arc::new(InternalOnlyServices::new(
+                config.node_config.internal_only_services.clone(),
+                [
+                    scopes.get_host_peer_id(),
+                    config.management_peer_id,
+                    builtins_peer_id,
+                ],
+            ));
+        }
 
         let mut metrics_registry = if config.metrics_config.metrics_enabled {
             Some(Registry::default())
@@ -246,8 +387,10 @@ impl<RT: AquaRuntime> Node<RT> {
         let libp2p_metrics = metrics_registry.as_mut().map(|r| Arc::new(Metrics::new(r)));
         let connectivity_metrics = metrics_registry.as_mut().map(ConnectivityMetrics::new);
         let connection_pool_metrics = metrics_registry.as_mut().map(ConnectionPoolMetrics::new);
+        let kademlia_metrics = metrics_registry.as_mut().map(KademliaMetrics::new);
         let plumber_metrics = metrics_registry.as_mut().map(ParticleExecutorMetrics::new);
         let vm_pool_metrics = metrics_registry.as_mut().map(VmPoolMetrics::new);
+        let shard_metrics = metrics_registry.as_mut().map(AquamarineShardMetrics::new);
         let spell_metrics = metrics_registry.as_mut().map(SpellMetrics::new);
         let chain_listener_metrics = metrics_registry.as_mut().map(ChainListenerMetrics::new);
 
@@ -277,11 +420,13 @@ impl<RT: AquaRuntime> Node<RT> {
             libp2p_metrics.clone(),
             connectivity_metrics,
             connection_pool_metrics,
+            kademlia_metrics,
             key_pair,
             &config,
             node_version,
             connection_limits,
         );
+        let clock_correction = network_config.clock_correction.clone();
 
         let allow_local_addresses = config.allow_local_addresses;
 
@@ -307,6 +452,27 @@ impl<RT: AquaRuntime> Node<RT> {
                 )
             };
 
+        let event_log = if config.event_log_config.enabled {
+            let path = config.dir_config.persistent_base_dir.join("events.log");
+            EventLog::open(
+                path,
+                config.event_log_config.max_events,
+                config.event_log_config.retention,
+            )
+            .wrap_err("error opening the event log")?
+        } else {
+            EventLog::default()
+        };
+
+        if config.read_only {
+            log::info!("Node runs as a read-only replica, state changing builtins are rejected");
+        }
+        if !config.disabled_builtins.is_empty() {
+            log::info!(
+                "Builtin namespaces are disabled: {:?}",
+                config.disabled_builtins
+            );
+        }
         let mut builtins = Self::builtins(
             connectivity.clone(),
             services_config,
@@ -316,16 +482,59 @@ impl<RT: AquaRuntime> Node<RT> {
             scopes.clone(),
             health_registry.as_mut(),
             config.system_services.decider.network_api_endpoint.clone(),
-        );
+            event_log.clone(),
+        )
+        .with_read_only(config.read_only)
+        .with_disabled_namespaces(config.disabled_builtins.clone())
+        .with_slow_call_threshold(config.slow_builtin_call_threshold);
+        let snapshot_config = &config.snapshot_sync_config;
+        let trusted_peers: Vec<PeerId> = snapshot_config
+            .sync_from
+            .iter()
+            .chain(&snapshot_config.trusted_peers)
+            .map(|peer_id| **peer_id)
+            .collect();
+        if !trusted_peers.is_empty() {
+            let max_age = snapshot_config.max_age.as_millis() as u64;
+            builtins = builtins.with_snapshot_policy(SnapshotPolicy::new(trusted_peers, max_age));
+        }
 
-        builtins.services.create_persisted_services().await?;
+        let deferred_services = builtins.services.create_persisted_services().await?;
 
         let builtins = Arc::new(builtins);
 
+        if !deferred_services.is_empty() {
+            let builtins = builtins.clone();
+            task::Builder::new()
+                .name("restore-worker-services")
+                .spawn(
+                    async move {
+                        builtins
+                            .services
+                            .restore_deferred_services(deferred_services)
+                            .await
+                    }
+                    .in_current_span(),
+                )
+                .expect("Could not spawn task");
+        }
+
         let (effects_out, effects_in) = mpsc::channel(config.node_config.effects_queue_buffer);
 
-        let pool_config =
-            VmPoolConfig::new(config.aquavm_pool_size, config.particle_execution_timeout);
+        let thread_pools = ThreadPools::new(
+            &config.node_config.thread_pools_config,
+            thread_pinner.clone(),
+        )?;
+        let pool_config = VmPoolConfig::new(
+            config.aquavm_pool_size,
+            config.aquamarine_shards,
+            config.particle_execution_timeout,
+            config.node_config.avm_config.args_limits,
+        )
+        .with_args_redaction(config.node_config.avm_config.args_redaction.clone())
+        .with_root_pools(thread_pools.root_pools())
+        .with_event_log(event_log.clone())
+        .with_clock_correction(clock_correction.clone());
         let avm_wasm_backend_config = avm_wasm_backend_config(&config);
         let (aquamarine_backend, aquamarine_api) = AquamarineBackend::new(
             pool_config,
@@ -336,44 +545,88 @@ impl<RT: AquaRuntime> Node<RT> {
             effects_out,
             plumber_metrics,
             vm_pool_metrics,
+            shard_metrics,
             health_registry.as_mut(),
             workers.clone(),
             key_storage.clone(),
             scopes.clone(),
             worker_events,
         )?;
-        let effectors = Effectors::new(connectivity.clone());
+        let breaker_config = &config.node_config.circuit_breaker_config;
+        let effectors = Effectors::new(connectivity.clone())
+            .with_loop_detector(LoopDetector::new(
+                &config.node_config.forwarding_loop_config,
+            ))
+            .with_circuit_breakers(CircuitBreakers::new(
+                breaker_config,
+                connectivity.metrics.clone(),
+            ))
+            .with_retries(breaker_config.max_retries, breaker_config.retry_backoff)
+            .with_clock_correction(clock_correction.clone());
+        let ttl_guard_config = &config.node_config.ttl_guard_config;
+        let effectors = if ttl_guard_config.min_forward_ttl.is_zero() {
+            effectors
+        } else {
+            effectors.with_ttl_guard(
+                TtlGuard::new(ttl_guard_config, root_key_pair.clone()).with_scopes(scopes.clone()),
+            )
+        };
         let dispatcher = {
             let parallelism = config.particle_processor_parallelism;
-            Dispatcher::new(
+            let dispatcher = Dispatcher::new(
                 scopes.get_host_peer_id(),
                 aquamarine_api.clone(),
                 effectors,
                 parallelism,
                 metrics_registry.as_mut(),
             )
+            .with_clock_correction(clock_correction);
+            let dispatcher = if config.particle_dedup_capacity > 0 {
+                dispatcher.with_particle_dedup(ParticleDedup::new(config.particle_dedup_capacity))
+            } else {
+                dispatcher
+            };
+            if config.persist_particle_queue {
+                let path = config
+                    .dir_config
+                    .persistent_base_dir
+                    .join("particle_queue.wal");
+                let (wal, replay) = ParticleWal::open(path, true)
+                    .wrap_err("error opening the particle queue log")?;
+                dispatcher.with_particle_wal(wal, replay)
+            } else {
+                dispatcher
+            }
         };
 
-        let recv_connection_pool_events = connectivity.connection_pool.lifecycle_events();
-        let sources = vec![recv_connection_pool_events.map(PeerEvent::from).boxed()];
-
-        let (spell_event_bus, spell_event_bus_api, spell_events_receiver) =
-            SpellEventBus::new(spell_metrics.clone(), sources);
-
-        let spell_service_api = spell_service_api::SpellServiceApi::new(builtins.services.clone());
-        let (sorcerer, mut custom_service_functions, spell_version) = Sorcerer::new(
-            builtins.services.clone(),
-            builtins.modules.clone(),
-            aquamarine_api.clone(),
-            config.clone(),
-            spell_event_bus_api.clone(),
-            workers.clone(),
-            key_storage.clone(),
-            scopes.clone(),
-            spell_service_api.clone(),
-            spell_metrics,
-        )
-        .await;
+        let swarm_health = SwarmListenHealth::default();
+        if let Some(registry) = health_registry.as_mut() {
+            registry.register("swarm_listening", swarm_health.clone());
+        }
+        let (spells, mut custom_service_functions) = spells
+            .build(SpellsContext {
+                config: &config,
+                builtins: &builtins,
+                aquamarine_api: &aquamarine_api,
+                connectivity: &connectivity,
+                workers: &workers,
+                key_storage: &key_storage,
+                scopes: &scopes,
+                health_registry: health_registry.as_mut(),
+                metrics: spell_metrics,
+            })
+            .await?;
+        let spell_version = spells.spell_version();
+
+        let snapshot_sync = config.snapshot_sync_config.sync_from.map(|sync_from| {
+            SnapshotSync::new(
+                *sync_from,
+                config.snapshot_sync_config.retry_interval,
+                root_key_pair.clone(),
+                aquamarine_api.clone(),
+                builtins.clone(),
+            )
+        });
 
         let allowed_binaries = config
             .allowed_effectors
@@ -398,10 +651,58 @@ impl<RT: AquaRuntime> Node<RT> {
                 node_info.spell_version.clone(),
             );
         }
-        custom_service_functions.extend_one(make_peer_builtin(node_info));
+        let versions = Versions::new(
+            node_version.to_string(),
+            air_version.to_string(),
+            spell_version,
+            vec![
+                PROTOCOL_NAME.to_string(),
+                config.kademlia.protocol_name.to_string(),
+                PEX_PROTOCOL.to_string(),
+            ],
+            spells.system_service_versions(),
+        );
+        custom_service_functions.extend_one(make_peer_builtin(node_info, versions.clone()));
+
+        // registered without allowed endpoints too, so that workers can inspect their egress policy
+        let (_, rpc_builtins) = RpcBuiltins::new(
+            &config.rpc_config,
+            &config.worker_egress_config,
+            scopes.clone(),
+        )?;
+        custom_service_functions.extend(rpc_builtins.into_iter());
+
+        let protocol_capture = ProtocolCapture::new(
+            connectivity.connection_pool.clone(),
+            config.dir_config.ephemeral_base_dir.join("captures"),
+            config.protocol_capture_config.clone(),
+            scopes.clone(),
+        );
+        custom_service_functions.extend_one(protocol_capture.make_builtin());
+
+        let client_sessions = ClientSessions::new(
+            connectivity.connection_pool.clone(),
+            builtins.provider_table(),
+            &config.session_resumption_config,
+        );
+        client_sessions.clone().start();
+        custom_service_functions.extend_one(client_sessions.make_builtin());
+
+        let restart_inlet = if config.self_update_config.download_url.is_some() {
+            let (self_update, restart_inlet) = SelfUpdate::new(
+                &config.self_update_config,
+                env!("CARGO_PKG_VERSION"),
+                config.dir_config.persistent_base_dir.join("update"),
+                scopes.clone(),
+                config.worker_egress_config.clone(),
+            )?;
+            custom_service_functions.extend_one(self_update.make_builtin());
+            Some(restart_inlet)
+        } else {
+            None
+        };
 
         let services = builtins.services.clone();
-        let modules = builtins.modules.clone();
 
         let connector = if let Some(chain_config) = config.chain_config.clone() {
             let host_id = scopes.get_host_peer_id();
@@ -443,28 +744,10 @@ impl<RT: AquaRuntime> Node<RT> {
             },
         );
 
-        let system_services_deployer = Deployer::new(
-            services,
-            modules,
-            sorcerer.spell_storage.clone(),
-            spell_event_bus_api.clone(),
-            spell_service_api,
-            scopes.get_host_peer_id(),
-            builtins_peer_id,
-            system_service_distros,
-        );
-
-        let versions = Versions::new(
-            node_version.to_string(),
-            air_version.to_string(),
-            spell_version,
-            system_services_deployer.versions(),
-        );
-
         let chain_listener =
             setup_listener(connector, &config, core_distributor, chain_listener_metrics).await?;
 
-        Ok(Self::with(
+        let mut node = Self::with(
             particle_stream,
             effects_in,
             swarm,
@@ -472,24 +755,28 @@ impl<RT: AquaRuntime> Node<RT> {
             aquamarine_api,
             dispatcher,
             aquamarine_backend,
-            system_services_deployer,
-            spell_event_bus_api,
-            spell_event_bus,
-            spell_events_receiver,
-            sorcerer,
+            spells,
             metrics_registry,
             health_registry,
             libp2p_metrics,
             services_metrics_backend,
-            config.http_listen_addr(),
+            gateway::listen_addr(&config),
             builtins_peer_id,
             scopes,
             allow_local_addresses,
             versions,
             chain_listener,
             workers.clone(),
+            services,
+            restart_inlet,
+            webrtc,
+            thread_pools,
+            event_log,
             config,
-        ))
+        );
+        node.snapshot_sync = snapshot_sync;
+        node.swarm_health = swarm_health;
+        Ok(node)
     }
 
     pub fn swarm(
@@ -540,6 +827,7 @@ impl<RT: AquaRuntime> Node<RT> {
         scopes: PeerScopes,
         health_registry: Option<&mut HealthCheckRegistry>,
         connector_api_endpoint: String,
+        event_log: EventLog,
     ) -> Builtins<Connectivity> {
         Builtins::new(
             connectivity,
@@ -550,6 +838,7 @@ impl<RT: AquaRuntime> Node<RT> {
             scopes,
             health_registry,
             connector_api_endpoint,
+            event_log,
         )
     }
 }
@@ -558,6 +847,11 @@ pub struct StartedNode {
     pub cancellation_token: CancellationToken,
     pub exit_outlet: oneshot::Sender<()>,
     pub http_listen_addr: Option<SocketAddr>,
+    /// Resolves to the path of the staged binary when the node is asked to restart with it
+    pub restart_inlet: Option<oneshot::Receiver<PathBuf>>,
+    /// Send new listen addresses here to rebind listeners without dropping connections
+    pub rebind_outlet: mpsc::UnboundedSender<Vec<Multiaddr>>,
+    pub event_log: EventLog,
 }
 
 impl<RT: AquaRuntime> Node<RT> {
@@ -570,11 +864,7 @@ impl<RT: AquaRuntime> Node<RT> {
         aquamarine_api: AquamarineApi,
         dispatcher: Dispatcher,
         aquamarine_backend: AquamarineBackend<RT, Arc<Builtins<Connectivity>>>,
-        system_service_deployer: Deployer,
-        spell_event_bus_api: SpellEventBusApi,
-        spell_event_bus: SpellEventBus,
-        spell_events_receiver: mpsc::UnboundedReceiver<TriggerEvent>,
-        sorcerer: Sorcerer,
+        spells: Box<dyn SpellRuntime>,
         metrics_registry: Option<Registry>,
         health_registry: Option<HealthCheckRegistry>,
         libp2p_metrics: Option<Arc<Metrics>>,
@@ -586,8 +876,18 @@ impl<RT: AquaRuntime> Node<RT> {
         versions: Versions,
         chain_listener: Option<ChainListener>,
         workers: Arc<Workers>,
+        services: ParticleAppServices,
+        restart_inlet: Option<oneshot::Receiver<PathBuf>>,
+        webrtc: Option<WebRtcListener>,
+        thread_pools: ThreadPools,
+        event_log: EventLog,
         config: ResolvedConfig,
     ) -> Box<Self> {
+        let pex = PeerExchange::new(
+            config.pex_config.clone(),
+            *swarm.local_peer_id(),
+            allow_local_addresses,
+        );
         let node_service = Self {
             particle_stream,
             effects_stream,
@@ -597,11 +897,7 @@ impl<RT: AquaRuntime> Node<RT> {
             aquamarine_api,
             dispatcher,
             aquamarine_backend,
-            system_service_deployer,
-            spell_event_bus_api,
-            spell_event_bus,
-            spell_events_receiver,
-            sorcerer,
+            spells,
 
             metrics_registry,
             health_registry,
@@ -614,12 +910,29 @@ impl<RT: AquaRuntime> Node<RT> {
             versions,
             chain_listener,
             workers,
+            services,
+            restart_inlet,
+            webrtc,
+            thread_pools,
+            listeners: Listeners::default(),
+            rendezvous: RendezvousRegistrations::new(&config.rendezvous_config),
+            pex,
+            event_log,
+            log_stream: None,
+            snapshot_sync: None,
+            swarm_health: SwarmListenHealth::default(),
             config,
         };
 
         Box::new(node_service)
     }
 
+    /// Serve the logs captured by `log_stream` to the management peer over the http endpoint
+    pub fn with_log_stream(mut self: Box<Self>, log_stream: LogStream) -> Box<Self> {
+        self.log_stream = Some(log_stream);
+        self
+    }
+
     /// Starts node service
     #[allow(clippy::boxed_local)] // Mike said it should be boxed
     pub async fn start(self: Box<Self>, peer_id: PeerId) -> eyre::Result<StartedNode> {
@@ -632,9 +945,14 @@ impl<RT: AquaRuntime> Node<RT> {
         let connectivity = self.connectivity;
         let dispatcher = self.dispatcher;
         let aquamarine_backend = self.aquamarine_backend;
-        let spell_event_bus = self.spell_event_bus;
-        let spell_events_receiver = self.spell_events_receiver;
-        let sorcerer = self.sorcerer;
+        let spell_webhooks = self
+            .config
+            .http_config
+            .as_ref()
+            .map_or(false, |c| c.spell_webhooks);
+        let spell_webhooks = self.spells.webhooks().filter(|_| spell_webhooks);
+        let spells_activation = self.spells.activation();
+        let spells = self.spells;
         let services_metrics_backend = self.services_metrics_backend;
         let http_listen_addr = self.http_listen_addr;
         let task_name = format!("node-{peer_id}");
@@ -642,13 +960,44 @@ impl<RT: AquaRuntime> Node<RT> {
         let allow_local_addresses = self.allow_local_addresses;
         let versions = self.versions;
         let workers = self.workers.clone();
+        let services = self.services;
         let chain_listener = self.chain_listener;
-
-        let http_endpoint_data = HttpEndpointData::new(
-            self.metrics_registry,
-            self.health_registry,
-            Some(self.config),
-        );
+        let restart_inlet = self.restart_inlet;
+        let mut listeners = self.listeners;
+        let rendezvous = self.rendezvous;
+        let mut pex = self.pex;
+        let thread_pools = self.thread_pools;
+        let rebind_grace_period = self.config.listen_config.rebind_grace_period;
+        let (rebind_outlet, mut rebind_inlet) = mpsc::unbounded_channel::<Vec<Multiaddr>>();
+
+        let metrics_registry = self.metrics_registry.map(Arc::new);
+        let snapshot_sync = self.snapshot_sync;
+        let swarm_health = self.swarm_health;
+        let metrics_pusher = match (&self.config.metrics_config.push, &metrics_registry) {
+            (Some(push), Some(registry)) => Some(
+                MetricsPusher::new(push.clone(), peer_id, registry.clone())
+                    .context("creating metrics pusher failed")?,
+            ),
+            _ => None,
+        };
+        let http_endpoint_data =
+            HttpEndpointData::new(metrics_registry, self.health_registry, Some(self.config));
+        let http_endpoint_data = match spell_webhooks {
+            Some(spell_event_bus_api) => {
+                http_endpoint_data.with_spell_webhooks(spell_event_bus_api)
+            }
+            None => http_endpoint_data,
+        };
+        let event_log = self.event_log;
+        let http_endpoint_data = http_endpoint_data.with_event_log(event_log.clone());
+        let http_endpoint_data = match self.log_stream {
+            Some(log_stream) => {
+                http_endpoint_data.with_log_stream(log_stream, self.builtins_management_peer_id)
+            }
+            None => http_endpoint_data,
+        };
+        let peer_events = connectivity.connection_pool.lifecycle_events();
+        let peer_churn_log = event_log.clone();
 
         let cancellation_token = CancellationToken::new();
         let task_cancellation_token = cancellation_token.clone();
@@ -668,23 +1017,102 @@ impl<RT: AquaRuntime> Node<RT> {
 
 
             let services_metrics_backend = services_metrics_backend.start();
-            let spell_event_bus = spell_event_bus.start();
-            let sorcerer = sorcerer.start(spell_events_receiver);
+            let metrics_pusher = metrics_pusher.map(|p| p.start());
+            let snapshot_sync = snapshot_sync.map(|s| s.start());
+            let peer_churn = tokio::spawn(record_peer_churn(peer_events, peer_churn_log));
+            let spells = spells.start();
             let chain_listener = chain_listener.map(|c| c.start());
             let aquamarine_backend = aquamarine_backend.start();
             let mut connectivity = connectivity.start();
             let mut dispatcher = dispatcher.start(particle_stream, effects_stream);
             let mut exit_inlet = Some(exit_inlet);
 
+            if rendezvous.is_enabled() {
+                for (_, addr) in rendezvous.points() {
+                    if let Err(err) = swarm.dial(addr.clone()) {
+                        log::warn!("Failed to dial rendezvous point {}: {}", addr, err);
+                    }
+                }
+            }
+            let refresh_period = rendezvous.refresh_period();
+            let mut rendezvous_refresh =
+                tokio::time::interval_at(tokio::time::Instant::now() + refresh_period, refresh_period);
+            let pex_period = pex.period();
+            let mut pex_exchange =
+                tokio::time::interval_at(tokio::time::Instant::now() + pex_period, pex_period);
+
             loop {
                 let exit_inlet = exit_inlet.as_mut().expect("Could not get exit inlet");
+                let next_retirement = listeners.next_retirement();
                 tokio::select! {
                     Some(e) = swarm.next() => {
                         if let Some(m) = libp2p_metrics.as_ref() { m.record(&e) }
-                        if let SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Identify(event)) = e {
-                            swarm.behaviour_mut().inject_identify_event(event, allow_local_addresses);
+                        match e {
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Identify(event)) => {
+                                pex.inject_identify_event(&event);
+                                swarm.behaviour_mut().inject_identify_event(event, allow_local_addresses);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Ping(ping::Event { peer, result: Ok(rtt), .. })) => {
+                                swarm.behaviour_mut().connection_pool.record_rtt(peer, rtt);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::RendezvousClient(event)) => {
+                                swarm.behaviour_mut().inject_rendezvous_event(event);
+                            }
+                            SwarmEvent::Behaviour(FluenceNetworkBehaviourEvent::Pex(event)) => {
+                                // Shared peers are only dialed, they're added to Kademlia and
+                                // the connection pool after Identify confirms they're Fluence nodes
+                                for (peer_id, addresses) in swarm.behaviour_mut().inject_pex_event(event, &pex) {
+                                    let opts = DialOpts::peer_id(peer_id)
+                                        .addresses(addresses)
+                                        .condition(PeerCondition::DisconnectedAndNotDialing)
+                                        .build();
+                                    if let Err(err) = swarm.dial(opts) {
+                                        log::debug!("Failed to dial PEX candidate {}: {}", peer_id, err);
+                                    }
+                                }
+                            }
+                            SwarmEvent::NewListenAddr { address, .. } => {
+                                swarm_health.on_new_listen_addr(address);
+                            }
+                            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                                swarm_health.on_expired_listen_addr(&address);
+                            }
+                            SwarmEvent::ListenerClosed { addresses, .. } => {
+                                for address in addresses {
+                                    swarm_health.on_expired_listen_addr(&address);
+                                }
+                            }
+                            SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                                pex.remove_peer(&peer_id);
+                            }
+                            SwarmEvent::ConnectionEstablished { peer_id, num_established, .. }
+                                if num_established.get() == 1 && rendezvous.is_point(&peer_id) => {
+                                swarm.behaviour_mut().register_at_rendezvous(&rendezvous, peer_id);
+                            }
+                            _ => {}
+                        }
+                    },
+                    Some(addrs) = rebind_inlet.recv() => {
+                        log::info!("Rebinding listeners to {:?}", addrs);
+                        if let Err(err) = listeners.rebind(&mut swarm, addrs, rebind_grace_period) {
+                            log::error!("Failed to rebind listeners: {}", err);
+                        }
+                    },
+                    _ = tokio::time::sleep_until(next_retirement.unwrap_or_else(tokio::time::Instant::now)), if next_retirement.is_some() => {
+                        listeners.retire_due(&mut swarm);
+                    },
+                    _ = rendezvous_refresh.tick(), if rendezvous.is_enabled() => {
+                        for (peer_id, addr) in rendezvous.points() {
+                            if swarm.is_connected(peer_id) {
+                                swarm.behaviour_mut().register_at_rendezvous(&rendezvous, *peer_id);
+                            } else if let Err(err) = swarm.dial(addr.clone()) {
+                                log::warn!("Failed to dial rendezvous point {}: {}", addr, err);
+                            }
                         }
                     },
+                    _ = pex_exchange.tick(), if pex.is_enabled() => {
+                        swarm.behaviour_mut().exchange_peers(&pex);
+                    },
                     _ = &mut http_server => {},
                     _ = &mut connectivity => {},
                     _ = &mut dispatcher => {},
@@ -698,27 +1126,21 @@ impl<RT: AquaRuntime> Node<RT> {
             log::info!("Stopping node");
             if let Some(c) = chain_listener { c.abort() }
             services_metrics_backend.abort();
-            spell_event_bus.abort();
-            sorcerer.abort();
+            if let Some(p) = metrics_pusher { p.abort() }
+            if let Some(s) = snapshot_sync { s.abort() }
+            peer_churn.abort();
+            spells.iter().for_each(|task| task.abort());
             dispatcher.cancel().await;
             connectivity.cancel().await;
             aquamarine_backend.abort();
+            services.seal_storage().await;
             workers.shutdown();
+            drop(thread_pools);
             task_cancellation_token.cancel()
         }.in_current_span()).expect("Could not spawn task");
 
         // Note: need to be after the start of the node to be able to subscribe spells
-        let deployer = self.system_service_deployer;
-        deployer
-            .deploy_system_services()
-            .await
-            .context("deploying system services failed")?;
-
-        self.spell_event_bus_api
-            .start_scheduling()
-            .await
-            .map_err(|e| eyre::eyre!("{e}"))
-            .context("running spell event bus failed")?;
+        spells_activation.await?;
 
         let http_listen_addr = OptionFuture::from(http_listen_addr.map(|_| async {
             let addr = http_bind_inlet.await.expect("http bind sender is dropped");
@@ -730,9 +1152,19 @@ impl<RT: AquaRuntime> Node<RT> {
             exit_outlet,
             http_listen_addr,
             cancellation_token,
+            restart_inlet,
+            rebind_outlet,
+            event_log,
         })
     }
 
+    /// Handle to the node's stable public API, can be obtained before the node is started
+    #[cfg(feature = "spells")]
+    pub fn handle(&self) -> crate::NodeHandle {
+        self.spells
+            .handle(self.connectivity.connection_pool.clone())
+    }
+
     /// Starts node service listener.
     #[inline]
     pub fn listen(
@@ -742,8 +1174,9 @@ impl<RT: AquaRuntime> Node<RT> {
         let addrs = addrs.into();
         log::info!("Fluence listening on {:?}", addrs);
 
-        for addr in addrs {
-            Swarm::listen_on(&mut self.swarm, addr)?;
+        self.listeners.listen(&mut self.swarm, addrs)?;
+        if let Some(webrtc) = &self.webrtc {
+            webrtc.listen(&mut self.swarm)?;
         }
         Ok(())
     }
@@ -774,26 +1207,16 @@ fn avm_wasm_backend_config(config: &ResolvedConfig) -> WasmBackendConfig {
 }
 
 fn services_wasm_backend_config(config: &ResolvedConfig) -> WasmBackendConfig {
+    to_wasm_backend_config(&config.node_config.services.wasm_backend)
+}
+
+fn to_wasm_backend_config(config: &WasmBackendSettings) -> WasmBackendConfig {
     WasmBackendConfig {
-        debug_info: config.node_config.services.wasm_backend.debug_info,
-        wasm_backtrace: config.node_config.services.wasm_backend.wasm_backtrace,
-        async_wasm_stack: config
-            .node_config
-            .services
-            .wasm_backend
-            .async_wasm_stack
-            .as_u64() as usize,
-        max_wasm_stack: config
-            .node_config
-            .services
-            .wasm_backend
-            .max_wasm_stack
-            .as_u64() as usize,
-        epoch_interruption_duration: config
-            .node_config
-            .services
-            .wasm_backend
-            .epoch_interruption_duration,
+        debug_info: config.debug_info,
+        wasm_backtrace: config.wasm_backtrace,
+        async_wasm_stack: config.async_wasm_stack.as_u64() as usize,
+        max_wasm_stack: config.max_wasm_stack.as_u64() as usize,
+        epoch_interruption_duration: config.epoch_interruption_duration,
     }
 }
 
@@ -817,7 +1240,7 @@ mod tests {
     use server_config::{default_base_dir, load_config_with_args, persistent_dir};
     use system_services::SystemServiceDistros;
 
-    use crate::Node;
+    use crate::{Node, Spells};
 
     #[tokio::test]
     async fn run_node() {
@@ -864,16 +1287,20 @@ mod tests {
             data_store_config,
             "some version",
             "some version",
-            system_service_distros,
+            Spells::new(system_service_distros),
         )
         .await
         .expect("create node");
 
         let listening_address: Multiaddr = "/ip4/127.0.0.1/tcp/7777".parse().unwrap();
         node.listen(vec![listening_address.clone()]).unwrap();
+        let handle = node.handle();
         let peer_id = PeerId::random();
         let started_node = node.start(peer_id).await.expect("start node");
 
+        assert!(handle.spells().list().is_empty());
+        assert!(handle.services().list().await.is_empty());
+
         let mut client = ConnectedClient::connect_to_with_timeout(
             listening_address,
             Duration::from_secs(10),
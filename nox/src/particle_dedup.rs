@@ -0,0 +1,126 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Filter of exact particle copies.
+//! Clients may send a critical particle through several relays at once, so the same particle
+//! can reach a peer more than once. Copies with the same id, signature and data are executed once.
+
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use parking_lot::Mutex;
+
+use particle_protocol::Particle;
+
+pub(crate) type IdempotencyKey = [u8; 32];
+
+pub struct ParticleDedup {
+    /// Max number of remembered particles
+    capacity: usize,
+    /// Idempotency keys of accepted particles mapped to the particle deadlines
+    seen: Mutex<HashMap<IdempotencyKey, u64>>,
+}
+
+impl ParticleDedup {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            seen: <_>::default(),
+        }
+    }
+
+    /// Remembers the particle and returns whether its exact copy was accepted before
+    pub fn is_duplicate(&self, particle: &Particle) -> bool {
+        let key = idempotency_key(particle);
+        let deadline = particle.deadline().unwrap_or(u64::MAX);
+        let now = now_ms();
+
+        let mut seen = self.seen.lock();
+        if seen.get(&key).is_some_and(|d| *d > now) {
+            return true;
+        }
+
+        if seen.len() >= self.capacity {
+            seen.retain(|_, d| *d > now);
+        }
+        if seen.len() >= self.capacity {
+            // forget the particle that expires first, its copies are the least likely to come
+            let first = seen.iter().min_by_key(|(_, d)| **d).map(|(k, _)| *k);
+            if let Some(first) = first {
+                seen.remove(&first);
+            }
+        }
+        seen.insert(key, deadline);
+
+        false
+    }
+}
+
+pub(crate) fn idempotency_key(particle: &Particle) -> IdempotencyKey {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(particle.id.as_bytes());
+    hasher.update(&particle.signature);
+    hasher.update(&particle.data);
+    *hasher.finalize().as_bytes()
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(id: &str, data: &[u8], ttl: u32) -> Particle {
+        let timestamp = now_ms() - if ttl == 0 { 60_000 } else { 0 };
+        Particle {
+            id: id.to_string(),
+            timestamp,
+            ttl,
+            data: data.to_vec(),
+            ..<_>::default()
+        }
+    }
+
+    #[test]
+    fn drops_exact_copies() {
+        let dedup = ParticleDedup::new(10);
+
+        assert!(!dedup.is_duplicate(&particle("a", b"1", 60_000)));
+        assert!(dedup.is_duplicate(&particle("a", b"1", 60_000)));
+        // copies with new data are merged by the interpreter, so they pass
+        assert!(!dedup.is_duplicate(&particle("a", b"2", 60_000)));
+        assert!(!dedup.is_duplicate(&particle("b", b"1", 60_000)));
+    }
+
+    #[test]
+    fn keeps_capacity() {
+        let dedup = ParticleDedup::new(2);
+
+        assert!(!dedup.is_duplicate(&particle("expired", b"1", 0)));
+        assert!(!dedup.is_duplicate(&particle("a", b"1", 60_000)));
+        assert!(!dedup.is_duplicate(&particle("b", b"1", 60_000)));
+        assert_eq!(dedup.seen.lock().len(), 2);
+        assert!(dedup.is_duplicate(&particle("a", b"1", 60_000)));
+        assert!(dedup.is_duplicate(&particle("b", b"1", 60_000)));
+    }
+}
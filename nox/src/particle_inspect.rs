@@ -0,0 +1,482 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `nox particle inspect` decodes a particle from a log capture or a traffic dump,
+//! verifies its signature and pretty-prints it. `nox particle capture` lists messages
+//! recorded by the `capture` builtin.
+
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use clap::{Parser, Subcommand, ValueEnum};
+use connection_pool::{CaptureDirection, CaptureRecord, CAPTURE_FILE_NAME};
+use eyre::{eyre, WrapErr};
+use fluence_keypair::PublicKey;
+use libp2p::PeerId;
+use particle_protocol::{FluenceCodec, Particle, ProtocolMessage};
+
+#[derive(Parser, Debug)]
+#[command(name = "nox particle", about = "Debugging tools for particles")]
+struct ParticleArgs {
+    #[command(subcommand)]
+    command: ParticleCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum ParticleCommand {
+    /// Decode, verify and pretty-print a particle
+    Inspect {
+        /// Path to a file with the particle, or the particle itself. Accepts JSON, wire frames
+        /// with or without the length prefix, and base64 of any of those
+        input: String,
+        /// Unix timestamp in milliseconds to compute remaining TTL against, defaults to now
+        #[arg(long)]
+        now: Option<u64>,
+    },
+    /// List messages of a protocol capture
+    Capture {
+        /// Capture file, or the capture directory to read all rotated files from, oldest first
+        path: String,
+        /// Show only messages exchanged with that peer
+        #[arg(long)]
+        peer: Option<String>,
+        /// Show only messages of that particle
+        #[arg(long)]
+        particle_id: Option<String>,
+        #[arg(long, value_enum)]
+        direction: Option<Direction>,
+        /// Print every particle in full, as `inspect` does
+        #[arg(long)]
+        full: bool,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Direction {
+    In,
+    Out,
+}
+
+impl From<CaptureDirection> for Direction {
+    fn from(direction: CaptureDirection) -> Self {
+        match direction {
+            CaptureDirection::In => Direction::In,
+            CaptureDirection::Out => Direction::Out,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CaptureFilter {
+    peer: Option<String>,
+    particle_id: Option<String>,
+    direction: Option<Direction>,
+}
+
+impl CaptureFilter {
+    fn matches(&self, record: &CaptureRecord) -> bool {
+        let particle_id = match &record.message {
+            ProtocolMessage::Particle(particle) => Some(particle.id.as_str()),
+            ProtocolMessage::Upgrade => None,
+        };
+        self.peer.as_ref().map_or(true, |p| p == &record.peer_id)
+            && self
+                .particle_id
+                .as_deref()
+                .map_or(true, |id| Some(id) == particle_id)
+            && self
+                .direction
+                .map_or(true, |d| d == Direction::from(record.direction))
+    }
+}
+
+/// Entrypoint of `nox particle`, `args` start with the subcommand name
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = ParticleArgs::parse_from(args);
+    match args.command {
+        ParticleCommand::Inspect { input, now } => {
+            let bytes = load_input(&input)?;
+            let particle = decode(&bytes)?;
+            let now = now.unwrap_or_else(now_ms);
+            print!("{}", inspect(&particle, now));
+            Ok(())
+        }
+        ParticleCommand::Capture {
+            path,
+            peer,
+            particle_id,
+            direction,
+            full,
+        } => {
+            let filter = CaptureFilter {
+                peer,
+                particle_id,
+                direction,
+            };
+            for file in capture_files(Path::new(&path))? {
+                let content = std::fs::read_to_string(&file)
+                    .wrap_err_with(|| format!("error reading {}", file.display()))?;
+                for record in parse_capture(&content)? {
+                    if filter.matches(&record) {
+                        print!("{}", render_record(&record, full));
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Capture files in the order they were written
+fn capture_files(path: &Path) -> eyre::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut rotated = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if let Some(n) = name
+            .strip_prefix(CAPTURE_FILE_NAME)
+            .and_then(|suffix| suffix.strip_prefix('.'))
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            rotated.push(n);
+        }
+    }
+    // higher numbers are older
+    rotated.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut files: Vec<_> = rotated
+        .into_iter()
+        .map(|n| path.join(format!("{CAPTURE_FILE_NAME}.{n}")))
+        .collect();
+    let current = path.join(CAPTURE_FILE_NAME);
+    if current.exists() {
+        files.push(current);
+    }
+    Ok(files)
+}
+
+fn parse_capture(content: &str) -> eyre::Result<Vec<CaptureRecord>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).wrap_err_with(|| format!("invalid record at line {}", i + 1))
+        })
+        .collect()
+}
+
+/// One line per record, followed by the full particle report if `full` is set
+fn render_record(record: &CaptureRecord, full: bool) -> String {
+    let direction = match record.direction {
+        CaptureDirection::In => "<-",
+        CaptureDirection::Out => "->",
+    };
+    let mut out = format!("{} {direction} {} ", record.timestamp, record.peer_id);
+    match &record.message {
+        ProtocolMessage::Particle(particle) => {
+            let _ = writeln!(
+                out,
+                "particle {} init_peer_id {} ttl {} ms data {} bytes",
+                particle.id,
+                particle.init_peer_id,
+                particle.ttl,
+                particle.data.len()
+            );
+            if full {
+                for line in inspect(particle, record.timestamp).lines() {
+                    let _ = writeln!(out, "    {line}");
+                }
+            }
+        }
+        ProtocolMessage::Upgrade => out.push_str("upgrade\n"),
+    }
+    out
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+fn load_input(input: &str) -> eyre::Result<Vec<u8>> {
+    let path = Path::new(input);
+    if path.is_file() {
+        std::fs::read(path).wrap_err_with(|| format!("error reading {}", path.display()))
+    } else {
+        Ok(input.as_bytes().to_vec())
+    }
+}
+
+/// Decodes a particle from any of the supported formats
+pub fn decode(bytes: &[u8]) -> eyre::Result<Particle> {
+    if let Some(particle) = decode_raw(bytes) {
+        return Ok(particle);
+    }
+
+    // base64 is whitespace-sensitive, and dumps usually end with a newline
+    let text = std::str::from_utf8(bytes)
+        .map(str::trim)
+        .unwrap_or_default();
+    if let Some(particle) = base64
+        .decode(text)
+        .ok()
+        .and_then(|decoded| decode_raw(&decoded))
+    {
+        return Ok(particle);
+    }
+
+    Err(eyre!(
+        "input is neither a particle in JSON, nor a wire frame, nor base64 of those"
+    ))
+}
+
+fn decode_raw(bytes: &[u8]) -> Option<Particle> {
+    let message = serde_json::from_slice::<ProtocolMessage>(bytes)
+        .ok()
+        .or_else(|| FluenceCodec::decode_frame(bytes).ok())
+        .or_else(|| strip_length_prefix(bytes).and_then(|f| FluenceCodec::decode_frame(f).ok()));
+    match message {
+        Some(ProtocolMessage::Particle(particle)) => Some(particle),
+        Some(ProtocolMessage::Upgrade) => None,
+        None => serde_json::from_slice::<Particle>(bytes).ok(),
+    }
+}
+
+/// Strips the unsigned varint length prefix if it matches the length of the rest of the frame
+fn strip_length_prefix(bytes: &[u8]) -> Option<&[u8]> {
+    let mut length: usize = 0;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        length |= ((byte & 0x7f) as usize).checked_shl(7 * i as u32)?;
+        if byte & 0x80 == 0 {
+            let frame = &bytes[i + 1..];
+            return (frame.len() == length).then_some(frame);
+        }
+    }
+    None
+}
+
+/// Renders a human-readable report about the particle
+pub fn inspect(particle: &Particle, now: u64) -> String {
+    let mut out = String::new();
+    // writing to a String can't fail
+    let _ = render(&mut out, particle, now);
+    out
+}
+
+fn render(out: &mut String, particle: &Particle, now: u64) -> std::fmt::Result {
+    writeln!(out, "id:           {}", particle.id)?;
+    writeln!(
+        out,
+        "init_peer_id: {}",
+        describe_peer(&particle.init_peer_id)
+    )?;
+    writeln!(out, "timestamp:    {}", particle.timestamp)?;
+    writeln!(out, "ttl:          {} ms", particle.ttl)?;
+    writeln!(out, "expires:      {}", describe_deadline(particle, now))?;
+    match particle.verify() {
+        Ok(()) => writeln!(out, "signature:    valid")?,
+        Err(err) => writeln!(out, "signature:    INVALID ({err})")?,
+    }
+
+    let peers: BTreeSet<_> = mentioned_peers(&particle.script)
+        .chain(mentioned_peers(&String::from_utf8_lossy(&particle.data)))
+        .filter(|p| p != &particle.init_peer_id)
+        .collect();
+    if !peers.is_empty() {
+        writeln!(out, "peers:")?;
+        for peer in peers {
+            writeln!(out, "  {}", describe_peer(&peer))?;
+        }
+    }
+
+    writeln!(out, "script:")?;
+    for line in particle.script.lines() {
+        writeln!(out, "  {line}")?;
+    }
+
+    writeln!(out, "data:         {} bytes", particle.data.len())?;
+    let data = &particle.data;
+    let pretty = serde_json::from_slice::<serde_json::Value>(data)
+        .ok()
+        .and_then(|v| serde_json::to_string_pretty(&v).ok());
+    match pretty {
+        Some(json) => {
+            for line in json.lines() {
+                writeln!(out, "  {line}")?;
+            }
+        }
+        None if !data.is_empty() => writeln!(out, "  {}", base64.encode(data))?,
+        None => {}
+    }
+
+    Ok(())
+}
+
+fn describe_peer(peer_id: &PeerId) -> String {
+    match PublicKey::try_from(*peer_id) {
+        Ok(pk) => format!("{peer_id} ({:?} key)", pk.get_key_format()),
+        Err(_) => format!("{peer_id} (hashed key)"),
+    }
+}
+
+fn describe_deadline(particle: &Particle, now: u64) -> String {
+    let Some(deadline) = particle.deadline() else {
+        return "never, timestamp + ttl overflows".to_string();
+    };
+    if deadline >= now {
+        format!("in {} ms, at {deadline}", deadline - now)
+    } else {
+        format!("EXPIRED {} ms ago, at {deadline}", now - deadline)
+    }
+}
+
+/// Peer ids found among the words of `text`
+fn mentioned_peers(text: &str) -> impl Iterator<Item = PeerId> + '_ {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| word.len() >= 46)
+        .filter_map(|word| PeerId::from_str(word).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD as base64, Engine};
+    use fluence_keypair::KeyPair;
+    use particle_protocol::{Particle, ProtocolMessage};
+
+    use connection_pool::{CaptureDirection, CaptureRecord};
+
+    use super::{
+        decode, inspect, parse_capture, render_record, strip_length_prefix, CaptureFilter,
+        Direction,
+    };
+
+    fn particle(script: String) -> (Particle, KeyPair) {
+        let keypair = KeyPair::generate_ed25519();
+        let mut particle = Particle {
+            id: "particle-1".to_string(),
+            init_peer_id: keypair.get_peer_id(),
+            timestamp: 1_000,
+            ttl: 500,
+            script,
+            signature: vec![],
+            data: br#"{"trace":[]}"#.to_vec(),
+            trail: None,
+            progress: false,
+            hops: 0,
+        };
+        particle.sign(&keypair).unwrap();
+        (particle, keypair)
+    }
+
+    #[test]
+    fn decodes_json_and_base64() {
+        let (particle, _) = particle("(null)".to_string());
+        let json = serde_json::to_vec(&ProtocolMessage::Particle(particle.clone())).unwrap();
+
+        assert_eq!(decode(&json).unwrap(), particle);
+        let encoded = format!("{}\n", base64.encode(&json));
+        assert_eq!(decode(encoded.as_bytes()).unwrap(), particle);
+        assert!(decode(b"not a particle").is_err());
+    }
+
+    #[test]
+    fn strips_matching_length_prefix_only() {
+        assert_eq!(strip_length_prefix(&[3, 1, 2, 3]), Some(&[1u8, 2, 3][..]));
+        assert_eq!(strip_length_prefix(&[4, 1, 2, 3]), None);
+    }
+
+    #[test]
+    fn reports_signature_ttl_and_peers() {
+        let other = KeyPair::generate_ed25519().get_peer_id();
+        let (mut particle, _) = particle(format!(r#"(call "{other}" ("op" "noop") [])"#));
+
+        let report = inspect(&particle, 1_200);
+        assert!(report.contains("signature:    valid"), "{report}");
+        assert!(report.contains("in 300 ms, at 1500"), "{report}");
+        assert!(
+            report.contains(&format!("  {other} (Ed25519 key)")),
+            "{report}"
+        );
+        assert!(report.contains(r#""trace": []"#), "{report}");
+
+        particle.ttl = 100;
+        let report = inspect(&particle, 1_200);
+        assert!(report.contains("signature:    INVALID"), "{report}");
+        assert!(report.contains("EXPIRED 100 ms ago, at 1100"), "{report}");
+    }
+
+    #[test]
+    fn filters_and_renders_capture() {
+        let (particle, _) = particle("(null)".to_string());
+        let peer = KeyPair::generate_ed25519().get_peer_id().to_base58();
+        let records = [
+            CaptureRecord {
+                timestamp: 1_000,
+                direction: CaptureDirection::In,
+                peer_id: peer.clone(),
+                message: ProtocolMessage::Particle(particle.clone()),
+            },
+            CaptureRecord {
+                timestamp: 1_001,
+                direction: CaptureDirection::Out,
+                peer_id: peer.clone(),
+                message: ProtocolMessage::Upgrade,
+            },
+        ];
+        let content = records
+            .iter()
+            .map(|r| serde_json::to_string(r).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let records = parse_capture(&content).unwrap();
+        assert_eq!(records.len(), 2);
+
+        let filter = CaptureFilter {
+            particle_id: Some(particle.id.clone()),
+            ..<_>::default()
+        };
+        assert!(filter.matches(&records[0]));
+        assert!(!filter.matches(&records[1]));
+        let filter = CaptureFilter {
+            direction: Some(Direction::Out),
+            ..<_>::default()
+        };
+        assert!(!filter.matches(&records[0]));
+
+        let line = render_record(&records[0], false);
+        assert!(
+            line.starts_with(&format!("1000 <- {peer} particle particle-1")),
+            "{line}"
+        );
+        let full = render_record(&records[0], true);
+        assert!(full.contains("    signature:    valid"), "{full}");
+        assert!(parse_capture("{").is_err());
+    }
+}
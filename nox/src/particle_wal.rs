@@ -0,0 +1,280 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Write-ahead log of particles accepted by the dispatcher but not executed yet.
+//! After a crash the node replays the particles that haven't expired.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use particle_protocol::Particle;
+
+/// The log is rewritten with only the pending particles once it has this many records
+/// and most of them are done
+const COMPACTION_THRESHOLD: usize = 10_000;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    Accepted {
+        seq: u64,
+        particle: Particle,
+    },
+    /// Execution of the oldest pending particle with this id has finished
+    Done {
+        particle_id: String,
+    },
+}
+
+#[derive(Default)]
+struct Pending {
+    particles: BTreeMap<u64, Particle>,
+    by_id: HashMap<String, VecDeque<u64>>,
+    next_seq: u64,
+}
+
+impl Pending {
+    fn accept(&mut self, seq: u64, particle: Particle) {
+        self.by_id
+            .entry(particle.id.clone())
+            .or_default()
+            .push_back(seq);
+        self.particles.insert(seq, particle);
+        self.next_seq = self.next_seq.max(seq + 1);
+    }
+
+    fn done(&mut self, particle_id: &str) {
+        if let Some(seqs) = self.by_id.get_mut(particle_id) {
+            if let Some(seq) = seqs.pop_front() {
+                self.particles.remove(&seq);
+            }
+            if seqs.is_empty() {
+                self.by_id.remove(particle_id);
+            }
+        }
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::Accepted { seq, particle } => self.accept(seq, particle),
+            Record::Done { particle_id } => self.done(&particle_id),
+        }
+    }
+}
+
+struct Inner {
+    file: File,
+    pending: Pending,
+    records: usize,
+}
+
+pub struct ParticleWal {
+    path: PathBuf,
+    /// Sync every record to disk, otherwise only a crash of the process (not of the host) is covered
+    sync: bool,
+    inner: Mutex<Inner>,
+}
+
+impl ParticleWal {
+    /// Opens the log at `path` and returns particles to replay, oldest first.
+    /// Expired particles and exact duplicates are dropped, and the log is compacted.
+    pub fn open(path: PathBuf, sync: bool) -> io::Result<(Self, Vec<Particle>)> {
+        let mut pending = Pending::default();
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for line in reader.lines() {
+                let line = line?;
+                match serde_json::from_str::<Record>(&line) {
+                    Ok(record) => pending.apply(record),
+                    // the last record could be cut short by a crash
+                    Err(err) => log::warn!("Skipping corrupted particle log record: {err}"),
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let replay: Vec<(u64, Particle)> = std::mem::take(&mut pending.particles)
+            .into_iter()
+            .filter(|(_, p)| !p.is_expired())
+            .filter(|(_, p)| seen.insert((p.id.clone(), p.signature.clone(), p.data.clone())))
+            .collect();
+
+        let next_seq = pending.next_seq;
+        let mut pending = Pending {
+            next_seq,
+            ..<_>::default()
+        };
+        for (seq, particle) in &replay {
+            pending.accept(*seq, particle.clone());
+        }
+        let file = write_compacted(&path, &pending)?;
+        let records = pending.particles.len();
+
+        let wal = Self {
+            path,
+            sync,
+            inner: Mutex::new(Inner {
+                file,
+                pending,
+                records,
+            }),
+        };
+        Ok((wal, replay.into_iter().map(|(_, p)| p).collect()))
+    }
+
+    /// Records a particle before it is sent for execution
+    pub fn accepted(&self, particle: &Particle) {
+        let mut inner = self.inner.lock();
+        let seq = inner.pending.next_seq;
+        let record = Record::Accepted {
+            seq,
+            particle: particle.clone(),
+        };
+        if let Err(err) = self.append(&mut inner, &record) {
+            log::warn!("Error writing particle {} to the log: {err}", particle.id);
+        }
+        inner.pending.accept(seq, particle.clone());
+    }
+
+    /// Records that a particle was executed (or failed), so it isn't replayed
+    pub fn done(&self, particle_id: &str) {
+        let mut inner = self.inner.lock();
+        if !inner.pending.by_id.contains_key(particle_id) {
+            return;
+        }
+        let record = Record::Done {
+            particle_id: particle_id.to_string(),
+        };
+        if let Err(err) = self.append(&mut inner, &record) {
+            log::warn!("Error writing particle {particle_id} to the log: {err}");
+        }
+        inner.pending.done(particle_id);
+
+        if inner.records > COMPACTION_THRESHOLD && inner.records > 2 * inner.pending.particles.len()
+        {
+            match write_compacted(&self.path, &inner.pending) {
+                Ok(file) => {
+                    inner.file = file;
+                    inner.records = inner.pending.particles.len();
+                }
+                Err(err) => log::warn!("Error compacting particle log: {err}"),
+            }
+        }
+    }
+
+    fn append(&self, inner: &mut Inner, record: &Record) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        inner.file.write_all(&line)?;
+        if self.sync {
+            inner.file.sync_data()?;
+        }
+        inner.records += 1;
+        Ok(())
+    }
+}
+
+/// Atomically replaces the log with records of pending particles, returns the file to append to
+fn write_compacted(path: &Path, pending: &Pending) -> io::Result<File> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        for (seq, particle) in &pending.particles {
+            let record = Record::Accepted {
+                seq: *seq,
+                particle: particle.clone(),
+            };
+            serde_json::to_writer(&mut file, &record)?;
+            file.write_all(b"\n")?;
+        }
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp, path)?;
+    OpenOptions::new().append(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::*;
+
+    fn particle(id: &str, data: &[u8], ttl: u32) -> Particle {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Particle {
+            id: id.to_string(),
+            // expired particles are a minute old
+            timestamp: now.as_millis() as u64 - if ttl == 0 { 60_000 } else { 0 },
+            ttl,
+            data: data.to_vec(),
+            ..<_>::default()
+        }
+    }
+
+    #[test]
+    fn replays_pending_particles() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("particles.wal");
+
+        let (wal, replay) = ParticleWal::open(path.clone(), false).unwrap();
+        assert!(replay.is_empty());
+        wal.accepted(&particle("a", b"1", 60_000));
+        wal.accepted(&particle("b", b"1", 60_000));
+        wal.accepted(&particle("a", b"2", 60_000));
+        wal.accepted(&particle("expired", b"1", 0));
+        wal.done("a");
+        drop(wal);
+
+        let (_, replay) = ParticleWal::open(path, false).unwrap();
+        let replay: Vec<_> = replay
+            .iter()
+            .map(|p| (p.id.as_str(), p.data.clone()))
+            .collect();
+        assert_eq!(replay, vec![("b", b"1".to_vec()), ("a", b"2".to_vec())]);
+    }
+
+    #[test]
+    fn dedups_and_survives_torn_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("particles.wal");
+
+        let (wal, _) = ParticleWal::open(path.clone(), true).unwrap();
+        let p = particle("a", b"1", 60_000);
+        wal.accepted(&p);
+        wal.accepted(&p);
+        drop(wal);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"op":"accepted","seq":7,"part"#)
+            .unwrap();
+
+        let (wal, replay) = ParticleWal::open(path.clone(), false).unwrap();
+        assert_eq!(replay.len(), 1);
+
+        // replayed particles stay pending until executed again
+        wal.done("a");
+        drop(wal);
+        let (_, replay) = ParticleWal::open(path, false).unwrap();
+        assert!(replay.is_empty());
+    }
+}
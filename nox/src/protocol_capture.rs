@@ -0,0 +1,153 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! `capture` builtin controls the capture of protocol messages for debugging.
+//!
+//! `capture.start(peers)` records messages exchanged with the given peers (or all peers, if the
+//! list is empty) into a rotating file, `capture.stop()` finishes it. Captures are inspected with
+//! `nox particle capture`. Only the host and the management peer can control the capture.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use connection_pool::{CaptureSettings, ConnectionPoolApi};
+use futures::FutureExt;
+use libp2p::PeerId;
+use particle_args::{Args, JError};
+use particle_builtins::{wrap, CustomService};
+use particle_execution::{ParticleParams, ServiceFunction};
+use serde_json::{json, Value as JValue};
+use server_config::ProtocolCaptureConfig;
+use thiserror::Error;
+use workers::PeerScopes;
+
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error("Forbidden. User id '{0}' cannot control protocol capture: only host and management peer can")]
+    Forbidden(PeerId),
+    #[error("Invalid peer id '{0}'")]
+    InvalidPeerId(String),
+    #[error("Failed to start protocol capture: {0}")]
+    Start(String),
+}
+
+pub struct ProtocolCapture {
+    connection_pool: ConnectionPoolApi,
+    dir: PathBuf,
+    config: ProtocolCaptureConfig,
+    scopes: PeerScopes,
+}
+
+impl ProtocolCapture {
+    pub fn new(
+        connection_pool: ConnectionPoolApi,
+        dir: PathBuf,
+        config: ProtocolCaptureConfig,
+        scopes: PeerScopes,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            connection_pool,
+            dir,
+            config,
+            scopes,
+        })
+    }
+
+    pub fn make_builtin(self: Arc<Self>) -> (String, CustomService) {
+        let start = {
+            let this = self.clone();
+            ServiceFunction::Immut(Box::new(move |args, params| {
+                let this = this.clone();
+                async move { wrap(this.start(args, params).await) }.boxed()
+            }))
+        };
+        let stop = {
+            let this = self.clone();
+            ServiceFunction::Immut(Box::new(move |_args, params| {
+                let this = this.clone();
+                async move { wrap(this.stop(params).await) }.boxed()
+            }))
+        };
+        let status = {
+            let this = self;
+            ServiceFunction::Immut(Box::new(move |_args, params| {
+                let this = this.clone();
+                async move { wrap(this.status(params).await) }.boxed()
+            }))
+        };
+        (
+            "capture".to_string(),
+            CustomService::new(
+                vec![("start", start), ("stop", stop), ("status", status)],
+                None,
+            ),
+        )
+    }
+
+    fn check_permissions(&self, params: &ParticleParams) -> Result<(), CaptureError> {
+        let init_peer_id = params.init_peer_id;
+        if self.scopes.is_host(init_peer_id) || self.scopes.is_management(init_peer_id) {
+            Ok(())
+        } else {
+            Err(CaptureError::Forbidden(init_peer_id))
+        }
+    }
+
+    /// capture.start(peers: []string) -> status
+    /// Replaces the capture in progress, if any
+    async fn start(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_permissions(&params)?;
+
+        let mut args = args.function_args.into_iter();
+        let peers: Vec<String> = Args::next("peers", &mut args)?;
+        let peers = peers
+            .into_iter()
+            .map(|p| PeerId::from_str(&p).map_err(|_| CaptureError::InvalidPeerId(p)))
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        let settings = CaptureSettings {
+            dir: self.dir.clone(),
+            peers,
+            max_file_size: self.config.max_file_size.as_u64(),
+            max_files: self.config.max_files,
+        };
+        self.connection_pool
+            .start_capture(settings)
+            .await
+            .map_err(CaptureError::Start)?;
+
+        Ok(json!(self.connection_pool.capture_status().await))
+    }
+
+    /// capture.stop() -> status
+    async fn stop(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_permissions(&params)?;
+
+        Ok(json!(self.connection_pool.stop_capture().await))
+    }
+
+    /// capture.status() -> status
+    async fn status(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_permissions(&params)?;
+
+        Ok(json!(self.connection_pool.capture_status().await))
+    }
+}
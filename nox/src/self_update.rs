@@ -0,0 +1,460 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Managed upgrade of the node binary.
+//!
+//! `self_update.download` fetches the manifest of the new binary from the configured URL,
+//! checks that it's signed by one of the trusted keys and that its version is newer than
+//! the running one, then fetches the binary, checks it against the hash from the manifest
+//! and stages it. `self_update.restart` stops the node gracefully and re-executes it from
+//! the staged binary with the same arguments. All the node state is persisted on disk,
+//! so the new binary picks it up on start.
+
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use eyre::{eyre, WrapErr};
+use fluence_keypair::{PublicKey, Signature};
+use futures::FutureExt;
+use libp2p::PeerId;
+use parking_lot::Mutex;
+use particle_args::JError;
+use particle_builtins::{wrap, wrap_unit, CustomService};
+use particle_execution::{ParticleParams, ServiceFunction};
+use serde::Deserialize;
+use serde_json::{json, Value as JValue};
+use server_config::{SelfUpdateConfig, WorkerEgressConfig};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::oneshot;
+use types::peer_scope::PeerScope;
+use workers::PeerScopes;
+
+const STAGED_BINARY_NAME: &str = "nox";
+/// Manifests are tiny, anything larger isn't a manifest
+const MAX_MANIFEST_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum SelfUpdateError {
+    #[error("Forbidden. User id '{0}' cannot update the node: only host and management peer can")]
+    Forbidden(PeerId),
+    #[error("Failed to download {url}: {err}")]
+    Download {
+        url: String,
+        #[source]
+        err: reqwest::Error,
+    },
+    #[error("Download from {url} is larger than {max_size} bytes")]
+    TooLarge { url: String, max_size: u64 },
+    #[error("Invalid manifest at {url}: {err}")]
+    InvalidManifest { url: String, err: String },
+    #[error("Manifest from {0} isn't signed by any of the trusted keys")]
+    InvalidSignature(String),
+    #[error("Version {version} from the manifest isn't newer than the running {current}")]
+    NotNewer { version: String, current: String },
+    #[error("Hash of the binary from {0} doesn't match the manifest")]
+    HashMismatch(String),
+    #[error("Failed to stage the binary at {path:?}: {err}")]
+    Stage {
+        path: PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+    #[error("No binary is staged, call self_update.download first")]
+    NothingStaged,
+    #[error("Restart is already in progress")]
+    AlreadyRestarting,
+    #[error("Egress policy of worker {worker_id} doesn't allow downloading from {url}")]
+    EgressDenied { worker_id: String, url: String },
+}
+
+/// Describes the binary at `download_url`, signed by a trusted key
+#[derive(Debug, Clone, Deserialize)]
+struct Manifest {
+    version: String,
+    /// Hex-encoded SHA-256 of the binary
+    sha256: String,
+    /// Hex-encoded signature of `"{version}\0{sha256}"`
+    signature: String,
+}
+
+impl Manifest {
+    fn signed_bytes(&self) -> Vec<u8> {
+        format!("{}\0{}", self.version, self.sha256).into_bytes()
+    }
+}
+
+pub struct SelfUpdate {
+    download_url: String,
+    manifest_url: String,
+    trusted_keys: Vec<(PeerId, PublicKey)>,
+    /// Version of the running binary, only newer ones are staged
+    current_version: String,
+    max_download_size: u64,
+    staging_dir: PathBuf,
+    scopes: PeerScopes,
+    egress: WorkerEgressConfig,
+    client: reqwest::Client,
+    staged: Mutex<Option<PathBuf>>,
+    restart_outlet: Mutex<Option<oneshot::Sender<PathBuf>>>,
+}
+
+impl SelfUpdate {
+    /// Returns the builtin and the receiver of restart requests with the path of the staged binary
+    pub fn new(
+        config: &SelfUpdateConfig,
+        current_version: &str,
+        staging_dir: PathBuf,
+        scopes: PeerScopes,
+        egress: WorkerEgressConfig,
+    ) -> eyre::Result<(Arc<Self>, oneshot::Receiver<PathBuf>)> {
+        let download_url = config
+            .download_url
+            .clone()
+            .ok_or_else(|| eyre!("self update download url isn't set"))?;
+        let manifest_url = config
+            .manifest_url
+            .clone()
+            .unwrap_or_else(|| format!("{download_url}.manifest"));
+        if config.trusted_keys.is_empty() {
+            return Err(eyre!("self update requires at least one trusted key"));
+        }
+        let trusted_keys = config
+            .trusted_keys
+            .iter()
+            .map(|key| {
+                let peer_id =
+                    PeerId::from_str(key).wrap_err_with(|| format!("invalid trusted key {key}"))?;
+                let public_key: PublicKey = peer_id.try_into().map_err(|err| {
+                    eyre!("trusted key {key} doesn't contain a public key: {err}")
+                })?;
+                Ok((peer_id, public_key))
+            })
+            .collect::<eyre::Result<_>>()?;
+        let client = reqwest::Client::builder()
+            .timeout(config.download_timeout)
+            .build()?;
+
+        let (restart_outlet, restart_inlet) = oneshot::channel();
+        let this = Self {
+            download_url,
+            manifest_url,
+            trusted_keys,
+            current_version: current_version.to_string(),
+            max_download_size: config.max_download_size.as_u64(),
+            staging_dir,
+            scopes,
+            egress,
+            client,
+            staged: Mutex::new(None),
+            restart_outlet: Mutex::new(Some(restart_outlet)),
+        };
+        Ok((Arc::new(this), restart_inlet))
+    }
+
+    pub fn make_builtin(self: Arc<Self>) -> (String, CustomService) {
+        let download = {
+            let this = self.clone();
+            ServiceFunction::Immut(Box::new(move |_args, params| {
+                let this = this.clone();
+                async move { wrap(this.download(params).await) }.boxed()
+            }))
+        };
+        let restart = {
+            let this = self;
+            ServiceFunction::Immut(Box::new(move |_args, params| {
+                let this = this.clone();
+                async move { wrap_unit(this.restart(params)) }.boxed()
+            }))
+        };
+        (
+            "self_update".to_string(),
+            CustomService::new(vec![("download", download), ("restart", restart)], None),
+        )
+    }
+
+    fn check_permissions(&self, params: &ParticleParams) -> Result<(), SelfUpdateError> {
+        let init_peer_id = params.init_peer_id;
+        if self.scopes.is_host(init_peer_id) || self.scopes.is_management(init_peer_id) {
+            Ok(())
+        } else {
+            Err(SelfUpdateError::Forbidden(init_peer_id))
+        }
+    }
+
+    /// self_update.download() -> {path, size, signer, version}
+    async fn download(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.check_permissions(&params)?;
+        self.check_egress(params.peer_scope)?;
+
+        let manifest = self.fetch(&self.manifest_url, MAX_MANIFEST_SIZE).await?;
+        let manifest: Manifest =
+            serde_json::from_slice(&manifest).map_err(|err| SelfUpdateError::InvalidManifest {
+                url: self.manifest_url.clone(),
+                err: err.to_string(),
+            })?;
+        let signer = verify(&manifest, &self.trusted_keys)
+            .ok_or_else(|| SelfUpdateError::InvalidSignature(self.manifest_url.clone()))?;
+        if !is_newer(&manifest.version, &self.current_version) {
+            return Err(SelfUpdateError::NotNewer {
+                version: manifest.version,
+                current: self.current_version.clone(),
+            }
+            .into());
+        }
+
+        // The binary is fetched only after the manifest is checked
+        let binary = self
+            .fetch(&self.download_url, self.max_download_size)
+            .await?;
+        if hex::encode(Sha256::digest(&binary)) != manifest.sha256.to_lowercase() {
+            return Err(SelfUpdateError::HashMismatch(self.download_url.clone()).into());
+        }
+
+        let path = stage(&self.staging_dir, &binary).map_err(|err| SelfUpdateError::Stage {
+            path: self.staging_dir.clone(),
+            err,
+        })?;
+        log::info!(
+            "Staged node binary {} from {} signed by {signer} at {path:?}",
+            manifest.version,
+            self.download_url
+        );
+        *self.staged.lock() = Some(path.clone());
+
+        Ok(json!({
+            "path": path.to_string_lossy(),
+            "size": binary.len(),
+            "signer": signer.to_base58(),
+            "version": manifest.version,
+        }))
+    }
+
+    /// self_update.restart()
+    /// Gracefully stops the node and starts the staged binary in its place
+    fn restart(&self, params: ParticleParams) -> Result<(), JError> {
+        self.check_permissions(&params)?;
+
+        let staged = self
+            .staged
+            .lock()
+            .clone()
+            .ok_or(SelfUpdateError::NothingStaged)?;
+        let outlet = self
+            .restart_outlet
+            .lock()
+            .take()
+            .ok_or(SelfUpdateError::AlreadyRestarting)?;
+        log::info!(
+            "Restart with the staged binary {staged:?} is requested by {}",
+            params.init_peer_id
+        );
+        outlet
+            .send(staged)
+            .map_err(|_| JError::new("node isn't waiting for restart requests"))?;
+        Ok(())
+    }
+
+    /// Downloads on workers are subject to their egress policy like any other outbound request
+    fn check_egress(&self, peer_scope: PeerScope) -> Result<(), SelfUpdateError> {
+        let PeerScope::WorkerId(worker_id) = peer_scope else {
+            return Ok(());
+        };
+        let worker_id = worker_id.to_string();
+        for url in [&self.download_url, &self.manifest_url] {
+            if !self.egress.allows_destination(&worker_id, url) {
+                return Err(SelfUpdateError::EgressDenied {
+                    worker_id,
+                    url: url.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the response in chunks, so a response larger than `max_size` is never buffered
+    async fn fetch(&self, url: &str, max_size: u64) -> Result<Vec<u8>, SelfUpdateError> {
+        let download = |err| SelfUpdateError::Download {
+            url: url.to_string(),
+            err,
+        };
+        let too_large = || SelfUpdateError::TooLarge {
+            url: url.to_string(),
+            max_size,
+        };
+
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(download)?;
+        if response.content_length().is_some_and(|len| len > max_size) {
+            return Err(too_large());
+        }
+        let mut body = vec![];
+        while let Some(chunk) = response.chunk().await.map_err(download)? {
+            if (body.len() + chunk.len()) as u64 > max_size {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+}
+
+/// Returns the peer id of the trusted key that signed the manifest
+fn verify(manifest: &Manifest, trusted_keys: &[(PeerId, PublicKey)]) -> Option<PeerId> {
+    let signature = hex::decode(&manifest.signature).ok()?;
+    let signed = manifest.signed_bytes();
+    trusted_keys.iter().find_map(|(peer_id, key)| {
+        let signature = Signature::from_bytes(key.get_key_format(), signature.clone());
+        key.verify(&signed, &signature).ok().map(|_| *peer_id)
+    })
+}
+
+/// Compares `major.minor.patch` versions, pre-release and build suffixes are ignored.
+/// Versions that can't be parsed are never newer.
+fn is_newer(version: &str, current: &str) -> bool {
+    fn parse(version: &str) -> Option<(u64, u64, u64)> {
+        let core = version.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|part| part.parse().ok());
+        let version = (parts.next()??, parts.next()??, parts.next()??);
+        parts.next().is_none().then_some(version)
+    }
+
+    match (parse(version), parse(current)) {
+        (Some(version), Some(current)) => version > current,
+        _ => false,
+    }
+}
+
+/// Write the binary to the staging dir and make it executable
+fn stage(staging_dir: &Path, binary: &[u8]) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(staging_dir)?;
+    let path = staging_dir.join(STAGED_BINARY_NAME);
+    // Write to a temporary file first, so a half-written binary is never staged
+    let tmp_path = staging_dir.join(format!("{STAGED_BINARY_NAME}.tmp"));
+    std::fs::write(&tmp_path, binary)?;
+    std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o755))?;
+    std::fs::rename(&tmp_path, &path)?;
+    Ok(path)
+}
+
+/// Put the staged binary in place of the current one, if the current location is writable,
+/// and replace the current process with it. Returns only on error.
+pub fn exec_staged(staged: PathBuf) -> eyre::Error {
+    let binary = match install(&staged) {
+        Ok(installed) => installed,
+        Err(err) => {
+            log::warn!("Can't replace the current binary, running the staged one: {err:?}");
+            staged
+        }
+    };
+    log::info!("Restarting from {binary:?}");
+    let err = std::process::Command::new(&binary)
+        .args(std::env::args_os().skip(1))
+        .exec();
+    eyre!("failed to exec {binary:?}: {err}")
+}
+
+/// Move the staged binary to the path of the current executable, keeping the previous one next to it
+fn install(staged: &Path) -> eyre::Result<PathBuf> {
+    let current = std::env::current_exe()?;
+    let previous = current.with_extension("prev");
+    std::fs::copy(&current, &previous)
+        .wrap_err_with(|| format!("failed to back up {current:?} to {previous:?}"))?;
+    std::fs::rename(staged, &current)
+        .wrap_err_with(|| format!("failed to move {staged:?} to {current:?}"))?;
+    Ok(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_keypair::KeyPair;
+
+    fn trusted(kp: &KeyPair) -> (PeerId, PublicKey) {
+        (kp.get_peer_id(), kp.public())
+    }
+
+    fn manifest(version: &str, binary: &[u8], kp: &KeyPair) -> Manifest {
+        let mut manifest = Manifest {
+            version: version.to_string(),
+            sha256: hex::encode(Sha256::digest(binary)),
+            signature: String::new(),
+        };
+        let signature = kp.sign(&manifest.signed_bytes()).unwrap();
+        manifest.signature = hex::encode(signature.to_vec());
+        manifest
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let trusted_kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+        let binary = b"new node binary";
+        let trusted_keys = vec![trusted(&other_kp), trusted(&trusted_kp)];
+
+        let signed = manifest("1.2.3", binary, &trusted_kp);
+        assert_eq!(
+            verify(&signed, &trusted_keys),
+            Some(trusted_kp.get_peer_id())
+        );
+
+        // the version can't be changed without breaking the signature, and neither can the hash
+        let mut tampered = signed.clone();
+        tampered.version = "9.9.9".to_string();
+        assert_eq!(verify(&tampered, &trusted_keys), None);
+        let mut tampered = signed;
+        tampered.sha256 = hex::encode(Sha256::digest(b"other binary"));
+        assert_eq!(verify(&tampered, &trusted_keys), None);
+
+        let untrusted_kp = KeyPair::generate_ed25519();
+        let untrusted = manifest("1.2.3", binary, &untrusted_kp);
+        assert_eq!(verify(&untrusted, &trusted_keys), None);
+    }
+
+    #[test]
+    fn test_only_newer_versions() {
+        assert!(is_newer("0.25.1", "0.25.0"));
+        assert!(is_newer("1.0.0", "0.99.99"));
+        assert!(is_newer("0.26.0-rc.1", "0.25.3"));
+        assert!(!is_newer("0.25.0", "0.25.0"));
+        assert!(!is_newer("0.24.9", "0.25.0"));
+        assert!(!is_newer("0.25", "0.24.0"));
+        assert!(!is_newer("latest", "0.24.0"));
+    }
+
+    #[test]
+    fn test_stage_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let staging_dir = dir.path().join("update");
+        let path = stage(&staging_dir, b"binary").unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"binary");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert!(!staging_dir.join("nox.tmp").exists());
+    }
+}
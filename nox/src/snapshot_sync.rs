@@ -0,0 +1,146 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Fast sync of provider and worker records on start.
+//! The node asks a designated peer for a signed snapshot of its records with a particle
+//! calling `providers.snapshot` there and `providers.apply_snapshot` back on the host.
+//! The particle is resent every `retry_interval` until a snapshot is applied.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use fluence_keypair::KeyPair;
+use fluence_libp2p::PeerId;
+use now_millis::now_ms;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span};
+use uuid_utils::uuid;
+
+use aquamarine::AquamarineApi;
+use particle_builtins::Builtins;
+use particle_protocol::{ExtendedParticle, Particle, ParticleError};
+
+use crate::Connectivity;
+
+pub struct SnapshotSync {
+    sync_from: PeerId,
+    retry_interval: Duration,
+    key_pair: KeyPair,
+    aquamarine_api: AquamarineApi,
+    builtins: Arc<Builtins<Connectivity>>,
+}
+
+impl SnapshotSync {
+    pub fn new(
+        sync_from: PeerId,
+        retry_interval: Duration,
+        key_pair: KeyPair,
+        aquamarine_api: AquamarineApi,
+        builtins: Arc<Builtins<Connectivity>>,
+    ) -> Self {
+        Self {
+            sync_from,
+            retry_interval,
+            key_pair,
+            aquamarine_api,
+            builtins,
+        }
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        tokio::task::Builder::new()
+            .name("snapshot-sync")
+            .spawn(self.run().in_current_span())
+            .expect("Could not spawn task")
+    }
+
+    async fn run(self) {
+        let mut retry = tokio::time::interval(self.retry_interval);
+        loop {
+            retry.tick().await;
+            if self.builtins.snapshot_synced() {
+                return;
+            }
+
+            log::info!("Requesting registry snapshot from {}", self.sync_from);
+            let particle = match sync_particle(self.sync_from, &self.key_pair, self.retry_interval)
+            {
+                Ok(particle) => particle,
+                Err(err) => {
+                    log::error!("Could not create registry snapshot particle: {}", err);
+                    return;
+                }
+            };
+            let particle = ExtendedParticle::new(particle, Span::current());
+            if let Err(err) = self.aquamarine_api.clone().execute(particle, None).await {
+                log::warn!("Could not request registry snapshot: {}", err);
+            }
+        }
+    }
+}
+
+/// Particle signed by the host, fetching a snapshot from `sync_from` and applying it on the host
+fn sync_particle(
+    sync_from: PeerId,
+    key_pair: &KeyPair,
+    ttl: Duration,
+) -> Result<Particle, ParticleError> {
+    let host = key_pair.get_peer_id();
+    let script = format!(
+        r#"
+        (seq
+            (call "{sync_from}" ("providers" "snapshot") [] snapshot)
+            (call "{host}" ("providers" "apply_snapshot") [snapshot])
+        )"#
+    );
+    let mut particle = Particle {
+        id: format!("snapshot_sync_{}", uuid()),
+        init_peer_id: host,
+        timestamp: now_ms() as u64,
+        ttl: u32::try_from(ttl.as_millis()).unwrap_or(u32::MAX),
+        script,
+        signature: vec![],
+        data: vec![],
+        trail: None,
+        progress: false,
+        hops: 0,
+    };
+    particle.sign(key_pair)?;
+    Ok(particle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_particle_is_signed_by_host() {
+        let key_pair = KeyPair::generate_ed25519();
+        let sync_from = PeerId::random();
+
+        let particle =
+            sync_particle(sync_from, &key_pair, Duration::from_secs(30)).expect("sign particle");
+
+        assert_eq!(particle.init_peer_id, key_pair.get_peer_id());
+        assert_eq!(particle.ttl, 30_000);
+        assert!(particle.script.contains(&format!(
+            r#"(call "{sync_from}" ("providers" "snapshot") [] snapshot)"#
+        )));
+        particle.verify().expect("particle must be signed");
+    }
+}
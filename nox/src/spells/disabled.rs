@@ -0,0 +1,74 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Spells are compiled out, the node runs as a relay
+
+use std::collections::HashMap;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::task::JoinHandle;
+
+use particle_builtins::CustomService;
+use server_config::ResolvedConfig;
+use spell_event_bus::api::SpellEventBusApi;
+
+use super::{SpellRuntime, SpellsContext};
+
+#[derive(Default)]
+pub struct Spells;
+
+impl Spells {
+    pub fn from_config(_config: &ResolvedConfig) -> eyre::Result<Self> {
+        Ok(Self)
+    }
+
+    pub(crate) async fn build(
+        self,
+        _context: SpellsContext<'_>,
+    ) -> eyre::Result<(Box<dyn SpellRuntime>, HashMap<String, CustomService>)> {
+        log::info!(
+            "Spells are compiled out, the node runs as a relay with the services runtime still enabled"
+        );
+        Ok((Box::new(NoSpells), HashMap::new()))
+    }
+}
+
+struct NoSpells;
+
+impl SpellRuntime for NoSpells {
+    fn spell_version(&self) -> String {
+        String::new()
+    }
+
+    fn system_service_versions(&self) -> Vec<(&'static str, &'static str)> {
+        vec![]
+    }
+
+    fn webhooks(&self) -> Option<SpellEventBusApi> {
+        None
+    }
+
+    fn activation(&self) -> BoxFuture<'static, eyre::Result<()>> {
+        futures::future::ok(()).boxed()
+    }
+
+    fn start(self: Box<Self>) -> Vec<JoinHandle<()>> {
+        vec![]
+    }
+}
@@ -0,0 +1,88 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Spells runtime of the node: the sorcerer executing spells, the event bus triggering them,
+//! system services and sinks of the spell values.
+//!
+//! The runtime is compiled in with the `spells` feature. Without it the node is a relay: it routes
+//! particles and serves Kademlia, and the node only talks to the runtime through [`SpellRuntime`].
+//!
+//! The relay isn't services-free yet: builtins are served by `particle-services`, so the services
+//! runtime stays linked and workers can still deploy services. Compiling it out needs a builtins
+//! set of its own for the relay and is left to a follow-up.
+
+#[cfg(not(feature = "spells"))]
+mod disabled;
+#[cfg(feature = "spells")]
+mod runtime;
+
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use tokio::task::JoinHandle;
+
+use aquamarine::AquamarineApi;
+use health::HealthCheckRegistry;
+use particle_builtins::Builtins;
+use peer_metrics::SpellMetrics;
+use server_config::ResolvedConfig;
+use spell_event_bus::api::SpellEventBusApi;
+use workers::{KeyStorage, PeerScopes, Workers};
+
+use crate::Connectivity;
+
+#[cfg(not(feature = "spells"))]
+pub use disabled::Spells;
+#[cfg(feature = "spells")]
+pub use runtime::Spells;
+
+/// Parts of the node the spells runtime is built on
+#[cfg_attr(not(feature = "spells"), allow(dead_code))]
+pub(crate) struct SpellsContext<'a> {
+    pub config: &'a ResolvedConfig,
+    pub builtins: &'a Arc<Builtins<Connectivity>>,
+    pub aquamarine_api: &'a AquamarineApi,
+    pub connectivity: &'a Connectivity,
+    pub workers: &'a Arc<Workers>,
+    pub key_storage: &'a Arc<KeyStorage>,
+    pub scopes: &'a PeerScopes,
+    pub health_registry: Option<&'a mut HealthCheckRegistry>,
+    pub metrics: Option<SpellMetrics>,
+}
+
+/// Spells runtime built from [`Spells`], started and stopped along with the node
+pub(crate) trait SpellRuntime: Send {
+    /// Version of the spell service, empty if spells are compiled out
+    fn spell_version(&self) -> String;
+
+    /// Names of the system services with their versions
+    fn system_service_versions(&self) -> Vec<(&'static str, &'static str)>;
+
+    /// Triggers spells by their webhooks, `None` if spells are compiled out
+    fn webhooks(&self) -> Option<SpellEventBusApi>;
+
+    /// Handle to the spells and services of the host
+    #[cfg(feature = "spells")]
+    fn handle(&self, connection_pool: connection_pool::ConnectionPoolApi) -> crate::NodeHandle;
+
+    /// Deploys system services and starts scheduling spells, run once the node is started
+    fn activation(&self) -> BoxFuture<'static, eyre::Result<()>>;
+
+    /// Spawns the tasks of the runtime, they're aborted when the node stops
+    fn start(self: Box<Self>) -> Vec<JoinHandle<()>>;
+}
@@ -0,0 +1,312 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Spells runtime compiled in with the `spells` feature
+
+mod partition_detector;
+mod peer_prober;
+mod resource_monitor;
+mod service_health;
+mod sinks;
+
+use std::collections::HashMap;
+
+use eyre::WrapErr;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use config_utils::to_peer_id;
+use connection_pool::{ConnectionPoolApi, ConnectionPoolT};
+use health::HealthCheck;
+use particle_builtins::CustomService;
+use particle_services::SpellKvWrite;
+use server_config::ResolvedConfig;
+use sorcerer::Sorcerer;
+use spell_event_bus::api::{
+    peer_events, KvChangeEvent, SpellEventBusApi, TriggerEvent, TriggerSource,
+};
+use spell_event_bus::bus::SpellEventBus;
+use spell_service_api::SpellServiceApi;
+use system_services::{Deployer, SystemServiceDistros};
+
+use super::{SpellRuntime, SpellsContext};
+use crate::api::NodeHandle;
+use partition_detector::{partition_events, PartitionHealth};
+use peer_prober::probe_events;
+use resource_monitor::resource_events;
+use service_health::health_events;
+use sinks::SpellSinks;
+
+/// System services to deploy and sources of the spell triggers besides the built-in ones
+pub struct Spells {
+    system_service_distros: SystemServiceDistros,
+    trigger_sources: Vec<Box<dyn TriggerSource>>,
+}
+
+impl Spells {
+    pub fn new(system_service_distros: SystemServiceDistros) -> Self {
+        Self {
+            system_service_distros,
+            trigger_sources: vec![],
+        }
+    }
+
+    /// Deploys the system services enabled in the config
+    pub fn from_config(config: &ResolvedConfig) -> eyre::Result<Self> {
+        let system_service_distros =
+            SystemServiceDistros::default_from(config.system_services.clone())
+                .wrap_err("Failed to get default system service distros")?;
+        Ok(Self::new(system_service_distros))
+    }
+
+    /// Spells can subscribe to the events of the source
+    pub fn with_trigger_source(mut self, source: Box<dyn TriggerSource>) -> Self {
+        self.trigger_sources.push(source);
+        self
+    }
+
+    /// Builds the runtime along with the spell builtins to add to the node
+    pub(crate) async fn build(
+        self,
+        context: SpellsContext<'_>,
+    ) -> eyre::Result<(Box<dyn SpellRuntime>, HashMap<String, CustomService>)> {
+        let SpellsContext {
+            config,
+            builtins,
+            aquamarine_api,
+            connectivity,
+            workers,
+            key_storage,
+            scopes,
+            mut health_registry,
+            metrics,
+        } = context;
+
+        let recv_connection_pool_events = connectivity.connection_pool.connection_events();
+        let sources = vec![peer_events(recv_connection_pool_events)];
+        let resource_sources = if config.resource_monitor_config.enabled {
+            vec![resource_events(
+                config.resource_monitor_config.clone(),
+                config.dir_config.services_persistent_dir.clone(),
+            )]
+        } else {
+            vec![]
+        };
+
+        let kv_sources = vec![builtins
+            .services
+            .kv_writes()
+            .map(|write| KvChangeEvent {
+                spell_id: write.spell_id,
+                key: write.key,
+                value: write.value,
+            })
+            .boxed()];
+
+        let probe_sources = if config.peer_probes_config.targets.is_empty() {
+            vec![]
+        } else {
+            vec![probe_events(
+                config.peer_probes_config.clone(),
+                connectivity.clone(),
+                builtins.services.clone(),
+            )]
+        };
+
+        let (spell_event_bus, spell_event_bus_api, spell_events_receiver) =
+            SpellEventBus::new(metrics.clone(), sources, resource_sources, kv_sources);
+        let health_sources = vec![health_events(
+            config.service_health_config.clone(),
+            builtins.services.clone(),
+            scopes.clone(),
+        )];
+        let partition_sources = if config.partition_detection_config.enabled {
+            let partition_health = PartitionHealth::default();
+            if let Some(registry) = health_registry.as_mut() {
+                registry.register("network_partition", partition_health.clone());
+            }
+            vec![partition_events(
+                config.partition_detection_config.clone(),
+                connectivity.connection_pool.lifecycle_events(),
+                partition_health,
+            )]
+        } else {
+            vec![]
+        };
+        if let Some(registry) = health_registry {
+            registry.register(
+                "spell_event_bus",
+                SpellEventBusHealth::new(spell_event_bus_api.clone()),
+            );
+        }
+        let spell_event_bus = spell_event_bus
+            .with_probe_sources(probe_sources)
+            .with_health_sources(health_sources)
+            .with_partition_sources(partition_sources);
+        let spell_event_bus = self
+            .trigger_sources
+            .into_iter()
+            .try_fold(spell_event_bus, |bus, source| {
+                bus.with_trigger_source(source)
+            })?;
+
+        let spell_service_api = SpellServiceApi::new(builtins.services.clone())
+            .with_max_kv_data_size(config.max_spell_data_size);
+        let (sorcerer, spell_builtins, spell_version) = Sorcerer::new(
+            builtins.services.clone(),
+            builtins.modules.clone(),
+            aquamarine_api.clone(),
+            config.clone(),
+            spell_event_bus_api.clone(),
+            workers.clone(),
+            key_storage.clone(),
+            scopes.clone(),
+            spell_service_api.clone(),
+            metrics,
+        )
+        .await;
+
+        let sinks = (!config.spell_sinks.is_empty()).then(|| {
+            let sinks = SpellSinks::new(
+                &config.spell_sinks,
+                spell_service_api.clone(),
+                scopes.clone(),
+                config.worker_egress_config.clone(),
+            );
+            (sinks, builtins.services.kv_writes())
+        });
+
+        let deployer = Deployer::new(
+            builtins.services.clone(),
+            builtins.modules.clone(),
+            sorcerer.spell_storage.clone(),
+            spell_event_bus_api.clone(),
+            spell_service_api,
+            scopes.get_host_peer_id(),
+            to_peer_id(&config.builtins_key_pair.clone().into()),
+            self.system_service_distros,
+        );
+
+        let runtime = SorcererRuntime {
+            sorcerer,
+            spell_event_bus,
+            spell_event_bus_api,
+            spell_events_receiver,
+            deployer,
+            spell_version,
+            sinks,
+        };
+        Ok((Box::new(runtime), spell_builtins))
+    }
+}
+
+struct SorcererRuntime {
+    sorcerer: Sorcerer,
+    spell_event_bus: SpellEventBus,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_events_receiver: mpsc::UnboundedReceiver<TriggerEvent>,
+    deployer: Deployer,
+    spell_version: String,
+    /// Forwards spell values to external sinks, set if any sinks are configured
+    sinks: Option<(SpellSinks, BoxStream<'static, SpellKvWrite>)>,
+}
+
+impl SpellRuntime for SorcererRuntime {
+    fn spell_version(&self) -> String {
+        self.spell_version.clone()
+    }
+
+    fn system_service_versions(&self) -> Vec<(&'static str, &'static str)> {
+        let versions = self.deployer.versions();
+        vec![
+            ("aqua_ipfs", versions.aqua_ipfs_version),
+            ("trust_graph", versions.trust_graph_version),
+            ("registry", versions.registry_version),
+            ("decider", versions.decider_version),
+        ]
+    }
+
+    fn webhooks(&self) -> Option<SpellEventBusApi> {
+        Some(self.spell_event_bus_api.clone())
+    }
+
+    fn handle(&self, connection_pool: ConnectionPoolApi) -> NodeHandle {
+        NodeHandle::new(&self.sorcerer, connection_pool)
+    }
+
+    fn activation(&self) -> BoxFuture<'static, eyre::Result<()>> {
+        let deployer = self.deployer.clone();
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        async move {
+            // Note: need to be after the start of the node to be able to subscribe spells
+            deployer
+                .deploy_system_services()
+                .await
+                .context("deploying system services failed")?;
+
+            spell_event_bus_api
+                .start_scheduling()
+                .await
+                .map_err(|e| eyre::eyre!("{e}"))
+                .context("running spell event bus failed")
+        }
+        .boxed()
+    }
+
+    fn start(self: Box<Self>) -> Vec<JoinHandle<()>> {
+        let Self {
+            sorcerer,
+            spell_event_bus,
+            spell_events_receiver,
+            sinks,
+            ..
+        } = *self;
+
+        let mut tasks = vec![];
+        if let Some((sinks, writes)) = sinks {
+            tasks.push(sinks.start(writes));
+        }
+        tasks.push(spell_event_bus.start());
+        tasks.push(sorcerer.start(spell_events_receiver));
+        tasks
+    }
+}
+
+#[derive(Clone)]
+struct SpellEventBusHealth {
+    api: SpellEventBusApi,
+}
+
+impl SpellEventBusHealth {
+    fn new(api: SpellEventBusApi) -> Self {
+        Self { api }
+    }
+}
+
+impl HealthCheck for SpellEventBusHealth {
+    fn status(&self) -> eyre::Result<()> {
+        if self.api.is_alive() {
+            Ok(())
+        } else {
+            Err(eyre::eyre!("Spell event bus has stopped"))
+        }
+    }
+}
@@ -0,0 +1,265 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use connection_pool::LifecycleEvent;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use health::HealthCheck;
+use libp2p::PeerId;
+use parking_lot::RwLock;
+use server_config::PartitionDetectionConfig;
+use spell_event_bus::api::{PartitionEvent, PartitionEventType};
+
+struct KnownPeer {
+    first_connected: Instant,
+    /// When the peer was disconnected, `None` while it's connected
+    disconnected_at: Option<Instant>,
+}
+
+/// Connection history of the peers the node has seen
+#[derive(Default)]
+struct PeerTracker {
+    peers: HashMap<PeerId, KnownPeer>,
+}
+
+impl PeerTracker {
+    fn on_event(&mut self, event: LifecycleEvent, now: Instant) {
+        match event {
+            LifecycleEvent::Connected(contact) => {
+                self.peers
+                    .entry(contact.peer_id)
+                    .and_modify(|peer| peer.disconnected_at = None)
+                    .or_insert(KnownPeer {
+                        first_connected: now,
+                        disconnected_at: None,
+                    });
+            }
+            LifecycleEvent::Disconnected(contact) => {
+                if let Some(peer) = self.peers.get_mut(&contact.peer_id) {
+                    peer.disconnected_at.get_or_insert(now);
+                }
+            }
+        }
+    }
+
+    /// Forget peers which are gone for longer than `known_after`, so the usual churn
+    /// isn't mistaken for a partition
+    fn prune(&mut self, known_after: Duration, now: Instant) {
+        self.peers.retain(|_, peer| {
+            peer.disconnected_at
+                .map_or(true, |at| now.saturating_duration_since(at) < known_after)
+        });
+    }
+
+    /// Returns the number of unreachable long-known peers and the number of long-known peers
+    fn count(&self, known_after: Duration, now: Instant) -> (u32, u32) {
+        self.peers
+            .values()
+            .filter(|peer| now.saturating_duration_since(peer.first_connected) >= known_after)
+            .fold((0, 0), |(unreachable, known), peer| {
+                (
+                    unreachable + u32::from(peer.disconnected_at.is_some()),
+                    known + 1,
+                )
+            })
+    }
+}
+
+/// Reports a possible partition to `/health` while the fraction of unreachable long-known peers
+/// is above the threshold
+#[derive(Clone, Default)]
+pub struct PartitionHealth {
+    /// Unreachable and known peers of the ongoing partition
+    partition: Arc<RwLock<Option<(u32, u32)>>>,
+}
+
+impl HealthCheck for PartitionHealth {
+    fn status(&self) -> eyre::Result<()> {
+        match *self.partition.read() {
+            Some((unreachable, known)) => Err(eyre::eyre!(
+                "Possible network partition: {unreachable} of {known} long-known peers are unreachable"
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Decides whether the node is partitioned, publishing an event only when that changes
+struct Detector {
+    threshold: f64,
+    min_known_peers: u32,
+    health: PartitionHealth,
+    partitioned: bool,
+}
+
+impl Detector {
+    fn update(&mut self, unreachable: u32, known: u32, timestamp: u64) -> Option<PartitionEvent> {
+        let partitioned = known > 0
+            && known >= self.min_known_peers
+            && f64::from(unreachable) / f64::from(known) >= self.threshold;
+        *self.health.partition.write() = partitioned.then_some((unreachable, known));
+        if partitioned == self.partitioned {
+            return None;
+        }
+        self.partitioned = partitioned;
+        let status = if partitioned {
+            log::warn!(
+                "Possible network partition: {unreachable} of {known} long-known peers are unreachable"
+            );
+            PartitionEventType::Partitioned
+        } else {
+            log::info!("Network partition healed: {unreachable} of {known} long-known peers are unreachable");
+            PartitionEventType::Healed
+        };
+        Some(PartitionEvent {
+            status,
+            unreachable,
+            known,
+            timestamp,
+        })
+    }
+}
+
+/// Track connections of the peers from the connection pool and periodically evaluate
+/// how many of the long-known ones are unreachable.
+/// Emits an event each time a possible partition is detected or healed.
+pub fn partition_events(
+    config: PartitionDetectionConfig,
+    lifecycle_events: BoxStream<'static, LifecycleEvent>,
+    health: PartitionHealth,
+) -> BoxStream<'static, PartitionEvent> {
+    let interval =
+        tokio::time::interval_at(tokio::time::Instant::now() + config.period, config.period);
+    let detector = Detector {
+        threshold: config.threshold,
+        min_known_peers: config.min_known_peers as u32,
+        health,
+        partitioned: false,
+    };
+    let known_after = config.known_after;
+    futures::stream::unfold(
+        (
+            interval,
+            lifecycle_events.fuse(),
+            PeerTracker::default(),
+            detector,
+        ),
+        move |(mut interval, mut lifecycle_events, mut tracker, mut detector)| async move {
+            loop {
+                tokio::select! {
+                    Some(event) = lifecycle_events.next() => {
+                        tracker.on_event(event, Instant::now());
+                    },
+                    _ = interval.tick() => {
+                        let now = Instant::now();
+                        // Unreachable peers are kept during a partition until they are back
+                        if !detector.partitioned {
+                            tracker.prune(known_after, now);
+                        }
+                        let (unreachable, known) = tracker.count(known_after, now);
+                        if let Some(event) = detector.update(unreachable, known, now_millis::now_sec()) {
+                            return Some((event, (interval, lifecycle_events, tracker, detector)));
+                        }
+                    }
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use particle_protocol::Contact;
+
+    fn contact(peer_id: PeerId) -> Contact {
+        Contact::new(peer_id, vec![])
+    }
+
+    fn detector(health: PartitionHealth) -> Detector {
+        Detector {
+            threshold: 0.5,
+            min_known_peers: 3,
+            health,
+            partitioned: false,
+        }
+    }
+
+    #[test]
+    fn test_tracker_counts_long_known_peers() {
+        let known_after = Duration::from_secs(60);
+        let start = Instant::now();
+        let old = PeerId::random();
+        let gone = PeerId::random();
+        let new = PeerId::random();
+
+        let mut tracker = PeerTracker::default();
+        tracker.on_event(LifecycleEvent::Connected(contact(old)), start);
+        tracker.on_event(LifecycleEvent::Connected(contact(gone)), start);
+        tracker.on_event(
+            LifecycleEvent::Connected(contact(new)),
+            start + Duration::from_secs(90),
+        );
+        tracker.on_event(
+            LifecycleEvent::Disconnected(contact(gone)),
+            start + Duration::from_secs(100),
+        );
+
+        // the recently connected peer isn't long-known yet
+        let now = start + Duration::from_secs(120);
+        assert_eq!(tracker.count(known_after, now), (1, 2));
+
+        // the peer is gone for too long to be counted
+        let now = start + Duration::from_secs(200);
+        tracker.prune(known_after, now);
+        assert_eq!(tracker.count(known_after, now), (0, 2));
+
+        // a reconnected peer keeps the time it was first seen
+        tracker.on_event(LifecycleEvent::Disconnected(contact(old)), now);
+        tracker.on_event(LifecycleEvent::Connected(contact(old)), now);
+        assert_eq!(tracker.count(known_after, now), (0, 2));
+    }
+
+    #[test]
+    fn test_partition_is_edge_triggered() {
+        let health = PartitionHealth::default();
+        let mut detector = detector(health.clone());
+
+        // too few peers to tell anything
+        assert!(detector.update(2, 2, 1).is_none());
+        assert!(health.status().is_ok());
+
+        let event = detector.update(3, 4, 2).expect("partition detected");
+        assert_eq!(event.status, PartitionEventType::Partitioned);
+        assert_eq!((event.unreachable, event.known), (3, 4));
+        assert!(health.status().is_err());
+
+        // still partitioned, no new event
+        assert!(detector.update(2, 4, 3).is_none());
+
+        let event = detector.update(1, 4, 4).expect("partition healed");
+        assert_eq!(event.status, PartitionEventType::Healed);
+        assert!(health.status().is_ok());
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use particle_services::{ParticleAppServices, PeerScope};
+use server_config::PeerProbesConfig;
+use spell_event_bus::api::ProbeEvent;
+use types::address::{Address, Protocol};
+
+use crate::Connectivity;
+
+const PROBE_PARTICLE_ID: &str = "peer_probe";
+
+/// Remembers which targets were reachable on the last probe, so an event is published only
+/// when a target changes its reachability. Targets are considered reachable before the first probe.
+#[derive(Default)]
+struct Reachability {
+    unreachable: HashMap<String, bool>,
+}
+
+impl Reachability {
+    fn update(&mut self, target: &str, reachable: bool, timestamp: u64) -> Option<ProbeEvent> {
+        let was_unreachable = self
+            .unreachable
+            .insert(target.to_string(), !reachable)
+            .unwrap_or(false);
+        (was_unreachable == reachable).then(|| ProbeEvent {
+            target: target.to_string(),
+            reachable,
+            timestamp,
+        })
+    }
+}
+
+/// Dial the first peer on the path of the address, or look up the service on the host
+/// if the address is a bare service
+async fn probe(
+    address: &Address,
+    timeout: Duration,
+    connectivity: &Connectivity,
+    services: &ParticleAppServices,
+) -> bool {
+    let peer = address
+        .protocols()
+        .iter()
+        .find_map(|protocol| match protocol {
+            Protocol::Peer(id) | Protocol::Relay(id) | Protocol::Client(id) => Some(*id),
+            Protocol::Service(_) => None,
+        });
+    let reached = async {
+        match (peer, address.protocols()) {
+            (Some(peer), _) => connectivity
+                .resolve_contact(peer, PROBE_PARTICLE_ID, timeout)
+                .await
+                .is_some(),
+            (None, [Protocol::Service(service_id)]) => services
+                .to_service_id(PeerScope::Host, service_id.clone(), PROBE_PARTICLE_ID)
+                .await
+                .is_ok(),
+            (None, _) => false,
+        }
+    };
+    tokio::time::timeout(timeout, reached)
+        .await
+        .unwrap_or(false)
+}
+
+/// Periodically probe the must-reach targets, reporting every result to the metrics.
+/// Emits an event each time a target becomes unreachable or reachable again.
+pub fn probe_events(
+    config: PeerProbesConfig,
+    connectivity: Connectivity,
+    services: ParticleAppServices,
+) -> BoxStream<'static, ProbeEvent> {
+    let interval =
+        tokio::time::interval_at(tokio::time::Instant::now() + config.period, config.period);
+    let targets = config.targets;
+    let timeout = config.timeout;
+    futures::stream::unfold(
+        (interval, Reachability::default()),
+        move |(mut interval, mut reachability)| {
+            let targets = targets.clone();
+            let connectivity = connectivity.clone();
+            let services = services.clone();
+            async move {
+                interval.tick().await;
+                let results = futures::future::join_all(
+                    targets
+                        .iter()
+                        .map(|target| probe(target, timeout, &connectivity, &services)),
+                )
+                .await;
+                let timestamp = now_millis::now_sec();
+                let mut events = vec![];
+                for (target, reachable) in targets.iter().zip(results) {
+                    let target = target.to_string();
+                    if let Some(m) = &connectivity.metrics {
+                        m.observe_probe(&target, reachable);
+                    }
+                    if let Some(event) = reachability.update(&target, reachable, timestamp) {
+                        if event.reachable {
+                            log::info!("Probe target {target} is reachable again");
+                        } else {
+                            log::warn!("Probe target {target} is unreachable");
+                        }
+                        events.push(event);
+                    }
+                }
+                Some((futures::stream::iter(events), (interval, reachability)))
+            }
+        },
+    )
+    .flatten()
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reachability_is_edge_triggered() {
+        let mut reachability = Reachability::default();
+        assert!(reachability.update("peer:a", true, 1).is_none());
+
+        let event = reachability
+            .update("peer:a", false, 2)
+            .expect("target became unreachable");
+        assert!(!event.reachable);
+        assert_eq!(event.timestamp, 2);
+
+        // still unreachable, no new event
+        assert!(reachability.update("peer:a", false, 3).is_none());
+        // targets are tracked separately, the first probe failing is a change too
+        assert!(reachability.update("peer:b", false, 3).is_some());
+
+        let event = reachability
+            .update("peer:a", true, 4)
+            .expect("target became reachable");
+        assert!(event.reachable);
+    }
+}
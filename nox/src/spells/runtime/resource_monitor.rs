@@ -0,0 +1,160 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::path::{Path, PathBuf};
+
+use eyre::WrapErr;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use server_config::ResourceMonitorConfig;
+use spell_event_bus::api::{ResourceEvent, ResourceEventType};
+
+/// Remembers which resources are under pressure, so an event is published only
+/// when a resource goes below its threshold and not on every check while it stays there.
+#[derive(Default)]
+struct Pressure {
+    disk: bool,
+    memory: bool,
+}
+
+impl Pressure {
+    fn update(
+        &mut self,
+        resource: ResourceEventType,
+        available: u64,
+        total: u64,
+        threshold_percent: u8,
+    ) -> Option<ResourceEvent> {
+        let is_low = available.saturating_mul(100) < total.saturating_mul(threshold_percent as u64);
+        let under_pressure = match resource {
+            ResourceEventType::Disk => &mut self.disk,
+            ResourceEventType::Memory => &mut self.memory,
+        };
+        let was_low = std::mem::replace(under_pressure, is_low);
+        (is_low && !was_low).then_some(ResourceEvent {
+            resource,
+            available,
+            total,
+        })
+    }
+}
+
+/// Returns (available, total) bytes on the disk where `path` is located
+fn disk_space(path: &Path) -> eyre::Result<(u64, u64)> {
+    let stat = nix::sys::statvfs::statvfs(path)
+        .wrap_err_with(|| format!("failed to get disk stats for {}", path.display()))?;
+    let fragment_size = stat.fragment_size() as u64;
+    Ok((
+        stat.blocks_available() as u64 * fragment_size,
+        stat.blocks() as u64 * fragment_size,
+    ))
+}
+
+/// Returns (available, total) bytes of memory
+fn memory() -> eyre::Result<(u64, u64)> {
+    let info = sys_info::mem_info().wrap_err("failed to get memory info")?;
+    // sys_info reports memory in KiB
+    Ok((info.avail * 1024, info.total * 1024))
+}
+
+/// Periodically check free space on the services disk and available memory.
+/// Emits an event each time one of them goes below the configured threshold.
+pub fn resource_events(
+    config: ResourceMonitorConfig,
+    services_dir: PathBuf,
+) -> BoxStream<'static, ResourceEvent> {
+    let interval = tokio::time::interval(config.check_period);
+    let disk_threshold = config.disk_free_threshold_percent;
+    let memory_threshold = config.memory_available_threshold_percent;
+    futures::stream::unfold(
+        (interval, Pressure::default()),
+        move |(mut interval, mut pressure)| {
+            let services_dir = services_dir.clone();
+            async move {
+                interval.tick().await;
+                let mut events = vec![];
+                match disk_space(&services_dir) {
+                    Ok((available, total)) => events.extend(pressure.update(
+                        ResourceEventType::Disk,
+                        available,
+                        total,
+                        disk_threshold,
+                    )),
+                    Err(err) => log::warn!("Resource monitor: {err:?}"),
+                }
+                match memory() {
+                    Ok((available, total)) => events.extend(pressure.update(
+                        ResourceEventType::Memory,
+                        available,
+                        total,
+                        memory_threshold,
+                    )),
+                    Err(err) => log::warn!("Resource monitor: {err:?}"),
+                }
+                for event in &events {
+                    log::warn!(
+                        "Low {:?}: {} of {} bytes available",
+                        event.resource,
+                        event.available,
+                        event.total
+                    );
+                }
+                Some((futures::stream::iter(events), (interval, pressure)))
+            }
+        },
+    )
+    .flatten()
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_is_edge_triggered() {
+        let mut pressure = Pressure::default();
+        assert!(pressure
+            .update(ResourceEventType::Disk, 50, 100, 10)
+            .is_none());
+
+        let event = pressure
+            .update(ResourceEventType::Disk, 5, 100, 10)
+            .expect("disk went below the threshold");
+        assert_eq!(event.resource, ResourceEventType::Disk);
+        assert_eq!(event.available, 5);
+
+        // still low, no new event
+        assert!(pressure
+            .update(ResourceEventType::Disk, 4, 100, 10)
+            .is_none());
+        // memory is tracked separately
+        assert!(pressure
+            .update(ResourceEventType::Memory, 1, 100, 10)
+            .is_some());
+
+        // recovered and went low again
+        assert!(pressure
+            .update(ResourceEventType::Disk, 20, 100, 10)
+            .is_none());
+        assert!(pressure
+            .update(ResourceEventType::Disk, 9, 100, 10)
+            .is_some());
+    }
+}
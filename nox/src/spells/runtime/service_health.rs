@@ -0,0 +1,92 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use particle_services::{HealthChange, ParticleAppServices, RestartPolicy, ServiceHealthEvent};
+use server_config::ServiceHealthConfig;
+use spell_event_bus::api::{HealthEvent, HealthEventType};
+use workers::PeerScopes;
+
+fn to_bus_event(event: ServiceHealthEvent, scopes: &PeerScopes) -> HealthEvent {
+    let status = match event.change {
+        HealthChange::Unhealthy => HealthEventType::Unhealthy,
+        HealthChange::Healthy => HealthEventType::Healthy,
+        HealthChange::Restarted => HealthEventType::Restarted,
+        HealthChange::RestartsExhausted => HealthEventType::Exhausted,
+    };
+    HealthEvent {
+        service_id: event.service_id,
+        worker_id: scopes.to_peer_id(event.peer_scope).to_string(),
+        status,
+        error: event.error.unwrap_or_default(),
+        timestamp: event.timestamp,
+    }
+}
+
+/// Periodically run health checks of the services declaring a health function,
+/// emitting an event each time a service changes its health or is restarted.
+pub fn health_events(
+    config: ServiceHealthConfig,
+    services: ParticleAppServices,
+    scopes: PeerScopes,
+) -> BoxStream<'static, HealthEvent> {
+    let interval =
+        tokio::time::interval_at(tokio::time::Instant::now() + config.period, config.period);
+    let policy = RestartPolicy {
+        timeout: config.timeout,
+        max_restarts: config.max_restarts,
+        backoff: config.restart_backoff,
+        max_backoff: config.max_restart_backoff,
+    };
+    futures::stream::unfold(interval, move |mut interval| {
+        let services = services.clone();
+        let scopes = scopes.clone();
+        let policy = policy.clone();
+        async move {
+            interval.tick().await;
+            let events = services.check_health(&policy).await;
+            for event in &events {
+                match event.change {
+                    HealthChange::Unhealthy => log::warn!(
+                        "Service {} failed its health check: {}",
+                        event.service_id,
+                        event.error.as_deref().unwrap_or_default()
+                    ),
+                    HealthChange::Healthy => {
+                        log::info!("Service {} is healthy again", event.service_id)
+                    }
+                    HealthChange::Restarted => {}
+                    HealthChange::RestartsExhausted => log::error!(
+                        "Service {} keeps failing its health check after {} restarts",
+                        event.service_id,
+                        policy.max_restarts
+                    ),
+                }
+            }
+            let events = events
+                .into_iter()
+                .map(|event| to_bus_event(event, &scopes))
+                .collect::<Vec<_>>();
+            Some((futures::stream::iter(events), interval))
+        }
+    })
+    .flatten()
+    .boxed()
+}
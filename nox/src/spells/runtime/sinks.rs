@@ -0,0 +1,637 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Values written by spells to their KV keys are forwarded to external sinks like Kafka or NATS
+//! according to the routes the spells set with `spell.set_sinks`. Spells don't talk to the sinks
+//! themselves, so they don't need network access or sink credentials. Values of spells on
+//! workers are only sent to the sinks their egress policy allows.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use particle_services::SpellKvWrite;
+use serde::Serialize;
+use server_config::{SpellSinkConfig, SpellSinkTarget, WorkerEgressConfig};
+use sorcerer::{SinkRoute, SINK_ROUTES_KEY};
+use spell_service_api::{CallParams, SpellServiceApi};
+use thiserror::Error;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use types::peer_scope::PeerScope;
+use workers::PeerScopes;
+
+/// TTL of the calls loading the routes of a spell from its KV
+const LOAD_ROUTES_TTL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum SinkError {
+    #[error("Kafka error: {0}")]
+    Kafka(#[from] rskafka::client::error::Error),
+    #[error("NATS error: {0}")]
+    Nats(String),
+    #[error("Failed to serialize a record: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
+
+/// A value written by a spell, as it is sent to the sinks
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SinkRecord {
+    pub spell_id: String,
+    pub key: String,
+    pub value: String,
+    /// Unix time of the write in milliseconds
+    pub timestamp: u64,
+}
+
+pub trait SinkTransport: Send + Sync + 'static {
+    fn publish<'a>(&'a self, records: &'a [SinkRecord]) -> BoxFuture<'a, Result<(), SinkError>>;
+}
+
+type LoadRoutes = Box<dyn Fn(PeerScope, String) -> BoxFuture<'static, Vec<SinkRoute>> + Send>;
+
+pub struct SpellSinks {
+    sinks: Vec<(String, SpellSinkConfig, Arc<dyn SinkTransport>)>,
+    load_routes: LoadRoutes,
+    egress: WorkerEgressConfig,
+}
+
+impl SpellSinks {
+    pub fn new(
+        configs: &HashMap<String, SpellSinkConfig>,
+        spell_service_api: SpellServiceApi,
+        scopes: PeerScopes,
+        egress: WorkerEgressConfig,
+    ) -> Self {
+        let sinks = configs
+            .iter()
+            .map(|(name, config)| {
+                let transport: Arc<dyn SinkTransport> = match &config.target {
+                    SpellSinkTarget::Kafka {
+                        brokers,
+                        topic,
+                        partition,
+                    } => Arc::new(KafkaSink::new(brokers.clone(), topic.clone(), *partition)),
+                    SpellSinkTarget::Nats { url, subject } => {
+                        Arc::new(NatsSink::new(url.clone(), subject.clone()))
+                    }
+                };
+                (name.clone(), config.clone(), transport)
+            })
+            .collect();
+
+        let load_routes: LoadRoutes = Box::new(move |peer_scope, spell_id| {
+            let spell_service_api = spell_service_api.clone();
+            let params = CallParams::local(
+                peer_scope,
+                spell_id.clone(),
+                scopes.to_peer_id(peer_scope),
+                LOAD_ROUTES_TTL,
+            );
+            async move {
+                let routes = spell_service_api
+                    .get_string(params, SINK_ROUTES_KEY.to_string())
+                    .await;
+                match routes {
+                    Ok(Some(routes)) => parse_routes(&spell_id, &routes),
+                    Ok(None) => vec![],
+                    Err(err) => {
+                        tracing::warn!(%spell_id, "Failed to load sink routes of the spell: {err}");
+                        vec![]
+                    }
+                }
+            }
+            .boxed()
+        });
+
+        Self {
+            sinks,
+            load_routes,
+            egress,
+        }
+    }
+
+    pub fn start(self, writes: BoxStream<'static, SpellKvWrite>) -> JoinHandle<()> {
+        let mut outlets = HashMap::new();
+        let mut destinations = HashMap::new();
+        for (name, config, transport) in self.sinks {
+            destinations.insert(name.clone(), sink_destinations(&config.target));
+            let (outlet, inlet) = mpsc::channel(config.buffer_size.max(1));
+            let worker = SinkWorker {
+                name: name.clone(),
+                config,
+                transport,
+            };
+            tokio::task::Builder::new()
+                .name(&format!("spell-sink-{name}"))
+                .spawn(worker.run(inlet))
+                .expect("Could not spawn task");
+            outlets.insert(name, outlet);
+        }
+
+        let router = Router {
+            outlets,
+            routes: HashMap::new(),
+            load_routes: self.load_routes,
+            destinations,
+            egress: self.egress,
+        };
+        tokio::task::Builder::new()
+            .name("spell-sinks")
+            .spawn(router.run(writes))
+            .expect("Could not spawn task")
+    }
+}
+
+/// Addresses the sink connects to, checked against the egress policies of workers
+fn sink_destinations(target: &SpellSinkTarget) -> Vec<String> {
+    match target {
+        SpellSinkTarget::Kafka { brokers, .. } => brokers.clone(),
+        SpellSinkTarget::Nats { url, .. } => vec![url.clone()],
+    }
+}
+
+fn parse_routes(spell_id: &str, routes: &str) -> Vec<SinkRoute> {
+    serde_json::from_str(routes).unwrap_or_else(|err| {
+        tracing::warn!(%spell_id, "Failed to parse sink routes of the spell: {err}");
+        vec![]
+    })
+}
+
+/// Matches spell writes with the routes of the spells, which are loaded from the spell KVs
+/// on the first write of a spell and updated when the spell sets new routes
+struct Router {
+    outlets: HashMap<String, mpsc::Sender<SinkRecord>>,
+    routes: HashMap<String, Vec<SinkRoute>>,
+    load_routes: LoadRoutes,
+    /// Addresses of each sink, by sink name
+    destinations: HashMap<String, Vec<String>>,
+    egress: WorkerEgressConfig,
+}
+
+impl Router {
+    async fn run(mut self, mut writes: BoxStream<'static, SpellKvWrite>) {
+        while let Some(write) = writes.next().await {
+            self.on_write(write).await;
+        }
+    }
+
+    async fn on_write(&mut self, write: SpellKvWrite) {
+        if write.key == SINK_ROUTES_KEY {
+            let routes = parse_routes(&write.spell_id, &write.value);
+            self.routes.insert(write.spell_id, routes);
+            return;
+        }
+
+        if !self.routes.contains_key(&write.spell_id) {
+            let routes = (self.load_routes)(write.peer_scope, write.spell_id.clone()).await;
+            self.routes.insert(write.spell_id.clone(), routes);
+        }
+        self.dispatch(write);
+    }
+
+    /// Address of the sink the worker isn't allowed to reach, if any
+    fn denied_destination(&self, peer_scope: PeerScope, sink: &str) -> Option<&String> {
+        let PeerScope::WorkerId(worker_id) = peer_scope else {
+            return None;
+        };
+        let worker_id = worker_id.to_string();
+        self.destinations
+            .get(sink)?
+            .iter()
+            .find(|destination| !self.egress.allows_destination(&worker_id, destination))
+    }
+
+    fn dispatch(&self, write: SpellKvWrite) {
+        let routes = self.routes.get(&write.spell_id).into_iter().flatten();
+        let record = SinkRecord {
+            spell_id: write.spell_id.clone(),
+            key: write.key.clone(),
+            value: write.value.clone(),
+            timestamp: now_millis::now_ms() as u64,
+        };
+        for route in routes.filter(|route| route.matches(&write.key)) {
+            let Some(outlet) = self.outlets.get(&route.sink) else {
+                // the sink was removed from the config after the route was set
+                continue;
+            };
+            if let Some(denied) = self.denied_destination(write.peer_scope, &route.sink) {
+                tracing::warn!(
+                    spell_id = %write.spell_id,
+                    key = %write.key,
+                    "Sink {} isn't allowed by the egress policy of {:?}: {denied} can't be reached, the value is dropped",
+                    route.sink,
+                    write.peer_scope
+                );
+                continue;
+            }
+            if let Err(mpsc::error::TrySendError::Full(_)) = outlet.try_send(record.clone()) {
+                tracing::warn!(
+                    spell_id = %write.spell_id,
+                    key = %write.key,
+                    "Buffer of sink {} is full, the value is dropped",
+                    route.sink
+                );
+            }
+        }
+    }
+}
+
+/// Sends the records routed to a sink in batches
+struct SinkWorker {
+    name: String,
+    config: SpellSinkConfig,
+    transport: Arc<dyn SinkTransport>,
+}
+
+impl SinkWorker {
+    async fn run(self, mut records: mpsc::Receiver<SinkRecord>) {
+        let batch_size = self.config.batch_size.max(1);
+        while let Some(record) = records.recv().await {
+            let mut batch = vec![record];
+            let flush = tokio::time::sleep(self.config.flush_interval);
+            tokio::pin!(flush);
+            while batch.len() < batch_size {
+                tokio::select! {
+                    record = records.recv() => match record {
+                        Some(record) => batch.push(record),
+                        None => break,
+                    },
+                    _ = &mut flush => break,
+                }
+            }
+            self.send(batch).await;
+        }
+    }
+
+    async fn send(&self, batch: Vec<SinkRecord>) {
+        let mut backoff = self.config.retry_backoff;
+        let mut attempt = 0;
+        loop {
+            match self.transport.publish(&batch).await {
+                Ok(()) => return,
+                Err(err) if attempt < self.config.max_retries => {
+                    tracing::debug!(
+                        "Failed to send a batch to sink {}, retrying: {err}",
+                        self.name
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to send {} values to sink {} after {} retries, dropping them: {err}",
+                        batch.len(),
+                        self.name,
+                        self.config.max_retries
+                    );
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Produces records to a partition of a Kafka topic, keyed by the spell id and the KV key.
+/// The connection is established on the first batch and again after a failure.
+struct KafkaSink {
+    brokers: Vec<String>,
+    topic: String,
+    partition: i32,
+    client: Mutex<Option<Arc<rskafka::client::partition::PartitionClient>>>,
+}
+
+impl KafkaSink {
+    fn new(brokers: Vec<String>, topic: String, partition: i32) -> Self {
+        Self {
+            brokers,
+            topic,
+            partition,
+            client: Mutex::new(None),
+        }
+    }
+
+    async fn client(&self) -> Result<Arc<rskafka::client::partition::PartitionClient>, SinkError> {
+        let mut client = self.client.lock().await;
+        if let Some(client) = client.as_ref() {
+            return Ok(client.clone());
+        }
+        let partition_client = rskafka::client::ClientBuilder::new(self.brokers.clone())
+            .build()
+            .await?
+            .partition_client(
+                self.topic.clone(),
+                self.partition,
+                rskafka::client::partition::UnknownTopicHandling::Error,
+            )
+            .await?;
+        let partition_client = Arc::new(partition_client);
+        *client = Some(partition_client.clone());
+        Ok(partition_client)
+    }
+
+    async fn produce(&self, records: &[SinkRecord]) -> Result<(), SinkError> {
+        let client = self.client().await?;
+        let records = records
+            .iter()
+            .map(|record| {
+                Ok(rskafka::record::Record {
+                    key: Some(format!("{}/{}", record.spell_id, record.key).into_bytes()),
+                    value: Some(serde_json::to_vec(record)?),
+                    headers: BTreeMap::new(),
+                    timestamp: Utc
+                        .timestamp_millis_opt(record.timestamp as i64)
+                        .single()
+                        .unwrap_or_else(Utc::now),
+                })
+            })
+            .collect::<Result<Vec<_>, SinkError>>()?;
+        let result = client
+            .produce(
+                records,
+                rskafka::client::partition::Compression::NoCompression,
+            )
+            .await;
+        if let Err(err) = result {
+            self.client.lock().await.take();
+            return Err(err.into());
+        }
+        Ok(())
+    }
+}
+
+impl SinkTransport for KafkaSink {
+    fn publish<'a>(&'a self, records: &'a [SinkRecord]) -> BoxFuture<'a, Result<(), SinkError>> {
+        self.produce(records).boxed()
+    }
+}
+
+/// Publishes each record as a message to a NATS subject.
+/// The client is created on the first batch and reconnects by itself afterwards.
+struct NatsSink {
+    url: String,
+    subject: String,
+    client: Mutex<Option<async_nats::Client>>,
+}
+
+impl NatsSink {
+    fn new(url: String, subject: String) -> Self {
+        Self {
+            url,
+            subject,
+            client: Mutex::new(None),
+        }
+    }
+
+    async fn client(&self) -> Result<async_nats::Client, SinkError> {
+        let mut client = self.client.lock().await;
+        if let Some(client) = client.as_ref() {
+            return Ok(client.clone());
+        }
+        let connected = async_nats::connect(self.url.as_str())
+            .await
+            .map_err(|err| SinkError::Nats(err.to_string()))?;
+        *client = Some(connected.clone());
+        Ok(connected)
+    }
+
+    async fn send(&self, records: &[SinkRecord]) -> Result<(), SinkError> {
+        let client = self.client().await?;
+        for record in records {
+            let payload = serde_json::to_vec(record)?;
+            client
+                .publish(self.subject.clone(), payload.into())
+                .await
+                .map_err(|err| SinkError::Nats(err.to_string()))?;
+        }
+        client
+            .flush()
+            .await
+            .map_err(|err| SinkError::Nats(err.to_string()))
+    }
+}
+
+impl SinkTransport for NatsSink {
+    fn publish<'a>(&'a self, records: &'a [SinkRecord]) -> BoxFuture<'a, Result<(), SinkError>> {
+        self.send(records).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use parking_lot::Mutex as SyncMutex;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Fails the first `failures` batches and records the rest
+    #[derive(Default)]
+    struct MockSink {
+        failures: AtomicU32,
+        batches: SyncMutex<Vec<Vec<SinkRecord>>>,
+    }
+
+    impl SinkTransport for MockSink {
+        fn publish<'a>(
+            &'a self,
+            records: &'a [SinkRecord],
+        ) -> BoxFuture<'a, Result<(), SinkError>> {
+            async move {
+                let failed = self
+                    .failures
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |f| f.checked_sub(1))
+                    .is_ok();
+                if failed {
+                    return Err(SinkError::Nats("unavailable".to_string()));
+                }
+                self.batches.lock().push(records.to_vec());
+                Ok(())
+            }
+            .boxed()
+        }
+    }
+
+    fn config(batch_size: usize, max_retries: u32) -> SpellSinkConfig {
+        SpellSinkConfig {
+            target: SpellSinkTarget::Nats {
+                url: "nats://localhost:4222".to_string(),
+                subject: "spells".to_string(),
+            },
+            batch_size,
+            flush_interval: Duration::from_millis(50),
+            max_retries,
+            retry_backoff: Duration::from_millis(1),
+            buffer_size: 100,
+        }
+    }
+
+    fn write(spell_id: &str, key: &str, value: &str) -> SpellKvWrite {
+        SpellKvWrite {
+            peer_scope: PeerScope::Host,
+            spell_id: spell_id.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn record(key: &str, value: &str) -> SinkRecord {
+        SinkRecord {
+            spell_id: "spell".to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn routes_matching_writes() {
+        let (outlet, mut inlet) = mpsc::channel(10);
+        let mut router = Router {
+            outlets: HashMap::from([("kafka".to_string(), outlet)]),
+            routes: HashMap::new(),
+            load_routes: Box::new(|_, spell_id| {
+                let routes = if spell_id == "stored" {
+                    vec![SinkRoute {
+                        key: "volume".to_string(),
+                        sink: "kafka".to_string(),
+                    }]
+                } else {
+                    vec![]
+                };
+                async move { routes }.boxed()
+            }),
+            destinations: HashMap::new(),
+            egress: WorkerEgressConfig::default(),
+        };
+
+        let routes = json!([{"key": "price_*", "sink": "kafka"}]).to_string();
+        router
+            .on_write(write("spell", SINK_ROUTES_KEY, &routes))
+            .await;
+        router.on_write(write("spell", "price_usd", "10")).await;
+        router.on_write(write("spell", "volume", "5")).await;
+        router.on_write(write("other", "price_usd", "11")).await;
+        router.on_write(write("stored", "volume", "6")).await;
+
+        let first = inlet.recv().await.unwrap();
+        assert_eq!(
+            (first.spell_id.as_str(), first.key.as_str()),
+            ("spell", "price_usd")
+        );
+        let second = inlet.recv().await.unwrap();
+        assert_eq!(
+            (second.spell_id.as_str(), second.value.as_str()),
+            ("stored", "6")
+        );
+        assert!(inlet.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn drops_writes_denied_by_egress_policy() {
+        let (outlet, mut inlet) = mpsc::channel(10);
+        let denied = libp2p::PeerId::random();
+        let allowed = libp2p::PeerId::random();
+        let mut router = Router {
+            outlets: HashMap::from([("nats".to_string(), outlet)]),
+            routes: HashMap::new(),
+            load_routes: Box::new(|_, _| {
+                let routes = vec![SinkRoute {
+                    key: "price_*".to_string(),
+                    sink: "nats".to_string(),
+                }];
+                async move { routes }.boxed()
+            }),
+            destinations: HashMap::from([(
+                "nats".to_string(),
+                vec!["nats://10.0.0.1:4222".to_string()],
+            )]),
+            egress: WorkerEgressConfig {
+                default_allowed: Some(vec![]),
+                workers: HashMap::from([(allowed.to_base58(), vec!["10.0.0.1:4222".to_string()])]),
+                trusted_binaries: vec![],
+            },
+        };
+        let on_worker = |worker_id: libp2p::PeerId, value| SpellKvWrite {
+            peer_scope: PeerScope::WorkerId(worker_id.into()),
+            ..write("spell", "price_usd", value)
+        };
+
+        router.on_write(on_worker(denied, "1")).await;
+        router.on_write(on_worker(allowed, "2")).await;
+        router.on_write(write("spell", "price_usd", "3")).await;
+
+        assert_eq!(inlet.recv().await.unwrap().value, "2");
+        assert_eq!(inlet.recv().await.unwrap().value, "3");
+        assert!(inlet.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn sends_batches_with_retries() {
+        let sink = Arc::new(MockSink {
+            failures: AtomicU32::new(2),
+            ..<_>::default()
+        });
+        let worker = SinkWorker {
+            name: "nats".to_string(),
+            config: config(2, 3),
+            transport: sink.clone(),
+        };
+
+        let (outlet, inlet) = mpsc::channel(10);
+        for i in 0..3 {
+            outlet.send(record("key", &i.to_string())).await.unwrap();
+        }
+        drop(outlet);
+        worker.run(inlet).await;
+
+        let batches = sink.batches.lock();
+        let sizes: Vec<_> = batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![2, 1]);
+        assert_eq!(batches[1][0].value, "2");
+    }
+
+    #[tokio::test]
+    async fn drops_batch_after_retries() {
+        let sink = Arc::new(MockSink {
+            failures: AtomicU32::new(2),
+            ..<_>::default()
+        });
+        let worker = SinkWorker {
+            name: "nats".to_string(),
+            config: config(1, 1),
+            transport: sink.clone(),
+        };
+
+        let (outlet, inlet) = mpsc::channel(10);
+        outlet.send(record("key", "lost")).await.unwrap();
+        outlet.send(record("key", "sent")).await.unwrap();
+        drop(outlet);
+        worker.run(inlet).await;
+
+        let batches = sink.batches.lock();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0][0].value, "sent");
+    }
+}
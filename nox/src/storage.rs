@@ -0,0 +1,149 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! `nox storage` encrypts or decrypts persistent dirs of services with the configured data key.
+//! It migrates plaintext data after encryption is enabled, finishes a key rotation without
+//! waiting for services to be reloaded, and reverts the data to plaintext before encryption
+//! is disabled. The node must be stopped while it runs.
+
+use std::ffi::OsString;
+use std::path::Path;
+
+use clap::{Parser, Subcommand};
+use eyre::{eyre, WrapErr};
+
+use particle_services::{SealStats, StorageKeys};
+use server_config::load_config_with_args;
+
+#[derive(Parser, Debug)]
+#[command(name = "nox storage", about = "Encryption at rest of service storage")]
+struct StorageArgs {
+    #[command(subcommand)]
+    command: StorageCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum StorageCommand {
+    /// Encrypt plaintext data and re-encrypt data sealed with previous keys
+    Encrypt {
+        /// Arguments the node is started with, the config is loaded from them the same way
+        #[arg(last = true)]
+        node_args: Vec<OsString>,
+    },
+    /// Decrypt all data back to plaintext
+    Decrypt {
+        #[arg(last = true)]
+        node_args: Vec<OsString>,
+    },
+}
+
+/// Entrypoint of `nox storage`, `args` start with the subcommand name
+pub fn run(args: impl IntoIterator<Item = OsString>) -> eyre::Result<()> {
+    let args = StorageArgs::parse_from(args);
+    let (encrypt, node_args) = match args.command {
+        StorageCommand::Encrypt { node_args } => (true, node_args),
+        StorageCommand::Decrypt { node_args } => (false, node_args),
+    };
+
+    let raw_args = std::iter::once(OsString::from("nox"))
+        .chain(node_args)
+        .collect();
+    let config = load_config_with_args(raw_args, None)?.resolve()?;
+    let storage_encryption = config
+        .node_config
+        .storage_encryption
+        .as_ref()
+        .ok_or_else(|| eyre!("storage_encryption isn't configured"))?;
+    let keys = StorageKeys::from_hex(
+        &storage_encryption.data_key,
+        &storage_encryption.previous_keys,
+    )?;
+
+    let workdir = config_utils::workdir(&config.dir_config.services_persistent_dir);
+    let stats = process_services(&keys, &workdir, encrypt)?;
+    println!(
+        "{} {} files of {} services, {} files were already {}",
+        if encrypt { "Encrypted" } else { "Decrypted" },
+        stats.files.processed,
+        stats.services,
+        stats.files.skipped,
+        if encrypt { "encrypted" } else { "plaintext" },
+    );
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Stats {
+    services: usize,
+    files: SealStats,
+}
+
+/// Seals or unseals the persistent dir of every service in the workdir
+fn process_services(keys: &StorageKeys, workdir: &Path, encrypt: bool) -> eyre::Result<Stats> {
+    let mut stats = Stats::default();
+    if !workdir.exists() {
+        return Ok(stats);
+    }
+    let entries = std::fs::read_dir(workdir)
+        .wrap_err_with(|| format!("error reading services workdir {workdir:?}"))?;
+    for entry in entries {
+        let dir = entry?.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        let files = if encrypt {
+            keys.seal_dir(&dir)
+        } else {
+            keys.unseal_dir(&dir)
+        }
+        .wrap_err_with(|| format!("error processing {dir:?}"))?;
+        stats.services += 1;
+        stats.files.processed += files.processed;
+        stats.files.skipped += files.skipped;
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use particle_services::StorageKeys;
+
+    use super::process_services;
+
+    #[test]
+    fn encrypt_and_decrypt_services() {
+        let workdir = tempfile::tempdir().unwrap();
+        for service in ["spell", "service"] {
+            let dir = workdir.path().join(service).join("module");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("db"), service).unwrap();
+        }
+        let keys = StorageKeys::from_hex(&"01".repeat(32), &[]).unwrap();
+
+        let stats = process_services(&keys, workdir.path(), true).unwrap();
+        assert_eq!(stats.services, 2);
+        assert_eq!(stats.files.processed, 2);
+        let data = std::fs::read(workdir.path().join("spell/module/db")).unwrap();
+        assert!(StorageKeys::is_sealed(&data));
+
+        let stats = process_services(&keys, workdir.path(), false).unwrap();
+        assert_eq!(stats.files.processed, 2);
+        let data = std::fs::read(workdir.path().join("spell/module/db")).unwrap();
+        assert_eq!(data, b"spell");
+    }
+}
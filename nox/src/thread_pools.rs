@@ -0,0 +1,140 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Dedicated thread pools of the host peer for AVM executions and service calls.
+//! They keep spells and heavy scripts from occupying the threads of the main pool,
+//! which serves networking and particle routing.
+
+use std::sync::Arc;
+
+use cpu_utils::pinning::ThreadPinner;
+use cpu_utils::LogicalCoreId;
+use eyre::{ensure, WrapErr};
+use tokio::runtime::Runtime;
+
+use aquamarine::RootPools;
+use server_config::{ThreadPoolConfig, ThreadPoolsConfig};
+
+pub struct ThreadPools {
+    avm: Option<Runtime>,
+    effectors: Option<Runtime>,
+}
+
+impl ThreadPools {
+    pub fn new(
+        config: &ThreadPoolsConfig,
+        thread_pinner: Arc<dyn ThreadPinner>,
+    ) -> eyre::Result<Self> {
+        let build = |name: &str, pool: &Option<ThreadPoolConfig>| {
+            pool.as_ref()
+                .map(|pool| build_runtime(name, pool, thread_pinner.clone()))
+                .transpose()
+        };
+        Ok(Self {
+            avm: build("avm", &config.avm)?,
+            effectors: build("effectors", &config.effectors)?,
+        })
+    }
+
+    pub fn root_pools(&self) -> RootPools {
+        RootPools {
+            avm: self.avm.as_ref().map(Runtime::handle).cloned(),
+            functions: self.effectors.as_ref().map(Runtime::handle).cloned(),
+        }
+    }
+}
+
+impl Drop for ThreadPools {
+    /// Stops the pools without waiting for the tasks running on them,
+    /// as dropping a runtime blocks, which isn't allowed in async context
+    fn drop(&mut self) {
+        for runtime in [self.avm.take(), self.effectors.take()]
+            .into_iter()
+            .flatten()
+        {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Logical CPUs the threads of the pool are pinned to
+fn pool_cpus(pool: &ThreadPoolConfig) -> Vec<LogicalCoreId> {
+    pool.cpus.iter().copied().map(LogicalCoreId::new).collect()
+}
+
+fn build_runtime(
+    name: &str,
+    pool: &ThreadPoolConfig,
+    thread_pinner: Arc<dyn ThreadPinner>,
+) -> eyre::Result<Runtime> {
+    ensure!(pool.threads > 0, "{name} thread pool must have threads");
+    let cpus = pool_cpus(pool);
+    tracing::info!(
+        "Creating {name} thread pool of {} threads, pinned to cores: {:?}",
+        pool.threads,
+        cpus
+    );
+
+    tokio::runtime::Builder::new_multi_thread()
+        .thread_name(format!("{name}-pool"))
+        .worker_threads(pool.threads)
+        // AVM executions and function calls run on the blocking threads
+        .max_blocking_threads(pool.threads)
+        .enable_all()
+        .on_thread_start(move || {
+            if !cpus.is_empty() {
+                thread_pinner.pin_current_thread_to_cpuset(&cpus);
+            }
+        })
+        .build()
+        .wrap_err_with(|| format!("error creating {name} thread pool"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pools_are_created_only_if_configured() {
+        let config = ThreadPoolsConfig {
+            network: None,
+            avm: Some(ThreadPoolConfig {
+                threads: 2,
+                cpus: vec![],
+            }),
+            effectors: None,
+        };
+        let pools = ThreadPools::new(&config, Arc::new(test_utils::pinning::DUMMY)).unwrap();
+        let root_pools = pools.root_pools();
+        assert!(root_pools.avm.is_some());
+        assert!(root_pools.functions.is_none());
+
+        let spawned = root_pools.avm.unwrap().spawn_blocking(|| 42);
+        assert_eq!(futures::executor::block_on(spawned).unwrap(), 42);
+        drop(pools);
+
+        let empty = ThreadPoolsConfig {
+            effectors: Some(ThreadPoolConfig {
+                threads: 0,
+                cpus: vec![],
+            }),
+            ..config
+        };
+        assert!(ThreadPools::new(&empty, Arc::new(test_utils::pinning::DUMMY)).is_err());
+    }
+}
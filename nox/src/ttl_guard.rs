@@ -0,0 +1,195 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Guard against forwarding particles that are doomed to expire.
+//! A particle deadline is its timestamp plus TTL, so the time it spent in queues and
+//! being executed on previous hops and on this node is already deducted from what's left
+//! when the node is about to send it further. If less than `min_forward_ttl` is left,
+//! the particle would only consume resources of the next peers, so it isn't sent,
+//! and its initiator gets an explicit `("expired" particle_id) [peer_id remaining_ms]` call
+//! instead of waiting for the particle until timeout.
+
+use std::time::Duration;
+
+use fluence_keypair::KeyPair;
+use fluence_libp2p::PeerId;
+use now_millis::now_ms;
+
+use particle_protocol::Particle;
+use server_config::TtlGuardConfig;
+use workers::PeerScopes;
+
+const NOTICE_SUFFIX: &str = "_expired";
+
+pub struct TtlGuard {
+    min_forward_ttl: Duration,
+    notify_initiator: bool,
+    notice_ttl: Duration,
+    key_pair: KeyPair,
+    /// Tells if initiators are local peers, they aren't sent notices over the network
+    scopes: Option<PeerScopes>,
+}
+
+impl TtlGuard {
+    pub fn new(config: &TtlGuardConfig, key_pair: KeyPair) -> Self {
+        Self {
+            min_forward_ttl: config.min_forward_ttl,
+            notify_initiator: config.notify_initiator,
+            notice_ttl: config.notice_ttl,
+            key_pair,
+            scopes: None,
+        }
+    }
+
+    /// Don't send notices to the host and workers of this node
+    pub fn with_scopes(mut self, scopes: PeerScopes) -> Self {
+        self.scopes = Some(scopes);
+        self
+    }
+
+    /// Returns the remaining TTL of the particle at `now_ms` if it's too short to send it further
+    pub fn check(&self, particle: &Particle, now_ms: u128) -> Option<Duration> {
+        let remaining = particle.time_to_live_at(now_ms);
+        (remaining < self.min_forward_ttl).then_some(remaining)
+    }
+
+    /// Particle calling `("expired" particle_id) [peer_id remaining_ms]` on the initiator
+    /// of the dropped particle, signed by the current peer.
+    ///
+    /// Notices aren't made about notices, so they can't bounce between peers.
+    pub fn expired_notice(&self, particle: &Particle, remaining: Duration) -> Option<Particle> {
+        if !self.notify_initiator || particle.id.ends_with(NOTICE_SUFFIX) {
+            return None;
+        }
+        let current_peer_id = self.peer_id();
+        let local = self
+            .scopes
+            .as_ref()
+            .map(|s| s.scope(particle.init_peer_id).is_ok());
+        if particle.init_peer_id == current_peer_id || local == Some(true) {
+            return None;
+        }
+
+        // AIR string literals can't be escaped
+        let particle_id = particle.id.replace(['"', '\\'], "'");
+        let script = format!(
+            r#"(call "{}" ("expired" "{particle_id}") ["{current_peer_id}" {}])"#,
+            particle.init_peer_id,
+            remaining.as_millis()
+        );
+        let mut notice = Particle {
+            id: format!("{particle_id}{NOTICE_SUFFIX}"),
+            init_peer_id: current_peer_id,
+            timestamp: now_ms() as u64,
+            ttl: u32::try_from(self.notice_ttl.as_millis()).unwrap_or(u32::MAX),
+            script,
+            signature: vec![],
+            data: vec![],
+            trail: None,
+            progress: false,
+            hops: 0,
+        };
+        match notice.sign(&self.key_pair) {
+            Ok(()) => Some(notice),
+            Err(err) => {
+                tracing::warn!(
+                    particle_id = particle.id,
+                    "Could not sign expired notice particle: {}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    fn peer_id(&self) -> PeerId {
+        self.key_pair.get_peer_id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::RandomPeerId;
+
+    use super::*;
+
+    fn guard(min_forward_ttl: Duration) -> TtlGuard {
+        TtlGuard::new(
+            &TtlGuardConfig {
+                min_forward_ttl,
+                notify_initiator: true,
+                notice_ttl: Duration::from_secs(10),
+            },
+            KeyPair::generate_ed25519(),
+        )
+    }
+
+    fn particle(ttl: u32) -> Particle {
+        Particle {
+            id: "particle_1".to_string(),
+            init_peer_id: RandomPeerId::random(),
+            timestamp: now_ms() as u64,
+            ttl,
+            ..<_>::default()
+        }
+    }
+
+    #[test]
+    fn checks_remaining_ttl() {
+        let guard = guard(Duration::from_millis(500));
+
+        assert_eq!(guard.check(&particle(60_000), now_ms()), None);
+        let remaining = guard
+            .check(&particle(100), now_ms())
+            .expect("too little TTL left");
+        assert!(remaining <= Duration::from_millis(100));
+
+        // time spent on the node counts against the particle
+        let mut late = particle(1000);
+        late.timestamp -= 700;
+        assert!(guard.check(&late, now_ms()).is_some());
+
+        assert_eq!(guard(Duration::ZERO).check(&particle(100), now_ms()), None);
+    }
+
+    #[test]
+    fn notifies_initiator() {
+        let guard = guard(Duration::from_millis(500));
+        let particle = particle(100);
+
+        let notice = guard
+            .expired_notice(&particle, Duration::from_millis(42))
+            .expect("create notice");
+
+        assert_eq!(notice.id, "particle_1_expired");
+        assert_eq!(notice.init_peer_id, guard.peer_id());
+        assert_eq!(notice.ttl, 10_000);
+        assert_eq!(
+            notice.script,
+            format!(
+                r#"(call "{}" ("expired" "particle_1") ["{}" 42])"#,
+                particle.init_peer_id,
+                guard.peer_id()
+            )
+        );
+        notice.verify().expect("notice must be signed");
+
+        // no notices about notices
+        assert!(guard.expired_notice(&notice, Duration::ZERO).is_none());
+    }
+}
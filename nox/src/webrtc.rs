@@ -0,0 +1,87 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+
+use fluence_libp2p::WebRtcCertificate;
+use libp2p::core::multiaddr::Protocol;
+use libp2p::core::transport::TransportError;
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{Multiaddr, Swarm};
+use server_config::ResolvedConfig;
+
+/// Listens for WebRTC connections on the first free UDP port from the configured range
+/// and advertises the external address along with the certificate hash
+pub struct WebRtcListener {
+    listen_ip: IpAddr,
+    external_ip: Option<IpAddr>,
+    ports: RangeInclusive<u16>,
+    certhash: Protocol<'static>,
+}
+
+impl WebRtcListener {
+    pub fn new(config: &ResolvedConfig, certificate: &WebRtcCertificate) -> Self {
+        Self {
+            listen_ip: config.listen_config.listen_ip,
+            external_ip: config.external_address,
+            ports: config.webrtc_config.udp_ports(),
+            certhash: Protocol::Certhash(certificate.fingerprint().to_multihash()),
+        }
+    }
+
+    pub fn listen<B: NetworkBehaviour>(
+        &self,
+        swarm: &mut Swarm<B>,
+    ) -> Result<(), TransportError<io::Error>> {
+        let mut last_error = None;
+        for port in self.ports.clone() {
+            match swarm.listen_on(webrtc_multiaddr(self.listen_ip, port)) {
+                Ok(_) => {
+                    if let Some(external_ip) = self.external_ip {
+                        let mut external = webrtc_multiaddr(external_ip, port);
+                        external.push(self.certhash.clone());
+                        log::info!("WebRTC external address: {external}");
+                        swarm.add_external_address(external);
+                    }
+                    return Ok(());
+                }
+                Err(err) => {
+                    log::warn!("Can't listen for WebRTC connections on UDP port {port}: {err}");
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            TransportError::Other(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "WebRTC UDP port range is empty",
+            ))
+        }))
+    }
+}
+
+fn webrtc_multiaddr(ip: IpAddr, port: u16) -> Multiaddr {
+    let mut maddr = Multiaddr::from(ip);
+    maddr.push(Protocol::Udp(port));
+    maddr.push(Protocol::WebRTCDirect);
+    maddr
+}
@@ -22,6 +22,7 @@ use std::fmt::Debug;
 use std::ops::Try;
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -37,28 +38,42 @@ use JValue::Array;
 
 use connection_pool::{ConnectionPoolApi, ConnectionPoolT};
 use health::HealthCheckRegistry;
+use json_utils::serialized_size;
 use kademlia::{KademliaApi, KademliaApiT};
+use node_events::{EventFilter, EventLog};
 use now_millis::{now_ms, now_sec};
-use particle_args::{from_base58, Args, ArgsError, JError};
+use particle_args::{from_base58, Args, ArgsError, ErrorCode, JError};
 use particle_execution::{FunctionOutcome, ParticleParams, ServiceFunction};
 use particle_modules::{
-    AddBlueprint, EffectorsMode, ModuleConfig, ModuleRepository, NamedModuleConfig, WASIConfig,
+    AddBlueprint, BlueprintPackage, EffectorsMode, ModuleConfig, ModuleRepository,
+    NamedModuleConfig, WASIConfig,
 };
 use particle_protocol::Contact;
 use particle_services::{
-    ParticleAppServices, ParticleAppServicesConfig, PeerScope, ServiceInfo, ServiceType,
+    format_labels, parse_labels, LabelSelector, Labels, ParticleAppServices,
+    ParticleAppServicesConfig, PeerScope, ServiceInfo, ServiceType,
 };
 use peer_metrics::ServicesMetrics;
 use types::peer_id;
 use uuid_utils::uuid;
 use workers::{KeyStorage, PeerScopes, Workers};
 
+use crate::capabilities::{capability_disabled_error, DisabledNamespaces};
+use crate::collect::Collectors;
 use crate::debug::fmt_custom_services;
 use crate::error::HostClosureCallError;
 use crate::error::HostClosureCallError::{DecodeBase58, DecodeUTF8};
-use crate::func::{binary, unary};
+use crate::func::{binary, ternary, unary};
 use crate::outcome::{ok, wrap, wrap_unit};
-use crate::{json, math};
+use crate::providers::{
+    ApplyResult, ProviderAnnouncer, ProviderTable, SignedAnnouncement, ANNOUNCEMENT_TTL_MS,
+};
+use crate::read_only::{is_mutating, read_only_error};
+use crate::run_as::{is_builtin_service, run_as_allows, run_as_error};
+use crate::snapshot::{RegistrySnapshot, SignedSnapshot, SnapshotImport, SnapshotPolicy};
+use crate::subnet::{WorkerRecord, WorkerRegistry, WORKER_RECORD_TTL_MS};
+use crate::time::MonotonicClock;
+use crate::{crypto, ids, json, math, random, time};
 
 pub struct CustomService {
     /// (function_name -> service function)
@@ -79,6 +94,10 @@ impl CustomService {
     }
 }
 
+/// Events returned by `event.query` when no limit is given
+const DEFAULT_EVENTS_LIMIT: usize = 100;
+const MAX_EVENTS_LIMIT: usize = 1000;
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Builtins<C> {
@@ -93,7 +112,32 @@ pub struct Builtins<C> {
     key_storage: Arc<KeyStorage>,
     #[derivative(Debug = "ignore")]
     scopes: PeerScopes,
+    #[derivative(Debug = "ignore")]
+    workers: Arc<Workers>,
     connector_api_endpoint: String,
+    #[derivative(Debug = "ignore")]
+    provider_announcer: parking_lot::Mutex<ProviderAnnouncer>,
+    #[derivative(Debug = "ignore")]
+    provider_table: Arc<parking_lot::RwLock<ProviderTable>>,
+    #[derivative(Debug = "ignore")]
+    worker_registry: parking_lot::RwLock<WorkerRegistry>,
+    /// Snapshots of provider and worker records are only applied if set,
+    /// see [`Builtins::with_snapshot_policy`]
+    #[derivative(Debug = "ignore")]
+    snapshot_policy: Option<SnapshotPolicy>,
+    /// Whether a snapshot was applied since the node started
+    snapshot_synced: AtomicBool,
+    clock: MonotonicClock,
+    #[derivative(Debug = "ignore")]
+    collectors: Collectors,
+    #[derivative(Debug = "ignore")]
+    events: EventLog,
+    /// Reject builtins changing the node state, see [`Builtins::with_read_only`]
+    read_only: bool,
+    /// Builtin namespaces rejected at dispatch, see [`Builtins::with_disabled_namespaces`]
+    disabled_namespaces: DisabledNamespaces,
+    /// Builtin calls taking longer are logged, see [`Builtins::with_slow_call_threshold`]
+    slow_call_threshold: Option<Duration>,
 }
 
 impl<C> Builtins<C>
@@ -109,6 +153,7 @@ where
         scope: PeerScopes,
         health_registry: Option<&mut HealthCheckRegistry>,
         connector_api_endpoint: String,
+        events: EventLog,
     ) -> Self {
         let modules_dir = &config.modules_dir;
         let blueprint_dir = &config.blueprint_dir;
@@ -130,7 +175,8 @@ where
             workers.clone(),
             scope.clone(),
         )
-        .expect("TODO async-marine: handle error from ParticleAppServices");
+        .expect("TODO async-marine: handle error from ParticleAppServices")
+        .with_event_log(events.clone());
 
         Self {
             connectivity,
@@ -139,11 +185,80 @@ where
             custom_services: <_>::default(),
             key_storage,
             scopes: scope,
+            workers,
             connector_api_endpoint,
+            provider_announcer: <_>::default(),
+            provider_table: <_>::default(),
+            worker_registry: <_>::default(),
+            snapshot_policy: None,
+            snapshot_synced: AtomicBool::new(false),
+            clock: MonotonicClock::new(),
+            collectors: <_>::default(),
+            events,
+            read_only: false,
+            disabled_namespaces: <_>::default(),
+            slow_call_threshold: None,
         }
     }
 
+    /// Make the node a read-only replica: builtins creating or changing services, spells, workers
+    /// and their configs are rejected with the `READ_ONLY` error code, everything else works as usual
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Reject calls to the builtins of these namespaces with the `CAPABILITY_DISABLED` error code.
+    /// The host and management peers can change the list at runtime with `capability.disable`
+    /// and `capability.enable`
+    pub fn with_disabled_namespaces(
+        mut self,
+        namespaces: impl IntoIterator<Item = String>,
+    ) -> Self {
+        self.disabled_namespaces = DisabledNamespaces::new(namespaces);
+        self
+    }
+
+    /// Log builtin calls taking longer than the threshold along with their args size and caller
+    pub fn with_slow_call_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_call_threshold = Some(threshold);
+        self
+    }
+
+    /// Apply snapshots of provider and worker records accepted by the policy
+    pub fn with_snapshot_policy(mut self, policy: SnapshotPolicy) -> Self {
+        self.snapshot_policy = Some(policy);
+        self
+    }
+
+    /// Whether a registry snapshot was applied since the node started
+    pub fn snapshot_synced(&self) -> bool {
+        self.snapshot_synced.load(Ordering::Relaxed)
+    }
+
+    /// Providers served by `providers.get`, shared with the `session` builtin registering clients
+    pub fn provider_table(&self) -> Arc<parking_lot::RwLock<ProviderTable>> {
+        self.provider_table.clone()
+    }
+
     pub async fn call(&self, args: Args, particle: ParticleParams) -> FunctionOutcome {
+        if self.disabled_namespaces.is_disabled(&args.service_id) {
+            return FunctionOutcome::Err(capability_disabled_error(
+                &args.service_id,
+                &args.function_name,
+            ));
+        }
+        if self.read_only && is_mutating(&args.service_id, &args.function_name) {
+            return FunctionOutcome::Err(read_only_error(&args.service_id, &args.function_name));
+        }
+        if let Err(err) = self.check_run_as(&args, &particle).await {
+            return FunctionOutcome::Err(err);
+        }
+        let service_id = args.service_id.clone();
+        let function_name = args.function_name.clone();
+        let args_size = serialized_size(&args.function_args);
+        let particle_id = particle.id.clone();
+        let init_peer_id = particle.init_peer_id;
         let mut start = Instant::now();
         let result = self.builtins_call(args, particle).await;
         let result = match result {
@@ -153,13 +268,32 @@ where
             }
             result => result,
         };
-        let end = start.elapsed().as_secs();
+        let elapsed = start.elapsed();
+        let end = elapsed.as_secs();
 
         match result {
             FunctionOutcome::NotDefined { args, params } => self.call_service(args, params).await,
             result => {
                 if let Some(metrics) = self.services.metrics.as_ref() {
                     metrics.observe_builtins(result.not_err(), end as f64);
+                    metrics.observe_builtin_call(
+                        &service_id,
+                        &function_name,
+                        result.not_err(),
+                        elapsed.as_secs_f64(),
+                    );
+                }
+                if self.slow_call_threshold.is_some_and(|t| elapsed > t) {
+                    log::warn!(
+                        target: "slow-builtin",
+                        "Slow builtin call {}.{} took {}ms: args size {} bytes, init peer id {}, particle id {}",
+                        service_id,
+                        function_name,
+                        elapsed.as_millis(),
+                        args_size,
+                        init_peer_id,
+                        particle_id
+                    );
                 }
                 result
             }
@@ -208,7 +342,7 @@ where
             ("kad", "neigh_with_addrs") => wrap(self.neighborhood_with_addresses(args).await),
             ("kad", "merge") => wrap(self.kad_merge(args.function_args)),
 
-            ("srv", "list") => ok(self.list_services(particle).await),
+            ("srv", "list") => wrap(self.list_services(args, particle).await),
             ("srv", "create") => wrap(self.create_service(args, particle).await),
             ("srv", "get_interface") => wrap(self.get_interface(args, particle).await),
             ("srv", "resolve_alias") => wrap(self.resolve_alias(args, particle).await),
@@ -216,6 +350,28 @@ where
             ("srv", "add_alias") => wrap_unit(self.add_alias(args, particle).await),
             ("srv", "remove") => wrap_unit(self.remove_service(args, particle).await),
             ("srv", "info") => wrap(self.get_service_info(args, particle).await),
+            ("srv", "health") => wrap(self.get_service_health(args, particle).await),
+            ("srv", "call_ordered") => self.call_ordered(args, particle).await,
+            ("event", "query") => wrap(self.query_events(args, particle).await),
+
+            ("capability", "disable") => wrap(self.disable_capability(args, particle)),
+            ("capability", "enable") => wrap(self.enable_capability(args, particle)),
+            ("capability", "list_disabled") => wrap(self.list_disabled_capabilities(particle)),
+
+            ("srv", "package") => wrap(self.package_service(args, particle).await),
+            ("srv", "import") => wrap(self.import_service(args, particle).await),
+            ("srv", "engine_report") => wrap(self.engine_report(particle).await),
+            ("srv", "engine_migrate") => wrap_unit(self.engine_migrate(args, particle).await),
+
+            ("providers", "announcement") => wrap(self.provider_announcement(args, particle).await),
+            ("providers", "apply") => wrap(self.apply_provider_announcement(args)),
+            ("providers", "get") => wrap(self.get_providers(args)),
+            ("providers", "snapshot") => wrap(self.registry_snapshot()),
+            ("providers", "apply_snapshot") => wrap(self.apply_registry_snapshot(args)),
+
+            ("collect", "create") => wrap(self.collect_create(args, particle)),
+            ("collect", "push") => wrap(self.collect_push(args)),
+            ("collect", "await") => wrap(self.collect_await(args).await),
 
             ("dist", "add_module_from_vault") => wrap(self.add_module_from_vault(args, particle).await),
             ("dist", "add_module") => wrap(self.add_module(args, particle).await),
@@ -256,6 +412,23 @@ where
             ("math", "rem") => binary(args, |x: i64, y: i64| -> R<i64, _> { math::rem(x, y) }),
             ("math", "pow") => binary(args, |x: i64, y: u32| -> R<i64, _> { math::pow(x, y) }),
             ("math", "log") => binary(args, |x: i64, y: i64| -> R<u32, _> { math::log(x, y) }),
+            ("math", "min") => binary(args, |x: i64, y: i64| -> R<i64, _> { math::min(x, y) }),
+            ("math", "max") => binary(args, |x: i64, y: i64| -> R<i64, _> { math::max(x, y) }),
+            ("math", "abs") => unary(args, |x: i64| -> R<i64, _> { math::abs(x) }),
+            ("math", "clamp") => ternary(args, |x: i64, lo: i64, hi: i64| -> R<i64, _> { math::clamp(x, lo, hi) }),
+
+            ("time", "monotonic_ms") => ok(json!(self.clock.now_ms())),
+            ("time", "elapsed_ms") => unary(args, |since: u64| -> R<u64, _> { self.clock.elapsed_ms(since) }),
+            ("time", "parse_duration") => unary(args, |d: String| -> R<u64, _> { time::parse_duration_ms(d) }),
+            ("time", "format_duration") => unary(args, |ms: u64| -> R<String, _> { time::format_duration_ms(ms) }),
+            ("time", "backoff") => ternary(args, |base: u64, attempt: u32, max: u64| -> R<u64, _> { time::backoff_ms(base, attempt, max) }),
+
+            ("rand", "int") => wrap(random::int(args)),
+            ("rand", "float") => wrap(random::float(args)),
+            ("rand", "sample") => wrap(random::sample(args)),
+
+            ("id", "generate") => unary(args, |kind: String| -> R<String, _> { ids::generate(kind) }),
+            ("id", "verify") => binary(args, |kind: String, id: String| -> R<ids::IdInfo, _> { ids::verify(kind, id) }),
 
             ("cmp", "gt") => binary(args, |x: i64, y: i64| -> R<bool, _> { math::gt(x, y) }),
             ("cmp", "gte") => binary(args, |x: i64, y: i64| -> R<bool, _> { math::gte(x, y) }),
@@ -275,6 +448,20 @@ where
             ("sig", "verify") => wrap(self.verify(args, particle)),
             ("sig", "get_peer_id") => wrap(self.get_peer_id(particle)),
 
+            ("crypto", "sha256") => unary(args, crypto::sha256),
+            ("crypto", "sha256_bytes") => unary(args, crypto::sha256_bytes),
+            ("crypto", "blake3") => unary(args, crypto::blake3),
+            ("crypto", "blake3_bytes") => unary(args, crypto::blake3_bytes),
+            ("crypto", "base64_encode") => unary(args, crypto::base64_encode),
+            ("crypto", "base64_decode") => unary(args, crypto::base64_decode),
+            ("crypto", "bytes_to_base64") => unary(args, crypto::bytes_to_base64),
+            ("crypto", "bytes_from_base64") => unary(args, crypto::bytes_from_base64),
+            ("crypto", "hex_encode") => unary(args, crypto::hex_encode),
+            ("crypto", "hex_decode") => unary(args, crypto::hex_decode),
+            ("crypto", "bytes_to_hex") => unary(args, crypto::bytes_to_hex),
+            ("crypto", "bytes_from_hex") => unary(args, crypto::bytes_from_hex),
+            ("crypto", "verify") => ternary(args, crypto::verify),
+
             ("json", "obj") => wrap(json::obj(args)),
             ("json", "put") => wrap(json::put(args)),
             ("json", "puts") => wrap(json::puts(args)),
@@ -287,6 +474,9 @@ where
             ("vault", "cat") => wrap(self.vault_cat(args, particle)),
 
             ("subnet", "resolve") => wrap(self.subnet_resolve(args).await),
+            ("subnet", "worker_record") => wrap(self.subnet_worker_record(particle)),
+            ("subnet", "register_worker") => wrap(self.subnet_register_worker(args)),
+            ("subnet", "resolve_workers") => wrap(self.subnet_resolve_workers(args)),
             ("run-console", "print") => {
                 self.guard_protected(&particle).await?;
 
@@ -807,18 +997,22 @@ where
     async fn create_service(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
         let mut args = args.function_args.into_iter();
         let blueprint_id: String = Args::next("blueprint_id", &mut args)?;
+        let labels: Option<String> = Args::next_opt("labels", &mut args)?;
+        let labels = labels.as_deref().map(parse_labels).transpose()?;
 
         self.guard_protected(&params).await?;
 
         let service_id = self
             .services
-            .create_service(
+            .create_service_with_labels(
                 params.peer_scope,
                 ServiceType::Service,
                 blueprint_id,
                 params.init_peer_id,
+                labels.unwrap_or_default(),
             )
-            .await?;
+            .await
+            .map_err(JError::coded)?;
 
         Ok(JValue::String(service_id))
     }
@@ -837,20 +1031,32 @@ where
                 params.init_peer_id,
                 false,
             )
-            .await?;
+            .await
+            .map_err(JError::coded)?;
 
         Ok(())
     }
 
-    async fn list_services(&self, params: ParticleParams) -> JValue {
-        Array(
+    /// srv.list(selector?)
+    /// Optional selector filters services by labels, e.g. "app=indexer,env!=dev"
+    async fn list_services(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let selector: Option<String> = Args::next_opt("selector", &mut args)?;
+        let selector = selector
+            .as_deref()
+            .map(LabelSelector::parse)
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Array(
             self.services
                 .list_services(params.peer_scope)
                 .await
                 .iter()
+                .filter(|info| selector.matches(&info.labels))
                 .map(|info| json!(Service::from(info, self.scopes.clone())))
                 .collect(),
-        )
+        ))
     }
 
     async fn call_service(&self, function_args: Args, particle: ParticleParams) -> FunctionOutcome {
@@ -859,13 +1065,52 @@ where
             .await
     }
 
+    /// srv.call_ordered(service_id, function_name, args, seq)
+    /// Calls the service function only after the call with the previous `seq`
+    /// from the same particle sender to the same service
+    async fn call_ordered(&self, args: Args, particle: ParticleParams) -> FunctionOutcome {
+        let Args {
+            function_args,
+            tetraplets,
+            ..
+        } = args;
+        let args: Result<Args, JError> = try {
+            let mut args = function_args.into_iter();
+            let service_id: String = Args::next("service_id", &mut args)?;
+            let function_name: String = Args::next("function_name", &mut args)?;
+            let function_args: Vec<JValue> = Args::next("args", &mut args)?;
+            let seq: u64 = Args::next("seq", &mut args)?;
+            // Arguments of the function inherit the tetraplet of the `args` array
+            let args_tetraplet = tetraplets.get(2).cloned().unwrap_or_default();
+            Args {
+                service_id,
+                function_name,
+                tetraplets: vec![args_tetraplet; function_args.len()],
+                function_args,
+                sequence: Some(seq),
+            }
+        };
+        let args = match args {
+            Ok(args) => args,
+            Err(err) => return FunctionOutcome::Err(err),
+        };
+        match self.call_service(args, particle).await {
+            FunctionOutcome::NotDefined { args, .. } => FunctionOutcome::Err(JError::with_code(
+                ErrorCode::ServiceNotFound,
+                format!("Service with id '{}' not found", args.service_id),
+            )),
+            result => result,
+        }
+    }
+
     async fn get_interface(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
         let mut args = args.function_args.into_iter();
         let service_id: String = Args::next("service_id", &mut args)?;
         Ok(self
             .services
             .get_interface(params.peer_scope, service_id, &params.id)
-            .await?)
+            .await
+            .map_err(JError::coded)?)
     }
 
     async fn add_alias(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
@@ -883,7 +1128,8 @@ where
                 service_id.clone(),
                 params.init_peer_id,
             )
-            .await?;
+            .await
+            .map_err(JError::coded)?;
 
         log::debug!(
             "Added alias {} for service {:?} {}",
@@ -901,7 +1147,8 @@ where
         let service_id = self
             .services
             .resolve_alias(params.peer_scope, alias.clone(), &params.id)
-            .await?;
+            .await
+            .map_err(JError::coded)?;
 
         log::debug!(
             "Resolved alias {} to service {:?} {}",
@@ -936,11 +1183,154 @@ where
         let info = self
             .services
             .get_service_info(params.peer_scope, service_id_or_alias, &params.id)
-            .await?;
+            .await
+            .map_err(JError::coded)?;
 
         Ok(json!(Service::from(&info, self.scopes.clone())))
     }
 
+    /// srv.health(service_id_or_alias)
+    /// Health state of a service declaring a health function with the `health` label
+    async fn get_service_health(
+        &self,
+        args: Args,
+        params: ParticleParams,
+    ) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id_or_alias: String = Args::next("service_id_or_alias", &mut args)?;
+        let health = self
+            .services
+            .get_service_health(params.peer_scope, service_id_or_alias, &params.id)
+            .await
+            .map_err(JError::coded)?;
+
+        Ok(json!(health))
+    }
+
+    /// srv.engine_report()
+    /// Wasm engines in use and services that haven't moved to the engine for new services yet
+    async fn engine_report(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.guard_protected(&params).await?;
+
+        Ok(json!(self.services.engine_report(params.peer_scope).await))
+    }
+
+    /// srv.engine_migrate(service_id_or_alias)
+    /// Moves the service to the engine for new services, it is reloaded on the next call
+    async fn engine_migrate(&self, args: Args, params: ParticleParams) -> Result<(), JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id_or_alias: String = Args::next("service_id_or_alias", &mut args)?;
+
+        self.guard_protected(&params).await?;
+
+        self.services
+            .migrate_engine(params.peer_scope, service_id_or_alias, &params.id)
+            .await
+            .map_err(JError::coded)
+    }
+
+    /// srv.package(service_id_or_alias)
+    /// Packs service blueprint, modules, aliases and labels so the service can be cloned onto another node
+    async fn package_service(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let service_id_or_alias: String = Args::next("service_id_or_alias", &mut args)?;
+
+        let info = self
+            .services
+            .get_service_info(params.peer_scope, service_id_or_alias, &params.id)
+            .await
+            .map_err(JError::coded)?;
+        if info.service_type != ServiceType::Service {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("{} is a spell, use spell.package instead", info.id),
+            ));
+        }
+
+        let package = ServicePackage {
+            blueprint: self.modules.export_blueprint(&info.blueprint_id)?,
+            aliases: info.aliases,
+            labels: info.labels,
+        };
+
+        Ok(json!(package))
+    }
+
+    /// srv.import(package)
+    /// Verifies module hashes of a package produced by srv.package and creates the service from it
+    async fn import_service(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let package: ServicePackage = Args::next("package", &mut args)?;
+
+        self.guard_protected(&params).await?;
+
+        let blueprint_id = self.modules.import_blueprint(package.blueprint)?;
+        let service_id = self
+            .services
+            .create_service_with_labels(
+                params.peer_scope,
+                ServiceType::Service,
+                blueprint_id,
+                params.init_peer_id,
+                package.labels,
+            )
+            .await
+            .map_err(JError::coded)?;
+
+        for alias in package.aliases {
+            let result = self
+                .services
+                .add_alias(
+                    params.peer_scope,
+                    alias,
+                    service_id.clone(),
+                    params.init_peer_id,
+                )
+                .await;
+            if let Err(err) = result {
+                // Don't leave a half-imported service behind
+                if let Err(remove_err) = self
+                    .services
+                    .remove_service(
+                        params.peer_scope,
+                        &params.id,
+                        &service_id,
+                        params.init_peer_id,
+                        false,
+                    )
+                    .await
+                {
+                    log::warn!(
+                        "Failed to remove partially imported service {service_id}: {remove_err}"
+                    );
+                }
+                return Err(JError::coded(err));
+            }
+        }
+
+        Ok(JValue::String(service_id))
+    }
+
+    /// event.query(filter?, since?, limit?)
+    /// Node events since the unix timestamp in milliseconds, oldest first.
+    /// On a worker, only the events of that worker are returned.
+    async fn query_events(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let filter: Option<EventFilter> = Args::next_opt("filter", &mut args)?;
+        let since: Option<u64> = Args::next_opt("since", &mut args)?;
+        let limit: Option<usize> = Args::next_opt("limit", &mut args)?;
+
+        self.guard_protected(&params).await?;
+
+        let mut filter = filter.unwrap_or_default();
+        if let PeerScope::WorkerId(worker_id) = params.peer_scope {
+            filter.worker_id = Some(PeerId::from(worker_id).to_base58());
+        }
+        let limit = limit.unwrap_or(DEFAULT_EVENTS_LIMIT).min(MAX_EVENTS_LIMIT);
+
+        Ok(json!(self.events.query(&filter, since.unwrap_or(0), limit)))
+    }
+
     fn kademlia(&self) -> &KademliaApi {
         self.connectivity.as_ref()
     }
@@ -970,7 +1360,8 @@ where
         let service_id = self
             .services
             .to_service_id(params.peer_scope, service_id_or_alias, &params.id)
-            .await?;
+            .await
+            .map_err(JError::coded)?;
         let metrics = self
             .services
             .metrics
@@ -1115,6 +1506,304 @@ where
         Ok(json!(result))
     }
 
+    /// Record of the current worker for its deal, signed by the worker key
+    /// and valid for `WORKER_RECORD_TTL_MS`. Only available in a worker scope.
+    fn subnet_worker_record(&self, params: ParticleParams) -> Result<JValue, JError> {
+        let PeerScope::WorkerId(worker_id) = params.peer_scope else {
+            return Err(JError::new(
+                "subnet.worker_record can only be called on a worker",
+            ));
+        };
+        let deal_id = self
+            .workers
+            .get_deal_id(worker_id)
+            .map_err(|err| JError::new(err.to_string()))?;
+        let keypair = self
+            .key_storage
+            .get_worker_key_pair(worker_id)
+            .ok_or_else(|| JError::new(format!("Key pair of worker {worker_id} not found")))?;
+        let record = WorkerRecord::sign(
+            deal_id,
+            worker_id.into(),
+            self.scopes.get_host_peer_id(),
+            now_ms() as u64 + WORKER_RECORD_TTL_MS,
+            &keypair,
+        )
+        .map_err(|err| JError::new(err.to_string()))?;
+        Ok(json!(record))
+    }
+
+    /// Caches a worker record, which may be forwarded by any peer:
+    /// the signature is checked against the key of the worker.
+    /// Returns false if a fresher record of the worker is already cached.
+    fn subnet_register_worker(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let record: WorkerRecord = Args::next("record", &mut args)?;
+
+        let registered = self
+            .worker_registry
+            .write()
+            .register(record, now_ms() as u64)
+            .map_err(|err| JError::with_code(ErrorCode::PermissionDenied, err.to_string()))?;
+        Ok(json!(registered))
+    }
+
+    /// Workers of the deal with the relays they're reachable through, from the cached records
+    fn subnet_resolve_workers(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let deal_id: String = Args::next("deal_id", &mut args)?;
+
+        Ok(json!(self
+            .worker_registry
+            .read()
+            .resolve(&deal_id.into(), now_ms() as u64)))
+    }
+
+    /// Announcement of the aliases of host services: a delta since the previous call,
+    /// or the full set when a sync is due or requested with `full = true`.
+    /// The announcement is signed by the host key and expires after `ANNOUNCEMENT_TTL_MS`.
+    /// Returns an empty array when nothing changed.
+    async fn provider_announcement(
+        &self,
+        args: Args,
+        particle: ParticleParams,
+    ) -> Result<JValue, JError> {
+        self.guard_protected(&particle).await?;
+
+        let mut args = args.function_args.into_iter();
+        let full: Option<bool> = Args::next_opt("full", &mut args)?;
+
+        let aliases = self
+            .services
+            .list_services(PeerScope::Host)
+            .await
+            .into_iter()
+            .flat_map(|info| info.aliases)
+            .collect();
+        let now = now_ms() as u64;
+        let announcement = self.provider_announcer.lock().next(
+            self.scopes.get_host_peer_id(),
+            aliases,
+            full.unwrap_or(false),
+            now,
+        );
+        let signed = announcement
+            .map(|announcement| {
+                SignedAnnouncement::sign(
+                    announcement,
+                    now + ANNOUNCEMENT_TTL_MS,
+                    &self.key_storage.root_key_pair,
+                )
+            })
+            .transpose()
+            .map_err(|err| JError::new(err.to_string()))?;
+
+        Ok(json!(signed.into_iter().collect::<Vec<_>>()))
+    }
+
+    /// Applies a signed announcement, which may be forwarded by any peer:
+    /// the signature is checked against the key of the announcing peer
+    fn apply_provider_announcement(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let announcement: SignedAnnouncement = Args::next("announcement", &mut args)?;
+
+        let result = self
+            .provider_table
+            .write()
+            .apply(announcement, now_ms() as u64)
+            .map_err(|err| JError::with_code(ErrorCode::PermissionDenied, err.to_string()))?;
+        Ok(json!(result))
+    }
+
+    fn get_providers(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let alias: String = Args::next("alias", &mut args)?;
+
+        Ok(json!(self
+            .provider_table
+            .read()
+            .providers(&alias, now_ms() as u64)))
+    }
+
+    /// Snapshot of the known provider and worker records signed by the host key,
+    /// so trusted nodes joining the cluster can start with them
+    fn registry_snapshot(&self) -> Result<JValue, JError> {
+        let now = now_ms() as u64;
+        let snapshot = RegistrySnapshot {
+            issuer: self.scopes.get_host_peer_id().to_base58(),
+            created_at: now,
+            providers: self.provider_table.read().export(now),
+            workers: self.worker_registry.read().export(now),
+        };
+        let signed = SignedSnapshot::sign(snapshot, &self.key_storage.root_key_pair)
+            .map_err(|err| JError::new(err.to_string()))?;
+        Ok(json!(signed))
+    }
+
+    /// Applies a snapshot made by a trusted peer with `providers.snapshot`.
+    /// Records older than the already known ones are skipped, worker records are verified one by one.
+    fn apply_registry_snapshot(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let signed: SignedSnapshot = Args::next("snapshot", &mut args)?;
+
+        let policy = self.snapshot_policy.as_ref().ok_or_else(|| {
+            JError::with_code(
+                ErrorCode::FailedPrecondition,
+                "Registry snapshot sync isn't configured on this node",
+            )
+        })?;
+        let now = now_ms() as u64;
+        policy
+            .check(&signed, now)
+            .map_err(|err| JError::with_code(ErrorCode::PermissionDenied, err.to_string()))?;
+
+        let snapshot = signed.snapshot;
+        let mut imported = SnapshotImport::default();
+        let mut provider_table = self.provider_table.write();
+        for record in snapshot.providers {
+            if provider_table.import(record, now) == ApplyResult::Applied {
+                imported.providers += 1;
+            }
+        }
+        drop(provider_table);
+        let mut worker_registry = self.worker_registry.write();
+        for record in snapshot.workers {
+            if let Ok(true) = worker_registry.register(record, now) {
+                imported.workers += 1;
+            }
+        }
+        drop(worker_registry);
+
+        self.snapshot_synced.store(true, Ordering::Relaxed);
+        log::info!(
+            "Applied registry snapshot of {}: {} provider records, {} worker records",
+            snapshot.issuer,
+            imported.providers,
+            imported.workers
+        );
+        Ok(json!(imported))
+    }
+
+    /// Opens a collector for `n` values. The timeout is capped by the remaining particle TTL,
+    /// so collectors never outlive the particle that created them.
+    fn collect_create(&self, args: Args, particle: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let expected: usize = Args::next("n", &mut args)?;
+        let timeout_ms: u64 = Args::next("timeout_ms", &mut args)?;
+
+        let particle_deadline = particle.timestamp + particle.ttl as u64;
+        let remaining_ttl = particle_deadline.saturating_sub(now_ms() as u64);
+        let timeout = Duration::from_millis(timeout_ms.min(remaining_ttl));
+
+        Ok(json!(self.collectors.create(expected, timeout)?))
+    }
+
+    fn collect_push(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let handle: String = Args::next("handle", &mut args)?;
+        let value: JValue = Args::next("value", &mut args)?;
+
+        Ok(json!(self.collectors.push(&handle, value)?))
+    }
+
+    /// Values pushed to the collector, all `n` of them or those that arrived before the timeout
+    async fn collect_await(&self, args: Args) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let handle: String = Args::next("handle", &mut args)?;
+
+        Ok(json!(self.collectors.wait(&handle).await?))
+    }
+
+    /// Spells installed with a run-as identity may only call the builtins they're granted and
+    /// those without side effects. Calls of services are authorized by `ParticleAppServices`
+    async fn check_run_as(&self, args: &Args, particle: &ParticleParams) -> Result<(), JError> {
+        let builtin = is_builtin_service(&args.service_id)
+            || self
+                .custom_services
+                .read()
+                .await
+                .contains_key(&args.service_id);
+        if !builtin {
+            return Ok(());
+        }
+        let Some(run_as) = self.services.spell_run_as(particle).await else {
+            return Ok(());
+        };
+        let allowed = run_as_allows(&run_as, &args.service_id, &args.function_name);
+        tracing::info!(
+            target: "audit",
+            particle_id = particle.id,
+            identity = run_as.name,
+            service_id = args.service_id,
+            function_name = args.function_name,
+            allowed,
+            "Spell builtin call under a run-as identity"
+        );
+        if allowed {
+            Ok(())
+        } else {
+            Err(run_as_error(&args.service_id, &args.function_name, &run_as))
+        }
+    }
+
+    /// capability.disable(namespace)
+    /// Reject calls to the builtins of the namespace until it's enabled again or the node restarts.
+    /// Returns false if the namespace was already disabled
+    fn disable_capability(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let namespace: String = Args::next("namespace", &mut args)?;
+
+        self.guard_operator(&params)?;
+
+        let disabled = self.disabled_namespaces.disable(namespace.clone())?;
+        if disabled {
+            log::warn!(
+                "Builtin namespace {namespace} is disabled by {}",
+                params.init_peer_id
+            );
+        }
+        Ok(json!(disabled))
+    }
+
+    /// capability.enable(namespace)
+    /// Returns false if the namespace wasn't disabled
+    fn enable_capability(&self, args: Args, params: ParticleParams) -> Result<JValue, JError> {
+        let mut args = args.function_args.into_iter();
+        let namespace: String = Args::next("namespace", &mut args)?;
+
+        self.guard_operator(&params)?;
+
+        let enabled = self.disabled_namespaces.enable(&namespace);
+        if enabled {
+            log::warn!(
+                "Builtin namespace {namespace} is enabled by {}",
+                params.init_peer_id
+            );
+        }
+        Ok(json!(enabled))
+    }
+
+    /// capability.list_disabled()
+    fn list_disabled_capabilities(&self, params: ParticleParams) -> Result<JValue, JError> {
+        self.guard_operator(&params)?;
+
+        Ok(json!(self.disabled_namespaces.list()))
+    }
+
+    /// Only the host and management peers operate the node, worker spells are rejected
+    fn guard_operator(&self, particle: &ParticleParams) -> Result<(), JError> {
+        if self.scopes.is_host(particle.init_peer_id)
+            || self.scopes.is_management(particle.init_peer_id)
+        {
+            Ok(())
+        } else {
+            Err(JError::with_code(
+                ErrorCode::PermissionDenied,
+                "This function is only available to the host or management peers",
+            ))
+        }
+    }
+
     async fn guard_protected(&self, particle: &ParticleParams) -> Result<(), JError> {
         if self.is_worker_spell(particle).await
             || self.scopes.is_host(particle.init_peer_id)
@@ -1122,7 +1811,8 @@ where
         {
             Ok(())
         } else {
-            Err(JError::new(
+            Err(JError::with_code(
+                ErrorCode::PermissionDenied,
                 "This function is only available to the host or worker spells",
             ))
         }
@@ -1239,6 +1929,7 @@ struct Service {
     pub aliases: Vec<String>,
     #[serde(serialize_with = "peer_id::serde::serialize")]
     pub worker_id: PeerId,
+    pub labels: Vec<String>,
 }
 
 impl Service {
@@ -1255,10 +1946,19 @@ impl Service {
             owner_id: service_info.owner_id,
             aliases: service_info.aliases.clone(),
             worker_id,
+            labels: format_labels(&service_info.labels),
         }
     }
 }
 
+/// Service packed by srv.package
+#[derive(Debug, Serialize, Deserialize)]
+struct ServicePackage {
+    pub blueprint: BlueprintPackage,
+    pub aliases: Vec<String>,
+    pub labels: Labels,
+}
+
 #[cfg(test)]
 mod prop_tests {
     use prop::collection::vec;
@@ -1330,6 +2030,7 @@ mod prop_tests {
                 function_name: "".to_string(),
                 function_args: args,
                 tetraplets: vec![],
+                sequence: None,
             };
 
             let config = make_module_config(args).expect("parse config via make_module_config");
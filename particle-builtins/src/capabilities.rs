@@ -0,0 +1,116 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::collections::BTreeSet;
+
+use parking_lot::RwLock;
+
+use particle_args::{ErrorCode, JError};
+
+/// Namespace of the builtins toggling the other namespaces, it can't be disabled itself
+pub(crate) const CAPABILITY_NAMESPACE: &str = "capability";
+
+/// Builtin namespaces rejected at dispatch, e.g. `kad` or `providers` on public relays.
+/// Starts from the node config and can be changed at runtime with `capability.disable`
+/// and `capability.enable`; runtime changes aren't persisted across restarts.
+#[derive(Debug, Default)]
+pub(crate) struct DisabledNamespaces {
+    namespaces: RwLock<BTreeSet<String>>,
+}
+
+impl DisabledNamespaces {
+    pub fn new(namespaces: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            namespaces: RwLock::new(
+                namespaces
+                    .into_iter()
+                    .filter(|namespace| namespace != CAPABILITY_NAMESPACE)
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn is_disabled(&self, namespace: &str) -> bool {
+        self.namespaces.read().contains(namespace)
+    }
+
+    /// Returns false if the namespace was already disabled
+    pub fn disable(&self, namespace: String) -> Result<bool, JError> {
+        if namespace.is_empty() {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                "namespace must not be empty",
+            ));
+        }
+        if namespace == CAPABILITY_NAMESPACE {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("{CAPABILITY_NAMESPACE} namespace can't be disabled"),
+            ));
+        }
+        Ok(self.namespaces.write().insert(namespace))
+    }
+
+    /// Returns false if the namespace wasn't disabled
+    pub fn enable(&self, namespace: &str) -> bool {
+        self.namespaces.write().remove(namespace)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.namespaces.read().iter().cloned().collect()
+    }
+}
+
+pub(crate) fn capability_disabled_error(service_id: &str, function_name: &str) -> JError {
+    JError::with_code(
+        ErrorCode::CapabilityDisabled,
+        format!("{service_id}.{function_name} is rejected: {service_id} builtins are disabled on this node"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_namespaces() {
+        let disabled = DisabledNamespaces::new(vec!["kad".to_string()]);
+        assert!(disabled.is_disabled("kad"));
+        assert!(!disabled.is_disabled("providers"));
+
+        assert!(disabled.disable("providers".to_string()).unwrap());
+        assert!(!disabled.disable("providers".to_string()).unwrap());
+        assert_eq!(disabled.list(), vec!["kad", "providers"]);
+
+        assert!(disabled.enable("kad"));
+        assert!(!disabled.enable("kad"));
+        assert!(!disabled.is_disabled("kad"));
+    }
+
+    #[test]
+    fn test_capability_namespace_stays_enabled() {
+        let disabled = DisabledNamespaces::new(vec![CAPABILITY_NAMESPACE.to_string()]);
+        assert!(!disabled.is_disabled(CAPABILITY_NAMESPACE));
+
+        let err = disabled
+            .disable(CAPABILITY_NAMESPACE.to_string())
+            .unwrap_err();
+        assert_eq!(err.code(), Some(ErrorCode::InvalidArgument));
+        assert!(disabled.disable(String::new()).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde_json::Value as JValue;
+use tokio::sync::watch;
+
+use particle_args::{ErrorCode, JError};
+use uuid_utils::uuid;
+
+/// Maximum number of collectors open at the same time
+pub const MAX_COLLECTORS: usize = 10_000;
+/// Maximum number of values a single collector waits for
+pub const MAX_COLLECTED_VALUES: usize = 1_000;
+
+struct Collector {
+    expected: usize,
+    deadline: Instant,
+    values: watch::Sender<Vec<JValue>>,
+}
+
+/// Host-side aggregation for scatter-gather scripts: values pushed by several calls
+/// are awaited by one call, until `n` values arrive or the timeout passes.
+/// Collectors are dropped once awaited or when their deadline passes.
+#[derive(Default)]
+pub struct Collectors {
+    collectors: Mutex<HashMap<String, Arc<Collector>>>,
+}
+
+impl Collectors {
+    /// Opens a collector waiting for `expected` values for at most `timeout`, returns its handle
+    pub fn create(&self, expected: usize, timeout: Duration) -> Result<String, JError> {
+        if expected == 0 || expected > MAX_COLLECTED_VALUES {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "collector must expect from 1 to {MAX_COLLECTED_VALUES} values, got {expected}"
+                ),
+            ));
+        }
+
+        let now = Instant::now();
+        let mut collectors = self.collectors.lock();
+        collectors.retain(|_, c| c.deadline > now);
+        if collectors.len() >= MAX_COLLECTORS {
+            return Err(JError::with_code(
+                ErrorCode::QuotaExceeded,
+                format!("too many open collectors, limit is {MAX_COLLECTORS}"),
+            ));
+        }
+
+        let handle = uuid();
+        let (values, _) = watch::channel(Vec::with_capacity(expected));
+        collectors.insert(
+            handle.clone(),
+            Arc::new(Collector {
+                expected,
+                deadline: now + timeout,
+                values,
+            }),
+        );
+        Ok(handle)
+    }
+
+    /// Adds a value to the collector. Returns false if the collector already has all values.
+    pub fn push(&self, handle: &str, value: JValue) -> Result<bool, JError> {
+        let collector = self.get(handle)?;
+        let mut accepted = false;
+        collector.values.send_if_modified(|values| {
+            accepted = values.len() < collector.expected;
+            if accepted {
+                values.push(value);
+            }
+            accepted
+        });
+        Ok(accepted)
+    }
+
+    /// Waits until the collector has all values or its deadline passes, returns collected values.
+    /// The collector is dropped afterwards.
+    pub async fn wait(&self, handle: &str) -> Result<Vec<JValue>, JError> {
+        let collector = self.get(handle)?;
+        let mut values = collector.values.subscribe();
+        let deadline = tokio::time::Instant::from_std(collector.deadline);
+        let expected = collector.expected;
+        // on timeout, return whatever was collected
+        let _ = tokio::time::timeout_at(deadline, values.wait_for(|v| v.len() >= expected)).await;
+
+        self.collectors.lock().remove(handle);
+        let collected = values.borrow().clone();
+        Ok(collected)
+    }
+
+    fn get(&self, handle: &str) -> Result<Arc<Collector>, JError> {
+        let collectors = self.collectors.lock();
+        collectors
+            .get(handle)
+            .filter(|c| c.deadline > Instant::now())
+            .cloned()
+            .ok_or_else(|| {
+                JError::with_code(
+                    ErrorCode::NotFound,
+                    format!("collector {handle} not found or expired"),
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn wait_returns_when_all_values_arrive() {
+        let collectors = Arc::new(Collectors::default());
+        let handle = collectors.create(2, Duration::from_secs(10)).unwrap();
+
+        let waiting = {
+            let collectors = collectors.clone();
+            let handle = handle.clone();
+            tokio::spawn(async move { collectors.wait(&handle).await })
+        };
+        assert!(collectors.push(&handle, json!(1)).unwrap());
+        assert!(collectors.push(&handle, json!(2)).unwrap());
+        assert!(!collectors.push(&handle, json!(3)).unwrap());
+
+        let values = waiting.await.unwrap().unwrap();
+        assert_eq!(values, vec![json!(1), json!(2)]);
+
+        // awaited collectors are dropped
+        assert!(collectors.push(&handle, json!(4)).is_err());
+    }
+
+    #[tokio::test]
+    async fn wait_returns_partial_results_on_timeout() {
+        let collectors = Collectors::default();
+        let handle = collectors.create(3, Duration::from_millis(50)).unwrap();
+        collectors.push(&handle, json!("a")).unwrap();
+
+        let values = collectors.wait(&handle).await.unwrap();
+        assert_eq!(values, vec![json!("a")]);
+    }
+
+    #[test]
+    fn expired_collectors_are_cleaned_up() {
+        let collectors = Collectors::default();
+        let handle = collectors.create(1, Duration::ZERO).unwrap();
+        assert!(collectors.push(&handle, json!(1)).is_err());
+
+        collectors.create(1, Duration::from_secs(10)).unwrap();
+        assert_eq!(collectors.collectors.lock().len(), 1);
+
+        assert!(collectors.create(0, Duration::from_secs(1)).is_err());
+        assert!(collectors
+            .create(MAX_COLLECTED_VALUES + 1, Duration::from_secs(1))
+            .is_err());
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Hashing, encoding and signature verification builtins of the `crypto` service.
+//! Hashes are returned as lowercase hex strings.
+
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use fluence_keypair::{PublicKey, Signature};
+use libp2p::PeerId;
+use sha2::{Digest, Sha256};
+
+use particle_args::{ErrorCode, JError};
+
+fn invalid(message: String) -> JError {
+    JError::with_code(ErrorCode::InvalidArgument, message)
+}
+
+fn utf8(bytes: Vec<u8>) -> Result<String, JError> {
+    String::from_utf8(bytes).map_err(|err| invalid(format!("decoded data isn't UTF-8: {err}")))
+}
+
+/// crypto.sha256(string)
+pub fn sha256(data: String) -> Result<String, JError> {
+    sha256_bytes(data.into_bytes())
+}
+
+/// crypto.sha256_bytes(bytes)
+pub fn sha256_bytes(data: Vec<u8>) -> Result<String, JError> {
+    Ok(hex::encode(Sha256::digest(data)))
+}
+
+/// crypto.blake3(string)
+pub fn blake3(data: String) -> Result<String, JError> {
+    blake3_bytes(data.into_bytes())
+}
+
+/// crypto.blake3_bytes(bytes)
+pub fn blake3_bytes(data: Vec<u8>) -> Result<String, JError> {
+    Ok(blake3::hash(&data).to_hex().to_string())
+}
+
+/// crypto.base64_encode(string)
+pub fn base64_encode(data: String) -> Result<String, JError> {
+    bytes_to_base64(data.into_bytes())
+}
+
+/// crypto.base64_decode(base64) decodes a UTF-8 string
+pub fn base64_decode(data: String) -> Result<String, JError> {
+    utf8(bytes_from_base64(data)?)
+}
+
+/// crypto.bytes_to_base64(bytes)
+pub fn bytes_to_base64(data: Vec<u8>) -> Result<String, JError> {
+    Ok(base64.encode(data))
+}
+
+/// crypto.bytes_from_base64(base64)
+pub fn bytes_from_base64(data: String) -> Result<Vec<u8>, JError> {
+    base64
+        .decode(data)
+        .map_err(|err| invalid(format!("invalid base64: {err}")))
+}
+
+/// crypto.hex_encode(string)
+pub fn hex_encode(data: String) -> Result<String, JError> {
+    bytes_to_hex(data.into_bytes())
+}
+
+/// crypto.hex_decode(hex) decodes a UTF-8 string
+pub fn hex_decode(data: String) -> Result<String, JError> {
+    utf8(bytes_from_hex(data)?)
+}
+
+/// crypto.bytes_to_hex(bytes)
+pub fn bytes_to_hex(data: Vec<u8>) -> Result<String, JError> {
+    Ok(hex::encode(data))
+}
+
+/// crypto.bytes_from_hex(hex), an optional `0x` prefix is ignored
+pub fn bytes_from_hex(data: String) -> Result<Vec<u8>, JError> {
+    let data = data.strip_prefix("0x").unwrap_or(&data);
+    hex::decode(data).map_err(|err| invalid(format!("invalid hex: {err}")))
+}
+
+/// crypto.verify(peer_id, signature, data)
+/// Checks the signature of the data with the public key embedded in the peer id of the signer,
+/// as produced by `sig.sign` on that peer
+pub fn verify(peer_id: String, signature: Vec<u8>, data: Vec<u8>) -> Result<bool, JError> {
+    let peer_id = PeerId::from_str(&peer_id)
+        .map_err(|err| invalid(format!("invalid peer id '{peer_id}': {err}")))?;
+    let pk = PublicKey::try_from(peer_id).map_err(|err| {
+        invalid(format!(
+            "public key can't be extracted from peer id '{peer_id}': {err}"
+        ))
+    })?;
+    let signature = Signature::from_bytes(pk.get_key_format(), signature);
+
+    Ok(pk.verify(&data, &signature).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_keypair::KeyPair;
+
+    #[test]
+    fn test_hashes() {
+        assert_eq!(
+            sha256("abc".into()).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_bytes(b"abc".to_vec()).unwrap(),
+            sha256("abc".into()).unwrap()
+        );
+        assert_eq!(
+            blake3("abc".into()).unwrap(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(base64_encode("hello".into()).unwrap(), "aGVsbG8=");
+        assert_eq!(base64_decode("aGVsbG8=".into()).unwrap(), "hello");
+        assert_eq!(hex_encode("hello".into()).unwrap(), "68656c6c6f");
+        assert_eq!(hex_decode("0x68656c6c6f".into()).unwrap(), "hello");
+        assert_eq!(bytes_from_hex("00ff".into()).unwrap(), vec![0, 255]);
+        assert_eq!(bytes_to_base64(vec![0, 255]).unwrap(), "AP8=");
+
+        let err = hex_decode("zz".into()).unwrap_err();
+        assert!(err.to_string().contains("invalid hex"), "{err}");
+        let err = base64_decode(bytes_to_base64(vec![0xff]).unwrap()).unwrap_err();
+        assert!(err.to_string().contains("UTF-8"), "{err}");
+    }
+
+    #[test]
+    fn test_verify() {
+        let kp = KeyPair::generate_ed25519();
+        let data = b"payload".to_vec();
+        let signature = kp.sign(&data).unwrap().to_vec();
+        let peer_id = kp.get_peer_id().to_string();
+
+        assert!(verify(peer_id.clone(), signature.clone(), data.clone()).unwrap());
+        assert!(!verify(peer_id.clone(), signature, b"other".to_vec()).unwrap());
+        assert!(verify("not a peer id".into(), vec![], data).is_err());
+    }
+}
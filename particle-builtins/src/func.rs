@@ -58,3 +58,24 @@ where
     let out = f(x, y)?;
     FunctionOutcome::Ok(json!(out))
 }
+
+pub fn ternary<X, Y, Z, Out, F>(args: Args, f: F) -> FunctionOutcome
+where
+    X: for<'de> Deserialize<'de>,
+    Y: for<'de> Deserialize<'de>,
+    Z: for<'de> Deserialize<'de>,
+    Out: Serialize,
+    F: Fn(X, Y, Z) -> Result<Out, JError>,
+{
+    if args.function_args.len() != 3 {
+        let err = format!("expected 3 arguments, got {}", args.function_args.len());
+        return FunctionOutcome::Err(JError::new(err));
+    }
+    let mut args = args.function_args.into_iter();
+
+    let x: X = Args::next("x", &mut args)?;
+    let y: Y = Args::next("y", &mut args)?;
+    let z: Z = Args::next("z", &mut args)?;
+    let out = f(x, y, z)?;
+    FunctionOutcome::Ok(json!(out))
+}
@@ -0,0 +1,202 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::str::FromStr;
+
+use rand::{thread_rng, RngCore};
+use serde::Serialize;
+use uuid::Uuid;
+
+use particle_args::{ErrorCode, JError};
+
+/// KSUID timestamps are seconds since this unix time, so they fit in 32 bits until 2150
+const KSUID_EPOCH: u64 = 1_400_000_000;
+const KSUID_LEN: usize = 27;
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdKind {
+    UuidV4,
+    /// Sortable by the millisecond it was generated at
+    UuidV7,
+    /// Sortable by the second it was generated at, 27 base62 characters
+    Ksuid,
+}
+
+impl FromStr for IdKind {
+    type Err = JError;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "uuid_v4" => Ok(IdKind::UuidV4),
+            "uuid_v7" => Ok(IdKind::UuidV7),
+            "ksuid" => Ok(IdKind::Ksuid),
+            _ => Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("unknown id kind '{kind}', expected one of uuid_v4, uuid_v7, ksuid"),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct IdInfo {
+    pub valid: bool,
+    /// When a sortable id was generated, empty for invalid and not sortable ids
+    pub timestamp_ms: Vec<u64>,
+}
+
+/// new id of the kind
+pub fn generate(kind: String) -> Result<String, JError> {
+    match kind.parse()? {
+        IdKind::UuidV4 => Ok(Uuid::new_v4().to_string()),
+        IdKind::UuidV7 => Ok(Uuid::now_v7().to_string()),
+        IdKind::Ksuid => {
+            let mut payload = [0u8; 16];
+            thread_rng().fill_bytes(&mut payload);
+            ksuid(now_millis::now_ms() as u64, payload)
+        }
+    }
+}
+
+/// whether the id is a valid id of the kind, and when it was generated if it's sortable
+pub fn verify(kind: String, id: String) -> Result<IdInfo, JError> {
+    let timestamp_ms = match kind.parse()? {
+        IdKind::UuidV4 => uuid_of_version(&id, 4).map(|_| None),
+        IdKind::UuidV7 => uuid_of_version(&id, 7).map(|uuid| {
+            uuid.get_timestamp().map(|ts| {
+                let (secs, nanos) = ts.to_unix();
+                secs * 1000 + nanos as u64 / 1_000_000
+            })
+        }),
+        IdKind::Ksuid => base62_decode(&id).map(|bytes| {
+            let secs = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            Some((KSUID_EPOCH + secs as u64) * 1000)
+        }),
+    };
+
+    Ok(IdInfo {
+        valid: timestamp_ms.is_some(),
+        timestamp_ms: timestamp_ms.flatten().into_iter().collect(),
+    })
+}
+
+fn uuid_of_version(id: &str, version: usize) -> Option<Uuid> {
+    Uuid::try_parse(id)
+        .ok()
+        .filter(|uuid| uuid.get_version_num() == version)
+}
+
+fn ksuid(now_ms: u64, payload: [u8; 16]) -> Result<String, JError> {
+    let secs = (now_ms / 1000)
+        .checked_sub(KSUID_EPOCH)
+        .and_then(|secs| u32::try_from(secs).ok())
+        .ok_or_else(|| {
+            JError::with_code(
+                ErrorCode::FailedPrecondition,
+                format!("time {now_ms}ms can't be represented in a ksuid"),
+            )
+        })?;
+    let mut bytes = [0u8; 20];
+    bytes[..4].copy_from_slice(&secs.to_be_bytes());
+    bytes[4..].copy_from_slice(&payload);
+    Ok(base62_encode(bytes))
+}
+
+/// 160 bits to base62, padded with zeros so ids sort as strings the same way as numbers
+fn base62_encode(mut bytes: [u8; 20]) -> String {
+    let mut digits = [b'0'; KSUID_LEN];
+    for digit in digits.iter_mut().rev() {
+        let mut rem = 0u32;
+        for byte in bytes.iter_mut() {
+            let acc = (rem << 8) | *byte as u32;
+            *byte = (acc / 62) as u8;
+            rem = acc % 62;
+        }
+        *digit = BASE62[rem as usize];
+    }
+    String::from_utf8(digits.to_vec()).expect("base62 digits are ascii")
+}
+
+/// None if the id isn't 27 base62 digits or doesn't fit in 160 bits
+fn base62_decode(id: &str) -> Option<[u8; 20]> {
+    if id.len() != KSUID_LEN {
+        return None;
+    }
+    let mut bytes = [0u8; 20];
+    for c in id.bytes() {
+        let mut carry = BASE62.iter().position(|&d| d == c)? as u32;
+        for byte in bytes.iter_mut().rev() {
+            let acc = *byte as u32 * 62 + carry;
+            *byte = acc as u8;
+            carry = acc >> 8;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_ids_are_valid() {
+        for kind in ["uuid_v4", "uuid_v7", "ksuid"] {
+            let id = generate(kind.to_string()).unwrap();
+            assert!(verify(kind.to_string(), id).unwrap().valid, "{kind}");
+        }
+
+        let v4 = generate("uuid_v4".to_string()).unwrap();
+        assert!(!verify("uuid_v7".to_string(), v4).unwrap().valid);
+        assert!(
+            !verify("ksuid".to_string(), "not an id".to_string())
+                .unwrap()
+                .valid
+        );
+        assert!(generate("snowflake".to_string()).is_err());
+    }
+
+    #[test]
+    fn sortable_ids_have_timestamps() {
+        let now = now_millis::now_ms() as u64;
+        let v7 = generate("uuid_v7".to_string()).unwrap();
+        let info = verify("uuid_v7".to_string(), v7).unwrap();
+        assert!(info.timestamp_ms[0].abs_diff(now) < 1000);
+
+        let ksuid = generate("ksuid".to_string()).unwrap();
+        let info = verify("ksuid".to_string(), ksuid).unwrap();
+        assert!(info.timestamp_ms[0].abs_diff(now) < 2000);
+    }
+
+    #[test]
+    fn ksuids_sort_by_time() {
+        let earlier = ksuid(1_700_000_000_000, [0xff; 16]).unwrap();
+        let later = ksuid(1_700_000_001_000, [0; 16]).unwrap();
+        assert_eq!(earlier.len(), KSUID_LEN);
+        assert!(earlier < later);
+
+        let max = base62_encode([0xff; 20]);
+        assert_eq!(max, "aWgEPTl1tmebfsQzFP4bxwgy80V");
+        assert_eq!(base62_decode(&max), Some([0xff; 20]));
+        assert_eq!(base62_decode("aWgEPTl1tmebfsQzFP4bxwgy80W"), None);
+    }
+}
@@ -36,12 +36,25 @@ pub use builtins::{Builtins, CustomService};
 pub use identify::NodeInfo;
 pub use outcome::{ok, wrap, wrap_unit};
 pub use particle_services::ParticleAppServicesConfig;
+pub use providers::ProviderTable;
+pub use snapshot::SnapshotPolicy;
 mod builtins;
+mod capabilities;
+mod collect;
+mod crypto;
 mod debug;
 mod error;
 mod func;
 mod identify;
+mod ids;
 mod json;
 mod math;
 mod outcome;
 mod particle_function;
+mod providers;
+mod random;
+mod read_only;
+mod run_as;
+mod snapshot;
+mod subnet;
+mod time;
@@ -74,6 +74,30 @@ pub fn log(x: i64, y: i64) -> Result<u32, JError> {
         .ok_or_else(|| JError::new("i64 log overflow"))
 }
 
+/// min(x, y)
+pub fn min(x: i64, y: i64) -> Result<i64, JError> {
+    Ok(x.min(y))
+}
+
+/// max(x, y)
+pub fn max(x: i64, y: i64) -> Result<i64, JError> {
+    Ok(x.max(y))
+}
+
+/// |x|
+pub fn abs(x: i64) -> Result<i64, JError> {
+    x.checked_abs()
+        .ok_or_else(|| JError::new("i64 abs overflow"))
+}
+
+/// x limited to [lo, hi]
+pub fn clamp(x: i64, lo: i64, hi: i64) -> Result<i64, JError> {
+    if lo > hi {
+        return Err(JError::new(format!("lo {lo} is greater than hi {hi}")));
+    }
+    Ok(x.clamp(lo, hi))
+}
+
 /// x > y
 pub fn gt(x: i64, y: i64) -> Result<bool, JError> {
     Ok(x.gt(&y))
@@ -0,0 +1,843 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeSet, HashMap};
+
+use fluence_keypair::{KeyPair, PublicKey, Signature};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Every `FULL_SYNC_EVERY`th announcement carries the full provider set
+pub const FULL_SYNC_EVERY: u64 = 10;
+/// How long a signed announcement keeps the announced providers alive
+pub const ANNOUNCEMENT_TTL_MS: u64 = 60 * 60 * 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Announcement {
+    /// Complete set of aliases provided by `peer_id`
+    Full {
+        peer_id: String,
+        seq: u64,
+        providers: Vec<String>,
+    },
+    /// Changes since the announcement with `seq - 1`
+    Delta {
+        peer_id: String,
+        seq: u64,
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+}
+
+impl Announcement {
+    pub fn peer_id(&self) -> &str {
+        match self {
+            Announcement::Full { peer_id, .. } | Announcement::Delta { peer_id, .. } => peer_id,
+        }
+    }
+
+    pub fn seq(&self) -> u64 {
+        match self {
+            Announcement::Full { seq, .. } | Announcement::Delta { seq, .. } => *seq,
+        }
+    }
+}
+
+/// Announcement signed by the key of the announcing peer, so it can be forwarded
+/// through other peers without letting them forge provider records
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAnnouncement {
+    pub announcement: Announcement,
+    /// Unix timestamp in milliseconds after which the announced providers are dropped
+    pub expires_at: u64,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AnnouncementError {
+    #[error("Provider announcement signature doesn't match peer {0}")]
+    InvalidSignature(String),
+    #[error("Provider announcement of peer {peer_id} expired at {expires_at}")]
+    Expired { peer_id: String, expires_at: u64 },
+    #[error("Failed to sign provider announcement: {0}")]
+    Signing(String),
+}
+
+impl SignedAnnouncement {
+    pub fn sign(
+        announcement: Announcement,
+        expires_at: u64,
+        keypair: &KeyPair,
+    ) -> Result<Self, AnnouncementError> {
+        let signature = keypair
+            .sign(&signed_bytes(&announcement, expires_at))
+            .map_err(|err| AnnouncementError::Signing(err.to_string()))?;
+        Ok(Self {
+            announcement,
+            expires_at,
+            signature: signature.to_vec().to_vec(),
+        })
+    }
+
+    /// Checks that the signature was made by the key of the announcing peer
+    pub fn verify(&self) -> Result<(), AnnouncementError> {
+        let peer_id = self.announcement.peer_id();
+        let invalid = || AnnouncementError::InvalidSignature(peer_id.to_string());
+        let peer_id: PeerId = peer_id.parse().map_err(|_| invalid())?;
+        let pk = PublicKey::try_from(peer_id).map_err(|_| invalid())?;
+        let signature = Signature::from_bytes(pk.get_key_format(), self.signature.clone());
+        pk.verify(
+            &signed_bytes(&self.announcement, self.expires_at),
+            &signature,
+        )
+        .map_err(|_| invalid())
+    }
+}
+
+/// Bytes covered by the signature, concatenation of:
+/// - "full" or "delta" and the peer id, each followed by a zero byte
+/// - seq and expires_at u64 as little-endian bytes
+/// - for each alias list, its length as u64 little-endian bytes,
+///   then the aliases, each followed by a zero byte
+fn signed_bytes(announcement: &Announcement, expires_at: u64) -> Vec<u8> {
+    let (kind, lists): (&str, Vec<&Vec<String>>) = match announcement {
+        Announcement::Full { providers, .. } => ("full", vec![providers]),
+        Announcement::Delta { added, removed, .. } => ("delta", vec![added, removed]),
+    };
+
+    let mut bytes = vec![];
+    for field in [kind, announcement.peer_id()] {
+        bytes.extend(field.as_bytes());
+        bytes.push(0);
+    }
+    bytes.extend(announcement.seq().to_le_bytes());
+    bytes.extend(expires_at.to_le_bytes());
+    for list in lists {
+        bytes.extend((list.len() as u64).to_le_bytes());
+        for alias in list {
+            bytes.extend(alias.as_bytes());
+            bytes.push(0);
+        }
+    }
+    bytes
+}
+
+/// Produces announcements of the local provider set, sending only the difference
+/// with the previous announcement unless a full sync is due
+#[derive(Debug, Default)]
+pub struct ProviderAnnouncer {
+    seq: u64,
+    announced: BTreeSet<String>,
+    /// When the last announcement was made, unix timestamp in milliseconds
+    announced_at: u64,
+}
+
+impl ProviderAnnouncer {
+    /// Returns `None` when nothing changed since the last announcement and no full sync is due.
+    /// A full sync is also due once half of `ANNOUNCEMENT_TTL_MS` has passed since the last
+    /// announcement, so that receivers get a renewed signature before the previous one expires.
+    pub fn next(
+        &mut self,
+        peer_id: PeerId,
+        current: BTreeSet<String>,
+        force_full: bool,
+        now: u64,
+    ) -> Option<Announcement> {
+        let seq = self.seq + 1;
+        let renewal_due = now.saturating_sub(self.announced_at) >= ANNOUNCEMENT_TTL_MS / 2;
+        let full_due = force_full || renewal_due || self.seq == 0 || seq % FULL_SYNC_EVERY == 0;
+        let announcement = if full_due {
+            Announcement::Full {
+                peer_id: peer_id.to_base58(),
+                seq,
+                providers: current.iter().cloned().collect(),
+            }
+        } else {
+            let added: Vec<_> = current.difference(&self.announced).cloned().collect();
+            let removed: Vec<_> = self.announced.difference(&current).cloned().collect();
+            if added.is_empty() && removed.is_empty() {
+                return None;
+            }
+            Announcement::Delta {
+                peer_id: peer_id.to_base58(),
+                seq,
+                added,
+                removed,
+            }
+        };
+
+        self.seq = seq;
+        self.announced = current;
+        self.announced_at = now;
+        Some(announcement)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyResult {
+    Applied,
+    /// Announcement is older than what we already have
+    Stale,
+    /// Some deltas were missed, the announcer should send a full sync
+    NeedFullSync,
+}
+
+/// Current providers of a peer as known to this node, see [`ProviderTable::export`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderRecord {
+    pub peer_id: String,
+    pub seq: u64,
+    pub expires_at: u64,
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug)]
+struct PeerProviders {
+    seq: u64,
+    expires_at: u64,
+    providers: BTreeSet<String>,
+    /// Registered by a client of this node rather than announced
+    client: bool,
+}
+
+/// Provider sets of remote peers, built from their announcements,
+/// and of clients of this node registered with `session.register`
+#[derive(Debug, Default)]
+pub struct ProviderTable {
+    peers: HashMap<String, PeerProviders>,
+}
+
+impl ProviderTable {
+    /// Verifies the signature and expiry of the announcement before applying it.
+    /// Every applied announcement extends the lifetime of the peer's providers to its `expires_at`,
+    /// but not further than `ANNOUNCEMENT_TTL_MS` from now.
+    pub fn apply(
+        &mut self,
+        signed: SignedAnnouncement,
+        now: u64,
+    ) -> Result<ApplyResult, AnnouncementError> {
+        signed.verify()?;
+        if signed.expires_at <= now {
+            return Err(AnnouncementError::Expired {
+                peer_id: signed.announcement.peer_id().to_string(),
+                expires_at: signed.expires_at,
+            });
+        }
+        let expires_at = clamp_expiry(signed.expires_at, now);
+        Ok(self.apply_verified(signed.announcement, expires_at, now))
+    }
+
+    /// Records of a peer whose announcements expired are dropped first, so a peer that restarted
+    /// with a new seq isn't rejected as stale
+    fn apply_verified(
+        &mut self,
+        announcement: Announcement,
+        expires_at: u64,
+        now: u64,
+    ) -> ApplyResult {
+        let peer_id = announcement.peer_id().to_string();
+        if self
+            .peers
+            .get(&peer_id)
+            .is_some_and(|known| known.expires_at <= now)
+        {
+            self.peers.remove(&peer_id);
+        }
+        let known_seq = self.peers.get(&peer_id).map(|known| known.seq);
+        let result = match (announcement, known_seq) {
+            (announcement, Some(known_seq)) if announcement.seq() <= known_seq => {
+                ApplyResult::Stale
+            }
+            (
+                Announcement::Full {
+                    peer_id,
+                    seq,
+                    providers,
+                },
+                _,
+            ) => {
+                let providers = providers.into_iter().collect();
+                self.peers.insert(
+                    peer_id,
+                    PeerProviders {
+                        seq,
+                        expires_at,
+                        providers,
+                        client: false,
+                    },
+                );
+                ApplyResult::Applied
+            }
+            (Announcement::Delta { seq, .. }, known_seq)
+                if known_seq.map(|known| known + 1) != Some(seq) =>
+            {
+                ApplyResult::NeedFullSync
+            }
+            (
+                Announcement::Delta {
+                    peer_id,
+                    seq,
+                    added,
+                    removed,
+                },
+                _,
+            ) => {
+                if let Some(known) = self.peers.get_mut(&peer_id) {
+                    known.seq = seq;
+                    known.expires_at = expires_at;
+                    known.providers.extend(added);
+                    for alias in removed {
+                        known.providers.remove(&alias);
+                    }
+                }
+                ApplyResult::Applied
+            }
+        };
+        self.debug_check_invariants(&peer_id, known_seq, result);
+        result
+    }
+
+    /// Only applied announcements may change the records of a peer, and they always advance its seq,
+    /// so a peer can't be rolled back to older providers. Checked in builds with debug assertions,
+    /// which includes tests and staging builds.
+    fn debug_check_invariants(&self, peer_id: &str, seq_before: Option<u64>, result: ApplyResult) {
+        let seq_after = self.peers.get(peer_id).map(|known| known.seq);
+        match result {
+            ApplyResult::Applied => debug_assert!(
+                seq_after > seq_before,
+                "announcement of {peer_id} was applied without advancing seq {seq_before:?}"
+            ),
+            ApplyResult::Stale | ApplyResult::NeedFullSync => debug_assert_eq!(
+                seq_after, seq_before,
+                "rejected announcement of {peer_id} changed its records"
+            ),
+        }
+    }
+
+    /// Providers of every peer whose announcements haven't expired yet.
+    /// Records aren't signed by the announcing peers, see [`crate::snapshot`].
+    /// Clients are reachable only through this node, so their records aren't exported.
+    pub fn export(&self, now: u64) -> Vec<ProviderRecord> {
+        let mut records: Vec<_> = self
+            .peers
+            .iter()
+            .filter(|(_, known)| known.expires_at > now && !known.client)
+            .map(|(peer_id, known)| ProviderRecord {
+                peer_id: peer_id.clone(),
+                seq: known.seq,
+                expires_at: known.expires_at,
+                providers: known.providers.iter().cloned().collect(),
+            })
+            .collect();
+        records.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+        records
+    }
+
+    /// Applies an exported record as a full announcement, so records older
+    /// than what's already known are `Stale`. The caller is responsible for trusting the record.
+    pub fn import(&mut self, record: ProviderRecord, now: u64) -> ApplyResult {
+        if record.expires_at <= now {
+            return ApplyResult::Stale;
+        }
+        let announcement = Announcement::Full {
+            peer_id: record.peer_id,
+            seq: record.seq,
+            providers: record.providers,
+        };
+        self.apply_verified(announcement, clamp_expiry(record.expires_at, now), now)
+    }
+
+    /// Makes a client of this node a provider of the alias. The registration lives while the
+    /// client is connected and for the session window after that, see [`Self::expire_client`].
+    /// Returns false if the client already provides `max_aliases` other aliases.
+    pub fn register_client(
+        &mut self,
+        peer_id: &str,
+        alias: String,
+        max_aliases: usize,
+        now: u64,
+    ) -> bool {
+        let known = self
+            .peers
+            .entry(peer_id.to_string())
+            .or_insert_with(|| PeerProviders {
+                seq: 0,
+                expires_at: 0,
+                providers: <_>::default(),
+                client: true,
+            });
+        if known.expires_at <= now {
+            known.providers.clear();
+        }
+        if !known.providers.contains(&alias) && known.providers.len() >= max_aliases {
+            return false;
+        }
+        known.providers.insert(alias);
+        known.expires_at = u64::MAX;
+        true
+    }
+
+    /// Returns whether the client was registered as a provider of the alias
+    pub fn unregister_client(&mut self, peer_id: &str, alias: &str, now: u64) -> bool {
+        self.peers
+            .get_mut(peer_id)
+            .filter(|known| known.client && known.expires_at > now)
+            .map_or(false, |known| known.providers.remove(alias))
+    }
+
+    /// Registrations of a disconnected client expire at `expires_at` unless it resumes its session
+    pub fn expire_client(&mut self, peer_id: &str, expires_at: u64, now: u64) {
+        let known = self.peers.get_mut(peer_id);
+        if let Some(known) = known.filter(|known| known.client && known.expires_at > now) {
+            known.expires_at = expires_at;
+        }
+    }
+
+    /// Keeps registrations of a client that resumed its session alive while it's connected,
+    /// returns the aliases it's still registered as a provider of
+    pub fn resume_client(&mut self, peer_id: &str, now: u64) -> Vec<String> {
+        match self.peers.get_mut(peer_id) {
+            Some(known) if known.client && known.expires_at > now => {
+                known.expires_at = u64::MAX;
+                known.providers.iter().cloned().collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    pub fn remove_client(&mut self, peer_id: &str) {
+        if self.peers.get(peer_id).is_some_and(|known| known.client) {
+            self.peers.remove(peer_id);
+        }
+    }
+
+    /// Peers providing `alias`, skipping those whose announcements expired
+    pub fn providers(&self, alias: &str, now: u64) -> Vec<String> {
+        self.peers
+            .iter()
+            .filter(|(_, known)| known.expires_at > now && known.providers.contains(alias))
+            .map(|(peer_id, _)| peer_id.clone())
+            .collect()
+    }
+}
+
+/// Announcements can't keep providers alive for longer than `ANNOUNCEMENT_TTL_MS`
+fn clamp_expiry(expires_at: u64, now: u64) -> u64 {
+    expires_at.min(now.saturating_add(ANNOUNCEMENT_TTL_MS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000_000;
+
+    fn set(aliases: &[&str]) -> BTreeSet<String> {
+        aliases.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn signed(announcement: Announcement, keypair: &KeyPair) -> SignedAnnouncement {
+        SignedAnnouncement::sign(announcement, NOW + ANNOUNCEMENT_TTL_MS, keypair).unwrap()
+    }
+
+    #[test]
+    fn announcer_sends_deltas_between_full_syncs() {
+        let peer_id = PeerId::random();
+        let mut announcer = ProviderAnnouncer::default();
+
+        let first = announcer
+            .next(peer_id, set(&["a", "b"]), false, NOW)
+            .unwrap();
+        assert!(matches!(first, Announcement::Full { seq: 1, .. }));
+
+        assert_eq!(announcer.next(peer_id, set(&["a", "b"]), false, NOW), None);
+
+        let delta = announcer
+            .next(peer_id, set(&["a", "c"]), false, NOW)
+            .unwrap();
+        assert_eq!(
+            delta,
+            Announcement::Delta {
+                peer_id: peer_id.to_base58(),
+                seq: 2,
+                added: vec!["c".to_string()],
+                removed: vec!["b".to_string()],
+            }
+        );
+
+        let forced = announcer
+            .next(peer_id, set(&["a", "c"]), true, NOW)
+            .unwrap();
+        assert!(matches!(forced, Announcement::Full { seq: 3, .. }));
+    }
+
+    #[test]
+    fn announcer_renews_before_expiry() {
+        let peer_id = PeerId::random();
+        let mut announcer = ProviderAnnouncer::default();
+
+        announcer.next(peer_id, set(&["a"]), false, NOW).unwrap();
+        assert_eq!(announcer.next(peer_id, set(&["a"]), false, NOW + 1), None);
+
+        let renewed = announcer.next(peer_id, set(&["a"]), false, NOW + ANNOUNCEMENT_TTL_MS / 2);
+        assert!(matches!(renewed, Some(Announcement::Full { seq: 2, .. })));
+    }
+
+    #[test]
+    fn table_detects_gaps() {
+        let keypair = KeyPair::generate_ed25519();
+        let peer_id = keypair.get_peer_id();
+        let mut announcer = ProviderAnnouncer::default();
+        let mut table = ProviderTable::default();
+
+        let mut next = |current| {
+            signed(
+                announcer.next(peer_id, current, false, NOW).unwrap(),
+                &keypair,
+            )
+        };
+        let full = next(set(&["a"]));
+        let delta = next(set(&["a", "b"]));
+        let missed = next(set(&["b"]));
+        let after_missed = next(set(&[]));
+
+        let mut apply = |signed| table.apply(signed, NOW).unwrap();
+        assert_eq!(apply(delta.clone()), ApplyResult::NeedFullSync);
+        assert_eq!(apply(full.clone()), ApplyResult::Applied);
+        assert_eq!(apply(full), ApplyResult::Stale);
+        assert_eq!(apply(delta), ApplyResult::Applied);
+        assert_eq!(apply(after_missed.clone()), ApplyResult::NeedFullSync);
+        assert_eq!(apply(missed), ApplyResult::Applied);
+        assert_eq!(table.providers("b", NOW), vec![peer_id.to_base58()]);
+        assert!(table.providers("a", NOW).is_empty());
+    }
+
+    #[test]
+    fn table_rejects_forged_announcements() {
+        let keypair = KeyPair::generate_ed25519();
+        let victim = KeyPair::generate_ed25519().get_peer_id();
+        let mut table = ProviderTable::default();
+
+        // signed by another key on behalf of the victim
+        let forged = signed(
+            Announcement::Full {
+                peer_id: victim.to_base58(),
+                seq: 1,
+                providers: vec!["popular".to_string()],
+            },
+            &keypair,
+        );
+        assert_eq!(
+            table.apply(forged, NOW),
+            Err(AnnouncementError::InvalidSignature(victim.to_base58()))
+        );
+
+        // tampered after signing
+        let mut tampered = signed(
+            Announcement::Full {
+                peer_id: keypair.get_peer_id().to_base58(),
+                seq: 1,
+                providers: vec!["a".to_string()],
+            },
+            &keypair,
+        );
+        tampered.announcement = Announcement::Full {
+            peer_id: keypair.get_peer_id().to_base58(),
+            seq: 1,
+            providers: vec!["popular".to_string()],
+        };
+        assert!(matches!(
+            table.apply(tampered.clone(), NOW),
+            Err(AnnouncementError::InvalidSignature(_))
+        ));
+
+        // extending the expiry also breaks the signature
+        tampered.announcement = Announcement::Full {
+            peer_id: keypair.get_peer_id().to_base58(),
+            seq: 1,
+            providers: vec!["a".to_string()],
+        };
+        tampered.expires_at += 1;
+        assert!(table.apply(tampered, NOW).is_err());
+
+        assert!(table.providers("popular", NOW).is_empty());
+    }
+
+    #[test]
+    fn table_drops_expired_providers() {
+        let keypair = KeyPair::generate_ed25519();
+        let peer_id = keypair.get_peer_id();
+        let mut announcer = ProviderAnnouncer::default();
+        let mut table = ProviderTable::default();
+
+        let full = signed(
+            announcer.next(peer_id, set(&["a"]), false, NOW).unwrap(),
+            &keypair,
+        );
+        let expires_at = full.expires_at;
+        assert_eq!(
+            table.apply(full.clone(), expires_at),
+            Err(AnnouncementError::Expired {
+                peer_id: peer_id.to_base58(),
+                expires_at,
+            })
+        );
+
+        assert_eq!(table.apply(full, NOW), Ok(ApplyResult::Applied));
+        assert_eq!(
+            table.providers("a", expires_at - 1),
+            vec![peer_id.to_base58()]
+        );
+        assert!(table.providers("a", expires_at).is_empty());
+
+        // a renewed announcement extends the lifetime
+        let renewal_at = NOW + ANNOUNCEMENT_TTL_MS / 2;
+        let renewed = announcer
+            .next(peer_id, set(&["a"]), false, renewal_at)
+            .unwrap();
+        let renewed =
+            SignedAnnouncement::sign(renewed, renewal_at + ANNOUNCEMENT_TTL_MS, &keypair).unwrap();
+        assert_eq!(table.apply(renewed, renewal_at), Ok(ApplyResult::Applied));
+        assert_eq!(table.providers("a", expires_at), vec![peer_id.to_base58()]);
+    }
+
+    #[test]
+    fn table_clamps_expiry_and_forgets_expired_peers() {
+        let keypair = KeyPair::generate_ed25519();
+        let peer_id = keypair.get_peer_id();
+        let mut announcer = ProviderAnnouncer::default();
+        let mut table = ProviderTable::default();
+
+        // signed to live for a hundred TTLs
+        let announcement = announcer.next(peer_id, set(&["a"]), false, NOW).unwrap();
+        let far = SignedAnnouncement::sign(announcement, NOW + 100 * ANNOUNCEMENT_TTL_MS, &keypair)
+            .unwrap();
+        assert_eq!(table.apply(far, NOW), Ok(ApplyResult::Applied));
+        assert_eq!(table.export(NOW)[0].expires_at, NOW + ANNOUNCEMENT_TTL_MS);
+        assert!(table.providers("a", NOW + ANNOUNCEMENT_TTL_MS).is_empty());
+
+        // the peer restarted and announces from seq 1 again after its records expired
+        let later = NOW + ANNOUNCEMENT_TTL_MS;
+        let mut restarted = ProviderAnnouncer::default();
+        let announcement = restarted.next(peer_id, set(&["b"]), false, later).unwrap();
+        let fresh =
+            SignedAnnouncement::sign(announcement, later + ANNOUNCEMENT_TTL_MS, &keypair).unwrap();
+        assert_eq!(table.apply(fresh.clone(), later), Ok(ApplyResult::Applied));
+        assert_eq!(table.providers("b", later), vec![peer_id.to_base58()]);
+        assert_eq!(table.apply(fresh, later), Ok(ApplyResult::Stale));
+    }
+
+    #[test]
+    fn table_keeps_client_registrations_for_the_session_window() {
+        let client = PeerId::random().to_base58();
+        let mut table = ProviderTable::default();
+
+        assert!(table.register_client(&client, "a".to_string(), 2, NOW));
+        assert!(table.register_client(&client, "b".to_string(), 2, NOW));
+        assert!(table.register_client(&client, "b".to_string(), 2, NOW));
+        assert!(!table.register_client(&client, "c".to_string(), 2, NOW));
+        assert!(table.unregister_client(&client, "b", NOW));
+        assert_eq!(table.providers("a", NOW), vec![client.clone()]);
+        assert!(table.export(NOW).is_empty());
+
+        // the client disconnects, then resumes within the window
+        let window = 60_000;
+        table.expire_client(&client, NOW + window, NOW);
+        assert_eq!(table.providers("a", NOW + window - 1), vec![client.clone()]);
+        assert_eq!(table.resume_client(&client, NOW + 1), vec!["a".to_string()]);
+        assert_eq!(table.providers("a", NOW + window), vec![client.clone()]);
+
+        // and doesn't come back in time
+        let later = NOW + 2 * window;
+        table.expire_client(&client, later + window, later);
+        assert!(table.providers("a", later + window).is_empty());
+        assert!(table.resume_client(&client, later + window).is_empty());
+        // an expired registration isn't extended by another disconnect
+        table.expire_client(&client, later + 3 * window, later + window);
+        assert!(table.providers("a", later + window).is_empty());
+
+        // registering again starts from scratch
+        assert!(table.register_client(&client, "c".to_string(), 2, later + window));
+        assert_eq!(
+            table.resume_client(&client, later + window),
+            vec!["c".to_string()]
+        );
+        table.remove_client(&client);
+        assert!(table.providers("c", later + window).is_empty());
+    }
+
+    #[test]
+    fn table_imports_exported_records() {
+        let keypair = KeyPair::generate_ed25519();
+        let peer_id = keypair.get_peer_id();
+        let mut announcer = ProviderAnnouncer::default();
+        let mut table = ProviderTable::default();
+
+        let mut next = |current| {
+            signed(
+                announcer.next(peer_id, current, false, NOW).unwrap(),
+                &keypair,
+            )
+        };
+        let full = next(set(&["a"]));
+        let delta = next(set(&["a", "b"]));
+        table.apply(full.clone(), NOW).unwrap();
+        table.apply(delta, NOW).unwrap();
+
+        let exported = table.export(NOW);
+        assert_eq!(
+            exported,
+            vec![ProviderRecord {
+                peer_id: peer_id.to_base58(),
+                seq: 2,
+                expires_at: full.expires_at,
+                providers: vec!["a".to_string(), "b".to_string()],
+            }]
+        );
+
+        let mut joined = ProviderTable::default();
+        assert_eq!(joined.apply(full, NOW), Ok(ApplyResult::Applied));
+        assert_eq!(
+            joined.import(exported[0].clone(), NOW),
+            ApplyResult::Applied
+        );
+        assert_eq!(joined.import(exported[0].clone(), NOW), ApplyResult::Stale);
+        assert_eq!(joined.providers("b", NOW), vec![peer_id.to_base58()]);
+        assert_eq!(
+            joined.import(exported[0].clone(), exported[0].expires_at),
+            ApplyResult::Stale
+        );
+        assert!(table.export(exported[0].expires_at).is_empty());
+    }
+}
+
+#[cfg(test)]
+mod prop_tests {
+    use super::*;
+    use proptest::collection::{btree_set, vec};
+    use proptest::prelude::*;
+
+    const NOW: u64 = 1_000_000;
+    const ALIASES: [&str; 5] = ["a", "b", "c", "d", "e"];
+    const MAX_ANNOUNCEMENTS: usize = 64;
+
+    /// Provider sets each peer goes through, one set per registration change
+    fn topology() -> impl Strategy<Value = Vec<Vec<BTreeSet<String>>>> {
+        vec(vec(btree_set("[a-e]", 0..4), 1..12), 1..5)
+    }
+
+    fn providers_of(table: &ProviderTable, now: u64) -> Vec<Vec<String>> {
+        ALIASES
+            .iter()
+            .map(|alias| {
+                let mut providers = table.providers(alias, now);
+                providers.sort();
+                providers
+            })
+            .collect()
+    }
+
+    proptest! {
+        /// Whatever is lost or reordered on the way, a full sync of every peer makes the table
+        /// route each alias to exactly the peers currently providing it
+        #[test]
+        fn table_converges(
+            topology in topology(),
+            order in vec(any::<u32>(), MAX_ANNOUNCEMENTS),
+            dropped in vec(prop::bool::weighted(0.2), MAX_ANNOUNCEMENTS),
+        ) {
+            let peers: Vec<_> = topology.iter().map(|_| PeerId::random()).collect();
+            let mut announcers: Vec<_> = peers.iter().map(|_| ProviderAnnouncer::default()).collect();
+            let mut announcements = vec![];
+            for ((peer_id, announcer), sets) in peers.iter().zip(&mut announcers).zip(&topology) {
+                for set in sets {
+                    announcements.extend(announcer.next(*peer_id, set.clone(), false, NOW));
+                }
+            }
+            let mut delivery: Vec<_> = announcements.into_iter().zip(order).zip(dropped).collect();
+            delivery.sort_by_key(|((_, order), _)| *order);
+
+            let mut table = ProviderTable::default();
+            for ((announcement, _), dropped) in delivery {
+                if dropped {
+                    continue;
+                }
+                let before = providers_of(&table, NOW);
+                let result = table.apply_verified(announcement, NOW + ANNOUNCEMENT_TTL_MS, NOW);
+                if result != ApplyResult::Applied {
+                    prop_assert_eq!(providers_of(&table, NOW), before);
+                }
+            }
+
+            for ((peer_id, announcer), sets) in peers.iter().zip(&mut announcers).zip(&topology) {
+                let last = sets.last().cloned().unwrap_or_default();
+                let full = announcer.next(*peer_id, last, true, NOW).unwrap();
+                let result = table.apply_verified(full, NOW + ANNOUNCEMENT_TTL_MS, NOW);
+                prop_assert_eq!(result, ApplyResult::Applied);
+            }
+
+            for alias in ALIASES {
+                let mut expected: Vec<_> = peers
+                    .iter()
+                    .zip(&topology)
+                    .filter(|(_, sets)| sets.last().map_or(false, |set| set.contains(alias)))
+                    .map(|(peer_id, _)| peer_id.to_base58())
+                    .collect();
+                expected.sort();
+                let mut providers = table.providers(alias, NOW);
+                providers.sort();
+                prop_assert_eq!(providers, expected);
+            }
+        }
+
+        /// Calls are never routed to a peer whose latest applied announcement has expired
+        #[test]
+        fn expired_providers_are_not_routed(
+            topology in topology(),
+            ttls in vec(1..ANNOUNCEMENT_TTL_MS, MAX_ANNOUNCEMENTS),
+            now in 0..ANNOUNCEMENT_TTL_MS,
+        ) {
+            let mut table = ProviderTable::default();
+            let mut expiry = HashMap::new();
+            let mut ttls = ttls.into_iter();
+            for sets in &topology {
+                let peer_id = PeerId::random();
+                let mut announcer = ProviderAnnouncer::default();
+                for set in sets {
+                    let Some(announcement) = announcer.next(peer_id, set.clone(), false, NOW) else {
+                        continue;
+                    };
+                    let expires_at = NOW + ttls.next().unwrap();
+                    if table.apply_verified(announcement, expires_at, NOW) == ApplyResult::Applied {
+                        expiry.insert(peer_id.to_base58(), expires_at);
+                    }
+                }
+            }
+
+            for providers in providers_of(&table, NOW + now) {
+                for peer_id in providers {
+                    prop_assert!(expiry[&peer_id] > NOW + now);
+                }
+            }
+        }
+    }
+}
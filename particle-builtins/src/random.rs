@@ -0,0 +1,109 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+use serde_json::{json, Value as JValue};
+
+use particle_args::{Args, JError};
+
+/// Seeded generator if the optional `seed` argument is given, so results can be reproduced
+fn rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_rng(thread_rng()).expect("thread_rng never fails"),
+    }
+}
+
+/// random integer in [min, max], takes an optional seed
+pub fn int(args: Args) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let min: i64 = Args::next("min", &mut args)?;
+    let max: i64 = Args::next("max", &mut args)?;
+    let seed: Option<u64> = Args::next_opt("seed", &mut args)?;
+    if min > max {
+        return Err(JError::new(format!("min {min} is greater than max {max}")));
+    }
+
+    Ok(json!(rng(seed).gen_range(min..=max)))
+}
+
+/// random float in [0, 1), takes an optional seed
+pub fn float(args: Args) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let seed: Option<u64> = Args::next_opt("seed", &mut args)?;
+
+    Ok(json!(rng(seed).gen::<f64>()))
+}
+
+/// true with probability `p`, takes an optional seed
+pub fn sample(args: Args) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let p: f64 = Args::next("p", &mut args)?;
+    let seed: Option<u64> = Args::next_opt("seed", &mut args)?;
+    if !(0.0..=1.0).contains(&p) {
+        return Err(JError::new(format!("probability {p} is not in [0, 1]")));
+    }
+
+    Ok(json!(rng(seed).gen_bool(p)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(function_args: Vec<JValue>) -> Args {
+        Args {
+            service_id: "rand".into(),
+            function_name: "test".into(),
+            function_args,
+            tetraplets: vec![],
+            sequence: None,
+        }
+    }
+
+    #[test]
+    fn seeded_is_reproducible() {
+        let first = int(args(vec![json!(0), json!(1000), json!([42])])).unwrap();
+        let second = int(args(vec![json!(0), json!(1000), json!([42])])).unwrap();
+        assert_eq!(first, second);
+
+        let first = float(args(vec![json!([7])])).unwrap();
+        let second = float(args(vec![json!([7])])).unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn int_in_range() {
+        for _ in 0..100 {
+            let x = int(args(vec![json!(-3), json!(3), json!([])])).unwrap();
+            assert!((-3..=3).contains(&x.as_i64().unwrap()));
+        }
+        assert!(int(args(vec![json!(3), json!(-3), json!([])])).is_err());
+    }
+
+    #[test]
+    fn sample_bounds() {
+        let never = sample(args(vec![json!(0.0), json!([])])).unwrap();
+        assert_eq!(never, json!(false));
+        let always = sample(args(vec![json!(1.0), json!([])])).unwrap();
+        assert_eq!(always, json!(true));
+        assert!(sample(args(vec![json!(1.5), json!([])])).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use particle_args::{ErrorCode, JError};
+
+/// Builtins changing services, spells, workers or their configs, rejected on read-only replicas
+const MUTATING_BUILTINS: &[(&str, &[&str])] = &[
+    (
+        "srv",
+        &["create", "add_alias", "remove", "import", "engine_migrate"],
+    ),
+    (
+        "dist",
+        &[
+            "add_module_from_vault",
+            "add_module",
+            "add_module_bytes_from_vault",
+            "add_blueprint",
+        ],
+    ),
+    (
+        "spell",
+        &[
+            "install",
+            "install_builtin",
+            "remove",
+            "update_trigger_config",
+            "update_script",
+            "import",
+            "kv_set_if_equals",
+            "kv_incr",
+            "set_resource_triggers",
+            "set_kv_triggers",
+            "set_probe_triggers",
+            "set_health_triggers",
+            "set_partition_triggers",
+            "set_custom_triggers",
+            "set_webhook",
+            "set_missed_runs",
+            "set_exclusion_windows",
+            "set_peer_payload",
+            "set_sinks",
+            "pause",
+            "resume",
+        ],
+    ),
+    (
+        "worker",
+        &["create", "remove", "activate", "deactivate", "transfer"],
+    ),
+    ("sched", &["submit", "cancel"]),
+];
+
+pub(crate) fn is_mutating(service_id: &str, function_name: &str) -> bool {
+    MUTATING_BUILTINS
+        .iter()
+        .any(|(service, functions)| *service == service_id && functions.contains(&function_name))
+}
+
+pub(crate) fn read_only_error(service_id: &str, function_name: &str) -> JError {
+    JError::with_code(
+        ErrorCode::ReadOnly,
+        format!("{service_id}.{function_name} is rejected: the node is a read-only replica"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_mutating() {
+        assert!(is_mutating("srv", "create"));
+        assert!(is_mutating("spell", "install"));
+        assert!(is_mutating("worker", "create"));
+        assert!(!is_mutating("srv", "list"));
+        assert!(!is_mutating("spell", "list"));
+        assert!(!is_mutating("kad", "neighborhood"));
+        assert!(!is_mutating("create", "srv"));
+    }
+}
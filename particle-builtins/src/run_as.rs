@@ -0,0 +1,128 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use particle_args::{ErrorCode, JError};
+use particle_services::RunAs;
+
+/// Services implemented by `Builtins::builtins_call`, custom services are registered at runtime
+const BUILTIN_SERVICES: &[&str] = &[
+    "peer",
+    "kad",
+    "srv",
+    "event",
+    "capability",
+    "providers",
+    "collect",
+    "dist",
+    "op",
+    "debug",
+    "stat",
+    "math",
+    "time",
+    "rand",
+    "id",
+    "cmp",
+    "array",
+    "sig",
+    "crypto",
+    "json",
+    "vault",
+    "subnet",
+    "run-console",
+    "aqua-ipfs",
+];
+
+/// Builtins without side effects on the node, granted to every run-as identity.
+/// Empty function list means all functions of the service
+const IMPLICIT_GRANTS: &[(&str, &[&str])] = &[
+    ("op", &[]),
+    ("debug", &[]),
+    ("math", &[]),
+    ("cmp", &[]),
+    ("array", &[]),
+    ("json", &[]),
+    ("crypto", &[]),
+    ("rand", &[]),
+    ("id", &[]),
+    ("run-console", &[]),
+    ("peer", &["timestamp_ms", "timestamp_sec"]),
+    ("time", &[]),
+    ("sig", &["verify", "get_peer_id"]),
+    ("getDataSrv", &[]),
+    ("errorHandlingSrv", &[]),
+    ("callbackSrv", &[]),
+];
+
+pub(crate) fn is_builtin_service(service_id: &str) -> bool {
+    BUILTIN_SERVICES.contains(&service_id)
+}
+
+/// Whether a spell under `run_as` may call the builtin `service_id.function_name`
+pub(crate) fn run_as_allows(run_as: &RunAs, service_id: &str, function_name: &str) -> bool {
+    let implicit = IMPLICIT_GRANTS.iter().any(|(service, functions)| {
+        *service == service_id && (functions.is_empty() || functions.contains(&function_name))
+    });
+    implicit || run_as.allows(service_id, &[], function_name)
+}
+
+pub(crate) fn run_as_error(service_id: &str, function_name: &str, run_as: &RunAs) -> JError {
+    JError::with_code(
+        ErrorCode::PermissionDenied,
+        format!(
+            "{service_id}.{function_name} is rejected: spell identity '{}' isn't granted it",
+            run_as.name
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use particle_services::Grant;
+
+    use super::*;
+
+    fn reader() -> RunAs {
+        RunAs {
+            name: "reader".to_string(),
+            grants: vec![Grant {
+                service: "kv".to_string(),
+                functions: vec!["get".to_string()],
+            }],
+        }
+    }
+
+    #[test]
+    fn only_granted_and_side_effect_free_builtins_are_allowed() {
+        let run_as = reader();
+        assert!(run_as_allows(&run_as, "kv", "get"));
+        assert!(run_as_allows(&run_as, "op", "noop"));
+        assert!(run_as_allows(&run_as, "peer", "timestamp_sec"));
+        assert!(run_as_allows(&run_as, "getDataSrv", "spell_id"));
+
+        // builtins changing the node or reaching outside of it aren't mutating in the
+        // read-only sense, but a run-as identity still needs a grant for them
+        assert!(!run_as_allows(&run_as, "capability", "disable"));
+        assert!(!run_as_allows(&run_as, "self_update", "download"));
+        assert!(!run_as_allows(&run_as, "rpc", "call"));
+        assert!(!run_as_allows(&run_as, "session", "open"));
+        assert!(!run_as_allows(&run_as, "peer", "connect"));
+        assert!(!run_as_allows(&run_as, "sig", "sign"));
+        assert!(!run_as_allows(&run_as, "srv", "create"));
+    }
+}
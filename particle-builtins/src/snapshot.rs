@@ -0,0 +1,226 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Snapshots of provider and worker records for fast sync between trusted nodes.
+//! A node joining a cluster doesn't have to wait for announcements and worker records
+//! to reach it organically: it fetches a snapshot of them from a designated peer.
+//! Provider records in a snapshot aren't signed by the announcing peers, so the whole snapshot
+//! is signed by the peer that made it, and it's only applied if that peer is trusted
+//! and the snapshot is fresh enough.
+
+use std::collections::HashSet;
+
+use fluence_keypair::{KeyPair, PublicKey, Signature};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::providers::ProviderRecord;
+use crate::subnet::WorkerRecord;
+
+/// Snapshots made by peers with clocks ahead of ours by up to this much are still accepted
+const MAX_CLOCK_SKEW_MS: u64 = 60 * 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistrySnapshot {
+    /// Peer that made and signed the snapshot
+    pub issuer: String,
+    /// Unix timestamp in milliseconds
+    pub created_at: u64,
+    pub providers: Vec<ProviderRecord>,
+    pub workers: Vec<WorkerRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedSnapshot {
+    pub snapshot: RegistrySnapshot,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SnapshotError {
+    #[error("Registry snapshot signature doesn't match issuer {0}")]
+    InvalidSignature(String),
+    #[error("Registry snapshot issuer {0} isn't trusted")]
+    UntrustedIssuer(String),
+    #[error(
+        "Registry snapshot of {issuer} made at {created_at} isn't fresh, max age is {max_age_ms}ms"
+    )]
+    Stale {
+        issuer: String,
+        created_at: u64,
+        max_age_ms: u64,
+    },
+    #[error("Failed to sign registry snapshot: {0}")]
+    Signing(String),
+}
+
+impl SignedSnapshot {
+    pub fn sign(snapshot: RegistrySnapshot, keypair: &KeyPair) -> Result<Self, SnapshotError> {
+        let signature = keypair
+            .sign(&signed_bytes(&snapshot)?)
+            .map_err(|err| SnapshotError::Signing(err.to_string()))?;
+        Ok(Self {
+            snapshot,
+            signature: signature.to_vec().to_vec(),
+        })
+    }
+
+    /// Checks that the signature was made by the key of the issuer
+    pub fn verify(&self) -> Result<(), SnapshotError> {
+        let issuer = &self.snapshot.issuer;
+        let invalid = || SnapshotError::InvalidSignature(issuer.clone());
+        let peer_id: PeerId = issuer.parse().map_err(|_| invalid())?;
+        let pk = PublicKey::try_from(peer_id).map_err(|_| invalid())?;
+        let signature = Signature::from_bytes(pk.get_key_format(), self.signature.clone());
+        let bytes = signed_bytes(&self.snapshot).map_err(|_| invalid())?;
+        pk.verify(&bytes, &signature).map_err(|_| invalid())
+    }
+}
+
+/// Bytes covered by the signature: the snapshot serialized to JSON.
+/// Field order is fixed by the struct definitions, so any peer gets the same bytes
+/// after deserializing the snapshot.
+fn signed_bytes(snapshot: &RegistrySnapshot) -> Result<Vec<u8>, SnapshotError> {
+    serde_json::to_vec(snapshot).map_err(|err| SnapshotError::Signing(err.to_string()))
+}
+
+/// Which snapshots are applied
+#[derive(Debug, Clone)]
+pub struct SnapshotPolicy {
+    trusted_peers: HashSet<PeerId>,
+    max_age_ms: u64,
+}
+
+impl SnapshotPolicy {
+    pub fn new(trusted_peers: impl IntoIterator<Item = PeerId>, max_age_ms: u64) -> Self {
+        Self {
+            trusted_peers: trusted_peers.into_iter().collect(),
+            max_age_ms,
+        }
+    }
+
+    /// Checks that the snapshot is signed by a trusted peer and isn't older than the max age
+    pub fn check(&self, signed: &SignedSnapshot, now: u64) -> Result<(), SnapshotError> {
+        let snapshot = &signed.snapshot;
+        let trusted = snapshot
+            .issuer
+            .parse()
+            .map_or(false, |issuer| self.trusted_peers.contains(&issuer));
+        if !trusted {
+            return Err(SnapshotError::UntrustedIssuer(snapshot.issuer.clone()));
+        }
+        signed.verify()?;
+
+        let too_old = now.saturating_sub(snapshot.created_at) > self.max_age_ms;
+        let from_future = snapshot.created_at > now.saturating_add(MAX_CLOCK_SKEW_MS);
+        if too_old || from_future {
+            return Err(SnapshotError::Stale {
+                issuer: snapshot.issuer.clone(),
+                created_at: snapshot.created_at,
+                max_age_ms: self.max_age_ms,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Number of records applied from a snapshot
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SnapshotImport {
+    pub providers: usize,
+    pub workers: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000_000;
+    const MAX_AGE_MS: u64 = 60_000;
+
+    fn signed(issuer: &KeyPair, created_at: u64) -> SignedSnapshot {
+        let snapshot = RegistrySnapshot {
+            issuer: issuer.get_peer_id().to_base58(),
+            created_at,
+            providers: vec![ProviderRecord {
+                peer_id: PeerId::random().to_base58(),
+                seq: 3,
+                expires_at: NOW + 1,
+                providers: vec!["a".to_string()],
+            }],
+            workers: vec![],
+        };
+        SignedSnapshot::sign(snapshot, issuer).unwrap()
+    }
+
+    #[test]
+    fn accepts_fresh_snapshots_of_trusted_peers() {
+        let issuer = KeyPair::generate_ed25519();
+        let policy = SnapshotPolicy::new([issuer.get_peer_id()], MAX_AGE_MS);
+
+        assert_eq!(policy.check(&signed(&issuer, NOW - 1), NOW), Ok(()));
+
+        // survives a round trip through JSON
+        let json = serde_json::to_value(signed(&issuer, NOW)).unwrap();
+        let parsed: SignedSnapshot = serde_json::from_value(json).unwrap();
+        assert_eq!(policy.check(&parsed, NOW), Ok(()));
+
+        let stale = signed(&issuer, NOW - MAX_AGE_MS - 1);
+        assert!(matches!(
+            policy.check(&stale, NOW),
+            Err(SnapshotError::Stale { .. })
+        ));
+        let from_future = signed(&issuer, NOW + MAX_CLOCK_SKEW_MS + 1);
+        assert!(matches!(
+            policy.check(&from_future, NOW),
+            Err(SnapshotError::Stale { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_untrusted_and_tampered_snapshots() {
+        let issuer = KeyPair::generate_ed25519();
+        let stranger = KeyPair::generate_ed25519();
+        let policy = SnapshotPolicy::new([issuer.get_peer_id()], MAX_AGE_MS);
+
+        assert_eq!(
+            policy.check(&signed(&stranger, NOW), NOW),
+            Err(SnapshotError::UntrustedIssuer(
+                stranger.get_peer_id().to_base58()
+            ))
+        );
+
+        // signed by a stranger on behalf of the trusted peer
+        let mut forged = signed(&stranger, NOW);
+        forged.snapshot.issuer = issuer.get_peer_id().to_base58();
+        assert_eq!(
+            policy.check(&forged, NOW),
+            Err(SnapshotError::InvalidSignature(
+                issuer.get_peer_id().to_base58()
+            ))
+        );
+
+        let mut tampered = signed(&issuer, NOW);
+        tampered.snapshot.providers[0].seq += 1;
+        assert!(matches!(
+            policy.check(&tampered, NOW),
+            Err(SnapshotError::InvalidSignature(_))
+        ));
+    }
+}
@@ -0,0 +1,229 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use fluence_keypair::{KeyPair, PublicKey, Signature};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use types::DealId;
+
+/// How long a signed worker record stays valid
+pub const WORKER_RECORD_TTL_MS: u64 = 60 * 60 * 1000;
+
+/// Record of a deal worker signed by the worker key, so it can be spread
+/// through any peers without letting them add forged workers to a subnet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerRecord {
+    pub deal_id: DealId,
+    pub worker_id: String,
+    /// Host of the worker, the worker is reachable through it
+    pub relay_id: String,
+    /// Unix timestamp in milliseconds after which the record is dropped
+    pub expires_at: u64,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WorkerRecordError {
+    #[error("Worker record signature doesn't match worker {0}")]
+    InvalidSignature(String),
+    #[error("Worker record of worker {worker_id} expired at {expires_at}")]
+    Expired { worker_id: String, expires_at: u64 },
+    #[error("Failed to sign worker record: {0}")]
+    Signing(String),
+}
+
+impl WorkerRecord {
+    pub fn sign(
+        deal_id: DealId,
+        worker_id: PeerId,
+        relay_id: PeerId,
+        expires_at: u64,
+        keypair: &KeyPair,
+    ) -> Result<Self, WorkerRecordError> {
+        let mut record = Self {
+            deal_id,
+            worker_id: worker_id.to_base58(),
+            relay_id: relay_id.to_base58(),
+            expires_at,
+            signature: vec![],
+        };
+        record.signature = keypair
+            .sign(&record.signed_bytes())
+            .map_err(|err| WorkerRecordError::Signing(err.to_string()))?
+            .to_vec()
+            .to_vec();
+        Ok(record)
+    }
+
+    /// Checks that the signature was made by the key of the worker
+    pub fn verify(&self) -> Result<(), WorkerRecordError> {
+        let invalid = || WorkerRecordError::InvalidSignature(self.worker_id.clone());
+        let worker_id: PeerId = self.worker_id.parse().map_err(|_| invalid())?;
+        let pk = PublicKey::try_from(worker_id).map_err(|_| invalid())?;
+        let signature = Signature::from_bytes(pk.get_key_format(), self.signature.clone());
+        pk.verify(&self.signed_bytes(), &signature)
+            .map_err(|_| invalid())
+    }
+
+    /// Bytes covered by the signature: deal id, worker id and relay id,
+    /// each followed by a zero byte, then expires_at u64 as little-endian bytes
+    fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for field in [self.deal_id.as_str(), &self.worker_id, &self.relay_id] {
+            bytes.extend(field.as_bytes());
+            bytes.push(0);
+        }
+        bytes.extend(self.expires_at.to_le_bytes());
+        bytes
+    }
+}
+
+/// Worker of a deal as returned by `subnet.resolve_workers`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolvedWorker {
+    pub worker_id: String,
+    pub relay_id: String,
+}
+
+/// Workers of deals across the network, built from their signed records.
+/// Records are cached until they expire, workers renew them by publishing new ones.
+#[derive(Debug, Default)]
+pub struct WorkerRegistry {
+    deals: HashMap<DealId, HashMap<String, WorkerRecord>>,
+}
+
+impl WorkerRegistry {
+    /// Verifies the signature and expiry of the record before caching it.
+    /// Returns false if a record of the worker expiring later is already cached.
+    pub fn register(&mut self, record: WorkerRecord, now: u64) -> Result<bool, WorkerRecordError> {
+        record.verify()?;
+        if record.expires_at <= now {
+            return Err(WorkerRecordError::Expired {
+                worker_id: record.worker_id,
+                expires_at: record.expires_at,
+            });
+        }
+
+        let workers = self.deals.entry(record.deal_id.clone()).or_default();
+        workers.retain(|_, known| known.expires_at > now);
+        match workers.get(&record.worker_id) {
+            Some(known) if known.expires_at >= record.expires_at => Ok(false),
+            _ => {
+                workers.insert(record.worker_id.clone(), record);
+                Ok(true)
+            }
+        }
+    }
+
+    /// Records of all workers that haven't expired yet
+    pub fn export(&self, now: u64) -> Vec<WorkerRecord> {
+        let mut records: Vec<_> = self
+            .deals
+            .values()
+            .flat_map(|workers| workers.values())
+            .filter(|record| record.expires_at > now)
+            .cloned()
+            .collect();
+        records.sort_by(|a, b| (&a.deal_id, &a.worker_id).cmp(&(&b.deal_id, &b.worker_id)));
+        records
+    }
+
+    /// Workers of the deal, skipping those whose records expired
+    pub fn resolve(&self, deal_id: &DealId, now: u64) -> Vec<ResolvedWorker> {
+        let mut workers: Vec<_> = self
+            .deals
+            .get(deal_id)
+            .into_iter()
+            .flat_map(|workers| workers.values())
+            .filter(|record| record.expires_at > now)
+            .map(|record| ResolvedWorker {
+                worker_id: record.worker_id.clone(),
+                relay_id: record.relay_id.clone(),
+            })
+            .collect();
+        workers.sort_by(|a, b| a.worker_id.cmp(&b.worker_id));
+        workers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000_000;
+    const DEAL: &str = "0x1234567890abcdef1234567890abcdef12345678";
+
+    fn record(keypair: &KeyPair, expires_at: u64) -> WorkerRecord {
+        WorkerRecord::sign(
+            DealId::from(DEAL),
+            keypair.get_peer_id(),
+            PeerId::random(),
+            expires_at,
+            keypair,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn registry_resolves_registered_workers() {
+        let worker = KeyPair::generate_ed25519();
+        let mut registry = WorkerRegistry::default();
+
+        let first = record(&worker, NOW + WORKER_RECORD_TTL_MS);
+        let renewed = record(&worker, NOW + WORKER_RECORD_TTL_MS + 1);
+        assert_eq!(registry.register(first.clone(), NOW), Ok(true));
+        assert_eq!(registry.register(renewed.clone(), NOW), Ok(true));
+        assert_eq!(registry.register(first, NOW), Ok(false));
+
+        let deal_id = DealId::from(DEAL);
+        assert_eq!(
+            registry.resolve(&deal_id, NOW),
+            vec![ResolvedWorker {
+                worker_id: renewed.worker_id,
+                relay_id: renewed.relay_id,
+            }]
+        );
+        assert!(registry
+            .resolve(&deal_id, NOW + WORKER_RECORD_TTL_MS + 1)
+            .is_empty());
+    }
+
+    #[test]
+    fn registry_rejects_forged_and_expired_records() {
+        let worker = KeyPair::generate_ed25519();
+        let mut registry = WorkerRegistry::default();
+
+        let mut forged = record(&worker, NOW + WORKER_RECORD_TTL_MS);
+        forged.relay_id = PeerId::random().to_base58();
+        assert_eq!(
+            registry.register(forged.clone(), NOW),
+            Err(WorkerRecordError::InvalidSignature(forged.worker_id))
+        );
+
+        let expired = record(&worker, NOW);
+        assert!(matches!(
+            registry.register(expired, NOW),
+            Err(WorkerRecordError::Expired { .. })
+        ));
+        assert!(registry.resolve(&DealId::from(DEAL), NOW).is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use humantime_serde::re::humantime;
+
+use particle_args::JError;
+
+/// Monotonic clock for scripts. Readings are milliseconds since the node started, so they only
+/// make sense when compared to other readings from the same node, but never go backwards.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    started_at: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+        }
+    }
+
+    /// milliseconds since the node started
+    pub fn now_ms(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+
+    /// milliseconds passed since the `since` reading, 0 if `since` is in the future
+    pub fn elapsed_ms(&self, since: u64) -> Result<u64, JError> {
+        Ok(self.now_ms().saturating_sub(since))
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// human readable duration ("1h 30m", "500ms") to milliseconds
+pub fn parse_duration_ms(duration: String) -> Result<u64, JError> {
+    let duration = humantime::parse_duration(&duration)
+        .map_err(|err| JError::new(format!("invalid duration '{duration}': {err}")))?;
+    Ok(duration.as_millis() as u64)
+}
+
+/// milliseconds to a human readable duration
+pub fn format_duration_ms(ms: u64) -> Result<String, JError> {
+    Ok(humantime::format_duration(Duration::from_millis(ms)).to_string())
+}
+
+/// min(base * 2 ^ attempt, max) (exponential backoff interval)
+pub fn backoff_ms(base: u64, attempt: u32, max: u64) -> Result<u64, JError> {
+    let backoff = 2u64
+        .checked_pow(attempt)
+        .and_then(|factor| base.checked_mul(factor))
+        .unwrap_or(u64::MAX);
+    Ok(backoff.min(max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monotonic_elapsed() {
+        let clock = MonotonicClock::new();
+        let start = clock.now_ms();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(clock.elapsed_ms(start).unwrap() >= 5);
+        assert_eq!(clock.elapsed_ms(u64::MAX).unwrap(), 0);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration_ms("1m 30s".into()).unwrap(), 90_000);
+        assert!(parse_duration_ms("soon".into()).is_err());
+        assert_eq!(format_duration_ms(90_000).unwrap(), "1m 30s");
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        assert_eq!(backoff_ms(100, 0, 10_000).unwrap(), 100);
+        assert_eq!(backoff_ms(100, 3, 10_000).unwrap(), 800);
+        assert_eq!(backoff_ms(100, 10, 10_000).unwrap(), 10_000);
+        assert_eq!(backoff_ms(100, 100, 10_000).unwrap(), 10_000);
+    }
+}
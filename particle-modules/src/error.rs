@@ -160,6 +160,16 @@ pub enum ModuleError {
         module_cid: String,
         binary_name: String,
     },
+    #[error("Unsupported package format version {version}")]
+    UnsupportedPackageVersion { version: u32 },
+    #[error("Module {module_name} in the package doesn't match its pinned hash: expected {expected}, got {actual}")]
+    PackageHashMismatch {
+        module_name: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Package blueprint id doesn't match its contents: expected {expected}, got {actual}")]
+    PackageBlueprintMismatch { expected: String, actual: String },
     #[error(transparent)]
     Vault(#[from] VaultError),
     #[error(transparent)]
@@ -36,11 +36,13 @@ extern crate fstrings;
 mod error;
 mod files;
 mod modules;
+mod package;
 
 pub use error::ModuleError;
 pub use files::{load_blueprint, load_module_by_path, load_module_descriptor};
 pub use modules::EffectorsMode;
 pub use modules::ModuleRepository;
+pub use package::{BlueprintPackage, PackagedModule, PACKAGE_FORMAT_VERSION};
 
 // reexport
 pub use fluence_app_service::{
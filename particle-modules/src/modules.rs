@@ -43,7 +43,8 @@ use crate::error::ModuleError::{
     BlueprintNotFound, EmptyDependenciesList, ReadModuleInterfaceError,
 };
 use crate::error::Result;
-use crate::files::{self, load_config_by_path, load_module_descriptor};
+use crate::files::{self, load_config_by_path, load_module_by_path, load_module_descriptor};
+use crate::package::{BlueprintPackage, PackagedModule, PACKAGE_FORMAT_VERSION};
 use crate::ModuleError::{
     ForbiddenEffector, IncorrectVaultModuleConfig, InvalidEffectorMountedBinary,
     SerializeBlueprintJson,
@@ -358,6 +359,45 @@ impl ModuleRepository {
         Ok(module_descriptors)
     }
 
+    /// Packs blueprint with its modules and their configs into a self-contained package
+    pub fn export_blueprint(&self, blueprint_id: &str) -> Result<BlueprintPackage> {
+        let blueprint = self.get_blueprint_from_cache(blueprint_id)?;
+
+        let modules = blueprint
+            .dependencies
+            .into_iter()
+            .map(|hash| {
+                let wasm =
+                    load_module_by_path(&self.modules_dir.join(module_file_name_hash(&hash)))?;
+                let config =
+                    load_config_by_path(&self.modules_dir.join(module_config_name_hash(&hash)))?;
+                Ok(PackagedModule {
+                    hash,
+                    config,
+                    wasm: base64.encode(wasm),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(BlueprintPackage {
+            format_version: PACKAGE_FORMAT_VERSION,
+            blueprint_id: blueprint.id,
+            name: blueprint.name,
+            modules,
+        })
+    }
+
+    /// Adds modules and blueprint from the package, returns blueprint id.
+    /// All module hashes are verified before anything is written to disk.
+    pub fn import_blueprint(&self, package: BlueprintPackage) -> Result<String> {
+        let modules = package.verify()?;
+        for (name, module) in modules {
+            self.add_module(name, module)?;
+        }
+
+        self.add_blueprint(package.add_blueprint())
+    }
+
     fn get_module_effects(module: &[u8]) -> Result<(bool, HashSet<String>)> {
         let effects = effects::extract_from_bytes(module)?;
         let mut logger_enabled = false;
@@ -509,6 +549,35 @@ mod tests {
         assert!(result.is_ok())
     }
 
+    #[test]
+    fn test_export_import_blueprint() {
+        let module_dir = TempDir::new("test").unwrap();
+        let bp_dir = TempDir::new("test2").unwrap();
+        let repo = ModuleRepository::new(module_dir.path(), bp_dir.path(), Default::default());
+
+        let module = load_module(
+            "../crates/nox-tests/tests/tetraplets/artifacts",
+            "tetraplets",
+        )
+        .expect("load module");
+        let hash = repo.add_module("tetra".to_string(), module).unwrap();
+        let blueprint_id = repo
+            .add_blueprint(AddBlueprint::new("tetra".to_string(), vec![hash.clone()]))
+            .unwrap();
+
+        let package = repo.export_blueprint(&blueprint_id).unwrap();
+        assert_eq!(package.modules.len(), 1);
+        assert_eq!(package.modules[0].hash, hash);
+
+        let module_dir2 = TempDir::new("test3").unwrap();
+        let bp_dir2 = TempDir::new("test4").unwrap();
+        let repo2 = ModuleRepository::new(module_dir2.path(), bp_dir2.path(), Default::default());
+        let imported_id = repo2.import_blueprint(package).unwrap();
+
+        assert_eq!(imported_id, blueprint_id);
+        assert!(repo2.get_interface(&hash.to_string()).is_ok());
+    }
+
     #[test]
     fn test_add_module_effector_allowed() {
         let effector_wasm_cid =
@@ -0,0 +1,155 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use fluence_app_service::TomlMarineNamedModuleConfig;
+use serde::{Deserialize, Serialize};
+
+use service_modules::{AddBlueprint, Blueprint, Hash};
+
+use crate::error::{ModuleError::*, Result};
+
+/// Version of the package format, bumped on incompatible changes
+pub const PACKAGE_FORMAT_VERSION: u32 = 1;
+
+/// Module pinned by the hash of its wasm bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackagedModule {
+    pub hash: Hash,
+    /// Config the module was deployed with on the exporting node.
+    /// On import the config is regenerated from module effects under the local effectors policy,
+    /// since mounted binaries are host-specific.
+    pub config: TomlMarineNamedModuleConfig,
+    /// Base64-encoded wasm bytes
+    pub wasm: String,
+}
+
+/// Self-contained blueprint with all of its modules.
+/// Blueprint id is derived from the module hashes, so importing a package
+/// reproduces the same blueprint id on any node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlueprintPackage {
+    pub format_version: u32,
+    pub blueprint_id: String,
+    pub name: String,
+    pub modules: Vec<PackagedModule>,
+}
+
+impl BlueprintPackage {
+    /// Decodes module bytes and checks them against pinned hashes and blueprint id.
+    /// Returns module names with their bytes in the blueprint dependency order.
+    pub fn verify(&self) -> Result<Vec<(String, Vec<u8>)>> {
+        if self.format_version != PACKAGE_FORMAT_VERSION {
+            return Err(UnsupportedPackageVersion {
+                version: self.format_version,
+            });
+        }
+        if self.modules.is_empty() {
+            return Err(EmptyDependenciesList {
+                id: self.name.clone(),
+            });
+        }
+
+        let modules = self
+            .modules
+            .iter()
+            .map(|module| {
+                let bytes = base64.decode(&module.wasm)?;
+                let actual = Hash::new(&bytes)?;
+                if actual != module.hash {
+                    return Err(PackageHashMismatch {
+                        module_name: module.config.name.clone(),
+                        expected: module.hash.to_string(),
+                        actual: actual.to_string(),
+                    });
+                }
+                Ok((module.config.name.clone(), bytes))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let blueprint = Blueprint::new(self.add_blueprint())
+            .map_err(|err| SerializeBlueprintJson(err.to_string()))?;
+        if blueprint.id != self.blueprint_id {
+            return Err(PackageBlueprintMismatch {
+                expected: self.blueprint_id.clone(),
+                actual: blueprint.id,
+            });
+        }
+
+        Ok(modules)
+    }
+
+    pub fn add_blueprint(&self) -> AddBlueprint {
+        AddBlueprint::new(
+            self.name.clone(),
+            self.modules.iter().map(|m| m.hash.clone()).collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD as base64, Engine};
+    use std::assert_matches::assert_matches;
+
+    use service_modules::{AddBlueprint, Blueprint, Hash};
+
+    use crate::ModuleError::{PackageBlueprintMismatch, PackageHashMismatch};
+    use crate::{BlueprintPackage, NamedModuleConfig, PackagedModule, PACKAGE_FORMAT_VERSION};
+
+    fn package(wasm: &[u8]) -> BlueprintPackage {
+        let hash = Hash::new(wasm).unwrap();
+        let blueprint =
+            Blueprint::new(AddBlueprint::new("bp".to_string(), vec![hash.clone()])).unwrap();
+        BlueprintPackage {
+            format_version: PACKAGE_FORMAT_VERSION,
+            blueprint_id: blueprint.id,
+            name: "bp".to_string(),
+            modules: vec![PackagedModule {
+                hash,
+                config: NamedModuleConfig {
+                    name: "module".to_string(),
+                    file_name: None,
+                    load_from: None,
+                    config: <_>::default(),
+                },
+                wasm: base64.encode(wasm),
+            }],
+        }
+    }
+
+    #[test]
+    fn verify_accepts_pinned_modules() {
+        let modules = package(&[1, 2, 3]).verify().unwrap();
+        assert_eq!(modules, vec![("module".to_string(), vec![1, 2, 3])]);
+    }
+
+    #[test]
+    fn verify_rejects_tampered_modules() {
+        let mut package = package(&[1, 2, 3]);
+        package.modules[0].wasm = base64.encode([3, 2, 1]);
+        assert_matches!(package.verify(), Err(PackageHashMismatch { .. }));
+    }
+
+    #[test]
+    fn verify_rejects_renamed_blueprint() {
+        let mut package = package(&[1, 2, 3]);
+        package.name = "other".to_string();
+        assert_matches!(package.verify(), Err(PackageBlueprintMismatch { .. }));
+    }
+}
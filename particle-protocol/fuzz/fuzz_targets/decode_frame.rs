@@ -0,0 +1,38 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+#![no_main]
+
+use asynchronous_codec::{BytesMut, Decoder};
+use libfuzzer_sys::fuzz_target;
+use particle_protocol::FluenceCodec;
+
+fuzz_target!(|data: &[u8]| {
+    // Raw stream as an untrusted peer could send it, length prefixes included
+    let mut codec = FluenceCodec::new();
+    let mut stream = BytesMut::from(data);
+    while let Ok(Some(_)) = codec.decode(&mut stream) {}
+    let _ = codec.decode_eof(&mut stream);
+
+    // The same bytes with a valid length prefix, so the deserializer is reached more often
+    let mut length = unsigned_varint::encode::usize_buffer();
+    let mut frame = BytesMut::from(unsigned_varint::encode::usize(data.len(), &mut length));
+    frame.extend_from_slice(data);
+    let _ = FluenceCodec::new().decode(&mut frame);
+});
@@ -0,0 +1,106 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Generates the seed corpus for the `decode_frame` fuzz target.
+//!
+//! Frames are taken from node logs recorded with `RUST_LOG=particle_protocol::wire=trace`,
+//! plus a few synthetic messages so the corpus is never empty.
+//!
+//! Usage: `cargo run --bin gen_corpus -- corpus/decode_frame [node.log ...]`
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use asynchronous_codec::{BytesMut, Encoder};
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
+use eyre::WrapErr;
+use particle_protocol::{FluenceCodec, Particle, ProtocolMessage};
+
+const FRAME_MARKER: &str = "inbound frame: ";
+
+fn main() -> eyre::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let out_dir = PathBuf::from(
+        args.next()
+            .ok_or_else(|| eyre::eyre!("usage: gen_corpus <out_dir> [log files...]"))?,
+    );
+    fs::create_dir_all(&out_dir)
+        .wrap_err_with(|| format!("failed to create {}", out_dir.display()))?;
+
+    let mut recorded = 0;
+    for log in args {
+        for frame in read_frames(Path::new(&log))? {
+            write_frame(&out_dir, &format!("recorded-{recorded}"), &frame)?;
+            recorded += 1;
+        }
+    }
+
+    let seeds = [
+        ProtocolMessage::Upgrade,
+        ProtocolMessage::Particle(Particle::default()),
+        ProtocolMessage::Particle(Particle {
+            script: "(call %init_peer_id% (\"op\" \"noop\") [])".to_string(),
+            signature: vec![0; 64],
+            data: vec![1, 2, 3],
+            ..<_>::default()
+        }),
+    ];
+    let synthetic = seeds.len();
+    let mut codec = FluenceCodec::new();
+    for (i, seed) in seeds.into_iter().enumerate() {
+        let mut bytes = BytesMut::new();
+        codec.encode(seed, &mut bytes)?;
+        fs::write(out_dir.join(format!("seed-{i}")), &bytes)?;
+    }
+
+    println!(
+        "wrote {recorded} recorded and {synthetic} synthetic inputs to {}",
+        out_dir.display()
+    );
+    Ok(())
+}
+
+/// Extract base64-encoded frames logged by the codec
+fn read_frames(log: &Path) -> eyre::Result<Vec<Vec<u8>>> {
+    let file = fs::File::open(log).wrap_err_with(|| format!("failed to open {}", log.display()))?;
+    let mut frames = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let Some((_, frame)) = line.split_once(FRAME_MARKER) else {
+            continue;
+        };
+        // the frame can be followed by other logfmt fields
+        let frame = frame.split_whitespace().next().unwrap_or_default();
+        match base64.decode(frame.trim_matches('"')) {
+            Ok(frame) => frames.push(frame),
+            Err(err) => eprintln!("skipping invalid frame in {}: {err}", log.display()),
+        }
+    }
+    Ok(frames)
+}
+
+/// Store the frame with its length prefix, as it was on the wire
+fn write_frame(out_dir: &Path, name: &str, frame: &[u8]) -> eyre::Result<()> {
+    let mut length = unsigned_varint::encode::usize_buffer();
+    let mut bytes = unsigned_varint::encode::usize(frame.len(), &mut length).to_vec();
+    bytes.extend_from_slice(frame);
+    fs::write(out_dir.join(name), bytes)?;
+    Ok(())
+}
@@ -17,6 +17,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 #![feature(async_closure)]
+#![feature(assert_matches)]
 #![recursion_limit = "512"]
 #![warn(rust_2018_idioms)]
 #![deny(
@@ -30,7 +31,7 @@
 )]
 
 mod libp2p_protocol {
-    mod codec;
+    pub(super) mod codec;
     pub(super) mod message;
     pub(super) mod upgrade;
 }
@@ -41,11 +42,13 @@ mod particle;
 
 pub use contact::Contact;
 pub use error::ParticleError;
+pub use libp2p_protocol::codec::{FluenceCodec, FluenceCodecError, WIRE_LOG_TARGET};
 pub use libp2p_protocol::message::CompletionChannel;
 pub use libp2p_protocol::message::SendStatus;
 pub use libp2p_protocol::message::{HandlerMessage, ProtocolMessage};
 pub use libp2p_protocol::upgrade::ProtocolConfig;
 pub use particle::ExtendedParticle;
 pub use particle::Particle;
+pub use particle::{TrailHop, MAX_TRAIL_HOPS};
 
 pub const PROTOCOL_NAME: &str = "/fluence/particle/2.0.0";
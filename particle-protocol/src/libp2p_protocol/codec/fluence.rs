@@ -23,11 +23,17 @@ use air_interpreter_sede::{
     ToSerialized as _,
 };
 use asynchronous_codec::{BytesMut, Decoder, Encoder};
+use base64::{engine::general_purpose::STANDARD as base64, Engine};
 use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use unsigned_varint::codec::UviBytes;
 
 const MAX_BUF_SIZE: usize = 100 * 1024 * 1024;
 
+/// Log target for raw inbound frames. With `trace` level enabled, every frame is logged in base64,
+/// which is the input format of the fuzzing corpus generator.
+pub const WIRE_LOG_TARGET: &str = "particle_protocol::wire";
+
 type ProtocolMessageFormat = MsgPackMultiformat;
 
 define_simple_representation!(
@@ -49,19 +55,40 @@ impl FluenceCodec {
     }
 }
 
+impl FluenceCodec {
+    /// Deserializes a single frame without the length prefix, as logged under [`WIRE_LOG_TARGET`]
+    pub fn decode_frame(bytes: &[u8]) -> Result<ProtocolMessage, FluenceCodecError> {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            ProtocolMessageRepresentation.deserialize(bytes)
+        }));
+        match result {
+            Ok(message) => message.map_err(FluenceCodecError::Deserialize),
+            Err(_) => Err(FluenceCodecError::Malformed(bytes.len())),
+        }
+    }
+}
+
+impl Default for FluenceCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Decoder for FluenceCodec {
     type Item = ProtocolMessage;
     type Error = FluenceCodecError;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let bytes = self.length.decode(src)?;
-        if let Some(bytes) = bytes {
-            return ProtocolMessageRepresentation
-                .deserialize(&bytes)
-                .map(Some)
-                .map_err(FluenceCodecError::Deserialize);
+        // UviBytes only fails on a malformed or too large length prefix
+        let bytes = self.length.decode(src).map_err(FluenceCodecError::Length)?;
+        let Some(bytes) = bytes else {
+            return Ok(None);
+        };
+        if log::log_enabled!(target: WIRE_LOG_TARGET, log::Level::Trace) {
+            log::trace!(target: WIRE_LOG_TARGET, "inbound frame: {}", base64.encode(&bytes));
         }
-        Ok(None)
+        // Frames come from untrusted peers, so a bug in the deserializer must not take down the handler
+        Self::decode_frame(&bytes).map(Some)
     }
 }
 
@@ -86,6 +113,8 @@ pub enum FluenceCodecError {
     Length(std::io::Error),
     Serialize(<ProtocolMessageFormat as SedeFormat<ProtocolMessage>>::SerializationError),
     Deserialize(<ProtocolMessageFormat as SedeFormat<ProtocolMessage>>::DeserializationError),
+    /// Deserializer panicked on a frame of the given size
+    Malformed(usize),
 }
 
 impl From<std::io::Error> for FluenceCodecError {
@@ -101,6 +130,7 @@ impl std::error::Error for FluenceCodecError {
             FluenceCodecError::Length(ref e) => Some(e),
             FluenceCodecError::Serialize(ref e) => Some(e),
             FluenceCodecError::Deserialize(ref e) => Some(e),
+            FluenceCodecError::Malformed(_) => None,
         }
     }
 }
@@ -109,9 +139,12 @@ impl std::fmt::Display for FluenceCodecError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FluenceCodecError::Io(e) => write!(f, "I/O error: {}", e),
-            FluenceCodecError::Length(e) => write!(f, "I/O error: {}", e),
+            FluenceCodecError::Length(e) => write!(f, "Invalid length prefix: {}", e),
             FluenceCodecError::Serialize(e) => write!(f, "Serialization error: {}", e),
             FluenceCodecError::Deserialize(e) => write!(f, "Deserialization error: {}", e),
+            FluenceCodecError::Malformed(size) => {
+                write!(f, "Malformed frame of {} bytes", size)
+            }
         }
     }
 }
@@ -123,17 +156,21 @@ impl From<FluenceCodecError> for std::io::Error {
             FluenceCodecError::Length(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
             FluenceCodecError::Serialize(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
             FluenceCodecError::Deserialize(e) => io::Error::new(io::ErrorKind::InvalidInput, e),
+            e @ FluenceCodecError::Malformed(_) => {
+                io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+            }
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::libp2p_protocol::codec::FluenceCodec;
+    use crate::libp2p_protocol::codec::{FluenceCodec, FluenceCodecError};
     use crate::{Particle, ProtocolMessage};
     use asynchronous_codec::{BytesMut, Decoder, Encoder};
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use libp2p::PeerId;
+    use std::assert_matches::assert_matches;
     use std::str::FromStr;
 
     #[test]
@@ -147,6 +184,9 @@ mod tests {
             script: "script".to_string(),
             signature: vec![0, 0, 128],
             data: vec![0, 0, 255],
+            trail: None,
+            progress: false,
+            hops: 0,
         });
         let mut bytes = BytesMut::new();
         codec
@@ -158,6 +198,73 @@ mod tests {
         assert_eq!(result_message, Some(initial_message))
     }
 
+    fn encode(message: ProtocolMessage) -> BytesMut {
+        let mut bytes = BytesMut::new();
+        FluenceCodec::new()
+            .encode(message, &mut bytes)
+            .expect("Encoding");
+        bytes
+    }
+
+    #[test]
+    fn truncated_frame_test() {
+        let mut bytes = encode(ProtocolMessage::Upgrade);
+        let full_len = bytes.len();
+        bytes.truncate(full_len - 1);
+
+        let mut codec = FluenceCodec::new();
+        let result = codec.decode(&mut bytes).expect("Decoding");
+        assert_eq!(result, None, "incomplete frame must wait for more data");
+
+        let result = codec.decode_eof(&mut bytes);
+        assert!(result.is_err(), "incomplete frame at EOF must be an error");
+    }
+
+    #[test]
+    fn oversized_length_test() {
+        // varint encoding of u64::MAX
+        let mut bytes = BytesMut::from(&[0xff; 9][..]);
+        bytes.extend_from_slice(&[0x01]);
+
+        let result = FluenceCodec::new().decode(&mut bytes);
+        assert_matches!(result, Err(FluenceCodecError::Length(_)));
+    }
+
+    #[test]
+    fn invalid_utf8_test() {
+        let message = ProtocolMessage::Particle(Particle {
+            script: "script".to_string(),
+            ..<_>::default()
+        });
+        let mut bytes = encode(message);
+        let position = bytes
+            .windows(6)
+            .position(|w| w == b"script" as &[u8])
+            .expect("script must be serialized as is");
+        // the last occurrence is the value, the first is the key
+        let value_position = bytes
+            .windows(6)
+            .rposition(|w| w == b"script" as &[u8])
+            .unwrap_or(position);
+        bytes[value_position] = 0xff;
+
+        let result = FluenceCodec::new().decode(&mut bytes);
+        assert_matches!(result, Err(FluenceCodecError::Deserialize(_)));
+    }
+
+    #[test]
+    fn garbage_frame_test() {
+        let mut codec = FluenceCodec::new();
+        let mut bytes = BytesMut::new();
+        for len in 0..64u8 {
+            bytes.clear();
+            bytes.extend_from_slice(&[len]);
+            bytes.extend((0..len).map(|i| i.wrapping_mul(37)));
+            // must not panic
+            let _ = codec.decode(&mut bytes);
+        }
+    }
+
     #[test]
     fn deserialization_test() {
         let raw_str = "zwKBBIimYWN0aW9uqFBhcnRpY2xlpGRhdGGQomlk2SRkMjA1ZDE0OC00Y2YxLTRlNzYtOGY2ZS1mY\
@@ -188,6 +295,9 @@ mod tests {
                 253, 156, 242, 141, 129, 217, 205, 181, 156, 231, 10,
             ],
             data: vec![],
+            trail: None,
+            progress: false,
+            hops: 0,
         });
 
         assert_eq!(result, Some(expected))
@@ -19,4 +19,4 @@
 
 mod fluence;
 
-pub use self::fluence::FluenceCodec;
+pub use self::fluence::{FluenceCodec, FluenceCodecError, WIRE_LOG_TARGET};
@@ -77,12 +77,24 @@ impl<OutProto: libp2p::swarm::handler::OutboundUpgradeSend, OutEvent> From<Proto
     for OneShotHandler<ProtocolConfig, OutProto, OutEvent>
 {
     fn from(item: ProtocolConfig) -> OneShotHandler<ProtocolConfig, OutProto, OutEvent> {
+        let max_outbound_streams = OneShotHandlerConfig::default().max_dial_negotiated;
+        item.handler(max_outbound_streams)
+    }
+}
+
+impl ProtocolConfig {
+    /// Connection handler opening at most `max_outbound_streams` outbound substreams at once
+    pub fn handler<OutProto: libp2p::swarm::handler::OutboundUpgradeSend, OutEvent>(
+        self,
+        max_outbound_streams: u32,
+    ) -> OneShotHandler<ProtocolConfig, OutProto, OutEvent> {
+        let upgrade_timeout = self.upgrade_timeout;
+        let outbound_substream_timeout = self.outbound_substream_timeout;
         OneShotHandler::new(
-            libp2p::swarm::handler::SubstreamProtocol::new(item.clone(), ())
-                .with_timeout(item.upgrade_timeout),
+            libp2p::swarm::handler::SubstreamProtocol::new(self, ()).with_timeout(upgrade_timeout),
             OneShotHandlerConfig {
-                outbound_substream_timeout: item.outbound_substream_timeout,
-                ..<_>::default()
+                outbound_substream_timeout,
+                max_dial_negotiated: max_outbound_streams,
             },
         )
     }
@@ -69,6 +69,21 @@ impl ExtendedParticle {
     }
 }
 
+/// Max number of hops kept in the particle trail, the oldest hops are dropped first
+pub const MAX_TRAIL_HOPS: usize = 32;
+
+/// A node that forwarded the particle
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TrailHop {
+    #[serde(
+        serialize_with = "peer_id::serde::serialize",
+        deserialize_with = "peer_id::serde::deserialize"
+    )]
+    pub peer_id: PeerId,
+    /// Unix timestamp in milliseconds when the node sent the particle further
+    pub timestamp: u64,
+}
+
 #[derive(Clone, Serialize, Deserialize, PartialEq, Derivative)]
 #[derivative(Debug)]
 pub struct Particle {
@@ -89,6 +104,22 @@ pub struct Particle {
     #[serde(with = "serde_bytes")]
     #[derivative(Debug(format_with = "fmt_data"))]
     pub data: Vec<u8>,
+    /// Route of the particle, for debugging. Recorded only if the initiator sends the particle
+    /// with an empty trail. It isn't signed, so any node on the route may alter it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trail: Option<Vec<TrailHop>>,
+    /// Ask the nodes to report results of host calls back to `init_peer_id` as progress particles
+    /// while the script is executed. It isn't signed, same as the trail.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub progress: bool,
+    /// Number of times the particle was sent from node to node, used to detect forwarding loops.
+    /// It isn't signed, same as the trail.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub hops: u32,
+}
+
+fn is_zero(hops: &u32) -> bool {
+    *hops == 0
 }
 
 impl Default for Particle {
@@ -102,14 +133,22 @@ impl Default for Particle {
             script: "".to_string(),
             signature: vec![],
             data: vec![],
+            trail: None,
+            progress: false,
+            hops: 0,
         }
     }
 }
 
 impl Particle {
     pub fn is_expired(&self) -> bool {
+        self.is_expired_at(now_ms())
+    }
+
+    /// Whether the particle is expired at `now_ms`, e.g. the local time corrected by clock skew
+    pub fn is_expired_at(&self, now_ms: u128) -> bool {
         if let Some(deadline) = self.deadline() {
-            return now_ms() > deadline as u128;
+            return now_ms > deadline as u128;
         }
 
         // If timestamp + ttl overflows u64, consider particle expired
@@ -123,13 +162,33 @@ impl Particle {
     }
 
     pub fn time_to_live(&self) -> Duration {
-        if let Some(ttl) = self.deadline().and_then(|d| d.checked_sub(now_ms() as u64)) {
+        self.time_to_live_at(now_ms())
+    }
+
+    /// Time left until the deadline at `now_ms`
+    pub fn time_to_live_at(&self, now_ms: u128) -> Duration {
+        if let Some(ttl) = self.deadline().and_then(|d| d.checked_sub(now_ms as u64)) {
             Duration::from_millis(ttl)
         } else {
             Duration::default()
         }
     }
 
+    /// Append the forwarding node to the trail if the trail is recorded
+    pub fn append_trail(&mut self, peer_id: PeerId, timestamp: u64) {
+        if let Some(trail) = self.trail.as_mut() {
+            if trail.len() >= MAX_TRAIL_HOPS {
+                trail.drain(..=trail.len() - MAX_TRAIL_HOPS);
+            }
+            trail.push(TrailHop { peer_id, timestamp });
+        }
+    }
+
+    /// Count another hop when the particle is sent further
+    pub fn increment_hops(&mut self) {
+        self.hops = self.hops.saturating_add(1);
+    }
+
     /// return immutable particle fields in bytes for signing
     /// concatenation of:
     /// - id as bytes
@@ -204,6 +263,7 @@ impl std::fmt::Display for Particle {
 
 #[cfg(test)]
 mod tests {
+    use crate::particle::MAX_TRAIL_HOPS;
     use crate::Particle;
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use fluence_keypair::{KeyFormat, KeyPair};
@@ -239,6 +299,9 @@ mod tests {
             script: "abc".to_string(),
             signature: vec![],
             data: vec![],
+            trail: None,
+            progress: false,
+            hops: 0,
         };
 
         let particle_bytes = p.as_bytes();
@@ -251,4 +314,34 @@ mod tests {
         assert!(p.verify().is_ok());
         assert_eq!(base64.encode(&p.signature), "KceXDnOfqe0dOnAxiDsyWBIvUq6WHoT0ge+VMHXOZsjZvCNH7/10oufdlYfcPomfv28On6E87ZhDcHGBZcb7Bw==");
     }
+
+    #[test]
+    fn test_trail() {
+        let peer_id = fluence_libp2p::RandomPeerId::random();
+
+        let mut particle = Particle::default();
+        particle.append_trail(peer_id, 1);
+        assert_eq!(particle.trail, None, "trail is recorded only on request");
+
+        particle.trail = Some(vec![]);
+        for timestamp in 0..(MAX_TRAIL_HOPS as u64 + 5) {
+            particle.append_trail(peer_id, timestamp);
+        }
+        let trail = particle.trail.as_ref().unwrap();
+        assert_eq!(trail.len(), MAX_TRAIL_HOPS);
+        assert_eq!(trail.first().unwrap().timestamp, 5);
+        assert_eq!(trail.last().unwrap().timestamp, MAX_TRAIL_HOPS as u64 + 4);
+
+        // the trail and the hop count don't affect the signature
+        let kp = KeyPair::generate_ed25519();
+        let mut particle = Particle {
+            init_peer_id: kp.get_peer_id(),
+            ..particle
+        };
+        particle.sign(&kp).unwrap();
+        particle.append_trail(peer_id, 100);
+        particle.increment_hops();
+        assert_eq!(particle.hops, 1);
+        assert!(particle.verify().is_ok());
+    }
 }
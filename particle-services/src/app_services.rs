@@ -17,27 +17,31 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 use std::ops::Deref;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 use std::{collections::HashMap, sync::Arc};
 
 use derivative::Derivative;
 use fluence_app_service::{
-    AppService, AppServiceConfig, AppServiceError, AppServiceFactory, CallParameters, EpochTicker,
-    MarineConfig, MarineError, MarineWASIConfig, ModuleDescriptor, SecurityTetraplet,
-    ServiceInterface, WasmtimeConfig,
+    AppService, AppServiceConfig, AppServiceError, CallParameters, MarineConfig, MarineError,
+    MarineWASIConfig, ModuleDescriptor, SecurityTetraplet, ServiceInterface,
 };
+use futures::stream::BoxStream;
 use futures::{stream, StreamExt};
 use humantime_serde::re::humantime::format_duration as pretty;
+use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JValue};
 use tokio::runtime::Handle;
-use tokio_stream::wrappers::IntervalStream;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use tokio_util::context::TokioContext;
 
 use fluence_libp2p::PeerId;
 use health::HealthCheckRegistry;
-use now_millis::now_ms;
+use node_events::{EventKind, EventLog};
+use now_millis::{now_ms, now_sec};
 use particle_args::{Args, JError};
 use particle_execution::{FunctionOutcome, ParticleParams, ParticleVault};
 use particle_modules::ModuleRepository;
@@ -49,14 +53,29 @@ use types::peer_scope::PeerScope;
 use uuid_utils::uuid;
 use workers::{PeerScopes, WorkerId, Workers};
 
+use crate::authorization::{CallAuthorizer, CallContext};
+use crate::call_tokens::CallTokens;
+use crate::engines::{Engine, EngineReport, Engines};
 use crate::error::ServiceError;
 use crate::error::ServiceError::{AliasAsServiceId, Forbidden, NoSuchAlias};
 use crate::health::PersistedServiceHealth;
+use crate::labels::Labels;
+use crate::memory_budget::LoadedInstance;
+use crate::ordering::OrderedDelivery;
 use crate::persistence::{load_persisted_services, remove_persisted_service, PersistedService};
+use crate::run_as::RunAs;
+use crate::service_health::{
+    is_healthy, HealthChange, HealthChecks, RestartPolicy, ServiceHealth, ServiceHealthEvent,
+    HEALTH_LABEL,
+};
+use crate::spell_kv_atomic::AtomicKvFunction;
+use crate::spell_kv_metrics::{is_kv_write_success, spell_kv_metric};
+use crate::spell_kv_writes::{spell_kv_writes, SpellKvWrite};
+use crate::storage_encryption::{remove_copy, CopySnapshot};
 use crate::ParticleAppServicesConfig;
 use crate::ServiceError::{
     FailedToCreateDirectory, ForbiddenAlias, ForbiddenAliasRoot, ForbiddenAliasWorker,
-    InternalError, NoSuchService,
+    InternalError, NoHealthFunction, NoSuchService,
 };
 
 type ServiceId = String;
@@ -83,30 +102,43 @@ pub struct ServiceInfo {
     pub owner_id: PeerId,
     pub aliases: Vec<ServiceAlias>,
     pub peer_scope: PeerScope,
+    pub labels: Labels,
 }
 
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct Service {
+    /// `None` while the instance is unloaded to fit the memory budget
     #[derivative(Debug(format_with = "fmt_service"))]
-    pub service: tokio::sync::Mutex<AppService>,
+    pub service: tokio::sync::Mutex<Option<AppService>>,
     pub service_id: String,
     pub blueprint_id: String,
     pub service_type: ServiceType,
     pub owner_id: PeerId,
     pub aliases: tokio::sync::RwLock<Vec<ServiceAlias>>,
     pub peer_scope: PeerScope,
+    pub labels: Labels,
+    /// Wasm engine that instantiates the service
+    engine: Mutex<String>,
+    /// Identity the spell calls services under, `None` for services and unrestricted spells
+    run_as: Mutex<Option<RunAs>>,
+    /// Memory used by the instance after the last call, 0 while unloaded
+    memory_bytes: AtomicU64,
+    last_used: Mutex<Instant>,
 }
 
 impl Service {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        service: tokio::sync::Mutex<AppService>,
+        service: tokio::sync::Mutex<Option<AppService>>,
         service_id: String,
         blueprint_id: String,
         service_type: ServiceType,
         owner_id: PeerId,
         aliases: Vec<ServiceAlias>,
         peer_scope: PeerScope,
+        labels: Labels,
+        engine: String,
     ) -> Self {
         Self {
             service,
@@ -116,9 +148,36 @@ impl Service {
             owner_id,
             aliases: tokio::sync::RwLock::new(aliases),
             peer_scope,
+            labels,
+            engine: Mutex::new(engine),
+            run_as: Mutex::new(None),
+            memory_bytes: AtomicU64::new(0),
+            last_used: Mutex::new(Instant::now()),
         }
     }
 
+    pub fn engine(&self) -> String {
+        self.engine.lock().clone()
+    }
+
+    pub fn run_as(&self) -> Option<RunAs> {
+        self.run_as.lock().clone()
+    }
+
+    fn record_use(&self, memory_bytes: u64) {
+        self.memory_bytes.store(memory_bytes, Ordering::Relaxed);
+        *self.last_used.lock() = Instant::now();
+    }
+
+    fn loaded_instance(&self) -> Option<LoadedInstance<ServiceId>> {
+        let memory = self.memory_bytes.load(Ordering::Relaxed);
+        (memory > 0).then(|| LoadedInstance {
+            key: self.service_id.clone(),
+            memory,
+            last_used: *self.last_used.lock(),
+        })
+    }
+
     pub async fn remove_alias(&self, alias: &str) {
         let mut aliases = self.aliases.write().await;
         if let Some(pos) = aliases.iter().position(|x| *x == alias) {
@@ -138,12 +197,13 @@ impl Service {
             owner_id: self.owner_id,
             aliases: self.aliases.read().await.clone(),
             peer_scope: self.peer_scope,
+            labels: self.labels.clone(),
         }
     }
 }
 
 impl Deref for Service {
-    type Target = tokio::sync::Mutex<AppService>;
+    type Target = tokio::sync::Mutex<Option<AppService>>;
 
     fn deref(&self) -> &Self::Target {
         &self.service
@@ -151,7 +211,7 @@ impl Deref for Service {
 }
 
 fn fmt_service(
-    _: &tokio::sync::Mutex<AppService>,
+    _: &tokio::sync::Mutex<Option<AppService>>,
     f: &mut std::fmt::Formatter<'_>,
 ) -> Result<(), std::fmt::Error> {
     f.debug_struct("Mutex<AppService>").finish()
@@ -172,6 +232,18 @@ struct Services {
     aliases: Arc<tokio::sync::RwLock<HashMap<ServiceAlias, ServiceId>>>,
 }
 
+/// Persisted worker services which are restored after the node has started
+pub struct DeferredServices {
+    workers: Vec<(WorkerId, Vec<(PersistedService, ServiceType)>)>,
+    has_errors: bool,
+}
+
+impl DeferredServices {
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+}
+
 #[derive(Derivative)]
 #[derivative(Debug, Clone)]
 pub struct ParticleAppServices {
@@ -190,13 +262,25 @@ pub struct ParticleAppServices {
     pub metrics: Option<ServicesMetrics>,
     health: Option<PersistedServiceHealth>,
     #[derivative(Debug = "ignore")]
-    app_service_factory: AppServiceFactory,
+    engines: Engines,
+    #[derivative(Debug = "ignore")]
+    ordered_delivery: OrderedDelivery,
+    #[derivative(Debug = "ignore")]
+    call_tokens: Arc<CallTokens>,
+    #[derivative(Debug = "ignore")]
+    kv_write_subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<SpellKvWrite>>>>,
+    #[derivative(Debug = "ignore")]
+    events: EventLog,
     #[derivative(Debug = "ignore")]
-    app_service_epoch_ticker: EpochTicker,
+    health_checks: Arc<Mutex<HealthChecks>>,
+    /// Decrypted copies of persistent dirs of loaded services, when storage encryption is on
+    #[derivative(Debug = "ignore")]
+    storage_copies: Arc<Mutex<HashMap<ServiceId, CopySnapshot>>>,
 }
 
 async fn resolve_alias(
     services: &Services,
+    metrics: Option<&ServicesMetrics>,
     alias: &String,
     particle_id: &str,
 ) -> Option<ServiceId> {
@@ -206,7 +290,11 @@ async fn resolve_alias(
         }
     }
 
-    services.aliases.read().await.get(alias).cloned()
+    let service_id = services.aliases.read().await.get(alias).cloned();
+    if let Some(metrics) = metrics {
+        metrics.observe_alias_resolution(service_id.is_some());
+    }
+    service_id
 }
 
 fn get_service(
@@ -239,21 +327,25 @@ impl ParticleAppServices {
             persisted_services
         });
 
-        let wasmtime_config: WasmtimeConfig = config.wasm_backend_config.clone().into();
+        let engines = Engines::new(
+            &config.wasm_backend_config,
+            config.wasm_backend_next.as_ref(),
+        )?;
 
-        let (app_service_factory, epoch_ticker) =
-            AppServiceFactory::new(wasmtime_config).map_err(ServiceError::Engine)?;
+        let call_tokens = Arc::new(CallTokens::new(
+            config.call_concurrency,
+            config.nested_call_reserve,
+        ));
 
-        // TODO: make a setting for that
-        let stream = IntervalStream::new(tokio::time::interval(Duration::from_secs(1)));
-        let ticket = epoch_ticker.clone();
-        tokio::task::spawn(async move {
-            stream
-                .for_each(|_| async {
-                    ticket.increment_epoch();
-                })
-                .await;
-        });
+        if let Some(encryption) = config.storage_encryption.as_ref() {
+            // copies left by a crash, the sealed data is up to date with them
+            if let Err(err) = remove_copy(&encryption.plaintext_dir) {
+                tracing::warn!(
+                    "Failed to remove decrypted copies of service storage: {}",
+                    err
+                );
+            }
+        }
 
         Ok(Self {
             config,
@@ -266,17 +358,135 @@ impl ParticleAppServices {
             scopes: scope,
             metrics,
             health,
-            app_service_factory,
-            app_service_epoch_ticker: epoch_ticker,
+            engines,
+            ordered_delivery: <_>::default(),
+            call_tokens,
+            kv_write_subscribers: <_>::default(),
+            events: <_>::default(),
+            health_checks: <_>::default(),
+            storage_copies: <_>::default(),
         })
     }
 
+    /// Records services and spells lifecycle and service call failures to the event log
+    pub fn with_event_log(mut self, events: EventLog) -> Self {
+        self.events = events;
+        self
+    }
+
+    fn record_event(
+        &self,
+        kind: EventKind,
+        peer_scope: PeerScope,
+        subject: &str,
+        message: Option<String>,
+    ) {
+        self.events
+            .record(kind, subject, scope_worker_id(peer_scope), message);
+    }
+
+    /// Streams the event to the event log subscribers without persisting it
+    pub fn publish_event(
+        &self,
+        kind: EventKind,
+        peer_scope: PeerScope,
+        subject: &str,
+        message: Option<String>,
+    ) {
+        self.events
+            .publish(kind, subject, scope_worker_id(peer_scope), message);
+    }
+
+    /// Stream of KV writes made by spells to their own KV via `call_service`
+    pub fn kv_writes(&self) -> BoxStream<'static, SpellKvWrite> {
+        let (out, inlet) = mpsc::unbounded_channel();
+        self.kv_write_subscribers.lock().push(out);
+        UnboundedReceiverStream::new(inlet).boxed()
+    }
+
+    fn notify_kv_writes(
+        &self,
+        peer_scope: PeerScope,
+        spell_id: &str,
+        writes: Vec<(String, String)>,
+    ) {
+        let mut subscribers = self.kv_write_subscribers.lock();
+        for (key, value) in writes {
+            let write = SpellKvWrite {
+                peer_scope,
+                spell_id: spell_id.to_string(),
+                key,
+                value,
+            };
+            subscribers.retain(|out| out.send(write.clone()).is_ok());
+        }
+    }
+
     pub async fn create_service(
         &self,
         peer_scope: PeerScope,
         service_type: ServiceType,
         blueprint_id: String,
         owner_id: PeerId,
+    ) -> Result<String, ServiceError> {
+        self.create_service_with_labels(
+            peer_scope,
+            service_type,
+            blueprint_id,
+            owner_id,
+            Labels::new(),
+        )
+        .await
+    }
+
+    /// Same as [`Self::create_service_with_labels`], for spells calling services under the identity
+    pub async fn create_spell_service(
+        &self,
+        peer_scope: PeerScope,
+        blueprint_id: String,
+        owner_id: PeerId,
+        labels: Labels,
+        run_as: Option<RunAs>,
+    ) -> Result<String, ServiceError> {
+        self.create_service_with_identity(
+            peer_scope,
+            ServiceType::Spell,
+            blueprint_id,
+            owner_id,
+            labels,
+            run_as,
+        )
+        .await
+    }
+
+    /// Same as [`Self::create_service`], with the labels attached to the service
+    pub async fn create_service_with_labels(
+        &self,
+        peer_scope: PeerScope,
+        service_type: ServiceType,
+        blueprint_id: String,
+        owner_id: PeerId,
+        labels: Labels,
+    ) -> Result<String, ServiceError> {
+        self.create_service_with_identity(
+            peer_scope,
+            service_type,
+            blueprint_id,
+            owner_id,
+            labels,
+            None,
+        )
+        .await
+    }
+
+    async fn create_service_with_identity(
+        &self,
+        peer_scope: PeerScope,
+        service_type: ServiceType,
+        blueprint_id: String,
+        owner_id: PeerId,
+        labels: Labels,
+        run_as: Option<RunAs>,
     ) -> Result<String, ServiceError> {
         let service_id = uuid::Uuid::new_v4().to_string();
 
@@ -288,6 +498,11 @@ impl ParticleAppServices {
             PeerScope::Host => self.root_runtime_handle.clone(),
         };
 
+        let kind = if service_type.is_spell() {
+            EventKind::SpellInstalled
+        } else {
+            EventKind::ServiceCreated
+        };
         let fut = async {
             self.create_service_inner(
                 service_type,
@@ -296,12 +511,17 @@ impl ParticleAppServices {
                 peer_scope,
                 service_id.clone(),
                 vec![],
+                labels,
+                None,
+                run_as,
             )
             .await
         };
 
         TokioContext::new(fut, runtime_handle).await?;
 
+        self.record_event(kind, peer_scope, &service_id, None);
+
         Ok(service_id)
     }
 
@@ -326,6 +546,155 @@ impl ParticleAppServices {
         Ok(service.get_info(&service_id).await)
     }
 
+    /// Health state of a service declaring a health function
+    pub async fn get_service_health(
+        &self,
+        peer_scope: PeerScope,
+        service_id_or_alias: String,
+        particle_id: &str,
+    ) -> Result<ServiceHealth, ServiceError> {
+        let (service, service_id) = self
+            .get_service(peer_scope, service_id_or_alias, particle_id)
+            .await?;
+        let function = service
+            .labels
+            .get(HEALTH_LABEL)
+            .ok_or_else(|| NoHealthFunction(service_id.clone()))?;
+
+        Ok(self
+            .health_checks
+            .lock()
+            .get(&service_id)
+            .unwrap_or_else(|| ServiceHealth::new(service_id, function.clone())))
+    }
+
+    /// Call health functions of all services declaring one, restarting failing instances
+    /// according to the policy. Returns changes of the services health.
+    pub async fn check_health(&self, policy: &RestartPolicy) -> Vec<ServiceHealthEvent> {
+        let checked: Vec<(ServiceInfo, String)> = self
+            .list_services_all()
+            .await
+            .into_iter()
+            .filter_map(|info| {
+                let function = info.labels.get(HEALTH_LABEL)?.clone();
+                Some((info, function))
+            })
+            .collect();
+        self.health_checks
+            .lock()
+            .retain(|service_id| checked.iter().any(|(info, _)| info.id == service_id));
+
+        let results = futures::future::join_all(checked.iter().map(|(info, function)| {
+            self.call_health_function(info.peer_scope, &info.id, function, policy.timeout)
+        }))
+        .await;
+
+        let mut events = vec![];
+        for ((info, function), result) in checked.into_iter().zip(results) {
+            let timestamp = now_sec();
+            let error = result.as_ref().err().cloned();
+            let verdict = self.health_checks.lock().observe(
+                &info.id,
+                &function,
+                result,
+                policy,
+                Instant::now(),
+                timestamp,
+            );
+            let mut changes = verdict.changes;
+            if verdict.restart {
+                let restarted = self.restart_service(info.peer_scope, &info.id).await;
+                let restart_error = restarted.as_ref().err().map(|err| err.to_string());
+                match &restart_error {
+                    None => {
+                        tracing::info!("Service {} restarted after failed health check", info.id);
+                        self.record_event(
+                            EventKind::ServiceRestarted,
+                            info.peer_scope,
+                            &info.id,
+                            error.clone(),
+                        );
+                        changes.push(HealthChange::Restarted);
+                    }
+                    Some(err) => tracing::warn!("Failed to restart service {}: {}", info.id, err),
+                }
+                self.health_checks.lock().restarted(
+                    &info.id,
+                    restart_error,
+                    policy,
+                    Instant::now(),
+                );
+            }
+            events.extend(changes.into_iter().map(|change| ServiceHealthEvent {
+                service_id: info.id.clone(),
+                peer_scope: info.peer_scope,
+                change,
+                error: error.clone(),
+                timestamp,
+            }));
+        }
+        events
+    }
+
+    async fn call_health_function(
+        &self,
+        peer_scope: PeerScope,
+        service_id: &str,
+        function: &str,
+        timeout: Duration,
+    ) -> Result<(), String> {
+        let call = self.call_function(
+            peer_scope,
+            service_id,
+            function,
+            vec![],
+            None,
+            self.scopes.get_host_peer_id(),
+            timeout,
+        );
+        match tokio::time::timeout(timeout, call).await {
+            Ok(FunctionOutcome::Ok(result)) if is_healthy(&result) => Ok(()),
+            Ok(FunctionOutcome::Ok(result)) => Err(format!("reported unhealthy: {result}")),
+            Ok(FunctionOutcome::Empty) => Ok(()),
+            Ok(FunctionOutcome::Err(err)) => Err(err.to_string()),
+            Ok(FunctionOutcome::NotDefined { .. }) => Err("service not found".to_string()),
+            Err(_) => Err(format!(
+                "health function didn't respond in {}",
+                pretty(timeout)
+            )),
+        }
+    }
+
+    /// Replace the instance of the service with a freshly created one
+    pub async fn restart_service(
+        &self,
+        peer_scope: PeerScope,
+        service_id: &str,
+    ) -> Result<(), ServiceError> {
+        let services = self.get_services(&peer_scope).await?;
+        let service = get_service(
+            &services.services.read().await,
+            peer_scope,
+            service_id.to_string(),
+        )?;
+
+        let mut instance = service.lock().await;
+        let engine = self.engines.get(Some(&service.engine()));
+        let app_service = self
+            .create_app_service(
+                engine,
+                self.scopes.to_peer_id(peer_scope),
+                service.blueprint_id.clone(),
+                service_id.to_string(),
+            )
+            .await?;
+        let memory = ServicesMetricsBuiltin::get_used_memory(&app_service.module_memory_stats());
+        *instance = Some(app_service);
+        service.record_use(memory);
+
+        Ok(())
+    }
+
     pub async fn remove_services(&self, peer_scope: PeerScope) -> Result<(), ServiceError> {
         let services = self.get_services(&peer_scope).await?;
         let service_ids: Vec<ServiceId> = services.services.read().await.keys().cloned().collect();
@@ -423,14 +792,39 @@ impl ParticleAppServices {
         }
         let service_type = self.get_service_type(&service, &service.peer_scope).await;
 
+        let kind = if service.service_type.is_spell() {
+            EventKind::SpellRemoved
+        } else {
+            EventKind::ServiceRemoved
+        };
+        self.record_event(kind, peer_scope, &service_id, None);
+        drop(services);
+        drop(aliases);
+        self.discard_service_storage(&service_id).await;
+
         let removal_end_time = removal_start_time.elapsed().as_secs();
         if let Some(metrics) = self.metrics.as_ref() {
             metrics.observe_removed(service_type, removal_end_time as f64);
+            if service.service_type.is_spell() {
+                metrics.observe_spell_removed(&service_id);
+            }
         }
 
         Ok(())
     }
 
+    /// Run-as identity of the local spell that sent the particle, if the spell has one
+    pub async fn spell_run_as(&self, particle: &ParticleParams) -> Option<RunAs> {
+        let spell_id = ParticleParams::get_spell_id(&particle.id)?;
+        // spell particles are sent by the host or worker the spell is installed on
+        if particle.init_peer_id != self.scopes.to_peer_id(particle.peer_scope) {
+            return None;
+        }
+        let services = self.get_services(&particle.peer_scope).await.ok()?;
+        let services = services.services.read().await;
+        services.get(&spell_id)?.run_as()
+    }
+
     pub async fn call_service(
         &self,
         function_args: Args,
@@ -464,6 +858,50 @@ impl ParticleAppServices {
         //         },
         //     ));
         // }
+        // spells always have access to their own KV
+        let own_service = ParticleParams::get_spell_id(&particle.id).as_ref() == Some(&service_id);
+        let run_as = if own_service {
+            None
+        } else {
+            self.spell_run_as(&particle).await
+        };
+        let authorized = {
+            let aliases = service.aliases.read().await;
+            let ctx = CallContext {
+                caller: particle.init_peer_id,
+                peer_scope,
+                worker_id: self.scopes.to_peer_id(peer_scope),
+                service_id: &service_id,
+                service_aliases: &aliases,
+                service_owner: service.owner_id,
+                function_name: &function_args.function_name,
+                identity: run_as.as_ref().map(|run_as| run_as.name.as_str()),
+            };
+            let authorized = run_as
+                .as_ref()
+                .map_or(Ok(()), |run_as| run_as.authorize(&ctx))
+                .and_then(|()| self.config.call_authorizer.authorize(&ctx));
+            if let Some(run_as) = &run_as {
+                tracing::info!(
+                    target: "audit",
+                    particle_id = particle.id,
+                    identity = run_as.name,
+                    service_id,
+                    function_name = function_args.function_name,
+                    allowed = authorized.is_ok(),
+                    "Spell call under a run-as identity"
+                );
+            }
+            authorized
+        };
+        if let Err(reason) = authorized {
+            return FunctionOutcome::Err(JError::coded(ServiceError::CallForbidden {
+                service_id,
+                function: function_args.function_name,
+                reason,
+            }));
+        }
+
         // Metrics collection are enables for services with aliases which are installed on root worker or worker spells.
         let service_type = self.get_service_type(service.as_ref(), &peer_scope).await;
 
@@ -476,6 +914,27 @@ impl ParticleAppServices {
             )?;
         }
 
+        // Keep the turn until the call is finished, so the next call of the stream waits for it
+        let _turn = match function_args.sequence {
+            Some(seq) => {
+                let remaining_ttl =
+                    (timestamp + particle.ttl as u64).saturating_sub(now_ms() as u64);
+                let deadline = Instant::now() + Duration::from_millis(remaining_ttl);
+                let turn = self
+                    .ordered_delivery
+                    .acquire(particle.init_peer_id, service_id.clone(), seq, deadline)
+                    .await
+                    .map_err(|err| {
+                        JError::coded(ServiceError::Ordering {
+                            service_id: service_id.clone(),
+                            err,
+                        })
+                    })?;
+                Some(turn)
+            }
+            None => None,
+        };
+
         let call_parameters_worker_id = self.scopes.to_peer_id(peer_scope);
 
         let params = CallParameters {
@@ -500,23 +959,99 @@ impl ParticleAppServices {
                 .collect(),
         };
         let function_name = function_args.function_name;
+        let (kv_metric, mut kv_writes) = if service.service_type.is_spell() {
+            (
+                spell_kv_metric(&function_name, &function_args.function_args),
+                spell_kv_writes(&function_name, &function_args.function_args),
+            )
+        } else {
+            (None, vec![])
+        };
+
+        // Taken before the instance lock, so a nested call to a busy instance fails instead of waiting
+        let _token = self
+            .call_tokens
+            .acquire(peer_scope, &service_id)
+            .await
+            .map_err(|err| {
+                JError::coded(ServiceError::NestedCall {
+                    service_id: service_id.clone(),
+                    err,
+                })
+            })?;
 
         let lock_acquire_start = Instant::now();
-        let mut service = service.lock().await;
-        let old_memory = service.module_memory_stats();
+        let mut instance = service.lock().await;
+        if instance.is_none() {
+            let reload_start = Instant::now();
+            let engine = self.engines.get(Some(&service.engine()));
+            let reloaded = self
+                .create_app_service(
+                    engine,
+                    self.scopes.to_peer_id(peer_scope),
+                    service.blueprint_id.clone(),
+                    service_id.clone(),
+                )
+                .await?;
+            *instance = Some(reloaded);
+            tracing::debug!(
+                "Service {} reloaded in {}",
+                service_id,
+                pretty(reload_start.elapsed())
+            );
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.observe_reloaded(reload_start.elapsed().as_secs_f64());
+            }
+        }
+        let app_service = instance.as_mut().expect("instance is loaded above");
+        let old_memory = app_service.module_memory_stats();
         let old_mem_usage = ServicesMetricsBuiltin::get_used_memory(&old_memory);
         // TODO async-marine: set execution timeout https://github.com/fluencelabs/fluence/issues/1212
         let call_time_start = Instant::now();
 
-        let result = service
-            .call_async(
-                function_name.clone(),
-                JValue::Array(function_args.function_args),
-                params,
+        let atomic_kv_function = service
+            .service_type
+            .is_spell()
+            .then(|| AtomicKvFunction::from_name(&function_name))
+            .flatten();
+        let result = match atomic_kv_function {
+            // The read and the write are both made under the instance lock taken above
+            Some(function) => CallTokens::executing(
+                peer_scope,
+                service_id.clone(),
+                function.call(app_service, function_args.function_args, params),
             )
-            .await;
+            .await
+            .map(|(result, write)| {
+                kv_writes.extend(write);
+                result
+            }),
+            None => {
+                CallTokens::executing(
+                    peer_scope,
+                    service_id.clone(),
+                    app_service.call_async(
+                        function_name.clone(),
+                        JValue::Array(function_args.function_args),
+                        params,
+                    ),
+                )
+                .await
+            }
+        };
+
+        // sealed under the instance lock, before the next call changes the copy
+        self.sync_service_storage(&service_id).await;
 
         let result = result.map_err(|e| {
+            if !is_unknown_function(&e) {
+                self.record_event(
+                    EventKind::ServiceFailed,
+                    peer_scope,
+                    &service_id,
+                    Some(format!("{function_name}: {e}")),
+                );
+            }
             if let Some(metrics) = self.metrics.as_ref() {
                 let stats = ServiceCallStats::Fail { timestamp };
                 // If the called function is unknown we don't want to save info
@@ -536,12 +1071,25 @@ impl ParticleAppServices {
             ServiceError::Engine(e)
         })?;
 
-        if let Some(metrics) = self.metrics.as_ref() {
-            let call_time_sec = call_time_start.elapsed().as_secs_f64();
-            let lock_wait_time_sec = lock_acquire_start.elapsed().as_secs_f64();
-            let new_memory = service.module_memory_stats();
-            let new_memory_usage = ServicesMetricsBuiltin::get_used_memory(&new_memory);
+        if let (Some(metrics), Some((name, value))) = (self.metrics.as_ref(), kv_metric) {
+            if is_kv_write_success(&result) {
+                metrics.observe_spell_kv_metric(&service_id, &name, value);
+            }
+        }
+        if !kv_writes.is_empty() && is_kv_write_success(&result) {
+            self.notify_kv_writes(peer_scope, &service_id, kv_writes);
+        }
 
+        let call_time_sec = call_time_start.elapsed().as_secs_f64();
+        let lock_wait_time_sec = lock_acquire_start.elapsed().as_secs_f64();
+        let new_memory = app_service.module_memory_stats();
+        let new_memory_usage = ServicesMetricsBuiltin::get_used_memory(&new_memory);
+        let new_memory = ServiceMemoryStat::new(&new_memory);
+        service.record_use(new_memory_usage);
+        drop(instance);
+        self.enforce_memory_budget(&service_id).await;
+
+        if let Some(metrics) = self.metrics.as_ref() {
             let memory_delta_bytes = new_memory_usage - old_mem_usage;
             let stats = ServiceCallStats::Success {
                 memory_delta_bytes: memory_delta_bytes as f64,
@@ -554,7 +1102,7 @@ impl ParticleAppServices {
                 service_id,
                 function_name,
                 service_type,
-                ServiceMemoryStat::new(&new_memory),
+                new_memory,
                 stats,
             );
         }
@@ -579,6 +1127,7 @@ impl ParticleAppServices {
             function_name: function_name.to_string(),
             function_args,
             tetraplets: vec![],
+            sequence: None,
         };
 
         let particle = ParticleParams {
@@ -656,9 +1205,10 @@ impl ParticleAppServices {
         }
 
         // retrieve service by alias
-        let resolved_id = resolve_alias(&services, &id_or_alias, particle_id)
-            .await
-            .ok_or(NoSuchService(id_or_alias.clone(), peer_scope))?;
+        let resolved_id =
+            resolve_alias(&services, self.metrics.as_ref(), &id_or_alias, particle_id)
+                .await
+                .ok_or(NoSuchService(id_or_alias.clone(), peer_scope))?;
 
         let service = get_service(&services_id_mapping, peer_scope, resolved_id.clone())?;
 
@@ -756,7 +1306,7 @@ impl ParticleAppServices {
         particle_id: &str,
     ) -> Result<String, ServiceError> {
         let services = self.get_or_create_services(peer_scope).await;
-        resolve_alias(&services, &alias, particle_id)
+        resolve_alias(&services, self.metrics.as_ref(), &alias, particle_id)
             .await
             .ok_or_else(|| NoSuchAlias(alias, peer_scope))
     }
@@ -854,6 +1404,58 @@ impl ParticleAppServices {
         result
     }
 
+    /// Reports services of the scope that don't use the engine for new services yet
+    pub async fn engine_report(&self, peer_scope: PeerScope) -> EngineReport {
+        let services = match self.get_services(&peer_scope).await {
+            Ok(services) => services
+                .services
+                .read()
+                .await
+                .iter()
+                .map(|(id, service)| (id.clone(), service.engine()))
+                .collect(),
+            Err(_) => vec![],
+        };
+
+        self.engines.report(services)
+    }
+
+    /// Moves the service to the engine for new services.
+    /// The instance is unloaded and created with that engine on the next call.
+    pub async fn migrate_engine(
+        &self,
+        peer_scope: PeerScope,
+        service_id: String,
+        particle_id: &str,
+    ) -> Result<(), ServiceError> {
+        let (service, _) = self
+            .get_service(peer_scope, service_id, particle_id)
+            .await?;
+
+        let engine = self.engines.for_new_services();
+        if service.engine() == engine.id {
+            return Ok(());
+        }
+
+        let mut instance = service.lock().await;
+        *service.engine.lock() = engine.id.clone();
+        PersistedService::from_service(&service)
+            .await
+            .persist(&self.config.services_dir)
+            .await?;
+        if instance.take().is_some() {
+            service.record_use(0);
+            self.release_service_storage(&service.service_id).await;
+        }
+        tracing::info!(
+            "Service {} moved to engine {}",
+            service.service_id,
+            engine.id
+        );
+
+        Ok(())
+    }
+
     pub async fn list_services(&self, peer_scope: PeerScope) -> Vec<ServiceInfo> {
         let services = self.get_services(&peer_scope).await;
         match services {
@@ -886,7 +1488,11 @@ impl ParticleAppServices {
             .await?;
 
         let lock = service.service.lock().await;
-        let stats = lock.module_memory_stats();
+        // an unloaded instance takes no memory
+        let Some(instance) = lock.as_ref() else {
+            return Ok(vec![]);
+        };
+        let stats = instance.module_memory_stats();
         let stats = stats
             .modules
             .into_iter()
@@ -903,104 +1509,180 @@ impl ParticleAppServices {
         Ok(stats)
     }
 
-    pub async fn create_persisted_services(&mut self) -> eyre::Result<()> {
+    /// Restores persisted host services and spells of all scopes, which are needed to start the node.
+    /// The rest of worker services are returned to be restored in the background with
+    /// [`Self::restore_deferred_services`], so particles can be served before all workers are up
+    pub async fn create_persisted_services(&self) -> eyre::Result<DeferredServices> {
         let services = load_persisted_services(&self.config.services_dir).await?;
-        let loaded_service_count = services.len();
-        if let Some(h) = self.health.as_mut() {
+        if let Some(h) = self.health.as_ref() {
+            h.set_total(services.len());
             h.start_creation()
         }
 
-        let mut created_service_count = 0;
+        let mut immediate = vec![];
+        let mut deferred: HashMap<WorkerId, Vec<_>> = HashMap::new();
         for (service, _) in services {
-            let start = Instant::now();
-            // If the service_type doesn't set in PersistedService, will try to find out if it's a spell by blueprint name
-            // This is mostly done for migration from the old detection method to the new.
-            let service_type = service.service_type.unwrap_or_else(|| {
-                let is_spell: Option<_> = try {
-                    let blueprint_name = self
-                        .modules
-                        .get_blueprint_from_cache(&service.blueprint_id)
-                        .ok()?
-                        .name;
-                    blueprint_name == "spell"
-                };
-                if is_spell.unwrap_or(false) {
-                    ServiceType::Spell
-                } else {
-                    ServiceType::Service
-                }
-            });
-            let result = self
-                .create_service_inner(
-                    service_type,
-                    service.blueprint_id,
-                    service.owner_id,
-                    service.peer_scope,
-                    service.service_id.clone(),
-                    service.aliases.clone(),
-                )
-                .await;
-            let replaced = match result {
-                Ok(replaced) => replaced,
-                Err(err) => {
-                    #[rustfmt::skip]
-                    tracing::warn!("Error creating service for persisted service {}: {:#?}", service.service_id, err);
-                    continue;
-                }
-            };
-
+            let service_type = self.persisted_service_type(&service);
             match service.peer_scope {
-                PeerScope::WorkerId(worker_id) => {
-                    let services = self.get_or_create_worker_services(worker_id).await;
-                    let mut aliases = services.aliases.write().await;
-                    for alias in service.aliases.iter() {
-                        let old = aliases.insert(alias.clone(), service.service_id.clone());
-                        if let Some(old) = old {
-                            tracing::warn!(
-                                "Alias `{}` is the same for {} and {}",
-                                alias,
-                                old,
-                                service.service_id
-                            );
-                        }
-                    }
+                PeerScope::WorkerId(worker_id) if !service_type.is_spell() => {
+                    deferred
+                        .entry(worker_id)
+                        .or_default()
+                        .push((service, service_type));
                 }
-                PeerScope::Host => {
-                    let mut aliases = self.root_services.aliases.write().await;
-                    for alias in service.aliases.iter() {
-                        let old = aliases.insert(alias.clone(), service.service_id.clone());
-                        if let Some(old) = old {
-                            tracing::warn!(
-                                "Alias `{}` is the same for {} and {}",
-                                alias,
-                                old,
-                                service.service_id
-                            );
+                _ => immediate.push((service, service_type)),
+            }
+        }
+
+        let start = Instant::now();
+        let total = immediate.len();
+        let failed = stream::iter(immediate)
+            .map(|(service, service_type)| self.restore_persisted_service(service, service_type))
+            .buffer_unordered(self.config.restore_parallelism.max(1))
+            .filter(|restored| futures::future::ready(!restored))
+            .count()
+            .await;
+        tracing::info!(
+            "Restored {} of {} persisted host services and spells in {}",
+            total - failed,
+            total,
+            pretty(start.elapsed())
+        );
+
+        let deferred = DeferredServices {
+            workers: deferred.into_iter().collect(),
+            has_errors: failed > 0,
+        };
+        if deferred.workers.is_empty() {
+            self.finish_restoration(deferred.has_errors);
+        }
+        Ok(deferred)
+    }
+
+    /// Restores worker services left by [`Self::create_persisted_services`].
+    /// Workers are restored concurrently, services of a single worker one by one
+    pub async fn restore_deferred_services(&self, deferred: DeferredServices) {
+        if deferred.workers.is_empty() {
+            return;
+        }
+        let start = Instant::now();
+        let worker_count = deferred.workers.len();
+        let restored_workers = AtomicUsize::new(0);
+        let failed = stream::iter(deferred.workers)
+            .map(|(worker_id, services)| {
+                let restored_workers = &restored_workers;
+                async move {
+                    let mut failed = 0;
+                    for (service, service_type) in services {
+                        if !self.restore_persisted_service(service, service_type).await {
+                            failed += 1;
                         }
                     }
+                    let restored = restored_workers.fetch_add(1, Ordering::Relaxed) + 1;
+                    tracing::info!(
+                        "Restored services of worker {worker_id} ({restored}/{worker_count} workers)"
+                    );
+                    failed
                 }
+            })
+            .buffer_unordered(self.config.restore_parallelism.max(1))
+            .fold(0, |acc, failed| futures::future::ready(acc + failed))
+            .await;
+        tracing::info!(
+            "Restored persisted services of {worker_count} workers in {}",
+            pretty(start.elapsed())
+        );
+        self.finish_restoration(deferred.has_errors || failed > 0);
+    }
+
+    fn finish_restoration(&self, has_errors: bool) {
+        if let Some(h) = self.health.as_ref() {
+            if !has_errors {
+                h.finish_creation()
+            }
+        }
+    }
+
+    // If the service_type doesn't set in PersistedService, will try to find out if it's a spell by blueprint name
+    // This is mostly done for migration from the old detection method to the new.
+    fn persisted_service_type(&self, service: &PersistedService) -> ServiceType {
+        service.service_type.clone().unwrap_or_else(|| {
+            let is_spell: Option<_> = try {
+                let blueprint_name = self
+                    .modules
+                    .get_blueprint_from_cache(&service.blueprint_id)
+                    .ok()?
+                    .name;
+                blueprint_name == "spell"
             };
+            if is_spell.unwrap_or(false) {
+                ServiceType::Spell
+            } else {
+                ServiceType::Service
+            }
+        })
+    }
 
-            debug_assert!(
-                replaced.is_none(),
-                "shouldn't replace any existing services"
-            );
-            created_service_count += 1;
-            tracing::info!(
-                "Persisted service {} created in {}, aliases: {:?}",
-                service.service_id,
-                pretty(start.elapsed()),
-                service.aliases
-            );
+    /// Returns whether the service was restored
+    async fn restore_persisted_service(
+        &self,
+        service: PersistedService,
+        service_type: ServiceType,
+    ) -> bool {
+        let start = Instant::now();
+        let result = self
+            .create_service_inner(
+                service_type,
+                service.blueprint_id,
+                service.owner_id,
+                service.peer_scope,
+                service.service_id.clone(),
+                service.aliases.clone(),
+                service.labels.clone(),
+                service.engine.clone(),
+                service.run_as.clone(),
+            )
+            .await;
+        if let Some(h) = self.health.as_ref() {
+            h.inc_processed()
         }
-        if created_service_count == loaded_service_count {
-            if let Some(h) = self.health.as_mut() {
-                h.finish_creation()
+        let replaced = match result {
+            Ok(replaced) => replaced,
+            Err(err) => {
+                #[rustfmt::skip]
+                tracing::warn!("Error creating service for persisted service {}: {:#?}", service.service_id, err);
+                return false;
             }
         };
-        Ok(())
+
+        let services = self.get_or_create_services(service.peer_scope).await;
+        let mut aliases = services.aliases.write().await;
+        for alias in service.aliases.iter() {
+            let old = aliases.insert(alias.clone(), service.service_id.clone());
+            if let Some(old) = old {
+                tracing::warn!(
+                    "Alias `{}` is the same for {} and {}",
+                    alias,
+                    old,
+                    service.service_id
+                );
+            }
+        }
+
+        debug_assert!(
+            replaced.is_none(),
+            "shouldn't replace any existing services"
+        );
+        tracing::info!(
+            "Persisted service {} created in {}, aliases: {:?}",
+            service.service_id,
+            pretty(start.elapsed()),
+            service.aliases
+        );
+        true
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn create_service_inner(
         &self,
         service_type: ServiceType,
@@ -1009,10 +1691,18 @@ impl ParticleAppServices {
         peer_scope: PeerScope,
         service_id: String,
         aliases: Vec<String>,
+        labels: Labels,
+        engine: Option<String>,
+        run_as: Option<RunAs>,
     ) -> Result<Option<Arc<Service>>, ServiceError> {
         let creation_start_time = Instant::now();
+        let engine = match engine {
+            Some(id) => self.engines.get(Some(&id)),
+            None => self.engines.for_new_services(),
+        };
         let service = self
             .create_app_service(
+                engine,
                 self.scopes.to_peer_id(peer_scope),
                 blueprint_id.clone(),
                 service_id.clone(),
@@ -1023,18 +1713,22 @@ impl ParticleAppServices {
                     metrics.observe_created_failed();
                 }
             })?;
-        let stats = service.module_memory_stats();
-        let stats = ServiceMemoryStat::new(&stats);
+        let memory_stats = service.module_memory_stats();
+        let stats = ServiceMemoryStat::new(&memory_stats);
 
         let service = Service::new(
-            tokio::sync::Mutex::new(service),
+            tokio::sync::Mutex::new(Some(service)),
             service_id.clone(),
             blueprint_id,
             service_type,
             owner_id,
             aliases,
             peer_scope,
+            labels,
+            engine.id.clone(),
         );
+        *service.run_as.lock() = run_as;
+        service.record_use(ServicesMetricsBuiltin::get_used_memory(&memory_stats));
         let service = Arc::new(service);
         // Save created service to disk, so it is recreated on restart
         let persisted_service = PersistedService::from_service(&service).await;
@@ -1049,12 +1743,192 @@ impl ParticleAppServices {
 
         if let Some(m) = self.metrics.as_ref() {
             let creation_end_time = creation_start_time.elapsed().as_secs();
-            m.observe_created(service_id, service_type, stats, creation_end_time as f64);
+            m.observe_created(
+                service_id.clone(),
+                service_type,
+                stats,
+                creation_end_time as f64,
+            );
         }
+        self.enforce_memory_budget(&service_id).await;
 
         Ok(replaced)
     }
 
+    /// Unloads least recently used idle instances while the loaded ones exceed the memory budget.
+    /// `keep` is the service that has just been used, it stays loaded.
+    async fn enforce_memory_budget(&self, keep: &str) {
+        let budget = &self.config.memory_budget;
+        if budget.is_unlimited() {
+            return;
+        }
+
+        let services = self.all_services().await;
+        let loaded = services
+            .values()
+            .filter_map(|s| s.loaded_instance())
+            .collect();
+        for service_id in budget.select_victims(loaded, &keep.to_string()) {
+            let Some(service) = services.get(&service_id) else {
+                continue;
+            };
+            // Busy instances are skipped, they are considered again after the next call
+            let Ok(mut instance) = service.service.try_lock() else {
+                continue;
+            };
+            if instance.take().is_some() {
+                service.record_use(0);
+                tracing::debug!("Service {} unloaded to fit the memory budget", service_id);
+                if let Some(metrics) = self.metrics.as_ref() {
+                    metrics.observe_unloaded();
+                }
+                // released under the lock, so the instance isn't recreated in the meantime
+                self.release_service_storage(&service_id).await;
+            }
+        }
+    }
+
+    /// Services of the host and all workers
+    async fn all_services(&self) -> HashMap<ServiceId, Arc<Service>> {
+        let mut services: HashMap<ServiceId, Arc<Service>> =
+            self.root_services.services.read().await.clone();
+        let worker_services: Vec<Services> = self
+            .worker_services
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect();
+        for worker in worker_services {
+            services.extend(
+                worker
+                    .services
+                    .read()
+                    .await
+                    .iter()
+                    .map(|(id, s)| (id.clone(), s.clone())),
+            );
+        }
+        services
+    }
+
+    /// Unloads all instances and removes decrypted copies of their storage, called on node shutdown
+    pub async fn seal_storage(&self) {
+        if self.config.storage_encryption.is_none() {
+            return;
+        }
+        for (service_id, service) in self.all_services().await {
+            let mut instance = service.service.lock().await;
+            instance.take();
+            self.release_service_storage(&service_id).await;
+        }
+    }
+
+    /// Seals files of the decrypted copy changed since the last sync into the persistent dir.
+    /// Called under the instance lock, so the copy doesn't change meanwhile.
+    async fn sync_service_storage(&self, service_id: &str) {
+        let Some(encryption) = self.config.storage_encryption.clone() else {
+            return;
+        };
+        let Some(mut snapshot) = self.storage_copies.lock().remove(service_id) else {
+            return;
+        };
+        let copy_dir = encryption.plaintext_dir.join(service_id);
+        let sealed_dir = self.config.persistent_work_dir.join(service_id);
+        let result = tokio::task::spawn_blocking(move || {
+            let result = encryption
+                .keys
+                .sync_copy(&copy_dir, &sealed_dir, &mut snapshot);
+            (snapshot, result)
+        })
+        .await;
+        let snapshot = match result {
+            Ok((snapshot, Ok(stats))) => {
+                if stats.processed > 0 {
+                    tracing::debug!(
+                        "Sealed storage of service {}: {} files encrypted",
+                        service_id,
+                        stats.processed
+                    );
+                }
+                snapshot
+            }
+            // unsealed files stay changed in the snapshot, the next sync retries them
+            Ok((snapshot, Err(err))) => {
+                tracing::error!("Failed to seal storage of service {}: {}", service_id, err);
+                snapshot
+            }
+            Err(err) => {
+                tracing::error!(
+                    "Sealing storage of service {} panicked: {}",
+                    service_id,
+                    err
+                );
+                // everything is sealed again by the next sync
+                CopySnapshot::default()
+            }
+        };
+        self.storage_copies
+            .lock()
+            .insert(service_id.to_string(), snapshot);
+    }
+
+    /// Seals the decrypted copy of a service whose instance is unloaded and removes the copy
+    async fn release_service_storage(&self, service_id: &str) {
+        self.sync_service_storage(service_id).await;
+        self.discard_service_storage(service_id).await;
+    }
+
+    /// Removes the decrypted copy without sealing it
+    async fn discard_service_storage(&self, service_id: &str) {
+        let Some(encryption) = self.config.storage_encryption.as_ref() else {
+            return;
+        };
+        self.storage_copies.lock().remove(service_id);
+        let copy_dir = encryption.plaintext_dir.join(service_id);
+        let result = tokio::task::spawn_blocking(move || remove_copy(&copy_dir)).await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::error!(
+                "Failed to remove decrypted storage of service {}: {}",
+                service_id,
+                err
+            ),
+            Err(err) => tracing::error!(
+                "Removing decrypted storage of service {} panicked: {}",
+                service_id,
+                err
+            ),
+        }
+    }
+
+    /// Dir the instance sees as its persistent storage: the persistent dir itself,
+    /// or its decrypted copy when storage encryption is on
+    async fn open_service_storage(
+        &self,
+        service_id: &str,
+        persistent_dir: &Path,
+    ) -> Result<PathBuf, ServiceError> {
+        let Some(encryption) = self.config.storage_encryption.clone() else {
+            return Ok(persistent_dir.to_path_buf());
+        };
+        let copy_dir = encryption.plaintext_dir.join(service_id);
+        let sealed_dir = persistent_dir.to_path_buf();
+        let copy = copy_dir.clone();
+        let snapshot =
+            tokio::task::spawn_blocking(move || encryption.keys.unseal_copy(&sealed_dir, &copy))
+                .await
+                .map_err(|err| InternalError(format!("storage unsealing task failed: {err}")))?
+                .map_err(|err| ServiceError::StorageEncryption {
+                    service_id: service_id.to_string(),
+                    err,
+                })?;
+        self.storage_copies
+            .lock()
+            .insert(service_id.to_string(), snapshot);
+        Ok(copy_dir)
+    }
+
     async fn get_or_create_worker_services(&self, worker_id: WorkerId) -> Services {
         let lock = self.worker_services.read().await;
         let worker_services = lock.get(&worker_id);
@@ -1131,6 +2005,7 @@ impl ParticleAppServices {
 
     async fn create_app_service(
         &self,
+        engine: &Engine,
         current_peer_id: PeerId,
         blueprint_id: String,
         service_id: String,
@@ -1153,6 +2028,26 @@ impl ParticleAppServices {
             })?;
 
         let mut modules_config = self.modules.resolve_blueprint(&blueprint_id)?;
+        if current_peer_id != self.config.local_peer_id {
+            for module in &modules_config {
+                let binaries = module.config.mounted_binaries.iter().flatten();
+                let forbidden = self
+                    .config
+                    .binary_egress
+                    .forbidden(current_peer_id, binaries.map(|(name, _)| name));
+                if let Some(binary) = forbidden {
+                    return Err(ServiceError::EgressForbiddenBinary {
+                        worker_id: current_peer_id,
+                        module: module.name.clone(),
+                        binary: binary.clone(),
+                    });
+                }
+            }
+        }
+
+        let storage_dir = self
+            .open_service_storage(&service_id, &persistent_dir)
+            .await?;
 
         // Create Particle File Vault for Worker
         self.vault.initialize_worker(current_peer_id)?;
@@ -1161,7 +2056,7 @@ impl ParticleAppServices {
             self.inject_default_wasi(module);
             // SAFETY: set wasi to Some in the code before calling inject_vault
             self.vault.inject_vault(current_peer_id, module).unwrap();
-            self.inject_persistent_dirs(module, persistent_dir.as_path())
+            self.inject_persistent_dirs(module, storage_dir.as_path())
                 .await?;
             self.inject_ephemeral_dirs(module, ephemeral_dir.as_path())
                 .await?;
@@ -1187,7 +2082,8 @@ impl ParticleAppServices {
             self.config.envs
         );
 
-        self.app_service_factory
+        engine
+            .factory
             .new_app_service(app_config, service_id, self.config.envs.clone())
             .await
             .map_err(ServiceError::Engine)
@@ -1231,11 +2127,19 @@ fn is_unknown_function(err: &AppServiceError) -> bool {
     )
 }
 
+fn scope_worker_id(peer_scope: PeerScope) -> Option<String> {
+    match peer_scope {
+        PeerScope::WorkerId(worker_id) => Some(PeerId::from(worker_id).to_base58()),
+        PeerScope::Host => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
     use std::path::PathBuf;
     use std::sync::Arc;
+    use std::time::Duration;
 
     use base64::{engine::general_purpose::STANDARD as base64, Engine};
     use fluence_app_service::{TomlMarineModuleConfig, TomlMarineNamedModuleConfig};
@@ -1246,7 +2150,9 @@ mod tests {
     use config_utils::modules_dir;
     use core_distributor::dummy::DummyCoreDistibutor;
     use fluence_libp2p::RandomPeerId;
+    use particle_execution::FunctionOutcome;
     use particle_modules::{AddBlueprint, ModuleRepository};
+    use serde_json::json;
     use service_modules::load_module;
     use service_modules::Hash;
     use types::peer_scope::PeerScope;
@@ -1254,7 +2160,10 @@ mod tests {
 
     use crate::app_services::{ServiceAlias, ServiceType};
     use crate::persistence::load_persisted_services;
-    use crate::{ParticleAppServices, ParticleAppServicesConfig, ServiceError, WasmBackendConfig};
+    use crate::{
+        ParticleAppServices, ParticleAppServicesConfig, ServiceError, StorageEncryption,
+        StorageKeys, WasmBackendConfig,
+    };
 
     fn create_pid() -> PeerId {
         let keypair = Keypair::generate_ed25519();
@@ -1266,6 +2175,15 @@ mod tests {
         root_keypair: Keypair,
         management_pid: PeerId,
         base_dir: PathBuf,
+    ) -> ParticleAppServices {
+        create_pas_with(root_keypair, management_pid, base_dir, |_| {}).await
+    }
+
+    async fn create_pas_with(
+        root_keypair: Keypair,
+        management_pid: PeerId,
+        base_dir: PathBuf,
+        configure: impl FnOnce(&mut ParticleAppServicesConfig),
     ) -> ParticleAppServices {
         let persistent_dir = base_dir.join("persistent");
         let ephemeral_dir = base_dir.join("ephemeral");
@@ -1304,7 +2222,7 @@ mod tests {
         let workers = Arc::new(workers);
         let wasm_backend_config = WasmBackendConfig::default();
 
-        let config = ParticleAppServicesConfig::new(
+        let mut config = ParticleAppServicesConfig::new(
             PeerId::from(root_keypair.public()),
             persistent_dir,
             ephemeral_dir,
@@ -1319,6 +2237,7 @@ mod tests {
             wasm_backend_config,
         )
         .unwrap();
+        configure(&mut config);
 
         let repo = ModuleRepository::new(
             &config.modules_dir,
@@ -1521,6 +2440,55 @@ mod tests {
         assert_eq!(persisted_service_1.aliases, vec![alias.to_string()]);
     }
 
+    #[tokio::test]
+    async fn test_alias_resolution_follows_alias_changes() {
+        let base_dir = TempDir::new("test4").unwrap();
+        let root_keypair = Keypair::generate_ed25519();
+        let management_pid = create_pid();
+        let pas = create_pas(root_keypair, management_pid, base_dir.into_path()).await;
+
+        let module_name = "tetra".to_string();
+        let m_hash = upload_tetra_service(&pas, module_name.clone());
+
+        let service_id1 = create_service(&pas, module_name.clone(), &m_hash, PeerScope::Host)
+            .await
+            .unwrap();
+        let service_id2 = create_service(&pas, module_name, &m_hash, PeerScope::Host)
+            .await
+            .unwrap();
+
+        let alias = "alias".to_string();
+        pas.add_alias(
+            PeerScope::Host,
+            alias.clone(),
+            service_id1.clone(),
+            management_pid,
+        )
+        .await
+        .unwrap();
+        let resolved = pas.to_service_id(PeerScope::Host, alias.clone(), "").await;
+        assert_eq!(resolved.unwrap(), service_id1);
+
+        // the alias is resolved to the service it's moved to
+        pas.add_alias(
+            PeerScope::Host,
+            alias.clone(),
+            service_id2.clone(),
+            management_pid,
+        )
+        .await
+        .unwrap();
+        let resolved = pas.resolve_alias(PeerScope::Host, alias.clone(), "").await;
+        assert_eq!(resolved.unwrap(), service_id2);
+
+        // and to nothing once the service is removed
+        pas.remove_service(PeerScope::Host, "", &service_id2, management_pid, false)
+            .await
+            .unwrap();
+        let resolved = pas.resolve_alias(PeerScope::Host, alias, "").await;
+        assert!(resolved.is_err());
+    }
+
     #[tokio::test]
     async fn test_add_alias_repeated() {
         let base_dir = TempDir::new("test4").unwrap();
@@ -1691,6 +2659,55 @@ mod tests {
         assert_eq!(service_1.owner_id, persisted_service_1.owner_id);
     }
 
+    #[tokio::test]
+    async fn test_storage_sealed_while_loaded() {
+        let base_dir = TempDir::new("test5").unwrap();
+        let plaintext_dir = base_dir.path().join("plaintext");
+        let keys = StorageKeys::from_hex(&"01".repeat(32), &[]).unwrap();
+        let encryption = StorageEncryption {
+            keys: Arc::new(keys),
+            plaintext_dir: plaintext_dir.clone(),
+        };
+        let pas = create_pas_with(
+            Keypair::generate_ed25519(),
+            create_pid(),
+            base_dir.path().into(),
+            |config| config.storage_encryption = Some(encryption),
+        )
+        .await;
+
+        let module_name = "tetra".to_string();
+        let m_hash = upload_tetra_service(&pas, module_name.clone());
+        let service_id = create_service(&pas, module_name.clone(), &m_hash, PeerScope::Host)
+            .await
+            .unwrap();
+
+        // the module sees the copy as /storage
+        let copy = plaintext_dir.join(&service_id).join(&module_name);
+        std::fs::write(copy.join("db"), b"secret kv").unwrap();
+        let result = pas
+            .call_function(
+                PeerScope::Host,
+                &service_id,
+                "not",
+                vec![json!(true)],
+                None,
+                create_pid(),
+                Duration::from_secs(10),
+            )
+            .await;
+        assert!(matches!(result, FunctionOutcome::Ok(_)));
+
+        // the instance is still loaded, yet the persistent dir has only sealed data
+        let persistent_dir = pas.config.persistent_work_dir.join(&service_id);
+        let sealed = std::fs::read(persistent_dir.join(&module_name).join("db")).unwrap();
+        assert!(StorageKeys::is_sealed(&sealed));
+        assert!(!sealed.windows(9).any(|w| w == b"secret kv"));
+
+        pas.seal_storage().await;
+        assert!(!plaintext_dir.join(&service_id).exists());
+    }
+
     // TODO: add more tests
     //       - add alias success & fail with service collision & test on rewriting alias
     //       - create_service success & fail
@@ -0,0 +1,139 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+use fluence_libp2p::PeerId;
+use types::peer_scope::PeerScope;
+
+/// Everything known about a service call at the moment it's authorized
+#[derive(Debug, Clone)]
+pub struct CallContext<'a> {
+    /// Init peer id of the particle making the call
+    pub caller: PeerId,
+    /// Scope (host or worker) the call is made in
+    pub peer_scope: PeerScope,
+    /// Peer id of the host or worker the call is made on
+    pub worker_id: PeerId,
+    /// Resolved id of the target service
+    pub service_id: &'a str,
+    /// Aliases of the target service
+    pub service_aliases: &'a [String],
+    /// Peer id of the service creator
+    pub service_owner: PeerId,
+    pub function_name: &'a str,
+    /// Run-as identity of the spell making the call, if the spell was installed with one
+    pub identity: Option<&'a str>,
+}
+
+/// Policy consulted before every service call.
+/// Returning an error rejects the call with `PERMISSION_DENIED`.
+pub trait CallAuthorizer: Debug + Send + Sync {
+    fn authorize(&self, ctx: &CallContext<'_>) -> Result<(), String>;
+}
+
+/// Allows every call, which is how the node behaved before authorization hooks existed
+#[derive(Debug, Default, Clone)]
+pub struct AllowAll;
+
+impl CallAuthorizer for AllowAll {
+    fn authorize(&self, _ctx: &CallContext<'_>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Allows calls to the listed services (by id or alias) only from trusted peers
+/// or from the host/worker the service is deployed on. Everything else is allowed.
+#[derive(Debug, Clone)]
+pub struct InternalOnlyServices {
+    services: HashSet<String>,
+    trusted: HashSet<PeerId>,
+}
+
+impl InternalOnlyServices {
+    pub fn new(
+        services: impl IntoIterator<Item = String>,
+        trusted: impl IntoIterator<Item = PeerId>,
+    ) -> Self {
+        Self {
+            services: services.into_iter().collect(),
+            trusted: trusted.into_iter().collect(),
+        }
+    }
+
+    fn is_internal(&self, ctx: &CallContext<'_>) -> bool {
+        self.services.contains(ctx.service_id)
+            || ctx
+                .service_aliases
+                .iter()
+                .any(|alias| self.services.contains(alias))
+    }
+}
+
+impl CallAuthorizer for InternalOnlyServices {
+    fn authorize(&self, ctx: &CallContext<'_>) -> Result<(), String> {
+        if !self.is_internal(ctx)
+            || ctx.caller == ctx.worker_id
+            || self.trusted.contains(&ctx.caller)
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "service '{}' is internal and can't be called by {}",
+                ctx.service_id, ctx.caller
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx<'a>(caller: PeerId, worker_id: PeerId, aliases: &'a [String]) -> CallContext<'a> {
+        CallContext {
+            caller,
+            peer_scope: PeerScope::Host,
+            worker_id,
+            service_id: "some-service-id",
+            service_aliases: aliases,
+            service_owner: worker_id,
+            function_name: "get",
+            identity: None,
+        }
+    }
+
+    #[test]
+    fn internal_only_services() {
+        let host = PeerId::random();
+        let manager = PeerId::random();
+        let stranger = PeerId::random();
+        let policy = InternalOnlyServices::new(vec!["registry".to_string()], vec![manager]);
+
+        let internal = vec!["registry".to_string()];
+        assert!(policy.authorize(&ctx(host, host, &internal)).is_ok());
+        assert!(policy.authorize(&ctx(manager, host, &internal)).is_ok());
+        assert!(policy.authorize(&ctx(stranger, host, &internal)).is_err());
+
+        let public = vec!["trust-graph".to_string()];
+        assert!(policy.authorize(&ctx(stranger, host, &public)).is_ok());
+        assert!(AllowAll.authorize(&ctx(stranger, host, &internal)).is_ok());
+    }
+}
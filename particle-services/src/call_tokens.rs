@@ -0,0 +1,247 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Concurrency tokens of service calls.
+//!
+//! Every service call of a peer scope takes a token, so a saturated worker queues calls instead of
+//! blocking all of its threads. A call that's made while the same task already holds a token of
+//! the same scope (a service calling back into its worker, e.g. through a host function) would wait
+//! for a token its own caller can't release, so it can never take a regular token. It's given one of
+//! the few reserved tokens instead, or fails right away when they are all in use. A nested call to
+//! the very service that is being executed fails right away too: the service instance is locked by
+//! the caller.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use thiserror::Error;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use types::peer_scope::PeerScope;
+
+/// How many nested calls of a scope may be executed at once by default
+pub const DEFAULT_NESTED_CALL_RESERVE: usize = 1;
+
+tokio::task_local! {
+    /// Services the current task is executing calls of
+    static EXECUTING: Vec<(PeerScope, String)>;
+}
+
+#[derive(Debug, Error)]
+pub enum NestedCallError {
+    #[error("service '{service_id}' calls itself, its instance is busy with the outer call")]
+    SelfCall { service_id: String },
+    #[error("all {reserve} tokens reserved for nested calls on {peer_scope:?} are in use")]
+    ReserveExhausted {
+        peer_scope: PeerScope,
+        reserve: usize,
+    },
+}
+
+struct ScopeTokens {
+    /// `None` when calls of the scope aren't limited
+    regular: Option<Arc<Semaphore>>,
+    reserved: Arc<Semaphore>,
+}
+
+/// Held while a service call is executed
+pub struct CallToken {
+    _permit: Option<OwnedSemaphorePermit>,
+    /// Whether the token was taken from the nested calls reserve
+    pub nested: bool,
+}
+
+pub struct CallTokens {
+    /// Regular tokens per scope, `None` for no limit
+    limit: Option<usize>,
+    /// Tokens per scope for nested calls
+    reserve: usize,
+    scopes: Mutex<HashMap<PeerScope, Arc<ScopeTokens>>>,
+}
+
+impl CallTokens {
+    pub fn new(limit: Option<usize>, reserve: usize) -> Self {
+        Self {
+            limit,
+            reserve,
+            scopes: <_>::default(),
+        }
+    }
+
+    fn scope_tokens(&self, peer_scope: PeerScope) -> Arc<ScopeTokens> {
+        self.scopes
+            .lock()
+            .entry(peer_scope)
+            .or_insert_with(|| {
+                Arc::new(ScopeTokens {
+                    regular: self.limit.map(|limit| Arc::new(Semaphore::new(limit))),
+                    reserved: Arc::new(Semaphore::new(self.reserve)),
+                })
+            })
+            .clone()
+    }
+
+    /// Takes a token for a call of `service_id` on `peer_scope`.
+    /// Waits for a regular token, unless it's a nested call: those never wait.
+    pub async fn acquire(
+        &self,
+        peer_scope: PeerScope,
+        service_id: &str,
+    ) -> Result<CallToken, NestedCallError> {
+        let executing = EXECUTING.try_with(|e| e.clone()).unwrap_or_default();
+        if executing
+            .iter()
+            .any(|(scope, id)| *scope == peer_scope && id == service_id)
+        {
+            return Err(NestedCallError::SelfCall {
+                service_id: service_id.to_string(),
+            });
+        }
+
+        let tokens = self.scope_tokens(peer_scope);
+        if executing.iter().any(|(scope, _)| *scope == peer_scope) {
+            let permit = tokens.reserved.clone().try_acquire_owned().map_err(|_| {
+                NestedCallError::ReserveExhausted {
+                    peer_scope,
+                    reserve: self.reserve,
+                }
+            })?;
+            return Ok(CallToken {
+                _permit: Some(permit),
+                nested: true,
+            });
+        }
+
+        let permit = match &tokens.regular {
+            Some(regular) => Some(
+                regular
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("call tokens semaphore is never closed"),
+            ),
+            None => None,
+        };
+        Ok(CallToken {
+            _permit: permit,
+            nested: false,
+        })
+    }
+
+    /// Executes `fut` as a call of `service_id`, so calls made from it are known to be nested
+    pub async fn executing<F: Future>(
+        peer_scope: PeerScope,
+        service_id: String,
+        fut: F,
+    ) -> F::Output {
+        let mut executing = EXECUTING.try_with(|e| e.clone()).unwrap_or_default();
+        executing.push((peer_scope, service_id));
+        EXECUTING.scope(executing, fut).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+
+    const WAIT: Duration = Duration::from_millis(200);
+
+    #[tokio::test]
+    async fn nested_call_on_saturated_scope_hangs_without_reserve() {
+        let tokens = CallTokens::new(Some(1), 0);
+        let _outer = tokens.acquire(PeerScope::Host, "a").await.unwrap();
+
+        // Nothing marks the call as nested, so it waits for the token its caller holds
+        let nested = timeout(WAIT, tokens.acquire(PeerScope::Host, "b")).await;
+        assert!(nested.is_err(), "call must hang");
+    }
+
+    #[tokio::test]
+    async fn nested_call_gets_reserved_token() {
+        let tokens = Arc::new(CallTokens::new(Some(1), 1));
+        let outer = tokens.acquire(PeerScope::Host, "a").await.unwrap();
+        assert!(!outer.nested);
+
+        let inner = CallTokens::executing(PeerScope::Host, "a".to_string(), {
+            let tokens = tokens.clone();
+            async move { timeout(WAIT, tokens.acquire(PeerScope::Host, "b")).await }
+        })
+        .await
+        .expect("nested call must not hang")
+        .unwrap();
+        assert!(inner.nested);
+    }
+
+    #[tokio::test]
+    async fn nested_call_fails_fast_when_reserve_is_exhausted() {
+        let tokens = Arc::new(CallTokens::new(Some(1), 1));
+        let _outer = tokens.acquire(PeerScope::Host, "a").await.unwrap();
+
+        let result = CallTokens::executing(PeerScope::Host, "a".to_string(), {
+            let tokens = tokens.clone();
+            async move {
+                let _first = tokens.acquire(PeerScope::Host, "b").await.unwrap();
+                CallTokens::executing(PeerScope::Host, "b".to_string(), async {
+                    timeout(WAIT, tokens.acquire(PeerScope::Host, "c")).await
+                })
+                .await
+            }
+        })
+        .await
+        .expect("nested call must not hang");
+        assert!(matches!(
+            result,
+            Err(NestedCallError::ReserveExhausted { reserve: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn self_call_fails_fast() {
+        let tokens = Arc::new(CallTokens::new(None, 1));
+
+        let result = CallTokens::executing(PeerScope::Host, "a".to_string(), {
+            let tokens = tokens.clone();
+            async move { timeout(WAIT, tokens.acquire(PeerScope::Host, "a")).await }
+        })
+        .await
+        .expect("self call must not hang");
+        assert!(matches!(result, Err(NestedCallError::SelfCall { .. })));
+    }
+
+    #[tokio::test]
+    async fn calls_of_other_scopes_are_not_nested() {
+        let tokens = Arc::new(CallTokens::new(Some(1), 0));
+        let worker = PeerScope::WorkerId(libp2p_identity::PeerId::random().into());
+
+        let token = CallTokens::executing(PeerScope::Host, "a".to_string(), {
+            let tokens = tokens.clone();
+            async move { timeout(WAIT, tokens.acquire(worker, "a")).await }
+        })
+        .await
+        .expect("call must not hang")
+        .unwrap();
+        assert!(!token.nested);
+    }
+}
@@ -23,11 +23,22 @@ use bytesize::ByteSize;
 use cid_utils::Hash;
 use fluence_app_service::WasmtimeConfig;
 use libp2p_identity::PeerId;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
-#[derive(Debug, Clone)]
+use derivative::Derivative;
+
+use crate::authorization::{AllowAll, CallAuthorizer};
+use crate::call_tokens::DEFAULT_NESTED_CALL_RESERVE;
+use crate::memory_budget::MemoryBudget;
+use crate::storage_encryption::StorageEncryption;
+
+const DEFAULT_RESTORE_PARALLELISM: usize = 4;
+
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
 pub struct ParticleAppServicesConfig {
     /// Peer id of the current node
     pub local_peer_id: PeerId,
@@ -61,6 +72,59 @@ pub struct ParticleAppServicesConfig {
     pub is_dev_mode: bool,
     /// config for the wasmtime backend
     pub wasm_backend_config: WasmBackendConfig,
+    /// config of the engine for new services during an upgrade window,
+    /// services created before keep the engine configured by `wasm_backend_config`
+    pub wasm_backend_next: Option<WasmBackendConfig>,
+    /// How many persisted services (or workers) are restored concurrently at startup
+    pub restore_parallelism: usize,
+    /// Limits on loaded service instances; idle instances over the limit are unloaded
+    pub memory_budget: MemoryBudget,
+    /// How many calls of one peer scope are executed at once, unlimited if `None`
+    pub call_concurrency: Option<usize>,
+    /// Tokens per peer scope reserved for calls made from inside another call of the same scope,
+    /// see [`crate::call_tokens`]
+    pub nested_call_reserve: usize,
+    /// Policy consulted before every service call
+    #[derivative(Debug = "ignore")]
+    pub call_authorizer: Arc<dyn CallAuthorizer>,
+    /// Encryption of persistent dirs of services at rest, not encrypted if `None`
+    pub storage_encryption: Option<StorageEncryption>,
+    /// Mounted binaries allowed on workers with restricted network egress
+    pub binary_egress: BinaryEgress,
+}
+
+/// The node can't see where mounted binaries connect to, so services of workers with
+/// restricted network egress may only mount the binaries trusted to respect the policy
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct BinaryEgress {
+    /// Whether network egress of the worker is restricted
+    #[derivative(Debug = "ignore")]
+    pub restricted: Arc<dyn Fn(PeerId) -> bool + Send + Sync>,
+    pub trusted_binaries: HashSet<String>,
+}
+
+impl BinaryEgress {
+    /// First binary mounted by `binaries` which the worker may not use
+    pub fn forbidden<'a>(
+        &self,
+        worker_id: PeerId,
+        mut binaries: impl Iterator<Item = &'a String>,
+    ) -> Option<&'a String> {
+        if !(self.restricted)(worker_id) {
+            return None;
+        }
+        binaries.find(|binary| !self.trusted_binaries.contains(*binary))
+    }
+}
+
+impl Default for BinaryEgress {
+    fn default() -> Self {
+        Self {
+            restricted: Arc::new(|_| false),
+            trusted_binaries: HashSet::new(),
+        }
+    }
 }
 
 impl ParticleAppServicesConfig {
@@ -138,6 +202,14 @@ impl ParticleAppServicesConfig {
             mounted_binaries_mapping,
             is_dev_mode,
             wasm_backend_config,
+            wasm_backend_next: None,
+            restore_parallelism: DEFAULT_RESTORE_PARALLELISM,
+            memory_budget: MemoryBudget::default(),
+            call_concurrency: None,
+            nested_call_reserve: DEFAULT_NESTED_CALL_RESERVE,
+            call_authorizer: Arc::new(AllowAll),
+            storage_encryption: None,
+            binary_egress: BinaryEgress::default(),
         };
 
         create_dirs(&[
@@ -193,3 +265,25 @@ impl Default for WasmBackendConfig {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restricted_workers_only_mount_trusted_binaries() {
+        let restricted = PeerId::random();
+        let egress = BinaryEgress {
+            restricted: Arc::new(move |worker_id| worker_id == restricted),
+            trusted_binaries: HashSet::from(["ipfs".to_string()]),
+        };
+        let binaries = ["ipfs".to_string(), "curl".to_string()];
+
+        assert_eq!(
+            egress.forbidden(restricted, binaries.iter()),
+            Some(&"curl".to_string())
+        );
+        assert_eq!(egress.forbidden(restricted, binaries[..1].iter()), None);
+        assert_eq!(egress.forbidden(PeerId::random(), binaries.iter()), None);
+    }
+}
@@ -0,0 +1,162 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use std::time::Duration;
+
+use fluence_app_service::{AppServiceFactory, EpochTicker, WasmtimeConfig};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio_stream::wrappers::IntervalStream;
+
+use crate::error::ServiceError;
+use crate::WasmBackendConfig;
+
+/// Wasm engine that instantiates services.
+/// Engines are identified by a fingerprint of their configuration, so services keep
+/// the engine they were created with as long as it is configured.
+#[derive(Clone)]
+pub struct Engine {
+    pub id: String,
+    pub factory: AppServiceFactory,
+    /// Kept alive to interrupt long running calls
+    #[allow(unused)]
+    epoch_ticker: EpochTicker,
+}
+
+impl Engine {
+    pub fn new(config: &WasmBackendConfig) -> Result<Self, ServiceError> {
+        let wasmtime_config: WasmtimeConfig = config.clone().into();
+        let (factory, epoch_ticker) =
+            AppServiceFactory::new(wasmtime_config).map_err(ServiceError::Engine)?;
+
+        // TODO: make a setting for that
+        let stream = IntervalStream::new(tokio::time::interval(Duration::from_secs(1)));
+        let ticker = epoch_ticker.clone();
+        tokio::task::spawn(async move {
+            stream
+                .for_each(|_| async {
+                    ticker.increment_epoch();
+                })
+                .await;
+        });
+
+        Ok(Self {
+            id: engine_id(config),
+            factory,
+            epoch_ticker,
+        })
+    }
+}
+
+/// Engines used side by side during an upgrade window: services created before keep
+/// the `current` engine, while new services are created with the `next` one
+#[derive(Clone)]
+pub struct Engines {
+    current: Engine,
+    next: Option<Engine>,
+}
+
+impl Engines {
+    pub fn new(
+        current: &WasmBackendConfig,
+        next: Option<&WasmBackendConfig>,
+    ) -> Result<Self, ServiceError> {
+        let current = Engine::new(current)?;
+        let next = next.map(Engine::new).transpose()?;
+        // same configuration means there's nothing to upgrade
+        let next = next.filter(|next| next.id != current.id);
+
+        Ok(Self { current, next })
+    }
+
+    /// Engine for newly created services
+    pub fn for_new_services(&self) -> &Engine {
+        self.next.as_ref().unwrap_or(&self.current)
+    }
+
+    /// Engine the service was created with, or the current one if that engine isn't configured anymore
+    pub fn get(&self, id: Option<&str>) -> &Engine {
+        match (id, self.next.as_ref()) {
+            (Some(id), Some(next)) if next.id == id => next,
+            _ => &self.current,
+        }
+    }
+
+    pub fn report(&self, services: Vec<(String, String)>) -> EngineReport {
+        let new_engine = &self.for_new_services().id;
+        let outdated = services
+            .into_iter()
+            .filter(|(_, engine)| engine != new_engine)
+            .map(|(service_id, _)| service_id)
+            .collect();
+
+        EngineReport {
+            current: self.current.id.clone(),
+            next: self.next.as_ref().map(|e| e.id.clone()),
+            outdated,
+        }
+    }
+}
+
+/// Which engines are in use, and which services haven't moved to the engine for new services yet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngineReport {
+    pub current: String,
+    pub next: Option<String>,
+    pub outdated: Vec<String>,
+}
+
+fn engine_id(config: &WasmBackendConfig) -> String {
+    let hash = blake3::hash(format!("{config:?}").as_bytes());
+    format!("wasmtime-{}", &hash.to_hex()[..8])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn new_services_use_next_engine() {
+        let current = WasmBackendConfig::default();
+        let next = WasmBackendConfig {
+            wasm_backtrace: false,
+            ..current.clone()
+        };
+
+        let engines = Engines::new(&current, None).unwrap();
+        assert_eq!(engines.for_new_services().id, engines.current.id);
+
+        let engines = Engines::new(&current, Some(&current)).unwrap();
+        assert!(engines.next.is_none());
+
+        let engines = Engines::new(&current, Some(&next)).unwrap();
+        let current_id = engines.current.id.clone();
+        let next_id = engines.for_new_services().id.clone();
+        assert_ne!(current_id, next_id);
+        assert_eq!(engines.get(Some(&next_id)).id, next_id);
+        assert_eq!(engines.get(Some("unknown")).id, current_id);
+        assert_eq!(engines.get(None).id, current_id);
+
+        let report = engines.report(vec![
+            ("old".to_string(), current_id.clone()),
+            ("new".to_string(), next_id.clone()),
+        ]);
+        assert_eq!(report.next, Some(next_id));
+        assert_eq!(report.outdated, vec!["old".to_string()]);
+    }
+}
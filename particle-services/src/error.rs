@@ -26,9 +26,13 @@ use thiserror::Error;
 
 use fluence_libp2p::PeerId;
 use json_utils::err_as_value;
-use particle_args::ArgsError;
+use particle_args::{ArgsError, ErrorCode, ErrorCoded};
 use particle_execution::VaultError;
 use particle_modules::ModuleError;
+
+use crate::call_tokens::NestedCallError;
+use crate::ordering::OrderingError;
+use crate::storage_encryption::StorageEncryptionError;
 use types::peer_scope::{PeerScope, WorkerId};
 
 #[derive(Debug, Error)]
@@ -50,6 +54,12 @@ pub enum ServiceError {
         function: &'static str,
         reason: &'static str,
     },
+    #[error("Forbidden. Call of '{service_id}.{function}' rejected: {reason}")]
+    CallForbidden {
+        service_id: String,
+        function: String,
+        reason: String,
+    },
     #[error("Forbidden. User id '{0}' cannot call function 'add_alias': only management peer id can add top-level aliases")]
     ForbiddenAliasRoot(PeerId),
     #[error("Forbidden. User id '{0}' cannot call function 'add_alias': only worker, worker creator and management peer id can add worker-level aliases")]
@@ -58,6 +68,12 @@ pub enum ServiceError {
     AliasAsServiceId(String),
     #[error("Cannot add alias '{0}' because it is reserved")]
     ForbiddenAlias(String),
+    #[error("Forbidden. Module '{module}' mounts binary '{binary}', which can't be used on worker {worker_id} with restricted network egress")]
+    EgressForbiddenBinary {
+        worker_id: PeerId,
+        module: String,
+        binary: String,
+    },
     #[error(transparent)]
     Engine(AppServiceError),
     #[error(transparent)]
@@ -109,6 +125,26 @@ pub enum ServiceError {
     InternalError(String),
     #[error("Worker {worker_id} not found")]
     WorkerNotFound { worker_id: WorkerId },
+    #[error("Ordered call to service '{service_id}' failed: {err}")]
+    Ordering {
+        service_id: String,
+        #[source]
+        err: OrderingError,
+    },
+    #[error("Nested call to service '{service_id}' rejected: {err}")]
+    NestedCall {
+        service_id: String,
+        #[source]
+        err: NestedCallError,
+    },
+    #[error("Service '{0}' doesn't declare a health function")]
+    NoHealthFunction(String),
+    #[error("Failed to decrypt storage of service '{service_id}': {err}")]
+    StorageEncryption {
+        service_id: String,
+        #[source]
+        err: StorageEncryptionError,
+    },
     #[error("Failed to create directory {path}: {err}")]
     FailedToCreateDirectory {
         path: PathBuf,
@@ -135,6 +171,38 @@ impl From<ModuleError> for ServiceError {
     }
 }
 
+impl ErrorCoded for ServiceError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            ServiceError::NoSuchService(..)
+            | ServiceError::NoSuchServiceWithFunction { .. }
+            | ServiceError::NoSuchAlias(..)
+            | ServiceError::CallServiceFailedWrongWorker { .. } => ErrorCode::ServiceNotFound,
+            ServiceError::Forbidden { .. }
+            | ServiceError::CallForbidden { .. }
+            | ServiceError::ForbiddenAliasRoot(_)
+            | ServiceError::ForbiddenAliasWorker(_)
+            | ServiceError::EgressForbiddenBinary { .. } => ErrorCode::PermissionDenied,
+            ServiceError::AliasAsServiceId(_) => ErrorCode::AlreadyExists,
+            ServiceError::ForbiddenAlias(_) => ErrorCode::InvalidArgument,
+            ServiceError::ArgParseError(err) => err.error_code(),
+            ServiceError::WorkerNotFound { .. } => ErrorCode::WorkerNotFound,
+            ServiceError::Ordering {
+                err: OrderingError::BufferFull { .. },
+                ..
+            } => ErrorCode::QuotaExceeded,
+            ServiceError::Ordering { .. } => ErrorCode::FailedPrecondition,
+            ServiceError::NestedCall {
+                err: NestedCallError::ReserveExhausted { .. },
+                ..
+            } => ErrorCode::QuotaExceeded,
+            ServiceError::NestedCall { .. } => ErrorCode::FailedPrecondition,
+            ServiceError::NoHealthFunction(_) => ErrorCode::FailedPrecondition,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
 impl From<ServiceError> for JValue {
     fn from(err: ServiceError) -> Self {
         err_as_value(err)
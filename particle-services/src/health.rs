@@ -19,12 +19,15 @@
 
 use health::HealthCheck;
 use parking_lot::RwLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct PersistedServiceHealth {
     started: Arc<RwLock<bool>>,
     has_errors: Arc<RwLock<bool>>,
+    total: Arc<AtomicUsize>,
+    processed: Arc<AtomicUsize>,
 }
 
 impl PersistedServiceHealth {
@@ -32,15 +35,26 @@ impl PersistedServiceHealth {
         PersistedServiceHealth {
             started: Arc::new(RwLock::new(false)),
             has_errors: Arc::new(RwLock::new(true)), // this is true by default because we wont to show error while loading in progress
+            total: Arc::new(AtomicUsize::new(0)),
+            processed: Arc::new(AtomicUsize::new(0)),
         }
     }
 
-    pub fn start_creation(&mut self) {
+    pub fn set_total(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Count a persisted service as processed, whether it was restored or failed
+    pub fn inc_processed(&self) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn start_creation(&self) {
         let mut guard = self.started.write();
         *guard = true;
     }
 
-    pub fn finish_creation(&mut self) {
+    pub fn finish_creation(&self) {
         let mut guard = self.has_errors.write();
         *guard = false;
     }
@@ -64,6 +78,17 @@ impl HealthCheck for PersistedServiceHealth {
             ))
         }
     }
+
+    fn progress(&self) -> Option<(usize, usize)> {
+        if *self.started.read() {
+            Some((
+                self.processed.load(Ordering::Relaxed),
+                self.total.load(Ordering::Relaxed),
+            ))
+        } else {
+            None
+        }
+    }
 }
 
 #[cfg(test)]
@@ -80,7 +105,7 @@ mod tests {
 
     #[test]
     fn test_persisted_service_health_started_with_errors() {
-        let mut health = PersistedServiceHealth::new();
+        let health = PersistedServiceHealth::new();
         health.start_creation();
         let status = health.status();
         assert!(status.is_err());
@@ -88,20 +113,37 @@ mod tests {
 
     #[test]
     fn test_persisted_service_health_started_without_errors() {
-        let mut health = PersistedServiceHealth::new();
+        let health = PersistedServiceHealth::new();
         health.start_creation();
         health.finish_creation();
         let status = health.status();
         assert!(status.is_ok());
     }
 
+    #[test]
+    fn test_persisted_service_health_progress() {
+        let health = PersistedServiceHealth::new();
+        assert_eq!(health.progress(), None);
+
+        health.set_total(2);
+        health.start_creation();
+        health.inc_processed();
+        assert_eq!(health.progress(), Some((1, 2)));
+        assert!(health.status().is_err());
+
+        health.inc_processed();
+        health.finish_creation();
+        assert_eq!(health.progress(), Some((2, 2)));
+        assert!(health.status().is_ok());
+    }
+
     #[test]
     fn persisted_service_health_concurrent_access() {
         let persisted_health = Arc::new(RwLock::new(PersistedServiceHealth::new()));
         let health_clone = persisted_health.clone();
 
         let thread_handle = thread::spawn(move || {
-            let mut health = health_clone.write();
+            let health = health_clone.write();
             health.start_creation();
             health.finish_creation();
         });
@@ -0,0 +1,221 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Key/value labels of services and spells, and selectors to filter them.
+//!
+//! Both are written as comma-separated lists: labels as `app=indexer,env=prod`,
+//! selectors as `app=indexer,env!=dev,tier`, where a bare key only requires the label to be set.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+pub type Labels = BTreeMap<String, String>;
+
+const MAX_LABELS: usize = 32;
+const MAX_KEY_LEN: usize = 63;
+const MAX_VALUE_LEN: usize = 63;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LabelError {
+    #[error("invalid label '{0}': expected key=value")]
+    InvalidLabel(String),
+    #[error("invalid label key '{0}': must be 1-{MAX_KEY_LEN} characters of [a-zA-Z0-9._/-]")]
+    InvalidKey(String),
+    #[error(
+        "invalid label value '{0}': must be at most {MAX_VALUE_LEN} characters of [a-zA-Z0-9._/-]"
+    )]
+    InvalidValue(String),
+    #[error("label '{0}' is set more than once")]
+    DuplicateKey(String),
+    #[error("too many labels: {0}, at most {MAX_LABELS} are allowed")]
+    TooMany(usize),
+    #[error("invalid selector requirement '{0}'")]
+    InvalidRequirement(String),
+}
+
+fn is_valid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '/' | '-')
+}
+
+fn check_key(key: &str) -> Result<(), LabelError> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN || !key.chars().all(is_valid_char) {
+        return Err(LabelError::InvalidKey(key.to_string()));
+    }
+    Ok(())
+}
+
+fn check_value(value: &str) -> Result<(), LabelError> {
+    if value.len() > MAX_VALUE_LEN || !value.chars().all(is_valid_char) {
+        return Err(LabelError::InvalidValue(value.to_string()));
+    }
+    Ok(())
+}
+
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Parse labels in the `key=value,key2=value2` form
+pub fn parse_labels(labels: &str) -> Result<Labels, LabelError> {
+    let mut result = Labels::new();
+    for label in split_list(labels) {
+        let (key, value) = label
+            .split_once('=')
+            .ok_or_else(|| LabelError::InvalidLabel(label.to_string()))?;
+        let (key, value) = (key.trim(), value.trim());
+        check_key(key)?;
+        check_value(value)?;
+        if result.insert(key.to_string(), value.to_string()).is_some() {
+            return Err(LabelError::DuplicateKey(key.to_string()));
+        }
+    }
+    if result.len() > MAX_LABELS {
+        return Err(LabelError::TooMany(result.len()));
+    }
+    Ok(result)
+}
+
+/// Format labels as `key=value` strings
+pub fn format_labels(labels: &Labels) -> Vec<String> {
+    labels
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    Exists(String),
+}
+
+impl Requirement {
+    fn matches(&self, labels: &Labels) -> bool {
+        match self {
+            Requirement::Equals(key, value) => labels.get(key) == Some(value),
+            Requirement::NotEquals(key, value) => labels.get(key) != Some(value),
+            Requirement::Exists(key) => labels.contains_key(key),
+        }
+    }
+}
+
+/// Set of requirements on labels, all of them must hold for a match.
+/// An empty selector matches everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LabelSelector {
+    requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    pub fn parse(selector: &str) -> Result<Self, LabelError> {
+        let requirements = split_list(selector)
+            .map(|requirement| {
+                let invalid = || LabelError::InvalidRequirement(requirement.to_string());
+                let parsed = if let Some((key, value)) = requirement.split_once("!=") {
+                    Requirement::NotEquals(key.trim().to_string(), value.trim().to_string())
+                } else if let Some((key, value)) = requirement.split_once('=') {
+                    Requirement::Equals(key.trim().to_string(), value.trim().to_string())
+                } else {
+                    Requirement::Exists(requirement.to_string())
+                };
+                match &parsed {
+                    Requirement::Equals(key, value) | Requirement::NotEquals(key, value) => {
+                        check_key(key).map_err(|_| invalid())?;
+                        check_value(value).map_err(|_| invalid())?;
+                    }
+                    Requirement::Exists(key) => check_key(key).map_err(|_| invalid())?,
+                }
+                Ok(parsed)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { requirements })
+    }
+
+    pub fn matches(&self, labels: &Labels) -> bool {
+        self.requirements.iter().all(|r| r.matches(labels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::btreemap;
+
+    #[test]
+    fn test_parse_labels() {
+        let labels = parse_labels("app=indexer, env=prod,,").unwrap();
+        assert_eq!(
+            labels,
+            btreemap! {
+                "app".to_string() => "indexer".to_string(),
+                "env".to_string() => "prod".to_string(),
+            }
+        );
+        assert_eq!(format_labels(&labels), vec!["app=indexer", "env=prod"]);
+        assert_eq!(parse_labels("").unwrap(), Labels::new());
+
+        assert_eq!(
+            parse_labels("app"),
+            Err(LabelError::InvalidLabel("app".to_string()))
+        );
+        assert_eq!(
+            parse_labels("app=a,app=b"),
+            Err(LabelError::DuplicateKey("app".to_string()))
+        );
+        assert_eq!(
+            parse_labels("=value"),
+            Err(LabelError::InvalidKey("".to_string()))
+        );
+        assert_eq!(
+            parse_labels("app=a b"),
+            Err(LabelError::InvalidValue("a b".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_selector() {
+        let labels = parse_labels("app=indexer,env=prod,tier=backend").unwrap();
+
+        let matching = [
+            "",
+            "app=indexer",
+            "app=indexer,env=prod",
+            "env!=dev",
+            "tier",
+            "missing!=value",
+        ];
+        for selector in matching {
+            let selector = LabelSelector::parse(selector).unwrap();
+            assert!(selector.matches(&labels), "{selector:?} must match");
+        }
+
+        let not_matching = ["app=other", "app=indexer,env=dev", "env!=prod", "missing"];
+        for selector in not_matching {
+            let selector = LabelSelector::parse(selector).unwrap();
+            assert!(!selector.matches(&labels), "{selector:?} must not match");
+        }
+
+        assert!(LabelSelector::parse("app==indexer").is_err());
+        assert!(LabelSelector::parse("a b").is_err());
+    }
+}
@@ -32,19 +32,44 @@
 
 pub use fluence_app_service::{IType, IValue};
 
+pub use app_services::DeferredServices;
 pub use app_services::ParticleAppServices;
 pub use app_services::ServiceType;
 
 pub use crate::error::ServiceError;
 
 mod app_services;
+mod authorization;
+mod call_tokens;
+mod engines;
 mod error;
 mod health;
+mod labels;
+mod memory_budget;
+mod ordering;
 mod persistence;
+mod run_as;
+mod service_health;
+mod spell_kv_atomic;
+mod spell_kv_metrics;
+mod spell_kv_writes;
+mod storage_encryption;
 
 mod config;
 
 pub use app_services::ServiceInfo;
-pub use config::ParticleAppServicesConfig;
+pub use authorization::{AllowAll, CallAuthorizer, CallContext, InternalOnlyServices};
 pub use config::WasmBackendConfig;
+pub use config::{BinaryEgress, ParticleAppServicesConfig};
+pub use engines::EngineReport;
+pub use labels::{format_labels, parse_labels, LabelError, LabelSelector, Labels};
+pub use memory_budget::MemoryBudget;
+pub use ordering::OrderingError;
+pub use run_as::{Grant, RunAs, RunAsError};
+pub use service_health::{
+    HealthChange, RestartPolicy, ServiceHealth, ServiceHealthEvent, HEALTH_LABEL,
+};
+pub use spell_kv_atomic::{INCR_U32, SET_STRING_IF_EQUALS};
+pub use spell_kv_writes::SpellKvWrite;
+pub use storage_encryption::{SealStats, StorageEncryption, StorageEncryptionError, StorageKeys};
 pub use types::peer_scope::PeerScope;
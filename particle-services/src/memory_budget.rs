@@ -0,0 +1,126 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Instant;
+
+/// Limits on loaded Wasm service instances. When a limit is exceeded, least recently used idle
+/// instances are unloaded. Their on-disk state is kept, and they are loaded again on the next call.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryBudget {
+    /// Total memory of loaded instances, in bytes
+    pub max_memory: Option<u64>,
+    /// Number of loaded instances
+    pub max_instances: Option<usize>,
+}
+
+/// Loaded instance as seen by [`MemoryBudget::select_victims`]
+pub struct LoadedInstance<K> {
+    pub key: K,
+    pub memory: u64,
+    pub last_used: Instant,
+}
+
+impl MemoryBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_memory.is_none() && self.max_instances.is_none()
+    }
+
+    fn is_exceeded(&self, memory: u64, instances: usize) -> bool {
+        self.max_memory.map_or(false, |max| memory > max)
+            || self.max_instances.map_or(false, |max| instances > max)
+    }
+
+    /// Keys of the instances to unload, least recently used first, so that the rest fit the budget.
+    /// `keep` is never selected, it is the instance that has just been used.
+    pub fn select_victims<K: PartialEq>(
+        &self,
+        mut loaded: Vec<LoadedInstance<K>>,
+        keep: &K,
+    ) -> Vec<K> {
+        let mut memory: u64 = loaded.iter().map(|i| i.memory).sum();
+        let mut instances = loaded.len();
+        if !self.is_exceeded(memory, instances) {
+            return vec![];
+        }
+
+        loaded.sort_by_key(|i| i.last_used);
+        let mut victims = vec![];
+        for instance in loaded {
+            if !self.is_exceeded(memory, instances) {
+                break;
+            }
+            if &instance.key == keep {
+                continue;
+            }
+            memory -= instance.memory;
+            instances -= 1;
+            victims.push(instance.key);
+        }
+        victims
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{LoadedInstance, MemoryBudget};
+
+    fn loaded(specs: &[(&'static str, u64, u64)]) -> Vec<LoadedInstance<&'static str>> {
+        let now = Instant::now();
+        specs
+            .iter()
+            .map(|(key, memory, age_sec)| LoadedInstance {
+                key: *key,
+                memory: *memory,
+                last_used: now - Duration::from_secs(*age_sec),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unloads_least_recently_used_until_memory_fits() {
+        let budget = MemoryBudget {
+            max_memory: Some(100),
+            max_instances: None,
+        };
+        let instances = loaded(&[("a", 50, 10), ("b", 50, 30), ("c", 50, 20), ("d", 10, 0)]);
+        assert_eq!(budget.select_victims(instances, &"d"), vec!["b", "c"]);
+    }
+
+    #[test]
+    fn never_unloads_the_kept_instance() {
+        let budget = MemoryBudget {
+            max_memory: None,
+            max_instances: Some(1),
+        };
+        let instances = loaded(&[("a", 1, 30), ("b", 1, 0)]);
+        assert_eq!(budget.select_victims(instances, &"a"), vec!["b"]);
+    }
+
+    #[test]
+    fn nothing_to_unload_within_budget() {
+        let budget = MemoryBudget {
+            max_memory: Some(100),
+            max_instances: Some(2),
+        };
+        let instances = loaded(&[("a", 50, 10), ("b", 50, 30)]);
+        assert!(budget.select_victims(instances, &"a").is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Ordered delivery of calls from one sender to one service.
+//!
+//! Particles from a client can overtake each other on retries or when sent over several connections.
+//! When a call carries a sequence number (see [`Args::sequence`](particle_args::Args)), it's executed
+//! only after the call with the previous number from the same sender to the same service.
+//! Calls that arrive too early wait in a small reorder buffer. If a gap isn't filled in time,
+//! the missing calls are considered lost and the waiting calls proceed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use fluence_libp2p::PeerId;
+use parking_lot::Mutex;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+/// How many early calls can wait for their turn in one stream
+const DEFAULT_REORDER_BUFFER_SIZE: usize = 32;
+/// How long an early call waits for the missing ones before they're skipped
+const DEFAULT_REORDER_TIMEOUT: Duration = Duration::from_secs(5);
+/// Streams without activity for that long are forgotten
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// Idle streams are cleaned up only when there are more streams than that
+const STREAMS_GC_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum OrderingError {
+    #[error("call #{seq} was already delivered, the stream expects #{next_seq}")]
+    AlreadyDelivered { seq: u64, next_seq: u64 },
+    #[error("call #{seq} is already waiting for its turn")]
+    Duplicate { seq: u64 },
+    #[error("reorder buffer is full ({capacity} calls), call #{seq} is rejected")]
+    BufferFull { seq: u64, capacity: usize },
+    #[error("particle expired while call #{seq} was waiting for its turn")]
+    Expired { seq: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct StreamKey {
+    sender: PeerId,
+    service_id: String,
+}
+
+struct Stream {
+    next_seq: u64,
+    /// Call `next_seq` or a call that took its place is being executed
+    in_flight: bool,
+    waiting: BTreeMap<u64, oneshot::Sender<()>>,
+    last_activity: Instant,
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Self {
+            next_seq: 0,
+            in_flight: false,
+            waiting: BTreeMap::new(),
+            last_activity: Instant::now(),
+        }
+    }
+}
+
+impl Stream {
+    /// Hand the turn to the call waiting for `next_seq`, if any.
+    /// Calls whose callers are gone are treated as delivered.
+    fn wake_next(&mut self) {
+        while let Some(waiter) = self.waiting.remove(&self.next_seq) {
+            if waiter.send(()).is_ok() {
+                self.in_flight = true;
+                return;
+            }
+            self.next_seq += 1;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct OrderedDelivery {
+    streams: Arc<Mutex<HashMap<StreamKey, Stream>>>,
+    buffer_size: usize,
+    timeout: Duration,
+}
+
+impl Default for OrderedDelivery {
+    fn default() -> Self {
+        Self::new(DEFAULT_REORDER_BUFFER_SIZE, DEFAULT_REORDER_TIMEOUT)
+    }
+}
+
+impl OrderedDelivery {
+    pub fn new(buffer_size: usize, timeout: Duration) -> Self {
+        Self {
+            streams: <_>::default(),
+            buffer_size,
+            timeout,
+        }
+    }
+
+    /// Wait until it's the turn of the call `seq` from `sender` to `service_id`.
+    /// The next call of the stream is let in when the returned [`Turn`] is dropped.
+    pub async fn acquire(
+        &self,
+        sender: PeerId,
+        service_id: String,
+        seq: u64,
+        deadline: Instant,
+    ) -> Result<Turn, OrderingError> {
+        let key = StreamKey { sender, service_id };
+        let mut receiver = {
+            let mut streams = self.streams.lock();
+            if streams.len() > STREAMS_GC_THRESHOLD {
+                streams.retain(|_, stream| {
+                    stream.in_flight
+                        || !stream.waiting.is_empty()
+                        || stream.last_activity.elapsed() < STREAM_IDLE_TIMEOUT
+                });
+            }
+            let stream = streams.entry(key.clone()).or_default();
+            stream.last_activity = Instant::now();
+
+            if seq < stream.next_seq {
+                return Err(OrderingError::AlreadyDelivered {
+                    seq,
+                    next_seq: stream.next_seq,
+                });
+            }
+            if seq == stream.next_seq && !stream.in_flight {
+                stream.in_flight = true;
+                return Ok(self.turn(key, seq));
+            }
+            if stream.waiting.contains_key(&seq) {
+                return Err(OrderingError::Duplicate { seq });
+            }
+            if stream.waiting.len() >= self.buffer_size {
+                return Err(OrderingError::BufferFull {
+                    seq,
+                    capacity: self.buffer_size,
+                });
+            }
+            let (sender, receiver) = oneshot::channel();
+            stream.waiting.insert(seq, sender);
+            receiver
+        };
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                self.give_up(&key, seq);
+                return Err(OrderingError::Expired { seq });
+            }
+            let wait = self.timeout.min(deadline - now);
+            if tokio::time::timeout(wait, &mut receiver).await.is_ok() {
+                return Ok(self.turn(key, seq));
+            }
+
+            // The gap wasn't filled in time, skip the missing calls
+            let mut streams = self.streams.lock();
+            let Some(stream) = streams.get_mut(&key) else {
+                return Err(OrderingError::Expired { seq });
+            };
+            if !stream.in_flight {
+                if let Some(first) = stream.waiting.keys().next() {
+                    tracing::debug!(
+                        "Ordered delivery for {} to {}: skipping calls #{}..#{}",
+                        key.sender,
+                        key.service_id,
+                        stream.next_seq,
+                        first
+                    );
+                    stream.next_seq = *first;
+                }
+                stream.wake_next();
+            }
+        }
+    }
+
+    fn turn(&self, key: StreamKey, seq: u64) -> Turn {
+        Turn {
+            streams: self.streams.clone(),
+            key,
+            seq,
+        }
+    }
+
+    fn give_up(&self, key: &StreamKey, seq: u64) {
+        let mut streams = self.streams.lock();
+        if let Some(stream) = streams.get_mut(key) {
+            // The call isn't waiting anymore, so it has been given the turn already: pass it on
+            if stream.waiting.remove(&seq).is_none() {
+                release(stream, seq);
+            }
+        }
+    }
+}
+
+/// The right to execute a call of an ordered stream. Lets the next call in on drop.
+pub struct Turn {
+    streams: Arc<Mutex<HashMap<StreamKey, Stream>>>,
+    key: StreamKey,
+    seq: u64,
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        let mut streams = self.streams.lock();
+        if let Some(stream) = streams.get_mut(&self.key) {
+            release(stream, self.seq);
+        }
+    }
+}
+
+fn release(stream: &mut Stream, seq: u64) {
+    stream.in_flight = false;
+    stream.next_seq = stream.next_seq.max(seq + 1);
+    stream.last_activity = Instant::now();
+    stream.wake_next();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deadline() -> Instant {
+        Instant::now() + Duration::from_secs(60)
+    }
+
+    #[tokio::test]
+    async fn test_in_order_calls_pass() {
+        let delivery = OrderedDelivery::default();
+        let sender = PeerId::random();
+        for seq in 0..3 {
+            let turn = delivery
+                .acquire(sender, "service".to_string(), seq, deadline())
+                .await
+                .expect("call in order must pass");
+            drop(turn);
+        }
+        let result = delivery
+            .acquire(sender, "service".to_string(), 1, deadline())
+            .await;
+        assert!(matches!(
+            result,
+            Err(OrderingError::AlreadyDelivered {
+                seq: 1,
+                next_seq: 3
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_early_call_waits_for_previous() {
+        let delivery = OrderedDelivery::default();
+        let sender = PeerId::random();
+        let (order_sender, mut order) = tokio::sync::mpsc::unbounded_channel();
+
+        let early = {
+            let delivery = delivery.clone();
+            let order_sender = order_sender.clone();
+            tokio::spawn(async move {
+                let _turn = delivery
+                    .acquire(sender, "service".to_string(), 1, deadline())
+                    .await
+                    .expect("call must pass");
+                order_sender.send(1).unwrap();
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        {
+            let _turn = delivery
+                .acquire(sender, "service".to_string(), 0, deadline())
+                .await
+                .expect("call must pass");
+            order_sender.send(0).unwrap();
+        }
+        early.await.unwrap();
+
+        assert_eq!(order.recv().await, Some(0));
+        assert_eq!(order.recv().await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_gap_is_skipped_after_timeout() {
+        let delivery = OrderedDelivery::new(4, Duration::from_millis(50));
+        let sender = PeerId::random();
+        let _turn = delivery
+            .acquire(sender, "service".to_string(), 2, deadline())
+            .await
+            .expect("call must pass after the gap is skipped");
+        let result = delivery
+            .acquire(sender, "service".to_string(), 0, deadline())
+            .await;
+        assert!(matches!(
+            result,
+            Err(OrderingError::AlreadyDelivered { seq: 0, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_streams_are_independent() {
+        let delivery = OrderedDelivery::new(4, Duration::from_secs(60));
+        let sender = PeerId::random();
+        let _first = delivery
+            .acquire(sender, "first".to_string(), 0, deadline())
+            .await
+            .unwrap();
+        // another service from the same sender isn't blocked by the first one
+        let _second = delivery
+            .acquire(sender, "second".to_string(), 0, deadline())
+            .await
+            .unwrap();
+        let _other_sender = delivery
+            .acquire(PeerId::random(), "first".to_string(), 0, deadline())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buffer_is_bounded() {
+        let delivery = OrderedDelivery::new(1, Duration::from_secs(60));
+        let sender = PeerId::random();
+        let waiting = {
+            let delivery = delivery.clone();
+            tokio::spawn(async move {
+                delivery
+                    .acquire(sender, "service".to_string(), 1, deadline())
+                    .await
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let result = delivery
+            .acquire(sender, "service".to_string(), 2, deadline())
+            .await;
+        assert!(matches!(
+            result,
+            Err(OrderingError::BufferFull { seq: 2, .. })
+        ));
+        waiting.abort();
+    }
+}
@@ -23,6 +23,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::app_services::Service;
 use crate::error::ServiceError;
+use crate::labels::Labels;
+use crate::run_as::RunAs;
 use crate::ServiceError::{SerializePersistedService, WritePersistedService};
 use crate::ServiceType;
 use fluence_libp2p::PeerId;
@@ -46,6 +48,14 @@ pub struct PersistedService {
     )]
     pub owner_id: PeerId,
     pub peer_scope: PeerScope,
+    #[serde(default)]
+    pub labels: Labels,
+    /// Wasm engine the service was created with, absent for services created before engines were tracked
+    #[serde(default)]
+    pub engine: Option<String>,
+    /// Run-as identity of the spell, absent for services and unrestricted spells
+    #[serde(default)]
+    pub run_as: Option<RunAs>,
 }
 
 impl PersistedService {
@@ -57,6 +67,9 @@ impl PersistedService {
             aliases: service.aliases.read().await.clone(),
             owner_id: service.owner_id,
             peer_scope: service.peer_scope,
+            labels: service.labels.clone(),
+            engine: Some(service.engine()),
+            run_as: service.run_as(),
         }
     }
 
@@ -95,6 +108,7 @@ pub async fn remove_persisted_service(
 #[cfg(test)]
 mod tests {
     use crate::persistence::{load_persisted_services, PersistedService};
+    use crate::run_as::{Grant, RunAs};
     use fluence_libp2p::RandomPeerId;
     use types::peer_scope::PeerScope;
 
@@ -109,6 +123,9 @@ mod tests {
             aliases: vec!["alias_1".to_string()],
             owner_id,
             peer_scope: PeerScope::WorkerId(owner_id.into()),
+            labels: <_>::default(),
+            engine: None,
+            run_as: None,
         };
         service_1
             .persist(tmp_dir.path())
@@ -122,6 +139,15 @@ mod tests {
             aliases: vec!["alias_2".to_string()],
             owner_id,
             peer_scope: PeerScope::Host,
+            labels: maplit::btreemap! { "env".to_string() => "prod".to_string() },
+            engine: Some("wasmtime-00000000".to_string()),
+            run_as: Some(RunAs {
+                name: "reader".to_string(),
+                grants: vec![Grant {
+                    service: "kv".to_string(),
+                    functions: vec!["get".to_string()],
+                }],
+            }),
         };
         service_2
             .persist(tmp_dir.path())
@@ -0,0 +1,171 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Run-as identities of spells.
+//!
+//! A spell may be installed with an identity narrower than the worker it runs on: its particles
+//! may only call the services and functions the identity allows. The identity is checked by
+//! the authorization hooks before the configured [`CallAuthorizer`], and every call made under it
+//! is logged with the `audit` target.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::authorization::{CallAuthorizer, CallContext};
+
+const MAX_NAME_LEN: usize = 63;
+const MAX_GRANTS: usize = 64;
+/// Grants of this service allow any service
+const ANY_SERVICE: &str = "*";
+
+/// Services of one kind a run-as identity may call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Grant {
+    /// Service id or alias, or `*` for any service
+    pub service: String,
+    /// Functions that may be called, all of them if empty
+    #[serde(default)]
+    pub functions: Vec<String>,
+}
+
+/// Restricted identity a spell calls services under
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunAs {
+    /// Name of the identity, shown to the authorization hooks and in audit logs
+    pub name: String,
+    pub grants: Vec<Grant>,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RunAsError {
+    #[error(
+        "invalid run-as identity name '{0}': must be 1-{MAX_NAME_LEN} characters of [a-zA-Z0-9._-]"
+    )]
+    InvalidName(String),
+    #[error("invalid run-as grant: service name can't be empty")]
+    EmptyService,
+    #[error("too many run-as grants: {0}, at most {MAX_GRANTS} are allowed")]
+    TooManyGrants(usize),
+}
+
+impl RunAs {
+    pub fn validate(&self) -> Result<(), RunAsError> {
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+        if self.name.is_empty()
+            || self.name.len() > MAX_NAME_LEN
+            || !self.name.chars().all(valid_char)
+        {
+            return Err(RunAsError::InvalidName(self.name.clone()));
+        }
+        if self.grants.len() > MAX_GRANTS {
+            return Err(RunAsError::TooManyGrants(self.grants.len()));
+        }
+        if self.grants.iter().any(|grant| grant.service.is_empty()) {
+            return Err(RunAsError::EmptyService);
+        }
+        Ok(())
+    }
+
+    /// Whether any grant allows calling `function_name` of the service known by `service_id` or `aliases`
+    pub fn allows(&self, service_id: &str, aliases: &[String], function_name: &str) -> bool {
+        self.grants.iter().any(|grant| {
+            let service = grant.service == ANY_SERVICE
+                || grant.service == service_id
+                || aliases.iter().any(|alias| *alias == grant.service);
+            let function =
+                grant.functions.is_empty() || grant.functions.iter().any(|f| f == function_name);
+            service && function
+        })
+    }
+}
+
+impl CallAuthorizer for RunAs {
+    fn authorize(&self, ctx: &CallContext<'_>) -> Result<(), String> {
+        if self.allows(ctx.service_id, ctx.service_aliases, ctx.function_name) {
+            Ok(())
+        } else {
+            Err(format!(
+                "spell identity '{}' isn't granted {}.{}",
+                self.name, ctx.service_id, ctx.function_name
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_libp2p::PeerId;
+    use types::peer_scope::PeerScope;
+
+    use super::*;
+
+    fn run_as(grants: Vec<Grant>) -> RunAs {
+        RunAs {
+            name: "reader".to_string(),
+            grants,
+        }
+    }
+
+    fn grant(service: &str, functions: &[&str]) -> Grant {
+        Grant {
+            service: service.to_string(),
+            functions: functions.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn grants_limit_calls() {
+        let identity = run_as(vec![grant("kv", &["get", "list"]), grant("metrics", &[])]);
+        let worker_id = PeerId::random();
+        let aliases = vec!["kv".to_string()];
+        let ctx = |function_name| CallContext {
+            caller: worker_id,
+            peer_scope: PeerScope::Host,
+            worker_id,
+            service_id: "some-service-id",
+            service_aliases: &aliases,
+            service_owner: worker_id,
+            function_name,
+            identity: Some("reader"),
+        };
+
+        assert!(identity.authorize(&ctx("get")).is_ok());
+        assert!(identity.authorize(&ctx("set")).is_err());
+        assert!(identity.allows("metrics", &[], "anything"));
+        assert!(!identity.allows("other", &[], "get"));
+        assert!(run_as(vec![grant("*", &["get"])]).allows("other", &[], "get"));
+        assert!(!run_as(vec![]).allows("kv", &[], "get"));
+    }
+
+    #[test]
+    fn validates_identities() {
+        assert_eq!(run_as(vec![grant("kv", &[])]).validate(), Ok(()));
+        assert_eq!(
+            run_as(vec![grant("", &[])]).validate(),
+            Err(RunAsError::EmptyService)
+        );
+        let unnamed = RunAs {
+            name: "no spaces".to_string(),
+            grants: vec![],
+        };
+        assert_eq!(
+            unnamed.validate(),
+            Err(RunAsError::InvalidName("no spaces".to_string()))
+        );
+    }
+}
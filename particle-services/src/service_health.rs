@@ -0,0 +1,287 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Health checks of services.
+//!
+//! A service declares its health function with the `health` label, e.g. `health=is_healthy`.
+//! The function is called without arguments on schedule; the service is healthy if the call succeeds
+//! and returns neither `false` nor an object with `success` or `healthy` set to `false`.
+//! A failing instance is restarted at most `max_restarts` times in a row, with the pause between
+//! restarts doubling from `backoff` up to `max_backoff`. Recovery resets the restart budget.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::Value as JValue;
+
+use types::peer_scope::PeerScope;
+
+/// Label with the name of the health function of a service
+pub const HEALTH_LABEL: &str = "health";
+
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    /// Timeout of a single call of the health function
+    pub timeout: Duration,
+    /// Restarts in a row after which a failing service is left as is until it recovers
+    pub max_restarts: u32,
+    /// Pause before the second restart, doubles after each restart
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RestartPolicy {
+    /// Pause after the given number of restarts in a row
+    fn backoff(&self, restarts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(restarts.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ServiceHealth {
+    pub service_id: String,
+    pub function: String,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    /// Restarts since the service was first checked
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// Unix timestamp in seconds of the last check, 0 if the service wasn't checked yet
+    pub last_check: u64,
+    #[serde(skip)]
+    restarts_in_row: u32,
+    #[serde(skip)]
+    next_restart: Option<Instant>,
+}
+
+impl ServiceHealth {
+    pub fn new(service_id: String, function: String) -> Self {
+        Self {
+            service_id,
+            function,
+            healthy: true,
+            consecutive_failures: 0,
+            restarts: 0,
+            last_error: None,
+            last_check: 0,
+            restarts_in_row: 0,
+            next_restart: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthChange {
+    Unhealthy,
+    Healthy,
+    Restarted,
+    /// The service keeps failing after `max_restarts` restarts in a row
+    RestartsExhausted,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServiceHealthEvent {
+    pub service_id: String,
+    pub peer_scope: PeerScope,
+    pub change: HealthChange,
+    pub error: Option<String>,
+    /// Unix timestamp in seconds
+    pub timestamp: u64,
+}
+
+/// Whether the value returned by a health function reports the service as healthy
+pub(crate) fn is_healthy(result: &JValue) -> bool {
+    match result {
+        JValue::Bool(healthy) => *healthy,
+        JValue::Object(fields) => ["success", "healthy"]
+            .iter()
+            .all(|field| fields.get(*field) != Some(&JValue::Bool(false))),
+        _ => true,
+    }
+}
+
+/// Outcome of a check: what has changed and whether the service should be restarted now
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct Verdict {
+    pub(crate) changes: Vec<HealthChange>,
+    pub(crate) restart: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct HealthChecks {
+    states: HashMap<String, ServiceHealth>,
+}
+
+impl HealthChecks {
+    pub(crate) fn get(&self, service_id: &str) -> Option<ServiceHealth> {
+        self.states.get(service_id).cloned()
+    }
+
+    /// Forget services that were removed or don't declare a health function anymore
+    pub(crate) fn retain(&mut self, checked: impl Fn(&str) -> bool) {
+        self.states.retain(|service_id, _| checked(service_id));
+    }
+
+    pub(crate) fn observe(
+        &mut self,
+        service_id: &str,
+        function: &str,
+        result: Result<(), String>,
+        policy: &RestartPolicy,
+        now: Instant,
+        timestamp: u64,
+    ) -> Verdict {
+        let state = self
+            .states
+            .entry(service_id.to_string())
+            .or_insert_with(|| ServiceHealth::new(service_id.to_string(), function.to_string()));
+        state.function = function.to_string();
+        state.last_check = timestamp;
+
+        let mut verdict = Verdict::default();
+        match result {
+            Ok(()) => {
+                if !state.healthy {
+                    verdict.changes.push(HealthChange::Healthy);
+                }
+                state.healthy = true;
+                state.consecutive_failures = 0;
+                state.last_error = None;
+                state.restarts_in_row = 0;
+                state.next_restart = None;
+            }
+            Err(error) => {
+                if state.healthy {
+                    verdict.changes.push(HealthChange::Unhealthy);
+                }
+                state.healthy = false;
+                state.consecutive_failures += 1;
+                state.last_error = Some(error);
+                if state.restarts_in_row < policy.max_restarts {
+                    verdict.restart = state.next_restart.map_or(true, |at| now >= at);
+                } else if state.next_restart.take().is_some() {
+                    // reported once, when the last restart didn't help
+                    verdict.changes.push(HealthChange::RestartsExhausted);
+                }
+            }
+        }
+        verdict
+    }
+
+    /// Record a restart of the service, successful or not
+    pub(crate) fn restarted(
+        &mut self,
+        service_id: &str,
+        error: Option<String>,
+        policy: &RestartPolicy,
+        now: Instant,
+    ) {
+        if let Some(state) = self.states.get_mut(service_id) {
+            state.restarts += 1;
+            state.restarts_in_row += 1;
+            state.next_restart = Some(now + policy.backoff(state.restarts_in_row));
+            if error.is_some() {
+                state.last_error = error;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy() -> RestartPolicy {
+        RestartPolicy {
+            timeout: Duration::from_secs(1),
+            max_restarts: 2,
+            backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(15),
+        }
+    }
+
+    #[test]
+    fn test_is_healthy() {
+        assert!(is_healthy(&json!(true)));
+        assert!(is_healthy(&json!(null)));
+        assert!(is_healthy(&json!({"success": true, "error": ""})));
+        assert!(!is_healthy(&json!(false)));
+        assert!(!is_healthy(
+            &json!({"success": false, "error": "db is gone"})
+        ));
+        assert!(!is_healthy(&json!({"healthy": false})));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = policy();
+        assert_eq!(policy.backoff(1), Duration::from_secs(10));
+        assert_eq!(policy.backoff(2), Duration::from_secs(15));
+        assert_eq!(policy.backoff(30), Duration::from_secs(15));
+    }
+
+    #[test]
+    fn test_bounded_restarts() {
+        let policy = policy();
+        let mut checks = HealthChecks::default();
+        let now = Instant::now();
+        let fail = || Err("boom".to_string());
+
+        let verdict = checks.observe("srv", "health", Ok(()), &policy, now, 1);
+        assert_eq!(verdict, Verdict::default());
+
+        // first failure restarts right away
+        let verdict = checks.observe("srv", "health", fail(), &policy, now, 2);
+        assert_eq!(verdict.changes, vec![HealthChange::Unhealthy]);
+        assert!(verdict.restart);
+        checks.restarted("srv", None, &policy, now);
+
+        // the next restart waits for the backoff
+        let verdict = checks.observe("srv", "health", fail(), &policy, now, 3);
+        assert_eq!(verdict, Verdict::default());
+        let later = now + Duration::from_secs(10);
+        let verdict = checks.observe("srv", "health", fail(), &policy, later, 4);
+        assert!(verdict.restart);
+        checks.restarted("srv", None, &policy, later);
+
+        // restarts are exhausted, reported once
+        let much_later = later + Duration::from_secs(60);
+        let verdict = checks.observe("srv", "health", fail(), &policy, much_later, 5);
+        assert_eq!(verdict.changes, vec![HealthChange::RestartsExhausted]);
+        assert!(!verdict.restart);
+        let verdict = checks.observe("srv", "health", fail(), &policy, much_later, 6);
+        assert_eq!(verdict, Verdict::default());
+
+        let state = checks.get("srv").unwrap();
+        assert!(!state.healthy);
+        assert_eq!(state.consecutive_failures, 5);
+        assert_eq!(state.restarts, 2);
+        assert_eq!(state.last_check, 6);
+
+        // recovery resets the restart budget
+        let verdict = checks.observe("srv", "health", Ok(()), &policy, much_later, 7);
+        assert_eq!(verdict.changes, vec![HealthChange::Healthy]);
+        let verdict = checks.observe("srv", "health", fail(), &policy, much_later, 8);
+        assert!(verdict.restart);
+        assert_eq!(checks.get("srv").unwrap().restarts, 2);
+    }
+}
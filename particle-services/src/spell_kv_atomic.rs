@@ -0,0 +1,213 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Read-modify-write operations on spell KVs. The spell service has no such functions,
+//! so the node makes the read and the write itself while holding the lock of the spell instance:
+//! no other call to the spell, including the writes of its own script, can get in between.
+
+use fluence_app_service::{AppService, AppServiceError, CallParameters};
+use serde_json::{json, Value as JValue};
+
+/// `set_string_if_equals(key, expected, value)` sets the string `key` to `value` if its current value
+/// is `expected`, null `expected` meaning that the key must be absent.
+/// Returns the previous value in the `get_string` format, so the value was set if it equals `expected`.
+pub const SET_STRING_IF_EQUALS: &str = "set_string_if_equals";
+/// `incr_u32(key, delta)` adds `delta` to the u32 `key`, an absent key is considered to be 0.
+/// Returns the new value in the `get_u32` format.
+pub const INCR_U32: &str = "incr_u32";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtomicKvFunction {
+    SetStringIfEquals,
+    IncrU32,
+}
+
+impl AtomicKvFunction {
+    pub fn from_name(function_name: &str) -> Option<Self> {
+        match function_name {
+            SET_STRING_IF_EQUALS => Some(Self::SetStringIfEquals),
+            INCR_U32 => Some(Self::IncrU32),
+            _ => None,
+        }
+    }
+
+    /// Call the function on a locked spell instance.
+    /// Returns the result in the format of the spell service and the KV write that was made, if any.
+    pub async fn call(
+        self,
+        service: &mut AppService,
+        args: Vec<JValue>,
+        params: CallParameters,
+    ) -> Result<(JValue, Option<(String, String)>), AppServiceError> {
+        match self {
+            Self::SetStringIfEquals => set_string_if_equals(service, args, params).await,
+            Self::IncrU32 => incr_u32(service, args, params).await,
+        }
+    }
+}
+
+async fn set_string_if_equals(
+    service: &mut AppService,
+    args: Vec<JValue>,
+    params: CallParameters,
+) -> Result<(JValue, Option<(String, String)>), AppServiceError> {
+    let Some((key, expected, value)) = parse_set_string_if_equals(&args) else {
+        return Ok((
+            failure(json!(""), "expected arguments: key, expected, value"),
+            None,
+        ));
+    };
+
+    let current = service
+        .call_async("get_string", json!([key]), params.clone())
+        .await?;
+    if !is_success(&current) {
+        return Ok((current, None));
+    }
+    let current_value = (current.get("absent") != Some(&JValue::Bool(true)))
+        .then(|| current.get("value").and_then(JValue::as_str))
+        .flatten();
+    if current_value != expected.as_deref() {
+        return Ok((current, None));
+    }
+
+    let set = service
+        .call_async("set_string", json!([key, value]), params)
+        .await?;
+    if !is_success(&set) {
+        return Ok((failure(json!(""), &error_of(&set)), None));
+    }
+    Ok((current, Some((key, value))))
+}
+
+async fn incr_u32(
+    service: &mut AppService,
+    args: Vec<JValue>,
+    params: CallParameters,
+) -> Result<(JValue, Option<(String, String)>), AppServiceError> {
+    let Some((key, delta)) = parse_incr_u32(&args) else {
+        return Ok((failure(json!(0), "expected arguments: key, delta"), None));
+    };
+
+    let current = service
+        .call_async("get_u32", json!([key]), params.clone())
+        .await?;
+    if !is_success(&current) {
+        return Ok((current, None));
+    }
+    let current_value = match current.get("absent") {
+        Some(JValue::Bool(true)) => 0,
+        _ => current.get("value").and_then(JValue::as_u64).unwrap_or(0) as u32,
+    };
+    let Some(value) = current_value.checked_add(delta) else {
+        return Ok((
+            failure(json!(0), &format!("value of {key} overflows u32")),
+            None,
+        ));
+    };
+
+    let set = service
+        .call_async("set_u32", json!([key, value]), params)
+        .await?;
+    if !is_success(&set) {
+        return Ok((failure(json!(0), &error_of(&set)), None));
+    }
+    let result = json!({
+        "value": value,
+        "success": true,
+        "error": "",
+        "absent": false,
+    });
+    Ok((result, Some((key, value.to_string()))))
+}
+
+fn parse_set_string_if_equals(args: &[JValue]) -> Option<(String, Option<String>, String)> {
+    let key = args.first()?.as_str()?.to_string();
+    let expected = match args.get(1)? {
+        JValue::Null => None,
+        JValue::String(expected) => Some(expected.clone()),
+        _ => return None,
+    };
+    let value = args.get(2)?.as_str()?.to_string();
+    Some((key, expected, value))
+}
+
+fn parse_incr_u32(args: &[JValue]) -> Option<(String, u32)> {
+    let key = args.first()?.as_str()?.to_string();
+    let delta = args.get(1)?.as_u64()?.try_into().ok()?;
+    Some((key, delta))
+}
+
+fn is_success(result: &JValue) -> bool {
+    result.get("success") == Some(&JValue::Bool(true))
+}
+
+fn error_of(result: &JValue) -> String {
+    result
+        .get("error")
+        .and_then(JValue::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Failed result in the format of the spell service
+fn failure(default_value: JValue, error: &str) -> JValue {
+    json!({
+        "value": default_value,
+        "success": false,
+        "error": error,
+        "absent": true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse_args() {
+        assert_eq!(
+            AtomicKvFunction::from_name("incr_u32"),
+            Some(AtomicKvFunction::IncrU32)
+        );
+        assert_eq!(AtomicKvFunction::from_name("set_string"), None);
+
+        assert_eq!(
+            parse_set_string_if_equals(&[json!("owner"), json!(null), json!("a")]),
+            Some(("owner".to_string(), None, "a".to_string()))
+        );
+        assert_eq!(
+            parse_set_string_if_equals(&[json!("owner"), json!("a"), json!("b")]),
+            Some(("owner".to_string(), Some("a".to_string()), "b".to_string()))
+        );
+        assert_eq!(
+            parse_set_string_if_equals(&[json!("owner"), json!(1), json!("b")]),
+            None
+        );
+
+        assert_eq!(
+            parse_incr_u32(&[json!("visits"), json!(2)]),
+            Some(("visits".to_string(), 2))
+        );
+        assert_eq!(parse_incr_u32(&[json!("visits"), json!(-1)]), None);
+        assert_eq!(parse_incr_u32(&[json!("visits"), json!(1u64 << 40)]), None);
+    }
+}
@@ -0,0 +1,101 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Spells publish numeric metrics by writing `metric:<name>` keys into their KV.
+//! Writes are observed on the way to the spell service, so the node doesn't need to scan the KV.
+
+use serde_json::Value as JValue;
+
+pub const SPELL_METRIC_PREFIX: &str = "metric:";
+const MAX_METRIC_NAME_LEN: usize = 64;
+
+/// Extracts metric name and value from a `set_u32` or `set_string` call to a spell service
+pub fn spell_kv_metric(function_name: &str, args: &[JValue]) -> Option<(String, f64)> {
+    if function_name != "set_u32" && function_name != "set_string" {
+        return None;
+    }
+
+    let name = args.first()?.as_str()?.strip_prefix(SPELL_METRIC_PREFIX)?;
+    if name.is_empty() || name.len() > MAX_METRIC_NAME_LEN {
+        return None;
+    }
+
+    let value = match args.get(1)? {
+        JValue::Number(n) => n.as_f64()?,
+        JValue::String(s) => s.trim().parse::<f64>().ok()?,
+        _ => return None,
+    };
+
+    if value.is_finite() {
+        Some((name.to_string(), value))
+    } else {
+        None
+    }
+}
+
+/// Spell KV functions report errors in the result instead of failing the call
+pub fn is_kv_write_success(result: &JValue) -> bool {
+    result.get("success") == Some(&JValue::Bool(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{is_kv_write_success, spell_kv_metric};
+
+    #[test]
+    fn parse_spell_kv_metric() {
+        assert_eq!(
+            spell_kv_metric("set_u32", &[json!("metric:processed"), json!(10)]),
+            Some(("processed".to_string(), 10.0))
+        );
+        assert_eq!(
+            spell_kv_metric("set_string", &[json!("metric:lag"), json!(" 0.5 ")]),
+            Some(("lag".to_string(), 0.5))
+        );
+
+        assert_eq!(
+            spell_kv_metric("set_string", &[json!("processed"), json!("1")]),
+            None
+        );
+        assert_eq!(
+            spell_kv_metric("set_string", &[json!("metric:"), json!("1")]),
+            None
+        );
+        assert_eq!(
+            spell_kv_metric("set_string", &[json!("metric:x"), json!("abc")]),
+            None
+        );
+        assert_eq!(
+            spell_kv_metric("set_string", &[json!("metric:x"), json!("NaN")]),
+            None
+        );
+        assert_eq!(spell_kv_metric("get_string", &[json!("metric:x")]), None);
+    }
+
+    #[test]
+    fn kv_write_result() {
+        assert!(is_kv_write_success(&json!({"success": true, "error": ""})));
+        assert!(!is_kv_write_success(
+            &json!({"success": false, "error": "oops"})
+        ));
+        assert!(!is_kv_write_success(&json!(null)));
+    }
+}
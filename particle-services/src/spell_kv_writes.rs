@@ -0,0 +1,103 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Writes to spell KVs are reported to the spell event bus,
+//! so spells can be triggered by changes of the data of other spells.
+
+use serde_json::Value as JValue;
+use types::peer_scope::PeerScope;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpellKvWrite {
+    /// Scope of the spell that made the write
+    pub peer_scope: PeerScope,
+    pub spell_id: String,
+    pub key: String,
+    pub value: String,
+}
+
+/// Extracts written keys and values from a `set_string`, `set_u32` or `set_json_fields` call to a spell service.
+/// Non-string values are represented by their JSON.
+pub fn spell_kv_writes(function_name: &str, args: &[JValue]) -> Vec<(String, String)> {
+    let to_string = |value: &JValue| match value {
+        JValue::String(s) => s.clone(),
+        value => value.to_string(),
+    };
+
+    match function_name {
+        "set_string" | "set_u32" => {
+            let write: Option<_> = try {
+                let key = args.first()?.as_str()?;
+                (key.to_string(), to_string(args.get(1)?))
+            };
+            write.into_iter().collect()
+        }
+        "set_json_fields" => args
+            .first()
+            .and_then(|fields| fields.as_str())
+            .and_then(|fields| serde_json::from_str::<JValue>(fields).ok())
+            .and_then(|fields| match fields {
+                JValue::Object(fields) => Some(fields),
+                _ => None,
+            })
+            .map(|fields| {
+                fields
+                    .iter()
+                    .map(|(k, v)| (k.clone(), to_string(v)))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::spell_kv_writes;
+
+    #[test]
+    fn parse_spell_kv_writes() {
+        assert_eq!(
+            spell_kv_writes("set_string", &[json!("price"), json!("10")]),
+            vec![("price".to_string(), "10".to_string())]
+        );
+        assert_eq!(
+            spell_kv_writes("set_u32", &[json!("counter"), json!(3)]),
+            vec![("counter".to_string(), "3".to_string())]
+        );
+
+        let mut fields = spell_kv_writes(
+            "set_json_fields",
+            &[json!(json!({"a": "x", "b": [1, 2]}).to_string())],
+        );
+        fields.sort();
+        assert_eq!(
+            fields,
+            vec![
+                ("a".to_string(), "x".to_string()),
+                ("b".to_string(), "[1,2]".to_string())
+            ]
+        );
+
+        assert!(spell_kv_writes("set_json_fields", &[json!("[1]")]).is_empty());
+        assert!(spell_kv_writes("get_string", &[json!("price")]).is_empty());
+        assert!(spell_kv_writes("set_string", &[json!("price")]).is_empty());
+    }
+}
@@ -0,0 +1,611 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+//! Encryption at rest of service persistent dirs, spell KVs included.
+//!
+//! Persistent dirs only ever hold sealed files. Services can't see encrypted files, so a loaded
+//! instance works on a decrypted copy of its persistent dir, kept outside of it, ideally on tmpfs.
+//! Files of the copy changed by a call are sealed back into the persistent dir once the call
+//! finishes, and the copy is removed when the instance is unloaded or the node stops.
+//! Every file is sealed separately with AES-256-GCM as
+//! `MAGIC | key id | nonce | ciphertext`, where the key id tells which key sealed it.
+//! The path of the file relative to the persistent dir is authenticated along with it,
+//! so sealed files can't be swapped or moved around.
+//! Files sealed with a previous key are decrypted with it and sealed again with the current one,
+//! that's how keys are rotated.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use thiserror::Error;
+
+const MAGIC: &[u8] = b"NOXENC01";
+const KEY_ID_LEN: usize = 8;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + KEY_ID_LEN + NONCE_LEN;
+/// Modification times tick coarser than the clock, so a write right after a sync may keep
+/// the time the file had when it was synced. Files modified that recently are sealed once more
+/// by the next sync.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Error)]
+pub enum StorageEncryptionError {
+    #[error("Invalid storage data key: {0}")]
+    InvalidKey(String),
+    #[error("Error accessing {path:?}: {err}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+    #[error("File {path:?} is sealed with an unknown key {key_id}")]
+    UnknownKey { path: PathBuf, key_id: String },
+    #[error("Failed to decrypt {path:?}, the file is corrupted or was moved")]
+    Corrupted { path: PathBuf },
+    #[error("Failed to encrypt {path:?}")]
+    Encrypt { path: PathBuf },
+}
+
+struct StorageKey {
+    id: [u8; KEY_ID_LEN],
+    cipher: Aes256Gcm,
+}
+
+impl StorageKey {
+    fn from_hex(key: &str) -> Result<Self, StorageEncryptionError> {
+        let key = hex::decode(key.trim().trim_start_matches("0x"))
+            .map_err(|err| StorageEncryptionError::InvalidKey(err.to_string()))?;
+        if key.len() != 32 {
+            return Err(StorageEncryptionError::InvalidKey(format!(
+                "expected 32 bytes, got {}",
+                key.len()
+            )));
+        }
+        let mut id = [0u8; KEY_ID_LEN];
+        id.copy_from_slice(&blake3::hash(&key).as_bytes()[..KEY_ID_LEN]);
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        Ok(Self { id, cipher })
+    }
+}
+
+/// What sealing or unsealing of a dir did
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SealStats {
+    /// Files encrypted or decrypted
+    pub processed: usize,
+    /// Files that were already in the requested state
+    pub skipped: usize,
+}
+
+/// Storage encryption as configured for services
+#[derive(Debug, Clone)]
+pub struct StorageEncryption {
+    pub keys: Arc<StorageKeys>,
+    /// Where loaded instances get decrypted copies of their persistent dirs, one per service
+    pub plaintext_dir: PathBuf,
+}
+
+/// Size and modification time of a file of a decrypted copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: SystemTime,
+}
+
+/// Files of a decrypted copy as they were when sealed last time, by path relative to the copy.
+/// Files that still have to be sealed with the current key are kept as `None`.
+#[derive(Debug, Default)]
+pub struct CopySnapshot(HashMap<PathBuf, Option<FileStamp>>);
+
+/// Node-level data key and the keys it replaced
+pub struct StorageKeys {
+    current: StorageKey,
+    previous: Vec<StorageKey>,
+}
+
+impl std::fmt::Debug for StorageKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StorageKeys")
+            .field("current", &hex::encode(self.current.id))
+            .field("previous", &self.previous.len())
+            .finish()
+    }
+}
+
+impl StorageKeys {
+    /// Keys are hex-encoded 32 bytes
+    pub fn from_hex(current: &str, previous: &[String]) -> Result<Self, StorageEncryptionError> {
+        Ok(Self {
+            current: StorageKey::from_hex(current)?,
+            previous: previous
+                .iter()
+                .map(|key| StorageKey::from_hex(key))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn key(&self, id: &[u8]) -> Option<&StorageKey> {
+        std::iter::once(&self.current)
+            .chain(self.previous.iter())
+            .find(|key| key.id == id)
+    }
+
+    pub fn is_sealed(data: &[u8]) -> bool {
+        data.len() >= HEADER_LEN && data.starts_with(MAGIC)
+    }
+
+    fn is_sealed_with_current(&self, data: &[u8]) -> bool {
+        Self::is_sealed(data) && data[MAGIC.len()..MAGIC.len() + KEY_ID_LEN] == self.current.id
+    }
+
+    /// Encrypts data of the file at `path`, which is `relative` to the persistent dir,
+    /// with the current key
+    fn seal(
+        &self,
+        path: &Path,
+        relative: &Path,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StorageEncryptionError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = aad(relative);
+        let payload = Payload {
+            msg: data,
+            aad: &aad,
+        };
+        let ciphertext = self.current.cipher.encrypt(&nonce, payload).map_err(|_| {
+            StorageEncryptionError::Encrypt {
+                path: path.to_path_buf(),
+            }
+        })?;
+
+        let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&self.current.id);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Decrypts sealed data with the key it was sealed with
+    fn unseal(
+        &self,
+        path: &Path,
+        relative: &Path,
+        data: &[u8],
+    ) -> Result<Vec<u8>, StorageEncryptionError> {
+        let key_id = &data[MAGIC.len()..MAGIC.len() + KEY_ID_LEN];
+        let nonce = Nonce::from_slice(&data[MAGIC.len() + KEY_ID_LEN..HEADER_LEN]);
+        let key = self
+            .key(key_id)
+            .ok_or_else(|| StorageEncryptionError::UnknownKey {
+                path: path.to_path_buf(),
+                key_id: hex::encode(key_id),
+            })?;
+        let aad = aad(relative);
+        let payload = Payload {
+            msg: &data[HEADER_LEN..],
+            aad: &aad,
+        };
+        key.cipher
+            .decrypt(nonce, payload)
+            .map_err(|_| StorageEncryptionError::Corrupted {
+                path: path.to_path_buf(),
+            })
+    }
+
+    /// Encrypts plaintext files of the dir in place and re-encrypts files sealed with previous keys
+    pub fn seal_dir(&self, dir: &Path) -> Result<SealStats, StorageEncryptionError> {
+        let mut stats = SealStats::default();
+        for relative in sealed_files(dir)? {
+            let path = dir.join(&relative);
+            let data = read(&path)?;
+            let plaintext = if Self::is_sealed(&data) {
+                if self.is_sealed_with_current(&data) {
+                    stats.skipped += 1;
+                    continue;
+                }
+                self.unseal(&path, &relative, &data)?
+            } else {
+                data
+            };
+            write(&path, &self.seal(&path, &relative, &plaintext)?)?;
+            stats.processed += 1;
+        }
+        Ok(stats)
+    }
+
+    /// Decrypts sealed files of the dir in place, plaintext files are left as is
+    pub fn unseal_dir(&self, dir: &Path) -> Result<SealStats, StorageEncryptionError> {
+        let mut stats = SealStats::default();
+        for relative in sealed_files(dir)? {
+            let path = dir.join(&relative);
+            let data = read(&path)?;
+            if !Self::is_sealed(&data) {
+                stats.skipped += 1;
+                continue;
+            }
+            write(&path, &self.unseal(&path, &relative, &data)?)?;
+            stats.processed += 1;
+        }
+        Ok(stats)
+    }
+
+    /// Decrypts the sealed dir into a fresh copy, replacing whatever the copy had.
+    /// Plaintext files and files sealed with previous keys are copied too,
+    /// they are sealed with the current key by the first [`Self::sync_copy`].
+    pub fn unseal_copy(
+        &self,
+        sealed_dir: &Path,
+        copy_dir: &Path,
+    ) -> Result<CopySnapshot, StorageEncryptionError> {
+        remove_copy(copy_dir)?;
+        fs::create_dir_all(copy_dir).map_err(io_error(copy_dir))?;
+
+        let mut snapshot = CopySnapshot::default();
+        for relative in sealed_files(sealed_dir)? {
+            let path = sealed_dir.join(&relative);
+            let data = read(&path)?;
+            let current = self.is_sealed_with_current(&data);
+            let plaintext = if Self::is_sealed(&data) {
+                self.unseal(&path, &relative, &data)?
+            } else {
+                data
+            };
+
+            let target = copy_dir.join(&relative);
+            create_parent(&target)?;
+            fs::write(&target, plaintext).map_err(io_error(&target))?;
+            // any write by the service moves the time forward
+            fs::File::options()
+                .write(true)
+                .open(&target)
+                .and_then(|file| file.set_modified(SystemTime::UNIX_EPOCH))
+                .map_err(io_error(&target))?;
+            let stamp = current.then(|| stamp(&target)).transpose()?;
+            snapshot.0.insert(relative, stamp);
+        }
+        Ok(snapshot)
+    }
+
+    /// Seals files of the copy changed since the snapshot into the sealed dir
+    /// and removes sealed files whose copies were deleted
+    pub fn sync_copy(
+        &self,
+        copy_dir: &Path,
+        sealed_dir: &Path,
+        snapshot: &mut CopySnapshot,
+    ) -> Result<SealStats, StorageEncryptionError> {
+        let mut stats = SealStats::default();
+        let mut present = HashSet::new();
+        let settled_before = SystemTime::now() - SETTLE_TIME;
+        for relative in files(copy_dir)? {
+            let path = copy_dir.join(&relative);
+            // taken before reading, so a write in between is sealed again next time
+            let stamp = stamp(&path)?;
+            present.insert(relative.clone());
+            if snapshot.0.get(&relative) == Some(&Some(stamp)) {
+                stats.skipped += 1;
+                continue;
+            }
+
+            let target = sealed_dir.join(&relative);
+            create_parent(&target)?;
+            write(&target, &self.seal(&target, &relative, &read(&path)?)?)?;
+            let settled = stamp.modified < settled_before;
+            snapshot.0.insert(relative, settled.then_some(stamp));
+            stats.processed += 1;
+        }
+
+        let deleted: Vec<PathBuf> = snapshot
+            .0
+            .keys()
+            .filter(|relative| !present.contains(*relative))
+            .cloned()
+            .collect();
+        for relative in deleted {
+            let target = sealed_dir.join(&relative);
+            match fs::remove_file(&target) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(io_error(&target)(err));
+                }
+                _ => {}
+            }
+            snapshot.0.remove(&relative);
+        }
+        Ok(stats)
+    }
+}
+
+/// Removes a decrypted copy, if there is one
+pub fn remove_copy(copy_dir: &Path) -> Result<(), StorageEncryptionError> {
+    match fs::remove_dir_all(copy_dir) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(io_error(copy_dir)(err)),
+        _ => Ok(()),
+    }
+}
+
+/// Relative path with `/` separators on every platform, so sealed files stay portable
+fn aad(relative: &Path) -> Vec<u8> {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+        .into_bytes()
+}
+
+fn io_error(path: &Path) -> impl FnOnce(std::io::Error) -> StorageEncryptionError + '_ {
+    move |err| StorageEncryptionError::Io {
+        path: path.to_path_buf(),
+        err,
+    }
+}
+
+fn stamp(path: &Path) -> Result<FileStamp, StorageEncryptionError> {
+    let metadata = fs::metadata(path).map_err(io_error(path))?;
+    Ok(FileStamp {
+        len: metadata.len(),
+        modified: metadata.modified().map_err(io_error(path))?,
+    })
+}
+
+/// Regular files of the dir and its subdirs relative to the dir, symlinks aren't followed
+fn files(dir: &Path) -> Result<Vec<PathBuf>, StorageEncryptionError> {
+    let mut files = vec![];
+    if !dir.exists() {
+        return Ok(files);
+    }
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current).map_err(io_error(&current))? {
+            let entry = entry.map_err(io_error(&current))?;
+            let path = entry.path();
+            let file_type = entry.file_type().map_err(io_error(&path))?;
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() {
+                let relative = path
+                    .strip_prefix(dir)
+                    .expect("files are listed inside the dir")
+                    .to_path_buf();
+                files.push(relative);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Files of a sealed dir, without leftovers of interrupted writes
+fn sealed_files(dir: &Path) -> Result<Vec<PathBuf>, StorageEncryptionError> {
+    let mut files = files(dir)?;
+    files.retain(|path| !is_tmp(path));
+    Ok(files)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, StorageEncryptionError> {
+    fs::read(path).map_err(io_error(path))
+}
+
+fn create_parent(path: &Path) -> Result<(), StorageEncryptionError> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).map_err(io_error(parent)),
+        None => Ok(()),
+    }
+}
+
+const TMP_EXTENSION: &str = "sealing";
+
+/// Leftover of a write interrupted by a crash
+fn is_tmp(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == TMP_EXTENSION)
+}
+
+/// Replaces the file atomically, so a crash leaves either the old or the new content
+fn write(path: &Path, data: &[u8]) -> Result<(), StorageEncryptionError> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".");
+    tmp.push(TMP_EXTENSION);
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, data).map_err(io_error(&tmp))?;
+    fs::rename(&tmp, path).map_err(io_error(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{SealStats, StorageEncryptionError, StorageKeys};
+
+    const KEY_1: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+    const KEY_2: &str = "0202020202020202020202020202020202020202020202020202020202020202";
+
+    #[test]
+    fn seal_and_unseal_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = dir.path().join("module").join("spell.sqlite");
+        fs::create_dir_all(db.parent().unwrap()).unwrap();
+        fs::write(&db, b"kv data").unwrap();
+
+        let keys = StorageKeys::from_hex(KEY_1, &[]).unwrap();
+        let stats = keys.seal_dir(dir.path()).unwrap();
+        assert_eq!(
+            stats,
+            SealStats {
+                processed: 1,
+                skipped: 0
+            }
+        );
+        let sealed = fs::read(&db).unwrap();
+        assert!(StorageKeys::is_sealed(&sealed));
+        assert!(!sealed.windows(7).any(|w| w == b"kv data"));
+
+        // sealing twice doesn't encrypt twice
+        let stats = keys.seal_dir(dir.path()).unwrap();
+        assert_eq!(
+            stats,
+            SealStats {
+                processed: 0,
+                skipped: 1
+            }
+        );
+
+        keys.unseal_dir(dir.path()).unwrap();
+        assert_eq!(fs::read(&db).unwrap(), b"kv data");
+    }
+
+    #[test]
+    fn rotate_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data");
+        fs::write(&file, b"secret").unwrap();
+
+        let old = StorageKeys::from_hex(KEY_1, &[]).unwrap();
+        old.seal_dir(dir.path()).unwrap();
+
+        let new = StorageKeys::from_hex(KEY_2, &[]).unwrap();
+        assert!(matches!(
+            new.unseal_dir(dir.path()),
+            Err(StorageEncryptionError::UnknownKey { .. })
+        ));
+
+        let rotated = StorageKeys::from_hex(KEY_2, &[KEY_1.to_string()]).unwrap();
+        let stats = rotated.seal_dir(dir.path()).unwrap();
+        assert_eq!(stats.processed, 1);
+
+        // the old key isn't needed anymore
+        new.unseal_dir(dir.path()).unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn corrupted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data");
+        fs::write(&file, b"secret").unwrap();
+
+        let keys = StorageKeys::from_hex(KEY_1, &[]).unwrap();
+        keys.seal_dir(dir.path()).unwrap();
+        let mut sealed = fs::read(&file).unwrap();
+        *sealed.last_mut().unwrap() ^= 1;
+        fs::write(&file, sealed).unwrap();
+
+        assert!(matches!(
+            keys.unseal_dir(dir.path()),
+            Err(StorageEncryptionError::Corrupted { .. })
+        ));
+    }
+
+    #[test]
+    fn moved_file_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a"), b"secret a").unwrap();
+        fs::write(dir.path().join("b"), b"secret b").unwrap();
+
+        let keys = StorageKeys::from_hex(KEY_1, &[]).unwrap();
+        keys.seal_dir(dir.path()).unwrap();
+        let a = fs::read(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("b"), a).unwrap();
+
+        assert!(matches!(
+            keys.unseal_dir(dir.path()),
+            Err(StorageEncryptionError::Corrupted { path }) if path.ends_with("b")
+        ));
+    }
+
+    #[test]
+    fn sync_copy() {
+        let sealed = tempfile::tempdir().unwrap();
+        let copies = tempfile::tempdir().unwrap();
+        let copy = copies.path().join("service");
+        let db = Path::new("module").join("db");
+        fs::create_dir_all(sealed.path().join("module")).unwrap();
+        fs::write(sealed.path().join(&db), b"v1").unwrap();
+
+        let keys = StorageKeys::from_hex(KEY_1, &[]).unwrap();
+        keys.seal_dir(sealed.path()).unwrap();
+        let mut snapshot = keys.unseal_copy(sealed.path(), &copy).unwrap();
+        assert_eq!(fs::read(copy.join(&db)).unwrap(), b"v1");
+
+        fs::write(copy.join(&db), b"v2").unwrap();
+        fs::write(copy.join("module").join("new"), b"new").unwrap();
+        // files modified right before a sync are sealed again by the next one
+        std::thread::sleep(super::SETTLE_TIME);
+        let stats = keys.sync_copy(&copy, sealed.path(), &mut snapshot).unwrap();
+        assert_eq!(stats.processed, 2);
+        for file in [db.as_path(), Path::new("module/new")] {
+            let data = fs::read(sealed.path().join(file)).unwrap();
+            assert!(StorageKeys::is_sealed(&data));
+        }
+
+        // nothing changed since the last sync
+        let stats = keys.sync_copy(&copy, sealed.path(), &mut snapshot).unwrap();
+        assert_eq!(
+            stats,
+            SealStats {
+                processed: 0,
+                skipped: 2
+            }
+        );
+
+        fs::remove_file(copy.join("module").join("new")).unwrap();
+        keys.sync_copy(&copy, sealed.path(), &mut snapshot).unwrap();
+        assert!(!sealed.path().join("module").join("new").exists());
+
+        keys.unseal_copy(sealed.path(), &copy).unwrap();
+        assert_eq!(fs::read(copy.join(&db)).unwrap(), b"v2");
+        assert!(!copy.join("module").join("new").exists());
+    }
+
+    #[test]
+    fn sync_seals_plaintext_and_rotated_files() {
+        let sealed = tempfile::tempdir().unwrap();
+        let copy = tempfile::tempdir().unwrap();
+        fs::write(sealed.path().join("old"), b"old key").unwrap();
+        StorageKeys::from_hex(KEY_1, &[])
+            .unwrap()
+            .seal_dir(sealed.path())
+            .unwrap();
+        fs::write(sealed.path().join("plain"), b"plaintext").unwrap();
+
+        let keys = StorageKeys::from_hex(KEY_2, &[KEY_1.to_string()]).unwrap();
+        let mut snapshot = keys.unseal_copy(sealed.path(), copy.path()).unwrap();
+        let stats = keys
+            .sync_copy(copy.path(), sealed.path(), &mut snapshot)
+            .unwrap();
+        assert_eq!(stats.processed, 2);
+
+        // the old key isn't needed anymore
+        let new = StorageKeys::from_hex(KEY_2, &[]).unwrap();
+        new.unseal_dir(sealed.path()).unwrap();
+        assert_eq!(fs::read(sealed.path().join("old")).unwrap(), b"old key");
+        assert_eq!(fs::read(sealed.path().join("plain")).unwrap(), b"plaintext");
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert!(StorageKeys::from_hex("abcd", &[]).is_err());
+        assert!(StorageKeys::from_hex(KEY_1, &["zz".to_string()]).is_err());
+        assert!(StorageKeys::from_hex(&format!("0x{KEY_1}"), &[]).is_ok());
+    }
+}
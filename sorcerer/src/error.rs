@@ -28,6 +28,17 @@ pub enum SorcererError {
         err: ParticleError,
         spell_id: String,
     },
+    #[error("Failed to sign particle for scheduled job {job_id} : {err}")]
+    JobSigningFailed {
+        #[source]
+        err: ParticleError,
+        job_id: String,
+    },
+    #[error("Keypair for scheduled job {job_id}:{peer_scope:?} is missing")]
+    JobKeypairMissing {
+        job_id: String,
+        peer_scope: PeerScope,
+    },
     #[error("Keypair for spell {spell_id}:{peer_scope:?} is missing")]
     ScopeKeypairMissing {
         spell_id: String,
@@ -19,15 +19,29 @@
 
 #![feature(try_blocks)]
 #![feature(extend_one)]
+pub use quarantine::{QuarantineStatus, SpellQuarantine};
+pub use receipts::{ReceiptLog, SpellReceipt};
+pub use scheduler::{JobScheduler, ScheduledJob};
+pub use sink_routes::{SinkRoute, SINK_ROUTES_KEY};
 pub use sorcerer::Sorcerer;
 pub use spell_builtins::{get_spell_info, install_spell, remove_spell, SpellInfo};
+pub use trigger_presets::TriggerPresets;
 
 #[macro_use]
 extern crate fstrings;
 
 mod error;
+mod quarantine;
+mod receipts;
+mod sched_builtins;
+mod scheduler;
 mod script_executor;
+mod sink_routes;
 mod sorcerer;
 mod spell_builtins;
+mod spell_library;
+mod spell_migration;
+mod stored_triggers;
+mod trigger_presets;
 mod utils;
 mod worker_builins;
@@ -0,0 +1,177 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use peer_metrics::SpellMetrics;
+use serde::Serialize;
+
+#[derive(Default)]
+struct Failures {
+    /// Failed runs in a row, the current one isn't counted until the next run starts
+    in_a_row: u32,
+    /// Whether the current run reported an error
+    current_failed: bool,
+}
+
+/// State of a spell returned by `spell.get_status`
+#[derive(Debug, Serialize, PartialEq)]
+pub struct QuarantineStatus {
+    pub quarantined: bool,
+    pub failures_in_a_row: u32,
+    /// How many times the period of the spell is stretched, before the cap
+    pub backoff_factor: u64,
+}
+
+/// Counts failed runs of the spells. A run fails if it reports an error, and the first run
+/// without errors resets the count. Past `threshold` failures in a row the spell is quarantined,
+/// the level of the quarantine grows by one with each failure.
+#[derive(Clone)]
+pub struct SpellQuarantine {
+    threshold: u32,
+    spells: Arc<Mutex<HashMap<String, Failures>>>,
+    metrics: Option<SpellMetrics>,
+}
+
+impl SpellQuarantine {
+    pub fn new(threshold: u32, metrics: Option<SpellMetrics>) -> Self {
+        Self {
+            threshold,
+            spells: <_>::default(),
+            metrics,
+        }
+    }
+
+    fn level(&self, in_a_row: u32) -> u32 {
+        if self.threshold == 0 || in_a_row < self.threshold {
+            0
+        } else {
+            in_a_row - self.threshold + 1
+        }
+    }
+
+    /// The current run of the spell reported an error
+    pub fn on_failure(&self, spell_id: &str) {
+        self.spells
+            .lock()
+            .entry(spell_id.to_string())
+            .or_default()
+            .current_failed = true;
+    }
+
+    /// A new run of the spell starts, so the previous one is over.
+    /// Returns the new quarantine level if it has changed.
+    pub fn on_run(&self, spell_id: &str) -> Option<u32> {
+        let mut spells = self.spells.lock();
+        let Some(failures) = spells.get_mut(spell_id) else {
+            // The first run since the node started, there's nothing to count yet
+            spells.insert(spell_id.to_string(), Failures::default());
+            return None;
+        };
+        let before = self.level(failures.in_a_row);
+        failures.in_a_row = if failures.current_failed {
+            failures.in_a_row.saturating_add(1)
+        } else {
+            0
+        };
+        failures.current_failed = false;
+        let after = self.level(failures.in_a_row);
+        if before == after {
+            return None;
+        }
+        if let Some(m) = &self.metrics {
+            match (before, after) {
+                (0, _) => m.observe_spell_quarantined(),
+                (_, 0) => m.observe_spell_released(),
+                _ => {}
+            }
+        }
+        Some(after)
+    }
+
+    pub fn status(&self, spell_id: &str) -> QuarantineStatus {
+        let in_a_row = self
+            .spells
+            .lock()
+            .get(spell_id)
+            .map_or(0, |failures| failures.in_a_row);
+        let level = self.level(in_a_row);
+        QuarantineStatus {
+            quarantined: level > 0,
+            failures_in_a_row: in_a_row,
+            backoff_factor: 1u64 << level.min(63),
+        }
+    }
+
+    pub fn remove(&self, spell_id: &str) {
+        let failures = self.spells.lock().remove(spell_id);
+        if let (Some(failures), Some(m)) = (failures, &self.metrics) {
+            if self.level(failures.in_a_row) > 0 {
+                m.observe_spell_released();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(quarantine: &SpellQuarantine, failed: bool) -> Option<u32> {
+        if failed {
+            quarantine.on_failure("spell");
+        }
+        quarantine.on_run("spell")
+    }
+
+    #[test]
+    fn quarantine_after_failures_in_a_row() {
+        let quarantine = SpellQuarantine::new(2, None);
+        assert_eq!(quarantine.on_run("spell"), None);
+
+        assert_eq!(run(&quarantine, true), None);
+        assert_eq!(run(&quarantine, true), Some(1));
+        assert_eq!(run(&quarantine, true), Some(2));
+        assert_eq!(
+            quarantine.status("spell"),
+            QuarantineStatus {
+                quarantined: true,
+                failures_in_a_row: 3,
+                backoff_factor: 4,
+            }
+        );
+
+        // the first successful run resets the count
+        assert_eq!(run(&quarantine, false), Some(0));
+        assert!(!quarantine.status("spell").quarantined);
+        assert_eq!(run(&quarantine, true), None);
+    }
+
+    #[test]
+    fn disabled_quarantine() {
+        let quarantine = SpellQuarantine::new(0, None);
+        quarantine.on_run("spell");
+        for _ in 0..10 {
+            assert_eq!(run(&quarantine, true), None);
+        }
+        assert_eq!(quarantine.status("spell").backoff_factor, 1);
+    }
+}
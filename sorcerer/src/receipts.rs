@@ -0,0 +1,191 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use fluence_keypair::{KeyPair, PublicKey, Signature};
+use fluence_libp2p::PeerId;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Signed statement that the node ran a spell script in response to a trigger
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct SpellReceipt {
+    pub spell_id: String,
+    pub particle_id: String,
+    /// Trigger event in the same JSON form the spell receives it
+    pub trigger: String,
+    /// blake3 of the executed script, hex
+    pub script_hash: String,
+    /// blake3 of the execution outcome, hex. Outcome is `ok` when the particle was accepted
+    /// by the interpreter, or the error message otherwise
+    pub result_hash: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp: u64,
+    /// Peer id of the host or worker that ran the spell
+    pub signer: String,
+    pub signature: Vec<u8>,
+}
+
+impl SpellReceipt {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        spell_id: String,
+        particle_id: String,
+        trigger: String,
+        script: &str,
+        outcome: &str,
+        timestamp: u64,
+        keypair: &KeyPair,
+    ) -> Self {
+        let mut receipt = Self {
+            spell_id,
+            particle_id,
+            trigger,
+            script_hash: blake3::hash(script.as_bytes()).to_hex().to_string(),
+            result_hash: blake3::hash(outcome.as_bytes()).to_hex().to_string(),
+            timestamp,
+            signer: keypair.get_peer_id().to_base58(),
+            signature: vec![],
+        };
+        receipt.signature = keypair
+            .sign(&receipt.signed_bytes())
+            .map(|s| s.to_vec().to_vec())
+            .unwrap_or_default();
+        receipt
+    }
+
+    /// Bytes covered by the signature, concatenation of:
+    /// - spell_id, particle_id and trigger as bytes, each followed by a zero byte
+    /// - script_hash and result_hash as hex strings
+    /// - timestamp u64 as little-endian bytes
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        for field in [&self.spell_id, &self.particle_id, &self.trigger] {
+            bytes.extend(field.as_bytes());
+            bytes.push(0);
+        }
+        bytes.extend(self.script_hash.as_bytes());
+        bytes.extend(self.result_hash.as_bytes());
+        bytes.extend(self.timestamp.to_le_bytes());
+        bytes
+    }
+
+    pub fn verify(&self) -> bool {
+        let Ok(peer_id) = self.signer.parse::<PeerId>() else {
+            return false;
+        };
+        let Ok(pk) = PublicKey::try_from(peer_id) else {
+            return false;
+        };
+        let signature = Signature::from_bytes(pk.get_key_format(), self.signature.clone());
+        pk.verify(&self.signed_bytes(), &signature).is_ok()
+    }
+}
+
+/// Last `capacity` receipts, the oldest are dropped first
+#[derive(Clone)]
+pub struct ReceiptLog {
+    receipts: Arc<Mutex<VecDeque<SpellReceipt>>>,
+    capacity: usize,
+}
+
+impl ReceiptLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            receipts: <_>::default(),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, receipt: SpellReceipt) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut receipts = self.receipts.lock();
+        if receipts.len() >= self.capacity {
+            receipts.pop_front();
+        }
+        receipts.push_back(receipt);
+    }
+
+    /// Newest first, receipts signed by `signer`, optionally only of `spell_id`
+    pub fn list(&self, signer: &str, spell_id: Option<&str>, limit: usize) -> Vec<SpellReceipt> {
+        self.receipts
+            .lock()
+            .iter()
+            .rev()
+            .filter(|r| r.signer == signer)
+            .filter(|r| spell_id.map_or(true, |id| r.spell_id == id))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fluence_keypair::KeyPair;
+
+    use super::{ReceiptLog, SpellReceipt};
+
+    fn receipt(spell_id: &str, keypair: &KeyPair) -> SpellReceipt {
+        SpellReceipt::new(
+            spell_id.to_string(),
+            format!("spell_{spell_id}_0"),
+            "{}".to_string(),
+            "(null)",
+            "ok",
+            1000,
+            keypair,
+        )
+    }
+
+    #[test]
+    fn receipt_signature_covers_fields() {
+        let keypair = KeyPair::generate_ed25519();
+        let mut receipt = receipt("spell", &keypair);
+        assert!(receipt.verify());
+
+        receipt.result_hash = blake3::hash(b"forged").to_hex().to_string();
+        assert!(!receipt.verify());
+    }
+
+    #[test]
+    fn log_is_bounded_and_filtered() {
+        let host = KeyPair::generate_ed25519();
+        let worker = KeyPair::generate_ed25519();
+        let log = ReceiptLog::new(3);
+        log.push(receipt("a", &host));
+        log.push(receipt("b", &host));
+        log.push(receipt("c", &worker));
+        log.push(receipt("d", &host));
+
+        let host_id = host.get_peer_id().to_base58();
+        let ids: Vec<_> = log
+            .list(&host_id, None, 10)
+            .into_iter()
+            .map(|r| r.spell_id)
+            .collect();
+        assert_eq!(ids, vec!["d", "b"]);
+        assert_eq!(log.list(&host_id, Some("b"), 10).len(), 1);
+        assert_eq!(log.list(&host_id, None, 1)[0].spell_id, "d");
+    }
+}
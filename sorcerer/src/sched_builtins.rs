@@ -0,0 +1,138 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+use serde_json::{json, Value as JValue};
+use std::sync::Arc;
+
+use crate::scheduler::{JobScheduler, ScheduledJob};
+use now_millis::now_sec;
+use particle_args::{Args, JError};
+use particle_execution::ParticleParams;
+use particle_services::PeerScope;
+use uuid_utils::uuid;
+use workers::{PeerScopes, Workers};
+
+/// Same rules as for spells: on a worker jobs can be managed by the worker creator,
+/// the worker itself or the peer manager, on the host only by the host or the peer manager
+fn check_permissions(
+    params: &ParticleParams,
+    workers: &Workers,
+    scopes: &PeerScopes,
+) -> Result<(), JError> {
+    let init_peer_id = params.init_peer_id;
+    let is_management = scopes.is_management(init_peer_id);
+    match params.peer_scope {
+        PeerScope::WorkerId(worker_id) => {
+            let worker_creator = workers.get_worker_creator(worker_id)?;
+            let is_worker = init_peer_id == worker_id.into();
+            if !is_management && !is_worker && init_peer_id != worker_creator {
+                return Err(JError::new(format!("Scheduled jobs on {worker_id} can be managed by worker creator {worker_creator}, worker itself or peer manager; init_peer_id={init_peer_id}")));
+            }
+        }
+        PeerScope::Host => {
+            if !is_management && !scopes.is_host(init_peer_id) {
+                return Err(JError::new(format!("Scheduled jobs on the host can be managed only by the host or peer manager; init_peer_id={init_peer_id}")));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Schedules `script` to run at `at_sec` UNIX timestamp or after `delay_sec` seconds.
+/// With `interval_sec` the script is run again every `interval_sec` seconds until the job is cancelled,
+/// the first run is then after `interval_sec` if neither `at_sec` nor `delay_sec` is set.
+/// Returns the id of the scheduled job.
+pub(crate) async fn sched_submit(
+    args: Args,
+    params: ParticleParams,
+    scheduler: JobScheduler,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let script: String = Args::next("script", &mut args)?;
+    let at_sec: Option<u64> = Args::next_opt("at_sec", &mut args)?;
+    let delay_sec: Option<u64> = Args::next_opt("delay_sec", &mut args)?;
+    let interval_sec: Option<u64> = Args::next_opt("interval_sec", &mut args)?;
+
+    check_permissions(&params, &workers, &scopes)?;
+
+    let run_at_sec = match (at_sec, delay_sec, interval_sec) {
+        (Some(at), None, _) => at,
+        (None, Some(delay), _) => now_sec().saturating_add(delay),
+        (None, None, Some(interval)) => now_sec().saturating_add(interval),
+        _ => {
+            return Err(JError::new(
+                "Exactly one of at_sec and delay_sec must be specified, or interval_sec alone",
+            ))
+        }
+    };
+
+    let job = ScheduledJob {
+        id: uuid(),
+        script,
+        peer_scope: params.peer_scope,
+        run_at_sec,
+        interval_sec,
+    };
+    let job_id = job.id.clone();
+    scheduler.submit(job).await?;
+
+    Ok(JValue::String(job_id))
+}
+
+pub(crate) async fn sched_cancel(
+    args: Args,
+    params: ParticleParams,
+    scheduler: JobScheduler,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let job_id: String = Args::next("job_id", &mut args)?;
+
+    check_permissions(&params, &workers, &scopes)?;
+
+    if scheduler.cancel(params.peer_scope, &job_id).await {
+        Ok(())
+    } else {
+        Err(JError::new(format!("Scheduled job {job_id} not found")))
+    }
+}
+
+pub(crate) fn sched_list(
+    params: ParticleParams,
+    scheduler: JobScheduler,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<JValue, JError> {
+    check_permissions(&params, &workers, &scopes)?;
+
+    let jobs = scheduler
+        .list(params.peer_scope)
+        .into_iter()
+        .map(|job| {
+            json!({
+                "id": job.id,
+                "run_at_sec": job.run_at_sec,
+                "interval_sec": job.interval_sec,
+            })
+        })
+        .collect();
+    Ok(JValue::Array(jobs))
+}
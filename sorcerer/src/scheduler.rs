@@ -0,0 +1,356 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! AIR scripts scheduled to run at a future time via `sched.submit`, once or repeatedly.
+//! Pending jobs are persisted to disk, jobs missed while the node was down are run on startup,
+//! a repeated job runs once for all the runs it missed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use now_millis::now_sec;
+use particle_args::JError;
+use particle_services::PeerScope;
+use server_config::SchedulerConfig;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub script: String,
+    pub peer_scope: PeerScope,
+    /// UNIX timestamp in seconds when the job should be run
+    pub run_at_sec: u64,
+    /// The job is run again after that many seconds, until it's cancelled
+    #[serde(default)]
+    pub interval_sec: Option<u64>,
+}
+
+impl ScheduledJob {
+    /// The first run of a repeated job after `now`, on the schedule of its previous runs
+    fn next_run_after(&self, now: u64) -> Option<u64> {
+        let interval = self.interval_sec.filter(|interval| *interval > 0)?;
+        let missed = now.saturating_sub(self.run_at_sec) / interval;
+        let next = self
+            .run_at_sec
+            .saturating_add(missed.saturating_add(1).saturating_mul(interval));
+        Some(next)
+    }
+}
+
+fn job_file_name(job_id: &str) -> String {
+    format!("{job_id}_job.toml")
+}
+
+fn is_job(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map_or(false, |n| n.ends_with("_job.toml"))
+}
+
+#[derive(Clone)]
+pub struct JobScheduler {
+    jobs_dir: PathBuf,
+    jobs: Arc<Mutex<HashMap<String, ScheduledJob>>>,
+    notify: Arc<Notify>,
+    config: SchedulerConfig,
+}
+
+impl JobScheduler {
+    /// Loads persisted jobs from `jobs_dir`
+    pub async fn load(jobs_dir: PathBuf, config: SchedulerConfig) -> eyre::Result<Self> {
+        let persisted = fs_utils::load_persisted_data(&jobs_dir, is_job, |bytes| {
+            toml_edit::de::from_slice(bytes).map_err(|e| e.into())
+        })
+        .await?;
+        let jobs = persisted
+            .into_iter()
+            .map(|(job, _): (ScheduledJob, _)| (job.id.clone(), job))
+            .collect();
+
+        Ok(Self {
+            jobs_dir,
+            jobs: Arc::new(Mutex::new(jobs)),
+            notify: Arc::new(Notify::new()),
+            config,
+        })
+    }
+
+    pub async fn submit(&self, job: ScheduledJob) -> Result<(), JError> {
+        let max_script_size = self.config.max_script_size.as_u64();
+        if job.script.len() as u64 > max_script_size {
+            return Err(JError::new(format!(
+                "Job script is {} bytes, the limit is {max_script_size} bytes",
+                job.script.len()
+            )));
+        }
+        let min_interval = self.config.min_interval.as_secs().max(1);
+        if job
+            .interval_sec
+            .is_some_and(|interval| interval < min_interval)
+        {
+            return Err(JError::new(format!(
+                "Job interval can't be shorter than {min_interval} seconds"
+            )));
+        }
+
+        // The job takes its place in the scope before it's persisted, so concurrent submits can't exceed the limit
+        {
+            let mut jobs = self.jobs.lock();
+            let max_jobs = self.config.max_jobs_per_scope;
+            let scope_jobs = jobs
+                .values()
+                .filter(|scheduled| scheduled.peer_scope == job.peer_scope)
+                .count();
+            if scope_jobs >= max_jobs {
+                return Err(JError::new(format!(
+                    "Too many scheduled jobs, the limit is {max_jobs} per host or worker"
+                )));
+            }
+            jobs.insert(job.id.clone(), job.clone());
+        }
+        if let Err(err) = self.persist(&job).await {
+            self.jobs.lock().remove(&job.id);
+            return Err(err);
+        }
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    async fn persist(&self, job: &ScheduledJob) -> Result<(), JError> {
+        let path = self.jobs_dir.join(job_file_name(&job.id));
+        let bytes = toml_edit::ser::to_vec(job)
+            .map_err(|err| JError::new(format!("Failed to serialize job {}: {err}", job.id)))?;
+        tokio::fs::write(&path, bytes).await.map_err(|err| {
+            JError::new(format!(
+                "Failed to persist job {} to {path:?}: {err}",
+                job.id
+            ))
+        })
+    }
+
+    /// Removes a pending job, returns `false` if there's no such job in the scope
+    pub async fn cancel(&self, peer_scope: PeerScope, job_id: &str) -> bool {
+        let removed = {
+            let mut jobs = self.jobs.lock();
+            match jobs.get(job_id) {
+                Some(job) if job.peer_scope == peer_scope => jobs.remove(job_id).is_some(),
+                _ => false,
+            }
+        };
+        if removed {
+            self.remove_persisted(job_id).await;
+        }
+        removed
+    }
+
+    pub fn list(&self, peer_scope: PeerScope) -> Vec<ScheduledJob> {
+        let mut jobs: Vec<_> = self
+            .jobs
+            .lock()
+            .values()
+            .filter(|job| job.peer_scope == peer_scope)
+            .cloned()
+            .collect();
+        jobs.sort_by_key(|job| job.run_at_sec);
+        jobs
+    }
+
+    /// Waits until there are jobs to run and takes them out of the schedule.
+    /// Repeated jobs stay in the schedule, moved to their next run.
+    pub(crate) async fn next_due(&self) -> Vec<ScheduledJob> {
+        loop {
+            let now = now_sec();
+            let next_run_at = {
+                let mut jobs = self.jobs.lock();
+                let due: Vec<_> = jobs
+                    .values()
+                    .filter(|job| job.run_at_sec <= now)
+                    .map(|job| job.id.clone())
+                    .collect();
+                if !due.is_empty() {
+                    let due = due
+                        .iter()
+                        .filter_map(|id| {
+                            let job = jobs.get_mut(id)?;
+                            match job.next_run_after(now) {
+                                Some(next_run_at) => {
+                                    let due = job.clone();
+                                    job.run_at_sec = next_run_at;
+                                    Some(due)
+                                }
+                                None => jobs.remove(id),
+                            }
+                        })
+                        .collect();
+                    return due;
+                }
+                jobs.values().map(|job| job.run_at_sec).min()
+            };
+
+            match next_run_at {
+                Some(at) => {
+                    let delay = Duration::from_secs(at.saturating_sub(now));
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {},
+                        _ = self.notify.notified() => {},
+                    }
+                }
+                None => self.notify.notified().await,
+            }
+        }
+    }
+
+    /// Persists the next run of a repeated job after it's run, removes a finished job
+    pub(crate) async fn complete(&self, job_id: &str) {
+        let rescheduled = self.jobs.lock().get(job_id).cloned();
+        match rescheduled {
+            Some(job) => {
+                if let Err(err) = self.persist(&job).await {
+                    log::warn!("Failed to persist the next run of job {job_id}: {err}");
+                }
+            }
+            None => self.remove_persisted(job_id).await,
+        }
+    }
+
+    async fn remove_persisted(&self, job_id: &str) {
+        let path = self.jobs_dir.join(job_file_name(job_id));
+        if let Err(err) = tokio::fs::remove_file(&path).await {
+            log::warn!("Failed to remove persisted job {job_id} at {path:?}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JobScheduler, ScheduledJob};
+    use now_millis::now_sec;
+    use particle_services::PeerScope;
+    use server_config::SchedulerConfig;
+
+    fn job(id: &str, run_at_sec: u64) -> ScheduledJob {
+        ScheduledJob {
+            id: id.to_string(),
+            script: "(null)".to_string(),
+            peer_scope: PeerScope::Host,
+            run_at_sec,
+            interval_sec: None,
+        }
+    }
+
+    async fn scheduler(dir: &tempfile::TempDir) -> JobScheduler {
+        JobScheduler::load(dir.path().to_path_buf(), SchedulerConfig::default())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn jobs_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = scheduler(&dir).await;
+        let later = now_sec() + 3600;
+        scheduler.submit(job("a", later)).await.unwrap();
+        scheduler.submit(job("b", later)).await.unwrap();
+        assert!(scheduler.cancel(PeerScope::Host, "b").await);
+        assert!(!scheduler.cancel(PeerScope::Host, "b").await);
+
+        let restored = scheduler(&dir).await;
+        let jobs = restored.list(PeerScope::Host);
+        assert_eq!(jobs.len(), 1);
+        assert_eq!(jobs[0].id, "a");
+        assert_eq!(jobs[0].run_at_sec, later);
+    }
+
+    #[tokio::test]
+    async fn due_jobs_are_taken_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = scheduler(&dir).await;
+        scheduler.submit(job("past", 1)).await.unwrap();
+        scheduler
+            .submit(job("future", now_sec() + 3600))
+            .await
+            .unwrap();
+
+        let due = scheduler.next_due().await;
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, "past");
+        assert_eq!(scheduler.list(PeerScope::Host).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn repeated_jobs_stay_scheduled() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = scheduler(&dir).await;
+        let now = now_sec();
+        let mut repeated = job("repeated", now - 25);
+        repeated.interval_sec = Some(10);
+        scheduler.submit(repeated).await.unwrap();
+
+        // Missed runs are made once, the next one keeps the schedule
+        let due = scheduler.next_due().await;
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].run_at_sec, now - 25);
+        scheduler.complete("repeated").await;
+        let restored = scheduler(&dir).await;
+        let jobs = restored.list(PeerScope::Host);
+        assert_eq!(jobs.len(), 1);
+        assert!(jobs[0].run_at_sec > now && jobs[0].run_at_sec <= now + 10);
+        assert_eq!((jobs[0].run_at_sec + 25 - now) % 10, 0);
+
+        // A cancelled job isn't rescheduled
+        assert!(scheduler.cancel(PeerScope::Host, "repeated").await);
+        scheduler.complete("repeated").await;
+        assert!(scheduler(&dir).await.list(PeerScope::Host).is_empty());
+    }
+
+    #[tokio::test]
+    async fn submits_are_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SchedulerConfig {
+            max_jobs_per_scope: 2,
+            ..SchedulerConfig::default()
+        };
+        let scheduler = JobScheduler::load(dir.path().to_path_buf(), config)
+            .await
+            .unwrap();
+        let later = now_sec() + 3600;
+
+        let mut large = job("large", later);
+        large.script = "(null)".repeat(11_000);
+        assert!(scheduler.submit(large).await.is_err());
+
+        let mut frequent = job("frequent", later);
+        frequent.interval_sec = Some(1);
+        assert!(scheduler.submit(frequent).await.is_err());
+
+        scheduler.submit(job("a", later)).await.unwrap();
+        scheduler.submit(job("b", later)).await.unwrap();
+        assert!(scheduler.submit(job("c", later)).await.is_err());
+        // A cancelled job frees its place
+        assert!(scheduler.cancel(PeerScope::Host, "a").await);
+        scheduler.submit(job("c", later)).await.unwrap();
+    }
+}
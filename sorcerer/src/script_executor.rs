@@ -19,14 +19,20 @@
 use std::sync::Arc;
 use tracing::{instrument, Span};
 
-use crate::error::SorcererError::{ParticleSigningFailed, ScopeKeypairMissing};
+use crate::error::SorcererError::{
+    JobKeypairMissing, JobSigningFailed, ParticleSigningFailed, ScopeKeypairMissing,
+};
+use crate::receipts::SpellReceipt;
+use crate::scheduler::ScheduledJob;
+use crate::spell_builtins::LAST_FIRED_KEY;
 use crate::Sorcerer;
 use fluence_libp2p::PeerId;
+use node_events::EventKind;
 use now_millis::now_ms;
 use particle_args::JError;
 use particle_protocol::{ExtendedParticle, Particle};
 use particle_services::PeerScope;
-use spell_event_bus::api::{TriggerEvent, TriggerInfoAqua};
+use spell_event_bus::api::{PeerEvent, TimerEvent, TriggerEvent, TriggerInfo, TriggerInfoAqua};
 use spell_service_api::CallParams;
 
 impl Sorcerer {
@@ -116,6 +122,9 @@ impl Sorcerer {
             script: spell_script,
             signature: vec![],
             data: vec![],
+            trail: None,
+            progress: false,
+            hops: 0,
         };
         particle
             .sign(&spell_keypair)
@@ -130,7 +139,7 @@ impl Sorcerer {
         peer_scope: PeerScope,
     ) -> Result<(), JError> {
         let init_peer_id = self.scopes.to_peer_id(peer_scope);
-        let serialized_event = serde_json::to_string(&TriggerInfoAqua::from(event.info))?;
+        let serialized_event = serialize_trigger(event.info)?;
         let params = CallParams::local(
             peer_scope,
             event.spell_id,
@@ -143,8 +152,52 @@ impl Sorcerer {
             .map_err(|e| JError::new(e.to_string()))
     }
 
+    /// Remember when the timer of the spell fired last to continue its schedule after a restart.
+    async fn store_last_fired(
+        &self,
+        peer_scope: PeerScope,
+        spell_id: String,
+        timestamp: u64,
+    ) -> Result<(), JError> {
+        let init_peer_id = self.scopes.to_peer_id(peer_scope);
+        let params = CallParams::local(
+            peer_scope,
+            spell_id,
+            init_peer_id,
+            self.spell_script_particle_ttl,
+        );
+        let timestamp = u32::try_from(timestamp).unwrap_or(u32::MAX);
+        self.spell_service_api
+            .set_u32(params, LAST_FIRED_KEY.to_string(), timestamp)
+            .await
+            .map_err(|e| JError::new(e.to_string()))
+    }
+
+    /// Count the previous run of the spell and back off its timer if it keeps failing
+    async fn update_quarantine(&self, spell_id: &str) -> Result<(), JError> {
+        let Some(level) = self.quarantine.on_run(spell_id) else {
+            return Ok(());
+        };
+        if level > 0 {
+            log::warn!("Spell {spell_id} keeps failing, quarantine level {level}");
+        } else {
+            log::info!("Spell {spell_id} succeeded, released from the quarantine");
+        }
+        self.spell_event_bus_api
+            .set_quarantine(spell_id.to_string(), level, self.quarantine_max_backoff)
+            .await
+            .map_err(|e| JError::new(e.to_string()))
+    }
+
     #[instrument(level = tracing::Level::INFO, skip_all)]
     pub async fn execute_script(&self, event: TriggerEvent, span: Arc<Span>) {
+        if let Err(err) = self.update_quarantine(&event.spell_id).await {
+            log::warn!(
+                "Failed to update quarantine of spell {}: {:?}",
+                event.spell_id,
+                err
+            );
+        }
         let error: Result<(), JError> = try {
             let peer_scope = self
                 .spell_storage
@@ -155,18 +208,58 @@ impl Sorcerer {
                 .make_spell_particle(peer_scope, event.spell_id.clone())
                 .await?;
 
+            if let TriggerInfo::Timer(TimerEvent { timestamp }) = event.info {
+                if let Err(err) = self
+                    .store_last_fired(peer_scope, event.spell_id.clone(), timestamp)
+                    .await
+                {
+                    log::warn!(
+                        "Failed to store the last timer run of spell {}: {:?}",
+                        event.spell_id,
+                        err
+                    );
+                }
+            }
+
+            let trigger = serialize_trigger(event.info.clone())?;
             self.store_trigger(event.clone(), peer_scope).await?;
+            self.services.publish_event(
+                EventKind::SpellTriggered,
+                peer_scope,
+                &event.spell_id,
+                Some(trigger.clone()),
+            );
             if let Some(m) = &self.spell_metrics {
                 m.observe_spell_cast();
             }
 
-            self.aquamarine
+            let particle_id = particle.id.clone();
+            let script = particle.script.clone();
+            let result = self
+                .aquamarine
                 .clone()
                 .execute(ExtendedParticle::linked(particle, span), None)
-                .await?;
+                .await;
+            let outcome = match &result {
+                Ok(()) => "ok".to_string(),
+                Err(err) => err.to_string(),
+            };
+            if let Some(keypair) = self.key_storage.get_keypair(peer_scope) {
+                self.receipts.push(SpellReceipt::new(
+                    event.spell_id.clone(),
+                    particle_id,
+                    trigger,
+                    &script,
+                    &outcome,
+                    now_ms() as u64,
+                    &keypair,
+                ));
+            }
+            result?;
         };
 
         if let Err(err) = error {
+            self.quarantine.on_failure(&event.spell_id);
             log::warn!(
                 "Failed to execute spell script id: {spell_id}, event: {:?}, error: {:?}",
                 event.info,
@@ -175,4 +268,68 @@ impl Sorcerer {
             );
         }
     }
+
+    fn make_job_particle(&self, job: ScheduledJob) -> Result<Particle, JError> {
+        let keypair = self
+            .key_storage
+            .get_keypair(job.peer_scope)
+            .ok_or(JobKeypairMissing {
+                job_id: job.id.clone(),
+                peer_scope: job.peer_scope,
+            })?;
+
+        let mut particle = Particle {
+            // A repeated job runs under the same job id, so the run time makes the particle id unique
+            id: format!("sched_{}_{}", job.id, job.run_at_sec),
+            init_peer_id: self.scopes.to_peer_id(job.peer_scope),
+            timestamp: now_ms() as u64,
+            ttl: self.spell_script_particle_ttl.as_millis() as u32,
+            script: job.script,
+            signature: vec![],
+            data: vec![],
+            trail: None,
+            progress: false,
+            hops: 0,
+        };
+        particle.sign(&keypair).map_err(|err| JobSigningFailed {
+            err,
+            job_id: job.id,
+        })?;
+
+        Ok(particle)
+    }
+
+    /// Runs jobs submitted via `sched.submit` as they become due
+    pub(crate) async fn run_scheduled_jobs(self) {
+        loop {
+            for job in self.scheduler.next_due().await {
+                let job_id = job.id.clone();
+                let span =
+                    Arc::new(tracing::info_span!("Sorcerer::run_scheduled_job", job_id = %job_id));
+                let result: Result<(), JError> = try {
+                    let particle = self.make_job_particle(job)?;
+                    self.aquamarine
+                        .clone()
+                        .execute(ExtendedParticle::linked(particle, span), None)
+                        .await?;
+                };
+                if let Err(err) = result {
+                    log::warn!("Failed to run scheduled job {job_id}: {err:?}");
+                }
+                self.scheduler.complete(&job_id).await;
+            }
+        }
+    }
+}
+
+/// Trigger as written to the spell mailbox, peer events rendered by a template of
+/// the subscription are written in their own shape
+fn serialize_trigger(info: TriggerInfo) -> serde_json::Result<String> {
+    match info {
+        TriggerInfo::Peer(PeerEvent {
+            payload: Some(payload),
+            ..
+        }) => Ok(payload.to_string()),
+        info => serde_json::to_string(&TriggerInfoAqua::from(info)),
+    }
 }
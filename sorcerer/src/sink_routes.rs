@@ -0,0 +1,108 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashSet;
+
+use particle_args::{ErrorCode, JError};
+use serde::{Deserialize, Serialize};
+
+/// KV key where the routes of the spell values to external sinks are stored
+pub const SINK_ROUTES_KEY: &str = "hw_sink_routes";
+
+/// Values written by a spell to the matching KV keys are forwarded to the named sink
+/// from the node config by the node, the spell itself doesn't talk to the sink
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SinkRoute {
+    /// KV key of the spell, a trailing `*` matches all keys with the prefix
+    pub key: String,
+    pub sink: String,
+}
+
+impl SinkRoute {
+    pub fn matches(&self, key: &str) -> bool {
+        match self.key.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => self.key == key,
+        }
+    }
+}
+
+/// Routes are checked when they are set, so values aren't silently lost
+/// because of a typo in a sink name
+pub(crate) fn check_sink_routes(
+    routes: &[SinkRoute],
+    sinks: &HashSet<String>,
+) -> Result<(), JError> {
+    for route in routes {
+        if route.key.is_empty() || route.key == "*" {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("Invalid key '{}' of a sink route", route.key),
+            ));
+        }
+        if route.key.starts_with("hw_") {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("Key '{}' is reserved by the node", route.key),
+            ));
+        }
+        if !sinks.contains(&route.sink) {
+            let mut known: Vec<&str> = sinks.iter().map(String::as_str).collect();
+            known.sort_unstable();
+            let known = known.join(", ");
+            return Err(JError::with_code(
+                ErrorCode::NotFound,
+                format!("Unknown sink '{}', known sinks: [{known}]", route.sink),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(key: &str, sink: &str) -> SinkRoute {
+        SinkRoute {
+            key: key.to_string(),
+            sink: sink.to_string(),
+        }
+    }
+
+    #[test]
+    fn matches_keys_and_prefixes() {
+        assert!(route("price", "kafka").matches("price"));
+        assert!(!route("price", "kafka").matches("price_usd"));
+        assert!(route("price_*", "kafka").matches("price_usd"));
+        assert!(!route("price_*", "kafka").matches("volume"));
+    }
+
+    #[test]
+    fn checks_routes() {
+        let sinks = HashSet::from(["kafka".to_string()]);
+        assert!(check_sink_routes(&[route("price", "kafka")], &sinks).is_ok());
+        assert!(check_sink_routes(&[], &sinks).is_ok());
+
+        let unknown = check_sink_routes(&[route("price", "nats")], &sinks).unwrap_err();
+        assert!(unknown.to_string().contains("kafka"));
+        assert!(check_sink_routes(&[route("*", "kafka")], &sinks).is_err());
+        assert!(check_sink_routes(&[route("hw_last_fired", "kafka")], &sinks).is_err());
+    }
+}
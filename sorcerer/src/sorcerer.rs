@@ -17,7 +17,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -26,29 +26,50 @@ use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
+use crate::quarantine::SpellQuarantine;
+use crate::receipts::ReceiptLog;
+use crate::sched_builtins::{sched_cancel, sched_list, sched_submit};
+use crate::scheduler::JobScheduler;
 use crate::spell_builtins::{
-    get_spell_arg, get_spell_id, spell_install, spell_list, spell_remove, spell_update_config,
-    store_error, store_response,
+    apply_missed_runs, get_spell_arg, get_spell_id, is_paused, load_trigger_config,
+    spell_get_status, spell_import, spell_install, spell_install_builtin, spell_kv_incr,
+    spell_kv_set_if_equals, spell_list, spell_list_builtin, spell_package, spell_pause,
+    spell_receipts, spell_remove, spell_resume, spell_set_custom_triggers,
+    spell_set_exclusion_windows, spell_set_health_triggers, spell_set_kv_triggers,
+    spell_set_missed_runs, spell_set_partition_triggers, spell_set_peer_payload,
+    spell_set_probe_triggers, spell_set_resource_triggers, spell_set_sinks, spell_set_webhook,
+    spell_update_config, spell_update_script, store_error, store_response,
 };
+use crate::stored_triggers::StoredTriggers;
+use crate::trigger_presets::TriggerPresets;
 use crate::worker_builins::{
-    activate_deal, create_worker, deactivate_deal, get_worker_peer_id, is_deal_active,
-    remove_worker, worker_list,
+    activate_deal, create_worker, deactivate_deal, deactivate_worker, get_worker_peer_id,
+    is_deal_active, key_derivation_paths, remove_worker, revoked_workers, transfer_worker,
+    worker_list,
 };
 use aquamarine::AquamarineApi;
+use fluence_libp2p::PeerId;
 use particle_args::JError;
 use particle_builtins::{wrap, wrap_unit, CustomService};
 use particle_execution::ServiceFunction;
 use particle_modules::ModuleRepository;
-use particle_services::ParticleAppServices;
+use particle_services::{ParticleAppServices, PeerScope};
 use peer_metrics::SpellMetrics;
 use serde_json::Value;
 use server_config::ResolvedConfig;
-use spell_event_bus::api::{from_user_config, SpellEventBusApi, TriggerEvent};
+use spell_event_bus::api::{
+    from_user_config, SpellEventBusApi, TriggerEvent, BACKOFF_HINT_KEY, MAX_PERIOD_SEC,
+};
 use spell_service_api::{CallParams, SpellServiceApi};
 use spell_storage::SpellStorage;
 use tracing::Instrument;
 use workers::{KeyStorage, PeerScopes, Workers};
 
+/// Number of retries to reschedule a spell that failed to recover on the node start
+const RECOVERY_RETRIES: u32 = 5;
+/// Delay before the first retry, doubled on each next one
+const RECOVERY_RETRY_DELAY: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 pub struct Sorcerer {
     pub aquamarine: AquamarineApi,
@@ -62,6 +83,16 @@ pub struct Sorcerer {
     pub spell_service_api: SpellServiceApi,
     pub spell_metrics: Option<SpellMetrics>,
     pub worker_period_sec: u32,
+    pub scheduler: JobScheduler,
+    pub receipts: ReceiptLog,
+    pub trigger_presets: TriggerPresets,
+    pub quarantine: SpellQuarantine,
+    /// The longest period of a quarantined spell
+    pub quarantine_max_backoff: Duration,
+    /// Workers created by these keys are paused until transferred to another creator
+    pub revoked_keys: Arc<HashSet<PeerId>>,
+    /// Names of the external sinks spell values can be routed to
+    pub sinks: Arc<HashSet<String>>,
 }
 
 impl Sorcerer {
@@ -82,7 +113,17 @@ impl Sorcerer {
             SpellStorage::create(&config.dir_config.spell_base_dir, &services, &modules)
                 .await
                 .expect("Spell storage creation");
+        let scheduler = JobScheduler::load(
+            config.dir_config.scheduled_jobs_dir.clone(),
+            config.scheduler_config.clone(),
+        )
+        .await
+        .expect("Job scheduler creation");
 
+        let quarantine = SpellQuarantine::new(
+            config.spell_quarantine_config.failures_threshold,
+            spell_metrics.clone(),
+        );
         let sorcerer = Self {
             aquamarine,
             services,
@@ -95,50 +136,171 @@ impl Sorcerer {
             spell_service_api,
             spell_metrics,
             worker_period_sec: config.system_services.decider.worker_period_sec,
+            scheduler,
+            receipts: ReceiptLog::new(config.spell_receipts_capacity),
+            trigger_presets: TriggerPresets::new(config.trigger_presets.clone()),
+            quarantine,
+            quarantine_max_backoff: config.spell_quarantine_config.max_backoff,
+            revoked_keys: Arc::new(config.revoked_keys.iter().map(|k| **k).collect()),
+            sinks: Arc::new(config.spell_sinks.keys().cloned().collect()),
         };
 
         let mut builtin_functions = sorcerer.make_spell_builtins();
         builtin_functions.extend_one(sorcerer.make_worker_builtin());
+        builtin_functions.extend_one(sorcerer.make_sched_builtin());
 
         (sorcerer, builtin_functions, spell_version)
     }
 
+    /// Pauses active workers created by the revoked keys along with their spells
+    async fn pause_revoked_workers(&self) {
+        for worker_id in self.workers.list_workers() {
+            let Ok(creator) = self.workers.get_worker_creator(worker_id) else {
+                continue;
+            };
+            if !self.revoked_keys.contains(&creator) || !self.workers.is_worker_active(worker_id) {
+                continue;
+            }
+
+            log::warn!("Pausing worker {worker_id} created by the revoked key {creator}");
+            let result = deactivate_worker(
+                worker_id,
+                self.spell_script_particle_ttl,
+                &self.workers,
+                &self.spell_storage,
+                &self.spell_event_bus_api,
+                &self.spell_service_api,
+            )
+            .await;
+            if let Err(e) = result {
+                log::error!("Failed to pause worker {worker_id} of the revoked key {creator}: {e}");
+            }
+        }
+    }
+
+    /// Recovery phase of the node start: subscribe every registered spell to its triggers again.
+    /// The timers continue from the last run of the spell according to its missed runs policy.
+    /// Spells that couldn't be recovered are retried in the background with a backoff.
     async fn resubscribe_spells(&self) {
+        let mut recovered = 0;
+        let mut failed = vec![];
         for (peer_scope, spells) in self.spell_storage.get_registered_spells() {
             for spell_id in spells {
                 log::info!("Rescheduling spell {} on {:?} peer", spell_id, peer_scope);
-                let result: Result<(), JError> = try {
-                    let spell_owner = self
-                        .services
-                        .get_service_owner(peer_scope, spell_id.clone(), "")
-                        .await?;
-                    let params = CallParams::local(
-                        peer_scope,
-                        spell_id.clone(),
-                        spell_owner,
-                        self.spell_script_particle_ttl,
-                    );
-                    let config = self.spell_service_api.get_trigger_config(params).await?;
-                    let period = config.clock.period_sec;
-                    let config = from_user_config(&config)?;
-                    if let Some(config) = config.and_then(|c| c.into_rescheduled()) {
-                        self.spell_event_bus_api
-                            .subscribe(spell_id.clone(), config)
-                            .await?;
-                        if let Some(m) = &self.spell_metrics {
-                            m.observe_started_spell(period);
-                        }
-                    } else {
-                        log::warn!("Spell {spell_id} is not rescheduled since its config is either not found or not reschedulable");
+                match self.resubscribe_spell(peer_scope, &spell_id).await {
+                    Ok(true) => recovered += 1,
+                    Ok(false) => {}
+                    Err(e) => {
+                        log::warn!("Failed to reschedule spell {}: {}.", spell_id, e);
+                        failed.push((peer_scope, spell_id));
                     }
-                };
-                if let Err(e) = result {
-                    // 1. We do not remove the spell we aren't able to reschedule. Users should be able to rerun it manually when updating trigger config.
-                    // 2. Maybe we should somehow register which spell are running and which are not and notify user about it.
-                    log::warn!("Failed to reschedule spell {}: {}.", spell_id, e);
                 }
             }
         }
+        log::info!(
+            "Rescheduled {recovered} spells, {} spells will be retried",
+            failed.len()
+        );
+
+        if !failed.is_empty() {
+            let sorcerer = self.clone();
+            tokio::task::Builder::new()
+                .name("sorcerer-recovery")
+                .spawn(async move { sorcerer.retry_resubscribe(failed).await })
+                .expect("Could not spawn task");
+        }
+    }
+
+    async fn retry_resubscribe(self, mut failed: Vec<(PeerScope, String)>) {
+        let mut backoff = RECOVERY_RETRY_DELAY;
+        for _ in 0..RECOVERY_RETRIES {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            let mut still_failed = vec![];
+            for (peer_scope, spell_id) in failed {
+                if let Err(e) = self.resubscribe_spell(peer_scope, &spell_id).await {
+                    log::debug!("Retry to reschedule spell {spell_id} failed: {e}");
+                    still_failed.push((peer_scope, spell_id));
+                }
+            }
+            failed = still_failed;
+            if failed.is_empty() {
+                return;
+            }
+        }
+        for (_, spell_id) in failed {
+            // We do not remove the spell we aren't able to reschedule. Users should be able to rerun it manually when updating trigger config.
+            log::error!("Spell {spell_id} is not rescheduled after {RECOVERY_RETRIES} retries");
+        }
+    }
+
+    /// Subscribe the spell to its stored triggers. Returns false when the spell
+    /// is paused or has nothing to be triggered by.
+    async fn resubscribe_spell(
+        &self,
+        peer_scope: PeerScope,
+        spell_id: &str,
+    ) -> Result<bool, JError> {
+        // The spell could have been removed while waiting for a retry
+        if !self
+            .spell_storage
+            .get_registered_spells_by(peer_scope)
+            .iter()
+            .any(|id| id == spell_id)
+        {
+            return Ok(false);
+        }
+        let spell_owner = self
+            .services
+            .get_service_owner(peer_scope, spell_id.to_string(), "")
+            .await?;
+        let params = CallParams::local(
+            peer_scope,
+            spell_id.to_string(),
+            spell_owner,
+            self.spell_script_particle_ttl,
+        );
+        let stored_triggers = StoredTriggers::load(&self.spell_service_api, params.clone()).await?;
+        if stored_triggers.paused {
+            log::info!("Spell {spell_id} is paused, not rescheduling it");
+            return Ok(false);
+        }
+        let (config, cron) = load_trigger_config(&self.spell_service_api, params.clone()).await?;
+        let period = config.clock.period_sec;
+        let config = from_user_config(&config, cron.as_ref())?.and_then(|c| c.into_rescheduled());
+        let config = stored_triggers.apply(config)?;
+        // Both the timer and the cron trigger catch up on the runs missed while the node was down
+        let config = apply_missed_runs(&self.spell_service_api, params.clone(), config).await?;
+        let Some(config) = config else {
+            log::warn!("Spell {spell_id} is not rescheduled since its config is either not found or not reschedulable");
+            return Ok(false);
+        };
+        // The spell could have been subscribed by a config update while waiting for a retry
+        self.spell_event_bus_api
+            .unsubscribe(spell_id.to_string())
+            .await?;
+        self.spell_event_bus_api
+            .subscribe(spell_id.to_string(), config)
+            .await?;
+        // The bus keeps backoff hints only while the node is running, so the stored one is restored here
+        let backoff_hint = self
+            .spell_service_api
+            .get_u32(params, BACKOFF_HINT_KEY.to_string())
+            .await?
+            .unwrap_or(0)
+            .min(MAX_PERIOD_SEC);
+        if backoff_hint > 0 {
+            self.spell_event_bus_api
+                .set_backoff(
+                    spell_id.to_string(),
+                    Duration::from_secs(backoff_hint as u64),
+                )
+                .await?;
+        }
+        if let Some(m) = &self.spell_metrics {
+            m.observe_started_spell(period);
+        }
+        Ok(true)
     }
 
     pub fn start(
@@ -148,10 +310,12 @@ impl Sorcerer {
         tokio::task::Builder::new()
             .name("sorcerer")
             .spawn(async {
+                self.pause_revoked_workers().await;
                 self.resubscribe_spells().await;
+                let scheduled_jobs = self.clone().run_scheduled_jobs();
                 let spell_events_stream = UnboundedReceiverStream::new(spell_events_receiver);
-                spell_events_stream
-                    .for_each_concurrent(None, move |spell_event| {
+                let spell_events =
+                    spell_events_stream.for_each_concurrent(None, move |spell_event| {
                         let root_span = tracing::info_span!(
                             "Sorcerer::task::for_each",
                             spell_id = spell_event.spell_id.to_string()
@@ -170,8 +334,8 @@ impl Sorcerer {
                                 .await;
                         }
                         .instrument(async_span)
-                    })
-                    .await;
+                    });
+                futures::future::join(spell_events, scheduled_jobs).await;
             })
             .expect("Could not spawn task")
     }
@@ -184,12 +348,58 @@ impl Sorcerer {
             CustomService::new(
                 vec![
                     ("install", self.make_spell_install_closure()),
+                    ("install_builtin", self.make_spell_install_builtin_closure()),
+                    ("list_builtin", self.make_spell_list_builtin_closure()),
                     ("remove", self.make_spell_remove_closure()),
                     ("list", self.make_spell_list_closure()),
                     (
                         "update_trigger_config",
                         self.make_spell_update_config_closure(),
                     ),
+                    (
+                        "kv_set_if_equals",
+                        self.make_spell_kv_set_if_equals_closure(),
+                    ),
+                    ("kv_incr", self.make_spell_kv_incr_closure()),
+                    ("update_script", self.make_spell_update_script_closure()),
+                    ("package", self.make_spell_package_closure()),
+                    ("import", self.make_spell_import_closure()),
+                    (
+                        "set_resource_triggers",
+                        self.make_spell_set_resource_triggers_closure(),
+                    ),
+                    ("set_kv_triggers", self.make_spell_set_kv_triggers_closure()),
+                    (
+                        "set_probe_triggers",
+                        self.make_spell_set_probe_triggers_closure(),
+                    ),
+                    (
+                        "set_health_triggers",
+                        self.make_spell_set_health_triggers_closure(),
+                    ),
+                    (
+                        "set_partition_triggers",
+                        self.make_spell_set_partition_triggers_closure(),
+                    ),
+                    (
+                        "set_custom_triggers",
+                        self.make_spell_set_custom_triggers_closure(),
+                    ),
+                    ("set_webhook", self.make_spell_set_webhook_closure()),
+                    ("set_missed_runs", self.make_spell_set_missed_runs_closure()),
+                    (
+                        "set_exclusion_windows",
+                        self.make_spell_set_exclusion_windows_closure(),
+                    ),
+                    (
+                        "set_peer_payload",
+                        self.make_spell_set_peer_payload_closure(),
+                    ),
+                    ("set_sinks", self.make_spell_set_sinks_closure()),
+                    ("pause", self.make_spell_pause_closure()),
+                    ("resume", self.make_spell_resume_closure()),
+                    ("receipts", self.make_spell_receipts_closure()),
+                    ("get_status", self.make_spell_get_status_closure()),
                 ],
                 None,
             ),
@@ -234,6 +444,26 @@ impl Sorcerer {
                     ("activate", self.make_activate_deal_closure()),
                     ("deactivate", self.make_deactivate_deal_closure()),
                     ("is_active", self.make_is_deal_active_closure()),
+                    ("revoked", self.make_revoked_workers_closure()),
+                    ("transfer", self.make_transfer_worker_closure()),
+                    (
+                        "key_derivation_paths",
+                        self.make_key_derivation_paths_closure(),
+                    ),
+                ],
+                None,
+            ),
+        )
+    }
+
+    fn make_sched_builtin(&self) -> (String, CustomService) {
+        (
+            "sched".to_string(),
+            CustomService::new(
+                vec![
+                    ("submit", self.make_sched_submit_closure()),
+                    ("cancel", self.make_sched_cancel_closure()),
+                    ("list", self.make_sched_list_closure()),
                 ],
                 None,
             ),
@@ -247,7 +477,9 @@ impl Sorcerer {
         let workers = self.workers.clone();
         let spell_service_api = self.spell_service_api.clone();
         let scope = self.scopes.clone();
+        let trigger_presets = self.trigger_presets.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
+            let trigger_presets = trigger_presets.clone();
             let storage = storage.clone();
             let services = services.clone();
             let spell_event_bus_api = spell_event_bus.clone();
@@ -265,6 +497,43 @@ impl Sorcerer {
                         spell_service_api,
                         workers,
                         scope,
+                        trigger_presets,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_install_builtin_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let storage = self.spell_storage.clone();
+        let spell_event_bus = self.spell_event_bus_api.clone();
+        let workers = self.workers.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        let scope = self.scopes.clone();
+        let trigger_presets = self.trigger_presets.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let trigger_presets = trigger_presets.clone();
+            let storage = storage.clone();
+            let services = services.clone();
+            let spell_event_bus_api = spell_event_bus.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scope = scope.clone();
+            async move {
+                wrap(
+                    spell_install_builtin(
+                        args,
+                        params,
+                        storage,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scope,
+                        trigger_presets,
                     )
                     .await,
                 )
@@ -273,12 +542,39 @@ impl Sorcerer {
         }))
     }
 
+    fn make_spell_list_builtin_closure(&self) -> ServiceFunction {
+        ServiceFunction::Immut(Box::new(move |_, _| {
+            async move { wrap(Ok(spell_list_builtin())) }.boxed()
+        }))
+    }
+
+    fn make_spell_receipts_closure(&self) -> ServiceFunction {
+        let receipts = self.receipts.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let receipts = receipts.clone();
+            let scopes = scopes.clone();
+            async move { wrap(spell_receipts(args, params, receipts, scopes)) }.boxed()
+        }))
+    }
+
+    fn make_spell_get_status_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let quarantine = self.quarantine.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let quarantine = quarantine.clone();
+            async move { wrap(spell_get_status(args, params, services, quarantine).await) }.boxed()
+        }))
+    }
+
     fn make_spell_remove_closure(&self) -> ServiceFunction {
         let services = self.services.clone();
         let storage = self.spell_storage.clone();
         let spell_event_bus_api = self.spell_event_bus_api.clone();
         let workers = self.workers.clone();
         let scopes = self.scopes.clone();
+        let quarantine = self.quarantine.clone();
 
         ServiceFunction::Immut(Box::new(move |args, params| {
             let storage = storage.clone();
@@ -286,9 +582,12 @@ impl Sorcerer {
             let api = spell_event_bus_api.clone();
             let workers = workers.clone();
             let scopes = scopes.clone();
+            let quarantine = quarantine.clone();
             async move {
-                let result =
-                    spell_remove(args, params, storage, services, api, workers, scopes).await;
+                let result = spell_remove(
+                    args, params, storage, services, api, workers, scopes, quarantine,
+                )
+                .await;
                 wrap_unit(result)
             }
             .boxed()
@@ -297,9 +596,11 @@ impl Sorcerer {
 
     fn make_spell_list_closure(&self) -> ServiceFunction {
         let storage = self.spell_storage.clone();
-        ServiceFunction::Immut(Box::new(move |_, params| {
+        let services = self.services.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
             let storage = storage.clone();
-            async move { wrap(spell_list(params, storage)) }.boxed()
+            let services = services.clone();
+            async move { wrap(spell_list(args, params, storage, services).await) }.boxed()
         }))
     }
 
@@ -309,7 +610,9 @@ impl Sorcerer {
         let workers = self.workers.clone();
         let scope = self.scopes.clone();
         let spell_service_api = self.spell_service_api.clone();
+        let trigger_presets = self.trigger_presets.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
+            let trigger_presets = trigger_presets.clone();
             let spell_event_bus_api = spell_event_bus_api.clone();
             let services = services.clone();
             let spell_service_api = spell_service_api.clone();
@@ -325,6 +628,7 @@ impl Sorcerer {
                         spell_service_api,
                         workers,
                         scopes,
+                        trigger_presets,
                     )
                     .await,
                 )
@@ -333,109 +637,656 @@ impl Sorcerer {
         }))
     }
 
-    fn make_get_spell_id_closure(&self) -> ServiceFunction {
-        ServiceFunction::Immut(Box::new(move |_, params| {
-            async move { wrap(get_spell_id(params)) }.boxed()
-        }))
-    }
-
-    fn make_get_relay_closure(&self) -> ServiceFunction {
-        let relay_peer_id = self.scopes.get_host_peer_id().to_base58();
-        ServiceFunction::Immut(Box::new(move |_, _| {
-            let relay = relay_peer_id.clone();
-            async move { wrap(Ok(Value::String(relay))) }.boxed()
-        }))
-    }
-
-    fn make_get_spell_arg_closure(&self) -> ServiceFunction {
+    fn make_spell_set_resource_triggers_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
         let spell_service_api = self.spell_service_api.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
             let spell_service_api = spell_service_api.clone();
-            async move { wrap(get_spell_arg(args, params, spell_service_api).await) }.boxed()
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_resource_triggers(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
         }))
     }
 
-    fn make_error_handler_closure(&self) -> ServiceFunction {
+    fn make_spell_set_probe_triggers_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
         let spell_service_api = self.spell_service_api.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
             let spell_service_api = spell_service_api.clone();
-            async move { wrap_unit(store_error(args, params, spell_service_api).await) }.boxed()
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_probe_triggers(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
         }))
     }
 
-    fn make_response_handler_closure(&self) -> ServiceFunction {
+    fn make_spell_set_exclusion_windows_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
         let spell_service_api = self.spell_service_api.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
             let spell_service_api = spell_service_api.clone();
-            async move { wrap_unit(store_response(args, params, spell_service_api).await) }.boxed()
-        }))
-    }
-
-    fn make_worker_create_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
-        let scopes = self.scopes.clone();
-        ServiceFunction::Immut(Box::new(move |args, params| {
             let workers = workers.clone();
-            let scopes = scopes.clone();
+            let scopes = scope.clone();
             async move {
-                let res: Result<Value, JError> = create_worker(args, params, scopes, workers).await;
-                wrap(res)
+                wrap_unit(
+                    spell_set_exclusion_windows(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
             }
             .boxed()
         }))
     }
 
-    fn make_worker_get_worker_id_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
-        ServiceFunction::Immut(Box::new(move |args, _| {
-            let workers = workers.clone();
-            async move { wrap(get_worker_peer_id(args, workers)) }.boxed()
-        }))
-    }
-
-    fn make_worker_list_closure(&self) -> ServiceFunction {
-        let workers = self.workers.clone();
-        ServiceFunction::Immut(Box::new(move |_, _| {
-            let workers = workers.clone();
-            async move { wrap(worker_list(workers)) }.boxed()
-        }))
-    }
-
-    fn make_worker_remove_closure(&self) -> ServiceFunction {
-        let services = self.services.clone();
-        let storage = self.spell_storage.clone();
+    fn make_spell_pause_closure(&self) -> ServiceFunction {
         let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
         let workers = self.workers.clone();
-        let scopes = self.scopes.clone();
-
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let storage = storage.clone();
+            let spell_event_bus_api = spell_event_bus_api.clone();
             let services = services.clone();
-            let api = spell_event_bus_api.clone();
+            let spell_service_api = spell_service_api.clone();
             let workers = workers.clone();
-            let scopes = scopes.clone();
+            let scopes = scope.clone();
             async move {
-                let res =
-                    remove_worker(args, params, workers, services, storage, api, scopes).await;
-                wrap_unit(res)
+                wrap_unit(
+                    spell_pause(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
             }
             .boxed()
         }))
     }
 
-    fn make_activate_deal_closure(&self) -> ServiceFunction {
+    fn make_spell_resume_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
         let workers = self.workers.clone();
         let scope = self.scopes.clone();
-        let services = self.services.clone();
-        let spell_event_bus_api = self.spell_event_bus_api.clone();
-        let spells_api = self.spell_service_api.clone();
-        let worker_period_sec = self.worker_period_sec;
+        let spell_service_api = self.spell_service_api.clone();
         ServiceFunction::Immut(Box::new(move |args, params| {
-            let services = services.clone();
             let spell_event_bus_api = spell_event_bus_api.clone();
-            let spells_api = spells_api.clone();
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_resume(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_peer_payload_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_peer_payload(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_health_triggers_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_health_triggers(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_partition_triggers_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_partition_triggers(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_custom_triggers_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_custom_triggers(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_kv_triggers_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_kv_triggers(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_webhook_closure(&self) -> ServiceFunction {
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_webhook(
+                        args,
+                        params,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_sinks_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        let sinks = self.sinks.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            let sinks = sinks.clone();
+            async move {
+                wrap_unit(
+                    spell_set_sinks(
+                        args,
+                        params,
+                        services,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                        sinks,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_set_missed_runs_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scope.clone();
+            async move {
+                wrap_unit(
+                    spell_set_missed_runs(
+                        args,
+                        params,
+                        services,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_kv_set_if_equals_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move {
+                wrap(
+                    spell_kv_set_if_equals(
+                        args,
+                        params,
+                        services,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_kv_incr_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move {
+                wrap(
+                    spell_kv_incr(args, params, services, spell_service_api, workers, scopes).await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_update_script_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move {
+                wrap_unit(
+                    spell_update_script(args, params, services, spell_service_api, workers, scopes)
+                        .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_package_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let spell_service_api = spell_service_api.clone();
+            let scopes = scopes.clone();
+            async move {
+                wrap(spell_package(args, params, services, spell_service_api, scopes).await)
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_spell_import_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let storage = self.spell_storage.clone();
+        let spell_event_bus = self.spell_event_bus_api.clone();
+        let workers = self.workers.clone();
+        let spell_service_api = self.spell_service_api.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let storage = storage.clone();
+            let services = services.clone();
+            let spell_event_bus_api = spell_event_bus.clone();
+            let spell_service_api = spell_service_api.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move {
+                wrap(
+                    spell_import(
+                        args,
+                        params,
+                        storage,
+                        services,
+                        spell_event_bus_api,
+                        spell_service_api,
+                        workers,
+                        scopes,
+                    )
+                    .await,
+                )
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_get_spell_id_closure(&self) -> ServiceFunction {
+        ServiceFunction::Immut(Box::new(move |_, params| {
+            async move { wrap(get_spell_id(params)) }.boxed()
+        }))
+    }
+
+    fn make_get_relay_closure(&self) -> ServiceFunction {
+        let relay_peer_id = self.scopes.get_host_peer_id().to_base58();
+        ServiceFunction::Immut(Box::new(move |_, _| {
+            let relay = relay_peer_id.clone();
+            async move { wrap(Ok(Value::String(relay))) }.boxed()
+        }))
+    }
+
+    fn make_get_spell_arg_closure(&self) -> ServiceFunction {
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_service_api = spell_service_api.clone();
+            async move { wrap(get_spell_arg(args, params, spell_service_api).await) }.boxed()
+        }))
+    }
+
+    fn make_error_handler_closure(&self) -> ServiceFunction {
+        let spell_service_api = self.spell_service_api.clone();
+        let quarantine = self.quarantine.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_service_api = spell_service_api.clone();
+            let quarantine = quarantine.clone();
+            async move { wrap_unit(store_error(args, params, spell_service_api, quarantine).await) }
+                .boxed()
+        }))
+    }
+
+    fn make_response_handler_closure(&self) -> ServiceFunction {
+        let spell_service_api = self.spell_service_api.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let spell_service_api = spell_service_api.clone();
+            async move { wrap_unit(store_response(args, params, spell_service_api).await) }.boxed()
+        }))
+    }
+
+    fn make_worker_create_closure(&self) -> ServiceFunction {
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        let revoked_keys = self.revoked_keys.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            let revoked_keys = revoked_keys.clone();
+            async move {
+                let res: Result<Value, JError> =
+                    create_worker(args, params, scopes, workers, revoked_keys).await;
+                wrap(res)
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_worker_get_worker_id_closure(&self) -> ServiceFunction {
+        let workers = self.workers.clone();
+        ServiceFunction::Immut(Box::new(move |args, _| {
+            let workers = workers.clone();
+            async move { wrap(get_worker_peer_id(args, workers)) }.boxed()
+        }))
+    }
+
+    fn make_sched_submit_closure(&self) -> ServiceFunction {
+        let scheduler = self.scheduler.clone();
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let scheduler = scheduler.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move { wrap(sched_submit(args, params, scheduler, workers, scopes).await) }
+                .boxed()
+        }))
+    }
+
+    fn make_sched_cancel_closure(&self) -> ServiceFunction {
+        let scheduler = self.scheduler.clone();
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let scheduler = scheduler.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move { wrap_unit(sched_cancel(args, params, scheduler, workers, scopes).await) }
+                .boxed()
+        }))
+    }
+
+    fn make_sched_list_closure(&self) -> ServiceFunction {
+        let scheduler = self.scheduler.clone();
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |_, params| {
+            let scheduler = scheduler.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move { wrap(sched_list(params, scheduler, workers, scopes)) }.boxed()
+        }))
+    }
+
+    fn make_worker_list_closure(&self) -> ServiceFunction {
+        let workers = self.workers.clone();
+        ServiceFunction::Immut(Box::new(move |_, _| {
+            let workers = workers.clone();
+            async move { wrap(worker_list(workers)) }.boxed()
+        }))
+    }
+
+    fn make_worker_remove_closure(&self) -> ServiceFunction {
+        let services = self.services.clone();
+        let storage = self.spell_storage.clone();
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let storage = storage.clone();
+            let services = services.clone();
+            let api = spell_event_bus_api.clone();
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move {
+                let res =
+                    remove_worker(args, params, workers, services, storage, api, scopes).await;
+                wrap_unit(res)
+            }
+            .boxed()
+        }))
+    }
+
+    fn make_activate_deal_closure(&self) -> ServiceFunction {
+        let workers = self.workers.clone();
+        let scope = self.scopes.clone();
+        let services = self.services.clone();
+        let spell_event_bus_api = self.spell_event_bus_api.clone();
+        let spells_api = self.spell_service_api.clone();
+        let worker_period_sec = self.worker_period_sec;
+        let revoked_keys = self.revoked_keys.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let services = services.clone();
+            let spell_event_bus_api = spell_event_bus_api.clone();
+            let spells_api = spells_api.clone();
             let workers = workers.clone();
             let scope = scope.clone();
+            let revoked_keys = revoked_keys.clone();
 
             async move {
                 let res = activate_deal(
@@ -447,6 +1298,7 @@ impl Sorcerer {
                     spell_event_bus_api,
                     spells_api,
                     worker_period_sec,
+                    revoked_keys,
                 )
                 .await;
                 wrap_unit(res)
@@ -486,6 +1338,44 @@ impl Sorcerer {
         }))
     }
 
+    fn make_key_derivation_paths_closure(&self) -> ServiceFunction {
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        ServiceFunction::Immut(Box::new(move |_, params| {
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            async move { wrap(key_derivation_paths(params, scopes, workers)) }.boxed()
+        }))
+    }
+
+    fn make_revoked_workers_closure(&self) -> ServiceFunction {
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        let revoked_keys = self.revoked_keys.clone();
+        ServiceFunction::Immut(Box::new(move |_, params| {
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            let revoked_keys = revoked_keys.clone();
+            async move { wrap(revoked_workers(params, scopes, workers, revoked_keys)) }.boxed()
+        }))
+    }
+
+    fn make_transfer_worker_closure(&self) -> ServiceFunction {
+        let workers = self.workers.clone();
+        let scopes = self.scopes.clone();
+        let revoked_keys = self.revoked_keys.clone();
+        ServiceFunction::Immut(Box::new(move |args, params| {
+            let workers = workers.clone();
+            let scopes = scopes.clone();
+            let revoked_keys = revoked_keys.clone();
+            async move {
+                let res = transfer_worker(args, params, scopes, workers, revoked_keys).await;
+                wrap_unit(res)
+            }
+            .boxed()
+        }))
+    }
+
     fn make_is_deal_active_closure(&self) -> ServiceFunction {
         let workers = self.workers.clone();
         ServiceFunction::Immut(Box::new(move |args, _| {
@@ -17,21 +17,52 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 use serde_json::{json, Value as JValue, Value, Value::Array};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+use crate::quarantine::SpellQuarantine;
+use crate::receipts::ReceiptLog;
+use crate::sink_routes::{check_sink_routes, SinkRoute, SINK_ROUTES_KEY};
+use crate::spell_library::{find_builtin_spell, BuiltinSpellConfig, BUILTIN_SPELLS};
+use crate::spell_migration::{self, MigrationStep, SCHEMA_VERSION_KEY};
+use crate::stored_triggers::StoredTriggers;
+use crate::trigger_presets::TriggerPresets;
 use crate::utils::parse_spell_id_from;
 use fluence_spell_dtos::trigger_config::TriggerConfig;
 use libp2p::PeerId;
-use particle_args::{Args, JError};
+use particle_args::{Args, ErrorCode, ErrorCoded, JError};
 use particle_execution::ParticleParams;
-use particle_services::{ParticleAppServices, PeerScope, ServiceType};
-use spell_event_bus::api::EventBusError;
+use particle_modules::PACKAGE_FORMAT_VERSION;
+use particle_services::{
+    parse_labels, LabelSelector, Labels, ParticleAppServices, PeerScope, RunAs, ServiceError,
+    ServiceType,
+};
+use spell_event_bus::api::{
+    CronSpec, CustomEventType, EventBusError, ExclusionWindow, HealthEventType, KvWatch,
+    MissedRunPolicy, PartitionEventType, PeerPayloadTemplate, ProbeEventType, ResourceEventType,
+    SpellTriggerConfigs,
+};
 use spell_event_bus::{api, api::SpellEventBusApi};
 use spell_service_api::{CallParams, SpellServiceApi};
 use spell_storage::SpellStorage;
 use std::time::Duration;
 use workers::{PeerScopes, Workers};
 
+/// KV key with the policy for the timer runs missed while the node was down
+const MISSED_RUNS_POLICY_KEY: &str = "hw_missed_runs_policy";
+/// KV key with the unix time in seconds of the last timer run of the spell
+pub(crate) const LAST_FIRED_KEY: &str = "hw_last_fired";
+/// KV key with the cron schedule of the spell, the trigger config of the spell service has no place for it
+const CRON_KEY: &str = "hw_cron";
+
+/// Service errors of spell builtins, where a missing service means a missing spell
+fn spell_error(err: ServiceError) -> JError {
+    match err.error_code() {
+        ErrorCode::ServiceNotFound => JError::with_code(ErrorCode::SpellNotFound, err.to_string()),
+        _ => JError::coded(err),
+    }
+}
+
 pub async fn remove_spell(
     particle_id: &str,
     spell_storage: &SpellStorage,
@@ -45,7 +76,7 @@ pub async fn remove_spell(
         log::warn!(
             "can't unsubscribe a spell {spell_id} from its triggers via spell-event-bus-api: {err}"
         );
-        return Err(JError::new(format!(
+        return Err(JError::with_code(ErrorCode::Internal, format!(
             "can't remove a spell {spell_id} due to an internal error while unsubscribing from the triggers: {err}"
         )));
     }
@@ -53,7 +84,8 @@ pub async fn remove_spell(
     spell_storage.unregister_spell(peer_scope, spell_id);
     services
         .remove_service(peer_scope, particle_id, spell_id, init_peer_id, true)
-        .await?;
+        .await
+        .map_err(spell_error)?;
     Ok(())
 }
 
@@ -67,20 +99,25 @@ pub async fn install_spell(
     particle_id: String,
     ttl: Duration,
     user_config: TriggerConfig,
+    cron: Option<CronSpec>,
     script: String,
     init_data: Value,
     owner_id: PeerId,
+    labels: Labels,
+    run_as: Option<RunAs>,
 ) -> Result<String, JError> {
-    let config = api::from_user_config(&user_config)?;
+    let config = api::from_user_config(&user_config, cron.as_ref())?;
 
     let spell_id = services
-        .create_service(
+        .create_spell_service(
             peer_scope,
-            ServiceType::Spell,
             spell_storage.get_blueprint(),
             owner_id,
+            labels,
+            run_as,
         )
-        .await?;
+        .await
+        .map_err(JError::coded)?;
     spell_storage.register_spell(peer_scope, spell_id.clone());
 
     let params = CallParams::local(peer_scope, spell_id.clone(), owner_id, ttl);
@@ -95,13 +132,23 @@ pub async fn install_spell(
         Some(self_particle_id),
         ttl,
     );
-    spell_service_api
+    if let Err(err) = spell_service_api
         .update_kv(self_params.clone(), init_data)
-        .await?;
+        .await
+    {
+        log::warn!(
+            "can't save init_data of a spell {spell_id}: {err}. Removing created spell service..."
+        );
+
+        spell_storage.unregister_spell(peer_scope, &spell_id);
+        services
+            .remove_service(peer_scope, &particle_id, &spell_id, owner_id, true)
+            .await?;
+
+        return Err(JError::coded(err));
+    }
     // Save trigger config
-    spell_service_api
-        .set_trigger_config(params, user_config)
-        .await?;
+    store_trigger_config(spell_service_api, params, user_config, cron.as_ref()).await?;
 
     if let Some(config) = config {
         // Scheduling the spell
@@ -116,7 +163,7 @@ pub async fn install_spell(
                 .remove_service(peer_scope, &particle_id, &spell_id, owner_id, true)
                 .await?;
 
-            return Err(JError::new(format!(
+            return Err(JError::with_code(ErrorCode::Internal, format!(
                 "can't install a spell due to an internal error while subscribing to the triggers: {err}"
             )));
         }
@@ -148,18 +195,26 @@ pub async fn get_spell_info(
     let trigger_config = spell_service_api
         .get_trigger_config(params.clone())
         .await
-        .map_err(|e| JError::new(f!("Failed to get trigger_config for spell {spell_id}: {e}")))?;
+        .map_err(|e| {
+            JError::with_code(
+                ErrorCode::Internal,
+                f!("Failed to get trigger_config for spell {spell_id}: {e}"),
+            )
+        })?;
 
-    let script = spell_service_api
-        .get_script(params)
-        .await
-        .map_err(|e| JError::new(f!("Failed to get trigger_config for spell {spell_id}: {e}")))?;
+    let script = spell_service_api.get_script(params).await.map_err(|e| {
+        JError::with_code(
+            ErrorCode::Internal,
+            f!("Failed to get trigger_config for spell {spell_id}: {e}"),
+        )
+    })?;
     Ok(SpellInfo {
         script,
         trigger_config,
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn spell_install(
     sargs: Args,
     params: ParticleParams,
@@ -169,13 +224,65 @@ pub(crate) async fn spell_install(
     spell_service_api: SpellServiceApi,
     workers: Arc<Workers>,
     scopes: PeerScopes,
+    trigger_presets: TriggerPresets,
 ) -> Result<JValue, JError> {
     let mut args = sargs.function_args.clone().into_iter();
     let script: String = Args::next("script", &mut args)?;
     let init_data: JValue = Args::next("data", &mut args)?;
-    let trigger_config: TriggerConfig = Args::next("trigger_config", &mut args)?;
+    // either a trigger config or a name of a preset from the node config
+    let trigger_config: JValue = Args::next("trigger_config", &mut args)?;
+    let (trigger_config, cron) = resolve_user_config(&trigger_presets, trigger_config)?;
     let alias: Option<String> = Args::next_opt("alias", &mut args)?;
+    let labels: Option<String> = Args::next_opt("labels", &mut args)?;
+    let labels = labels.as_deref().map(parse_labels).transpose()?;
+    // restricted identity the spell calls services under
+    let run_as: Option<RunAs> = Args::next_opt("run_as", &mut args)?;
+    if let Some(run_as) = &run_as {
+        run_as
+            .validate()
+            .map_err(|err| JError::with_code(ErrorCode::InvalidArgument, err.to_string()))?;
+    }
+
+    let spell_id = install_spell_for_caller(
+        &params,
+        &spell_storage,
+        &services,
+        &spell_event_bus_api,
+        &spell_service_api,
+        &workers,
+        &scopes,
+        trigger_config,
+        cron,
+        script,
+        init_data,
+        alias.into_iter().collect(),
+        labels.unwrap_or_default(),
+        run_as,
+    )
+    .await?;
+
+    Ok(JValue::String(spell_id))
+}
 
+/// Installs a spell on behalf of the particle sender, then adds aliases to it.
+/// The spell is removed if any of the aliases can't be added.
+#[allow(clippy::too_many_arguments)]
+async fn install_spell_for_caller(
+    params: &ParticleParams,
+    spell_storage: &SpellStorage,
+    services: &ParticleAppServices,
+    spell_event_bus_api: &SpellEventBusApi,
+    spell_service_api: &SpellServiceApi,
+    workers: &Workers,
+    scopes: &PeerScopes,
+    trigger_config: TriggerConfig,
+    cron: Option<CronSpec>,
+    script: String,
+    init_data: JValue,
+    aliases: Vec<String>,
+    labels: Labels,
+    run_as: Option<RunAs>,
+) -> Result<String, JError> {
     let init_peer_id = params.init_peer_id;
 
     let is_management = scopes.is_management(init_peer_id);
@@ -186,34 +293,37 @@ pub(crate) async fn spell_install(
             let worker_creator = workers.get_worker_creator(worker_id)?;
             let is_worker_creator = init_peer_id == worker_creator;
             if !is_management && !is_worker && !is_worker_creator {
-                return Err(JError::new(format!("Failed to install spell on {worker_id:?}, spell can be installed by worker creator {worker_creator}, worker itself {worker_id} or peer manager; init_peer_id={init_peer_id}")));
+                return Err(JError::with_code(ErrorCode::PermissionDenied, format!("Failed to install spell on {worker_id:?}, spell can be installed by worker creator {worker_creator}, worker itself {worker_id} or peer manager; init_peer_id={init_peer_id}")));
             }
             worker_id.into()
         }
         PeerScope::Host => {
             if !is_management {
-                return Err(JError::new("Failed to install spell in the root scope, only management peer id can install top-level spells"));
+                return Err(JError::with_code(ErrorCode::PermissionDenied, "Failed to install spell in the root scope, only management peer id can install top-level spells"));
             }
             scopes.get_host_peer_id()
         }
     };
 
     let spell_id = install_spell(
-        &services,
-        &spell_storage,
-        &spell_event_bus_api,
-        &spell_service_api,
+        services,
+        spell_storage,
+        spell_event_bus_api,
+        spell_service_api,
         params.peer_scope,
         params.id.clone(),
         Duration::from_millis(params.ttl as u64),
         trigger_config,
+        cron,
         script,
         init_data,
         owner_id,
+        labels,
+        run_as,
     )
     .await?;
 
-    if let Some(alias) = alias {
+    for alias in aliases {
         if let Err(e) = services
             .add_alias(
                 params.peer_scope,
@@ -226,38 +336,141 @@ pub(crate) async fn spell_install(
             // Remove the spell if we failed to add an alias
             remove_spell(
                 &params.id,
-                &spell_storage,
-                &services,
-                &spell_event_bus_api,
+                spell_storage,
+                services,
+                spell_event_bus_api,
                 &spell_id,
                 params.peer_scope,
                 owner_id,
             )
             .await?;
 
-            return Err(JError::new(format!(
-                "Failed to add alias {} for spell {}: {:?}",
-                alias, spell_id, e
-            )));
+            return Err(JError::with_code(
+                ErrorCode::FailedPrecondition,
+                format!(
+                    "Failed to add alias {} for spell {}: {:?}",
+                    alias, spell_id, e
+                ),
+            ));
         }
     }
 
-    Ok(JValue::String(spell_id))
+    Ok(spell_id)
 }
 
-pub(crate) fn spell_list(
+/// spell.install_builtin(name, config?)
+/// Installs a spell shipped with the node. Config may override `trigger_config`,
+/// extend the default init `data`, and set `alias` and `labels`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn spell_install_builtin(
+    sargs: Args,
     params: ParticleParams,
     spell_storage: SpellStorage,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+    trigger_presets: TriggerPresets,
 ) -> Result<JValue, JError> {
-    Ok(Array(
-        spell_storage
-            .get_registered_spells_by(params.peer_scope)
-            .into_iter()
-            .map(JValue::String)
-            .collect(),
-    ))
+    let mut args = sargs.function_args.clone().into_iter();
+    let name: String = Args::next("name", &mut args)?;
+    let config: Option<BuiltinSpellConfig> = Args::next_opt("config", &mut args)?;
+    let config = config.unwrap_or_default();
+
+    let spell = find_builtin_spell(&name).ok_or_else(|| {
+        JError::with_code(
+            ErrorCode::NotFound,
+            format!("Builtin spell '{name}' not found, see spell.list_builtin"),
+        )
+    })?;
+
+    let install_args = Args {
+        function_args: vec![
+            json!(spell.script),
+            spell.init_data(&config)?,
+            json!(spell.trigger_config(&config)),
+            json!(config.alias.into_iter().collect::<Vec<_>>()),
+            json!(config.labels.into_iter().collect::<Vec<_>>()),
+        ],
+        ..sargs
+    };
+    spell_install(
+        install_args,
+        params,
+        spell_storage,
+        services,
+        spell_event_bus_api,
+        spell_service_api,
+        workers,
+        scopes,
+        trigger_presets,
+    )
+    .await
+}
+
+/// spell.list_builtin() -> names of the spells shipped with the node
+pub(crate) fn spell_list_builtin() -> JValue {
+    json!(BUILTIN_SPELLS
+        .iter()
+        .map(|spell| spell.name)
+        .collect::<Vec<_>>())
+}
+
+/// spell.receipts(spell_id?, limit?)
+/// Newest first receipts of spell runs on the current peer scope, at most `limit` (100 by default)
+pub(crate) fn spell_receipts(
+    args: Args,
+    params: ParticleParams,
+    receipts: ReceiptLog,
+    scopes: PeerScopes,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id: Option<String> = Args::next_opt("spell_id", &mut args)?;
+    let limit: Option<usize> = Args::next_opt("limit", &mut args)?;
+
+    let signer = scopes.to_peer_id(params.peer_scope).to_base58();
+    let receipts = receipts.list(&signer, spell_id.as_deref(), limit.unwrap_or(100));
+    Ok(json!(receipts))
 }
 
+/// spell.list(selector?)
+/// Optional selector filters spells by labels, e.g. "app=indexer,env=prod"
+pub(crate) async fn spell_list(
+    args: Args,
+    params: ParticleParams,
+    spell_storage: SpellStorage,
+    services: ParticleAppServices,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let selector: Option<String> = Args::next_opt("selector", &mut args)?;
+    let spells = spell_storage.get_registered_spells_by(params.peer_scope);
+
+    let spells = match selector {
+        None => spells,
+        Some(selector) => {
+            let selector = LabelSelector::parse(&selector)?;
+            let labels: HashMap<String, Labels> = services
+                .list_services(params.peer_scope)
+                .await
+                .into_iter()
+                .map(|info| (info.id, info.labels))
+                .collect();
+            spells
+                .into_iter()
+                .filter(|spell_id| {
+                    labels
+                        .get(spell_id)
+                        .map_or(false, |labels| selector.matches(labels))
+                })
+                .collect()
+        }
+    };
+
+    Ok(Array(spells.into_iter().map(JValue::String).collect()))
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn spell_remove(
     args: Args,
     params: ParticleParams,
@@ -266,6 +479,7 @@ pub(crate) async fn spell_remove(
     spell_event_bus_api: SpellEventBusApi,
     workers: Arc<Workers>,
     scopes: PeerScopes,
+    quarantine: SpellQuarantine,
 ) -> Result<(), JError> {
     let mut args = args.function_args.into_iter();
     let spell_id: String = Args::next("spell_id", &mut args)?;
@@ -280,7 +494,7 @@ pub(crate) async fn spell_remove(
             let is_worker = init_peer_id == worker_id.into();
             let is_management = scopes.is_management(init_peer_id);
             if !is_worker_creator && !is_worker && !is_management {
-                return Err(JError::new(format!(
+                return Err(JError::with_code(ErrorCode::PermissionDenied, format!(
                     "Failed to remove spell {spell_id}, spell can be removed by worker creator {worker_creator}, worker itself {worker_id} or peer manager"
                 )));
             }
@@ -291,7 +505,7 @@ pub(crate) async fn spell_remove(
             let is_host = init_peer_id == host_peer_id;
             let is_management = scopes.is_management(init_peer_id);
             if !is_host && !is_management {
-                return Err(JError::new(format!(
+                return Err(JError::with_code(ErrorCode::PermissionDenied, format!(
                     "Failed to remove spell {spell_id}, worker itself {host_peer_id} or peer manager"
                 )));
             }
@@ -301,7 +515,8 @@ pub(crate) async fn spell_remove(
 
     let spell_id = services
         .to_service_id(params.peer_scope, spell_id, &params.id)
-        .await?;
+        .await
+        .map_err(spell_error)?;
 
     remove_spell(
         &params.id,
@@ -312,9 +527,29 @@ pub(crate) async fn spell_remove(
         peer_scope,
         owner_peer_id,
     )
-    .await
+    .await?;
+    quarantine.remove(&spell_id);
+    Ok(())
+}
+
+/// spell.get_status(spell_id)
+/// Whether the spell is quarantined after failing in a row, and how much its timer is backed off
+pub(crate) async fn spell_get_status(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    quarantine: SpellQuarantine,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let spell_id = services
+        .to_service_id(params.peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    Ok(json!(quarantine.status(&spell_id)))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn spell_update_config(
     args: Args,
     params: ParticleParams,
@@ -323,43 +558,22 @@ pub(crate) async fn spell_update_config(
     spell_service_api: SpellServiceApi,
     workers: Arc<Workers>,
     scopes: PeerScopes,
+    trigger_presets: TriggerPresets,
 ) -> Result<(), JError> {
     let mut args = args.function_args.into_iter();
     let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
 
     let peer_scope = params.peer_scope;
-    let init_peer_id = params.init_peer_id;
-
-    match peer_scope {
-        PeerScope::WorkerId(worker_id) => {
-            let worker_creator = workers.get_worker_creator(worker_id)?;
-            let is_worker_creator = init_peer_id == worker_creator;
-            let is_worker = init_peer_id == worker_id.into();
-            let is_management = scopes.is_management(init_peer_id);
-            if !is_worker_creator && !is_worker && !is_management {
-                return Err(JError::new(format!(
-                    "Failed to update spell config {spell_id_or_alias}, spell config can be updated by worker creator {worker_creator}, worker itself {worker_id} or peer manager; init_peer_id={init_peer_id}"
-                )));
-            }
-        }
-        PeerScope::Host => {
-            let host_peer_id = scopes.get_host_peer_id();
-            let is_host = init_peer_id == host_peer_id;
-            let is_management = scopes.is_management(init_peer_id);
-            if !is_host && !is_management {
-                return Err(JError::new(format!(
-                    "Failed to update spell config {spell_id_or_alias}, spell config can be updated by worker itself {host_peer_id} or peer manager; init_peer_id={init_peer_id}"
-                )));
-            }
-        }
-    }
-
     let spell_id = services
-        .to_service_id(peer_scope, spell_id_or_alias.clone(), &params.id)
-        .await?;
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
 
-    let user_config: TriggerConfig = Args::next("config", &mut args)?;
-    let config = api::from_user_config(&user_config)?;
+    let user_config: JValue = Args::next("config", &mut args)?;
+    let (user_config, cron) = resolve_user_config(&trigger_presets, user_config)?;
+    // Validate the config before storing it
+    api::from_user_config(&user_config, cron.as_ref())?;
     let init_peer_id = scopes.to_peer_id(peer_scope);
     let params = CallParams::local(
         peer_scope,
@@ -367,76 +581,1204 @@ pub(crate) async fn spell_update_config(
         init_peer_id,
         Duration::from_millis(params.ttl as u64),
     );
-    spell_service_api
-        .set_trigger_config(params, user_config)
-        .await?;
+    store_trigger_config(
+        &spell_service_api,
+        params.clone(),
+        user_config,
+        cron.as_ref(),
+    )
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
 
-    let result: Result<(), EventBusError> = try {
-        // we unsubscribe the spell from the current config anyway
-        spell_event_bus_api.unsubscribe(spell_id.clone()).await?;
-        if let Some(config) = config {
-            // and if the config isn't empty, we subscribe it to the new one
-            spell_event_bus_api
-                .subscribe(spell_id.clone(), config.clone())
-                .await?;
+/// spell.update_script(spell_id, script, schema_version?, migration?)
+/// Replace the spell script. When `schema_version` is newer than the stored one,
+/// the `migration` steps are applied to the spell KV first; the migration runs once,
+/// updating to the stored version again skips it. If the migration or the update fails,
+/// the KV, the script and the version are rolled back.
+pub(crate) async fn spell_update_script(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let script: String = Args::next("script", &mut args)?;
+    let schema_version: Option<u32> = Args::next_opt("schema_version", &mut args)?;
+    let migration: Option<Vec<MigrationStep>> = Args::next_opt("migration", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias.clone(), &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+
+    let current_version = spell_service_api
+        .get_u32(params.clone(), SCHEMA_VERSION_KEY.to_string())
+        .await?
+        .unwrap_or(0);
+    let target_version = match schema_version {
+        None if migration.is_some() => {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                "A migration requires the schema version it migrates to",
+            ))
+        }
+        None => current_version,
+        Some(version) if version < current_version => {
+            return Err(JError::with_code(
+                ErrorCode::FailedPrecondition,
+                format!("Spell {spell_id_or_alias} KV schema is at version {current_version}, can't downgrade to {version}"),
+            ))
         }
+        Some(version) => version,
     };
+    if target_version == current_version {
+        spell_service_api.set_script(params, script).await?;
+        return Ok(());
+    }
 
+    let old_script = spell_service_api.get_script(params.clone()).await?;
+    let undo = spell_migration::migrate(
+        &spell_service_api,
+        params.clone(),
+        &migration.unwrap_or_default(),
+    )
+    .await?;
+    let result: Result<(), JError> = try {
+        spell_service_api
+            .set_u32(
+                params.clone(),
+                SCHEMA_VERSION_KEY.to_string(),
+                target_version,
+            )
+            .await?;
+        spell_service_api.set_script(params.clone(), script).await?;
+    };
     if let Err(err) = result {
-        log::warn!(
-            "can't update a spell {spell_id_or_alias} config via spell-event-bus-api: {err}"
-        );
-        return Err(JError::new(format!(
-            "can't update a spell {spell_id_or_alias} config due to an internal error while updating the triggers: {err}"
-        )));
+        log::warn!("Failed to update spell {spell_id} script, rolling back the migration: {err}");
+        spell_migration::rollback(&spell_service_api, params.clone(), &undo).await;
+        let restored: Result<(), JError> = try {
+            spell_service_api
+                .set_u32(
+                    params.clone(),
+                    SCHEMA_VERSION_KEY.to_string(),
+                    current_version,
+                )
+                .await?;
+            spell_service_api.set_script(params, old_script).await?;
+        };
+        if let Err(restore_err) = restored {
+            log::error!(
+                "Failed to restore spell {spell_id} script and schema version: {restore_err}"
+            );
+        }
+        return Err(err);
     }
 
     Ok(())
 }
 
-pub(crate) fn get_spell_id(params: ParticleParams) -> Result<JValue, JError> {
-    Ok(json!(parse_spell_id_from(&params)?))
+/// Spell packed by spell.package, pinned by the script hash and the spell blueprint id
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SpellPackage {
+    pub format_version: u32,
+    /// Spell service blueprint the package was made on
+    pub blueprint_id: String,
+    /// blake3 of the script, hex
+    pub script_hash: String,
+    pub script: String,
+    pub trigger_config: TriggerConfig,
+    #[serde(default)]
+    pub cron: Option<CronSpec>,
+    pub schema_version: u32,
+    pub aliases: Vec<String>,
+    pub labels: Labels,
 }
 
-pub(crate) async fn get_spell_arg(
+/// spell.package(spell_id)
+pub(crate) async fn spell_package(
     args: Args,
     params: ParticleParams,
+    services: ParticleAppServices,
     spell_service_api: SpellServiceApi,
+    scopes: PeerScopes,
 ) -> Result<JValue, JError> {
-    let spell_id = parse_spell_id_from(&params)?;
-    let key = args.function_name;
-    let call_params = CallParams::from(spell_id.clone(), params);
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
 
-    let str_value = spell_service_api
-        .get_string(call_params, key.clone())
+    let info = services
+        .get_service_info(params.peer_scope, spell_id_or_alias.clone(), &params.id)
         .await
-        .map_err(|e| JError::new(f!("Failed to get argument {key} for spell {spell_id}: {e}")))
-        .and_then(|value| value.ok_or_else(|| JError::new("value not found")))?;
+        .map_err(spell_error)?;
+    if info.service_type != ServiceType::Spell {
+        return Err(JError::with_code(
+            ErrorCode::SpellNotFound,
+            format!("{spell_id_or_alias} is not a spell, use srv.package instead"),
+        ));
+    }
 
-    serde_json::from_str(&str_value).map_err(|e| {
-        JError::new(f!(
-            "Failed to parse argument `{key} -> {str_value}` for spell {spell_id}: {e}"
-        ))
-    })
+    let call_params = CallParams::local(
+        params.peer_scope,
+        info.id.clone(),
+        scopes.to_peer_id(params.peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    let script = spell_service_api.get_script(call_params.clone()).await?;
+    let (trigger_config, cron) =
+        load_trigger_config(&spell_service_api, call_params.clone()).await?;
+    let schema_version = spell_service_api
+        .get_u32(call_params, SCHEMA_VERSION_KEY.to_string())
+        .await?
+        .unwrap_or(0);
+
+    let package = SpellPackage {
+        format_version: PACKAGE_FORMAT_VERSION,
+        blueprint_id: info.blueprint_id,
+        script_hash: blake3::hash(script.as_bytes()).to_hex().to_string(),
+        script,
+        trigger_config,
+        cron,
+        schema_version,
+        aliases: info.aliases,
+        labels: info.labels,
+    };
+
+    Ok(json!(package))
 }
 
-pub(crate) async fn store_error(
-    mut args: Args,
+/// spell.import(package)
+/// Installs a spell from a package made by spell.package on another node
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn spell_import(
+    args: Args,
     params: ParticleParams,
+    spell_storage: SpellStorage,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
     spell_service_api: SpellServiceApi,
-) -> Result<(), JError> {
-    let spell_id = parse_spell_id_from(&params)?;
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let package: SpellPackage = Args::next("package", &mut args)?;
 
-    args.function_args.push(json!(params.timestamp));
-    let call_params = CallParams::from(spell_id.clone(), params);
-    spell_service_api
-        .store_error(call_params, args.function_args.clone())
-        .await
-        .map_err(|e| {
-            JError::new(format!(
-                "Failed to store error {:?} for spell {}: {}",
-                args.function_args, spell_id, e
-            ))
+    if package.format_version != PACKAGE_FORMAT_VERSION {
+        return Err(JError::with_code(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Unsupported package format version {}",
+                package.format_version
+            ),
+        ));
+    }
+    let script_hash = blake3::hash(package.script.as_bytes()).to_hex().to_string();
+    if script_hash != package.script_hash {
+        return Err(JError::with_code(
+            ErrorCode::InvalidArgument,
+            format!(
+                "Spell script doesn't match its pinned hash: expected {}, got {script_hash}",
+                package.script_hash
+            ),
+        ));
+    }
+    let blueprint_id = spell_storage.get_blueprint();
+    if package.blueprint_id != blueprint_id {
+        return Err(JError::with_code(
+            ErrorCode::FailedPrecondition,
+            format!(
+                "Spell was packaged on spell blueprint {}, but this node runs spells on {blueprint_id}",
+                package.blueprint_id
+            ),
+        ));
+    }
+
+    let spell_id = install_spell_for_caller(
+        &params,
+        &spell_storage,
+        &services,
+        &spell_event_bus_api,
+        &spell_service_api,
+        &workers,
+        &scopes,
+        package.trigger_config,
+        package.cron,
+        package.script,
+        json!({}),
+        package.aliases,
+        package.labels,
+        None,
+    )
+    .await?;
+
+    if package.schema_version > 0 {
+        let owner_id = scopes.to_peer_id(params.peer_scope);
+        let call_params = CallParams::local(
+            params.peer_scope,
+            spell_id.clone(),
+            owner_id,
+            Duration::from_millis(params.ttl as u64),
+        );
+        if let Err(err) = spell_service_api
+            .set_u32(
+                call_params,
+                SCHEMA_VERSION_KEY.to_string(),
+                package.schema_version,
+            )
+            .await
+        {
+            remove_spell(
+                &params.id,
+                &spell_storage,
+                &services,
+                &spell_event_bus_api,
+                &spell_id,
+                params.peer_scope,
+                owner_id,
+            )
+            .await?;
+            return Err(err);
+        }
+    }
+
+    Ok(JValue::String(spell_id))
+}
+
+/// Spell config can be updated by the worker creator, the worker itself (and so its spells) or peer manager
+fn check_config_permissions(
+    spell_id_or_alias: &str,
+    params: &ParticleParams,
+    workers: &Workers,
+    scopes: &PeerScopes,
+) -> Result<(), JError> {
+    let init_peer_id = params.init_peer_id;
+    match params.peer_scope {
+        PeerScope::WorkerId(worker_id) => {
+            let worker_creator = workers.get_worker_creator(worker_id)?;
+            let is_worker_creator = init_peer_id == worker_creator;
+            let is_worker = init_peer_id == worker_id.into();
+            let is_management = scopes.is_management(init_peer_id);
+            if !is_worker_creator && !is_worker && !is_management {
+                return Err(JError::with_code(ErrorCode::PermissionDenied, format!(
+                    "Failed to update spell config {spell_id_or_alias}, spell config can be updated by worker creator {worker_creator}, worker itself {worker_id} or peer manager; init_peer_id={init_peer_id}"
+                )));
+            }
+        }
+        PeerScope::Host => {
+            let host_peer_id = scopes.get_host_peer_id();
+            let is_host = init_peer_id == host_peer_id;
+            let is_management = scopes.is_management(init_peer_id);
+            if !is_host && !is_management {
+                return Err(JError::with_code(ErrorCode::PermissionDenied, format!(
+                    "Failed to update spell config {spell_id_or_alias}, spell config can be updated by worker itself {host_peer_id} or peer manager; init_peer_id={init_peer_id}"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Trigger config from a spell builtin argument, either a config or a preset name.
+/// A config can have a cron schedule next to the fields of the spell service config,
+/// e.g. `{"clock": {..}, "cron": {"schedule": "0 9 * * mon-fri", "timezone": "Europe/Berlin"}}`.
+fn resolve_user_config(
+    trigger_presets: &TriggerPresets,
+    mut value: JValue,
+) -> Result<(TriggerConfig, Option<CronSpec>), JError> {
+    let cron = value
+        .as_object_mut()
+        .and_then(|config| config.remove("cron"))
+        .filter(|cron| !cron.is_null())
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|err| {
+            JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("Invalid cron trigger: {err}"),
+            )
+        })?;
+    Ok((trigger_presets.resolve(value)?, cron))
+}
+
+/// Save the trigger config to the spell service and the cron schedule next to it
+async fn store_trigger_config(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+    config: TriggerConfig,
+    cron: Option<&CronSpec>,
+) -> Result<(), JError> {
+    spell_service_api
+        .set_trigger_config(params.clone(), config)
+        .await?;
+    match cron {
+        Some(cron) => {
+            spell_service_api
+                .set_string(params, CRON_KEY.to_string(), json!(cron).to_string())
+                .await?
+        }
+        None => {
+            spell_service_api
+                .remove_key(params, CRON_KEY.to_string())
+                .await?
+        }
+    }
+    Ok(())
+}
+
+/// Trigger config of the spell and its cron schedule saved by [`store_trigger_config`]
+pub(crate) async fn load_trigger_config(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+) -> Result<(TriggerConfig, Option<CronSpec>), JError> {
+    let config = spell_service_api.get_trigger_config(params.clone()).await?;
+    let cron = spell_service_api
+        .get_string(params, CRON_KEY.to_string())
+        .await?;
+    let cron = cron
+        .map(|cron| serde_json::from_str(&cron))
+        .transpose()
+        .map_err(|err| {
+            JError::with_code(
+                ErrorCode::Internal,
+                format!("Failed to parse {CRON_KEY} of the spell: {err}"),
+            )
+        })?;
+    Ok((config, cron))
+}
+
+/// Subscribe the spell to its trigger config and stored triggers anew after either of them is changed.
+/// A `rescheduled` timer starts from the current time instead of the start time of the config.
+async fn resubscribe(
+    spell_id: &str,
+    params: CallParams,
+    spell_event_bus_api: &SpellEventBusApi,
+    spell_service_api: &SpellServiceApi,
+    rescheduled: bool,
+) -> Result<(), JError> {
+    let (user_config, cron) = load_trigger_config(spell_service_api, params.clone()).await?;
+    let config = api::from_user_config(&user_config, cron.as_ref())?;
+    let config = if rescheduled {
+        config.and_then(|c| c.into_rescheduled())
+    } else {
+        config
+    };
+    let config = StoredTriggers::load(spell_service_api, params)
+        .await?
+        .apply(config)?;
+
+    let result: Result<(), EventBusError> = try {
+        // we unsubscribe the spell from the current config anyway
+        spell_event_bus_api
+            .unsubscribe(spell_id.to_string())
+            .await?;
+        if let Some(config) = config {
+            // and if the config isn't empty, we subscribe it to the new one
+            spell_event_bus_api
+                .subscribe(spell_id.to_string(), config)
+                .await?;
+        }
+    };
+    result.map_err(|err| {
+        log::warn!("can't update a spell {spell_id} config via spell-event-bus-api: {err}");
+        JError::with_code(ErrorCode::Internal, format!(
+            "can't update a spell {spell_id} config due to an internal error while updating the triggers: {err}"
+        ))
+    })
+}
+
+/// spell.pause(spell_id)
+/// Stop triggering the spell. The spell service and its KV stay as is,
+/// so the spell can be resumed later with `spell.resume`.
+pub(crate) async fn spell_pause(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias.clone(), &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params, |triggers| {
+        triggers.paused = true
+    })
+    .await?;
+
+    spell_event_bus_api
+        .unsubscribe(spell_id.clone())
+        .await
+        .map_err(|err| {
+            JError::with_code(ErrorCode::Internal, format!(
+                "can't pause a spell {spell_id_or_alias} due to an internal error while updating the triggers: {err}"
+            ))
+        })
+}
+
+/// spell.resume(spell_id)
+/// Subscribe a paused spell to its triggers again. The timer continues from the current time,
+/// the runs missed while the spell was paused aren't made.
+pub(crate) async fn spell_resume(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    if !StoredTriggers::load(&spell_service_api, params.clone())
+        .await?
+        .paused
+    {
+        return Ok(());
+    }
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.paused = false
+    })
+    .await?;
+
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        true,
+    )
+    .await
+}
+
+/// Continue the timer of the spell from its last run, making the runs missed while the node was down
+/// according to the spell missed runs policy
+pub(crate) async fn apply_missed_runs(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+    config: Option<SpellTriggerConfigs>,
+) -> Result<Option<SpellTriggerConfigs>, JError> {
+    let policy = match spell_service_api
+        .get_string(params.clone(), MISSED_RUNS_POLICY_KEY.to_string())
+        .await?
+    {
+        Some(policy) => serde_json::from_str(&policy).map_err(|e| {
+            JError::with_code(
+                ErrorCode::Internal,
+                format!("Failed to parse {MISSED_RUNS_POLICY_KEY} of the spell: {e}"),
+            )
+        })?,
+        None => MissedRunPolicy::default(),
+    };
+    let last_fired = spell_service_api
+        .get_u32(params, LAST_FIRED_KEY.to_string())
+        .await?;
+    Ok(api::apply_missed_run_policy(
+        config,
+        policy,
+        last_fired.map(u64::from),
+    ))
+}
+
+/// spell.set_peer_payload(spell_id, template)
+/// Set the shape of the trigger written to the mailbox on connection pool events: a JSON string
+/// where `$peer_id`, `$event`, `$direction`, `$protocols` and `$timestamp` are replaced with
+/// the event fields, e.g. `{"peer": "$peer_id", "at": "$timestamp"}`. An empty string restores the default shape.
+pub(crate) async fn spell_set_peer_payload(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let template: String = Args::next("template", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+    // Validate the template before storing it
+    let template = if template.is_empty() {
+        None
+    } else {
+        let value: JValue = serde_json::from_str(&template).map_err(|e| {
+            JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("template must be a JSON string: {e}"),
+            )
+        })?;
+        PeerPayloadTemplate::parse(value.clone())?;
+        Some(value)
+    };
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.peer_payload = template.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_exclusion_windows(spell_id, windows)
+/// Keep the spell from being triggered in weekly UTC windows, e.g. `[{"weekdays": ["sun"], "start": "02:00", "end": "03:00"}]`.
+/// Timer runs falling into a window are skipped, the schedule goes on. An empty list removes the windows.
+pub(crate) async fn spell_set_exclusion_windows(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let windows: Vec<ExclusionWindow> = Args::next("windows", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+    // Validate the windows before storing them
+    api::add_exclusion_windows(None, &windows)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.exclusion_windows = windows.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_probe_triggers(spell_id, events)
+/// Subscribe the spell to the results of must-reach target probes ("unreachable", "reachable")
+/// configured in `peer_probes_config`. An empty list removes the subscription.
+pub(crate) async fn spell_set_probe_triggers(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let events: Vec<ProbeEventType> = Args::next("events", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.probe = events.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_health_triggers(spell_id, events)
+/// Subscribe the spell to health changes of services declaring a health function
+/// ("unhealthy", "healthy", "restarted", "exhausted"). An empty list removes the subscription.
+pub(crate) async fn spell_set_health_triggers(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let events: Vec<HealthEventType> = Args::next("events", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.health = events.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_partition_triggers(spell_id, events)
+/// Subscribe the spell to network partition changes ("partitioned", "healed"), e.g. to pause writes
+/// while the node can't reach most of its long-known peers. An empty list removes the subscription.
+pub(crate) async fn spell_set_partition_triggers(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let events: Vec<PartitionEventType> = Args::next("events", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.partition = events.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_custom_triggers(spell_id, events)
+/// Subscribe the spell to events of the trigger sources added by the embedder of the node,
+/// e.g. `[{"source": "mqtt", "kind": "sensors/temperature"}]`. An empty list removes the subscription.
+pub(crate) async fn spell_set_custom_triggers(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let events: Vec<CustomEventType> = Args::next("events", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+    if let Some(event) = events
+        .iter()
+        .find(|event| !spell_event_bus_api.has_trigger_source(&event.source))
+    {
+        return Err(JError::with_code(
+            ErrorCode::InvalidArgument,
+            format!("unknown trigger source {}", event.source),
+        ));
+    }
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.custom = events.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_resource_triggers(spell_id, events)
+/// Subscribe the spell to node resource pressure events ("disk", "memory") in addition to its trigger config.
+/// An empty list removes the subscription.
+pub(crate) async fn spell_set_resource_triggers(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let events: Vec<ResourceEventType> = Args::next("events", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.resource = events.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_missed_runs(spell_id, policy)
+/// Set what to do with the timer runs the spell misses while the node is down:
+/// "skip" them, run "once" or run "all" of them (bounded). Takes effect on the next node start.
+pub(crate) async fn spell_set_missed_runs(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let policy: MissedRunPolicy = Args::next("policy", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id,
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    spell_service_api
+        .set_string(
+            params,
+            MISSED_RUNS_POLICY_KEY.to_string(),
+            json!(policy).to_string(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Routes values written to the spell KV keys to external sinks, replacing the previous routes
+pub(crate) async fn spell_set_sinks(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+    sinks: Arc<HashSet<String>>,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let routes: Vec<SinkRoute> = Args::next("routes", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+    check_sink_routes(&routes, &sinks)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id,
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    spell_service_api
+        .set_string(
+            params,
+            SINK_ROUTES_KEY.to_string(),
+            json!(routes).to_string(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// spell.set_kv_triggers(spell_id, watches)
+/// Subscribe the spell to changes of KV keys of other spells on the same peer, e.g. `[{"spell_id": "fetcher", "key": "price"}]`.
+/// The spell is triggered only when a written value differs from the previous one, so spells can be chained cheaply.
+/// An empty list removes the subscription.
+pub(crate) async fn spell_set_kv_triggers(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let watches: Vec<KvWatch> = Args::next("watches", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    // Watched spells are resolved in the same scope, so aliases can be used as well
+    let mut resolved = Vec::with_capacity(watches.len());
+    for watch in watches {
+        let watched_id = services
+            .to_service_id(peer_scope, watch.spell_id, &params.id)
+            .await
+            .map_err(spell_error)?;
+        if watched_id == spell_id {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!("Spell {spell_id} can't be triggered by changes of its own KV"),
+            ));
+        }
+        resolved.push(KvWatch {
+            spell_id: watched_id,
+            key: watch.key,
+        });
+    }
+
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.kv = resolved.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// spell.set_webhook(spell_id, token)
+/// Let HTTP requests to `/spells/:spell_id/trigger` with `Authorization: Bearer <token>` trigger the spell,
+/// with the request body as the payload. Only the hash of the token is kept. An empty token removes the webhook.
+pub(crate) async fn spell_set_webhook(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_event_bus_api: SpellEventBusApi,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let token: Option<String> = Args::next_opt("token", &mut args)?;
+    check_config_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let peer_scope = params.peer_scope;
+    let spell_id = services
+        .to_service_id(peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let params = CallParams::local(
+        peer_scope,
+        spell_id.clone(),
+        scopes.to_peer_id(peer_scope),
+        Duration::from_millis(params.ttl as u64),
+    );
+    let token_hash = token
+        .filter(|token| !token.is_empty())
+        .map(|token| api::webhook_token_hash(&token));
+    StoredTriggers::update(&spell_service_api, params.clone(), |triggers| {
+        triggers.webhook_token_hash = token_hash.clone()
+    })
+    .await?;
+    resubscribe(
+        &spell_id,
+        params,
+        &spell_event_bus_api,
+        &spell_service_api,
+        false,
+    )
+    .await
+}
+
+/// Spell KV can be updated by the worker creator, the worker itself (and so its spells) or peer manager
+fn check_kv_permissions(
+    spell_id_or_alias: &str,
+    params: &ParticleParams,
+    workers: &Workers,
+    scopes: &PeerScopes,
+) -> Result<(), JError> {
+    let init_peer_id = params.init_peer_id;
+    match params.peer_scope {
+        PeerScope::WorkerId(worker_id) => {
+            let worker_creator = workers.get_worker_creator(worker_id)?;
+            let is_worker_creator = init_peer_id == worker_creator;
+            let is_worker = init_peer_id == worker_id.into();
+            let is_management = scopes.is_management(init_peer_id);
+            if !is_worker_creator && !is_worker && !is_management {
+                return Err(JError::with_code(ErrorCode::PermissionDenied, format!(
+                    "Failed to update spell KV {spell_id_or_alias}, spell KV can be updated by worker creator {worker_creator}, worker itself {worker_id} or peer manager; init_peer_id={init_peer_id}"
+                )));
+            }
+        }
+        PeerScope::Host => {
+            let host_peer_id = scopes.get_host_peer_id();
+            let is_host = init_peer_id == host_peer_id;
+            let is_management = scopes.is_management(init_peer_id);
+            if !is_host && !is_management {
+                return Err(JError::with_code(ErrorCode::PermissionDenied, format!(
+                    "Failed to update spell KV {spell_id_or_alias}, spell KV can be updated by host {host_peer_id} or peer manager; init_peer_id={init_peer_id}"
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// spell.kv_set_if_equals(spell_id, key, expected, value) -> bool
+/// Set the string `key` to `value` if its current value is `expected`; an empty `expected` means absent.
+pub(crate) async fn spell_kv_set_if_equals(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let key: String = Args::next("key", &mut args)?;
+    let expected: Option<String> = Args::next_opt("expected", &mut args)?;
+    let value: String = Args::next("value", &mut args)?;
+    check_kv_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let spell_id = services
+        .to_service_id(params.peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let call_params = CallParams::from(spell_id.clone(), params);
+    let is_set = spell_service_api
+        .set_string_if_equals(call_params, key.clone(), expected, value)
+        .await
+        .map_err(|e| {
+            JError::with_code(
+                ErrorCode::Internal,
+                f!("Failed to set {key} for spell {spell_id}: {e}"),
+            )
+        })?;
+    Ok(json!(is_set))
+}
+
+/// spell.kv_incr(spell_id, key, delta) -> u32
+/// Add `delta` to the u32 `key` and return the new value.
+pub(crate) async fn spell_kv_incr(
+    args: Args,
+    params: ParticleParams,
+    services: ParticleAppServices,
+    spell_service_api: SpellServiceApi,
+    workers: Arc<Workers>,
+    scopes: PeerScopes,
+) -> Result<JValue, JError> {
+    let mut args = args.function_args.into_iter();
+    let spell_id_or_alias: String = Args::next("spell_id", &mut args)?;
+    let key: String = Args::next("key", &mut args)?;
+    let delta: u32 = Args::next("delta", &mut args)?;
+    check_kv_permissions(&spell_id_or_alias, &params, &workers, &scopes)?;
+
+    let spell_id = services
+        .to_service_id(params.peer_scope, spell_id_or_alias, &params.id)
+        .await
+        .map_err(spell_error)?;
+    let call_params = CallParams::from(spell_id.clone(), params);
+    let value = spell_service_api
+        .incr_u32(call_params, key.clone(), delta)
+        .await
+        .map_err(|e| {
+            JError::with_code(
+                ErrorCode::Internal,
+                f!("Failed to increment {key} for spell {spell_id}: {e}"),
+            )
+        })?;
+    Ok(json!(value))
+}
+
+pub(crate) fn get_spell_id(params: ParticleParams) -> Result<JValue, JError> {
+    Ok(json!(parse_spell_id_from(&params)?))
+}
+
+pub(crate) async fn get_spell_arg(
+    args: Args,
+    params: ParticleParams,
+    spell_service_api: SpellServiceApi,
+) -> Result<JValue, JError> {
+    let spell_id = parse_spell_id_from(&params)?;
+    let key = args.function_name;
+    let call_params = CallParams::from(spell_id.clone(), params);
+
+    let str_value = spell_service_api
+        .get_string(call_params, key.clone())
+        .await
+        .map_err(|e| {
+            JError::with_code(
+                ErrorCode::Internal,
+                f!("Failed to get argument {key} for spell {spell_id}: {e}"),
+            )
+        })
+        .and_then(|value| {
+            value.ok_or_else(|| JError::with_code(ErrorCode::NotFound, "value not found"))
+        })?;
+
+    serde_json::from_str(&str_value).map_err(|e| {
+        JError::with_code(
+            ErrorCode::InvalidArgument,
+            f!("Failed to parse argument `{key} -> {str_value}` for spell {spell_id}: {e}"),
+        )
+    })
+}
+
+pub(crate) async fn store_error(
+    mut args: Args,
+    params: ParticleParams,
+    spell_service_api: SpellServiceApi,
+    quarantine: SpellQuarantine,
+) -> Result<(), JError> {
+    let spell_id = parse_spell_id_from(&params)?;
+    quarantine.on_failure(&spell_id);
+
+    args.function_args.push(json!(params.timestamp));
+    let call_params = CallParams::from(spell_id.clone(), params);
+    spell_service_api
+        .store_error(call_params, args.function_args.clone())
+        .await
+        .map_err(|e| {
+            JError::with_code(
+                ErrorCode::Internal,
+                format!(
+                    "Failed to store error {:?} for spell {}: {}",
+                    args.function_args, spell_id, e
+                ),
+            )
         })
 }
 
@@ -450,13 +1792,15 @@ pub(crate) async fn store_response(
 
     if let Some(response) = response {
         let call_params = CallParams::from(spell_id.clone(), params);
+        // The response isn't put into the error, it may be huge
         spell_service_api
-            .update_kv(call_params, response.clone())
+            .update_kv(call_params, response)
             .await
             .map_err(|err| {
-                JError::new(format!(
-                    "Failed to store response {response} for spell {spell_id}: {err}"
-                ))
+                JError::with_code(
+                    err.error_code(),
+                    format!("Failed to store response for spell {spell_id}: {err}"),
+                )
             })
     } else {
         Ok(())
@@ -0,0 +1,163 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use fluence_spell_dtos::trigger_config::{ClockConfig, TriggerConfig};
+use serde::Deserialize;
+use serde_json::{Map, Value as JValue};
+
+use particle_args::{ErrorCode, JError};
+
+/// Spell shipped with the node, installed with `spell.install_builtin(name, config)`
+pub struct BuiltinSpell {
+    pub name: &'static str,
+    pub script: &'static str,
+    /// Default clock period in seconds
+    period_sec: u32,
+    /// Default init data as a JSON object
+    default_data: &'static str,
+    /// Init data keys that must be provided in the config
+    required_data: &'static [&'static str],
+}
+
+pub const BUILTIN_SPELLS: &[BuiltinSpell] = &[
+    BuiltinSpell {
+        name: "heartbeat",
+        script: include_str!("../spells/heartbeat.air"),
+        period_sec: 60,
+        default_data: r#"{"function_name": "heartbeat"}"#,
+        required_data: &["target_peer", "service_id"],
+    },
+    BuiltinSpell {
+        name: "disk_cleaner",
+        script: include_str!("../spells/disk_cleaner.air"),
+        period_sec: 3600,
+        default_data: r#"{"selector": "ephemeral=true"}"#,
+        required_data: &[],
+    },
+    BuiltinSpell {
+        name: "provider_reannouncer",
+        script: include_str!("../spells/provider_reannouncer.air"),
+        period_sec: 600,
+        default_data: "{}",
+        required_data: &[],
+    },
+    BuiltinSpell {
+        name: "deal_status_poller",
+        script: include_str!("../spells/deal_status_poller.air"),
+        period_sec: 300,
+        default_data: "{}",
+        required_data: &["deal_id"],
+    },
+];
+
+pub fn find_builtin_spell(name: &str) -> Option<&'static BuiltinSpell> {
+    BUILTIN_SPELLS.iter().find(|spell| spell.name == name)
+}
+
+/// Second argument of `spell.install_builtin`, every field is optional
+#[derive(Debug, Default, Deserialize)]
+pub struct BuiltinSpellConfig {
+    #[serde(default)]
+    pub trigger_config: Option<TriggerConfig>,
+    /// Merged over the default init data of the spell
+    #[serde(default)]
+    pub data: Map<String, JValue>,
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(default)]
+    pub labels: Option<String>,
+}
+
+impl BuiltinSpell {
+    pub fn trigger_config(&self, config: &BuiltinSpellConfig) -> TriggerConfig {
+        config
+            .trigger_config
+            .clone()
+            .unwrap_or_else(|| TriggerConfig {
+                clock: ClockConfig {
+                    start_sec: 1,
+                    end_sec: 0,
+                    period_sec: self.period_sec,
+                },
+                ..Default::default()
+            })
+    }
+
+    pub fn init_data(&self, config: &BuiltinSpellConfig) -> Result<JValue, JError> {
+        let mut data: Map<String, JValue> =
+            serde_json::from_str(self.default_data).expect("default data must be a JSON object");
+        data.extend(config.data.clone());
+
+        let missing: Vec<_> = self
+            .required_data
+            .iter()
+            .filter(|key| !data.contains_key(**key))
+            .collect();
+        if !missing.is_empty() {
+            return Err(JError::with_code(
+                ErrorCode::InvalidArgument,
+                format!(
+                    "Builtin spell {} requires {:?} in config data",
+                    self.name, missing
+                ),
+            ));
+        }
+
+        Ok(JValue::Object(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn builtin_spells_have_valid_defaults() {
+        let config = BuiltinSpellConfig::default();
+        for spell in BUILTIN_SPELLS {
+            assert!(!spell.script.is_empty());
+            assert_eq!(
+                spell.trigger_config(&config).clock.period_sec,
+                spell.period_sec
+            );
+            let data = spell.init_data(&config);
+            assert_eq!(
+                data.is_ok(),
+                spell.required_data.is_empty(),
+                "{}",
+                spell.name
+            );
+        }
+    }
+
+    #[test]
+    fn config_data_overrides_defaults() {
+        let spell = find_builtin_spell("heartbeat").unwrap();
+        let config: BuiltinSpellConfig = serde_json::from_value(json!({
+            "data": { "target_peer": "peer", "service_id": "srv", "function_name": "beat" }
+        }))
+        .unwrap();
+
+        let data = spell.init_data(&config).unwrap();
+        assert_eq!(data["function_name"], json!("beat"));
+        assert_eq!(data["target_peer"], json!("peer"));
+    }
+}
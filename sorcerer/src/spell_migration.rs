@@ -0,0 +1,213 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use particle_args::{ErrorCode, JError};
+use serde::Deserialize;
+use spell_service_api::{CallParams, SpellServiceApi};
+
+/// KV key with the version of the spell KV layout, absent means 0
+pub(crate) const SCHEMA_VERSION_KEY: &str = "hw_schema_version";
+
+/// Values of string keys, `None` for absent keys
+pub(crate) type KvSnapshot = BTreeMap<String, Option<String>>;
+
+/// A step of a spell KV migration, run once when a spell script is updated to a new schema version
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum MigrationStep {
+    Set {
+        key: String,
+        value: String,
+    },
+    Delete {
+        key: String,
+    },
+    /// Move the value of `from` to `to`, does nothing if `from` is absent
+    Rename {
+        from: String,
+        to: String,
+    },
+    /// Fail the migration if `key` is absent
+    Require {
+        key: String,
+    },
+}
+
+impl MigrationStep {
+    fn keys(&self) -> Vec<&str> {
+        match self {
+            MigrationStep::Set { key, .. }
+            | MigrationStep::Delete { key }
+            | MigrationStep::Require { key } => vec![key],
+            MigrationStep::Rename { from, to } => vec![from, to],
+        }
+    }
+}
+
+/// Applies the steps to the values of the keys they touch, returns the new values of changed keys
+pub(crate) fn plan(steps: &[MigrationStep], snapshot: &KvSnapshot) -> Result<KvSnapshot, JError> {
+    let mut kv = snapshot.clone();
+    for (n, step) in steps.iter().enumerate() {
+        match step {
+            MigrationStep::Set { key, value } => {
+                kv.insert(key.clone(), Some(value.clone()));
+            }
+            MigrationStep::Delete { key } => {
+                kv.insert(key.clone(), None);
+            }
+            MigrationStep::Rename { from, to } => {
+                if let Some(value) = kv.insert(from.clone(), None).flatten() {
+                    kv.insert(to.clone(), Some(value));
+                }
+            }
+            MigrationStep::Require { key } => {
+                if kv.get(key).cloned().flatten().is_none() {
+                    return Err(JError::with_code(
+                        ErrorCode::FailedPrecondition,
+                        format!("Migration step {n} requires key '{key}', but it's absent"),
+                    ));
+                }
+            }
+        }
+    }
+    kv.retain(|key, value| snapshot.get(key) != Some(value));
+    Ok(kv)
+}
+
+/// Runs the migration on the spell KV. Returns the previous values of the changed keys
+/// to undo the migration with `rollback`. If writing fails halfway, the written keys are rolled back.
+pub(crate) async fn migrate(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+    steps: &[MigrationStep],
+) -> Result<KvSnapshot, JError> {
+    let keys: BTreeSet<&str> = steps.iter().flat_map(MigrationStep::keys).collect();
+    let mut snapshot = KvSnapshot::new();
+    for key in keys {
+        let value = spell_service_api
+            .get_string(params.clone(), key.to_string())
+            .await?;
+        snapshot.insert(key.to_string(), value);
+    }
+
+    let changes = plan(steps, &snapshot)?;
+    let mut undo = KvSnapshot::new();
+    for (key, value) in changes {
+        undo.insert(key.clone(), snapshot[&key].clone());
+        if let Err(err) = write(spell_service_api, params.clone(), key.clone(), value).await {
+            rollback(spell_service_api, params, &undo).await;
+            return Err(JError::with_code(
+                ErrorCode::Internal,
+                format!("Migration failed to write key '{key}', rolled back: {err}"),
+            ));
+        }
+    }
+    Ok(undo)
+}
+
+/// Restores the keys to the given values, errors are logged as there is nothing left to fall back to
+pub(crate) async fn rollback(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+    undo: &KvSnapshot,
+) {
+    for (key, value) in undo {
+        if let Err(err) = write(
+            spell_service_api,
+            params.clone(),
+            key.clone(),
+            value.clone(),
+        )
+        .await
+        {
+            log::error!("Failed to roll back spell KV key '{key}' after a failed migration: {err}");
+        }
+    }
+}
+
+async fn write(
+    spell_service_api: &SpellServiceApi,
+    params: CallParams,
+    key: String,
+    value: Option<String>,
+) -> Result<(), spell_service_api::CallError> {
+    match value {
+        Some(value) => spell_service_api.set_string(params, key, value).await,
+        None => spell_service_api.remove_key(params, key).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(kv: &[(&str, Option<&str>)]) -> KvSnapshot {
+        kv.iter()
+            .map(|(k, v)| (k.to_string(), v.map(str::to_string)))
+            .collect()
+    }
+
+    #[test]
+    fn plan_returns_only_changes() {
+        let steps: Vec<MigrationStep> = serde_json::from_value(serde_json::json!([
+            {"op": "rename", "from": "last_block", "to": "last_block_v2"},
+            {"op": "set", "key": "mode", "value": "fast"},
+            {"op": "delete", "key": "obsolete"},
+            {"op": "set", "key": "same", "value": "1"},
+        ]))
+        .unwrap();
+        let before = snapshot(&[
+            ("last_block", Some("42")),
+            ("last_block_v2", None),
+            ("mode", None),
+            ("obsolete", Some("x")),
+            ("same", Some("1")),
+        ]);
+
+        let changes = plan(&steps, &before).unwrap();
+        assert_eq!(
+            changes,
+            snapshot(&[
+                ("last_block", None),
+                ("last_block_v2", Some("42")),
+                ("mode", Some("fast")),
+                ("obsolete", None),
+            ])
+        );
+    }
+
+    #[test]
+    fn plan_fails_on_missing_required_key() {
+        let steps = vec![
+            MigrationStep::Rename {
+                from: "a".to_string(),
+                to: "b".to_string(),
+            },
+            MigrationStep::Require {
+                key: "b".to_string(),
+            },
+        ];
+
+        assert!(plan(&steps, &snapshot(&[("a", Some("1")), ("b", None)])).is_ok());
+        let err = plan(&steps, &snapshot(&[("a", None), ("b", None)])).unwrap_err();
+        assert_eq!(err.code(), Some(ErrorCode::FailedPrecondition));
+    }
+}
@@ -0,0 +1,175 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use particle_args::{ErrorCode, JError};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JValue};
+use spell_event_bus::api::{
+    self, CustomEventType, ExclusionWindow, HealthEventType, KvWatch, PartitionEventType,
+    PeerPayloadTemplate, ProbeEventType, ResourceEventType, SpellTriggerConfigs,
+};
+use spell_service_api::{CallParams, SpellServiceApi};
+
+/// KV key where the triggers a spell has in addition to its trigger config are stored
+const STORED_TRIGGERS_KEY: &str = "hw_triggers";
+/// How many times an update is retried when the record is changed concurrently
+const UPDATE_ATTEMPTS: usize = 5;
+
+/// Triggers set by `spell.set_*` builtins. They are kept in the spell KV as a single JSON record,
+/// so resubscribing a spell takes one KV read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct StoredTriggers {
+    /// A paused spell stays unsubscribed until it's resumed
+    pub paused: bool,
+    /// Node resource events
+    pub resource: Vec<ResourceEventType>,
+    /// KV keys of other spells
+    pub kv: Vec<KvWatch>,
+    /// Probe results of must-reach targets
+    pub probe: Vec<ProbeEventType>,
+    /// Service health changes
+    pub health: Vec<HealthEventType>,
+    /// Network partition changes
+    pub partition: Vec<PartitionEventType>,
+    /// Events of custom trigger sources
+    pub custom: Vec<CustomEventType>,
+    /// Hash of the token of the spell webhook
+    pub webhook_token_hash: Option<String>,
+    /// Template of the peer event triggers written to the mailbox
+    pub peer_payload: Option<JValue>,
+    /// Windows when the spell isn't triggered
+    pub exclusion_windows: Vec<ExclusionWindow>,
+}
+
+impl StoredTriggers {
+    fn parse(record: Option<&str>) -> Result<Self, JError> {
+        match record {
+            Some(record) => serde_json::from_str(record).map_err(|e| {
+                JError::with_code(
+                    ErrorCode::Internal,
+                    format!("Failed to parse {STORED_TRIGGERS_KEY} of the spell: {e}"),
+                )
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub(crate) async fn load(
+        spell_service_api: &SpellServiceApi,
+        params: CallParams,
+    ) -> Result<Self, JError> {
+        let record = spell_service_api
+            .get_string(params, STORED_TRIGGERS_KEY.to_string())
+            .await?;
+        Self::parse(record.as_deref())
+    }
+
+    /// Change the stored triggers with `update`. The record is written only if nobody
+    /// has changed it since it was read, otherwise the update is applied again.
+    pub(crate) async fn update(
+        spell_service_api: &SpellServiceApi,
+        params: CallParams,
+        update: impl Fn(&mut Self),
+    ) -> Result<(), JError> {
+        for _ in 0..UPDATE_ATTEMPTS {
+            let record = spell_service_api
+                .get_string(params.clone(), STORED_TRIGGERS_KEY.to_string())
+                .await?;
+            let mut triggers = Self::parse(record.as_deref())?;
+            update(&mut triggers);
+            let is_set = spell_service_api
+                .set_string_if_equals(
+                    params.clone(),
+                    STORED_TRIGGERS_KEY.to_string(),
+                    record,
+                    json!(triggers).to_string(),
+                )
+                .await?;
+            if is_set {
+                return Ok(());
+            }
+        }
+        Err(JError::with_code(
+            ErrorCode::Internal,
+            format!(
+                "Failed to update {STORED_TRIGGERS_KEY} of the spell, it's changed concurrently"
+            ),
+        ))
+    }
+
+    /// Add the stored triggers to the trigger config of the spell
+    pub(crate) fn apply(
+        self,
+        config: Option<SpellTriggerConfigs>,
+    ) -> Result<Option<SpellTriggerConfigs>, JError> {
+        if self.paused {
+            return Ok(None);
+        }
+        let peer_payload = self
+            .peer_payload
+            .map(PeerPayloadTemplate::parse)
+            .transpose()?;
+
+        let config = api::add_resource_triggers(config, self.resource);
+        let config = api::add_kv_triggers(config, self.kv);
+        let config = api::add_probe_triggers(config, self.probe);
+        let config = api::add_health_triggers(config, self.health);
+        let config = api::add_partition_triggers(config, self.partition);
+        let config = api::add_custom_triggers(config, self.custom);
+        let config = api::add_webhook_trigger(config, self.webhook_token_hash);
+        let config = api::add_peer_payload_template(config, peer_payload);
+        Ok(api::add_exclusion_windows(config, &self.exclusion_windows)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_partial_record() {
+        assert_eq!(
+            StoredTriggers::parse(None).unwrap(),
+            StoredTriggers::default()
+        );
+
+        let triggers = StoredTriggers::parse(Some(r#"{"resource": ["disk"]}"#)).unwrap();
+        assert_eq!(triggers.resource, vec![ResourceEventType::Disk]);
+        assert!(!triggers.paused);
+
+        assert!(StoredTriggers::parse(Some("not json")).is_err());
+    }
+
+    #[test]
+    fn paused_spell_has_no_triggers() {
+        let triggers = StoredTriggers {
+            paused: true,
+            resource: vec![ResourceEventType::Disk],
+            ..<_>::default()
+        };
+        assert!(triggers.apply(None).unwrap().is_none());
+
+        let triggers = StoredTriggers {
+            resource: vec![ResourceEventType::Disk],
+            ..<_>::default()
+        };
+        assert!(triggers.apply(None).unwrap().is_some());
+    }
+}
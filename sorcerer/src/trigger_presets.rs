@@ -0,0 +1,90 @@
+/*
+ * Nox Fluence Peer
+ *
+ * Copyright (C) 2024 Fluence DAO
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation version 3 of the
+ * License.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use fluence_spell_dtos::trigger_config::TriggerConfig;
+use particle_args::{ErrorCode, JError};
+use serde_json::Value as JValue;
+
+/// Named trigger configs from the node config. Spells can be installed or reconfigured
+/// with a preset name instead of a trigger config, so scheduling policy lives in one place.
+/// A preset is resolved once, changing it later doesn't affect already installed spells.
+#[derive(Clone, Debug, Default)]
+pub struct TriggerPresets(Arc<HashMap<String, TriggerConfig>>);
+
+impl TriggerPresets {
+    pub fn new(presets: HashMap<String, TriggerConfig>) -> Self {
+        Self(Arc::new(presets))
+    }
+
+    /// Trigger config from a spell builtin argument, which is either a config or a preset name
+    pub fn resolve(&self, value: JValue) -> Result<TriggerConfig, JError> {
+        match value {
+            JValue::String(name) => self.0.get(&name).cloned().ok_or_else(|| {
+                let mut known: Vec<&str> = self.0.keys().map(String::as_str).collect();
+                known.sort_unstable();
+                let known = known.join(", ");
+                JError::with_code(
+                    ErrorCode::NotFound,
+                    format!("Unknown trigger preset '{name}', known presets: [{known}]"),
+                )
+            }),
+            value => serde_json::from_value(value).map_err(|err| {
+                JError::with_code(
+                    ErrorCode::InvalidArgument,
+                    format!("Invalid trigger config: {err}"),
+                )
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn every_minute() -> TriggerConfig {
+        let mut config = TriggerConfig::default();
+        config.clock.start_sec = 1;
+        config.clock.period_sec = 60;
+        config
+    }
+
+    #[test]
+    fn resolves_names_and_configs() {
+        let presets = TriggerPresets::new(HashMap::from([(
+            "every-minute".to_string(),
+            every_minute(),
+        )]));
+
+        let resolved = presets.resolve(json!("every-minute")).unwrap();
+        assert_eq!(resolved.clock.period_sec, 60);
+
+        let inline = presets.resolve(json!(every_minute())).unwrap();
+        assert_eq!(inline.clock.period_sec, 60);
+
+        let unknown = presets.resolve(json!("hourly")).unwrap_err();
+        assert!(unknown.to_string().contains("every-minute"));
+        assert!(presets.resolve(json!(42)).is_err());
+    }
+}
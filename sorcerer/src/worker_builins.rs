@@ -19,7 +19,8 @@
 use fluence_libp2p::PeerId;
 use fluence_spell_dtos::trigger_config::TriggerConfig;
 use futures::TryFutureExt;
-use serde_json::Value as JValue;
+use serde_json::{json, Value as JValue};
+use std::collections::HashSet;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
@@ -31,13 +32,14 @@ use particle_services::{ParticleAppServices, PeerScope};
 use spell_event_bus::api::{from_user_config, SpellEventBusApi};
 use spell_service_api::{CallParams, SpellServiceApi};
 use spell_storage::SpellStorage;
-use workers::{PeerScopes, WorkerParams, Workers, CUID};
+use workers::{PeerScopes, WorkerId, WorkerParams, Workers, CUID};
 
 pub(crate) async fn create_worker(
     args: Args,
     params: ParticleParams,
     scopes: PeerScopes,
     workers: Arc<Workers>,
+    revoked_keys: Arc<HashSet<PeerId>>,
 ) -> Result<JValue, JError> {
     let mut args = args.function_args.into_iter();
     let deal_id: String = Args::next("deal_id", &mut args)?;
@@ -49,6 +51,13 @@ pub(crate) async fn create_worker(
         ));
     }
 
+    if revoked_keys.contains(&params.init_peer_id) {
+        return Err(JError::new(format!(
+            "Worker can't be created by the revoked key {}",
+            params.init_peer_id
+        )));
+    }
+
     Ok(JValue::String(
         workers
             .create_worker(WorkerParams::new(
@@ -136,6 +145,34 @@ pub(crate) fn worker_list(workers: Arc<Workers>) -> Result<JValue, JError> {
     ))
 }
 
+/// Workers with deals and derivation paths of their keys, `path` is null for random keys.
+/// Keys with paths can be recovered from the root key alone.
+pub(crate) fn key_derivation_paths(
+    params: ParticleParams,
+    scopes: PeerScopes,
+    workers: Arc<Workers>,
+) -> Result<JValue, JError> {
+    if !scopes.is_management(params.init_peer_id) && !scopes.is_host(params.init_peer_id) {
+        return Err(JError::new(
+            "Only management or host peer can export key derivation paths",
+        ));
+    }
+
+    Ok(JValue::Array(
+        workers
+            .key_derivation_paths()
+            .into_iter()
+            .map(|(worker_id, deal_id, path)| {
+                json!({
+                    "worker_id": worker_id.to_string(),
+                    "deal_id": deal_id.to_address(),
+                    "path": path,
+                })
+            })
+            .collect(),
+    ))
+}
+
 pub(crate) async fn deactivate_deal(
     args: Args,
     params: ParticleParams,
@@ -160,6 +197,26 @@ pub(crate) async fn deactivate_deal(
         return Err(JError::new("Deal has already been deactivated"));
     }
 
+    deactivate_worker(
+        worker_id,
+        Duration::from_millis(params.ttl as u64),
+        &workers,
+        &spell_storage,
+        &spell_event_bus_api,
+        &spell_service_api,
+    )
+    .await
+}
+
+/// Stops all spells of the worker and marks it inactive
+pub(crate) async fn deactivate_worker(
+    worker_id: WorkerId,
+    ttl: Duration,
+    workers: &Workers,
+    spell_storage: &SpellStorage,
+    spell_event_bus_api: &SpellEventBusApi,
+    spell_service_api: &SpellServiceApi,
+) -> Result<(), JError> {
     let spells = spell_storage.get_registered_spells_by(PeerScope::WorkerId(worker_id));
 
     for spell_id in spells.into_iter() {
@@ -178,7 +235,7 @@ pub(crate) async fn deactivate_deal(
                     PeerScope::WorkerId(worker_id),
                     spell_id.clone(),
                     worker_id.into(),
-                    Duration::from_millis(params.ttl as u64),
+                    ttl,
                 ),
                 TriggerConfig::default(),
             )
@@ -204,6 +261,7 @@ pub(crate) async fn activate_deal(
     spell_event_bus_api: SpellEventBusApi,
     spell_service_api: SpellServiceApi,
     worker_period_sec: u32,
+    revoked_keys: Arc<HashSet<PeerId>>,
 ) -> Result<(), JError> {
     let mut args = args.function_args.into_iter();
     let deal_id: String = Args::next("deal_id", &mut args)?;
@@ -220,6 +278,13 @@ pub(crate) async fn activate_deal(
         return Err(JError::new("Deal has already been activated"));
     }
 
+    let creator = workers.get_worker_creator(worker_id)?;
+    if revoked_keys.contains(&creator) {
+        return Err(JError::new(format!(
+            "Worker {worker_id} was created by the revoked key {creator}, transfer it to another creator before activation"
+        )));
+    }
+
     let installation_spell_id = services
         .resolve_alias(
             PeerScope::WorkerId(worker_id),
@@ -245,7 +310,7 @@ pub(crate) async fn activate_deal(
         )
         .await?;
 
-    let trigger_config = from_user_config(&worker_config)?.ok_or(JError::new(
+    let trigger_config = from_user_config(&worker_config, None)?.ok_or(JError::new(
         "Deal activation failed due to failure to parse trigger config",
     ))?;
 
@@ -262,6 +327,71 @@ pub(crate) async fn activate_deal(
     Ok(())
 }
 
+/// Workers created by the revoked keys, they stay inactive until transferred to another creator
+pub(crate) fn revoked_workers(
+    params: ParticleParams,
+    scopes: PeerScopes,
+    workers: Arc<Workers>,
+    revoked_keys: Arc<HashSet<PeerId>>,
+) -> Result<JValue, JError> {
+    if !scopes.is_management(params.init_peer_id) && !scopes.is_host(params.init_peer_id) {
+        return Err(JError::new(
+            "Only management or host peer can list workers of the revoked keys",
+        ));
+    }
+
+    let mut revoked = vec![];
+    for worker_id in workers.list_workers() {
+        let creator = workers.get_worker_creator(worker_id)?;
+        if revoked_keys.contains(&creator) {
+            revoked.push(json!({
+                "worker_id": worker_id.to_string(),
+                "deal_id": workers.get_deal_id(worker_id)?.to_address(),
+                "creator": creator.to_string(),
+                "active": workers.is_worker_active(worker_id),
+            }));
+        }
+    }
+
+    Ok(JValue::Array(revoked))
+}
+
+/// Transfers the worker to a new creator, the worker has to be activated afterwards
+/// if it was paused due to the revoked key of its former creator
+pub(crate) async fn transfer_worker(
+    args: Args,
+    params: ParticleParams,
+    scopes: PeerScopes,
+    workers: Arc<Workers>,
+    revoked_keys: Arc<HashSet<PeerId>>,
+) -> Result<(), JError> {
+    let mut args = args.function_args.into_iter();
+    let worker_id: String = Args::next("worker_id", &mut args)?;
+    let creator: String = Args::next("creator", &mut args)?;
+    let worker_peer_id = PeerId::from_str(&worker_id)?;
+    let creator = PeerId::from_str(&creator)?;
+
+    if !scopes.is_management(params.init_peer_id) && !scopes.is_host(params.init_peer_id) {
+        return Err(JError::new(
+            "Only management or host peer can transfer worker",
+        ));
+    }
+
+    if revoked_keys.contains(&creator) {
+        return Err(JError::new(format!(
+            "Worker {worker_id} can't be transferred to the revoked key {creator}"
+        )));
+    }
+
+    match scopes.scope(worker_peer_id) {
+        Ok(PeerScope::WorkerId(worker_id)) => {
+            workers.set_worker_creator(worker_id, creator).await?;
+            Ok(())
+        }
+        _ => Err(JError::new(format!("Worker {worker_id} not found"))),
+    }
+}
+
 pub(crate) fn is_deal_active(args: Args, workers: Arc<Workers>) -> Result<JValue, JError> {
     let mut args = args.function_args.into_iter();
     let deal_id: String = Args::next("deal_id", &mut args)?;